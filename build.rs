@@ -79,6 +79,9 @@ fn generate_typescript_bindings() {
         IceNegotiation::export_to_string().unwrap(),
         MediaNegotiation::export_to_string().unwrap(),
         EndSessionQuestion::export_to_string().unwrap(),
+        StartSessionQuestion::export_to_string().unwrap(),
+        StartSessionRejectedAnswer::export_to_string().unwrap(),
+        IceServer::export_to_string().unwrap(),
     ]
     .join("\n\n");
     // Remove all typescript "import type" because all types are going to live in the same typescritp file
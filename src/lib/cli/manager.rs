@@ -3,7 +3,7 @@ use clap;
 use std::sync::Arc;
 use tracing::error;
 
-use crate::{custom, stream::gst::utils::PluginRankConfig};
+use crate::{custom, logger::manager::LogFormat, stream::gst::utils::PluginRankConfig};
 
 use clap::Parser;
 use constcat::concat;
@@ -18,13 +18,14 @@ const fn version() -> &'static str {
 #[derive(Parser, Debug)]
 #[command(version = version(), author = env!("CARGO_PKG_AUTHORS"), about = env!("CARGO_PKG_DESCRIPTION"))]
 struct Args {
-    /// Sets the mavlink connection string
+    /// Sets the mavlink connection strings, comma separated to reach the cameras through several links at once, like "udpin:0.0.0.0:14550,serial:/dev/ttyUSB0:57600". More can be added at runtime through the REST API.
     #[arg(
         long,
         value_name = "<TYPE>:<IP/SERIAL>:<PORT/BAUDRATE>",
-        default_value = "udpin:0.0.0.0:14550"
+        default_value = "udpin:0.0.0.0:14550",
+        value_delimiter = ','
     )]
-    mavlink: String,
+    mavlink: Vec<String>,
 
     /// Sets the settings file path
     #[arg(
@@ -46,6 +47,14 @@ struct Args {
     #[arg(long, value_name = "<IP>:<PORT>", default_value = "0.0.0.0:6020")]
     rest_server: String,
 
+    /// Sets the PEM certificate chain used to serve the REST API and its WebSockets over TLS (HTTPS and WSS), along with "--tls-key". Plain HTTP is served when missing.
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<String>,
+
+    /// Sets the PEM private key of the "--tls-cert" certificate.
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<String>,
+
     /// Sets the address for the stun server
     #[arg(
         long,
@@ -101,6 +110,63 @@ struct Args {
     /// Sets the MAVLink System ID.
     #[arg(long, value_name = "SYSTEM_ID", default_value = "1")]
     mavlink_system_id: u8,
+
+    /// Turns on the pipelines' dot files dumping, which is always on for debug builds.
+    #[arg(long)]
+    enable_dot_files: bool,
+
+    /// Specifies the path in which the dot files will be stored, otherwise GST_DEBUG_DUMP_DOT_DIR env variable is used.
+    #[arg(long, value_name = "PATH")]
+    dot_files_path: Option<String>,
+
+    /// Sets the maximum number of dot files retained per pipeline in the dot files path, the oldest ones are removed first.
+    #[arg(long, value_name = "COUNT", default_value = "20")]
+    dot_files_max: usize,
+
+    /// Sets the paths in which captured images and videos are stored, the first one is used for new captures, and all of them are reported as MAVLink storages.
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_delimiter = ',',
+        default_value = "~/.local/share/mavlink-camera-manager/storage"
+    )]
+    storage_path: Vec<String>,
+
+    /// Sets the minimum bitrate, in kbps, that the WebRTC bandwidth adaptation can request from the stream's encoder.
+    #[arg(long, value_name = "KBPS", default_value = "300")]
+    webrtc_min_bitrate: u32,
+
+    /// Sets the maximum bitrate, in kbps, that the WebRTC bandwidth adaptation can request from the stream's encoder.
+    #[arg(long, value_name = "KBPS", default_value = "5000")]
+    webrtc_max_bitrate: u32,
+
+    /// Sets the encoders to be preferred when a stream needs encoding, in order, like "x264enc,nvh264enc". Encoders not listed are ordered as hardware ones first.
+    #[arg(long, value_name = "<GST_ELEMENT_NAME>", value_delimiter = ',')]
+    encoder_preference: Vec<String>,
+
+    /// Sets the maximum time, in seconds, to wait for all streams to drain their pipelines and finalize their recordings when shutting down. Each pipeline's own drain timeout is set by its "eos_timeout_ms" configuration.
+    #[arg(long, value_name = "SECONDS", default_value = "10")]
+    shutdown_timeout: u64,
+
+    /// Sets the minimum interval, in milliseconds, between writes to the same camera control. Rapid updates, like from a slider being dragged, are coalesced to the latest value, which is always written. Zero disables it.
+    #[arg(long, value_name = "MILLISECONDS", default_value = "50")]
+    controls_debounce_interval: u64,
+
+    /// Sets how many times, at startup, the auto-started streams whose device is absent are retried, as cameras might still be enumerating. Streams still without their device are then marked with an error, starting once their device is connected. Zero disables it, only retrying when a device is connected.
+    #[arg(long, value_name = "ATTEMPTS", default_value = "30")]
+    startup_retry_attempts: u32,
+
+    /// Sets the interval, in seconds, between the startup retries of the streams whose device is absent.
+    #[arg(long, value_name = "SECONDS", default_value = "2")]
+    startup_retry_interval: u64,
+
+    /// Sets the format of the console and file logs, "json" emits one object per event, with its level, target and span fields like the stream and pipeline ids.
+    #[arg(long, value_name = "FORMAT", default_value = "human")]
+    log_format: LogFormat,
+
+    /// Sets the interval, in seconds, between the thumbnails captured from each running stream, served at "/streams/{id}/thumbnail.jpg". Zero disables it.
+    #[arg(long, value_name = "SECONDS", default_value = "10")]
+    thumbnails_interval: u64,
 }
 
 #[derive(Debug)]
@@ -154,8 +220,18 @@ pub fn is_tracy() -> bool {
 }
 
 #[allow(dead_code)]
-// Return the mavlink connection string
+// Return the first mavlink connection string
 pub fn mavlink_connection_string() -> String {
+    MANAGER
+        .clap_matches
+        .mavlink
+        .first()
+        .cloned()
+        .unwrap_or_default()
+}
+
+// Return all the mavlink connection strings
+pub fn mavlink_connection_strings() -> Vec<String> {
     MANAGER.clap_matches.mavlink.clone()
 }
 
@@ -184,6 +260,22 @@ pub fn server_address() -> String {
     MANAGER.clap_matches.rest_server.clone()
 }
 
+// Return the certificate and key paths to serve the REST API over TLS, if enabled
+pub fn tls_paths() -> Option<(String, String)> {
+    let expand = |path: &String| {
+        shellexpand::full(path)
+            .expect("Failed to expand path")
+            .to_string()
+    };
+
+    MANAGER
+        .clap_matches
+        .tls_cert
+        .as_ref()
+        .map(expand)
+        .zip(MANAGER.clap_matches.tls_key.as_ref().map(expand))
+}
+
 // Return the desired address for the STUN server
 pub fn stun_server_address() -> String {
     MANAGER.clap_matches.stun_server.clone()
@@ -219,6 +311,88 @@ pub fn mavlink_system_id() -> u8 {
     MANAGER.clap_matches.mavlink_system_id
 }
 
+pub fn enable_dot_files() -> bool {
+    MANAGER.clap_matches.enable_dot_files
+}
+
+pub fn dot_files_path() -> Option<String> {
+    MANAGER.clap_matches.dot_files_path.as_ref().map(|path| {
+        shellexpand::full(path)
+            .expect("Failed to expand path")
+            .to_string()
+    })
+}
+
+pub fn dot_files_max() -> usize {
+    MANAGER.clap_matches.dot_files_max
+}
+
+// Return the path in which captured images and videos are stored
+pub fn storage_path() -> String {
+    storage_paths()
+        .into_iter()
+        .next()
+        .expect("At least one storage path should be configured")
+}
+
+// Return all the paths configured to store captured images and videos
+pub fn storage_paths() -> Vec<String> {
+    MANAGER
+        .clap_matches
+        .storage_path
+        .iter()
+        .map(|storage_path| {
+            shellexpand::full(storage_path)
+                .expect("Failed to expand path")
+                .to_string()
+        })
+        .collect()
+}
+
+// Return the WebRTC bitrate adaptation limits, in bits per second
+pub fn webrtc_bitrate_limits() -> (u64, u64) {
+    let min = MANAGER.clap_matches.webrtc_min_bitrate as u64 * 1000;
+    let max = MANAGER.clap_matches.webrtc_max_bitrate as u64 * 1000;
+
+    (min.min(max), max)
+}
+
+// Return the encoders preferred by the user, in order
+pub fn encoder_preference() -> Vec<String> {
+    MANAGER.clap_matches.encoder_preference.clone()
+}
+
+// Return the maximum time to wait for all streams to shut down
+pub fn shutdown_timeout() -> std::time::Duration {
+    std::time::Duration::from_secs(MANAGER.clap_matches.shutdown_timeout)
+}
+
+// Return the minimum interval between writes to the same camera control
+pub fn controls_debounce_interval() -> std::time::Duration {
+    std::time::Duration::from_millis(MANAGER.clap_matches.controls_debounce_interval)
+}
+
+// Return how many times the streams whose device is absent are retried at startup
+pub fn startup_retry_attempts() -> u32 {
+    MANAGER.clap_matches.startup_retry_attempts
+}
+
+// Return the interval between the startup retries of the streams whose device is absent
+pub fn startup_retry_interval() -> std::time::Duration {
+    std::time::Duration::from_secs(MANAGER.clap_matches.startup_retry_interval.max(1))
+}
+
+// Return the format of the console and file logs
+pub fn log_format() -> LogFormat {
+    MANAGER.clap_matches.log_format.clone()
+}
+
+// Return the interval between the thumbnails captured from each stream, if enabled
+pub fn thumbnails_interval() -> Option<std::time::Duration> {
+    let interval = MANAGER.clap_matches.thumbnails_interval;
+    (interval > 0).then(|| std::time::Duration::from_secs(interval))
+}
+
 // Return the command line used to start this application
 pub fn command_line_string() -> String {
     std::env::args().collect::<Vec<String>>().join(" ")
@@ -52,6 +52,7 @@ pub fn udp() -> Vec<VideoAndStreamInformation> {
 
             Some(VideoAndStreamInformation {
                 name: format!("UDP Stream {}", index),
+                tags: vec![],
                 stream_information: StreamInformation {
                     endpoints: vec![
                         Url::parse(&format!("udp://192.168.2.1:{}", 5600 + index)).ok()?
@@ -97,6 +98,7 @@ pub fn rtsp() -> Vec<VideoAndStreamInformation> {
 
             Some(VideoAndStreamInformation {
                 name: format!("RTSP Stream {index}"),
+                tags: vec![],
                 stream_information: StreamInformation {
                     endpoints: vec![Url::parse(&format!(
                         "rtsp://{visible_qgc_ip_address}:8554/video_{index}"
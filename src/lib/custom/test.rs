@@ -8,6 +8,7 @@ pub fn take_webrtc_stream() -> Vec<VideoAndStreamInformation> {
     let size = STANDARD_SIZES.last().unwrap();
     vec![VideoAndStreamInformation {
         name: "WebRTC fake stream for thread leak".to_string(),
+        tags: vec![],
         stream_information: StreamInformation {
             endpoints: vec![Url::parse("udp://0.0.0.0:8554/test").unwrap()],
             configuration: CaptureConfiguration::Video(VideoCaptureConfiguration {
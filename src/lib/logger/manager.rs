@@ -1,8 +1,32 @@
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+
 use crate::cli;
 
 use tracing::{metadata::LevelFilter, *};
 use tracing_log::LogTracer;
-use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Layer};
+use tracing_subscriber::{
+    fmt::{self, format::DefaultFields, writer::MakeWriter},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    reload, EnvFilter, Layer, Registry,
+};
+
+#[derive(ValueEnum, PartialEq, Debug, Clone)]
+#[clap(rename_all = "lower")]
+pub enum LogFormat {
+    Human,
+    Json,
+}
+
+lazy_static! {
+    // Allows changing the console log filter without restarting the service
+    static ref CONSOLE_FILTER_HANDLE: Mutex<Option<reload::Handle<EnvFilter, Registry>>> =
+        Default::default();
+}
 
 // Start logger, should be done inside main
 pub fn init() {
@@ -21,6 +45,11 @@ pub fn init() {
         // Hyper is used for http request by our thread leak test
         // And it's pretty verbose when it's on
         .add_directive("hyper=off".parse().unwrap());
+    let (console_env_filter, console_filter_handle) = reload::Layer::new(console_env_filter);
+    CONSOLE_FILTER_HANDLE
+        .lock()
+        .unwrap()
+        .replace(console_filter_handle);
     let console_layer = fmt::Layer::new()
         .with_writer(std::io::stdout)
         .with_ansi(true)
@@ -29,8 +58,8 @@ pub fn init() {
         .with_span_events(fmt::format::FmtSpan::NONE)
         .with_target(false)
         .with_thread_ids(true)
-        .with_thread_names(true)
-        .with_filter(console_env_filter);
+        .with_thread_names(true);
+    let console_layer = with_log_format(console_layer).with_filter(console_env_filter);
 
     // Configure the file log
     let file_env_filter = if cli::manager::is_tracing() {
@@ -48,8 +77,8 @@ pub fn init() {
         .with_span_events(fmt::format::FmtSpan::NONE)
         .with_target(false)
         .with_thread_ids(true)
-        .with_thread_names(true)
-        .with_filter(file_env_filter);
+        .with_thread_names(true);
+    let file_layer = with_log_format(file_layer).with_filter(file_env_filter);
 
     // Configure the default subscriber
     match cli::manager::is_tracy() {
@@ -99,6 +128,59 @@ pub fn init() {
     );
 }
 
+/// Switches the layer to JSON when chosen by the user, keeping the human-readable format otherwise
+fn with_log_format<S, W>(
+    layer: fmt::Layer<S, DefaultFields, fmt::format::Format, W>,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    match cli::manager::log_format() {
+        LogFormat::Human => layer.boxed(),
+        // Aggregators group the events by their target and the fields of their spans, like the stream_id
+        LogFormat::Json => layer
+            .json()
+            .with_ansi(false)
+            .with_target(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+/// The filter currently applied to the console log, like "info,hyper=off"
+pub fn console_filter() -> Result<String> {
+    CONSOLE_FILTER_HANDLE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .context("Logger not initialized")?
+        .with_current(|filter| filter.to_string())
+        .context("Logger no longer exists")
+}
+
+/// Replaces the console log filter, taking effect immediately. The directives follow the RUST_LOG syntax, like
+/// "info,mavlink_camera_manager::stream=debug"
+#[instrument(level = "debug")]
+pub fn set_console_filter(directives: &str) -> Result<()> {
+    let filter = EnvFilter::try_new(directives)
+        .context(format!("Invalid log filter {directives:?}"))?
+        .add_directive("hyper=off".parse().unwrap());
+
+    CONSOLE_FILTER_HANDLE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .context("Logger not initialized")?
+        .reload(filter)
+        .context("Logger no longer exists")?;
+
+    info!("Console log filter changed to {directives:?}");
+
+    Ok(())
+}
+
 fn redirect_gstreamer_logs_to_tracing() {
     gst::log::add_log_function(
         |category, gst_level, file, function, line, object, message| {
@@ -1,23 +1,40 @@
+use std::collections::HashMap;
 use std::marker::Send;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 
+use anyhow::{anyhow, Context, Result};
 use mavlink::common::MavMessage;
-use mavlink::{MavConnection, MavHeader};
+use mavlink::{MavConnection, MavHeader, Message as _};
 
 use tokio::sync::broadcast;
 use tracing::*;
 
-use crate::settings;
+use crate::{cli, settings};
+
+/// The kinds of connection strings accepted, like "udpin:0.0.0.0:14550" or "serial:/dev/ttyUSB0:57600"
+const ENDPOINT_KINDS: [&str; 7] = [
+    "udpin", "udpout", "udpbcast", "tcpin", "tcpout", "serial", "file",
+];
 
 lazy_static! {
     static ref MANAGER: Arc<Mutex<Manager>> = Default::default();
 }
 
 pub struct Manager {
-    connection: Arc<RwLock<Connection>>,
+    sender: broadcast::Sender<Message>,
+    /// The endpoints the cameras are reachable through, by their connection string
+    endpoints: Arc<RwLock<HashMap<String, Endpoint>>>,
     ids: Arc<RwLock<Vec<u8>>>,
 }
 
+/// A MAVLink connection, with its own threads receiving from it and sending to it
+struct Endpoint {
+    connection: Arc<RwLock<Connection>>,
+    /// Stops the threads of the endpoint once it is removed
+    stopped: Arc<AtomicBool>,
+}
+
 struct Connection {
     address: String,
     connection: Option<Box<dyn MavConnection<MavMessage> + Sync + Send>>,
@@ -26,40 +43,82 @@ struct Connection {
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    Received((MavHeader, MavMessage)),
+    /// A message received through the endpoint of the given connection string
+    Received((String, MavHeader, MavMessage)),
+    /// A message to be sent through all the endpoints, like the heartbeats
     ToBeSent((MavHeader, MavMessage)),
+    /// A message to be sent only through the endpoint of the given connection string, like the answers to the
+    /// messages received through it
+    Reply((String, MavHeader, MavMessage)),
 }
 
 impl Default for Manager {
     #[instrument(level = "debug")]
     fn default() -> Self {
-        let address = settings::manager::mavlink_endpoint();
-
         let (sender, _receiver) = broadcast::channel(100);
 
+        // The endpoints added through the REST API are kept along with the ones from the CLI arguments
+        let mut addresses = cli::manager::mavlink_connection_strings();
+        for address in settings::manager::mavlink_extra_endpoints() {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+
+        let endpoints = addresses
+            .into_iter()
+            .map(|address| {
+                let endpoint = Endpoint::new(&address, sender.clone());
+                (address, endpoint)
+            })
+            .collect();
+
+        Self {
+            sender,
+            endpoints: Arc::new(RwLock::new(endpoints)),
+            ids: Arc::new(RwLock::new(vec![])),
+        }
+    }
+}
+
+impl Endpoint {
+    #[instrument(level = "debug", skip(sender))]
+    fn new(address: &str, sender: broadcast::Sender<Message>) -> Self {
         let this = Self {
             connection: Arc::new(RwLock::new(Connection {
-                address,
+                address: address.to_string(),
                 connection: None,
                 sender,
             })),
-            ids: Arc::new(RwLock::new(vec![])),
+            stopped: Arc::new(AtomicBool::new(false)),
         };
 
         let connection = this.connection.clone();
+        let stopped = this.stopped.clone();
         std::thread::Builder::new()
             .name("MavSender".into())
-            .spawn(move || Manager::sender_loop(connection))
+            .spawn(move || Manager::sender_loop(connection, stopped))
             .expect("Failed to spawn MavSender thread");
 
         let connection = this.connection.clone();
+        let stopped = this.stopped.clone();
         std::thread::Builder::new()
             .name("MavReceiver".into())
-            .spawn(move || Manager::receiver_loop(connection))
+            .spawn(move || Manager::receiver_loop(connection, stopped))
             .expect("Failed to spawn MavReceiver thread");
 
         this
     }
+
+    /// Stops the threads of the endpoint, closing its connection right away unless a thread is blocked using it, in
+    /// which case it is closed once the thread is done
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+
+        if let Ok(mut connection) = self.connection.try_write() {
+            connection.connection.take();
+        }
+    }
 }
 
 impl Manager {
@@ -69,9 +128,9 @@ impl Manager {
         MANAGER.as_ref();
     }
 
-    #[instrument(level = "debug", skip(inner))]
-    fn receiver_loop(inner: Arc<RwLock<Connection>>) {
-        loop {
+    #[instrument(level = "debug", skip(inner, stopped))]
+    fn receiver_loop(inner: Arc<RwLock<Connection>>, stopped: Arc<AtomicBool>) {
+        while !stopped.load(Ordering::Relaxed) {
             loop {
                 let Ok(inner_guard) = inner.read() else {
                     break; // Break to trigger reconnection
@@ -101,8 +160,15 @@ impl Manager {
                     }
                 };
 
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+
                 trace!("Message received: {header:?}, {message:?}");
 
+                // The vehicle's telemetry comes from its autopilot, which is filtered out below
+                super::telemetry::update(&message);
+
                 // Early filter non-GCS messages to avoid passing unwanted ones to the camera componenets.
                 let allowed_component_ids = [
                     mavlink::common::MavComponent::MAV_COMP_ID_ALL as u8,
@@ -116,11 +182,12 @@ impl Manager {
 
                 debug!("Message accepted: {header:?}, {message:?}");
 
-                // Send the received message to the cameras
-                if let Err(error) = inner_guard
-                    .sender
-                    .send(Message::Received((header, message)))
-                {
+                // Send the received message to the cameras, telling them where to answer it
+                if let Err(error) = inner_guard.sender.send(Message::Received((
+                    inner_guard.address.clone(),
+                    header,
+                    message,
+                ))) {
                     error!("Failed handling message: {error:?}");
                     continue;
                 }
@@ -130,23 +197,31 @@ impl Manager {
             {
                 let mut inner = inner.write().unwrap();
                 let address = inner.address.clone();
-                inner.connection.replace(Connection::connect(&address));
+                inner.connection = Connection::connect(&address, &stopped);
             }
 
             std::thread::sleep(std::time::Duration::from_millis(500));
         }
+
+        debug!("MavReceiver stopped");
     }
 
-    #[instrument(level = "debug", skip(inner))]
-    fn sender_loop(inner: Arc<RwLock<Connection>>) {
-        let mut receiver = { inner.read().unwrap().sender.subscribe() };
+    #[instrument(level = "debug", skip(inner, stopped))]
+    fn sender_loop(inner: Arc<RwLock<Connection>>, stopped: Arc<AtomicBool>) {
+        let (mut receiver, address) = {
+            let inner = inner.read().unwrap();
+            (inner.sender.subscribe(), inner.address.clone())
+        };
+
+        // Links with little bandwidth only get the unsolicited messages the ground stations asked an interval for
+        let mut rate_limiter = super::message_intervals::is_bandwidth_constrained(&address)
+            .then(|| super::message_intervals::RateLimiter::new(&address));
 
-        loop {
+        while !stopped.load(Ordering::Relaxed) {
             loop {
                 // Receive answer from the cameras
-                let (header, message) = match receiver.blocking_recv() {
-                    Ok(Message::ToBeSent(message)) => message,
-                    Ok(Message::Received(_)) => continue,
+                let message = match receiver.blocking_recv() {
+                    Ok(message) => message,
                     Err(broadcast::error::RecvError::Closed) => {
                         unreachable!(
                             "Closed channel: This should never happen, this channel is static!"
@@ -158,6 +233,34 @@ impl Manager {
                     }
                 };
 
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let is_unsolicited = matches!(message, Message::ToBeSent(_));
+                let Some((header, message)) = outgoing(message, &address) else {
+                    continue;
+                };
+
+                // The messages disabled through this link aren't sent through it even when they change
+                if is_unsolicited
+                    && super::message_intervals::is_disabled(
+                        &address,
+                        header.component_id,
+                        message.message_id(),
+                    )
+                {
+                    continue;
+                }
+
+                if let Some(rate_limiter) = rate_limiter.as_mut() {
+                    if is_unsolicited
+                        && !rate_limiter.allow(&header, &message, std::time::Instant::now())
+                    {
+                        continue;
+                    }
+                }
+
                 let Ok(inner_guard) = inner.read() else {
                     break; // Break to trigger reconnection
                 };
@@ -175,31 +278,87 @@ impl Manager {
                 debug!("Message sent: {header:?}, {message:?}");
             }
 
+            if stopped.load(Ordering::Relaxed) {
+                break;
+            }
+
             // Reconnects
             {
                 let mut inner = inner.write().unwrap();
                 let address = inner.address.clone();
-                inner.connection.replace(Connection::connect(&address));
+                inner.connection = Connection::connect(&address, &stopped);
             }
 
             std::thread::sleep(std::time::Duration::from_millis(500));
         }
+
+        debug!("MavSender stopped");
     }
 
+    /// The connection strings of the endpoints the cameras are reachable through
     #[instrument(level = "debug")]
-    pub fn new_component_id() -> u8 {
+    pub fn endpoints() -> Vec<String> {
         let manager = MANAGER.lock().unwrap();
+        let endpoints = manager.endpoints.read().unwrap();
 
-        let mut id = mavlink::common::MavComponent::MAV_COMP_ID_CAMERA as u8;
-        let mut vector = manager.ids.write().unwrap();
+        let mut endpoints = endpoints.keys().cloned().collect::<Vec<String>>();
+        endpoints.sort();
+        endpoints
+    }
+
+    /// Connects to one more endpoint, which receives everything the cameras send, along with the other endpoints
+    #[instrument(level = "debug")]
+    pub fn add_endpoint(address: &str) -> Result<()> {
+        validate_endpoint(address)?;
 
-        // Find the closest ID available
-        while vector.contains(&id) {
-            id += 1;
+        let manager = MANAGER.lock().unwrap();
+        let mut endpoints = manager.endpoints.write().unwrap();
+
+        if endpoints.contains_key(address) {
+            return Err(anyhow!("MAVLink endpoint {address:?} already exists"));
         }
 
+        endpoints.insert(
+            address.to_string(),
+            Endpoint::new(address, manager.sender.clone()),
+        );
+        save_extra_endpoints(&endpoints);
+
+        info!("MAVLink endpoint {address:?} added");
+
+        Ok(())
+    }
+
+    /// Disconnects from the endpoint, leaving the other ones untouched
+    #[instrument(level = "debug")]
+    pub fn remove_endpoint(address: &str) -> Result<()> {
+        let manager = MANAGER.lock().unwrap();
+        let mut endpoints = manager.endpoints.write().unwrap();
+
+        let endpoint = endpoints
+            .remove(address)
+            .context(format!("MAVLink endpoint {address:?} not found"))?;
+        endpoint.stop();
+        save_extra_endpoints(&endpoints);
+        super::message_intervals::remove_link(address);
+
+        info!("MAVLink endpoint {address:?} removed");
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug")]
+    pub fn new_component_id() -> Option<u8> {
+        let manager = MANAGER.lock().unwrap();
+        let mut vector = manager.ids.write().unwrap();
+
+        let Some(id) = available_component_id(&vector) else {
+            warn!("All the MAVLink camera component ids are in use: {vector:?}");
+            return None;
+        };
+
         vector.push(id);
-        id
+        Some(id)
     }
 
     #[instrument(level = "debug")]
@@ -218,24 +377,85 @@ impl Manager {
     pub fn get_sender() -> broadcast::Sender<Message> {
         let manager = MANAGER.lock().unwrap();
 
-        let connection = manager.connection.read().unwrap();
+        manager.sender.clone()
+    }
+}
+
+/// The closest camera component id not in use
+fn available_component_id(ids: &[u8]) -> Option<u8> {
+    let first = mavlink::common::MavComponent::MAV_COMP_ID_CAMERA as u8;
+    let last = mavlink::common::MavComponent::MAV_COMP_ID_CAMERA6 as u8;
+
+    (first..=last).find(|id| !ids.contains(id))
+}
+
+/// Keeps the endpoints not given in the CLI arguments in the settings. The ones from the CLI arguments are connected
+/// on every start, even if removed meanwhile
+fn save_extra_endpoints(endpoints: &HashMap<String, Endpoint>) {
+    let cli_endpoints = cli::manager::mavlink_connection_strings();
 
-        connection.sender.clone()
+    let mut extra_endpoints = endpoints
+        .keys()
+        .filter(|address| !cli_endpoints.contains(address))
+        .cloned()
+        .collect::<Vec<String>>();
+    extra_endpoints.sort();
+
+    settings::manager::set_mavlink_extra_endpoints(extra_endpoints);
+}
+
+/// The message to be sent through the endpoint, if any
+fn outgoing(message: Message, address: &str) -> Option<(MavHeader, MavMessage)> {
+    match message {
+        Message::ToBeSent(message) => Some(message),
+        Message::Reply((destination, header, message)) if destination == address => {
+            Some((header, message))
+        }
+        Message::Reply(_) | Message::Received(_) => None,
+    }
+}
+
+#[instrument(level = "debug")]
+pub fn validate_endpoint(address: &str) -> Result<()> {
+    let (kind, rest) = address.split_once(':').context(format!(
+        "MAVLink endpoint {address:?} should be like \"udpin:0.0.0.0:14550\""
+    ))?;
+
+    if !ENDPOINT_KINDS.contains(&kind) {
+        return Err(anyhow!(
+            "MAVLink endpoint {address:?} should start with one of {ENDPOINT_KINDS:?}"
+        ));
+    }
+
+    if rest.trim().is_empty() {
+        return Err(anyhow!(
+            "MAVLink endpoint {address:?} is missing its address"
+        ));
     }
+
+    Ok(())
 }
 
 impl Connection {
-    #[instrument(level = "debug")]
-    fn connect(address: &str) -> Box<dyn MavConnection<MavMessage> + Sync + Send> {
+    /// Connects to the address, retrying until it succeeds. Returns None if the endpoint is stopped meanwhile
+    #[instrument(level = "debug", skip(stopped))]
+    fn connect(
+        address: &str,
+        stopped: &AtomicBool,
+    ) -> Option<Box<dyn MavConnection<MavMessage> + Sync + Send>> {
         loop {
             std::thread::sleep(std::time::Duration::from_secs(1));
 
+            if stopped.load(Ordering::Relaxed) {
+                return None;
+            }
+
             debug!("Connecting...");
 
             match mavlink::connect(address) {
                 Ok(connection) => {
                     info!("Successfully connected");
-                    return connection;
+                    return Some(connection);
                 }
                 Err(error) => {
                     error!("Failed to connect, trying again in one second. Reason: {error:?}.");
@@ -244,3 +464,62 @@ impl Connection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_endpoint() {
+        assert!(validate_endpoint("udpin:0.0.0.0:14550").is_ok());
+        assert!(validate_endpoint("tcpout:192.168.2.1:5760").is_ok());
+        assert!(validate_endpoint("serial:/dev/ttyUSB0:57600").is_ok());
+        assert!(validate_endpoint("udp:0.0.0.0:14550").is_err());
+        assert!(validate_endpoint("serial:").is_err());
+        assert!(validate_endpoint("0.0.0.0").is_err());
+    }
+
+    #[test]
+    fn test_outgoing() {
+        let message = || {
+            (
+                MavHeader::default(),
+                MavMessage::HEARTBEAT(mavlink::common::HEARTBEAT_DATA::default()),
+            )
+        };
+        let reply = |address: &str| {
+            let (header, message) = message();
+            Message::Reply((address.to_string(), header, message))
+        };
+
+        // Answers only go back through the endpoint the message came from
+        assert!(outgoing(
+            reply("serial:/dev/ttyUSB0:57600"),
+            "serial:/dev/ttyUSB0:57600"
+        )
+        .is_some());
+        assert!(outgoing(reply("serial:/dev/ttyUSB0:57600"), "tcpin:0.0.0.0:5760").is_none());
+        assert!(outgoing(Message::ToBeSent(message()), "tcpin:0.0.0.0:5760").is_some());
+        let (header, message) = message();
+        assert!(outgoing(
+            Message::Received(("tcpin:0.0.0.0:5760".to_string(), header, message)),
+            "tcpin:0.0.0.0:5760"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_available_component_id() {
+        assert_eq!(available_component_id(&[]), Some(100));
+        assert_eq!(available_component_id(&[100, 101, 103]), Some(102));
+        assert_eq!(
+            available_component_id(&[100, 101, 102, 103, 104]),
+            Some(105)
+        );
+        // Past MAV_COMP_ID_CAMERA6
+        assert_eq!(
+            available_component_id(&[100, 101, 102, 103, 104, 105]),
+            None
+        );
+    }
+}
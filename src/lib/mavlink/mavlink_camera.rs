@@ -1,25 +1,32 @@
 use std::sync::Arc;
 
 use crate::{
-    cli, mavlink::mavlink_camera_component::MavlinkCameraComponent,
-    network::utils::get_visible_qgc_address, video::types::VideoSourceType,
+    cli,
+    mavlink::mavlink_camera_component::MavlinkCameraComponent,
+    network::utils::get_visible_qgc_address,
+    stream::types::{CameraMode, RecordingSettings},
+    video::types::VideoSourceType,
     video_stream::types::VideoAndStreamInformation,
 };
 
 use anyhow::{anyhow, Context, Result};
-use mavlink::{common::MavMessage, MavHeader};
+use mavlink::{common::MavMessage, MavHeader, MessageData};
 use tokio::sync::broadcast;
 use tracing::*;
 use url::Url;
 
 use super::manager::Message;
+use super::message_intervals;
+use super::params::{self, ParamTable};
 use super::utils::*;
 
 #[derive(Debug)]
 pub struct MavlinkCamera {
     inner: Arc<MavlinkCameraInner>,
-    heartbeat_handle: Option<tokio::task::JoinHandle<()>>,
+    periodic_messages_handle: Option<tokio::task::JoinHandle<()>>,
     messages_handle: Option<tokio::task::JoinHandle<()>>,
+    stream_status_handle: Option<tokio::task::JoinHandle<()>>,
+    params_sync_handle: Option<tokio::task::JoinHandle<()>>,
 }
 
 #[derive(Debug, Clone)]
@@ -29,24 +36,62 @@ struct MavlinkCameraInner {
     video_stream_uri: Url,
     video_stream_name: String,
     video_source_type: VideoSourceType,
+    stream_id: uuid::Uuid,
+    mode: Arc<std::sync::Mutex<CameraMode>>,
+    image_capture: Arc<std::sync::Mutex<ImageCaptureState>>,
+    video_capture: Arc<std::sync::Mutex<VideoCaptureState>>,
+    /// If the stream's pipeline is playing, as reported in the video stream status
+    running: Arc<std::sync::atomic::AtomicBool>,
+}
+
+#[derive(Debug, Default)]
+struct VideoCaptureState {
+    started_at: Option<std::time::Instant>,
+    /// The link the capture status interval was set for when the recording started, to be set back once it stops
+    status_interval_link: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct ImageCaptureState {
+    handle: Option<tokio::task::JoinHandle<()>>,
+    /// Seconds between each image of the current capture, zero when capturing a single image
+    interval: f32,
+    /// Number of images captured since this camera was created, used as the image index
+    image_count: i32,
+}
+
+impl ImageCaptureState {
+    fn is_capturing(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
 }
 
 impl MavlinkCamera {
     #[instrument(level = "debug")]
-    pub async fn try_new(video_and_stream_information: &VideoAndStreamInformation) -> Result<Self> {
-        let inner = Arc::new(MavlinkCameraInner::try_new(video_and_stream_information)?);
+    pub async fn try_new(
+        video_and_stream_information: &VideoAndStreamInformation,
+        stream_id: &uuid::Uuid,
+    ) -> Result<Self> {
+        let inner = Arc::new(MavlinkCameraInner::try_new(
+            video_and_stream_information,
+            stream_id,
+        )?);
 
         let sender = crate::mavlink::manager::Manager::get_sender();
 
-        debug!("Starting MAVLink HeartBeat task...");
+        message_intervals::register(inner.component.component_id);
+
+        debug!("Starting MAVLink Periodic Messages task...");
 
         let inner_cloned = inner.clone();
         let sender_cloned = sender.clone();
-        let heartbeat_handle = Some(tokio::spawn(async move {
-            debug!("MAVLink HeartBeat task started!");
-            match MavlinkCameraInner::heartbeat_loop(inner_cloned, sender_cloned).await {
-                Ok(_) => debug!("MAVLink HeartBeat task eneded with no errors"),
-                Err(error) => warn!("MAVLink HeartBeat task ended with error: {error:#?}"),
+        let periodic_messages_handle = Some(tokio::spawn(async move {
+            debug!("MAVLink Periodic Messages task started!");
+            match MavlinkCameraInner::periodic_messages_loop(inner_cloned, sender_cloned).await {
+                Ok(_) => debug!("MAVLink Periodic Messages task eneded with no errors"),
+                Err(error) => warn!("MAVLink Periodic Messages task ended with error: {error:#?}"),
             };
         }));
 
@@ -62,21 +107,57 @@ impl MavlinkCamera {
             };
         }));
 
+        debug!("Starting MAVLink Stream Status task...");
+
+        let inner_cloned = inner.clone();
+        let sender_cloned = sender.clone();
+        let stream_status_handle = Some(tokio::spawn(async move {
+            debug!("MAVLink Stream Status task started!");
+            match MavlinkCameraInner::stream_status_loop(inner_cloned, sender_cloned).await {
+                Ok(_) => debug!("MAVLink Stream Status task eneded with no errors"),
+                Err(error) => warn!("MAVLink Stream Status task ended with error: {error:#?}"),
+            };
+        }));
+
+        debug!("Starting MAVLink Params Sync task...");
+
+        let inner_cloned = inner.clone();
+        let sender_cloned = sender.clone();
+        let params_sync_handle = Some(tokio::spawn(async move {
+            debug!("MAVLink Params Sync task started!");
+            match MavlinkCameraInner::params_sync_loop(inner_cloned, sender_cloned).await {
+                Ok(_) => debug!("MAVLink Params Sync task eneded with no errors"),
+                Err(error) => warn!("MAVLink Params Sync task ended with error: {error:#?}"),
+            };
+        }));
+
         Ok(Self {
             inner,
-            heartbeat_handle,
+            periodic_messages_handle,
             messages_handle,
+            stream_status_handle,
+            params_sync_handle,
         })
     }
+
+    /// The MAVLink component id this camera is advertised with
+    pub fn component_id(&self) -> u8 {
+        self.inner.component.component_id
+    }
 }
 
 impl MavlinkCameraInner {
     #[instrument(level = "debug")]
-    pub fn try_new(video_and_stream_information: &VideoAndStreamInformation) -> Result<Self> {
-        let video_stream_uri = video_and_stream_information
-            .stream_information
-            .endpoints
-            .first()
+    pub fn try_new(
+        video_and_stream_information: &VideoAndStreamInformation,
+        stream_id: &uuid::Uuid,
+    ) -> Result<Self> {
+        // Prefer the endpoints MAVLink can describe, like when SRT endpoints come first
+        let endpoints = &video_and_stream_information.stream_information.endpoints;
+        let video_stream_uri = endpoints
+            .iter()
+            .find(|endpoint| matches!(endpoint.scheme(), "rtsp" | "udp"))
+            .or(endpoints.first())
             .context("Empty URI list")?
             .to_owned();
 
@@ -90,13 +171,15 @@ impl MavlinkCameraInner {
             }
         };
 
-        let video_stream_name = video_and_stream_information.name.clone();
+        let video_stream_name = video_and_stream_information.display_name().to_string();
 
         let video_source_type = video_and_stream_information.video_source.clone();
 
-        let component_id = super::manager::Manager::new_component_id();
-        let component =
-            MavlinkCameraComponent::try_new(video_and_stream_information, component_id)?;
+        let component_id = super::manager::Manager::new_component_id().context(
+            "All the MAVLink camera component ids are in use, MAVLink supports up to 6 cameras per system",
+        )?;
+        let component = MavlinkCameraComponent::try_new(video_and_stream_information, component_id)
+            .inspect_err(|_| super::manager::Manager::drop_id(component_id))?;
 
         let this = Self {
             component,
@@ -104,6 +187,16 @@ impl MavlinkCameraInner {
             video_stream_uri,
             video_stream_name,
             video_source_type,
+            stream_id: *stream_id,
+            mode: Arc::new(std::sync::Mutex::new(
+                video_and_stream_information
+                    .stream_information
+                    .camera_mode(),
+            )),
+            image_capture: Default::default(),
+            video_capture: Default::default(),
+            // The camera is created once its stream was started
+            running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
         };
 
         debug!("Starting new MAVLink camera: {this:#?}");
@@ -121,7 +214,8 @@ impl MavlinkCameraInner {
         // systems.
         let visible_qgc_ip_address = get_visible_qgc_address();
         let address = cli::manager::server_address();
-        let server_port = address.split(':').collect::<Vec<&str>>()[1];
+        // The port comes after the last colon, as IPv6 addresses have their own
+        let server_port = address.rsplit_once(':')?.1;
         let video_source_path = self.video_source_type.inner().source_string();
         Url::parse(&format!(
             "http://{visible_qgc_ip_address}:{server_port}/xml?file={video_source_path}"
@@ -129,38 +223,210 @@ impl MavlinkCameraInner {
         .ok()
     }
 
-    #[instrument(level = "trace", skip(sender))]
+    /// Sends the heartbeat and the messages the ground stations asked an interval for with
+    /// MAV_CMD_SET_MESSAGE_INTERVAL, each one at its own interval, through each link with the intervals asked through it
     #[instrument(level = "debug", skip_all, fields(component_id = camera.component.component_id))]
-    pub async fn heartbeat_loop(
+    pub async fn periodic_messages_loop(
         camera: Arc<MavlinkCameraInner>,
         sender: broadcast::Sender<Message>,
     ) -> Result<()> {
+        // The longest wait between checks, so changed intervals and links are picked up soon enough
+        const MAX_WAIT: std::time::Duration = std::time::Duration::from_millis(100);
+
         let component_id = camera.component.component_id;
-        let system_id = camera.component.system_id;
+        let mut last_sent = std::collections::HashMap::<(String, u32), std::time::Instant>::new();
 
-        let mut period = tokio::time::interval(tokio::time::Duration::from_secs(1));
         loop {
-            period.tick().await;
+            let now = std::time::Instant::now();
+            let mut next = now + MAX_WAIT;
+
+            let links = crate::mavlink::manager::Manager::endpoints();
+            last_sent.retain(|(link, _), _| links.contains(link));
+
+            for link in links {
+                for (message_id, _) in message_intervals::PERIODIC_MESSAGES {
+                    let key = (link.clone(), message_id);
+
+                    let Some(interval) =
+                        message_intervals::interval(&link, component_id, message_id)
+                    else {
+                        last_sent.remove(&key);
+                        continue;
+                    };
+
+                    let due = last_sent
+                        .get(&key)
+                        .map_or(now, |last_sent| *last_sent + interval);
+                    if due > now {
+                        next = next.min(due);
+                        continue;
+                    }
+
+                    last_sent.insert(key, now);
+                    next = next.min(now + interval);
+
+                    let Some(message) = camera.periodic_message(message_id) else {
+                        continue;
+                    };
+                    let header = camera.component.header(None);
+                    if let Err(error) = sender.send(Message::Reply((link.clone(), header, message)))
+                    {
+                        error!("Failed to send message: {error:?}");
+                    }
+                }
+            }
+
+            tokio::time::sleep_until(next.into()).await;
+        }
+    }
+
+    fn periodic_message(&self, message_id: u32) -> Option<MavMessage> {
+        match message_id {
+            mavlink::common::HEARTBEAT_DATA::ID => Some(self.heartbeat_message()),
+            mavlink::common::VIDEO_STREAM_STATUS_DATA::ID => {
+                Some(self.video_stream_status_message())
+            }
+            mavlink::common::CAMERA_CAPTURE_STATUS_DATA::ID => Some(self.capture_status_message()),
+            _ => None,
+        }
+    }
+
+    fn heartbeat_message(&self) -> MavMessage {
+        MavMessage::HEARTBEAT(mavlink::common::HEARTBEAT_DATA {
+            custom_mode: 0,
+            mavtype: mavlink::common::MavType::MAV_TYPE_CAMERA,
+            autopilot: mavlink::common::MavAutopilot::MAV_AUTOPILOT_INVALID,
+            base_mode: mavlink::common::MavModeFlag::empty(),
+            // The camera only exists while its stream is running
+            system_status: mavlink::common::MavState::MAV_STATE_ACTIVE,
+            mavlink_version: 0x3,
+        })
+    }
+
+    /// The URL the ground stations should use to receive the stream, which is resolved on every request, just like
+    /// the camera definition, as the default network route might change
+    #[instrument(level = "debug")]
+    pub fn video_stream_uri(&self) -> Url {
+        reachable_stream_uri(&self.video_stream_uri, &get_visible_qgc_address())
+    }
+
+    fn video_stream_status_message(&self) -> MavMessage {
+        MavMessage::VIDEO_STREAM_STATUS(mavlink::common::VIDEO_STREAM_STATUS_DATA {
+            framerate: self.component.framerate,
+            bitrate: self.component.bitrate,
+            flags: get_stream_status_flag(
+                &self.component,
+                self.running.load(std::sync::atomic::Ordering::Relaxed),
+            ),
+            resolution_h: self.component.resolution_h,
+            resolution_v: self.component.resolution_v,
+            rotation: self.component.rotation,
+            hfov: self.component.hfov,
+            stream_id: self.component.stream_id,
+        })
+    }
+
+    /// Sends the video stream status whenever the stream's pipeline starts or stops playing, so the ground stations
+    /// don't need to poll it, except through the links its interval was disabled through
+    #[instrument(level = "debug", skip_all, fields(component_id = camera.component.component_id))]
+    pub async fn stream_status_loop(
+        camera: Arc<MavlinkCameraInner>,
+        sender: broadcast::Sender<Message>,
+    ) -> Result<()> {
+        use crate::stream::pipeline::runner::PipelineEventKind;
+
+        let mut events = crate::stream::pipeline::runner::subscribe_events();
 
-            let header = mavlink::MavHeader {
-                system_id,
-                component_id,
-                ..Default::default()
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
             };
+            if event.pipeline_id != camera.stream_id {
+                continue;
+            }
 
-            let message = MavMessage::HEARTBEAT(mavlink::common::HEARTBEAT_DATA {
-                custom_mode: 0,
-                mavtype: mavlink::common::MavType::MAV_TYPE_CAMERA,
-                autopilot: mavlink::common::MavAutopilot::MAV_AUTOPILOT_INVALID,
-                base_mode: mavlink::common::MavModeFlag::empty(),
-                system_status: mavlink::common::MavState::MAV_STATE_STANDBY,
-                mavlink_version: 0x3,
-            });
+            let running = match event.kind {
+                PipelineEventKind::Playing => true,
+                PipelineEventKind::StateChanged { current, .. } => current == "Playing",
+                PipelineEventKind::Restarting { .. }
+                | PipelineEventKind::Eos
+                | PipelineEventKind::Error { .. }
+                | PipelineEventKind::Stopped { .. } => false,
+                _ => continue,
+            };
+            if camera
+                .running
+                .swap(running, std::sync::atomic::Ordering::Relaxed)
+                == running
+            {
+                continue;
+            }
+
+            let our_header = camera.component.header(None);
+            let message = camera.video_stream_status_message();
+            if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+                warn!("Failed to send message: {error:?}");
+            }
+        }
 
-            if let Err(error) = sender.send(Message::ToBeSent((header, message))) {
-                error!("Failed to send message: {error:?}");
+        Ok(())
+    }
+
+    /// Sends the new value of the parameters whose controls were written through any interface, like the REST API,
+    /// so the ground stations show their current values
+    #[instrument(level = "debug", skip_all, fields(component_id = camera.component.component_id))]
+    pub async fn params_sync_loop(
+        camera: Arc<MavlinkCameraInner>,
+        sender: broadcast::Sender<Message>,
+    ) -> Result<()> {
+        let mut writes = crate::video::video_source::subscribe_control_writes();
+
+        loop {
+            let write = match writes.recv().await {
+                Ok(write) => write,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            if write.source_string != camera.video_source_type.inner().source_string() {
                 continue;
             }
+
+            let table = ParamTable::new(&camera.video_source_type.inner().controls());
+            let Some(param) = table.by_control_id(write.control_id) else {
+                continue;
+            };
+
+            camera.send_param_value(&sender, None, &table, param, write.value);
+        }
+
+        Ok(())
+    }
+
+    /// Sends the parameter's value through the given link, or through all of them
+    fn send_param_value(
+        &self,
+        sender: &broadcast::Sender<Message>,
+        link: Option<&str>,
+        table: &ParamTable,
+        param: &params::Param,
+        value: i64,
+    ) {
+        let our_header = self.component.header(None);
+        let message = MavMessage::PARAM_VALUE(mavlink::common::PARAM_VALUE_DATA {
+            param_value: params::param_value(value),
+            param_count: table.count(),
+            param_index: param.index,
+            param_id: params::encode_param_id(&param.param_id),
+            param_type: param.param_type,
+        });
+        let message = match link {
+            Some(link) => Message::Reply((link.to_string(), our_header, message)),
+            None => Message::ToBeSent((our_header, message)),
+        };
+        if let Err(error) = sender.send(message) {
+            warn!("Failed to send message: {error:?}");
         }
     }
 
@@ -175,12 +441,13 @@ impl MavlinkCameraInner {
 
         loop {
             match receiver.recv().await {
-                Ok(Received((header, message))) => {
+                Ok(Received((link, header, message))) => {
                     trace!("Message received: {header:?}, {message:?}");
 
-                    Self::handle_message(camera.clone(), sender.clone(), header, message).await;
+                    Self::handle_message(camera.clone(), sender.clone(), link, header, message)
+                        .await;
                 }
-                Ok(Message::ToBeSent(_)) => (),
+                Ok(Message::ToBeSent(_) | Message::Reply(_)) => (),
                 Err(error) => {
                     error!("Failed receiving from broadcast channel: {error:#?}. Resubscribing to the channel...");
 
@@ -195,25 +462,38 @@ impl MavlinkCameraInner {
     async fn handle_message(
         camera: Arc<MavlinkCameraInner>,
         sender: broadcast::Sender<Message>,
+        link: String,
         header: MavHeader,
         message: MavMessage,
     ) {
         match &message {
             MavMessage::COMMAND_LONG(data) => {
                 debug!("Received message");
-                Self::handle_command_long(&camera, sender, &header, data).await;
+                Self::handle_command_long(&camera, sender, &link, &header, data).await;
+            }
+            MavMessage::PARAM_REQUEST_LIST(data) => {
+                debug!("Received message");
+                Self::handle_param_request_list(&camera, sender, &link, &header, data).await;
+            }
+            MavMessage::PARAM_REQUEST_READ(data) => {
+                debug!("Received message");
+                Self::handle_param_request_read(&camera, sender, &link, &header, data).await;
+            }
+            MavMessage::PARAM_SET(data) => {
+                debug!("Received message");
+                Self::handle_param_set(&camera, sender, &link, &header, data).await;
             }
             MavMessage::PARAM_EXT_SET(data) => {
                 debug!("Received message");
-                Self::handle_param_ext_set(&camera, sender, &header, data).await;
+                Self::handle_param_ext_set(&camera, sender, &link, &header, data).await;
             }
             MavMessage::PARAM_EXT_REQUEST_READ(data) => {
                 debug!("Received message");
-                Self::handle_param_ext_request_read(&camera, sender, &header, data).await;
+                Self::handle_param_ext_request_read(&camera, sender, &link, &header, data).await;
             }
             MavMessage::PARAM_EXT_REQUEST_LIST(data) => {
                 debug!("Received message");
-                Self::handle_param_ext_request_list(&camera, sender, &header, data).await;
+                Self::handle_param_ext_request_list(&camera, sender, &link, &header, data).await;
             }
             MavMessage::HEARTBEAT(_data) => {
                 // We receive a bunch of heartbeat messages, we can ignore it, but as it can be useful for debugging.
@@ -231,18 +511,21 @@ impl MavlinkCameraInner {
     async fn handle_command_long(
         camera: &MavlinkCameraInner,
         sender: broadcast::Sender<Message>,
+        link: &str,
         their_header: &MavHeader,
         data: &mavlink::common::COMMAND_LONG_DATA,
     ) {
         #[instrument(level = "debug", skip(sender))]
         fn send_ack(
             sender: &broadcast::Sender<Message>,
+            link: &str,
             our_header: mavlink::MavHeader,
             their_header: &mavlink::MavHeader,
             command: mavlink::common::MavCmd,
             result: mavlink::common::MavResult,
         ) {
-            if let Err(error) = sender.send(Message::ToBeSent((
+            if let Err(error) = sender.send(Message::Reply((
+                link.to_string(),
                 our_header,
                 MavMessage::COMMAND_ACK(mavlink::common::COMMAND_ACK_DATA { command, result }),
             ))) {
@@ -262,16 +545,35 @@ impl MavlinkCameraInner {
         match data.command {
             mavlink::common::MavCmd::MAV_CMD_REQUEST_CAMERA_INFORMATION => {
                 let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
-                send_ack(&sender, our_header, their_header, data.command, result);
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+
+                let has_zoom = crate::stream::manager::zoom(&camera.stream_id)
+                    .await
+                    .is_ok();
+                let flags = camera_capability_flags(has_zoom);
+
+                // Without a camera definition, the ground stations only show the controls of the flags
+                let cam_definition_uri = camera
+                    .cam_definition_uri()
+                    .map(|uri| uri.to_string())
+                    .unwrap_or_default();
 
                 let message =
                     MavMessage::CAMERA_INFORMATION(mavlink::common::CAMERA_INFORMATION_DATA {
                         time_boot_ms: super::sys_info::sys_info().time_boot_ms,
                         firmware_version: 0,
-                        focal_length: 0.0,
+                        // Zero when unknown
+                        focal_length: camera.component.focal_length,
                         sensor_size_h: 0.0,
                         sensor_size_v: 0.0,
-                        flags: mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_HAS_VIDEO_STREAM,
+                        flags,
                         resolution_h: camera.component.resolution_h,
                         resolution_v: camera.component.resolution_v,
                         cam_definition_version: 0,
@@ -279,71 +581,297 @@ impl MavlinkCameraInner {
                             &camera.component.vendor_name,
                         ),
                         model_name: from_string_to_sized_u8_array_with_null_terminator(
-                            &camera.component.vendor_name,
+                            &camera.component.model_name,
                         ),
 
                         lens_id: 0,
                         cam_definition_uri: from_string_to_sized_u8_array_with_null_terminator(
-                            camera.cam_definition_uri().unwrap().as_str(),
+                            &cam_definition_uri,
                         ),
                     });
 
-                if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+                if let Err(error) =
+                    sender.send(Message::Reply((link.to_string(), our_header, message)))
+                {
                     warn!("Failed to send message: {error:?}");
                 }
             }
             mavlink::common::MavCmd::MAV_CMD_REQUEST_CAMERA_SETTINGS => {
                 let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
-                send_ack(&sender, our_header, their_header, data.command, result);
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
 
+                // Zoom and focus levels are extensions not carried by this MAVLink version's CAMERA_SETTINGS
                 let message = MavMessage::CAMERA_SETTINGS(mavlink::common::CAMERA_SETTINGS_DATA {
                     time_boot_ms: super::sys_info::sys_info().time_boot_ms,
-                    mode_id: mavlink::common::CameraMode::CAMERA_MODE_VIDEO,
+                    mode_id: camera.mavlink_mode(),
                 });
 
-                if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+                if let Err(error) =
+                    sender.send(Message::Reply((link.to_string(), our_header, message)))
+                {
                     warn!("Failed to send message: {error:?}");
                 }
             }
-            mavlink::common::MavCmd::MAV_CMD_REQUEST_STORAGE_INFORMATION => {
+            mavlink::common::MavCmd::MAV_CMD_SET_CAMERA_MODE => {
+                let mode = match data.param2 as u32 {
+                    0 => CameraMode::Image,
+                    1 => CameraMode::Video,
+                    unsupported => {
+                        warn!("Unsupported camera mode: {unsupported:?}");
+
+                        let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
+                        send_ack(
+                            &sender,
+                            link,
+                            our_header,
+                            their_header,
+                            data.command,
+                            result,
+                        );
+
+                        return;
+                    }
+                };
+
+                // Switching modes would leave the current capture behind
+                let is_capturing = camera.image_capture.lock().unwrap().is_capturing()
+                    || camera.video_capture.lock().unwrap().started_at.is_some();
+                if is_capturing && *camera.mode.lock().unwrap() != mode {
+                    warn!("Can't change the camera mode to {mode:?} while capturing");
+
+                    let result = mavlink::common::MavResult::MAV_RESULT_TEMPORARILY_REJECTED;
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
+
+                    return;
+                }
+
+                *camera.mode.lock().unwrap() = mode;
+
+                // Persisted, so the mode survives the camera being recreated
+                if let Err(error) =
+                    crate::stream::manager::set_camera_mode(&camera.stream_id, mode).await
+                {
+                    warn!("Failed to persist the camera mode: {error:?}");
+                }
+
                 let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
-                send_ack(&sender, our_header, their_header, data.command, result);
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
 
-                let sys_info = super::sys_info::sys_info();
-                let message =
-                    MavMessage::STORAGE_INFORMATION(mavlink::common::STORAGE_INFORMATION_DATA {
-                        time_boot_ms: sys_info.time_boot_ms,
-                        total_capacity: sys_info.total_capacity,
-                        used_capacity: sys_info.used_capacity,
-                        available_capacity: sys_info.available_capacity,
-                        read_speed: 1000.0,
-                        write_speed: 1000.0,
-                        storage_id: 0,
-                        storage_count: 0,
-                        status: mavlink::common::StorageStatus::STORAGE_STATUS_READY,
-                    });
+                let message = MavMessage::CAMERA_SETTINGS(mavlink::common::CAMERA_SETTINGS_DATA {
+                    time_boot_ms: super::sys_info::sys_info().time_boot_ms,
+                    mode_id: camera.mavlink_mode(),
+                });
 
-                if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+                if let Err(error) =
+                    sender.send(Message::Reply((link.to_string(), our_header, message)))
+                {
                     warn!("Failed to send message: {error:?}");
                 }
             }
+            mavlink::common::MavCmd::MAV_CMD_SET_CAMERA_ZOOM => {
+                let zoom = match crate::stream::manager::zoom(&camera.stream_id).await {
+                    Ok(zoom) => zoom,
+                    Err(error) => {
+                        warn!("Failed to zoom: {error:?}");
+
+                        let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
+                        send_ack(
+                            &sender,
+                            link,
+                            our_header,
+                            their_header,
+                            data.command,
+                            result,
+                        );
+
+                        return;
+                    }
+                };
+
+                // param1 is the CAMERA_ZOOM_TYPE, and param2 its value
+                let result = match data.param1 as u32 {
+                    // ZOOM_TYPE_STEP: -1 zooms out, 1 zooms in
+                    0 => zoom.step(data.param2 as f64),
+                    // ZOOM_TYPE_CONTINUOUS: -1 zooms out, 0 stops, 1 zooms in
+                    1 => {
+                        zoom.start_continuous(data.param2 as f64);
+                        Ok(())
+                    }
+                    // ZOOM_TYPE_RANGE: percentage of the zoom range
+                    2 => zoom.set_range(data.param2 as f64),
+                    unsupported => {
+                        warn!("Unsupported zoom type: {unsupported:?}");
+
+                        let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
+                        send_ack(
+                            &sender,
+                            link,
+                            our_header,
+                            their_header,
+                            data.command,
+                            result,
+                        );
+
+                        return;
+                    }
+                };
+
+                let result = match result {
+                    Ok(_) => mavlink::common::MavResult::MAV_RESULT_ACCEPTED,
+                    Err(error) => {
+                        warn!("Failed to zoom: {error:?}");
+                        mavlink::common::MavResult::MAV_RESULT_FAILED
+                    }
+                };
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+            }
+            mavlink::common::MavCmd::MAV_CMD_REQUEST_STORAGE_INFORMATION => {
+                const ALL_STORAGES: u8 = 0u8;
+                let storage_paths = cli::manager::storage_paths();
+                let requested_storage_id = data.param1 as u8;
+                if requested_storage_id as usize > storage_paths.len() {
+                    warn!("Unknown storage id: {:#?}.", data.param1);
+
+                    let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
+
+                    return;
+                }
+
+                let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+
+                let time_boot_ms = super::sys_info::sys_info().time_boot_ms;
+                let storage_count = storage_paths.len() as u8;
+                for (storage_id, storage_path) in (1..).zip(storage_paths.iter()) {
+                    if requested_storage_id != ALL_STORAGES && requested_storage_id != storage_id {
+                        continue;
+                    }
+
+                    let message = MavMessage::STORAGE_INFORMATION(storage_information(
+                        time_boot_ms,
+                        storage_id,
+                        storage_count,
+                        storage_path,
+                    ));
+
+                    if let Err(error) =
+                        sender.send(Message::Reply((link.to_string(), our_header, message)))
+                    {
+                        warn!("Failed to send message: {error:?}");
+                    }
+                }
+            }
+            mavlink::common::MavCmd::MAV_CMD_STORAGE_FORMAT => {
+                const ALL_STORAGES: u8 = 0u8;
+                let storage_paths = cli::manager::storage_paths();
+                let requested_storage_id = data.param1 as u8;
+                if requested_storage_id as usize > storage_paths.len() {
+                    warn!("Unknown storage id: {:#?}.", data.param1);
+
+                    let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
+
+                    return;
+                }
+
+                // Instead of formatting the filesystem, which is shared with the system, only the captured files are removed
+                let format = data.param2 == 1.0;
+                let mut result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
+                if format {
+                    for (storage_id, storage_path) in (1..).zip(storage_paths.iter()) {
+                        if requested_storage_id != ALL_STORAGES
+                            && requested_storage_id != storage_id
+                        {
+                            continue;
+                        }
+
+                        if let Err(error) = remove_captured_files(storage_path).await {
+                            error!("Failed to erase storage {storage_path:?}: {error:?}");
+                            result = mavlink::common::MavResult::MAV_RESULT_FAILED;
+                        }
+                    }
+                }
+
+                // Reset the image log, so the image indexes start from zero again
+                let reset_image_log = data.param3 == 1.0;
+                if reset_image_log {
+                    camera.image_capture.lock().unwrap().image_count = 0;
+                }
+
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+            }
             mavlink::common::MavCmd::MAV_CMD_REQUEST_CAMERA_CAPTURE_STATUS => {
                 let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
-                send_ack(&sender, our_header, their_header, data.command, result);
-
-                let sys_info = super::sys_info::sys_info();
-                let message = MavMessage::CAMERA_CAPTURE_STATUS(
-                    mavlink::common::CAMERA_CAPTURE_STATUS_DATA {
-                        time_boot_ms: sys_info.time_boot_ms,
-                        image_interval: 0.0,
-                        recording_time_ms: 0,
-                        available_capacity: sys_info.available_capacity,
-                        image_status: 0,
-                        video_status: 0,
-                    },
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
                 );
 
-                if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+                let message = camera.capture_status_message();
+                if let Err(error) =
+                    sender.send(Message::Reply((link.to_string(), our_header, message)))
+                {
                     warn!("Failed to send message: {error:?}");
                 }
             }
@@ -355,43 +883,70 @@ impl MavlinkCameraInner {
                     warn!("Unknown stream id: {:#?}.", data.param2);
 
                     let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
-                    send_ack(&sender, our_header, their_header, data.command, result);
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
 
                     return;
                 }
 
                 let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
-                send_ack(&sender, our_header, their_header, data.command, result);
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
 
-                // The only important information here is the mavtype and uri variables, everything else can be fake
+                // The mavtype and uri are what the ground stations use to receive the stream
                 let message = MavMessage::VIDEO_STREAM_INFORMATION(
                     mavlink::common::VIDEO_STREAM_INFORMATION_DATA {
                         framerate: camera.component.framerate,
                         bitrate: camera.component.bitrate,
-                        flags: get_stream_status_flag(&camera.component),
+                        flags: get_stream_status_flag(
+                            &camera.component,
+                            camera.running.load(std::sync::atomic::Ordering::Relaxed),
+                        ),
                         resolution_h: camera.component.resolution_h,
                         resolution_v: camera.component.resolution_v,
                         rotation: camera.component.rotation,
                         hfov: camera.component.hfov,
                         stream_id: camera.component.stream_id,
-                        count: 0,
+                        // Each camera has a single stream
+                        count: 1,
                         mavtype: camera.mavlink_stream_type,
                         name: from_string_to_sized_u8_array_with_null_terminator(
                             &camera.video_stream_name,
                         ),
                         uri: from_string_to_sized_u8_array_with_null_terminator(
-                            camera.video_stream_uri.as_ref(),
+                            camera.video_stream_uri().as_str(),
                         ),
                     },
                 );
 
-                if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+                if let Err(error) =
+                    sender.send(Message::Reply((link.to_string(), our_header, message)))
+                {
                     warn!("Failed to send message: {error:?}");
                 }
             }
             mavlink::common::MavCmd::MAV_CMD_RESET_CAMERA_SETTINGS => {
                 let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
-                send_ack(&sender, our_header, their_header, data.command, result);
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
 
                 let source_string = camera.video_source_type.inner().source_string();
                 let result = match crate::video::video_source::reset_controls(source_string) {
@@ -402,60 +957,517 @@ impl MavlinkCameraInner {
                     }
                 };
 
-                send_ack(&sender, our_header, their_header, data.command, result);
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
             }
             mavlink::common::MavCmd::MAV_CMD_REQUEST_VIDEO_STREAM_STATUS => {
                 let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
-                send_ack(&sender, our_header, their_header, data.command, result);
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+
+                let message = camera.video_stream_status_message();
 
-                // The only important information here is the mavtype and uri variables, everything else can be fake
+                if let Err(error) =
+                    sender.send(Message::Reply((link.to_string(), our_header, message)))
+                {
+                    warn!("Failed to send message: {error:?}");
+                }
+            }
+            mavlink::common::MavCmd::MAV_CMD_IMAGE_START_CAPTURE => {
+                if *camera.mode.lock().unwrap() != CameraMode::Image {
+                    warn!("Image capture requires the camera to be in the image mode");
+
+                    let result = mavlink::common::MavResult::MAV_RESULT_DENIED;
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
+
+                    return;
+                }
+
+                let interval = data.param2;
+                let total_images = data.param3.max(0.0) as u32;
+
+                // Without an interval, only a single image can be captured
+                if total_images != 1 && interval <= 0.0 {
+                    warn!(
+                        "Invalid image capture interval {interval:?} for {total_images:?} images"
+                    );
+
+                    let result = mavlink::common::MavResult::MAV_RESULT_DENIED;
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
+
+                    return;
+                }
+
+                let mut image_capture = camera.image_capture.lock().unwrap();
+                if image_capture.is_capturing() {
+                    warn!("Image capture already in progress");
+
+                    let result = mavlink::common::MavResult::MAV_RESULT_TEMPORARILY_REJECTED;
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
+
+                    return;
+                }
+
+                let interval = if total_images == 1 { 0.0 } else { interval };
+                image_capture.interval = interval;
+
+                let camera_cloned = camera.clone();
+                let sender_cloned = sender.clone();
+                image_capture.handle = Some(tokio::spawn(async move {
+                    Self::image_capture_loop(camera_cloned, sender_cloned, interval, total_images)
+                        .await
+                }));
+                drop(image_capture);
+
+                let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+            }
+            mavlink::common::MavCmd::MAV_CMD_IMAGE_STOP_CAPTURE => {
+                let mut image_capture = camera.image_capture.lock().unwrap();
+                if let Some(handle) = image_capture.handle.take() {
+                    handle.abort();
+                }
+                image_capture.interval = 0.0;
+                drop(image_capture);
+
+                let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+            }
+            mavlink::common::MavCmd::MAV_CMD_VIDEO_START_CAPTURE => {
+                if *camera.mode.lock().unwrap() != CameraMode::Video {
+                    warn!("Video capture requires the camera to be in the video mode");
+
+                    let result = mavlink::common::MavResult::MAV_RESULT_DENIED;
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
+
+                    return;
+                }
+
+                const ALL_STREAMS: u8 = 0u8;
+                if data.param1 != (camera.component.stream_id as f32)
+                    && data.param1 != (ALL_STREAMS as f32)
+                {
+                    warn!("Unknown stream id: {:#?}.", data.param1);
+
+                    let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
+
+                    return;
+                }
+
+                let settings = RecordingSettings {
+                    path: cli::manager::storage_path(),
+                    format: Default::default(),
+                    max_size_bytes: None,
+                    max_duration_secs: None,
+                };
+
+                let result =
+                    match crate::stream::manager::start_recording(&camera.stream_id, &settings)
+                        .await
+                    {
+                        Ok(location) => {
+                            info!("Recording started to {location:?}");
+                            mavlink::common::MavResult::MAV_RESULT_ACCEPTED
+                        }
+                        Err(error) => {
+                            error!("Failed to start recording: {error:?}");
+                            mavlink::common::MavResult::MAV_RESULT_FAILED
+                        }
+                    };
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+
+                if result != mavlink::common::MavResult::MAV_RESULT_ACCEPTED {
+                    return;
+                }
+
+                let mut video_capture = camera.video_capture.lock().unwrap();
+                video_capture.started_at = Some(std::time::Instant::now());
+
+                // Status frequency, in Hz. Zero means no periodic status. It is sent by the periodic messages task,
+                // just like when its interval is set with MAV_CMD_SET_MESSAGE_INTERVAL
+                let status_frequency = data.param2;
+                if status_frequency > 0.0 {
+                    match message_intervals::set(
+                        link,
+                        camera.component.component_id,
+                        mavlink::common::CAMERA_CAPTURE_STATUS_DATA::ID,
+                        1e6 / status_frequency,
+                    ) {
+                        Ok(_) => video_capture.status_interval_link = Some(link.to_string()),
+                        Err(error) => warn!("Failed to set the capture status interval: {error:?}"),
+                    }
+                }
+            }
+            mavlink::common::MavCmd::MAV_CMD_VIDEO_STOP_CAPTURE => {
+                {
+                    let mut video_capture = camera.video_capture.lock().unwrap();
+                    if let Some(status_interval_link) = video_capture.status_interval_link.take() {
+                        if let Err(error) = message_intervals::set(
+                            &status_interval_link,
+                            camera.component.component_id,
+                            mavlink::common::CAMERA_CAPTURE_STATUS_DATA::ID,
+                            0.0,
+                        ) {
+                            warn!("Failed to reset the capture status interval: {error:?}");
+                        }
+                    }
+                    video_capture.started_at = None;
+                }
+
+                // This only returns after the recording file is finalized
+                let result = match crate::stream::manager::stop_recording(&camera.stream_id).await {
+                    Ok(_) => mavlink::common::MavResult::MAV_RESULT_ACCEPTED,
+                    Err(error) => {
+                        error!("Failed to stop recording: {error:?}");
+                        mavlink::common::MavResult::MAV_RESULT_FAILED
+                    }
+                };
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+
+                let message = camera.capture_status_message();
+                if let Err(error) =
+                    sender.send(Message::Reply((link.to_string(), our_header, message)))
+                {
+                    warn!("Failed to send message: {error:?}");
+                }
+            }
+            mavlink::common::MavCmd::MAV_CMD_DO_DIGICAM_CONTROL => {
+                // Only the shot (param5) is used, saving the instant replay kept in memory
+                if data.param5 != 1.0 {
+                    let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
+                    send_ack(
+                        &sender,
+                        link,
+                        our_header,
+                        their_header,
+                        data.command,
+                        result,
+                    );
+
+                    return;
+                }
+
+                // Finalizing the file takes a while, so it's not waited for
+                let stream_id = camera.stream_id;
+                tokio::spawn(async move {
+                    match crate::stream::manager::record_clip(&stream_id, Default::default()).await
+                    {
+                        Ok(path) => info!("Instant replay saved to {path:?}"),
+                        Err(error) => error!("Failed to save the instant replay: {error:?}"),
+                    }
+                });
+
+                let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+            }
+            mavlink::common::MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL => {
+                let message_id = data.param1 as u32;
+                // Only the link the request came through is affected
+                let result = match message_intervals::set(
+                    link,
+                    camera.component.component_id,
+                    message_id,
+                    data.param2,
+                ) {
+                    Ok(_) => mavlink::common::MavResult::MAV_RESULT_ACCEPTED,
+                    Err(error) => {
+                        warn!("Failed to set the message interval: {error:?}");
+                        mavlink::common::MavResult::MAV_RESULT_DENIED
+                    }
+                };
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+            }
+            mavlink::common::MavCmd::MAV_CMD_GET_MESSAGE_INTERVAL => {
+                let result = mavlink::common::MavResult::MAV_RESULT_ACCEPTED;
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
+
+                let message_id = data.param1 as u32;
+                let interval_us =
+                    message_intervals::interval_us(link, camera.component.component_id, message_id);
                 let message =
-                    MavMessage::VIDEO_STREAM_STATUS(mavlink::common::VIDEO_STREAM_STATUS_DATA {
-                        framerate: camera.component.framerate,
-                        bitrate: camera.component.bitrate,
-                        flags: get_stream_status_flag(&camera.component),
-                        resolution_h: camera.component.resolution_h,
-                        resolution_v: camera.component.resolution_v,
-                        rotation: camera.component.rotation,
-                        hfov: camera.component.hfov,
-                        stream_id: camera.component.stream_id,
+                    MavMessage::MESSAGE_INTERVAL(mavlink::common::MESSAGE_INTERVAL_DATA {
+                        interval_us: interval_us.clamp(-1, i32::MAX as i64) as i32,
+                        message_id: message_id as u16,
                     });
-
-                if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+                if let Err(error) =
+                    sender.send(Message::Reply((link.to_string(), our_header, message)))
+                {
                     warn!("Failed to send message: {error:?}");
                 }
             }
             mavlink::common::MavCmd::MAV_CMD_REQUEST_MESSAGE => {
                 let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
-                send_ack(&sender, our_header, their_header, data.command, result);
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
 
                 warn!("MAVLink message \"MAV_CMD_REQUEST_MESSAGE\" is not supported yet, please report this issue so we can prioritize it. Meanwhile, you can use the original definitions for the MAVLink Camera Protocol. Read more in: https://mavlink.io/en/services/camera.html#migration-notes-for-gcs--mavlink-sdks");
             }
             message => {
                 let result = mavlink::common::MavResult::MAV_RESULT_UNSUPPORTED;
-                send_ack(&sender, our_header, their_header, data.command, result);
+                send_ack(
+                    &sender,
+                    link,
+                    our_header,
+                    their_header,
+                    data.command,
+                    result,
+                );
 
                 trace!("Ignoring unknown message received: {message:?}")
             }
         }
     }
 
+    fn mavlink_mode(&self) -> mavlink::common::CameraMode {
+        match *self.mode.lock().unwrap() {
+            CameraMode::Image => mavlink::common::CameraMode::CAMERA_MODE_IMAGE,
+            CameraMode::Video => mavlink::common::CameraMode::CAMERA_MODE_VIDEO,
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn capture_status_message(&self) -> MavMessage {
+        let (image_interval, image_status) = {
+            let image_capture = self.image_capture.lock().unwrap();
+            // 0: idle, 1: capture in progress, 3: interval set and capture in progress
+            let image_status = match image_capture.is_capturing() {
+                false => 0,
+                true if image_capture.interval > 0.0 => 3,
+                true => 1,
+            };
+            (image_capture.interval, image_status)
+        };
+
+        // 0: idle, 1: capture in progress
+        let (recording_time_ms, video_status) = match self.video_capture.lock().unwrap().started_at
+        {
+            Some(started_at) => (started_at.elapsed().as_millis() as u32, 1),
+            None => (0, 0),
+        };
+
+        let sys_info = super::sys_info::sys_info();
+        let available_capacity =
+            super::sys_info::storage_info(std::path::Path::new(&cli::manager::storage_path()))
+                .map(|storage_info| storage_info.available_capacity)
+                .unwrap_or(sys_info.available_capacity);
+
+        MavMessage::CAMERA_CAPTURE_STATUS(mavlink::common::CAMERA_CAPTURE_STATUS_DATA {
+            time_boot_ms: sys_info.time_boot_ms,
+            image_interval,
+            recording_time_ms,
+            available_capacity,
+            image_status,
+            video_status,
+        })
+    }
+
+    /// Captures `total_images` images (or until stopped, when zero), one every `interval` seconds
+    #[instrument(level = "debug", skip(sender, camera), fields(component_id = camera.component.component_id))]
+    async fn image_capture_loop(
+        camera: MavlinkCameraInner,
+        sender: broadcast::Sender<Message>,
+        interval: f32,
+        total_images: u32,
+    ) {
+        let mut period = tokio::time::interval(std::time::Duration::from_secs_f32(
+            interval.max(f32::EPSILON),
+        ));
+        period.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let mut captured_images = 0;
+        while total_images == 0 || captured_images < total_images {
+            period.tick().await;
+
+            let image_index = {
+                let mut image_capture = camera.image_capture.lock().unwrap();
+                let image_index = image_capture.image_count;
+                image_capture.image_count += 1;
+                image_index
+            };
+
+            let (capture_result, file_url) = match camera.capture_image(image_index).await {
+                Ok(file_path) => {
+                    debug!("Image {image_index} captured to {file_path:?}");
+                    (1, file_path)
+                }
+                Err(error) => {
+                    warn!("Failed capturing image {image_index}: {error:?}");
+                    (0, String::new())
+                }
+            };
+
+            let our_header = camera.component.header(None);
+            let message =
+                MavMessage::CAMERA_IMAGE_CAPTURED(mavlink::common::CAMERA_IMAGE_CAPTURED_DATA {
+                    time_utc: chrono::Utc::now().timestamp_micros() as u64,
+                    time_boot_ms: super::sys_info::sys_info().time_boot_ms,
+                    lat: 0,
+                    lon: 0,
+                    alt: 0,
+                    relative_alt: 0,
+                    q: [1.0, 0.0, 0.0, 0.0],
+                    image_index,
+                    camera_id: 0,
+                    capture_result,
+                    file_url: from_string_to_sized_u8_array_with_null_terminator(&file_url),
+                });
+            if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+                warn!("Failed to send message: {error:?}");
+            }
+
+            captured_images += 1;
+        }
+
+        camera.image_capture.lock().unwrap().interval = 0.0;
+    }
+
+    /// Grabs a frame from the running stream and saves it in the storage path, returning its path
+    #[instrument(level = "debug", skip(self))]
+    async fn capture_image(&self, image_index: i32) -> Result<String> {
+        let image = crate::stream::manager::snapshot(&self.stream_id).await?;
+
+        let directory = std::path::PathBuf::from(cli::manager::storage_path());
+        tokio::fs::create_dir_all(&directory)
+            .await
+            .context(format!("Failed creating storage directory {directory:?}"))?;
+
+        let file_name = format!(
+            "{}-{}-{image_index:05}.jpg",
+            self.video_stream_name
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        );
+        let file_path = directory.join(file_name);
+
+        tokio::fs::write(&file_path, image)
+            .await
+            .context(format!("Failed writing image to {file_path:?}"))?;
+
+        Ok(file_path.to_string_lossy().to_string())
+    }
+
     #[instrument(level = "trace", skip(sender))]
     #[instrument(level = "debug", skip(sender, camera), fields(component_id = camera.component.component_id))]
     async fn handle_param_ext_set(
         camera: &MavlinkCameraInner,
         sender: broadcast::Sender<Message>,
+        link: &str,
         header: &MavHeader,
         data: &mavlink::common::PARAM_EXT_SET_DATA,
     ) {
         #[instrument(level = "debug", skip(sender))]
         fn send_ack(
             sender: &broadcast::Sender<Message>,
+            link: &str,
             our_header: mavlink::MavHeader,
             data: &mavlink::common::PARAM_EXT_SET_DATA,
             result: mavlink::common::ParamAck,
         ) {
-            if let Err(error) = sender.send(Message::ToBeSent((
+            if let Err(error) = sender.send(Message::Reply((
+                link.to_string(),
                 our_header,
                 MavMessage::PARAM_EXT_ACK(mavlink::common::PARAM_EXT_ACK_DATA {
                     param_id: data.param_id,
@@ -481,24 +1493,28 @@ impl MavlinkCameraInner {
         let control_value = control_value_from_param_value(&data.param_value, &data.param_type);
         let (Some(control_id), Some(control_value)) = (control_id, control_value) else {
             let result = mavlink::common::ParamAck::PARAM_ACK_VALUE_UNSUPPORTED;
-            send_ack(&sender, our_header, data, result);
+            send_ack(&sender, link, our_header, data, result);
 
             return;
         };
 
-        let result = match camera
-            .video_source_type
-            .inner()
-            .set_control_by_id(control_id, control_value)
-        {
+        let result = match crate::video::video_source::write_control(
+            camera.video_source_type.clone(),
+            control_id,
+            control_value,
+        ) {
             Ok(_) => mavlink::common::ParamAck::PARAM_ACK_ACCEPTED,
+            Err(error) if error.kind() == std::io::ErrorKind::Unsupported => {
+                warn!("Rejected parameter {control_id:?} with value {control_value:?} for {:#?}. Reason: {error:?}", our_header.component_id);
+                mavlink::common::ParamAck::PARAM_ACK_VALUE_UNSUPPORTED
+            }
             Err(error) => {
                 error!("Failed to set parameter {control_id:?} with value {control_value:?} for {:#?}. Reason: {error:?}", our_header.component_id);
                 mavlink::common::ParamAck::PARAM_ACK_FAILED
             }
         };
 
-        send_ack(&sender, our_header, data, result);
+        send_ack(&sender, link, our_header, data, result);
     }
 
     #[instrument(level = "trace", skip(sender))]
@@ -506,6 +1522,7 @@ impl MavlinkCameraInner {
     async fn handle_param_ext_request_read(
         camera: &MavlinkCameraInner,
         sender: broadcast::Sender<Message>,
+        link: &str,
         header: &MavHeader,
         data: &mavlink::common::PARAM_EXT_REQUEST_READ_DATA,
     ) {
@@ -547,7 +1564,7 @@ impl MavlinkCameraInner {
             param_value,
             param_type: mavlink::common::MavParamExtType::MAV_PARAM_EXT_TYPE_INT64,
         });
-        if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+        if let Err(error) = sender.send(Message::Reply((link.to_string(), our_header, message))) {
             warn!("Failed to send message: {error:?}");
         }
     }
@@ -557,6 +1574,7 @@ impl MavlinkCameraInner {
     async fn handle_param_ext_request_list(
         camera: &MavlinkCameraInner,
         sender: broadcast::Sender<Message>,
+        link: &str,
         header: &MavHeader,
         data: &mavlink::common::PARAM_EXT_REQUEST_LIST_DATA,
     ) {
@@ -598,11 +1616,194 @@ impl MavlinkCameraInner {
                     param_value,
                     param_type: mavlink::common::MavParamExtType::MAV_PARAM_EXT_TYPE_INT64,
                 });
-                if let Err(error) = sender.send(Message::ToBeSent((our_header, message))) {
+                if let Err(error) =
+                    sender.send(Message::Reply((link.to_string(), our_header, message)))
+                {
                     warn!("Failed to send message: {error:?}");
                 }
             });
     }
+
+    #[instrument(level = "trace", skip(sender))]
+    #[instrument(level = "debug", skip(sender, camera), fields(component_id = camera.component.component_id))]
+    async fn handle_param_request_list(
+        camera: &MavlinkCameraInner,
+        sender: broadcast::Sender<Message>,
+        link: &str,
+        header: &MavHeader,
+        data: &mavlink::common::PARAM_REQUEST_LIST_DATA,
+    ) {
+        let our_header = camera.component.header(None);
+
+        if data.target_system != our_header.system_id
+            || data.target_component != our_header.component_id
+        {
+            trace!("Ignoring {data:?}, wrong command id or system id");
+            return;
+        }
+
+        let table = ParamTable::new(&camera.video_source_type.inner().controls());
+        for param in table.iter() {
+            camera.send_param_value(&sender, Some(link), &table, param, param.value);
+        }
+    }
+
+    #[instrument(level = "trace", skip(sender))]
+    #[instrument(level = "debug", skip(sender, camera), fields(component_id = camera.component.component_id))]
+    async fn handle_param_request_read(
+        camera: &MavlinkCameraInner,
+        sender: broadcast::Sender<Message>,
+        link: &str,
+        header: &MavHeader,
+        data: &mavlink::common::PARAM_REQUEST_READ_DATA,
+    ) {
+        let our_header = camera.component.header(None);
+
+        if data.target_system != our_header.system_id
+            || data.target_component != our_header.component_id
+        {
+            trace!("Ignoring {data:?}, wrong command id or system id");
+            return;
+        }
+
+        let table = ParamTable::new(&camera.video_source_type.inner().controls());
+        // The parameter id is used when the index is -1
+        let param = match data.param_index {
+            -1 => table.by_param_id(&params::decode_param_id(&data.param_id)),
+            param_index => table.by_index(param_index as u16),
+        };
+        let Some(param) = param else {
+            warn!(
+                "Unknown parameter {:?} at index {}",
+                params::decode_param_id(&data.param_id),
+                data.param_index
+            );
+            return;
+        };
+
+        camera.send_param_value(&sender, Some(link), &table, param, param.value);
+    }
+
+    #[instrument(level = "trace", skip(sender))]
+    #[instrument(level = "debug", skip(sender, camera), fields(component_id = camera.component.component_id))]
+    async fn handle_param_set(
+        camera: &MavlinkCameraInner,
+        sender: broadcast::Sender<Message>,
+        link: &str,
+        header: &MavHeader,
+        data: &mavlink::common::PARAM_SET_DATA,
+    ) {
+        let our_header = camera.component.header(None);
+
+        if data.target_system != our_header.system_id
+            || data.target_component != our_header.component_id
+        {
+            trace!("Ignoring {data:?}, wrong command id or system id");
+            return;
+        }
+
+        let param_id = params::decode_param_id(&data.param_id);
+        let table = ParamTable::new(&camera.video_source_type.inner().controls());
+        let Some(param) = table.by_param_id(&param_id) else {
+            warn!("Unknown parameter {param_id:?}");
+            return;
+        };
+
+        // The answer is the parameter's value, which stays the same when it isn't written
+        let Some(control_value) = params::control_value(data.param_value) else {
+            warn!(
+                "Rejected parameter {param_id:?} with the non-integer value {:?}",
+                data.param_value
+            );
+            camera.send_param_value(&sender, Some(link), &table, param, param.value);
+            return;
+        };
+
+        match crate::video::video_source::write_control(
+            camera.video_source_type.clone(),
+            param.control_id,
+            control_value,
+        ) {
+            Ok(_) => camera.send_param_value(&sender, Some(link), &table, param, control_value),
+            Err(error) => {
+                warn!("Failed to set parameter {param_id:?} with value {control_value:?} for {:#?}. Reason: {error:?}", our_header.component_id);
+                camera.send_param_value(&sender, Some(link), &table, param, param.value);
+            }
+        }
+    }
+}
+
+#[instrument(level = "debug")]
+fn storage_information(
+    time_boot_ms: u32,
+    storage_id: u8,
+    storage_count: u8,
+    storage_path: &str,
+) -> mavlink::common::STORAGE_INFORMATION_DATA {
+    let path = std::path::Path::new(storage_path);
+    if let Err(error) = std::fs::create_dir_all(path) {
+        warn!("Failed creating storage directory {storage_path:?}: {error:?}");
+    }
+
+    let Some(storage_info) = super::sys_info::storage_info(path) else {
+        warn!("Failed to fetch storage info for {storage_path:?}");
+
+        return mavlink::common::STORAGE_INFORMATION_DATA {
+            time_boot_ms,
+            total_capacity: 0.0,
+            used_capacity: 0.0,
+            available_capacity: 0.0,
+            read_speed: 0.0,
+            write_speed: 0.0,
+            storage_id,
+            storage_count,
+            status: mavlink::common::StorageStatus::STORAGE_STATUS_NOT_SUPPORTED,
+        };
+    };
+
+    // The speeds are unknown, which is represented by zero
+    mavlink::common::STORAGE_INFORMATION_DATA {
+        time_boot_ms,
+        total_capacity: storage_info.total_capacity,
+        used_capacity: storage_info.used_capacity,
+        available_capacity: storage_info.available_capacity,
+        read_speed: 0.0,
+        write_speed: 0.0,
+        storage_id,
+        storage_count,
+        status: mavlink::common::StorageStatus::STORAGE_STATUS_READY,
+    }
+}
+
+/// Removes the images and videos captured into the given storage path
+#[instrument(level = "debug")]
+async fn remove_captured_files(storage_path: &str) -> Result<()> {
+    const CAPTURED_FILES_EXTENSIONS: &[&str] = &["jpg", "mp4", "mkv"];
+
+    let mut entries = match tokio::fs::read_dir(storage_path).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        let is_captured_file = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| CAPTURED_FILES_EXTENSIONS.contains(&extension));
+        if !is_captured_file || !entry.file_type().await?.is_file() {
+            continue;
+        }
+
+        tokio::fs::remove_file(&path)
+            .await
+            .context(format!("Failed removing {path:?}"))?;
+        debug!("Removed {path:?}");
+    }
+
+    Ok(())
 }
 
 impl Drop for MavlinkCamera {
@@ -610,15 +1811,15 @@ impl Drop for MavlinkCamera {
     fn drop(&mut self) {
         debug!("Dropping MavlinkCameraHandle...");
 
-        if let Some(handle) = self.heartbeat_handle.take() {
+        if let Some(handle) = self.periodic_messages_handle.take() {
             if !handle.is_finished() {
                 handle.abort();
                 tokio::spawn(async move {
                     let _ = handle.await;
-                    debug!("Mavlink Heartbeat task aborted");
+                    debug!("Mavlink Periodic Messages task aborted");
                 });
             } else {
-                debug!("Mavlink Heartbeat task nicely finished!");
+                debug!("Mavlink Periodic Messages task nicely finished!");
             }
         }
 
@@ -634,6 +1835,37 @@ impl Drop for MavlinkCamera {
             }
         }
 
+        if let Some(handle) = self.stream_status_handle.take() {
+            if !handle.is_finished() {
+                handle.abort();
+                tokio::spawn(async move {
+                    let _ = handle.await;
+                    debug!("Mavlink Stream Status task aborted");
+                });
+            } else {
+                debug!("Mavlink Stream Status task nicely finished!");
+            }
+        }
+
+        if let Some(handle) = self.params_sync_handle.take() {
+            if !handle.is_finished() {
+                handle.abort();
+                tokio::spawn(async move {
+                    let _ = handle.await;
+                    debug!("Mavlink Params Sync task aborted");
+                });
+            } else {
+                debug!("Mavlink Params Sync task nicely finished!");
+            }
+        }
+
+        if let Some(handle) = self.inner.image_capture.lock().unwrap().handle.take() {
+            handle.abort();
+            debug!("Mavlink image capture task aborted");
+        }
+
+        message_intervals::unregister(self.inner.component.component_id);
+
         super::manager::Manager::drop_id(self.inner.component.component_id);
 
         debug!("MavlinkCameraHandle Dropped!");
@@ -15,6 +15,7 @@ pub struct MavlinkCameraComponent {
     pub firmware_version: u32,
     pub resolution_h: u16,
     pub resolution_v: u16,
+    pub focal_length: f32,
     pub framerate: f32,
     pub bitrate: u32,
     pub rotation: u16,
@@ -33,9 +34,15 @@ impl MavlinkCameraComponent {
             .configuration
         {
             crate::stream::types::CaptureConfiguration::Video(cfg) => {
-                let framerate =
-                    cfg.frame_interval.denominator as f32 / cfg.frame_interval.numerator as f32;
-                (cfg.height as u16, cfg.width as u16, framerate)
+                // The framerate streamed, which the maximum framerate might cap below the captured one
+                let frame_interval = crate::stream::pipeline::frame_rate::output_interval(
+                    &cfg.frame_interval,
+                    video_and_stream_information
+                        .stream_information
+                        .max_framerate(),
+                );
+                let framerate = frame_interval.denominator as f32 / frame_interval.numerator as f32;
+                (cfg.width as u16, cfg.height as u16, framerate)
             }
             crate::stream::types::CaptureConfiguration::Redirect(_) => (0, 0, 0.0),
         };
@@ -57,10 +64,14 @@ impl MavlinkCameraComponent {
                 .inner()
                 .name()
                 .to_string(),
-            model_name: video_and_stream_information.name.clone(),
+            model_name: video_and_stream_information.display_name().to_string(),
             firmware_version: 0,
             resolution_h,
             resolution_v,
+            focal_length: video_and_stream_information
+                .stream_information
+                .focal_length_mm()
+                .unwrap_or_default(),
             bitrate: 5000,
             rotation: 0,
             hfov: 90,
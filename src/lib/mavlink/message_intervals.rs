@@ -0,0 +1,396 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use mavlink::{common::MavMessage, MavHeader, Message, MessageData};
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use tracing::*;
+
+/// The messages each camera emits on its own, which can have their interval changed with MAV_CMD_SET_MESSAGE_INTERVAL
+pub const PERIODIC_MESSAGES: [(u32, &str); 3] = [
+    (
+        mavlink::common::HEARTBEAT_DATA::ID,
+        mavlink::common::HEARTBEAT_DATA::NAME,
+    ),
+    (
+        mavlink::common::VIDEO_STREAM_STATUS_DATA::ID,
+        mavlink::common::VIDEO_STREAM_STATUS_DATA::NAME,
+    ),
+    (
+        mavlink::common::CAMERA_CAPTURE_STATUS_DATA::ID,
+        mavlink::common::CAMERA_CAPTURE_STATUS_DATA::NAME,
+    ),
+];
+
+/// The heartbeat is the only message sent periodically by default, as it is how the cameras are discovered
+const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The shortest interval accepted, so a single request can't flood the links
+const MIN_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Interval {
+    Disabled,
+    Every(Duration),
+}
+
+lazy_static! {
+    /// The intervals requested for the messages of each camera, by component id and by the link they were requested
+    /// through, so each link only gets what was asked through it. Messages without one use their default interval
+    static ref INTERVALS: Mutex<HashMap<u8, HashMap<String, HashMap<u32, Interval>>>> = Default::default();
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Serialize)]
+pub struct MessageIntervalStatus {
+    pub component_id: u8,
+    /// The MAVLink endpoint the messages are sent through, like "serial:/dev/ttyUSB0:57600"
+    pub link: String,
+    pub message_id: u32,
+    pub message_name: String,
+    /// Microseconds between each message, like in MESSAGE_INTERVAL: -1 when disabled and 0 when only sent when
+    /// requested or changed
+    pub interval_us: i64,
+    /// If the interval was requested, instead of being the default one
+    pub requested: bool,
+}
+
+fn default_interval(message_id: u32) -> Option<Duration> {
+    (message_id == mavlink::common::HEARTBEAT_DATA::ID).then_some(DEFAULT_HEARTBEAT_INTERVAL)
+}
+
+/// If the message is one of the [`PERIODIC_MESSAGES`], while the other ones are only sent on events, like
+/// CAMERA_IMAGE_CAPTURED
+pub fn is_periodic_message(message_id: u32) -> bool {
+    PERIODIC_MESSAGES.iter().any(|(id, _)| *id == message_id)
+}
+
+/// Starts tracking the intervals of the camera, all of them with their defaults
+#[instrument(level = "debug")]
+pub fn register(component_id: u8) {
+    INTERVALS
+        .lock()
+        .unwrap()
+        .insert(component_id, Default::default());
+}
+
+/// Forgets the intervals of the camera, so the next one using its component id starts with the defaults
+#[instrument(level = "debug")]
+pub fn unregister(component_id: u8) {
+    INTERVALS.lock().unwrap().remove(&component_id);
+}
+
+/// Forgets the intervals requested through the link, so it starts with the defaults if added again
+#[instrument(level = "debug")]
+pub fn remove_link(link: &str) {
+    for camera_intervals in INTERVALS.lock().unwrap().values_mut() {
+        camera_intervals.remove(link);
+    }
+}
+
+/// Changes the interval of the message sent through the link, following MAV_CMD_SET_MESSAGE_INTERVAL: -1 disables
+/// it, 0 goes back to its default, and anything else is the interval in microseconds
+#[instrument(level = "debug")]
+pub fn set(link: &str, component_id: u8, message_id: u32, interval_us: f32) -> Result<()> {
+    if !is_periodic_message(message_id) {
+        return Err(anyhow!(
+            "Message {message_id} isn't sent periodically by the cameras"
+        ));
+    }
+
+    let interval = match interval_us {
+        interval_us if interval_us == -1.0 => Some(Interval::Disabled),
+        interval_us if interval_us == 0.0 => None,
+        interval_us if interval_us.is_finite() && interval_us > 0.0 => {
+            let interval = Duration::from_secs_f64(interval_us as f64 / 1e6);
+            if interval < MIN_INTERVAL {
+                return Err(anyhow!(
+                    "Interval of {interval:?} is shorter than the minimum of {MIN_INTERVAL:?}"
+                ));
+            }
+            Some(Interval::Every(interval))
+        }
+        _ => return Err(anyhow!("Invalid interval of {interval_us:?} us")),
+    };
+
+    let mut intervals = INTERVALS.lock().unwrap();
+    let link_intervals = intervals
+        .entry(component_id)
+        .or_default()
+        .entry(link.to_string())
+        .or_default();
+    match interval {
+        Some(interval) => link_intervals.insert(message_id, interval),
+        None => link_intervals.remove(&message_id),
+    };
+
+    debug!("Message {message_id} of component {component_id} through {link:?} set to {interval:?}");
+
+    Ok(())
+}
+
+fn requested(link: &str, component_id: u8, message_id: u32) -> Option<Interval> {
+    INTERVALS
+        .lock()
+        .unwrap()
+        .get(&component_id)
+        .and_then(|camera_intervals| camera_intervals.get(link))
+        .and_then(|link_intervals| link_intervals.get(&message_id))
+        .copied()
+}
+
+/// The interval the message is sent periodically with through the link, if any
+pub fn interval(link: &str, component_id: u8, message_id: u32) -> Option<Duration> {
+    match requested(link, component_id, message_id) {
+        Some(Interval::Disabled) => None,
+        Some(Interval::Every(interval)) => Some(interval),
+        None => default_interval(message_id),
+    }
+}
+
+/// If the message was disabled through the link with an interval of -1, so it shouldn't be sent through it even
+/// when it changes
+pub fn is_disabled(link: &str, component_id: u8, message_id: u32) -> bool {
+    requested(link, component_id, message_id) == Some(Interval::Disabled)
+}
+
+/// The interval of the message through the link, as answered in MESSAGE_INTERVAL
+pub fn interval_us(link: &str, component_id: u8, message_id: u32) -> i64 {
+    if is_disabled(link, component_id, message_id) {
+        return -1;
+    }
+
+    interval(link, component_id, message_id).map_or(0, |interval| interval.as_micros() as i64)
+}
+
+/// The interval of every periodic message of each camera, through each of the given links
+pub fn intervals(links: &[String]) -> Vec<MessageIntervalStatus> {
+    let mut component_ids = INTERVALS
+        .lock()
+        .unwrap()
+        .keys()
+        .copied()
+        .collect::<Vec<u8>>();
+    component_ids.sort();
+
+    component_ids
+        .into_iter()
+        .flat_map(|component_id| {
+            links.iter().flat_map(move |link| {
+                PERIODIC_MESSAGES
+                    .iter()
+                    .map(move |(message_id, message_name)| MessageIntervalStatus {
+                        component_id,
+                        link: link.clone(),
+                        message_id: *message_id,
+                        message_name: message_name.to_string(),
+                        interval_us: interval_us(link, component_id, *message_id),
+                        requested: requested(link, component_id, *message_id).is_some(),
+                    })
+            })
+        })
+        .collect()
+}
+
+/// If the endpoint is a link with little bandwidth, which only gets the unsolicited periodic messages with an
+/// interval
+pub fn is_bandwidth_constrained(address: &str) -> bool {
+    address.starts_with("serial:")
+}
+
+/// Keeps the unsolicited messages of the cameras from flooding a link with little bandwidth, like a telemetry radio.
+/// Only the periodic messages with an interval through this link go through, at most once per interval. The answers
+/// to requests and the messages sent on events, like CAMERA_IMAGE_CAPTURED, are never limited
+#[derive(Debug)]
+pub struct RateLimiter {
+    link: String,
+    last_sent: HashMap<(u8, u32), Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(link: &str) -> Self {
+        Self {
+            link: link.to_string(),
+            last_sent: Default::default(),
+        }
+    }
+
+    pub fn allow(&mut self, header: &MavHeader, message: &MavMessage, now: Instant) -> bool {
+        if !is_periodic_message(message.message_id()) {
+            return true;
+        }
+
+        let key = (header.component_id, message.message_id());
+
+        let Some(interval) = interval(&self.link, header.component_id, message.message_id()) else {
+            trace!("Unsolicited {} dropped", message.message_name());
+            return false;
+        };
+
+        // Some slack, so messages sent periodically with the same interval aren't dropped by their jitter
+        if let Some(last_sent) = self.last_sent.get(&key) {
+            if now.duration_since(*last_sent) + interval / 10 < interval {
+                trace!("Unsolicited {} rate limited", message.message_name());
+                return false;
+            }
+        }
+
+        self.last_sent.insert(key, now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERIAL: &str = "serial:/dev/ttyUSB0:57600";
+    const UDP: &str = "udpin:0.0.0.0:14550";
+
+    #[test]
+    fn test_set_interval() {
+        let component_id = 200;
+        let heartbeat = mavlink::common::HEARTBEAT_DATA::ID;
+        let stream_status = mavlink::common::VIDEO_STREAM_STATUS_DATA::ID;
+
+        register(component_id);
+        assert_eq!(
+            interval(UDP, component_id, heartbeat),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(interval(UDP, component_id, stream_status), None);
+        assert_eq!(interval_us(UDP, component_id, stream_status), 0);
+
+        set(UDP, component_id, stream_status, 500_000.0).unwrap();
+        assert_eq!(
+            interval(UDP, component_id, stream_status),
+            Some(Duration::from_millis(500))
+        );
+        // Only the link it was requested through is affected
+        assert_eq!(interval(SERIAL, component_id, stream_status), None);
+
+        set(UDP, component_id, heartbeat, -1.0).unwrap();
+        assert!(is_disabled(UDP, component_id, heartbeat));
+        assert_eq!(interval_us(UDP, component_id, heartbeat), -1);
+        assert!(!is_disabled(SERIAL, component_id, heartbeat));
+
+        // Back to the default
+        set(UDP, component_id, heartbeat, 0.0).unwrap();
+        assert_eq!(interval_us(UDP, component_id, heartbeat), 1_000_000);
+
+        assert!(set(UDP, component_id, stream_status, 1000.0).is_err());
+        assert!(set(UDP, component_id, stream_status, -2.0).is_err());
+        assert!(set(
+            UDP,
+            component_id,
+            mavlink::common::PARAM_VALUE_DATA::ID,
+            0.0
+        )
+        .is_err());
+
+        let status = intervals(&[UDP.to_string(), SERIAL.to_string()])
+            .into_iter()
+            .filter(|status| status.component_id == component_id)
+            .collect::<Vec<MessageIntervalStatus>>();
+        assert_eq!(status.len(), 2 * PERIODIC_MESSAGES.len());
+        assert!(status.iter().any(|status| status.link == UDP
+            && status.message_name == "VIDEO_STREAM_STATUS"
+            && status.requested));
+        assert!(!status
+            .iter()
+            .any(|status| status.link == SERIAL && status.requested));
+
+        remove_link(UDP);
+        assert_eq!(interval(UDP, component_id, stream_status), None);
+
+        set(UDP, component_id, stream_status, 500_000.0).unwrap();
+        unregister(component_id);
+        assert_eq!(interval(UDP, component_id, stream_status), None);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let component_id = 201;
+        register(component_id);
+
+        let header = MavHeader {
+            component_id,
+            ..Default::default()
+        };
+        let stream_status = MavMessage::VIDEO_STREAM_STATUS(Default::default());
+
+        let mut limiter = RateLimiter::new(SERIAL);
+        let start = Instant::now();
+
+        // Without an interval, no periodic message goes through
+        assert!(!limiter.allow(&header, &stream_status, start));
+
+        // An interval requested through another link doesn't count
+        set(
+            UDP,
+            component_id,
+            mavlink::common::VIDEO_STREAM_STATUS_DATA::ID,
+            1e6,
+        )
+        .unwrap();
+        assert!(!limiter.allow(&header, &stream_status, start));
+
+        set(
+            SERIAL,
+            component_id,
+            mavlink::common::VIDEO_STREAM_STATUS_DATA::ID,
+            1e6,
+        )
+        .unwrap();
+        assert!(limiter.allow(&header, &stream_status, start));
+        assert!(!limiter.allow(&header, &stream_status, start + Duration::from_millis(500)));
+        assert!(limiter.allow(&header, &stream_status, start + Duration::from_millis(950)));
+
+        unregister(component_id);
+    }
+
+    #[test]
+    fn test_rate_limiter_events() {
+        let component_id = 202;
+        register(component_id);
+
+        let header = MavHeader {
+            component_id,
+            ..Default::default()
+        };
+        let image_captured =
+            MavMessage::CAMERA_IMAGE_CAPTURED(mavlink::common::CAMERA_IMAGE_CAPTURED_DATA {
+                time_utc: 0,
+                time_boot_ms: 0,
+                lat: 0,
+                lon: 0,
+                alt: 0,
+                relative_alt: 0,
+                q: [1.0, 0.0, 0.0, 0.0],
+                image_index: 0,
+                camera_id: 0,
+                capture_result: 1,
+                file_url: [0; 205],
+            });
+        let param_value = MavMessage::PARAM_VALUE(Default::default());
+
+        // Messages sent on events have no interval, but are never dropped
+        let mut limiter = RateLimiter::new(SERIAL);
+        let start = Instant::now();
+        assert!(limiter.allow(&header, &image_captured, start));
+        assert!(limiter.allow(&header, &image_captured, start));
+        assert!(limiter.allow(&header, &param_value, start));
+
+        unregister(component_id);
+    }
+
+    #[test]
+    fn test_bandwidth_constrained() {
+        assert!(is_bandwidth_constrained(SERIAL));
+        assert!(!is_bandwidth_constrained(UDP));
+    }
+}
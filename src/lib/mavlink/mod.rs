@@ -1,5 +1,8 @@
 pub mod manager;
 pub mod mavlink_camera;
 mod mavlink_camera_component;
+pub mod message_intervals;
+mod params;
 mod sys_info;
+pub mod telemetry;
 mod utils;
@@ -0,0 +1,208 @@
+use mavlink::common::MavParamType;
+
+use crate::video::types::{Control, ControlType};
+
+/// The maximum length of a MAVLink parameter id, which is only null terminated when shorter
+pub const PARAM_ID_LENGTH: usize = 16;
+
+/// A camera control exposed as a MAVLink parameter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub index: u16,
+    pub param_id: String,
+    pub control_id: u64,
+    pub param_type: MavParamType,
+    pub value: i64,
+}
+
+/// Maps the writable controls of a camera to MAVLink parameters. Control names don't fit the parameter ids, so each
+/// control gets an id derived from its name, like "EXPOSURE_TIME_AB" for "Exposure Time, Absolute", with a number
+/// replacing its end when two controls would share it. The mapping only depends on the controls and their order,
+/// so it is the same every time it is built for the same camera
+#[derive(Debug, Default)]
+pub struct ParamTable {
+    params: Vec<Param>,
+}
+
+impl ParamTable {
+    pub fn new(controls: &[Control]) -> Self {
+        let mut params: Vec<Param> = vec![];
+
+        for control in controls
+            .iter()
+            .filter(|control| !(control.state.is_disabled || control.state.is_read_only))
+        {
+            let base_id = param_id_from_name(&control.name);
+            let mut param_id = base_id.clone();
+            let mut suffix = 2;
+            while param_id.is_empty() || params.iter().any(|param| param.param_id == param_id) {
+                let suffix_string = format!("_{suffix}");
+                let end = base_id.len().min(PARAM_ID_LENGTH - suffix_string.len());
+                param_id = format!("{}{suffix_string}", base_id[..end].trim_end_matches('_'));
+                suffix += 1;
+            }
+
+            let (param_type, value) = match &control.configuration {
+                ControlType::Bool(bool) => (MavParamType::MAV_PARAM_TYPE_UINT8, bool.value),
+                ControlType::Slider(slider) => (MavParamType::MAV_PARAM_TYPE_INT32, slider.value),
+                ControlType::Menu(menu) => (MavParamType::MAV_PARAM_TYPE_INT32, menu.value),
+            };
+
+            params.push(Param {
+                index: params.len() as u16,
+                param_id,
+                control_id: control.id,
+                param_type,
+                value,
+            });
+        }
+
+        Self { params }
+    }
+
+    pub fn count(&self) -> u16 {
+        self.params.len() as u16
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Param> {
+        self.params.iter()
+    }
+
+    pub fn by_index(&self, index: u16) -> Option<&Param> {
+        self.params.get(index as usize)
+    }
+
+    pub fn by_param_id(&self, param_id: &str) -> Option<&Param> {
+        self.params.iter().find(|param| param.param_id == param_id)
+    }
+
+    pub fn by_control_id(&self, control_id: u64) -> Option<&Param> {
+        self.params
+            .iter()
+            .find(|param| param.control_id == control_id)
+    }
+}
+
+/// The name in uppercase, with anything other than letters and digits as single underscores, cut to the id length
+fn param_id_from_name(name: &str) -> String {
+    let param_id = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("_")
+        .to_ascii_uppercase();
+
+    param_id[..param_id.len().min(PARAM_ID_LENGTH)]
+        .trim_end_matches('_')
+        .to_string()
+}
+
+pub fn encode_param_id(param_id: &str) -> [u8; PARAM_ID_LENGTH] {
+    let mut buf = [0u8; PARAM_ID_LENGTH];
+
+    let bytes = param_id.as_bytes();
+    let len = bytes.len().min(PARAM_ID_LENGTH);
+    buf[..len].copy_from_slice(&bytes[..len]);
+
+    buf
+}
+
+pub fn decode_param_id(param_id: &[u8; PARAM_ID_LENGTH]) -> String {
+    let len = param_id
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(PARAM_ID_LENGTH);
+
+    String::from_utf8_lossy(&param_id[..len]).to_string()
+}
+
+/// Parameter values are floats, with integers converted to them (MAV_PROTOCOL_CAPABILITY_PARAM_ENCODE_C_CAST),
+/// which is exact for the ranges of the camera controls
+pub fn param_value(value: i64) -> f32 {
+    value as f32
+}
+
+/// The control value of a parameter value, which has to be an integer
+pub fn control_value(param_value: f32) -> Option<i64> {
+    (param_value.is_finite() && param_value.fract() == 0.0).then_some(param_value as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::types::{ControlBool, ControlSlider, ControlState};
+
+    fn control(id: u64, name: &str, configuration: ControlType) -> Control {
+        Control {
+            name: name.to_string(),
+            cpp_type: "int64".to_string(),
+            id,
+            state: ControlState::default(),
+            configuration,
+        }
+    }
+
+    fn slider(value: i64) -> ControlType {
+        ControlType::Slider(ControlSlider {
+            default: 0,
+            value,
+            step: 1,
+            max: 100,
+            min: 0,
+        })
+    }
+
+    #[test]
+    fn test_param_table() {
+        let mut read_only = control(4, "Pan, Absolute", slider(0));
+        read_only.state.is_read_only = true;
+
+        let controls = vec![
+            control(1, "Exposure Time, Absolute", slider(156)),
+            control(
+                2,
+                "Exposure, Dynamic Framerate",
+                ControlType::Bool(ControlBool {
+                    default: 0,
+                    value: 1,
+                }),
+            ),
+            read_only,
+            control(3, "Exposure Time, Absolute (2)", slider(10)),
+        ];
+
+        let table = ParamTable::new(&controls);
+        assert_eq!(table.count(), 3);
+
+        let param = table.by_param_id("EXPOSURE_TIME_AB").unwrap();
+        assert_eq!(param.control_id, 1);
+        assert_eq!(param.index, 0);
+        assert_eq!(param.param_type, MavParamType::MAV_PARAM_TYPE_INT32);
+        assert_eq!(param.value, 156);
+
+        let param = table.by_control_id(2).unwrap();
+        assert_eq!(param.param_id, "EXPOSURE_DYNAMIC");
+        assert_eq!(param.param_type, MavParamType::MAV_PARAM_TYPE_UINT8);
+
+        // Read-only controls are skipped, and clashing ids are told apart
+        assert!(table.by_control_id(4).is_none());
+        let param = table.by_index(2).unwrap();
+        assert_eq!(param.param_id, "EXPOSURE_TIME_2");
+        assert_eq!(param.control_id, 3);
+
+        for param in table.iter() {
+            assert!(param.param_id.len() <= PARAM_ID_LENGTH);
+            assert_eq!(
+                decode_param_id(&encode_param_id(&param.param_id)),
+                param.param_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(control_value(param_value(-20)), Some(-20));
+        assert_eq!(control_value(1.5), None);
+        assert_eq!(control_value(f32::NAN), None);
+    }
+}
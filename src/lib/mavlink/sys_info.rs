@@ -43,3 +43,39 @@ pub fn sys_info() -> SysInfo {
         available_capacity: local_available_capacity as f32 / f32::powf(2.0, 10.0),
     }
 }
+
+#[derive(Debug)]
+pub struct StorageInfo {
+    /// In MiB
+    pub total_capacity: f32,
+    /// In MiB
+    pub used_capacity: f32,
+    /// In MiB
+    pub available_capacity: f32,
+}
+
+/// Describes the filesystem in which the given path is stored
+#[instrument(level = "debug")]
+pub fn storage_info(path: &std::path::Path) -> Option<StorageInfo> {
+    let path = std::fs::canonicalize(path).ok()?;
+
+    let mut system = System::new();
+    system.refresh_disks_list();
+
+    // The filesystem is the one with the deepest mount point containing the path
+    let disk = system
+        .disks()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().components().count())?;
+
+    const MIB: f32 = 1024.0 * 1024.0;
+    let total_capacity = disk.total_space();
+    let available_capacity = disk.available_space();
+
+    Some(StorageInfo {
+        total_capacity: total_capacity as f32 / MIB,
+        used_capacity: total_capacity.saturating_sub(available_capacity) as f32 / MIB,
+        available_capacity: available_capacity as f32 / MIB,
+    })
+}
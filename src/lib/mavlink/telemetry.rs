@@ -0,0 +1,37 @@
+use std::sync::RwLock;
+
+use mavlink::common::MavMessage;
+
+lazy_static! {
+    static ref TELEMETRY: RwLock<Telemetry> = Default::default();
+}
+
+/// The latest vehicle telemetry received, where each value is missing until its first message arrives
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Telemetry {
+    /// Depth below the surface, in meters
+    pub depth: Option<f32>,
+    /// Heading, in degrees (0..360)
+    pub heading: Option<i16>,
+    /// Altitude, in meters
+    pub altitude: Option<f32>,
+    /// Speed over ground, in meters per second
+    pub groundspeed: Option<f32>,
+}
+
+/// Keeps the values from the vehicle's telemetry messages
+pub fn update(message: &MavMessage) {
+    if let MavMessage::VFR_HUD(data) = message {
+        let mut telemetry = TELEMETRY.write().unwrap();
+        telemetry.altitude = Some(data.alt);
+        // Underwater vehicles report their depth as a negative altitude
+        telemetry.depth = Some(-data.alt);
+        telemetry.heading = Some(data.heading);
+        telemetry.groundspeed = Some(data.groundspeed);
+    }
+}
+
+/// The latest vehicle telemetry received
+pub fn telemetry() -> Telemetry {
+    *TELEMETRY.read().unwrap()
+}
@@ -18,11 +18,57 @@ pub fn from_string_to_sized_u8_array_with_null_terminator<const N: usize>(src: &
 #[instrument(level = "debug")]
 pub fn get_stream_status_flag(
     component: &MavlinkCameraComponent,
+    running: bool,
 ) -> mavlink::common::VideoStreamStatusFlags {
-    match component.thermal {
-        true => mavlink::common::VideoStreamStatusFlags::VIDEO_STREAM_STATUS_FLAGS_THERMAL,
-        false => mavlink::common::VideoStreamStatusFlags::VIDEO_STREAM_STATUS_FLAGS_RUNNING,
+    let mut flags = mavlink::common::VideoStreamStatusFlags::empty();
+    if running {
+        flags |= mavlink::common::VideoStreamStatusFlags::VIDEO_STREAM_STATUS_FLAGS_RUNNING;
     }
+    if component.thermal {
+        flags |= mavlink::common::VideoStreamStatusFlags::VIDEO_STREAM_STATUS_FLAGS_THERMAL;
+    }
+
+    flags
+}
+
+/// What the camera can do, deciding which controls the ground stations show for it. Images and videos are captured
+/// from the stream itself, with the camera modes switching between them
+#[instrument(level = "debug")]
+pub fn camera_capability_flags(has_zoom: bool) -> mavlink::common::CameraCapFlags {
+    let mut flags = mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_HAS_VIDEO_STREAM
+        | mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_CAPTURE_IMAGE
+        | mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_CAPTURE_VIDEO
+        | mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_HAS_MODES;
+    if has_zoom {
+        flags |= mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_HAS_BASIC_ZOOM;
+    }
+
+    flags
+}
+
+/// The URL the clients should use to receive the stream. Servers listening on all the interfaces, like the RTSP one,
+/// are reached through the given address, while UDP streams are sent to their own address
+#[instrument(level = "debug")]
+pub fn reachable_stream_uri(uri: &url::Url, visible_host: &str) -> url::Url {
+    let unspecified = match uri.host() {
+        Some(url::Host::Ipv4(address)) => address.is_unspecified(),
+        Some(url::Host::Ipv6(address)) => address.is_unspecified(),
+        // The IPv4 hosts of non-special schemes, like rtsp, aren't parsed as addresses
+        Some(url::Host::Domain(domain)) => domain
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|address| address.is_unspecified()),
+        None => false,
+    };
+    if uri.scheme() == "udp" || !unspecified {
+        return uri.clone();
+    }
+
+    let mut uri = uri.clone();
+    if let Err(error) = uri.set_host(Some(visible_host)) {
+        warn!("Failed setting the host {visible_host:?} to {uri}: {error:?}");
+    }
+
+    uri
 }
 
 #[instrument(level = "debug")]
@@ -120,3 +166,41 @@ pub fn control_id_from_param_id<const N: usize>(param_id: &[u8; N]) -> Option<u6
 
     id_string.parse().ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reachable_stream_uri() {
+        let uri = |uri: &str| url::Url::parse(uri).unwrap();
+
+        assert_eq!(
+            reachable_stream_uri(&uri("rtsp://0.0.0.0:8554/front"), "192.168.2.2"),
+            uri("rtsp://192.168.2.2:8554/front")
+        );
+        assert_eq!(
+            reachable_stream_uri(&uri("rtsp://[::]:8554/front"), "[fd00::2]"),
+            uri("rtsp://[fd00::2]:8554/front")
+        );
+        assert_eq!(
+            reachable_stream_uri(&uri("rtsp://10.0.0.5:8554/front"), "192.168.2.2"),
+            uri("rtsp://10.0.0.5:8554/front")
+        );
+        // UDP streams are received on their destination
+        assert_eq!(
+            reachable_stream_uri(&uri("udp://0.0.0.0:5600"), "192.168.2.2"),
+            uri("udp://0.0.0.0:5600")
+        );
+    }
+
+    #[test]
+    fn test_flags() {
+        let flags = camera_capability_flags(false);
+        assert!(flags.contains(mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_HAS_VIDEO_STREAM));
+        assert!(flags.contains(mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_HAS_MODES));
+        assert!(!flags.contains(mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_HAS_BASIC_ZOOM));
+        assert!(camera_capability_flags(true)
+            .contains(mavlink::common::CameraCapFlags::CAMERA_CAP_FLAGS_HAS_BASIC_ZOOM));
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeSet;
+
+use tokio::sync::broadcast;
+use tracing::*;
+
+/// Period between each check of the host's network interfaces
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// The addresses of the host's network interfaces changed, like after a DHCP renew or a WiFi roam
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkEvent {
+    /// Addresses that appeared, like `wlan0 192.168.2.10/24`
+    pub added: Vec<String>,
+    /// Addresses that are gone, in the same format
+    pub removed: Vec<String>,
+}
+
+lazy_static! {
+    static ref EVENTS: broadcast::Sender<NetworkEvent> = broadcast::channel(16).0;
+}
+
+/// Receives the network changes detected after the monitor was started
+pub fn subscribe() -> broadcast::Receiver<NetworkEvent> {
+    EVENTS.subscribe()
+}
+
+/// Starts polling the host's network interfaces, publishing any change of their addresses
+#[instrument(level = "debug")]
+pub fn start() {
+    if let Err(error) = std::thread::Builder::new()
+        .name("NetworkMonitor".to_string())
+        .spawn(network_monitor_thread)
+    {
+        error!("Failed to spawn the network monitor thread: {error:?}");
+    }
+}
+
+#[instrument(level = "debug")]
+fn network_monitor_thread() {
+    debug!("Network monitor started");
+
+    let mut previous = interface_addresses();
+
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+
+        let current = interface_addresses();
+
+        if let Some(event) = changes(&previous, &current) {
+            info!("Network event: {event:?}");
+
+            // Having no subscribers is not an error
+            let _ = EVENTS.send(event);
+        }
+
+        previous = current;
+    }
+}
+
+/// The addresses of the interfaces that are up, ignoring loopback ones
+fn interface_addresses() -> BTreeSet<String> {
+    pnet::datalink::interfaces()
+        .iter()
+        .filter(|interface| interface.is_up() && !interface.is_loopback())
+        .flat_map(|interface| {
+            interface
+                .ips
+                .iter()
+                .map(move |ip| format!("{} {ip}", interface.name))
+        })
+        .collect()
+}
+
+fn changes(previous: &BTreeSet<String>, current: &BTreeSet<String>) -> Option<NetworkEvent> {
+    let added = current
+        .difference(previous)
+        .cloned()
+        .collect::<Vec<String>>();
+    let removed = previous
+        .difference(current)
+        .cloned()
+        .collect::<Vec<String>>();
+
+    if added.is_empty() && removed.is_empty() {
+        return None;
+    }
+
+    Some(NetworkEvent { added, removed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes() {
+        let previous = BTreeSet::from([
+            "eth0 192.168.2.2/24".to_string(),
+            "wlan0 10.0.0.5/24".to_string(),
+        ]);
+
+        assert_eq!(changes(&previous, &previous), None);
+
+        // A WiFi roam to another network
+        let current = BTreeSet::from([
+            "eth0 192.168.2.2/24".to_string(),
+            "wlan0 10.0.1.7/24".to_string(),
+        ]);
+        assert_eq!(
+            changes(&previous, &current),
+            Some(NetworkEvent {
+                added: vec!["wlan0 10.0.1.7/24".to_string()],
+                removed: vec!["wlan0 10.0.0.5/24".to_string()],
+            })
+        );
+    }
+}
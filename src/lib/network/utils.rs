@@ -3,14 +3,60 @@ use tracing::*;
 
 use crate::cli::manager::vehicle_ddns;
 
+/// The host other systems should use to reach us, ready to be used in an URL (IPv6 literals are bracketed)
 pub fn get_visible_qgc_address() -> String {
-    match vehicle_ddns() {
-        Some(ddns) => ddns.to_string(),
-        None => get_ipv4_addresses()
-            .last()
-            .unwrap_or(&std::net::Ipv4Addr::UNSPECIFIED)
-            .to_string(),
+    if let Some(ddns) = vehicle_ddns() {
+        return ddns.to_string();
     }
+
+    match get_ipv4_addresses().last() {
+        Some(ip) if !ip.is_unspecified() => ip.to_string(),
+        // IPv6-only networks
+        _ => match get_ipv6_addresses().first() {
+            Some(ip) => format!("[{ip}]"),
+            None => std::net::Ipv4Addr::UNSPECIFIED.to_string(),
+        },
+    }
+}
+
+/// The address servers should listen on: "::" when the host supports IPv6, which also accepts IPv4 connections,
+/// otherwise "0.0.0.0"
+pub fn unspecified_address() -> std::net::IpAddr {
+    let ipv6 = std::net::IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED);
+
+    match std::net::UdpSocket::bind((ipv6, 0)) {
+        Ok(_) => ipv6,
+        Err(_) => std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
+    }
+}
+
+/// The addresses of all interfaces that are up, so multi-homed hosts are reachable from each of their networks.
+/// Link-local IPv6 addresses are skipped, as they can't be used without their scope
+pub fn get_host_addresses() -> Vec<std::net::IpAddr> {
+    pnet::datalink::interfaces()
+        .iter()
+        .filter(|e| e.is_up() && !e.is_loopback() && !e.name.contains("docker"))
+        .flat_map(|interface| interface.ips.iter().map(|ip_network| ip_network.ip()))
+        .filter(|ip| match ip {
+            std::net::IpAddr::V4(_) => true,
+            std::net::IpAddr::V6(ip) => (ip.segments()[0] & 0xffc0) != 0xfe80,
+        })
+        .collect()
+}
+
+/// The routable IPv6 addresses of the interfaces that are up. Link-local ones are skipped, as they can't be
+/// used without their scope
+pub fn get_ipv6_addresses() -> Vec<std::net::Ipv6Addr> {
+    pnet::datalink::interfaces()
+        .iter()
+        .filter(|e| e.is_up() && !e.is_loopback() && !e.name.contains("docker"))
+        .flat_map(|interface| interface.ips.iter())
+        .filter_map(|ip_network| match ip_network {
+            pnet::ipnetwork::IpNetwork::V6(ipv6_network) => Some(ipv6_network.ip()),
+            _ => None,
+        })
+        .filter(|ip| (ip.segments()[0] & 0xffc0) != 0xfe80)
+        .collect()
 }
 
 pub fn get_ipv4_addresses() -> Vec<std::net::Ipv4Addr> {
@@ -0,0 +1,216 @@
+use actix_web::{error::InternalError, http::header, http::Method, HttpRequest, HttpResponse};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use crate::settings;
+
+/// The health and metrics endpoints, which can be reached without credentials when allowed by the settings
+const PUBLIC_HEALTH_PATHS: &[&str] = &["/info", "/health", "/metrics"];
+/// The query parameter carrying the API key, for clients that can't set headers, like browser WebSockets
+const API_KEY_QUERY_PARAMETER: &str = "api_key";
+const API_KEY_HEADER: &str = "X-API-Key";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can only read, like listing the streams
+    ReadOnly,
+    /// Can also change the configuration, like creating and removing streams
+    ReadWrite,
+}
+
+/// Protects the REST API and its WebSockets. When present in the settings, every request needs one of the
+/// credentials below, either as an API key or through HTTP basic authentication
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuthSettings {
+    /// Keys accepted in the "X-API-Key" header, as "Authorization: Bearer <KEY>", or in the "api_key" query parameter
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    /// Users accepted through HTTP basic authentication
+    #[serde(default)]
+    pub users: Vec<User>,
+    /// If the health and metrics endpoints ("/info", "/health" and "/metrics") can be reached without credentials
+    #[serde(default)]
+    pub public_health: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ApiKey {
+    pub key: String,
+    pub role: Role,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct User {
+    pub username: String,
+    pub password: String,
+    pub role: Role,
+}
+
+/// Checks the credentials of the given request against the settings, answering with 401 when they are missing or
+/// wrong, and with 403 when a read-only client tries to change something
+pub fn authorize(request: &HttpRequest) -> Result<(), actix_web::Error> {
+    let Some(auth_settings) = settings::manager::auth() else {
+        return Ok(());
+    };
+
+    // CORS preflights never carry credentials
+    if request.method() == Method::OPTIONS {
+        return Ok(());
+    }
+
+    if auth_settings.public_health && PUBLIC_HEALTH_PATHS.contains(&request.path()) {
+        return Ok(());
+    }
+
+    let Some(role) = role(&auth_settings, request) else {
+        debug!(
+            "Unauthenticated request {} {} from {:?}",
+            request.method(),
+            request.path(),
+            request.peer_addr()
+        );
+        let response = HttpResponse::Unauthorized()
+            .insert_header((
+                header::WWW_AUTHENTICATE,
+                format!("Basic realm=\"{}\"", env!("CARGO_PKG_NAME")),
+            ))
+            .content_type("text/plain")
+            .body("Missing or invalid credentials");
+        return Err(InternalError::from_response("Unauthorized", response).into());
+    };
+
+    if role == Role::ReadOnly && !is_read_only(request.method()) {
+        let response = HttpResponse::Forbidden()
+            .content_type("text/plain")
+            .body("Read-only credentials can't change the configuration");
+        return Err(InternalError::from_response("Forbidden", response).into());
+    }
+
+    Ok(())
+}
+
+fn is_read_only(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// The role of the first matching credential of the request
+fn role(auth_settings: &AuthSettings, request: &HttpRequest) -> Option<Role> {
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+
+    let api_key = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+        .or_else(|| {
+            authorization
+                .and_then(|authorization| authorization.strip_prefix("Bearer "))
+                .map(String::from)
+        })
+        .or_else(|| {
+            url::form_urlencoded::parse(request.query_string().as_bytes())
+                .find(|(name, _)| name == API_KEY_QUERY_PARAMETER)
+                .map(|(_, value)| value.to_string())
+        });
+    if let Some(api_key) = api_key {
+        return auth_settings
+            .api_keys
+            .iter()
+            .find(|candidate| constant_time_eq(candidate.key.as_bytes(), api_key.as_bytes()))
+            .map(|candidate| candidate.role);
+    }
+
+    let (username, password) = authorization
+        .and_then(|authorization| authorization.strip_prefix("Basic "))
+        .and_then(|encoded| {
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .ok()
+        })
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|decoded| {
+            decoded
+                .split_once(':')
+                .map(|(username, password)| (username.to_string(), password.to_string()))
+        })?;
+
+    auth_settings
+        .users
+        .iter()
+        .find(|user| {
+            // Both are always compared, so the timing doesn't tell which one is wrong
+            let username_matches = constant_time_eq(user.username.as_bytes(), username.as_bytes());
+            let password_matches = constant_time_eq(user.password.as_bytes(), password.as_bytes());
+            username_matches & password_matches
+        })
+        .map(|user| user.role)
+}
+
+/// Compares the secrets without leaking, through the time taken, how much of them matches
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::test::TestRequest;
+
+    fn auth_settings() -> AuthSettings {
+        AuthSettings {
+            api_keys: vec![ApiKey {
+                key: "dashboard-key".into(),
+                role: Role::ReadOnly,
+            }],
+            users: vec![User {
+                username: "admin".into(),
+                password: "secret".into(),
+                role: Role::ReadWrite,
+            }],
+            public_health: true,
+        }
+    }
+
+    #[test]
+    fn test_role() {
+        let auth_settings = auth_settings();
+        let basic = |credentials: &str| {
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            )
+        };
+
+        let request = TestRequest::default().to_http_request();
+        assert_eq!(role(&auth_settings, &request), None);
+
+        let request = TestRequest::default()
+            .insert_header((API_KEY_HEADER, "dashboard-key"))
+            .to_http_request();
+        assert_eq!(role(&auth_settings, &request), Some(Role::ReadOnly));
+
+        let request = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer wrong-key"))
+            .to_http_request();
+        assert_eq!(role(&auth_settings, &request), None);
+
+        let request = TestRequest::with_uri("/ws/events?api_key=dashboard-key").to_http_request();
+        assert_eq!(role(&auth_settings, &request), Some(Role::ReadOnly));
+
+        let request = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, basic("admin:secret")))
+            .to_http_request();
+        assert_eq!(role(&auth_settings, &request), Some(Role::ReadWrite));
+
+        let request = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, basic("admin:wrong")))
+            .to_http_request();
+        assert_eq!(role(&auth_settings, &request), None);
+    }
+}
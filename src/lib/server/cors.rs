@@ -0,0 +1,105 @@
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Restricts which web pages, hosted elsewhere, can use the REST API. When missing from the settings, any origin
+/// can use it
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CorsSettings {
+    /// The origins allowed, like "https://cockpit.example.com". When empty, any origin is allowed
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// The methods allowed, like "GET". When empty, any method is allowed
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+    /// The request headers allowed, like "Authorization". When empty, any header is allowed
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+}
+
+impl CorsSettings {
+    pub fn validate(&self) -> Result<()> {
+        for origin in &self.allowed_origins {
+            let url = url::Url::parse(origin)
+                .map_err(|error| anyhow!("Invalid CORS origin {origin:?}: {error}"))?;
+            // The browsers send the origins without path, like "https://host:port"
+            if url.origin().ascii_serialization() != origin.trim_end_matches('/') {
+                return Err(anyhow!(
+                    "Invalid CORS origin {origin:?}, it should only have a scheme, host and port"
+                ));
+            }
+        }
+
+        for method in &self.allowed_methods {
+            Method::from_bytes(method.as_bytes())
+                .map_err(|error| anyhow!("Invalid CORS method {method:?}: {error}"))?;
+        }
+
+        for header in &self.allowed_headers {
+            HeaderName::from_bytes(header.as_bytes())
+                .map_err(|error| anyhow!("Invalid CORS header {header:?}: {error}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The CORS middleware of the REST API, as permissive as possible unless restricted by the given settings
+pub fn middleware(cors_settings: Option<&CorsSettings>) -> Cors {
+    let cors_settings = cors_settings.cloned().unwrap_or_default();
+    let mut cors = Cors::default().max_age(3600);
+
+    cors = if cors_settings.allowed_origins.is_empty() {
+        cors.allow_any_origin().send_wildcard()
+    } else {
+        // Known origins can also send credentials, like for the basic authentication
+        cors_settings
+            .allowed_origins
+            .iter()
+            .fold(cors, |cors, origin| {
+                cors.allowed_origin(origin.trim_end_matches('/'))
+            })
+            .supports_credentials()
+    };
+
+    cors = if cors_settings.allowed_methods.is_empty() {
+        cors.allow_any_method()
+    } else {
+        cors.allowed_methods(cors_settings.allowed_methods.iter().map(String::as_str))
+    };
+
+    if cors_settings.allowed_headers.is_empty() {
+        cors.allow_any_header()
+    } else {
+        cors.allowed_headers(cors_settings.allowed_headers.iter().map(String::as_str))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let cors_settings = |origin: &str, method: &str| CorsSettings {
+            allowed_origins: vec![origin.to_string()],
+            allowed_methods: vec![method.to_string()],
+            allowed_headers: vec!["Authorization".to_string()],
+        };
+
+        assert!(cors_settings("https://cockpit.example.com", "GET")
+            .validate()
+            .is_ok());
+        assert!(cors_settings("http://192.168.2.2:8080", "POST")
+            .validate()
+            .is_ok());
+        assert!(cors_settings("*", "GET").validate().is_err());
+        assert!(cors_settings("https://cockpit.example.com/ui", "GET")
+            .validate()
+            .is_err());
+        assert!(cors_settings("https://cockpit.example.com", "GET POST")
+            .validate()
+            .is_err());
+    }
+}
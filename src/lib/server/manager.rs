@@ -1,6 +1,6 @@
-use super::pages;
+use super::{auth, cors, pages, websocket};
+use crate::settings;
 
-use actix_cors::Cors;
 use actix_extensible_rate_limit::{
     backend::{memory::InMemoryBackend, SimpleInputFunctionBuilder},
     RateLimiter,
@@ -20,25 +20,35 @@ fn json_error_handler(error: JsonPayloadError, _: &HttpRequest) -> actix_web::Er
     error.into()
 }
 
-// Start REST API server with the desired address
-pub async fn run(server_address: &str) -> Result<(), std::io::Error> {
+// Start REST API server with the desired address, over TLS when configured
+pub async fn run(
+    server_address: &str,
+    tls_config: Option<rustls::ServerConfig>,
+) -> Result<(), std::io::Error> {
     let server_address = server_address.to_string();
 
-    HttpServer::new(move || {
+    // The CORS settings are only read when starting, an invalid one would make every worker panic
+    let cors_settings = settings::manager::cors();
+    if let Some(cors_settings) = &cors_settings {
+        cors_settings.validate().map_err(|error| {
+            error!("Invalid CORS settings: {error:#}");
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{error:#}"))
+        })?;
+    }
+
+    let server = HttpServer::new(move || {
         App::new()
             // Add debug call for API access
             .wrap_fn(|req, srv| {
                 trace!("{req:#?}");
                 srv.call(req)
             })
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .allow_any_header()
-                    .send_wildcard()
-                    .max_age(3600),
-            )
+            // Inside the CORS middleware, so the rejections carry its headers
+            .wrap_fn(|req, srv| match auth::authorize(req.request()) {
+                Ok(()) => futures::future::Either::Left(srv.call(req)),
+                Err(error) => futures::future::Either::Right(futures::future::err(error)),
+            })
+            .wrap(cors::middleware(cors_settings.as_ref()))
             .wrap(TracingLogger::default())
             .wrap(actix_web::middleware::Logger::default())
             .wrap_api_with_spec(Api {
@@ -65,12 +75,175 @@ pub async fn run(server_address: &str) -> Result<(), std::io::Error> {
             )
             .route("/gst_info", web::get().to(pages::gst_info))
             .route("/info", web::get().to(pages::info))
+            .route("/system/gstreamer", web::get().to(pages::system_gstreamer))
+            .route("/health", web::get().to(pages::health))
+            .route("/metrics", web::get().to(pages::metrics))
+            .route("/log-level", web::get().to(pages::log_level))
+            .route("/log-level", web::post().to(pages::log_level_post))
+            .route("/webrtc/sessions", web::get().to(pages::webrtc_sessions))
+            .route(
+                "/webrtc/ice-servers",
+                web::get().to(pages::webrtc_ice_servers),
+            )
+            .route(
+                "/webrtc/ice-servers",
+                web::post().to(pages::webrtc_ice_servers_post),
+            )
+            .route(
+                "/mavlink/endpoints",
+                web::get().to(pages::mavlink_endpoints),
+            )
+            .route(
+                "/mavlink/endpoints",
+                web::post().to(pages::mavlink_endpoints_post),
+            )
+            .route(
+                "/mavlink/endpoints",
+                web::delete().to(pages::mavlink_endpoints_delete),
+            )
+            .route(
+                "/mavlink/message_intervals",
+                web::get().to(pages::mavlink_message_intervals),
+            )
+            .route("/rtsp/credentials", web::get().to(pages::rtsp_credentials))
+            .route(
+                "/rtsp/credentials",
+                web::post().to(pages::rtsp_credentials_post),
+            )
             .route("/delete_stream", web::delete().to(pages::remove_stream))
             .route("/reset_settings", web::post().to(pages::reset_settings))
+            .route("/config/export", web::get().to(pages::config_export))
+            .route("/config/import", web::post().to(pages::config_import))
             .route("/streams", web::get().to(pages::streams))
             .route("/streams", web::post().to(pages::streams_post))
+            .route("/streams/validate", web::post().to(pages::streams_validate))
+            .route("/streams/{id}", web::patch().to(pages::stream_patch))
+            .route("/streams/{id}", web::delete().to(pages::stream_delete))
+            .route(
+                "/streams/{id}/latency",
+                web::post().to(pages::stream_latency),
+            )
+            .route(
+                "/streams/{id}/keyframe-interval",
+                web::post().to(pages::stream_keyframe_interval),
+            )
+            .route(
+                "/streams/{id}/rate-control",
+                web::post().to(pages::stream_rate_control),
+            )
+            .route(
+                "/streams/{id}/h264-profile",
+                web::post().to(pages::stream_h264_profile),
+            )
+            .route(
+                "/streams/{id}/reconnection",
+                web::post().to(pages::stream_reconnection),
+            )
+            .route("/streams/{id}/clip", web::post().to(pages::stream_clip))
+            .route("/streams/{id}/replay", web::post().to(pages::stream_replay))
+            .route(
+                "/streams/{id}/replay-buffer",
+                web::post().to(pages::stream_replay_buffer),
+            )
+            .route(
+                "/streams/{id}/output-resolution",
+                web::post().to(pages::stream_output_resolution),
+            )
+            .route(
+                "/streams/{id}/max-framerate",
+                web::post().to(pages::stream_max_framerate),
+            )
+            .route("/streams/{id}/zoom", web::get().to(pages::stream_zoom))
+            .route(
+                "/streams/{id}/zoom",
+                web::post().to(pages::stream_zoom_post),
+            )
+            .route(
+                "/streams/{id}/overlay",
+                web::get().to(pages::stream_overlay),
+            )
+            .route(
+                "/streams/{id}/overlay",
+                web::post().to(pages::stream_overlay_post),
+            )
+            .route(
+                "/streams/{id}/clients",
+                web::get().to(pages::stream_clients),
+            )
+            .route(
+                "/streams/{id}/clients/{client_id}",
+                web::delete().to(pages::stream_client_delete),
+            )
+            .route(
+                "/streams/{id}/ice-servers",
+                web::post().to(pages::stream_ice_servers_post),
+            )
+            .route("/streams/{id}/caps", web::get().to(pages::stream_caps))
+            .route("/streams/{id}/mjpeg", web::get().to(pages::stream_mjpeg))
+            .route("/streams/{id}/stream.sdp", web::get().to(pages::stream_sdp))
+            .route(
+                "/streams/{id}/stats/history",
+                web::get().to(pages::stream_stats_history),
+            )
+            .route(
+                "/streams/{id}/bandwidth",
+                web::get().to(pages::stream_bandwidth),
+            )
+            .route(
+                "/streams/{id}/bandwidth/reset",
+                web::post().to(pages::stream_bandwidth_reset),
+            )
+            .route(
+                "/streams/{id}/thumbnail.jpg",
+                web::get().to(pages::stream_thumbnail),
+            )
+            .route("/streams/{id}/audio", web::get().to(pages::stream_audio))
+            .route(
+                "/streams/{id}/audio",
+                web::post().to(pages::stream_audio_post),
+            )
+            .route("/streams/{id}/clock", web::get().to(pages::stream_clock))
+            .route(
+                "/streams/{id}/clock",
+                web::post().to(pages::stream_clock_post),
+            )
+            .route(
+                "/streams/{id}/restart",
+                web::post().to(pages::stream_restart),
+            )
+            .route("/streams/{id}/enable", web::post().to(pages::stream_enable))
+            .route(
+                "/streams/{id}/disable",
+                web::post().to(pages::stream_disable),
+            )
+            .route(
+                "/streams/{id}/force-keyframe",
+                web::post().to(pages::stream_force_keyframe),
+            )
+            .route(
+                "/streams/{id}/udp-destinations",
+                web::post().to(pages::stream_udp_destination_post),
+            )
+            .route(
+                "/streams/{id}/udp-destinations",
+                web::delete().to(pages::stream_udp_destination_delete),
+            )
+            .route("/audio/devices", web::get().to(pages::audio_devices))
             .route("/v4l", web::get().to(pages::v4l))
             .route("/v4l", web::post().to(pages::v4l_post))
+            .route("/v4l/{device}/formats", web::get().to(pages::v4l_formats))
+            .route(
+                "/v4l/{device}/controls",
+                web::get().to(pages::v4l_device_controls),
+            )
+            .route(
+                "/v4l/{device}/control",
+                web::post().to(pages::v4l_device_control),
+            )
+            .route(
+                "/v4l/{device}/controls/reset",
+                web::post().to(pages::v4l_device_controls_reset),
+            )
             .route(
                 "/camera/reset_controls",
                 web::post().to(pages::camera_reset_controls),
@@ -93,9 +266,17 @@ pub async fn run(server_address: &str) -> Result<(), std::io::Error> {
                     .route("", web::get().to(pages::thumbnail)),
             )
             .build()
-    })
-    .bind(server_address)
-    .expect("Failed starting web API")
-    .run()
-    .await
+            // WebSockets are not described by the OpenAPI spec
+            .route("/ws/events", actix_web::web::get().to(websocket::events))
+    });
+
+    let server = match tls_config {
+        Some(tls_config) => {
+            info!("Serving the REST API over HTTPS at {server_address}");
+            server.bind_rustls(&server_address, tls_config)
+        }
+        None => server.bind(&server_address),
+    };
+
+    server.expect("Failed starting web API").run().await
 }
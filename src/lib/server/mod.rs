@@ -1,2 +1,6 @@
+pub mod auth;
+pub mod cors;
 pub mod manager;
 mod pages;
+pub mod tls;
+mod websocket;
@@ -1,8 +1,24 @@
 use crate::helper;
+use crate::logger;
+use crate::mavlink::{manager as mavlink_manager, message_intervals as mavlink_message_intervals};
 use crate::settings;
-use crate::stream::{gst as gst_stream, manager as stream_manager, types::StreamInformation};
+use crate::stream::{
+    gst as gst_stream, health as stream_health, manager as stream_manager,
+    metrics as stream_metrics,
+    pipeline::{audio as stream_audio, runner::PipelineError, zoom as stream_zoom},
+    rtsp::rtsp_auth as stream_rtsp_auth,
+    sink::mjpeg_sink as stream_mjpeg_sink,
+    thumbnails as stream_thumbnails,
+    types::{
+        AudioConfiguration, AudioDevice, ClockConfiguration, H264ProfileConfiguration, IceServer,
+        OutputResolutionConfiguration, OverlayConfiguration, RateControlConfiguration,
+        ReconnectionConfiguration, RtspCredentials, StreamInformation, ValidationReport,
+    },
+    validation as stream_validation,
+    webrtc::ice_servers as stream_ice_servers,
+};
 use crate::video::{
-    types::{Control, Format, VideoSourceType},
+    types::{Control, Format, FormatDescription, VideoSourceType},
     video_source,
     video_source::VideoSource,
     xml,
@@ -25,6 +41,9 @@ use std::io::prelude::*;
 pub struct ApiVideoSource {
     name: String,
     source: String,
+    /// For local devices, a path that keeps identifying the device when its "/dev/videoN" source changes, which
+    /// can be used as the source as well
+    stable_id: Option<String>,
     formats: Vec<Format>,
     controls: Vec<Control>,
 }
@@ -36,13 +55,163 @@ pub struct V4lControl {
     value: i64,
 }
 
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct DeviceControl {
+    id: u64,
+    value: i64,
+}
+
 #[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
 pub struct PostStream {
+    /// A user friendly name, which doesn't need to be unique
+    #[serde(default)]
     name: String,
+    #[serde(default)]
+    tags: Vec<String>,
     source: String,
     stream_information: StreamInformation,
 }
 
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct PatchStream {
+    /// A user friendly name, which doesn't need to be unique. When empty, the source's name is used instead
+    name: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct StreamLatency {
+    /// Jitterbuffer latency, in milliseconds (0..=2000)
+    latency_ms: u32,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct StreamKeyframeInterval {
+    /// Maximum distance between keyframes, in frames (1..=1000)
+    keyframe_interval: u32,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct StreamClock {
+    /// The clock and base time of the pipeline, or none to let GStreamer pick them
+    #[serde(default)]
+    clock: Option<ClockConfiguration>,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct StreamAudio {
+    /// The audio to capture along with the video, or none for a video only stream
+    #[serde(default)]
+    audio: Option<AudioConfiguration>,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct IceServers {
+    /// The STUN and TURN servers to use, or none to go back to the defaults
+    #[serde(default)]
+    ice_servers: Option<Vec<IceServer>>,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct RtspCredentialsList {
+    /// The users accepted by the RTSP server, or none to open it to anyone
+    #[serde(default)]
+    credentials: Vec<RtspCredentials>,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct LogFilter {
+    /// Directives following the RUST_LOG syntax, like "info,mavlink_camera_manager::stream=debug"
+    filter: String,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize)]
+pub struct MjpegRequest {
+    /// Maximum frames per second sent to this client, 5 by default
+    max_framerate: Option<u32>,
+}
+
+/// Separates the JPEG frames of the MJPEG streams
+const MJPEG_BOUNDARY: &str = "frame";
+
+/// Removes the MJPEG branch of a client once its response is dropped, like when it disconnects
+struct MjpegClientGuard {
+    stream_id: uuid::Uuid,
+    sink_id: uuid::Uuid,
+}
+
+impl Drop for MjpegClientGuard {
+    fn drop(&mut self) {
+        let (stream_id, sink_id) = (self.stream_id, self.sink_id);
+        tokio::spawn(async move {
+            debug!("MJPEG client {sink_id:?} of stream {stream_id:?} is gone");
+            if let Err(error) = stream_manager::remove_mjpeg_client(&stream_id, &sink_id).await {
+                debug!("Failed removing MJPEG client {sink_id:?}: {error:?}");
+            }
+        });
+    }
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize)]
+pub struct ClipRequest {
+    /// The seconds of video recorded after the request, from 1 to 300
+    duration: u64,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize)]
+pub struct ReplayRequest {
+    /// The seconds of video recorded after the request, up to 300 (default: 0)
+    #[serde(default)]
+    post_roll: u64,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct ReplayBuffer {
+    /// The seconds of video kept in memory, up to 30, or 0 to disable it and free its memory
+    depth_secs: u32,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct Clip {
+    /// The finalized clip file
+    path: String,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct StreamOutputResolution {
+    /// The resolution streamed, or none to stream the captured one
+    #[serde(default)]
+    output_resolution: Option<OutputResolutionConfiguration>,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct StreamMaxFramerate {
+    /// The highest framerate streamed, in frames per second, or none to stream the captured one
+    #[serde(default)]
+    max_framerate: Option<u32>,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct StreamZoom {
+    /// Digital zoom factor, from 1 (no zoom) to `max_level`. Values out of this range are clamped
+    level: f64,
+    /// The maximum digital zoom factor, ignored when changing the zoom
+    #[serde(default)]
+    max_level: f64,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct UdpDestination {
+    /// Like "udp://192.168.2.1:5600"
+    destination: url::Url,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct MavlinkEndpoint {
+    /// Like "udpin:0.0.0.0:14550", "tcpout:192.168.2.1:5760" or "serial:/dev/ttyUSB0:57600"
+    endpoint: String,
+}
+
 #[derive(Apiv2Schema, Debug, Deserialize)]
 pub struct RemoveStream {
     name: String,
@@ -191,18 +360,21 @@ pub async fn v4l() -> Json<Vec<ApiVideoSource>> {
             VideoSourceType::Local(cam) => ApiVideoSource {
                 name: cam.name().clone(),
                 source: cam.source_string().to_string(),
+                stable_id: cam.stable_id.clone(),
                 formats: cam.formats(),
                 controls: cam.controls(),
             },
             VideoSourceType::Gst(gst) => ApiVideoSource {
                 name: gst.name().clone(),
                 source: gst.source_string().to_string(),
+                stable_id: None,
                 formats: gst.formats(),
                 controls: gst.controls(),
             },
             VideoSourceType::Redirect(redirect) => ApiVideoSource {
                 name: redirect.name().clone(),
                 source: redirect.source_string().to_string(),
+                stable_id: None,
                 formats: redirect.formats(),
                 controls: redirect.controls(),
             },
@@ -213,18 +385,168 @@ pub async fn v4l() -> Json<Vec<ApiVideoSource>> {
 }
 
 #[api_v2_operation]
-/// Change video control for a specific source
+/// Provides the formats, frame sizes and frame intervals of a specific source, as reported by it
+pub async fn v4l_formats(device: web::Path<String>) -> HttpResponse {
+    let source_string = source_string_from_device(device.into_inner());
+
+    let video_source = match video_source::get_video_source(&source_string) {
+        Ok(video_source) => video_source,
+        Err(error) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("{error:#?}"));
+        }
+    };
+
+    let formats: Vec<FormatDescription> = match &video_source {
+        VideoSourceType::Local(local) => local.formats_description(),
+        other => other
+            .inner()
+            .formats()
+            .iter()
+            .map(FormatDescription::from)
+            .collect(),
+    };
+
+    match serde_json::to_string_pretty(&formats) {
+        Ok(json) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(json),
+        Err(error) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Provides every control of a specific source with its limits, current value and flags, read in a single request.
+/// Volatile controls change on their own, like the exposure under an automatic mode, so their values should be polled.
+pub async fn v4l_device_controls(device: web::Path<String>) -> HttpResponse {
+    let source_string = source_string_from_device(device.into_inner());
+
+    let video_source = match video_source::get_video_source(&source_string) {
+        Ok(video_source) => video_source,
+        Err(error) => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain")
+                .body(format!("{error:#?}"));
+        }
+    };
+
+    let controls = video_source.inner().controls();
+
+    match serde_json::to_string_pretty(&controls) {
+        Ok(json) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(json),
+        Err(error) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Change video control for a specific source.
+/// Controls that only take effect while streaming are queued until it starts, answered with 202 Accepted.
 pub fn v4l_post(json: web::Json<V4lControl>) -> HttpResponse {
     let control = json.into_inner();
     let answer = video_source::set_control(&control.device, control.v4l_id, control.value);
 
-    if let Err(error) = answer {
-        return HttpResponse::NotAcceptable()
+    match answer {
+        Ok(status) => control_write_response(&control.device, control.v4l_id, status),
+        Err(error) => HttpResponse::NotAcceptable()
             .content_type("text/plain")
-            .body(format!("{error:#?}"));
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Change a video control of a specific source, applied without restarting its stream.
+/// The value is validated against the limits reported by the driver.
+/// Controls that only take effect while streaming are queued until it starts, answered with 202 Accepted.
+pub fn v4l_device_control(
+    device: web::Path<String>,
+    json: web::Json<DeviceControl>,
+) -> HttpResponse {
+    let source_string = source_string_from_device(device.into_inner());
+    let control = json.into_inner();
+
+    match video_source::set_control(&source_string, control.id, control.value) {
+        Ok(status) => control_write_response(&source_string, control.id, status),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
     }
+}
 
-    HttpResponse::Ok().finish()
+#[api_v2_operation]
+/// Reset the video controls of a specific source to their driver defaults, without restarting its stream.
+/// Inactive, disabled and read-only controls are skipped. Provides the controls that were changed.
+pub fn v4l_device_controls_reset(device: web::Path<String>) -> HttpResponse {
+    let source_string = source_string_from_device(device.into_inner());
+
+    let changes = match video_source::reset_controls(&source_string) {
+        Ok(changes) => changes,
+        Err(errors) => {
+            let body = errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<String>>()
+                .join("\n");
+
+            if errors
+                .iter()
+                .any(|error| error.kind() == std::io::ErrorKind::NotFound)
+            {
+                return HttpResponse::NotFound()
+                    .content_type("text/plain")
+                    .body(body);
+            }
+
+            return HttpResponse::NotAcceptable()
+                .content_type("text/plain")
+                .body(format!(
+                    "One or more controls were not reset due to the following errors:\n{body}"
+                ));
+        }
+    };
+
+    match serde_json::to_string_pretty(&changes) {
+        Ok(json) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(json),
+        Err(error) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+/// Tells apart the control writes applied from the ones waiting for the device to stream
+fn control_write_response(
+    source_string: &str,
+    control_id: u64,
+    status: video_source::ControlWriteStatus,
+) -> HttpResponse {
+    match status {
+        video_source::ControlWriteStatus::Applied => HttpResponse::Ok().finish(),
+        video_source::ControlWriteStatus::Queued => HttpResponse::Accepted()
+            .content_type("text/plain")
+            .body(format!(
+                "Control {control_id} of {source_string:?} only takes effect while streaming, it will be written once it starts"
+            )),
+    }
+}
+
+/// Local devices can be referred by their name, like "video0" for "/dev/video0"
+fn source_string_from_device(device: String) -> String {
+    if device.starts_with('/') {
+        device
+    } else {
+        format!("/dev/{device}")
+    }
 }
 
 #[api_v2_operation]
@@ -245,6 +567,35 @@ pub async fn reset_settings(query: web::Query<ResetSettings>) -> HttpResponse {
         .body("Missing argument for reset_settings.")
 }
 
+#[api_v2_operation]
+/// Provides the whole configuration of this unit (streams, MAVLink endpoints and ICE servers) as a versioned JSON,
+/// to be imported back here or on another unit. The credentials are never exported
+pub async fn config_export() -> Json<settings::backup::Configuration> {
+    Json(settings::backup::export())
+}
+
+#[api_v2_operation]
+/// Replaces the whole configuration with an exported one, recreating all the streams. Everything is validated
+/// first, so nothing changes when any part of it is invalid
+pub async fn config_import(json: web::Json<settings::backup::Configuration>) -> HttpResponse {
+    // The STUN and TURN servers are exported without their credentials, which are taken from this unit
+    let configuration = settings::backup::with_local_credentials(json.into_inner());
+
+    if let Err(error) = settings::backup::validate(&configuration) {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    if let Err(error) = settings::backup::import(configuration).await {
+        return HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
 #[api_v2_operation]
 /// Provide a list of all streams configured
 pub async fn streams() -> HttpResponse {
@@ -281,13 +632,19 @@ pub async fn streams_post(json: web::Json<PostStream>) -> HttpResponse {
         }
     };
 
-    if let Err(error) = stream_manager::add_stream_and_start(VideoAndStreamInformation {
+    if let Err(error) = stream_manager::create_stream(VideoAndStreamInformation {
         name: json.name,
+        tags: json.tags,
         stream_information: json.stream_information,
         video_source,
     })
     .await
     {
+        // Errors posted by GStreamer are structured, so clients can show which element failed and why
+        if let Some(pipeline_error) = error.downcast_ref::<PipelineError>() {
+            return HttpResponse::NotAcceptable().json(pipeline_error);
+        }
+
         return HttpResponse::NotAcceptable()
             .content_type("text/plain")
             .body(format!("{error:#?}"));
@@ -313,88 +670,727 @@ pub async fn streams_post(json: web::Json<PostStream>) -> HttpResponse {
 }
 
 #[api_v2_operation]
-/// Remove a desired stream
-pub fn remove_stream(query: web::Query<RemoveStream>) -> HttpResponse {
-    if let Err(error) = stream_manager::remove_stream_by_name(&query.name).await {
+/// Run the same checks of the stream creation (source availability, format, conflicts, ports and pipeline negotiation) without creating it, reporting each of them
+pub async fn streams_validate(json: web::Json<PostStream>) -> Json<ValidationReport> {
+    let json = json.into_inner();
+
+    Json(
+        stream_validation::validate(json.name, json.tags, &json.source, json.stream_information)
+            .await,
+    )
+}
+
+#[api_v2_operation]
+/// Change the name and/or the tags of a stream
+pub async fn stream_patch(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<PatchStream>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+    let json = json.into_inner();
+
+    if let Err(error) = stream_manager::set_metadata(&stream_id, json.name, json.tags).await {
         return HttpResponse::NotAcceptable()
             .content_type("text/plain")
             .body(format!("{error:#?}"));
     }
 
-    let streams = match stream_manager::streams().await {
-        Ok(streams) => streams,
-        Err(error) => {
-            return HttpResponse::InternalServerError()
-                .content_type("text/plain")
-                .body(format!("{error:#?}"))
-        }
-    };
+    HttpResponse::Ok().finish()
+}
 
-    match serde_json::to_string_pretty(&streams) {
-        Ok(json) => HttpResponse::Ok()
-            .content_type("application/json")
-            .body(json),
-        Err(error) => HttpResponse::InternalServerError()
+#[api_v2_operation]
+/// Remove a stream by its id
+pub async fn stream_delete(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::remove_stream(&stream_id).await {
+        return HttpResponse::NotAcceptable()
             .content_type("text/plain")
-            .body(format!("{error:#?}")),
+            .body(format!("{error:#?}"));
     }
+
+    HttpResponse::Ok().finish()
 }
 
 #[api_v2_operation]
-/// Reset controls from a given camera source
-pub fn camera_reset_controls(json: web::Json<ResetCameraControls>) -> HttpResponse {
-    if let Err(errors) = video_source::reset_controls(&json.device) {
-        let mut error: String = Default::default();
-        errors
-            .iter()
-            .enumerate()
-            .for_each(|(i, e)| error.push_str(&format!("{}: {e}\n", i + 1)));
+/// Change the jitterbuffer latency of a running stream. Higher values absorb more network jitter at the cost of a larger delay.
+pub async fn stream_latency(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<StreamLatency>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::set_latency(&stream_id, json.latency_ms).await {
         return HttpResponse::NotAcceptable()
             .content_type("text/plain")
-            .body(format!(
-                "One or more controls were not reseted due to the following errors: \n{error:#?}",
-            ));
+            .body(format!("{error:#?}"));
     }
 
-    let streams = match stream_manager::streams().await {
-        Ok(streams) => streams,
-        Err(error) => {
-            return HttpResponse::InternalServerError()
-                .content_type("text/plain")
-                .body(format!("{error:#?}"))
-        }
-    };
+    HttpResponse::Ok().finish()
+}
 
-    match serde_json::to_string_pretty(&streams) {
-        Ok(json) => HttpResponse::Ok()
-            .content_type("application/json")
-            .body(json),
-        Err(error) => HttpResponse::InternalServerError()
+#[api_v2_operation]
+/// Change the keyframe interval of a running stream. Shorter intervals let clients start faster and recover sooner from losses, at the cost of bitrate.
+pub async fn stream_keyframe_interval(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<StreamKeyframeInterval>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) =
+        stream_manager::set_keyframe_interval(&stream_id, json.keyframe_interval).await
+    {
+        return HttpResponse::NotAcceptable()
             .content_type("text/plain")
-            .body(format!("{error:#?}")),
+            .body(format!("{error:#?}"));
     }
+
+    HttpResponse::Ok().finish()
 }
 
 #[api_v2_operation]
-/// Provides a xml description file that contains information for a specific device, based on: https://mavlink.io/en/services/camera_def.html
-pub fn xml(xml_file_request: web::Query<XmlFileRequest>) -> HttpResponse {
-    debug!("{xml_file_request:#?}");
-    let cameras = video_source::cameras_available();
-    let camera = cameras
-        .iter()
-        .find(|source| source.inner().source_string() == xml_file_request.file);
+/// Change the rate control (CBR, VBR or CQP) of a running stream. Encoders that can't change it while running have their stream rebuilt.
+pub async fn stream_rate_control(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<RateControlConfiguration>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
 
-    let Some(camera) = camera else {
-        return HttpResponse::NotFound()
+    if let Err(error) = stream_manager::set_rate_control(&stream_id, json.into_inner()).await {
+        return HttpResponse::NotAcceptable()
             .content_type("text/plain")
-            .body(format!(
-                "File for {} does not exist.",
-                xml_file_request.file
-            ));
-    };
+            .body(format!("{error:#?}"));
+    }
 
-    match xml::from_video_source(camera.inner()) {
-        Ok(xml) => HttpResponse::Ok().content_type("text/xml").body(xml),
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Change the H264 profile (constrained-baseline, baseline, main or high) and level of a stream encoded by this service. The stream is rebuilt with them.
+pub async fn stream_h264_profile(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<H264ProfileConfiguration>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::set_h264_profile(&stream_id, json.into_inner()).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Change how a stream recovers when the host's network changes, like on a DHCP renew or a WiFi roam: its UDP destinations are resolved again, its RTSP clients are disconnected so they reconnect, and optionally a keyframe is forced
+pub async fn stream_reconnection(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<ReconnectionConfiguration>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::set_reconnection(&stream_id, json.into_inner()).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Change the resolution streamed, scaled from the captured one with letterboxing or stretching, without changing the camera's format. The running pipeline is renegotiated. Only available for raw video.
+pub async fn stream_output_resolution(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<StreamOutputResolution>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) =
+        stream_manager::set_output_resolution(&stream_id, json.into_inner().output_resolution).await
+    {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Change the highest framerate streamed, dropping the captured frames above it without changing the camera's format. It can't exceed the capture framerate. The running pipeline is renegotiated. Only available for raw video.
+pub async fn stream_max_framerate(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<StreamMaxFramerate>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) =
+        stream_manager::set_max_framerate(&stream_id, json.into_inner().max_framerate).await
+    {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Record a short MP4 clip of a running stream into the storage path, separate from its recording. The clip includes the stream's configured pre-roll, and the response is sent once its file is finalized.
+pub async fn stream_clip(
+    stream_id: web::Path<uuid::Uuid>,
+    query: web::Query<ClipRequest>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+    let duration = std::time::Duration::from_secs(query.duration);
+
+    match stream_manager::record_clip(&stream_id, duration).await {
+        Ok(path) => HttpResponse::Ok().json(Clip { path }),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Save the video kept in memory by a running stream (the seconds before this request), followed by an optional post-roll, as an MP4 file in the storage path. The response is sent once the file is finalized.
+pub async fn stream_replay(
+    stream_id: web::Path<uuid::Uuid>,
+    query: web::Query<ReplayRequest>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+    let post_roll = std::time::Duration::from_secs(query.post_roll);
+
+    match stream_manager::record_clip(&stream_id, post_roll).await {
+        Ok(path) => HttpResponse::Ok().json(Clip { path }),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Change how many seconds of a running stream's encoded video are kept in memory for the instant replays and clips
+pub async fn stream_replay_buffer(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<ReplayBuffer>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::set_clip_pre_roll(&stream_id, json.depth_secs).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Get the digital zoom factor of a running stream
+pub async fn stream_zoom(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    let zoom = match stream_manager::zoom(&stream_id).await {
+        Ok(zoom) => zoom,
+        Err(error) => {
+            return HttpResponse::NotAcceptable()
+                .content_type("text/plain")
+                .body(format!("{error:#?}"));
+        }
+    };
+
+    HttpResponse::Ok().json(StreamZoom {
+        level: zoom.level(),
+        max_level: stream_zoom::MAX_ZOOM,
+    })
+}
+
+#[api_v2_operation]
+/// Change the digital zoom factor of a running stream. The zoom crops the center of the frames, keeping the stream resolution.
+pub async fn stream_zoom_post(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<StreamZoom>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    let result = match stream_manager::zoom(&stream_id).await {
+        Ok(zoom) => zoom.set_level(json.level).map(|_| zoom),
+        Err(error) => Err(error),
+    };
+
+    match result {
+        Ok(zoom) => HttpResponse::Ok().json(StreamZoom {
+            level: zoom.level(),
+            max_level: stream_zoom::MAX_ZOOM,
+        }),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Get the text overlay of a running stream
+pub async fn stream_overlay(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    match stream_manager::overlay(&stream_id).await {
+        Ok(overlay) => HttpResponse::Ok().json(overlay),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Enable, disable or edit the text overlay of a running stream, without rebuilding its pipeline. Only available for raw video.
+pub async fn stream_overlay_post(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<OverlayConfiguration>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::set_overlay(&stream_id, json.into_inner()).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// List the WebRTC and RTSP clients watching a stream
+pub async fn stream_clients(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    match stream_manager::clients(&stream_id).await {
+        Ok(clients) => HttpResponse::Ok().json(clients),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Disconnect a single client from a stream, without affecting the other clients or the encoding
+pub async fn stream_client_delete(path: web::Path<(uuid::Uuid, uuid::Uuid)>) -> HttpResponse {
+    let (stream_id, client_id) = path.into_inner();
+
+    if let Err(error) = stream_manager::disconnect_client(&stream_id, &client_id).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Get the caps currently negotiated by each pad of a running stream's pipeline, to diagnose caps negotiation problems
+pub async fn stream_caps(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    match stream_manager::caps(&stream_id).await {
+        Ok(caps) => HttpResponse::Ok().json(caps),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Get the last thumbnail captured from a stream, periodically refreshed while it runs. The Age header tells how old
+/// it is, as the last one is kept while the stream is down
+pub async fn stream_thumbnail(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    match stream_thumbnails::get(&stream_id) {
+        Some(thumbnail) => HttpResponse::Ok()
+            .content_type("image/jpeg")
+            .insert_header((header::AGE, thumbnail.captured_at.elapsed().as_secs()))
+            .insert_header((header::CACHE_CONTROL, "no-cache"))
+            .body(thumbnail.image),
+        None => HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body(format!(
+                "No thumbnail captured yet for stream {stream_id:?}"
+            )),
+    }
+}
+
+#[api_v2_operation]
+/// Stream the video as MJPEG (multipart/x-mixed-replace JPEG frames), to be embedded as an image by simple dashboards.
+/// The framerate is limited independently of the stream's, bounding the bandwidth used by each client
+pub async fn stream_mjpeg(
+    stream_id: web::Path<uuid::Uuid>,
+    query: web::Query<MjpegRequest>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+    let max_framerate = query
+        .max_framerate
+        .unwrap_or(stream_mjpeg_sink::DEFAULT_MJPEG_MAX_FRAMERATE);
+
+    let (sink_id, frames) = match stream_manager::add_mjpeg_client(&stream_id, max_framerate).await
+    {
+        Ok(client) => client,
+        Err(error) => {
+            return HttpResponse::NotAcceptable()
+                .content_type("text/plain")
+                .body(format!("{error:#?}"))
+        }
+    };
+
+    let guard = MjpegClientGuard { stream_id, sink_id };
+    let parts = futures::stream::unfold((frames, guard), |(mut frames, guard)| async move {
+        let frame = frames.recv().await?;
+
+        let mut part = format!(
+            "--{MJPEG_BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            frame.len()
+        )
+        .into_bytes();
+        part.extend_from_slice(&frame);
+        part.extend_from_slice(b"\r\n");
+
+        Some((
+            Ok::<_, actix_web::Error>(web::Bytes::from(part)),
+            (frames, guard),
+        ))
+    });
+
+    HttpResponse::Ok()
+        .content_type(format!(
+            "multipart/x-mixed-replace; boundary={MJPEG_BOUNDARY}"
+        ))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(parts)
+}
+
+#[api_v2_operation]
+/// Get the SDP file of a stream's UDP endpoints, to play them with VLC or ffmpeg. It follows the currently negotiated
+/// caps, including the H264/H265 parameter sets, so it is playable without waiting for a keyframe
+pub async fn stream_sdp(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    match stream_manager::sdp(&stream_id)
+        .await
+        .and_then(|sdp| Ok(sdp.as_text()?))
+    {
+        Ok(sdp) => HttpResponse::Ok().content_type("application/sdp").body(sdp),
+        Err(error) => HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, 1))
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Get the bitrate, framerate and number of clients of a stream over the last 5 minutes, sampled each second, oldest
+/// first
+pub async fn stream_stats_history(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    match stream_metrics::history(&stream_id) {
+        Some(history) => HttpResponse::Ok().json(history),
+        None => HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body(format!(
+                "No statistics sampled yet for stream {stream_id:?}"
+            )),
+    }
+}
+
+#[api_v2_operation]
+/// Get the bytes sent by a stream through all of its sinks, in total and since the counter was last reset
+pub async fn stream_bandwidth(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    match stream_manager::bandwidth(&stream_id).await {
+        Ok(bandwidth) => HttpResponse::Ok().json(bandwidth),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Reset the counter of bytes sent by a stream, keeping its total
+pub async fn stream_bandwidth_reset(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::reset_bandwidth(&stream_id).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Get the audio of a stream, and if it is being captured
+pub async fn stream_audio(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    match stream_manager::audio(&stream_id).await {
+        Ok(audio) => HttpResponse::Ok().json(audio),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Enable, change or disable the audio of a stream, muxed into its recordings and RTSP endpoints. The stream is rebuilt, and stays video only if the audio device is absent.
+pub async fn stream_audio_post(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<StreamAudio>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::set_audio(&stream_id, json.into_inner().audio).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Get the clock of a stream, its base time and its latency
+pub async fn stream_clock(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    match stream_manager::clock(&stream_id).await {
+        Ok(clock) => HttpResponse::Ok().json(clock),
+        Err(error) => HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Change the clock and base time of a stream, like an NTP or PTP clock shared with the streams of other cameras, so their frames are timestamped on the same timeline. The stream is rebuilt.
+pub async fn stream_clock_post(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<StreamClock>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::set_clock(&stream_id, json.into_inner().clock).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Change the STUN and TURN servers of a stream, overriding the global ones. Only new WebRTC sessions are affected
+pub async fn stream_ice_servers_post(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<IceServers>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) =
+        stream_manager::set_ice_servers(&stream_id, json.into_inner().ice_servers).await
+    {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// List the audio capture devices available
+pub async fn audio_devices() -> Json<Vec<AudioDevice>> {
+    Json(stream_audio::available_devices())
+}
+
+#[api_v2_operation]
+/// Restart a stream, rebuilding its pipeline with the same configuration and id, so its clients can reconnect to
+/// the same URLs
+pub async fn stream_restart(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::restart_stream(&stream_id).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Disable a stream, releasing its device and ports while keeping its configuration
+pub async fn stream_disable(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::disable_stream(&stream_id).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Enable a disabled stream. If its device is absent or in use, it starts once a device is connected
+pub async fn stream_enable(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::enable_stream(&stream_id).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Ask the encoder of a running stream for a keyframe, so clients can start decoding right away
+pub async fn stream_force_keyframe(stream_id: web::Path<uuid::Uuid>) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::force_keyframe(&stream_id).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Add an UDP destination to a running stream, without disturbing its current destinations
+pub async fn stream_udp_destination_post(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<UdpDestination>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::add_udp_destination(&stream_id, &json.destination).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Remove an UDP destination from a running stream, without disturbing its other destinations
+pub async fn stream_udp_destination_delete(
+    stream_id: web::Path<uuid::Uuid>,
+    json: web::Json<UdpDestination>,
+) -> HttpResponse {
+    let stream_id = stream_id.into_inner();
+
+    if let Err(error) = stream_manager::remove_udp_destination(&stream_id, &json.destination).await
+    {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Remove a desired stream
+pub fn remove_stream(query: web::Query<RemoveStream>) -> HttpResponse {
+    if let Err(error) = stream_manager::remove_stream_by_name(&query.name).await {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    let streams = match stream_manager::streams().await {
+        Ok(streams) => streams,
+        Err(error) => {
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body(format!("{error:#?}"))
+        }
+    };
+
+    match serde_json::to_string_pretty(&streams) {
+        Ok(json) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(json),
+        Err(error) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Reset controls from a given camera source
+pub fn camera_reset_controls(json: web::Json<ResetCameraControls>) -> HttpResponse {
+    if let Err(errors) = video_source::reset_controls(&json.device) {
+        let mut error: String = Default::default();
+        errors
+            .iter()
+            .enumerate()
+            .for_each(|(i, e)| error.push_str(&format!("{}: {e}\n", i + 1)));
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!(
+                "One or more controls were not reseted due to the following errors: \n{error:#?}",
+            ));
+    }
+
+    let streams = match stream_manager::streams().await {
+        Ok(streams) => streams,
+        Err(error) => {
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body(format!("{error:#?}"))
+        }
+    };
+
+    match serde_json::to_string_pretty(&streams) {
+        Ok(json) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(json),
+        Err(error) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Provides a xml description file that contains information for a specific device, based on: https://mavlink.io/en/services/camera_def.html
+pub fn xml(xml_file_request: web::Query<XmlFileRequest>) -> HttpResponse {
+    debug!("{xml_file_request:#?}");
+    let cameras = video_source::cameras_available();
+    let camera = cameras
+        .iter()
+        .find(|source| source.inner().source_string() == xml_file_request.file);
+
+    let Some(camera) = camera else {
+        return HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body(format!(
+                "File for {} does not exist.",
+                xml_file_request.file
+            ));
+    };
+
+    match xml::from_video_source(camera.inner()) {
+        Ok(xml) => HttpResponse::Ok().content_type("text/xml").body(xml),
         Err(error) => HttpResponse::InternalServerError().body(format!(
             "Failed getting XML file {}: {error:?}",
             xml_file_request.file
@@ -479,3 +1475,153 @@ pub async fn gst_info() -> HttpResponse {
             .body(format!("{error:#?}")),
     }
 }
+
+#[api_v2_operation]
+/// Provides the GStreamer version and which of the encoders, payloaders and sinks used by the streams are installed
+pub async fn system_gstreamer() -> Json<gst_stream::availability::GStreamerAvailability> {
+    Json(gst_stream::availability::availability())
+}
+
+#[api_v2_operation]
+/// Provides the filter currently applied to the console log
+pub async fn log_level() -> HttpResponse {
+    match logger::manager::console_filter() {
+        Ok(filter) => HttpResponse::Ok().json(LogFilter { filter }),
+        Err(error) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Change the filter of the console log, taking effect immediately without restarting the service
+pub async fn log_level_post(json: web::Json<LogFilter>) -> HttpResponse {
+    if let Err(error) = logger::manager::set_console_filter(&json.filter) {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Provides the STUN and TURN servers used by the WebRTC sessions of streams without their own
+pub async fn webrtc_ice_servers() -> Json<Vec<IceServer>> {
+    Json(stream_ice_servers::global())
+}
+
+#[api_v2_operation]
+/// Change the STUN and TURN servers used by the WebRTC sessions of streams without their own.
+/// Only new sessions are affected, so credentials can be rotated without dropping the active ones
+pub async fn webrtc_ice_servers_post(json: web::Json<IceServers>) -> HttpResponse {
+    if let Err(error) = stream_ice_servers::set_global(json.into_inner().ice_servers) {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Provides the usernames accepted by the RTSP server, without their passwords. When empty, the RTSP server is open
+pub async fn rtsp_credentials() -> Json<Vec<String>> {
+    Json(stream_rtsp_auth::usernames())
+}
+
+#[api_v2_operation]
+/// Change the users accepted by the RTSP server, through basic or digest authentication.
+/// Only new connections are affected, so credentials can be rotated without dropping the clients already playing
+pub async fn rtsp_credentials_post(json: web::Json<RtspCredentialsList>) -> HttpResponse {
+    if let Err(error) = stream_rtsp_auth::set_credentials(json.into_inner().credentials) {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Provides the MAVLink endpoints the cameras are reachable through
+pub async fn mavlink_endpoints() -> Json<Vec<String>> {
+    Json(mavlink_manager::Manager::endpoints())
+}
+
+#[api_v2_operation]
+/// Connect to one more MAVLink endpoint, which receives the heartbeats and messages of all cameras along with the
+/// other endpoints, while the answers to its commands are only sent back through it
+pub async fn mavlink_endpoints_post(json: web::Json<MavlinkEndpoint>) -> HttpResponse {
+    if let Err(error) = mavlink_manager::Manager::add_endpoint(&json.endpoint) {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Disconnect from a MAVLink endpoint, without disturbing the other ones
+pub async fn mavlink_endpoints_delete(json: web::Json<MavlinkEndpoint>) -> HttpResponse {
+    if let Err(error) = mavlink_manager::Manager::remove_endpoint(&json.endpoint) {
+        return HttpResponse::NotAcceptable()
+            .content_type("text/plain")
+            .body(format!("{error:#?}"));
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+#[api_v2_operation]
+/// Provides the interval of the messages each MAVLink camera sends on its own through each MAVLink endpoint, as
+/// requested by the ground stations through it with MAV_CMD_SET_MESSAGE_INTERVAL, for debugging links with little
+/// bandwidth
+pub async fn mavlink_message_intervals(
+) -> Json<Vec<mavlink_message_intervals::MessageIntervalStatus>> {
+    let links = mavlink_manager::Manager::endpoints();
+    Json(mavlink_message_intervals::intervals(&links))
+}
+
+#[api_v2_operation]
+/// Provides the status of all WebRTC sessions, including their current target bitrate
+pub async fn webrtc_sessions() -> HttpResponse {
+    let sessions = stream_manager::webrtc_sessions().await;
+
+    match serde_json::to_string_pretty(&sessions) {
+        Ok(json) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(json),
+        Err(error) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
+
+#[api_v2_operation]
+/// Provides the overall status of the service, for liveness and readiness probes. Answers with 503 when the
+/// service can't serve any stream
+pub async fn health() -> HttpResponse {
+    let health = stream_health::health().await;
+
+    match health.status {
+        stream_health::HealthStatus::Unhealthy => HttpResponse::ServiceUnavailable().json(health),
+        _ => HttpResponse::Ok().json(health),
+    }
+}
+
+#[api_v2_operation]
+/// Provides per-stream metrics in the Prometheus text exposition format
+pub async fn metrics() -> HttpResponse {
+    let streams = stream_manager::metrics().await;
+
+    match stream_metrics::render(&streams) {
+        Ok(output) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(output),
+        Err(error) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("{error:#?}")),
+    }
+}
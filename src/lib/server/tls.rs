@@ -0,0 +1,73 @@
+use std::{fs::File, io::BufReader};
+
+use anyhow::{anyhow, Context, Result};
+use tracing::*;
+
+use crate::cli;
+
+/// The TLS configuration of the REST API server, when enabled by the user. A missing or invalid certificate or key
+/// is an error, so the service doesn't silently fall back to plain HTTP
+#[instrument(level = "debug")]
+pub fn server_config() -> Result<Option<rustls::ServerConfig>> {
+    let Some((cert_path, key_path)) = cli::manager::tls_paths() else {
+        return Ok(None);
+    };
+
+    load(&cert_path, &key_path).map(Some)
+}
+
+/// Loads a PEM certificate chain and its private key (PKCS#1, PKCS#8 or SEC1)
+fn load(cert_path: &str, key_path: &str) -> Result<rustls::ServerConfig> {
+    let mut reader = BufReader::new(
+        File::open(cert_path).context(format!("Failed opening TLS certificate {cert_path:?}"))?,
+    );
+    let certificates = rustls_pemfile::certs(&mut reader)
+        .map(|certificate| certificate.map(|certificate| rustls::Certificate(certificate.to_vec())))
+        .collect::<Result<Vec<rustls::Certificate>, _>>()
+        .context(format!("Failed reading TLS certificate {cert_path:?}"))?;
+    if certificates.is_empty() {
+        return Err(anyhow!("No PEM certificate found in {cert_path:?}"));
+    }
+
+    let mut reader = BufReader::new(
+        File::open(key_path).context(format!("Failed opening TLS key {key_path:?}"))?,
+    );
+    let key = rustls_pemfile::private_key(&mut reader)
+        .context(format!("Failed reading TLS key {key_path:?}"))?
+        .context(format!("No PEM private key found in {key_path:?}"))?;
+
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certificates, rustls::PrivateKey(key.secret_der().to_vec()))
+        .context(format!(
+            "Invalid TLS certificate {cert_path:?} or key {key_path:?}"
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_errors() {
+        let path = |name: &str| {
+            std::env::temp_dir()
+                .join(format!("{}-{name}", uuid::Uuid::new_v4()))
+                .to_string_lossy()
+                .to_string()
+        };
+
+        // Missing files
+        assert!(load(&path("cert.pem"), &path("key.pem")).is_err());
+
+        // Files without any PEM section
+        let not_pem = path("not.pem");
+        std::fs::write(&not_pem, "not a certificate").unwrap();
+        assert!(load(&not_pem, &not_pem)
+            .unwrap_err()
+            .to_string()
+            .contains("No PEM certificate"));
+        std::fs::remove_file(&not_pem).unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+use tracing::*;
+
+use crate::stream::pipeline::runner::{subscribe_events, PipelineEvent};
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    /// Only forward the events of this stream
+    stream_id: Option<uuid::Uuid>,
+}
+
+/// Pushes the pipeline, stream and client events as JSON messages, optionally filtered by a stream id
+#[instrument(level = "debug", skip(req, body))]
+pub async fn events(
+    req: HttpRequest,
+    body: web::Payload,
+    query: web::Query<EventsQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+
+    let stream_id = query.into_inner().stream_id;
+    actix_web::rt::spawn(forward_events(session, messages, stream_id));
+
+    Ok(response)
+}
+
+#[instrument(level = "debug", skip(session, messages))]
+async fn forward_events(
+    mut session: actix_ws::Session,
+    mut messages: actix_ws::MessageStream,
+    stream_id: Option<uuid::Uuid>,
+) {
+    debug!("Events client connected");
+
+    let mut events = subscribe_events();
+    let is_wanted =
+        |event: &PipelineEvent| stream_id.map_or(true, |stream_id| event.pipeline_id == stream_id);
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            message = messages.next() => match message {
+                Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                Some(Ok(actix_ws::Message::Close(reason))) => {
+                    debug!("Events client disconnected: {reason:?}");
+                    let _ = session.close(reason).await;
+                    return;
+                }
+                Some(Ok(_)) => continue,
+                None | Some(Err(_)) => break,
+            },
+        };
+
+        let event = match event {
+            Ok(event) => event,
+            // A slow client would make us buffer events without bounds, so skip to the latest one instead
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Events client is falling behind, skipping at least {skipped} events");
+                match latest_event(&mut events, is_wanted) {
+                    Some(event) => event,
+                    None => continue,
+                }
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        if !is_wanted(&event) {
+            continue;
+        }
+
+        let message = match serde_json::to_string(&event) {
+            Ok(message) => message,
+            Err(error) => {
+                warn!("Failed serializing event {event:?}: {error:?}");
+                continue;
+            }
+        };
+
+        if session.text(message).await.is_err() {
+            break;
+        }
+    }
+
+    debug!("Events client disconnected");
+    let _ = session.close(None).await;
+}
+
+/// Drains the receiver, returning only the latest wanted event
+fn latest_event(
+    events: &mut broadcast::Receiver<PipelineEvent>,
+    is_wanted: impl Fn(&PipelineEvent) -> bool,
+) -> Option<PipelineEvent> {
+    let mut latest = None;
+
+    loop {
+        match events.try_recv() {
+            Ok(event) if is_wanted(&event) => latest = Some(event),
+            Ok(_) | Err(TryRecvError::Lagged(_)) => continue,
+            Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+        }
+    }
+
+    latest
+}
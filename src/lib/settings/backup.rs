@@ -0,0 +1,371 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Context, Result};
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use crate::{
+    cli,
+    mavlink::manager::{validate_endpoint, Manager as MavlinkManager},
+    stream::{types::IceServer, webrtc::ice_servers},
+    video_stream::types::VideoAndStreamInformation,
+};
+
+/// The version of the exported configuration, increased whenever older versions of the service can't import it
+pub const CONFIGURATION_VERSION: u32 = 1;
+
+/// The configuration of a unit, to be restored on it or on another one. The API credentials, the allowed origins and
+/// the RTSP credentials are not part of it, and the STUN and TURN servers are exported without their username and
+/// credential, so secrets are never exported and each unit keeps its own
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Configuration {
+    /// The version of this format, see [`CONFIGURATION_VERSION`]
+    pub version: u32,
+    /// All the streams, including the disabled ones and the ones not auto-started
+    pub streams: Vec<VideoAndStreamInformation>,
+    /// The MAVLink endpoints added through the REST API. The ones from the CLI arguments belong to each unit, and
+    /// are kept connected
+    #[serde(default)]
+    pub mavlink_endpoints: Vec<String>,
+    /// The STUN and TURN servers of all streams. When missing, the ones from the CLI arguments are used
+    #[serde(default)]
+    pub ice_servers: Option<Vec<IceServer>>,
+}
+
+#[instrument(level = "debug")]
+pub fn export() -> Configuration {
+    without_credentials(current())
+}
+
+fn without_credentials(mut configuration: Configuration) -> Configuration {
+    for ice_server in
+        ice_servers_mut(&mut configuration).flat_map(|ice_servers| ice_servers.iter_mut())
+    {
+        ice_server.username = None;
+        ice_server.credential = None;
+    }
+
+    configuration
+}
+
+/// The running configuration, with all its credentials
+fn current() -> Configuration {
+    Configuration {
+        version: CONFIGURATION_VERSION,
+        streams: super::manager::streams(),
+        mavlink_endpoints: super::manager::mavlink_extra_endpoints(),
+        ice_servers: super::manager::ice_servers(),
+    }
+}
+
+/// The global STUN and TURN servers, and the ones of each stream
+fn ice_servers_mut(configuration: &mut Configuration) -> impl Iterator<Item = &mut Vec<IceServer>> {
+    configuration.ice_servers.iter_mut().chain(
+        configuration
+            .streams
+            .iter_mut()
+            .filter_map(|stream| stream.stream_information.extended_configuration.as_mut())
+            .filter_map(|extended_configuration| extended_configuration.ice_servers.as_mut()),
+    )
+}
+
+/// Fills the username and credential of the STUN and TURN servers exported without them, from the servers with the
+/// same urls known by this unit, so an exported configuration keeps working when imported back
+#[instrument(level = "debug", skip(configuration))]
+pub fn with_local_credentials(mut configuration: Configuration) -> Configuration {
+    let mut local = current();
+    let mut known_ice_servers = ice_servers_mut(&mut local)
+        .flat_map(|ice_servers| ice_servers.drain(..))
+        .collect::<Vec<IceServer>>();
+    known_ice_servers.extend(ice_servers::from_cli());
+
+    for ice_server in
+        ice_servers_mut(&mut configuration).flat_map(|ice_servers| ice_servers.iter_mut())
+    {
+        let Some(known) = known_ice_servers
+            .iter()
+            .find(|known| known.urls == ice_server.urls)
+        else {
+            continue;
+        };
+
+        if ice_server.username.is_none() {
+            ice_server.username.clone_from(&known.username);
+        }
+        if ice_server.credential.is_none() {
+            ice_server.credential.clone_from(&known.credential);
+        }
+    }
+
+    configuration
+}
+
+/// Checks everything in the configuration, without touching the running state. The devices of the streams don't
+/// need to be connected, as the streams wait for them
+#[instrument(level = "debug", skip(configuration))]
+pub fn validate(configuration: &Configuration) -> Result<()> {
+    if configuration.version != CONFIGURATION_VERSION {
+        return Err(anyhow!(
+            "Configuration version {} is not supported, only version {CONFIGURATION_VERSION} is",
+            configuration.version
+        ));
+    }
+
+    // Names don't need to be unique, the streams are told apart by their ids, created when imported
+    for stream in &configuration.streams {
+        crate::stream::validate_configuration(stream)
+            .with_context(|| format!("Invalid stream {:?}", stream.name))?;
+    }
+
+    // Disabled streams release their devices and ports, so only the enabled ones can conflict
+    let enabled_streams = configuration
+        .streams
+        .iter()
+        .filter(|stream| stream.stream_information.enabled())
+        .collect::<Vec<&VideoAndStreamInformation>>();
+    for (index, stream) in enabled_streams.iter().enumerate() {
+        for other in &enabled_streams[..index] {
+            other.conflicts_with(stream)?;
+        }
+    }
+
+    let mut endpoints = HashSet::new();
+    for endpoint in &configuration.mavlink_endpoints {
+        validate_endpoint(endpoint)?;
+
+        if !endpoints.insert(endpoint) {
+            return Err(anyhow!("MAVLink endpoint {endpoint:?} is repeated"));
+        }
+    }
+
+    if let Some(ice_servers) = &configuration.ice_servers {
+        ice_servers::validate(ice_servers)?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the running configuration with the given one, recreating all the streams. Nothing is changed when the
+/// configuration is invalid, and the previous configuration is restored when applying it fails
+#[instrument(level = "debug", skip(configuration))]
+pub async fn import(configuration: Configuration) -> Result<()> {
+    let configuration = with_local_credentials(configuration);
+    validate(&configuration)?;
+
+    let previous = current();
+    let streams = configuration.streams.len();
+
+    apply_or_restore(configuration, previous, apply).await?;
+
+    info!("Configuration imported with {streams} streams");
+
+    Ok(())
+}
+
+/// Applies the configuration, applying the previous one back when any part of it fails
+async fn apply_or_restore<F, Fut>(
+    configuration: Configuration,
+    previous: Configuration,
+    apply: F,
+) -> Result<()>
+where
+    F: Fn(Configuration) -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    if let Err(error) = apply(configuration).await {
+        warn!("Failed importing the configuration, restoring the previous one: {error:#?}");
+
+        if let Err(restore_error) = apply(previous).await {
+            error!("Failed restoring the previous configuration: {restore_error:#?}");
+        }
+
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Fails if any of the streams can't be started, as they would be silently dropped from the settings otherwise
+async fn apply(configuration: Configuration) -> Result<()> {
+    ice_servers::set_global(configuration.ice_servers)?;
+
+    // The endpoints from the CLI arguments are kept, while the other ones are replaced
+    let cli_endpoints = cli::manager::mavlink_connection_strings();
+    let wanted_endpoints = cli_endpoints
+        .iter()
+        .chain(&configuration.mavlink_endpoints)
+        .collect::<HashSet<&String>>();
+    let current_endpoints = MavlinkManager::endpoints();
+    for endpoint in current_endpoints
+        .iter()
+        .filter(|endpoint| !wanted_endpoints.contains(endpoint))
+    {
+        MavlinkManager::remove_endpoint(endpoint)?;
+    }
+    for endpoint in configuration
+        .mavlink_endpoints
+        .iter()
+        .filter(|endpoint| !current_endpoints.contains(endpoint))
+    {
+        MavlinkManager::add_endpoint(endpoint)?;
+    }
+
+    // The streams are recreated from the settings, just like when the service starts
+    super::manager::set_streams(&configuration.streams);
+    crate::stream::manager::start_default().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::types::{
+        CaptureConfiguration, ExtendedConfiguration, StreamInformation, VideoCaptureConfiguration,
+    };
+    use crate::video::{
+        types::{FrameInterval, VideoEncodeType, VideoSourceType},
+        video_source_local::{VideoSourceLocal, VideoSourceLocalType},
+    };
+    use url::Url;
+
+    fn stream(name: &str, device_path: &str, endpoint: &str) -> VideoAndStreamInformation {
+        VideoAndStreamInformation {
+            name: name.into(),
+            tags: vec![],
+            stream_information: StreamInformation {
+                endpoints: vec![Url::parse(endpoint).unwrap()],
+                configuration: CaptureConfiguration::Video(VideoCaptureConfiguration {
+                    encode: VideoEncodeType::H264,
+                    height: 1080,
+                    width: 1920,
+                    frame_interval: FrameInterval {
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                }),
+                extended_configuration: None,
+            },
+            video_source: VideoSourceType::Local(VideoSourceLocal {
+                name: name.into(),
+                device_path: device_path.into(),
+                typ: VideoSourceLocalType::Usb("usb-0000:00:00.0-1".into()),
+                stable_id: None,
+            }),
+        }
+    }
+
+    fn configuration(streams: Vec<VideoAndStreamInformation>) -> Configuration {
+        Configuration {
+            version: CONFIGURATION_VERSION,
+            streams,
+            mavlink_endpoints: vec!["udpin:0.0.0.0:14551".to_string()],
+            ice_servers: None,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let front = stream("Front", "/dev/video0", "udp://192.168.2.1:5600");
+        let bottom = stream("Bottom", "/dev/video2", "udp://192.168.2.1:5602");
+        assert!(validate(&configuration(vec![front.clone(), bottom.clone()])).is_ok());
+
+        let mut newer = configuration(vec![]);
+        newer.version = CONFIGURATION_VERSION + 1;
+        assert!(validate(&newer).is_err());
+
+        // Streams conflicting with each other
+        let same_device = stream("Other", "/dev/video0", "udp://192.168.2.1:5604");
+        assert!(validate(&configuration(vec![front.clone(), same_device])).is_err());
+
+        // Streams can share the same name
+        let same_name = stream("Front", "/dev/video4", "udp://192.168.2.1:5606");
+        assert!(validate(&configuration(vec![front.clone(), same_name])).is_ok());
+
+        let mut repeated_endpoints = configuration(vec![front]);
+        repeated_endpoints
+            .mavlink_endpoints
+            .push("udpin:0.0.0.0:14551".to_string());
+        assert!(validate(&repeated_endpoints).is_err());
+
+        let mut invalid_endpoint = configuration(vec![bottom]);
+        invalid_endpoint.mavlink_endpoints = vec!["udp:0.0.0.0:14551".to_string()];
+        assert!(validate(&invalid_endpoint).is_err());
+    }
+
+    #[test]
+    fn test_without_credentials() {
+        let turn_server = IceServer {
+            urls: vec!["turn:turn.example.com:3478".to_string()],
+            username: Some("user".to_string()),
+            credential: Some("password".to_string()),
+        };
+
+        let mut front = stream("Front", "/dev/video0", "udp://192.168.2.1:5600");
+        front.stream_information.extended_configuration = Some(ExtendedConfiguration {
+            ice_servers: Some(vec![turn_server.clone()]),
+            ..Default::default()
+        });
+        let mut exported = configuration(vec![front]);
+        exported.ice_servers = Some(vec![turn_server]);
+
+        let exported = without_credentials(exported);
+        let json = serde_json::to_string(&exported).unwrap();
+        assert!(!json.contains("password"));
+        assert!(!json.contains("user"));
+        assert!(json.contains("turn:turn.example.com:3478"));
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let exported = configuration(vec![stream(
+            "Front",
+            "/dev/video0",
+            "udp://192.168.2.1:5600",
+        )]);
+
+        let json = serde_json::to_string(&exported).unwrap();
+        let imported: Configuration = serde_json::from_str(&json).unwrap();
+        assert_eq!(imported, exported);
+    }
+
+    #[tokio::test]
+    async fn test_apply_or_restore() {
+        let previous = configuration(vec![stream(
+            "Front",
+            "/dev/video0",
+            "udp://192.168.2.1:5600",
+        )]);
+        let applied = std::sync::Mutex::new(vec![]);
+        // Like a stream failing to start
+        let apply = |configuration: Configuration| {
+            let fails = configuration
+                .streams
+                .iter()
+                .any(|stream| stream.name == "Broken");
+            applied.lock().unwrap().push(configuration);
+            async move {
+                if fails {
+                    return Err(anyhow!("Failed starting 1 streams"));
+                }
+                Ok(())
+            }
+        };
+
+        let broken = configuration(vec![stream(
+            "Broken",
+            "/dev/video2",
+            "udp://192.168.2.1:5602",
+        )]);
+        assert!(apply_or_restore(broken.clone(), previous.clone(), &apply)
+            .await
+            .is_err());
+        assert_eq!(*applied.lock().unwrap(), vec![broken, previous.clone()]);
+
+        applied.lock().unwrap().clear();
+        let working = configuration(vec![]);
+        assert!(apply_or_restore(working.clone(), previous, &apply)
+            .await
+            .is_ok());
+        assert_eq!(*applied.lock().unwrap(), vec![working]);
+    }
+}
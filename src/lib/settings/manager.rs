@@ -8,8 +8,12 @@ use tracing::*;
 
 use crate::cli;
 use crate::custom;
+use crate::server::{auth::AuthSettings, cors::CorsSettings};
+use crate::stream::types::{IceServer, RtspCredentials};
 use crate::video_stream::types::VideoAndStreamInformation;
 
+use super::migrations;
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct HeaderSettingsFile {
     pub name: String,
@@ -20,7 +24,22 @@ pub struct HeaderSettingsFile {
 pub struct SettingsStruct {
     pub header: HeaderSettingsFile,
     pub mavlink_endpoint: String, //TODO: Move to URL
+    /// The MAVLink endpoints added through the REST API, connected along with the ones from the CLI arguments
+    #[serde(default)]
+    pub mavlink_extra_endpoints: Vec<String>,
     pub streams: Vec<VideoAndStreamInformation>,
+    /// The STUN and TURN servers of all streams. When missing, the ones from the CLI arguments are used
+    #[serde(default)]
+    pub ice_servers: Option<Vec<IceServer>>,
+    /// The credentials required by the REST API. When missing, it is open to anyone reaching it
+    #[serde(default)]
+    pub auth: Option<AuthSettings>,
+    /// The web pages allowed to use the REST API from other origins. When missing, any origin is allowed
+    #[serde(default)]
+    pub cors: Option<CorsSettings>,
+    /// The users accepted by the RTSP server. When missing, any client can play the RTSP streams
+    #[serde(default)]
+    pub rtsp_credentials: Option<Vec<RtspCredentials>>,
 }
 
 #[derive(Debug)]
@@ -42,10 +61,15 @@ impl Default for SettingsStruct {
         SettingsStruct {
             header: HeaderSettingsFile {
                 name: "Camera Manager".to_string(),
-                version: 0,
+                version: migrations::CURRENT_VERSION,
             },
             mavlink_endpoint: cli::manager::mavlink_connection_string(),
+            mavlink_extra_endpoints: vec![],
             streams: custom::create_default_streams(),
+            ice_servers: None,
+            auth: None,
+            cors: None,
+            rtsp_credentials: None,
         }
     }
 }
@@ -124,12 +148,34 @@ fn load_settings_from_file(file_name: &str) -> SettingsStruct {
     std::fs::read_to_string(file_name)
         .map_err(Error::msg)
         .and_then(|value| serde_json::from_str(&value).map_err(Error::msg))
+        .and_then(|value| migrate_settings(file_name, value))
+        .and_then(|value| serde_json::from_value(value).map_err(Error::msg))
         .unwrap_or_else(|error| {
             warn!("Failed to load settings file {file_name:?}. Reason: {error}");
             fallback_settings_with_backup_file(file_name)
         })
 }
 
+/// Upgrades settings written by older releases to the current version, keeping a backup of the original file, as
+/// older releases can't load the upgraded one
+fn migrate_settings(file_name: &str, mut settings: serde_json::Value) -> Result<serde_json::Value> {
+    let version = migrations::version(&settings)?;
+    if version >= migrations::CURRENT_VERSION {
+        migrations::migrate(&mut settings)?;
+        return Ok(settings);
+    }
+
+    let backup_file_name = format!("{file_name}.v{version}.bak");
+    info!("The settings file {file_name:?} is from version {version}, it will be backed-up as {backup_file_name:?} and migrated to version {}.", migrations::CURRENT_VERSION);
+    std::fs::copy(file_name, &backup_file_name).map_err(|error| {
+        anyhow!("Failed to create backup file {backup_file_name:?}. Reason: {error:#?}")
+    })?;
+
+    migrations::migrate(&mut settings)?;
+
+    Ok(settings)
+}
+
 fn create_directories(file_name: &str) -> Result<()> {
     let path = Path::new(&file_name);
     if let Some(parent) = path.parent() {
@@ -196,6 +242,66 @@ pub fn set_mavlink_endpoint(endpoint: &str) {
     save();
 }
 
+pub fn mavlink_extra_endpoints() -> Vec<String> {
+    let manager = MANAGER.read().unwrap();
+    let content = manager.content.as_ref();
+    content.unwrap().config.mavlink_extra_endpoints.clone()
+}
+
+pub fn set_mavlink_extra_endpoints(endpoints: Vec<String>) {
+    // Take care of scope RwLock
+    {
+        let mut manager = MANAGER.write().unwrap();
+        let mut content = manager.content.as_mut();
+        content.as_mut().unwrap().config.mavlink_extra_endpoints = endpoints;
+    }
+    save();
+}
+
+pub fn ice_servers() -> Option<Vec<IceServer>> {
+    let manager = MANAGER.read().unwrap();
+    let content = manager.content.as_ref();
+    content.unwrap().config.ice_servers.clone()
+}
+
+pub fn set_ice_servers(ice_servers: Option<Vec<IceServer>>) {
+    // Take care of scope RwLock
+    {
+        let mut manager = MANAGER.write().unwrap();
+        let mut content = manager.content.as_mut();
+        content.as_mut().unwrap().config.ice_servers = ice_servers;
+    }
+    save();
+}
+
+pub fn auth() -> Option<AuthSettings> {
+    let manager = MANAGER.read().unwrap();
+    let content = manager.content.as_ref();
+    content.unwrap().config.auth.clone()
+}
+
+pub fn cors() -> Option<CorsSettings> {
+    let manager = MANAGER.read().unwrap();
+    let content = manager.content.as_ref();
+    content.unwrap().config.cors.clone()
+}
+
+pub fn rtsp_credentials() -> Option<Vec<RtspCredentials>> {
+    let manager = MANAGER.read().unwrap();
+    let content = manager.content.as_ref();
+    content.unwrap().config.rtsp_credentials.clone()
+}
+
+pub fn set_rtsp_credentials(rtsp_credentials: Option<Vec<RtspCredentials>>) {
+    // Take care of scope RwLock
+    {
+        let mut manager = MANAGER.write().unwrap();
+        let mut content = manager.content.as_mut();
+        content.as_mut().unwrap().config.rtsp_credentials = rtsp_credentials;
+    }
+    save();
+}
+
 pub fn streams() -> Vec<VideoAndStreamInformation> {
     let manager = MANAGER.read().unwrap();
     let content = manager.content.as_ref();
@@ -222,7 +328,14 @@ pub fn reset() {
     // Take care of scope RwLock
     {
         let mut manager = MANAGER.write().unwrap();
-        manager.content.as_mut().unwrap().config = SettingsStruct::default();
+        let config = &mut manager.content.as_mut().unwrap().config;
+        // Resetting shouldn't open the API, nor the RTSP streams, to anyone, anywhere
+        *config = SettingsStruct {
+            auth: config.auth.take(),
+            cors: config.cors.take(),
+            rtsp_credentials: config.rtsp_credentials.take(),
+            ..Default::default()
+        };
     }
     save();
 }
@@ -273,8 +386,13 @@ mod tests {
         set_mavlink_endpoint(fake_mavlink_endpoint);
         assert_eq!(mavlink_endpoint(), fake_mavlink_endpoint);
 
+        let fake_mavlink_extra_endpoints = vec!["serial:/dev/potato:57600".to_string()];
+        set_mavlink_extra_endpoints(fake_mavlink_extra_endpoints.clone());
+        assert_eq!(mavlink_extra_endpoints(), fake_mavlink_extra_endpoints);
+
         let fake_streams = vec![VideoAndStreamInformation {
             name: "PotatoTestStream".into(),
+            tags: vec![],
             stream_information: StreamInformation {
                 endpoints: vec![Url::parse("udp://potatohost:4242").unwrap()],
                 configuration: CaptureConfiguration::Video(VideoCaptureConfiguration {
@@ -292,6 +410,7 @@ mod tests {
                 name: "Fake Potato Test Video Source Camera".into(),
                 device_path: "/dev/potatovideo".into(),
                 typ: VideoSourceLocalType::Usb("usb-0420:08:47.42-77".into()),
+                stable_id: None,
             }),
         }];
         set_streams(&fake_streams);
@@ -299,4 +418,52 @@ mod tests {
 
         save();
     }
+
+    #[test]
+    fn test_migrate_on_load() {
+        let file_name = generate_random_settings_file_name();
+        let version_0_settings = r#"{
+            "header": { "name": "Camera Manager", "version": 0 },
+            "mavlink_endpoint": "udpout:127.0.0.1:14550",
+            "streams": [{
+                "name": "Thermal",
+                "stream_information": {
+                    "endpoints": ["udp://192.168.2.1:5600"],
+                    "configuration": {
+                        "type": "video",
+                        "encode": "H264",
+                        "height": 512,
+                        "width": 640,
+                        "frame_interval": { "numerator": 1, "denominator": 30 }
+                    },
+                    "extended_configuration": { "thermal": true }
+                },
+                "video_source": {
+                    "Local": {
+                        "name": "Thermal Camera",
+                        "device_path": "/dev/video0",
+                        "type": { "Usb": "usb-0000:01:00.0-1" }
+                    }
+                }
+            }]
+        }"#;
+        std::fs::write(&file_name, version_0_settings).unwrap();
+
+        let settings = load_settings_from_file(&file_name);
+        assert_eq!(settings.header.version, migrations::CURRENT_VERSION);
+        assert_eq!(settings.streams.len(), 1);
+        let extended_configuration = settings.streams[0]
+            .stream_information
+            .extended_configuration
+            .as_ref()
+            .unwrap();
+        assert!(extended_configuration.thermal);
+        assert!(!extended_configuration.disable_mavlink);
+
+        // The original is kept untouched for the older releases
+        assert_eq!(
+            std::fs::read_to_string(format!("{file_name}.v0.bak")).unwrap(),
+            version_0_settings
+        );
+    }
 }
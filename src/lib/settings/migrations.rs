@@ -0,0 +1,165 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use tracing::*;
+
+/// The version of the settings written by this release, increased whenever a release changes them in a way the
+/// previous files can't be loaded as they are, along with a migration from the previous version
+pub const CURRENT_VERSION: u32 = 1;
+
+type Migration = fn(&mut Value) -> Result<()>;
+
+/// The migration of each version to the next one, by the version it upgrades from
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1];
+
+/// The version of the settings, where files from before the versioning are version 0
+pub fn version(settings: &Value) -> Result<u32> {
+    match settings.pointer("/header/version") {
+        None | Some(Value::Null) => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .context(format!("Invalid settings version: {version}")),
+    }
+}
+
+/// Upgrades the settings to the current version, one version at a time. Returns the version they had. Settings from
+/// a newer release are refused, as they can't be downgraded
+#[instrument(level = "debug", skip(settings))]
+pub fn migrate(settings: &mut Value) -> Result<u32> {
+    let original_version = version(settings)?;
+
+    if original_version > CURRENT_VERSION {
+        return Err(anyhow!(
+            "Settings version {original_version} is newer than the supported version {CURRENT_VERSION}"
+        ));
+    }
+
+    for (version, migration) in MIGRATIONS
+        .iter()
+        .enumerate()
+        .skip(original_version as usize)
+    {
+        migration(settings)
+            .with_context(|| format!("Failed migrating the settings from version {version}"))?;
+
+        set_version(settings, version as u32 + 1)?;
+        info!(
+            "Settings migrated from version {version} to version {}",
+            version + 1
+        );
+    }
+
+    Ok(original_version)
+}
+
+fn set_version(settings: &mut Value, version: u32) -> Result<()> {
+    let settings = settings
+        .as_object_mut()
+        .context("Settings should be a JSON object")?;
+
+    let header = settings
+        .entry("header")
+        .or_insert_with(|| json!({ "name": "Camera Manager" }));
+    header
+        .as_object_mut()
+        .context("Settings header should be a JSON object")?
+        .insert("version".to_string(), json!(version));
+
+    Ok(())
+}
+
+/// Version 0 files may come from releases before "disable_mavlink" existed, when the extended configuration only had
+/// "thermal", and both are required
+fn v0_to_v1(settings: &mut Value) -> Result<()> {
+    let Some(streams) = settings.get_mut("streams").and_then(Value::as_array_mut) else {
+        return Ok(());
+    };
+
+    for extended_configuration in streams.iter_mut().filter_map(|stream| {
+        stream
+            .pointer_mut("/stream_information/extended_configuration")
+            .and_then(Value::as_object_mut)
+    }) {
+        for field in ["thermal", "disable_mavlink"] {
+            extended_configuration
+                .entry(field)
+                .or_insert(Value::Bool(false));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version_0_settings() -> Value {
+        json!({
+            "header": { "name": "Camera Manager", "version": 0 },
+            "mavlink_endpoint": "udpout:127.0.0.1:14550",
+            "streams": [{
+                "name": "Thermal",
+                "stream_information": {
+                    "endpoints": ["udp://192.168.2.1:5600"],
+                    "configuration": {
+                        "type": "video",
+                        "encode": "H264",
+                        "height": 512,
+                        "width": 640,
+                        "frame_interval": { "numerator": 1, "denominator": 30 }
+                    },
+                    "extended_configuration": { "thermal": true }
+                },
+                "video_source": {
+                    "Local": {
+                        "name": "Thermal Camera",
+                        "device_path": "/dev/video0",
+                        "type": { "Usb": "usb-0000:01:00.0-1" }
+                    }
+                }
+            }]
+        })
+    }
+
+    #[test]
+    fn test_v0_to_v1() {
+        let mut settings = version_0_settings();
+
+        assert_eq!(migrate(&mut settings).unwrap(), 0);
+        assert_eq!(version(&settings).unwrap(), CURRENT_VERSION);
+
+        let extended_configuration = settings
+            .pointer("/streams/0/stream_information/extended_configuration")
+            .unwrap();
+        assert_eq!(extended_configuration["thermal"], json!(true));
+        assert_eq!(extended_configuration["disable_mavlink"], json!(false));
+
+        // Only the extended configurations are affected
+        assert_eq!(
+            settings.pointer("/streams/0/video_source"),
+            version_0_settings().pointer("/streams/0/video_source")
+        );
+
+        // Migrating again changes nothing
+        let migrated = settings.clone();
+        assert_eq!(migrate(&mut settings).unwrap(), CURRENT_VERSION);
+        assert_eq!(settings, migrated);
+    }
+
+    #[test]
+    fn test_versions() {
+        // Files from before the header had a version
+        let mut settings = json!({ "streams": [] });
+        assert_eq!(version(&settings).unwrap(), 0);
+        migrate(&mut settings).unwrap();
+        assert_eq!(settings["header"]["name"], json!("Camera Manager"));
+        assert_eq!(version(&settings).unwrap(), CURRENT_VERSION);
+
+        let mut newer =
+            json!({ "header": { "name": "Camera Manager", "version": CURRENT_VERSION + 1 } });
+        assert!(migrate(&mut newer).is_err());
+
+        assert!(version(&json!({ "header": { "version": "1" } })).is_err());
+    }
+}
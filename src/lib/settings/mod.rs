@@ -1 +1,3 @@
+pub mod backup;
 pub mod manager;
+mod migrations;
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use gst::prelude::*;
+use lazy_static::lazy_static;
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+
+lazy_static! {
+    static ref COUNTERS: Arc<Mutex<HashMap<uuid::Uuid, Arc<ByteCounter>>>> = Default::default();
+}
+
+/// The bytes sent by a stream through all of its sinks. It lives as long as the stream, so it is kept across
+/// clients coming and going, and across the pipeline being rebuilt
+#[derive(Debug, Default)]
+struct ByteCounter {
+    total_bytes: AtomicU64,
+    bytes_since_reset: AtomicU64,
+    /// Unix time, in milliseconds
+    reset_at: Mutex<Option<i64>>,
+}
+
+#[derive(Apiv2Schema, Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BandwidthStatus {
+    /// Bytes sent since the stream was created
+    pub total_bytes: u64,
+    /// Bytes sent since the counter was last reset, or since the stream was created
+    pub bytes_since_reset: u64,
+    /// When the counter was last reset, in Unix time milliseconds
+    pub reset_at: Option<i64>,
+}
+
+fn counter(stream_id: &uuid::Uuid) -> Arc<ByteCounter> {
+    COUNTERS
+        .lock()
+        .unwrap()
+        .entry(*stream_id)
+        .or_default()
+        .clone()
+}
+
+/// Counts the bytes going through the given pad of a sink branch as sent by the given stream. When the branch fans
+/// out to many destinations, like UDP, each buffer is counted once per destination. The probe goes away with the
+/// pad, when the sink is removed
+pub fn attach(
+    stream_id: &uuid::Uuid,
+    pad: &gst::Pad,
+    destinations: Option<Arc<AtomicUsize>>,
+) -> Option<gst::PadProbeId> {
+    let counter = counter(stream_id);
+
+    pad.add_probe(
+        gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+        move |_pad, info| {
+            let bytes = match &info.data {
+                Some(gst::PadProbeData::Buffer(buffer)) => buffer.size(),
+                Some(gst::PadProbeData::BufferList(list)) => list.calculate_size(),
+                _ => return gst::PadProbeReturn::Ok,
+            };
+            let destinations = destinations
+                .as_ref()
+                .map_or(1, |destinations| destinations.load(Ordering::Relaxed));
+
+            counter.record((bytes * destinations) as u64);
+
+            gst::PadProbeReturn::Ok
+        },
+    )
+}
+
+/// The bytes sent by the given stream
+pub fn get(stream_id: &uuid::Uuid) -> BandwidthStatus {
+    let counter = counter(stream_id);
+
+    BandwidthStatus {
+        total_bytes: counter.total_bytes.load(Ordering::Relaxed),
+        bytes_since_reset: counter.bytes_since_reset.load(Ordering::Relaxed),
+        reset_at: *counter.reset_at.lock().unwrap(),
+    }
+}
+
+/// Restarts counting the bytes since reset of the given stream, keeping its total
+pub fn reset(stream_id: &uuid::Uuid) {
+    let counter = counter(stream_id);
+
+    counter.bytes_since_reset.store(0, Ordering::Relaxed);
+    *counter.reset_at.lock().unwrap() = Some(chrono::Utc::now().timestamp_millis());
+}
+
+/// Forgets the counters of a removed stream
+pub fn remove(stream_id: &uuid::Uuid) {
+    COUNTERS.lock().unwrap().remove(stream_id);
+}
+
+impl ByteCounter {
+    fn record(&self, bytes: u64) {
+        self.total_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.bytes_since_reset.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter() {
+        gst::init().unwrap();
+
+        let stream_id = uuid::Uuid::new_v4();
+        let pad = gst::Pad::builder(gst::PadDirection::Src).build();
+        let destinations = Arc::new(AtomicUsize::new(2));
+        attach(&stream_id, &pad, Some(destinations.clone())).unwrap();
+        pad.set_active(true).unwrap();
+
+        // The probe sees the buffers even without a peer
+        let push = |size: usize| {
+            let _ = pad.push(gst::Buffer::with_size(size).unwrap());
+        };
+
+        // Each buffer is sent to every destination
+        push(100);
+        destinations.store(3, Ordering::Relaxed);
+        push(10);
+        assert_eq!(get(&stream_id).total_bytes, 230);
+
+        reset(&stream_id);
+        push(10);
+        let status = get(&stream_id);
+        assert_eq!(status.total_bytes, 260);
+        assert_eq!(status.bytes_since_reset, 30);
+        assert!(status.reset_at.is_some());
+
+        remove(&stream_id);
+        assert_eq!(get(&stream_id), BandwidthStatus::default());
+    }
+}
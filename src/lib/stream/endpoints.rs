@@ -0,0 +1,227 @@
+use std::net::IpAddr;
+
+use url::Url;
+
+use crate::{
+    cli, network::utils::get_host_addresses, video::types::VideoEncodeType,
+    video_stream::types::VideoAndStreamInformation,
+};
+
+use super::{
+    rtsp::{rtsp_auth, rtsp_scheme::RTSPScheme, rtsp_server::RTSP_SERVER_PORT},
+    sink::udp_sink::is_multicast,
+    types::{CaptureConfiguration, EndpointDescriptor, EndpointProtocol},
+};
+
+/// The URLs clients should use to receive the given stream, with one entry per host address for the
+/// endpoints listening on all of them
+pub fn describe(
+    stream_id: &uuid::Uuid,
+    video_and_stream_information: &VideoAndStreamInformation,
+    srt_urls: &[Url],
+) -> Vec<EndpointDescriptor> {
+    let signalling_url = Url::parse(&cli::manager::signalling_server_address()).ok();
+
+    describe_for_addresses(
+        stream_id,
+        video_and_stream_information,
+        srt_urls,
+        signalling_url.as_ref(),
+        &get_host_addresses(),
+    )
+}
+
+fn describe_for_addresses(
+    stream_id: &uuid::Uuid,
+    video_and_stream_information: &VideoAndStreamInformation,
+    srt_urls: &[Url],
+    signalling_url: Option<&Url>,
+    addresses: &[IpAddr],
+) -> Vec<EndpointDescriptor> {
+    let codec = match &video_and_stream_information
+        .stream_information
+        .configuration
+    {
+        CaptureConfiguration::Video(configuration) => Some(format!("{:?}", configuration.encode)),
+        CaptureConfiguration::Redirect(_) => None,
+    };
+    let descriptor = |protocol, url| EndpointDescriptor {
+        protocol,
+        url,
+        codec: codec.clone(),
+        producer_id: None,
+        multicast: false,
+        requires_credentials: false,
+    };
+
+    let mut descriptors = vec![];
+
+    for endpoint in &video_and_stream_information.stream_information.endpoints {
+        if RTSPScheme::try_from(endpoint.scheme()).is_ok() {
+            // All streams are served by the same RTSP server, only their paths come from the endpoints
+            for mut url in expand(endpoint, addresses) {
+                let _ = url.set_port(Some(RTSP_SERVER_PORT));
+                descriptors.push(EndpointDescriptor {
+                    requires_credentials: rtsp_auth::requires_credentials(),
+                    ..descriptor(EndpointProtocol::Rtsp, url)
+                });
+            }
+        } else if matches!(endpoint.scheme(), "udp" | "udp265") {
+            // UDP is sent to the clients, so they receive it on the destination itself
+            descriptors.push(EndpointDescriptor {
+                multicast: is_multicast(endpoint),
+                ..descriptor(EndpointProtocol::Udp, endpoint.clone())
+            });
+        }
+    }
+
+    for srt_url in srt_urls {
+        for url in expand(srt_url, addresses) {
+            descriptors.push(descriptor(EndpointProtocol::Srt, url));
+        }
+    }
+
+    if let Some(signalling_url) = signalling_url {
+        if supports_webrtc(video_and_stream_information) {
+            for url in expand(signalling_url, addresses) {
+                descriptors.push(EndpointDescriptor {
+                    producer_id: Some(*stream_id),
+                    ..descriptor(EndpointProtocol::WebRTC, url)
+                });
+            }
+        }
+    }
+
+    descriptors
+}
+
+/// The same streams listed as available by the signalling server
+fn supports_webrtc(video_and_stream_information: &VideoAndStreamInformation) -> bool {
+    match &video_and_stream_information
+        .stream_information
+        .configuration
+    {
+        CaptureConfiguration::Video(configuration) => matches!(
+            configuration.encode,
+            VideoEncodeType::H264 | VideoEncodeType::H265
+        ),
+        CaptureConfiguration::Redirect(_) => video_and_stream_information
+            .stream_information
+            .endpoints
+            .first()
+            .is_some_and(|endpoint| endpoint.scheme() == "rtsp"),
+    }
+}
+
+/// Replaces an unspecified host, like "0.0.0.0", by each of the given addresses, so each URL is reachable
+fn expand(url: &Url, addresses: &[IpAddr]) -> Vec<Url> {
+    let is_unspecified = match url.host() {
+        Some(url::Host::Ipv4(ip)) => ip.is_unspecified(),
+        Some(url::Host::Ipv6(ip)) => ip.is_unspecified(),
+        _ => false,
+    };
+
+    if !is_unspecified {
+        return vec![url.clone()];
+    }
+
+    addresses
+        .iter()
+        .filter_map(|address| {
+            let mut url = url.clone();
+            url.set_ip_host(*address).ok()?;
+            Some(url)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        stream::types::{StreamInformation, VideoCaptureConfiguration},
+        video::{
+            types::{FrameInterval, VideoSourceType},
+            video_source_gst::{VideoSourceGst, VideoSourceGstType},
+        },
+    };
+
+    #[test]
+    fn test_describe_for_addresses() {
+        let stream_id = uuid::Uuid::new_v4();
+        let video_and_stream_information = VideoAndStreamInformation {
+            name: "Test".into(),
+            tags: Default::default(),
+            stream_information: StreamInformation {
+                endpoints: vec![
+                    Url::parse("rtsp://0.0.0.0:8554/test").unwrap(),
+                    Url::parse("udp://192.168.2.1:5600").unwrap(),
+                    Url::parse("udp://239.1.2.3:5601").unwrap(),
+                ],
+                configuration: CaptureConfiguration::Video(VideoCaptureConfiguration {
+                    encode: VideoEncodeType::H264,
+                    height: 720,
+                    width: 1280,
+                    frame_interval: FrameInterval {
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                }),
+                extended_configuration: None,
+            },
+            video_source: VideoSourceType::Gst(VideoSourceGst {
+                name: "Fake source".into(),
+                source: VideoSourceGstType::Fake("ball".into()),
+            }),
+        };
+        let srt_urls = [Url::parse("srt://0.0.0.0:9000?mode=caller&latency=200").unwrap()];
+        let signalling_url = Url::parse("ws://0.0.0.0:6021").unwrap();
+        let addresses = [
+            "192.168.2.2".parse::<IpAddr>().unwrap(),
+            "2001:db8::2".parse::<IpAddr>().unwrap(),
+        ];
+
+        let descriptors = describe_for_addresses(
+            &stream_id,
+            &video_and_stream_information,
+            &srt_urls,
+            Some(&signalling_url),
+            &addresses,
+        );
+
+        let urls = descriptors
+            .iter()
+            .map(|descriptor| (descriptor.protocol, descriptor.url.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                (EndpointProtocol::Rtsp, "rtsp://192.168.2.2:8554/test"),
+                (EndpointProtocol::Rtsp, "rtsp://[2001:db8::2]:8554/test"),
+                (EndpointProtocol::Udp, "udp://192.168.2.1:5600"),
+                (EndpointProtocol::Udp, "udp://239.1.2.3:5601"),
+                (
+                    EndpointProtocol::Srt,
+                    "srt://192.168.2.2:9000?mode=caller&latency=200"
+                ),
+                (
+                    EndpointProtocol::Srt,
+                    "srt://[2001:db8::2]:9000?mode=caller&latency=200"
+                ),
+                (EndpointProtocol::WebRTC, "ws://192.168.2.2:6021/"),
+                (EndpointProtocol::WebRTC, "ws://[2001:db8::2]:6021/"),
+            ]
+        );
+        assert!(descriptors
+            .iter()
+            .all(|descriptor| descriptor.codec.as_deref() == Some("H264")));
+        assert!(descriptors.iter().all(|descriptor| {
+            (descriptor.protocol == EndpointProtocol::WebRTC)
+                == (descriptor.producer_id == Some(stream_id))
+        }));
+        assert!(descriptors.iter().all(|descriptor| {
+            descriptor.multicast == (descriptor.url.as_str() == "udp://239.1.2.3:5601")
+        }));
+    }
+}
@@ -0,0 +1,406 @@
+use anyhow::{anyhow, Result};
+use tracing::*;
+
+use crate::{
+    video::types::{Format, FrameInterval, VideoEncodeType, VideoSourceType},
+    video_stream::types::VideoAndStreamInformation,
+};
+
+use super::types::{CaptureConfiguration, NegotiationPriority, VideoCaptureConfiguration};
+
+/// How many of the closest formats are listed when the requested one is not supported
+const CLOSEST_FORMATS: usize = 5;
+
+/// Checks the capture configuration against the formats reported by the source. When the source can't capture
+/// it, either fails listing the closest formats it can, or, if the stream allows it, switches to the nearest one
+/// following its negotiation priority, returning a description of the change. Only local devices report their
+/// formats, the other sources are checked when negotiating the pipeline caps
+#[instrument(level = "debug", skip(video_and_stream_information))]
+pub fn negotiate(
+    video_and_stream_information: &mut VideoAndStreamInformation,
+) -> Result<Option<String>> {
+    if !matches!(
+        video_and_stream_information.video_source,
+        VideoSourceType::Local(_)
+    ) {
+        return Ok(None);
+    }
+
+    let formats = video_and_stream_information.video_source.inner().formats();
+    let nearest_format = video_and_stream_information
+        .stream_information
+        .nearest_format();
+    let priority = video_and_stream_information
+        .stream_information
+        .negotiation_priority();
+
+    let stream_information = &mut video_and_stream_information.stream_information;
+    let configuration = match stream_information
+        .extended_configuration
+        .as_mut()
+        .and_then(|configuration| configuration.shared_capture.as_mut())
+    {
+        // Streams sharing their device open it as their shared capture says
+        Some(shared_capture) => shared_capture,
+        None => match &mut stream_information.configuration {
+            CaptureConfiguration::Video(configuration) => configuration,
+            CaptureConfiguration::Redirect(_) => return Ok(None),
+        },
+    };
+
+    // Only the priorities not keeping the encode can replace a missing one
+    let encode_available = formats
+        .iter()
+        .any(|format| format.encode == configuration.encode);
+    if !encode_available && (!nearest_format || priority == NegotiationPriority::PreferFormat) {
+        return Err(anyhow!(
+            "Source doesn't capture {:?}, the available encodes are: {:?}",
+            configuration.encode,
+            formats
+                .iter()
+                .map(|format| &format.encode)
+                .collect::<Vec<_>>()
+        ));
+    }
+
+    if is_supported(&formats, configuration) {
+        return Ok(None);
+    }
+
+    let closest = closest(&formats, configuration, priority);
+
+    if !nearest_format {
+        return Err(anyhow!(
+            "Source doesn't capture {}, the closest available formats are: {:?}. Set \"nearest_format\" in the stream's extended configuration to use the nearest one instead",
+            describe(configuration),
+            closest
+                .iter()
+                .take(CLOSEST_FORMATS)
+                .map(describe)
+                .collect::<Vec<_>>()
+        ));
+    }
+
+    let nearest = closest
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Source has no format for {:?}", configuration.encode))?;
+    let description = format!(
+        "Source doesn't capture {}, using {} instead",
+        describe(configuration),
+        describe(&nearest)
+    );
+    warn!("{description}");
+
+    *configuration = nearest;
+
+    Ok(Some(description))
+}
+
+/// If the source captures exactly the given configuration. Sizes without frame intervals accept any of them
+pub fn is_supported(formats: &[Format], configuration: &VideoCaptureConfiguration) -> bool {
+    formats
+        .iter()
+        .filter(|format| format.encode == configuration.encode)
+        .flat_map(|format| &format.sizes)
+        .any(|size| {
+            size.width == configuration.width
+                && size.height == configuration.height
+                && (size.intervals.is_empty()
+                    || size.intervals.contains(&configuration.frame_interval))
+        })
+}
+
+/// The configurations the source captures, from the closest to the farthest from the given one, as the priority
+/// says:
+/// - prefer-format: only the same encode, with the same or a lower resolution preferred over a higher one, and then
+///   the same or a lower framerate
+/// - prefer-resolution: any encode the pipelines stream, by resolution, then framerate, then encode
+/// - prefer-framerate: any encode the pipelines stream, by framerate, then resolution, then encode
+///
+/// When the encode can change, a framerate far below the requested one comes after the higher ones, so a compressed
+/// encode keeping up with the request is preferred over a raw one the USB bandwidth limits to a few frames per
+/// second. Between encodes, the configured one comes first, and then the compressed ones before the raw ones
+pub fn closest(
+    formats: &[Format],
+    configuration: &VideoCaptureConfiguration,
+    priority: NegotiationPriority,
+) -> Vec<VideoCaptureConfiguration> {
+    let mut candidates = formats
+        .iter()
+        .filter(|format| {
+            format.encode == configuration.encode
+                || (priority != NegotiationPriority::PreferFormat && is_streamed(&format.encode))
+        })
+        .flat_map(|format| {
+            format.sizes.iter().flat_map(|size| {
+                let intervals = if size.intervals.is_empty() {
+                    vec![configuration.frame_interval.clone()]
+                } else {
+                    size.intervals.clone()
+                };
+
+                intervals
+                    .into_iter()
+                    .map(|frame_interval| VideoCaptureConfiguration {
+                        encode: format.encode.clone(),
+                        height: size.height,
+                        width: size.width,
+                        frame_interval,
+                    })
+            })
+        })
+        .collect::<Vec<VideoCaptureConfiguration>>();
+
+    let requested_pixels = pixels(configuration);
+    let requested_framerate = framerate(&configuration.frame_interval);
+    let resolution_order = |a: &VideoCaptureConfiguration, b: &VideoCaptureConfiguration| {
+        let key = |candidate: &VideoCaptureConfiguration| {
+            let fits =
+                candidate.width <= configuration.width && candidate.height <= configuration.height;
+            (!fits, pixels(candidate).abs_diff(requested_pixels))
+        };
+        key(a).cmp(&key(b))
+    };
+    let framerate_order = |a: &VideoCaptureConfiguration, b: &VideoCaptureConfiguration| {
+        let key = |candidate: &VideoCaptureConfiguration| {
+            let framerate = framerate(&candidate.frame_interval);
+            (
+                priority != NegotiationPriority::PreferFormat
+                    && framerate < requested_framerate * FAR_BELOW_FRAMERATE,
+                framerate > requested_framerate,
+                (framerate - requested_framerate).abs(),
+            )
+        };
+        let (a_far_below, a_higher, a_distance) = key(a);
+        let (b_far_below, b_higher, b_distance) = key(b);
+        a_far_below
+            .cmp(&b_far_below)
+            .then_with(|| a_higher.cmp(&b_higher))
+            .then_with(|| a_distance.total_cmp(&b_distance))
+    };
+    let encode_order = |a: &VideoCaptureConfiguration, b: &VideoCaptureConfiguration| {
+        let key = |candidate: &VideoCaptureConfiguration| {
+            (
+                candidate.encode != configuration.encode,
+                is_raw(&candidate.encode),
+            )
+        };
+        key(a).cmp(&key(b))
+    };
+
+    candidates.sort_by(|a, b| match priority {
+        NegotiationPriority::PreferFormat | NegotiationPriority::PreferResolution => {
+            resolution_order(a, b)
+                .then_with(|| framerate_order(a, b))
+                .then_with(|| encode_order(a, b))
+        }
+        NegotiationPriority::PreferFramerate => framerate_order(a, b)
+            .then_with(|| resolution_order(a, b))
+            .then_with(|| encode_order(a, b)),
+    });
+    candidates.dedup();
+
+    candidates
+}
+
+/// Below this fraction of the requested framerate, a candidate falls far short of it
+const FAR_BELOW_FRAMERATE: f64 = 0.5;
+
+/// If the local device pipelines can stream the encode
+fn is_streamed(encode: &VideoEncodeType) -> bool {
+    matches!(
+        encode,
+        VideoEncodeType::H264
+            | VideoEncodeType::H265
+            | VideoEncodeType::Mjpg
+            | VideoEncodeType::Yuyv
+    )
+}
+
+/// If the encode is uncompressed, taking much more of the USB bandwidth for the same resolution and framerate
+fn is_raw(encode: &VideoEncodeType) -> bool {
+    matches!(encode, VideoEncodeType::Yuyv | VideoEncodeType::Rgb)
+}
+
+fn pixels(configuration: &VideoCaptureConfiguration) -> u64 {
+    configuration.width as u64 * configuration.height as u64
+}
+
+/// In frames per second
+fn framerate(frame_interval: &FrameInterval) -> f64 {
+    if frame_interval.numerator == 0 {
+        return 0.0;
+    }
+
+    frame_interval.denominator as f64 / frame_interval.numerator as f64
+}
+
+/// Like "Mjpg 1920x1080 at 30 fps"
+fn describe(configuration: &VideoCaptureConfiguration) -> String {
+    format!(
+        "{:?} {}x{} at {} fps",
+        configuration.encode,
+        configuration.width,
+        configuration.height,
+        (framerate(&configuration.frame_interval) * 100.0).round() / 100.0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::video::types::Size;
+
+    fn interval(framerate: u32) -> FrameInterval {
+        FrameInterval {
+            numerator: 1,
+            denominator: framerate,
+        }
+    }
+
+    fn size(width: u32, height: u32, framerates: &[u32]) -> Size {
+        Size {
+            width,
+            height,
+            intervals: framerates
+                .iter()
+                .map(|framerate| interval(*framerate))
+                .collect(),
+        }
+    }
+
+    fn configuration(width: u32, height: u32, framerate: u32) -> VideoCaptureConfiguration {
+        VideoCaptureConfiguration {
+            encode: VideoEncodeType::Mjpg,
+            height,
+            width,
+            frame_interval: interval(framerate),
+        }
+    }
+
+    fn formats() -> Vec<Format> {
+        vec![Format {
+            encode: VideoEncodeType::Mjpg,
+            sizes: vec![
+                size(640, 480, &[30, 60]),
+                size(1280, 720, &[15, 30]),
+                size(1920, 1080, &[15, 30]),
+            ],
+        }]
+    }
+
+    #[test]
+    fn test_is_supported() {
+        let formats = formats();
+
+        assert!(is_supported(&formats, &configuration(1280, 720, 30)));
+        assert!(!is_supported(&formats, &configuration(1280, 720, 60)));
+        assert!(!is_supported(&formats, &configuration(3840, 2160, 30)));
+    }
+
+    #[test]
+    fn test_closest() {
+        let formats = formats();
+
+        // Same or lower resolution, then same or lower framerate
+        let nearest = |width, height, framerate| {
+            closest(
+                &formats,
+                &configuration(width, height, framerate),
+                NegotiationPriority::PreferFormat,
+            )
+            .into_iter()
+            .next()
+            .unwrap()
+        };
+        assert_eq!(nearest(3840, 2160, 60), configuration(1920, 1080, 30));
+        assert_eq!(nearest(1280, 720, 60), configuration(1280, 720, 30));
+        assert_eq!(nearest(1280, 720, 20), configuration(1280, 720, 15));
+        assert_eq!(nearest(1024, 768, 30), configuration(640, 480, 30));
+
+        // Only higher ones left, so the closest of them
+        assert_eq!(nearest(320, 240, 10), configuration(640, 480, 30));
+    }
+
+    #[test]
+    fn test_negotiation_priority() {
+        use NegotiationPriority::*;
+        use VideoEncodeType::*;
+
+        let format = |encode, sizes: Vec<Size>| Format { encode, sizes };
+        let formats = vec![
+            format(Mjpg, vec![size(640, 480, &[30]), size(1920, 1080, &[60])]),
+            format(Yuyv, vec![size(640, 480, &[30]), size(1920, 1080, &[5])]),
+        ];
+        let capture = |encode, width, height, framerate| VideoCaptureConfiguration {
+            encode,
+            ..configuration(width, height, framerate)
+        };
+        let nearest = |requested: &VideoCaptureConfiguration, priority| {
+            closest(&formats, requested, priority)
+                .into_iter()
+                .next()
+                .unwrap()
+        };
+
+        // The encode is kept, even if that costs the framerate
+        let requested = capture(Yuyv, 1920, 1080, 60);
+        assert_eq!(
+            nearest(&requested, PreferFormat),
+            capture(Yuyv, 1920, 1080, 5)
+        );
+        assert!(closest(&formats, &requested, PreferFormat)
+            .iter()
+            .all(|candidate| candidate.encode == Yuyv));
+
+        // The resolution and the framerate are kept by changing the encode
+        assert_eq!(
+            nearest(&requested, PreferResolution),
+            capture(Mjpg, 1920, 1080, 60)
+        );
+        assert_eq!(
+            nearest(&requested, PreferFramerate),
+            capture(Mjpg, 1920, 1080, 60)
+        );
+
+        // A compressed encode keeping up with the framerate is preferred over a raw one falling far short of it
+        let requested = capture(Yuyv, 1920, 1080, 30);
+        assert_eq!(
+            nearest(&requested, PreferResolution),
+            capture(Mjpg, 1920, 1080, 60)
+        );
+        assert_eq!(
+            closest(&formats, &requested, PreferResolution)[1],
+            capture(Yuyv, 1920, 1080, 5)
+        );
+        // Or the resolution is given up first
+        assert_eq!(
+            nearest(&requested, PreferFramerate),
+            capture(Yuyv, 640, 480, 30)
+        );
+
+        // A framerate slightly below the requested one is still preferred over a higher one
+        let slightly_below = vec![
+            format(Mjpg, vec![size(1920, 1080, &[60])]),
+            format(Yuyv, vec![size(1920, 1080, &[25])]),
+        ];
+        assert_eq!(
+            closest(&slightly_below, &requested, PreferResolution)[0],
+            capture(Yuyv, 1920, 1080, 25)
+        );
+
+        // The configured encode is preferred, and then the compressed ones
+        let requested = capture(Mjpg, 1280, 720, 30);
+        assert_eq!(
+            nearest(&requested, PreferFramerate),
+            capture(Mjpg, 640, 480, 30)
+        );
+        let requested = capture(H264, 1280, 720, 30);
+        assert_eq!(
+            nearest(&requested, PreferFramerate),
+            capture(Mjpg, 640, 480, 30)
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use tracing::*;
+
+use crate::video::types::VideoEncodeType;
+
+use super::encoders;
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElementKind {
+    Source,
+    Parser,
+    Encoder,
+    Payloader,
+    Sink,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ElementAvailability {
+    /// The GStreamer element factory name, like "x264enc"
+    pub name: String,
+    pub kind: ElementKind,
+    /// The encode produced or handled by the element, if specific to one
+    pub encode: Option<VideoEncodeType>,
+    pub available: bool,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GStreamerAvailability {
+    /// Like "GStreamer 1.22.0"
+    pub version: String,
+    /// Encodes with at least one encoder available, so they can be streamed from sources that don't deliver them
+    pub encodable: Vec<VideoEncodeType>,
+    pub elements: Vec<ElementAvailability>,
+}
+
+/// The elements used by the pipelines and sinks, besides the encoders
+const ELEMENTS: &[(&str, ElementKind, Option<VideoEncodeType>)] = &[
+    ("v4l2src", ElementKind::Source, None),
+    ("videotestsrc", ElementKind::Source, None),
+    ("qrtimestampsrc", ElementKind::Source, None),
+    ("rtspsrc", ElementKind::Source, None),
+    (
+        "h264parse",
+        ElementKind::Parser,
+        Some(VideoEncodeType::H264),
+    ),
+    (
+        "h265parse",
+        ElementKind::Parser,
+        Some(VideoEncodeType::H265),
+    ),
+    ("jpegenc", ElementKind::Encoder, Some(VideoEncodeType::Mjpg)),
+    (
+        "rtph264pay",
+        ElementKind::Payloader,
+        Some(VideoEncodeType::H264),
+    ),
+    (
+        "rtph265pay",
+        ElementKind::Payloader,
+        Some(VideoEncodeType::H265),
+    ),
+    (
+        "rtpjpegpay",
+        ElementKind::Payloader,
+        Some(VideoEncodeType::Mjpg),
+    ),
+    ("rtpvrawpay", ElementKind::Payloader, None),
+    ("multiudpsink", ElementKind::Sink, None),
+    ("shmsink", ElementKind::Sink, None),
+    ("srtsink", ElementKind::Sink, None),
+    ("webrtcbin", ElementKind::Sink, None),
+    ("splitmuxsink", ElementKind::Sink, None),
+    ("appsink", ElementKind::Sink, None),
+];
+
+/// Which of the elements relevant to the streams are installed in this system
+#[instrument(level = "debug")]
+pub fn availability() -> GStreamerAvailability {
+    if let Err(error) = gst::init() {
+        error!("Error! {error}");
+    }
+
+    let elements = encoders::known_encoders()
+        .map(|(encode, candidate)| (candidate.name, ElementKind::Encoder, Some(encode)))
+        .chain(ELEMENTS.iter().cloned())
+        .map(|(name, kind, encode)| ElementAvailability {
+            name: name.to_string(),
+            kind,
+            encode,
+            available: gst::ElementFactory::find(name).is_some(),
+        })
+        .collect::<Vec<ElementAvailability>>();
+
+    let mut encodable = elements
+        .iter()
+        .filter(|element| element.kind == ElementKind::Encoder && element.available)
+        .filter_map(|element| element.encode.clone())
+        .collect::<Vec<VideoEncodeType>>();
+    encodable.sort();
+    encodable.dedup();
+
+    GStreamerAvailability {
+        version: gst::version_string().to_string(),
+        encodable,
+        elements,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_availability() {
+        let availability = availability();
+
+        assert!(availability.version.starts_with("GStreamer"));
+
+        let element = |name: &str| {
+            availability
+                .elements
+                .iter()
+                .find(|element| element.name == name)
+                .unwrap()
+        };
+        assert!(element("appsink").available);
+        assert_eq!(element("x264enc").kind, ElementKind::Encoder);
+        assert_eq!(element("x265enc").encode, Some(VideoEncodeType::H265));
+
+        // Only encodes with an installed encoder
+        for encode in &availability.encodable {
+            assert!(availability.elements.iter().any(|element| {
+                element.kind == ElementKind::Encoder
+                    && element.available
+                    && element.encode.as_ref() == Some(encode)
+            }));
+        }
+    }
+}
@@ -0,0 +1,856 @@
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::*;
+
+use crate::{
+    stream::types::{
+        H264Profile, H264ProfileConfiguration, RateControlConfiguration, RateControlMode,
+    },
+    video::types::VideoEncodeType,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncoderCandidate {
+    pub name: &'static str,
+    /// How the encoder is used in a pipeline description, including its properties
+    pub description: &'static str,
+    pub hardware: bool,
+}
+
+const fn candidate(
+    name: &'static str,
+    description: &'static str,
+    hardware: bool,
+) -> EncoderCandidate {
+    EncoderCandidate {
+        name,
+        description,
+        hardware,
+    }
+}
+
+/// Known H264 encoders, hardware ones first, each targeting 5 Mbps
+const H264_ENCODERS: &[EncoderCandidate] = &[
+    candidate(
+        "nvv4l2h264enc",
+        // Jetson's encoders only accept NVMM memory
+        "nvvidconv ! nvv4l2h264enc bitrate=5000000 insert-sps-pps=true",
+        true,
+    ),
+    candidate(
+        "v4l2h264enc",
+        "v4l2h264enc extra-controls=\"controls,video_bitrate=5000000\"",
+        true,
+    ),
+    candidate("nvh264enc", "nvh264enc bitrate=5000", true),
+    candidate("vaapih264enc", "vaapih264enc bitrate=5000", true),
+    candidate("msdkh264enc", "msdkh264enc bitrate=5000", true),
+    candidate(
+        "x264enc",
+        "x264enc tune=zerolatency speed-preset=ultrafast bitrate=5000",
+        false,
+    ),
+    candidate("openh264enc", "openh264enc bitrate=5000000", false),
+];
+
+/// Known H265 encoders, hardware ones first, each targeting 5 Mbps
+const H265_ENCODERS: &[EncoderCandidate] = &[
+    candidate(
+        "nvv4l2h265enc",
+        "nvvidconv ! nvv4l2h265enc bitrate=5000000 insert-sps-pps=true",
+        true,
+    ),
+    candidate(
+        "v4l2h265enc",
+        "v4l2h265enc extra-controls=\"controls,video_bitrate=5000000\"",
+        true,
+    ),
+    candidate("nvh265enc", "nvh265enc bitrate=5000", true),
+    candidate("vaapih265enc", "vaapih265enc bitrate=5000", true),
+    candidate("msdkh265enc", "msdkh265enc bitrate=5000", true),
+    candidate(
+        "x265enc",
+        "x265enc tune=zerolatency speed-preset=ultrafast bitrate=5000",
+        false,
+    ),
+];
+
+lazy_static! {
+    /// Names of the known encoders found in the registry by the last probe
+    static ref AVAILABLE_ENCODERS: RwLock<Option<Vec<&'static str>>> = Default::default();
+}
+
+fn candidates(encode: &VideoEncodeType) -> &'static [EncoderCandidate] {
+    match encode {
+        VideoEncodeType::H264 => H264_ENCODERS,
+        VideoEncodeType::H265 => H265_ENCODERS,
+        _ => &[],
+    }
+}
+
+/// All the known encoders, with the encode they produce
+pub fn known_encoders() -> impl Iterator<Item = (VideoEncodeType, &'static EncoderCandidate)> {
+    H264_ENCODERS
+        .iter()
+        .map(|candidate| (VideoEncodeType::H264, candidate))
+        .chain(
+            H265_ENCODERS
+                .iter()
+                .map(|candidate| (VideoEncodeType::H265, candidate)),
+        )
+}
+
+/// Orders the candidates by preference: the user's preferred encoders first (in the given order),
+/// then the hardware ones, then the software ones. Unavailable candidates are discarded
+pub fn preference_list(
+    candidates: &'static [EncoderCandidate],
+    preferred: &[String],
+    is_available: impl Fn(&str) -> bool,
+) -> Vec<&'static EncoderCandidate> {
+    let mut list: Vec<&'static EncoderCandidate> = candidates
+        .iter()
+        .filter(|candidate| is_available(candidate.name))
+        .collect();
+
+    // Stable sort, keeping the original order between candidates of the same priority
+    list.sort_by_key(|candidate| {
+        let preference = preferred
+            .iter()
+            .position(|name| name == candidate.name)
+            .unwrap_or(preferred.len());
+
+        (preference, !candidate.hardware)
+    });
+
+    list
+}
+
+/// Enumerates the known encoders available in the GStreamer registry, caching the result
+#[instrument(level = "debug")]
+pub fn probe() -> Vec<&'static str> {
+    if let Err(error) = gst::init() {
+        error!("Error! {error}");
+    }
+
+    let available = H264_ENCODERS
+        .iter()
+        .chain(H265_ENCODERS)
+        .map(|candidate| candidate.name)
+        .filter(|name| gst::ElementFactory::find(name).is_some())
+        .collect::<Vec<&'static str>>();
+
+    info!("Available encoders: {available:?}");
+
+    *AVAILABLE_ENCODERS.write().unwrap() = Some(available.clone());
+
+    available
+}
+
+fn is_available(name: &str) -> bool {
+    if let Some(available) = AVAILABLE_ENCODERS.read().unwrap().as_ref() {
+        return available.contains(&name);
+    }
+
+    probe().contains(&name)
+}
+
+/// Selects the best encoder available in this system for the given encode
+#[instrument(level = "debug")]
+pub fn select_encoder(encode: &VideoEncodeType) -> Result<&'static EncoderCandidate> {
+    let candidates = candidates(encode);
+    if candidates.is_empty() {
+        return Err(anyhow!("There is no known encoder for {encode:?}"));
+    }
+
+    let preferred = crate::cli::manager::encoder_preference();
+
+    preference_list(candidates, &preferred, is_available)
+        .first()
+        .copied()
+        .ok_or_else(|| {
+            anyhow!(
+                "No {encode:?} encoder available in this system. Install one of the following GStreamer elements: {:?}",
+                candidates
+                    .iter()
+                    .map(|candidate| candidate.name)
+                    .collect::<Vec<_>>()
+            )
+        })
+}
+
+/// The range of keyframe intervals accepted, in frames
+pub const KEYFRAME_INTERVAL_RANGE: std::ops::RangeInclusive<u32> = 1..=1000;
+
+pub fn validate_keyframe_interval(frames: u32) -> Result<()> {
+    if !KEYFRAME_INTERVAL_RANGE.contains(&frames) {
+        return Err(anyhow!(
+            "Keyframe interval of {frames} frames is out of the allowed range ({KEYFRAME_INTERVAL_RANGE:?} frames)"
+        ));
+    }
+
+    Ok(())
+}
+
+/// The outcome of applying a setting to the encoders of a pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncoderUpdate {
+    /// Every encoder found took the new value
+    Applied,
+    /// Some encoder only reads it when starting, so the pipeline has to be rebuilt
+    RequiresRebuild,
+    /// No encoder with a configurable setting was found, like when the camera encodes the video itself
+    Unsupported,
+}
+
+impl EncoderUpdate {
+    /// Combines the outcome of each encoder of a pipeline
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::RequiresRebuild, _) | (_, Self::RequiresRebuild) => Self::RequiresRebuild,
+            (Self::Applied, _) | (_, Self::Applied) => Self::Applied,
+            _ => Self::Unsupported,
+        }
+    }
+}
+
+/// A value to be set to an encoder property, converted to the property's type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PropertyValue {
+    Number(u64),
+    /// The nick of an enum value, like "cbr"
+    Nick(&'static str),
+    /// The nick of a flag, set alone, like "zerolatency"
+    Flag(&'static str),
+    Bool(bool),
+}
+
+fn property_value(pspec: &gst::glib::ParamSpec, value: PropertyValue) -> Option<gst::glib::Value> {
+    match value {
+        // Some encoders use u32, others i32
+        PropertyValue::Number(number) => {
+            let value_type = pspec.value_type();
+            if value_type == u32::static_type() {
+                Some((number.min(u32::MAX as u64) as u32).to_value())
+            } else if value_type == i32::static_type() {
+                Some((number.min(i32::MAX as u64) as i32).to_value())
+            } else if value_type == u64::static_type() {
+                Some(number.to_value())
+            } else if value_type == i64::static_type() {
+                Some((number.min(i64::MAX as u64) as i64).to_value())
+            } else {
+                None
+            }
+        }
+        PropertyValue::Nick(nick) => pspec
+            .downcast_ref::<gst::glib::ParamSpecEnum>()?
+            .enum_class()
+            .to_value_by_nick(nick),
+        PropertyValue::Flag(nick) => pspec
+            .downcast_ref::<gst::glib::ParamSpecFlags>()?
+            .flags_class()
+            .builder()
+            .set_by_nick(nick)
+            .build(),
+        PropertyValue::Bool(value) => {
+            (pspec.value_type() == bool::static_type()).then(|| value.to_value())
+        }
+    }
+}
+
+/// Sets all the given properties of the encoder, or none of them if any can't be changed in its current state
+fn set_encoder_properties(
+    element: &gst::Element,
+    properties: &[(&'static str, PropertyValue)],
+) -> Result<EncoderUpdate> {
+    let running = element.current_state() > gst::State::Ready;
+
+    let mut values = Vec::with_capacity(properties.len());
+    for (property, value) in properties {
+        let pspec = element.find_property(property).ok_or_else(|| {
+            anyhow!(
+                "Encoder {:?} has no property {property:?}",
+                element.factory().map(|factory| factory.name())
+            )
+        })?;
+
+        let value = property_value(&pspec, *value).ok_or_else(|| {
+            anyhow!(
+                "Encoder {:?} doesn't accept {value:?} for {property:?}",
+                element.factory().map(|factory| factory.name())
+            )
+        })?;
+
+        if running && !pspec.flags().contains(gst::PARAM_FLAG_MUTABLE_PLAYING) {
+            debug!(
+                "Encoder {:?} can't change {property:?} while running",
+                element.name()
+            );
+            return Ok(EncoderUpdate::RequiresRebuild);
+        }
+
+        values.push((*property, value));
+    }
+
+    for (property, value) in values {
+        element.set_property_from_value(property, &value);
+        debug!("Encoder {:?} {property:?} set to {value:?}", element.name());
+    }
+
+    Ok(EncoderUpdate::Applied)
+}
+
+/// Applies the properties given for each encoder of the pipeline, which returns None for unknown elements
+fn apply_to_encoders(
+    pipeline: &gst::Pipeline,
+    properties: impl Fn(&str) -> Option<Result<Vec<(&'static str, PropertyValue)>>>,
+) -> Result<EncoderUpdate> {
+    let mut update = EncoderUpdate::Unsupported;
+
+    for element in pipeline.iterate_recurse().into_iter().flatten() {
+        let Some(factory) = element.factory() else {
+            continue;
+        };
+
+        let Some(properties) = properties(factory.name().as_str()) else {
+            continue;
+        };
+
+        update = update.merge(set_encoder_properties(&element, &properties?)?);
+    }
+
+    Ok(update)
+}
+
+/// The property holding the maximum distance between keyframes of known encoders
+fn keyframe_interval_property(factory_name: &str) -> Option<&'static str> {
+    let property = match factory_name {
+        "x264enc" | "x265enc" => "key-int-max",
+        "nvh264enc" | "nvh265enc" | "msdkh264enc" | "msdkh265enc" | "openh264enc" => "gop-size",
+        "vaapih264enc" | "vaapih265enc" => "keyframe-period",
+        "nvv4l2h264enc" | "nvv4l2h265enc" => "iframeinterval",
+        "vp8enc" | "vp9enc" => "keyframe-max-dist",
+        _ => return None,
+    };
+
+    Some(property)
+}
+
+/// Sets the keyframe interval, in frames, of all known encoders of the pipeline.
+/// Encoders that don't accept it while running are left untouched, and reported as [`EncoderUpdate::RequiresRebuild`]
+#[instrument(level = "debug", skip(pipeline))]
+pub fn apply_keyframe_interval(pipeline: &gst::Pipeline, frames: u32) -> Result<EncoderUpdate> {
+    apply_to_encoders(pipeline, |factory_name| {
+        let property = keyframe_interval_property(factory_name)?;
+
+        Some(Ok(vec![(property, PropertyValue::Number(frames as u64))]))
+    })
+}
+
+/// Maps the low latency profile to the properties of known encoders: no lookahead and no B-frames, so each
+/// frame leaves the encoder as soon as it is encoded
+fn low_latency_properties(factory_name: &str) -> Option<Vec<(&'static str, PropertyValue)>> {
+    use PropertyValue::{Bool, Flag, Nick, Number};
+
+    let properties = match factory_name {
+        // The zero latency tuning already disables the lookahead and the B-frames
+        "x264enc" => vec![("tune", Flag("zerolatency")), ("bframes", Number(0))],
+        "x265enc" => vec![("tune", Nick("zerolatency"))],
+        "nvh264enc" => vec![("zerolatency", Bool(true)), ("bframes", Number(0))],
+        "nvh265enc" => vec![("zerolatency", Bool(true))],
+        "vaapih264enc" | "vaapih265enc" => vec![("max-bframes", Number(0))],
+        "msdkh264enc" | "msdkh265enc" => vec![("b-frames", Number(0))],
+        "nvv4l2h264enc" | "nvv4l2h265enc" => vec![("num-B-Frames", Number(0))],
+        // Realtime deadline, without holding frames for the alternate references
+        "vp8enc" | "vp9enc" => vec![("deadline", Number(1)), ("lag-in-frames", Number(0))],
+        _ => return None,
+    };
+
+    Some(properties)
+}
+
+/// Tunes all known encoders of the pipeline for the lowest latency.
+/// Encoders that don't accept it while running are left untouched, and reported as [`EncoderUpdate::RequiresRebuild`]
+#[instrument(level = "debug", skip(pipeline))]
+pub fn apply_low_latency(pipeline: &gst::Pipeline) -> Result<EncoderUpdate> {
+    apply_to_encoders(pipeline, |factory_name| {
+        low_latency_properties(factory_name).map(Ok)
+    })
+}
+
+/// The range of target bitrates accepted, in kbit/s
+pub const RATE_CONTROL_BITRATE_RANGE: std::ops::RangeInclusive<u32> = 100..=100_000;
+/// The range of quantizers accepted, from best to worst quality
+pub const RATE_CONTROL_QUALITY_RANGE: std::ops::RangeInclusive<u32> = 0..=51;
+
+pub fn validate_rate_control(configuration: &RateControlConfiguration) -> Result<()> {
+    let RateControlConfiguration {
+        mode,
+        bitrate,
+        max_bitrate,
+        quality,
+    } = configuration;
+
+    for bitrate in [bitrate, max_bitrate].into_iter().flatten() {
+        if !RATE_CONTROL_BITRATE_RANGE.contains(bitrate) {
+            return Err(anyhow!(
+                "Bitrate of {bitrate} kbit/s is out of the allowed range ({RATE_CONTROL_BITRATE_RANGE:?} kbit/s)"
+            ));
+        }
+    }
+
+    if let Some(quality) = quality {
+        if !RATE_CONTROL_QUALITY_RANGE.contains(quality) {
+            return Err(anyhow!(
+                "Quality of {quality} is out of the allowed range ({RATE_CONTROL_QUALITY_RANGE:?})"
+            ));
+        }
+    }
+
+    match mode {
+        RateControlMode::Cbr if bitrate.is_none() => {
+            Err(anyhow!("CBR rate control requires a bitrate"))
+        }
+        RateControlMode::Vbr if bitrate.is_none() => {
+            Err(anyhow!("VBR rate control requires a bitrate"))
+        }
+        RateControlMode::Vbr
+            if max_bitrate.is_some_and(|max_bitrate| Some(max_bitrate) < *bitrate) =>
+        {
+            Err(anyhow!(
+                "VBR maximum bitrate can't be lower than its target bitrate"
+            ))
+        }
+        RateControlMode::Cqp if quality.is_none() => {
+            Err(anyhow!("CQP rate control requires a quality"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Maps the rate control to the properties of known encoders, or an error if the encoder doesn't support it
+fn rate_control_properties(
+    factory_name: &str,
+    configuration: &RateControlConfiguration,
+) -> Option<Result<Vec<(&'static str, PropertyValue)>>> {
+    use PropertyValue::{Nick, Number};
+
+    let mode = &configuration.mode;
+    // In kbit/s
+    let bitrate = configuration.bitrate.unwrap_or_default() as u64;
+    let max_bitrate = configuration
+        .max_bitrate
+        .map(|max_bitrate| max_bitrate as u64);
+    let quality = configuration.quality.unwrap_or_default() as u64;
+
+    let properties = match (factory_name, mode) {
+        ("x264enc", RateControlMode::Cbr) => {
+            vec![("pass", Nick("cbr")), ("bitrate", Number(bitrate))]
+        }
+        // In the quality mode, x264enc's bitrate is used as the maximum bitrate
+        ("x264enc", RateControlMode::Vbr) => vec![
+            ("pass", Nick("qual")),
+            (
+                "quantizer",
+                Number(configuration.quality.unwrap_or(21) as u64),
+            ),
+            ("bitrate", Number(max_bitrate.unwrap_or(bitrate))),
+        ],
+        ("x264enc", RateControlMode::Cqp) => {
+            vec![("pass", Nick("quant")), ("quantizer", Number(quality))]
+        }
+        ("x265enc", RateControlMode::Cbr) => vec![("bitrate", Number(bitrate))],
+        ("x265enc", RateControlMode::Cqp) => vec![("qp", Number(quality))],
+        ("nvh264enc" | "nvh265enc", RateControlMode::Cbr) => {
+            vec![("rc-mode", Nick("cbr")), ("bitrate", Number(bitrate))]
+        }
+        ("nvh264enc" | "nvh265enc", RateControlMode::Vbr) => vec![
+            ("rc-mode", Nick("vbr")),
+            ("bitrate", Number(bitrate)),
+            ("max-bitrate", Number(max_bitrate.unwrap_or_default())),
+        ],
+        ("nvh264enc" | "nvh265enc", RateControlMode::Cqp) => {
+            vec![("rc-mode", Nick("constqp")), ("qp-const", Number(quality))]
+        }
+        ("vaapih264enc" | "vaapih265enc", RateControlMode::Cbr) => {
+            vec![("rate-control", Nick("cbr")), ("bitrate", Number(bitrate))]
+        }
+        // VA-API's bitrate is the maximum one, while the target is a percentage of it
+        ("vaapih264enc" | "vaapih265enc", RateControlMode::Vbr) => {
+            let max_bitrate = max_bitrate.unwrap_or(bitrate);
+            vec![
+                ("rate-control", Nick("vbr")),
+                ("bitrate", Number(max_bitrate)),
+                (
+                    "target-percentage",
+                    Number((bitrate * 100 / max_bitrate).max(1)),
+                ),
+            ]
+        }
+        ("vaapih264enc" | "vaapih265enc", RateControlMode::Cqp) => {
+            vec![("rate-control", Nick("cqp")), ("init-qp", Number(quality))]
+        }
+        ("msdkh264enc" | "msdkh265enc", RateControlMode::Cbr) => {
+            vec![("rate-control", Nick("cbr")), ("bitrate", Number(bitrate))]
+        }
+        ("msdkh264enc" | "msdkh265enc", RateControlMode::Vbr) => vec![
+            ("rate-control", Nick("vbr")),
+            ("bitrate", Number(bitrate)),
+            ("max-vbv-bitrate", Number(max_bitrate.unwrap_or_default())),
+        ],
+        ("msdkh264enc" | "msdkh265enc", RateControlMode::Cqp) => vec![
+            ("rate-control", Nick("cqp")),
+            ("qpi", Number(quality)),
+            ("qpp", Number(quality)),
+            ("qpb", Number(quality)),
+        ],
+        // In bit/s
+        ("openh264enc", RateControlMode::Cbr) => vec![
+            ("rate-control", Nick("bitrate")),
+            ("bitrate", Number(bitrate * 1000)),
+        ],
+        ("nvv4l2h264enc" | "nvv4l2h265enc", RateControlMode::Cbr) => vec![
+            ("control-rate", Nick("constant_bitrate")),
+            ("bitrate", Number(bitrate * 1000)),
+        ],
+        ("nvv4l2h264enc" | "nvv4l2h265enc", RateControlMode::Vbr) => vec![
+            ("control-rate", Nick("variable_bitrate")),
+            ("bitrate", Number(bitrate * 1000)),
+            (
+                "peak-bitrate",
+                Number(max_bitrate.unwrap_or(bitrate) * 1000),
+            ),
+        ],
+        (
+            "x265enc" | "openh264enc" | "nvv4l2h264enc" | "nvv4l2h265enc" | "v4l2h264enc"
+            | "v4l2h265enc",
+            mode,
+        ) => {
+            return Some(Err(anyhow!(
+                "Encoder {factory_name:?} doesn't support the {mode:?} rate control"
+            )))
+        }
+        _ => return None,
+    };
+
+    Some(Ok(properties))
+}
+
+/// Sets the rate control of all known encoders of the pipeline, failing if any of them doesn't support it.
+/// Encoders that don't accept it while running are left untouched, and reported as [`EncoderUpdate::RequiresRebuild`]
+#[instrument(level = "debug", skip(pipeline))]
+pub fn apply_rate_control(
+    pipeline: &gst::Pipeline,
+    configuration: &RateControlConfiguration,
+) -> Result<EncoderUpdate> {
+    validate_rate_control(configuration)?;
+
+    apply_to_encoders(pipeline, |factory_name| {
+        rate_control_properties(factory_name, configuration)
+    })
+}
+
+/// The H264 levels accepted, with their level_idc
+const H264_LEVELS: &[(&str, u8)] = &[
+    ("1", 10),
+    ("1.1", 11),
+    ("1.2", 12),
+    ("1.3", 13),
+    ("2", 20),
+    ("2.1", 21),
+    ("2.2", 22),
+    ("3", 30),
+    ("3.1", 31),
+    ("3.2", 32),
+    ("4", 40),
+    ("4.1", 41),
+    ("4.2", 42),
+    ("5", 50),
+    ("5.1", 51),
+    ("5.2", 52),
+];
+
+/// The name of the profile in the H264 caps
+fn h264_profile_name(profile: &H264Profile) -> &'static str {
+    match profile {
+        H264Profile::ConstrainedBaseline => "constrained-baseline",
+        H264Profile::Baseline => "baseline",
+        H264Profile::Main => "main",
+        H264Profile::High => "high",
+    }
+}
+
+fn h264_level_idc(level: &str) -> Option<u8> {
+    H264_LEVELS
+        .iter()
+        .find(|(name, _)| *name == level)
+        .map(|(_, level_idc)| *level_idc)
+}
+
+pub fn validate_h264_profile(configuration: &H264ProfileConfiguration) -> Result<()> {
+    if let Some(level) = &configuration.level {
+        if h264_level_idc(level).is_none() {
+            return Err(anyhow!(
+                "H264 level {level:?} is not valid, the available options are: {:?}",
+                H264_LEVELS
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the profile and level against the source caps the encoder advertises
+#[instrument(level = "debug")]
+pub fn validate_h264_profile_for_encoder(
+    configuration: &H264ProfileConfiguration,
+    encoder_name: &str,
+) -> Result<()> {
+    validate_h264_profile(configuration)?;
+
+    let factory = gst::ElementFactory::find(encoder_name)
+        .ok_or_else(|| anyhow!("Encoder {encoder_name:?} not found"))?;
+
+    let mut caps = gst::Caps::builder("video/x-h264")
+        .field("profile", h264_profile_name(&configuration.profile));
+    if let Some(level) = &configuration.level {
+        caps = caps.field("level", level);
+    }
+    let caps = caps.build();
+
+    let supported = factory
+        .static_pad_templates()
+        .iter()
+        .filter(|template| template.direction() == gst::PadDirection::Src)
+        .any(|template| template.caps().can_intersect(&caps));
+
+    if !supported {
+        return Err(anyhow!(
+            "Encoder {encoder_name:?} doesn't support the H264 {:?} profile{}",
+            configuration.profile,
+            configuration
+                .level
+                .as_ref()
+                .map(|level| format!(" at level {level}"))
+                .unwrap_or_default()
+        ));
+    }
+
+    Ok(())
+}
+
+/// The profile and level fields of the caps following an H264 encoder, like "profile=main,level=(string)4.1"
+pub fn h264_caps_fields(configuration: Option<&H264ProfileConfiguration>) -> String {
+    let Some(configuration) = configuration else {
+        return format!(
+            "profile={}",
+            h264_profile_name(&H264Profile::ConstrainedBaseline)
+        );
+    };
+
+    let profile = h264_profile_name(&configuration.profile);
+    match &configuration.level {
+        Some(level) => format!("profile={profile},level=(string){level}"),
+        None => format!("profile={profile}"),
+    }
+}
+
+/// The SDP's profile-level-id for the given profile and level. Without a level, the one of `current` is kept
+pub fn h264_profile_level_id(configuration: &H264ProfileConfiguration, current: u32) -> u32 {
+    // The profile_idc and the constraint flags
+    let profile: u32 = match configuration.profile {
+        H264Profile::ConstrainedBaseline => 0x42e0,
+        H264Profile::Baseline => 0x4200,
+        H264Profile::Main => 0x4d00,
+        H264Profile::High => 0x6400,
+    };
+
+    let level_idc = configuration
+        .level
+        .as_deref()
+        .and_then(h264_level_idc)
+        .map_or(current & 0xff, |level_idc| level_idc as u32);
+
+    (profile << 8) | level_idc
+}
+
+/// Finds the first known encoder of the pipeline for the given encode
+pub fn pipeline_encoder(
+    pipeline: &gst::Pipeline,
+    encode: &VideoEncodeType,
+) -> Option<&'static str> {
+    let candidates = candidates(encode);
+
+    pipeline
+        .iterate_recurse()
+        .into_iter()
+        .flatten()
+        .find_map(|element| {
+            let factory = element.factory()?;
+
+            candidates
+                .iter()
+                .find(|candidate| candidate.name == factory.name().as_str())
+                .map(|candidate| candidate.name)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: Vec<&'static EncoderCandidate>) -> Vec<&'static str> {
+        list.iter().map(|candidate| candidate.name).collect()
+    }
+
+    #[test]
+    fn test_preference_list() {
+        // Only software available
+        let registry = ["x264enc", "openh264enc"];
+        let list = preference_list(H264_ENCODERS, &[], |name| registry.contains(&name));
+        assert_eq!(names(list), vec!["x264enc", "openh264enc"]);
+
+        // Hardware is picked when present
+        let registry = ["x264enc", "v4l2h264enc"];
+        let list = preference_list(H264_ENCODERS, &[], |name| registry.contains(&name));
+        assert_eq!(names(list), vec!["v4l2h264enc", "x264enc"]);
+
+        // The user's preference overrides it
+        let preferred = vec!["x264enc".to_string(), "unknown".to_string()];
+        let list = preference_list(H264_ENCODERS, &preferred, |name| registry.contains(&name));
+        assert_eq!(names(list), vec!["x264enc", "v4l2h264enc"]);
+
+        // Nothing available
+        let list = preference_list(H265_ENCODERS, &[], |_| false);
+        assert!(list.is_empty());
+    }
+
+    fn x264enc_pipeline() -> (gst::Pipeline, gst::Element) {
+        gst::init().unwrap();
+
+        let pipeline = gst::parse::launch("videotestsrc ! x264enc name=encoder ! fakesink")
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+        let encoder = pipeline.by_name("encoder").unwrap();
+
+        (pipeline, encoder)
+    }
+
+    #[test]
+    fn test_keyframe_interval() {
+        assert!(validate_keyframe_interval(0).is_err());
+        assert!(validate_keyframe_interval(30).is_ok());
+        assert!(validate_keyframe_interval(1001).is_err());
+
+        let (pipeline, encoder) = x264enc_pipeline();
+        assert_eq!(
+            apply_keyframe_interval(&pipeline, 30).unwrap(),
+            EncoderUpdate::Applied
+        );
+        assert_eq!(encoder.property::<u32>("key-int-max"), 30);
+
+        let pipeline = gst::parse::launch("videotestsrc ! fakesink")
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+        assert_eq!(
+            apply_keyframe_interval(&pipeline, 30).unwrap(),
+            EncoderUpdate::Unsupported
+        );
+    }
+
+    #[test]
+    fn test_rate_control() {
+        let configuration = |mode, bitrate, max_bitrate, quality| RateControlConfiguration {
+            mode,
+            bitrate,
+            max_bitrate,
+            quality,
+        };
+
+        // Validation
+        assert!(
+            validate_rate_control(&configuration(RateControlMode::Cbr, None, None, None)).is_err()
+        );
+        assert!(
+            validate_rate_control(&configuration(RateControlMode::Cbr, Some(50), None, None))
+                .is_err()
+        );
+        assert!(validate_rate_control(&configuration(
+            RateControlMode::Vbr,
+            Some(4000),
+            Some(2000),
+            None
+        ))
+        .is_err());
+        assert!(
+            validate_rate_control(&configuration(RateControlMode::Cqp, None, None, Some(52)))
+                .is_err()
+        );
+
+        // Unsupported modes are refused
+        let vbr = configuration(RateControlMode::Vbr, Some(2000), Some(4000), None);
+        assert!(validate_rate_control(&vbr).is_ok());
+        assert!(rate_control_properties("x265enc", &vbr).unwrap().is_err());
+        assert!(rate_control_properties("identity", &vbr).is_none());
+
+        let (pipeline, encoder) = x264enc_pipeline();
+        let cqp = configuration(RateControlMode::Cqp, None, None, Some(30));
+        assert_eq!(
+            apply_rate_control(&pipeline, &cqp).unwrap(),
+            EncoderUpdate::Applied
+        );
+        assert_eq!(encoder.property::<u32>("quantizer"), 30);
+
+        let cbr = configuration(RateControlMode::Cbr, Some(2000), None, None);
+        assert_eq!(
+            apply_rate_control(&pipeline, &cbr).unwrap(),
+            EncoderUpdate::Applied
+        );
+        assert_eq!(encoder.property::<u32>("bitrate"), 2000);
+    }
+
+    #[test]
+    fn test_h264_profile() {
+        gst::init().unwrap();
+
+        let configuration = |profile, level: Option<&str>| H264ProfileConfiguration {
+            profile,
+            level: level.map(str::to_string),
+        };
+
+        assert!(validate_h264_profile(&configuration(H264Profile::Main, Some("4.1"))).is_ok());
+        assert!(validate_h264_profile(&configuration(H264Profile::Main, Some("7"))).is_err());
+
+        assert!(validate_h264_profile_for_encoder(
+            &configuration(H264Profile::High, Some("4.1")),
+            "x264enc"
+        )
+        .is_ok());
+        assert!(validate_h264_profile_for_encoder(
+            &configuration(H264Profile::High, None),
+            "unknown"
+        )
+        .is_err());
+
+        assert_eq!(h264_caps_fields(None), "profile=constrained-baseline");
+        assert_eq!(
+            h264_caps_fields(Some(&configuration(H264Profile::Main, Some("4.1")))),
+            "profile=main,level=(string)4.1"
+        );
+
+        assert_eq!(
+            h264_profile_level_id(&configuration(H264Profile::High, Some("4.1")), 0x42e01f),
+            0x640029
+        );
+        assert_eq!(
+            h264_profile_level_id(&configuration(H264Profile::Main, None), 0x42e01f),
+            0x4d001f
+        );
+    }
+}
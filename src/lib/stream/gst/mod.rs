@@ -1,2 +1,4 @@
+pub mod availability;
+pub mod encoders;
 pub mod info;
 pub mod utils;
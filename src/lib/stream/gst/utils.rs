@@ -1,12 +1,111 @@
 use anyhow::{anyhow, Result};
 use gst::prelude::*;
 
+use crate::stream::types::{ElementCaps, PadCaps, PadDirection};
+
 #[derive(Debug)]
 pub struct PluginRankConfig {
     pub name: String,
     pub rank: gst::Rank,
 }
 
+#[derive(Debug, Clone)]
+pub struct DotFileConfig {
+    /// Enables pipeline graph dumps. Disabled by default in release builds
+    pub enabled: bool,
+    /// When set, dot files are written here instead of the GST_DEBUG_DUMP_DOT_DIR env variable
+    pub directory: Option<std::path::PathBuf>,
+    /// Maximum number of dot files retained per pipeline in `directory`, the oldest ones are removed first
+    pub max_files_per_pipeline: usize,
+}
+
+impl Default for DotFileConfig {
+    fn default() -> Self {
+        Self {
+            enabled: cfg!(debug_assertions),
+            directory: None,
+            max_files_per_pipeline: 20,
+        }
+    }
+}
+
+lazy_static! {
+    static ref DOT_FILE_CONFIG: std::sync::RwLock<DotFileConfig> = Default::default();
+}
+
+pub fn set_dot_file_config(config: DotFileConfig) {
+    *DOT_FILE_CONFIG.write().unwrap() = config;
+}
+
+pub fn dot_file_config() -> DotFileConfig {
+    DOT_FILE_CONFIG.read().unwrap().clone()
+}
+
+/// Dumps the pipeline graph, if dot files are enabled
+pub fn dump_dot_file(pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid, name: &str) {
+    if !DOT_FILE_CONFIG.read().unwrap().enabled {
+        return;
+    }
+
+    write_dot_file(pipeline, pipeline_id, name);
+}
+
+/// Dumps the pipeline graph, regardless of dot files being enabled
+pub fn write_dot_file(pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid, name: &str) {
+    let config = dot_file_config();
+
+    let Some(directory) = config.directory else {
+        pipeline.debug_to_dot_file_with_ts(
+            gst::DebugGraphDetails::all(),
+            format!("pipeline-{pipeline_id}-{name}"),
+        );
+        return;
+    };
+
+    // The timestamp goes right after the pipeline prefix, so sorting the names also sorts them chronologically
+    let prefix = format!("pipeline-{pipeline_id}-");
+    let timestamp = chrono::Local::now().format("%Y%m%dT%H%M%S%.6f");
+    let path = directory.join(format!("{prefix}{timestamp}-{name}.dot"));
+
+    let result = std::fs::create_dir_all(&directory)
+        .and_then(|_| {
+            std::fs::write(
+                &path,
+                pipeline
+                    .debug_to_dot_data(gst::DebugGraphDetails::all())
+                    .as_str(),
+            )
+        })
+        .map_err(anyhow::Error::msg)
+        .and_then(|_| rotate_dot_files(&directory, &prefix, config.max_files_per_pipeline));
+
+    if let Err(error) = result {
+        tracing::warn!("Failed writing dot file {path:?}. Reason: {error:?}");
+    }
+}
+
+/// Removes the oldest dot files starting with `prefix` until only `max_files` are left
+fn rotate_dot_files(directory: &std::path::Path, prefix: &str, max_files: usize) -> Result<()> {
+    let mut files = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(prefix) && name.ends_with(".dot"))
+        })
+        .collect::<Vec<std::path::PathBuf>>();
+
+    files.sort();
+
+    let excess = files.len().saturating_sub(max_files);
+    for file in files.into_iter().take(excess) {
+        std::fs::remove_file(&file)?;
+    }
+
+    Ok(())
+}
+
 #[allow(dead_code)] // TODO: Use this to check all used plugins are available
 pub fn is_gst_plugin_available(plugin_name: &str, min_version: Option<&str>) -> bool {
     // reference: https://github.com/GStreamer/gst/blob/b4ca58df7624b005a33e182a511904d7cceea890/tools/gst-inspect.c#L2148
@@ -105,3 +204,193 @@ pub async fn wait_for_element_state_async(
 
     Ok(())
 }
+
+/// Asks the upstream elements of the given element for a keyframe, returning if any of them handled it
+pub fn request_keyframe(element: &gst::Element) -> bool {
+    let event = gst_video::UpstreamForceKeyUnitEvent::builder()
+        .all_headers(true)
+        .build();
+
+    // Upstream events are sent into the src pads linked to this element, travelling upstream from there
+    element
+        .sink_pads()
+        .iter()
+        .filter_map(|pad| pad.peer())
+        .fold(false, |handled, peer| {
+            peer.send_event(event.clone()) || handled
+        })
+}
+
+/// The "encoding-name" of RTP caps, like "H264"
+pub fn rtp_encoding_name(rtp_caps: &gst::Caps) -> Option<String> {
+    rtp_caps.iter().find_map(|structure| {
+        structure.iter().find_map(|(key, sendvalue)| {
+            if key == "encoding-name" {
+                Some(
+                    sendvalue
+                        .to_value()
+                        .get::<String>()
+                        .expect("Failed accessing encoding-name parameter"),
+                )
+            } else {
+                None
+            }
+        })
+    })
+}
+
+/// The current caps of each pad of every element in the pipeline, including the ones inside bins
+pub fn negotiated_caps(pipeline: &gst::Pipeline) -> Vec<ElementCaps> {
+    pipeline
+        .iterate_recurse()
+        .into_iter()
+        .flatten()
+        .map(|element| ElementCaps {
+            element: element.name().to_string(),
+            factory: element.factory().map(|factory| factory.name().to_string()),
+            pads: element
+                .pads()
+                .iter()
+                .map(|pad| PadCaps {
+                    pad: pad.name().to_string(),
+                    direction: match pad.direction() {
+                        gst::PadDirection::Src => PadDirection::Src,
+                        gst::PadDirection::Sink => PadDirection::Sink,
+                        _ => PadDirection::Unknown,
+                    },
+                    caps: pad.current_caps().map(|caps| caps.to_string()),
+                    peer: pad.peer().map(|peer| {
+                        let parent = peer
+                            .parent_element()
+                            .map(|element| element.name().to_string())
+                            .unwrap_or_default();
+                        format!("{parent}:{}", peer.name())
+                    }),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiated_caps() {
+        gst::init().unwrap();
+
+        let pipeline = gst::parse::launch(concat!(
+            "videotestsrc name=source num-buffers=1",
+            " ! video/x-raw,width=320,height=240",
+            " ! fakesink name=sink",
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+
+        // Nothing negotiated yet
+        let caps = negotiated_caps(&pipeline);
+        let source = caps
+            .iter()
+            .find(|element| element.element == "source")
+            .unwrap();
+        assert_eq!(source.factory.as_deref(), Some("videotestsrc"));
+        assert_eq!(source.pads.len(), 1);
+        assert_eq!(source.pads[0].direction, PadDirection::Src);
+        assert!(source.pads[0].caps.is_none());
+
+        pipeline.set_state(gst::State::Paused).unwrap();
+        pipeline.state(gst::ClockTime::from_seconds(5)).0.unwrap();
+
+        let caps = negotiated_caps(&pipeline);
+        let sink = caps
+            .iter()
+            .find(|element| element.element == "sink")
+            .unwrap();
+        let sink_caps = sink.pads[0].caps.as_deref().unwrap();
+        assert!(sink_caps.starts_with("video/x-raw"));
+        assert!(sink_caps.contains("width=(int)320"));
+        assert!(sink.pads[0].peer.as_deref().unwrap().ends_with(":src"));
+
+        pipeline.set_state(gst::State::Null).unwrap();
+    }
+
+    #[test]
+    fn test_request_keyframe() {
+        gst::init().unwrap();
+
+        // A GOP much longer than the test, so any keyframe after the first one is a forced one
+        let pipeline = gst::parse::launch(concat!(
+            "videotestsrc is-live=true",
+            " ! video/x-raw,width=320,height=240,framerate=30/1",
+            " ! x264enc tune=zerolatency speed-preset=ultrafast key-int-max=3000",
+            " ! h264parse",
+            " ! tee name=tee allow-not-linked=true",
+            " ! appsink name=sink sync=false",
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        let appsink = pipeline
+            .by_name("sink")
+            .unwrap()
+            .downcast::<gst_app::AppSink>()
+            .unwrap();
+        pipeline.set_state(gst::State::Playing).unwrap();
+
+        let is_keyframe = |sample: &gst::Sample| {
+            !sample
+                .buffer()
+                .unwrap()
+                .flags()
+                .contains(gst::BufferFlags::DELTA_UNIT)
+        };
+
+        let timeout = gst::ClockTime::from_seconds(10);
+        assert!(is_keyframe(&appsink.try_pull_sample(timeout).unwrap()));
+        for _ in 0..5 {
+            assert!(!is_keyframe(&appsink.try_pull_sample(timeout).unwrap()));
+        }
+
+        assert!(request_keyframe(&pipeline.by_name("tee").unwrap()));
+
+        // The encoder might already be working on the next frame when the request arrives
+        let keyframe_position =
+            (0..3).position(|_| is_keyframe(&appsink.try_pull_sample(timeout).unwrap()));
+        assert!(keyframe_position.is_some());
+
+        pipeline.set_state(gst::State::Null).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_dot_files() {
+        let directory = std::env::temp_dir().join(format!("dot-files-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let prefix = "pipeline-a-";
+        for index in 0..5 {
+            std::fs::write(directory.join(format!("{prefix}{index}-eos.dot")), "").unwrap();
+        }
+        // Files from other pipelines should be kept
+        std::fs::write(directory.join("pipeline-b-0-eos.dot"), "").unwrap();
+
+        rotate_dot_files(&directory, prefix, 2).unwrap();
+
+        let mut names = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "pipeline-a-3-eos.dot",
+                "pipeline-a-4-eos.dot",
+                "pipeline-b-0-eos.dot"
+            ]
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use tracing::*;
+
+use super::{manager, metrics::StreamMetrics};
+
+/// How far back the restarts of a stream are counted
+const FLAPPING_WINDOW: Duration = Duration::from_secs(5 * 60);
+/// A stream restarting this many times within the window is flapping
+const FLAPPING_RESTARTS: usize = 3;
+
+lazy_static! {
+    static ref RESTARTS: Arc<Mutex<HashMap<uuid::Uuid, RestartHistory>>> = Default::default();
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    /// Every enabled stream is running steadily
+    Healthy,
+    /// Serving, but some enabled streams are not running or keep restarting
+    Degraded,
+    /// Not able to serve any stream, like when GStreamer failed to initialize
+    Unhealthy,
+}
+
+#[derive(Apiv2Schema, Debug, Clone, PartialEq, Serialize)]
+pub struct Health {
+    pub status: HealthStatus,
+    pub gstreamer_initialized: bool,
+    /// Every stream of the settings, including the disabled ones
+    pub streams_configured: usize,
+    pub streams_running: usize,
+    /// Streams restarted at least 3 times in the last 5 minutes
+    pub streams_flapping: usize,
+}
+
+/// When the restart count of a stream was seen increasing
+#[derive(Debug, Default)]
+struct RestartHistory {
+    restart_count: usize,
+    restarts: VecDeque<Instant>,
+}
+
+/// Follows the restart counts of the given streams, forgetting the removed ones. To be called periodically
+pub fn observe(streams: &[StreamMetrics]) {
+    record(&mut RESTARTS.lock().unwrap(), streams, Instant::now());
+}
+
+/// The overall status of the service, for liveness and readiness probes
+#[instrument(level = "debug")]
+pub async fn health() -> Health {
+    let gstreamer_initialized = match gst::init() {
+        Ok(()) => true,
+        Err(error) => {
+            warn!("GStreamer is not initialized: {error:?}");
+            false
+        }
+    };
+
+    let streams = manager::streams().await.unwrap_or_else(|error| {
+        warn!("Failed listing the streams: {error:?}");
+        vec![]
+    });
+    let streams_flapping = flapping(&RESTARTS.lock().unwrap(), Instant::now());
+
+    summarize(
+        gstreamer_initialized,
+        streams.len(),
+        streams.iter().filter(|stream| !stream.disabled).count(),
+        streams.iter().filter(|stream| stream.running).count(),
+        streams_flapping,
+    )
+}
+
+fn summarize(
+    gstreamer_initialized: bool,
+    streams_configured: usize,
+    streams_enabled: usize,
+    streams_running: usize,
+    streams_flapping: usize,
+) -> Health {
+    let status = if !gstreamer_initialized {
+        HealthStatus::Unhealthy
+    } else if streams_running < streams_enabled || streams_flapping > 0 {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Healthy
+    };
+
+    Health {
+        status,
+        gstreamer_initialized,
+        streams_configured,
+        streams_running,
+        streams_flapping,
+    }
+}
+
+fn record(
+    history: &mut HashMap<uuid::Uuid, RestartHistory>,
+    streams: &[StreamMetrics],
+    now: Instant,
+) {
+    history.retain(|stream_id, _| streams.iter().any(|stream| stream.id == *stream_id));
+
+    for stream in streams {
+        let Some(restart_history) = history.get_mut(&stream.id) else {
+            // Restarts from before we started following the stream are not timed, so they don't count
+            history.insert(
+                stream.id,
+                RestartHistory {
+                    restart_count: stream.restart_count,
+                    restarts: VecDeque::new(),
+                },
+            );
+            continue;
+        };
+
+        let new_restarts = stream
+            .restart_count
+            .saturating_sub(restart_history.restart_count);
+        restart_history.restart_count = stream.restart_count;
+        restart_history
+            .restarts
+            .extend(std::iter::repeat(now).take(new_restarts));
+
+        while restart_history
+            .restarts
+            .front()
+            .is_some_and(|restart| now.duration_since(*restart) > FLAPPING_WINDOW)
+        {
+            restart_history.restarts.pop_front();
+        }
+    }
+}
+
+fn flapping(history: &HashMap<uuid::Uuid, RestartHistory>, now: Instant) -> usize {
+    history
+        .values()
+        .filter(|restart_history| {
+            restart_history
+                .restarts
+                .iter()
+                .filter(|restart| now.duration_since(**restart) <= FLAPPING_WINDOW)
+                .count()
+                >= FLAPPING_RESTARTS
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_metrics(id: uuid::Uuid, restart_count: usize) -> StreamMetrics {
+        StreamMetrics {
+            id,
+            name: "camera".to_string(),
+            running: true,
+            bitrate: 0.0,
+            framerate: 30.0,
+            uptime_seconds: None,
+            restart_count,
+            clients: 0,
+            dropped_frames: 0,
+            cpu_seconds: 0.0,
+            queued_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_flapping() {
+        let (first, second) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let mut history = HashMap::new();
+        let start = Instant::now();
+
+        // Restarts from before the first observation don't count
+        record(
+            &mut history,
+            &[stream_metrics(first, 10), stream_metrics(second, 0)],
+            start,
+        );
+        assert_eq!(flapping(&history, start), 0);
+
+        let now = start + Duration::from_secs(1);
+        record(
+            &mut history,
+            &[stream_metrics(first, 13), stream_metrics(second, 1)],
+            now,
+        );
+        assert_eq!(flapping(&history, now), 1);
+
+        // Steady again once the restarts get old
+        let later = now + FLAPPING_WINDOW + Duration::from_secs(1);
+        record(
+            &mut history,
+            &[stream_metrics(first, 13), stream_metrics(second, 1)],
+            later,
+        );
+        assert_eq!(flapping(&history, later), 0);
+
+        // Removed streams are forgotten
+        record(&mut history, &[stream_metrics(first, 13)], later);
+        assert!(!history.contains_key(&second));
+    }
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(summarize(true, 3, 2, 2, 0).status, HealthStatus::Healthy);
+        assert_eq!(summarize(true, 0, 0, 0, 0).status, HealthStatus::Healthy);
+        assert_eq!(summarize(true, 3, 2, 1, 0).status, HealthStatus::Degraded);
+        assert_eq!(summarize(true, 2, 2, 2, 1).status, HealthStatus::Degraded);
+        assert_eq!(summarize(false, 2, 2, 0, 0).status, HealthStatus::Unhealthy);
+    }
+}
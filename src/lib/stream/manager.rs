@@ -2,12 +2,15 @@ use std::{collections::HashMap, sync::Arc};
 
 use tokio::sync::RwLock;
 
+use crate::{
+    mavlink::mavlink_camera::MavlinkCamera, stream::sink::SinkInterface,
+    video::types::VideoSourceType,
+};
 use crate::{
     settings,
     stream::{types::CaptureConfiguration, webrtc::signalling_protocol::BindAnswer},
     video::video_source,
 };
-use crate::{stream::sink::SinkInterface, video::types::VideoSourceType};
 use crate::{
     stream::sink::{webrtc_sink::WebRTCSink, Sink},
     video_stream::types::VideoAndStreamInformation,
@@ -22,8 +25,21 @@ use futures::stream::StreamExt;
 use tracing::*;
 
 use super::{
-    pipeline::PipelineGstreamerInterface,
-    types::StreamStatus,
+    bandwidth::{self, BandwidthStatus},
+    endpoints, formats,
+    metrics::StreamMetrics,
+    pipeline::{
+        self,
+        runner::{publish_event, PipelineEventKind},
+        zoom::DigitalZoom,
+        PipelineGstreamerInterface,
+    },
+    types::{
+        AudioConfiguration, AudioStatus, CameraMode, ClientStatus, ClockConfiguration, ClockStatus,
+        ElementCaps, H264ProfileConfiguration, IceServer, OutputResolutionConfiguration,
+        OverlayConfiguration, RateControlConfiguration, ReconnectionConfiguration,
+        RecordingSettings, StreamStatus, WebRTCSessionStatus,
+    },
     webrtc::{self, signalling_protocol::RTCSessionDescription},
     Stream,
 };
@@ -31,6 +47,14 @@ use super::{
 #[derive(Default)]
 pub struct Manager {
     streams: HashMap<uuid::Uuid, Stream>,
+    /// Configured streams that are not running, either because they are not auto-started, or because
+    /// their device was absent when the service started. They are kept so they persist in the settings
+    pending_streams: Vec<VideoAndStreamInformation>,
+    /// Streams disabled by the user, or enabled but waiting for their device. They keep their id, so they can be
+    /// enabled through it
+    idle_streams: HashMap<uuid::Uuid, VideoAndStreamInformation>,
+    /// Why the idle streams aren't running, like the ones that gave up waiting for their device at startup
+    idle_errors: HashMap<uuid::Uuid, String>,
 }
 
 lazy_static! {
@@ -42,153 +66,1175 @@ impl Manager {
     async fn update_settings(&self) {
         use futures::StreamExt;
 
-        let streams = futures::stream::iter(self.streams.values());
+        let streams = futures::stream::iter(self.streams.values());
+
+        let video_and_stream_informations =
+            futures::StreamExt::filter_map(streams, |stream| async move {
+                let stream_guard = stream.state.read().await;
+
+                stream_guard
+                    .as_ref()
+                    .map(|state| state.video_and_stream_information.clone())
+            })
+            .collect::<Vec<VideoAndStreamInformation>>()
+            .await
+            .into_iter()
+            .chain(self.pending_streams.iter().cloned())
+            .chain(self.idle_streams.values().cloned())
+            .collect::<Vec<VideoAndStreamInformation>>();
+
+        settings::manager::set_streams(video_and_stream_informations.as_slice());
+    }
+}
+
+#[instrument(level = "debug")]
+pub fn init() {
+    debug!("Starting video stream service.");
+
+    if let Err(error) = gst::init() {
+        error!("Error! {error}");
+    };
+
+    config_gst_plugins();
+
+    config_dot_files();
+
+    crate::stream::gst::encoders::probe();
+}
+
+#[instrument(level = "debug")]
+fn config_dot_files() {
+    let default = crate::stream::gst::utils::DotFileConfig::default();
+
+    crate::stream::gst::utils::set_dot_file_config(crate::stream::gst::utils::DotFileConfig {
+        enabled: default.enabled || crate::cli::manager::enable_dot_files(),
+        directory: crate::cli::manager::dot_files_path().map(std::path::PathBuf::from),
+        max_files_per_pipeline: crate::cli::manager::dot_files_max(),
+    });
+}
+
+#[instrument(level = "debug")]
+fn config_gst_plugins() {
+    let plugins_config = crate::cli::manager::gst_feature_rank();
+
+    for config in plugins_config {
+        match crate::stream::gst::utils::set_plugin_rank(config.name.as_str(), config.rank) {
+            Ok(_) => info!(
+                "Gstreamer Plugin {name:?} configured with rank {rank:?}.",
+                name = config.name,
+                rank = config.rank,
+            ),
+            Err(error) => error!("Error when trying to configure plugin {name:?} rank to {rank:?}. Reason: {error:?}", name = config.name, rank = config.rank, error=error.to_string()),
+        }
+    }
+}
+
+pub async fn remove_all_streams() -> Result<()> {
+    let keys = {
+        let mut ids = vec![];
+
+        MANAGER.read().await.streams.keys().for_each(|id| {
+            ids.push(*id);
+        });
+
+        ids
+    };
+
+    for stream_id in keys.iter() {
+        if let Err(error) = Manager::remove_stream(stream_id).await {
+            warn!("Failed removing stream {stream_id:?}: {error:?}")
+        }
+    }
+
+    let mut manager = MANAGER.write().await;
+    manager.pending_streams.clear();
+    manager.idle_streams.clear();
+    manager.idle_errors.clear();
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+#[instrument(level = "debug")]
+pub async fn start_default() -> Result<()> {
+    // Get streams from default settings, this needs to be done first because
+    // remove_all_streams will modify the settings as its using the stream manager
+    // to remove the streams, and the stream manager will save the state after
+    // each removal in the settings
+    let streams = settings::manager::streams();
+
+    // Gently remove all streams as we are going to replace the entire list below
+    remove_all_streams().await?;
+
+    // Disabled streams are only listed, until enabled
+    let (streams, idle_streams): (Vec<_>, Vec<_>) = streams
+        .into_iter()
+        .partition(|stream| stream.stream_information.enabled());
+    for stream in &idle_streams {
+        info!("Stream {:?} is disabled", stream.name);
+    }
+    add_idle_streams(idle_streams).await;
+
+    // Streams not auto-started are only kept in the settings
+    let (mut streams, disabled_streams): (Vec<_>, Vec<_>) = streams
+        .into_iter()
+        .partition(|stream| stream.stream_information.auto_start());
+    for stream in &disabled_streams {
+        info!("Stream {:?} is not auto-started", stream.name);
+    }
+    add_pending_streams(disabled_streams).await;
+
+    // Update all local video sources to make sure that they are available
+    let mut candidates = video_source::cameras_available();
+    update_devices(&mut streams, &mut candidates, true);
+
+    debug!("Streams: {streams:#?}");
+
+    let result = start_streams(streams).await;
+
+    // Cameras might still be enumerating at boot, so retry the streams waiting for their devices for a while, and
+    // whenever one is connected
+    static PENDING_STREAMS_TASK: std::sync::Once = std::sync::Once::new();
+    PENDING_STREAMS_TASK.call_once(|| {
+        tokio::spawn(pending_streams_task());
+    });
+
+    result
+}
+
+/// Starts the given streams, keeping the ones with an absent device as pending. Fails with the reasons of the streams
+/// that couldn't be started, after starting all the others
+async fn start_streams(streams: Vec<VideoAndStreamInformation>) -> Result<()> {
+    let mut pending_streams = vec![];
+    let mut errors = vec![];
+
+    for stream in streams {
+        if !stream.video_source.inner().is_valid() {
+            warn!(
+                "Stream {:?} is waiting for its device to be connected",
+                stream.name
+            );
+            pending_streams.push(stream);
+            continue;
+        }
+
+        let name = stream.name.clone();
+        if let Err(error) = add_stream_and_start(stream).await {
+            error!("Not possible to start stream: {error:?}");
+            errors.push(format!("Stream {name:?}: {error:#}"));
+        };
+    }
+
+    add_pending_streams(pending_streams).await;
+
+    if !errors.is_empty() {
+        return Err(anyhow!(
+            "Failed starting {} streams: {}",
+            errors.len(),
+            errors.join("; ")
+        ));
+    }
+
+    Ok(())
+}
+
+async fn add_pending_streams(streams: Vec<VideoAndStreamInformation>) {
+    if streams.is_empty() {
+        return;
+    }
+
+    let mut manager = MANAGER.write().await;
+    manager.pending_streams.extend(streams);
+    manager.update_settings().await;
+}
+
+async fn add_idle_streams(streams: Vec<VideoAndStreamInformation>) {
+    if streams.is_empty() {
+        return;
+    }
+
+    let mut manager = MANAGER.write().await;
+    manager.idle_streams.extend(
+        streams
+            .into_iter()
+            .map(|stream| (Manager::generate_uuid(), stream)),
+    );
+    manager.update_settings().await;
+}
+
+/// The local cameras not in use by any running stream
+async fn free_cameras() -> Vec<VideoSourceType> {
+    let running_sources = Manager::streams_information()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|status| {
+            status
+                .running
+                .then_some(status.video_and_stream.video_source)
+        })
+        .collect::<Vec<VideoSourceType>>();
+
+    let mut candidates = video_source::cameras_available();
+    candidates.retain(|candidate| !running_sources.contains(candidate));
+
+    candidates
+}
+
+/// Tries to start the pending auto-start streams periodically after startup, giving up after the configured attempts,
+/// and whenever a video device is connected
+#[instrument(level = "debug")]
+async fn pending_streams_task() {
+    let mut device_events = crate::video::device_monitor::subscribe();
+
+    let retry_attempts = crate::cli::manager::startup_retry_attempts();
+    let mut retry_interval = tokio::time::interval(crate::cli::manager::startup_retry_interval());
+    retry_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick completes immediately, right after the streams were first started
+    retry_interval.tick().await;
+    let mut attempts = 0;
+
+    loop {
+        tokio::select! {
+            event = device_events.recv() => match event {
+                Ok(crate::video::device_monitor::DeviceEvent::Added(device)) => {
+                    debug!("Device {device:?} was connected, retrying pending streams");
+                }
+                Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            },
+            _ = retry_interval.tick(), if attempts < retry_attempts => {
+                attempts += 1;
+                debug!("Retrying pending streams, attempt {attempts} of {retry_attempts}");
+            }
+        }
+
+        retry_pending_streams().await;
+
+        if attempts == retry_attempts && retry_attempts > 0 {
+            give_up_pending_streams(attempts).await;
+            // Any stream pending from now on only waits for its device to be connected
+            attempts += 1;
+        }
+    }
+}
+
+/// Tries to start the pending auto-start streams, picking them a free device
+async fn retry_pending_streams() {
+    let mut streams = MANAGER.write().await.take_auto_start_pending_streams();
+    if streams.is_empty() {
+        return;
+    }
+
+    // Discards any source from running streams, as their devices are in use
+    let mut candidates = free_cameras().await;
+
+    update_devices(&mut streams, &mut candidates, false);
+
+    // Each failure is already logged, and the stream is retried when its device shows up again
+    let _ = start_streams(streams).await;
+}
+
+/// Stops retrying the auto-start streams still without their device, keeping them configured as enabled idle streams
+/// with an error, so they are listed with the others, and start once their device is connected
+async fn give_up_pending_streams(attempts: u32) {
+    let stream_ids = {
+        let mut manager = MANAGER.write().await;
+
+        let stream_ids = manager
+            .take_auto_start_pending_streams()
+            .into_iter()
+            .map(|stream| {
+                let stream_id = Manager::generate_uuid();
+                error!(
+                    "Stream {:?} gave up waiting for its device after {attempts} attempts",
+                    stream.name
+                );
+                manager.idle_errors.insert(
+                    stream_id,
+                    format!("The device wasn't found after {attempts} attempts at startup"),
+                );
+                manager.idle_streams.insert(stream_id, stream);
+
+                stream_id
+            })
+            .collect::<Vec<uuid::Uuid>>();
+        if !stream_ids.is_empty() {
+            manager.update_settings().await;
+        }
+
+        stream_ids
+    };
+
+    for stream_id in stream_ids {
+        tokio::spawn(idle_stream_task(stream_id));
+    }
+}
+
+#[instrument(level = "debug")]
+pub fn update_devices(
+    streams: &mut Vec<VideoAndStreamInformation>,
+    candidates: &mut Vec<VideoSourceType>,
+    verbose: bool,
+) {
+    for stream in streams {
+        let VideoSourceType::Local(source) = &mut stream.video_source else {
+            continue;
+        };
+        let CaptureConfiguration::Video(capture_configuration) =
+            &stream.stream_information.configuration
+        else {
+            continue;
+        };
+
+        match source.try_identify_device(capture_configuration, candidates) {
+            Ok(Some(candidate_source_string)) => {
+                let Some((idx, candidate)) =
+                    candidates.iter().enumerate().find_map(|(idx, candidate)| {
+                        (candidate.inner().source_string() == candidate_source_string)
+                            .then_some((idx, candidate))
+                    })
+                else {
+                    error!("CRITICAL: The device was identified as {candidate_source_string:?}, but it is not the candidates list"); // This shouldn't ever be reachable, otherwise the above logic is flawed
+                    continue;
+                };
+
+                let VideoSourceType::Local(camera) = candidate else {
+                    error!("CRITICAL: The device was identified as {candidate_source_string:?}, but it is not a Local device"); // This shouldn't ever be reachable, otherwise the above logic is flawed
+                    continue;
+                };
+                *source = camera.clone();
+                // Only remove the candidate from the list after using it, avoiding the wrong but possible logic from the CRITICAL erros branches above, the additional cost is this clone
+                candidates.remove(idx);
+            }
+            Err(reason) => {
+                // Invalidate the device
+                source.device_path = "".into();
+                if verbose {
+                    warn!("Device {source:?} was invalidated. Reason: {reason:?}");
+                };
+            }
+            _ => (),
+        }
+    }
+}
+
+#[instrument(level = "debug")]
+pub async fn streams() -> Result<Vec<StreamStatus>> {
+    let mut streams = Manager::streams_information().await?;
+
+    // Idle streams are listed too, so they can be enabled through their id
+    let manager = MANAGER.read().await;
+    streams.extend(
+        manager
+            .idle_streams
+            .iter()
+            .map(|(stream_id, video_and_stream_information)| StreamStatus {
+                id: *stream_id,
+                running: false,
+                video_and_stream: video_and_stream_information.clone(),
+                restart_count: 0,
+                last_error: manager.idle_errors.get(stream_id).cloned(),
+                srt_urls: vec![],
+                clients: 0,
+                max_clients: video_and_stream_information
+                    .stream_information
+                    .max_clients(),
+                endpoints: endpoints::describe(stream_id, video_and_stream_information, &[]),
+                last_heartbeat: None,
+                disabled: !video_and_stream_information.stream_information.enabled(),
+                format_fallback: None,
+                dropped_frames: Default::default(),
+                cpu_seconds: 0.0,
+                queued_bytes: 0,
+                mavlink_component_id: None,
+            }),
+    );
+
+    Ok(streams)
+}
+
+/// Grabs a single JPEG frame from the given running stream
+#[instrument(level = "debug")]
+pub async fn snapshot(stream_id: &uuid::Uuid) -> Result<Vec<u8>> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.snapshot().await
+}
+
+/// The SDP of the given stream's UDP endpoints
+#[instrument(level = "debug")]
+pub async fn sdp(stream_id: &uuid::Uuid) -> Result<gst_sdp::SDPMessage> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.sdp().await
+}
+
+/// Starts encoding the given stream as JPEG frames for a new MJPEG client
+#[instrument(level = "debug")]
+pub async fn add_mjpeg_client(
+    stream_id: &uuid::Uuid,
+    max_framerate: u32,
+) -> Result<(uuid::Uuid, tokio::sync::mpsc::Receiver<Vec<u8>>)> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.add_mjpeg_client(max_framerate).await
+}
+
+/// Stops encoding the JPEG frames of a MJPEG client that is gone
+#[instrument(level = "debug")]
+pub async fn remove_mjpeg_client(stream_id: &uuid::Uuid, sink_id: &uuid::Uuid) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.remove_mjpeg_client(sink_id).await
+}
+
+/// Changes the jitterbuffer latency of the given stream, in milliseconds
+#[instrument(level = "debug")]
+pub async fn set_latency(stream_id: &uuid::Uuid, latency_ms: u32) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_latency(latency_ms).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// The bytes sent by the given stream through all of its sinks
+#[instrument(level = "debug")]
+pub async fn bandwidth(stream_id: &uuid::Uuid) -> Result<BandwidthStatus> {
+    let manager = MANAGER.read().await;
+
+    if !manager.streams.contains_key(stream_id) && !manager.idle_streams.contains_key(stream_id) {
+        return Err(anyhow!("Stream {stream_id:?} not found"));
+    }
+
+    Ok(bandwidth::get(stream_id))
+}
+
+/// Restarts counting the bytes sent by the given stream since reset, keeping its total
+#[instrument(level = "debug")]
+pub async fn reset_bandwidth(stream_id: &uuid::Uuid) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    if !manager.streams.contains_key(stream_id) && !manager.idle_streams.contains_key(stream_id) {
+        return Err(anyhow!("Stream {stream_id:?} not found"));
+    }
+
+    bandwidth::reset(stream_id);
+
+    Ok(())
+}
+
+/// Tears down the given stream's pipeline and builds it again from its current configuration, keeping its id so
+/// clients can reconnect to the same URLs. If it can't be rebuilt, the stream is kept as a pending stream
+#[instrument(level = "debug")]
+pub async fn restart_stream(stream_id: &uuid::Uuid) -> Result<()> {
+    let mut manager = MANAGER.write().await;
+
+    let video_and_stream_information = {
+        let stream = manager
+            .streams
+            .get(stream_id)
+            .context(format!("Stream {stream_id:?} not found"))?;
+        let state_guard = stream.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        state.video_and_stream_information.clone()
+    };
+
+    info!("Restarting stream {stream_id}...");
+
+    // The old pipeline has to be gone before the new one takes its device, ports and RTSP path
+    if let Some(stream) = manager.streams.remove(stream_id) {
+        stream.stop("The stream is restarting").await;
+    }
+
+    let stream = match Stream::try_new_with_id(&video_and_stream_information, *stream_id).await {
+        Ok(stream) => stream,
+        Err(error) => {
+            manager.pending_streams.push(video_and_stream_information);
+            manager.update_settings().await;
+            publish_event(*stream_id, PipelineEventKind::StreamRemoved);
+
+            return Err(error.context(format!(
+                "Failed restarting stream {stream_id:?}, it was kept as a pending stream"
+            )));
+        }
+    };
+    manager.streams.insert(*stream_id, stream);
+
+    info!("Stream {stream_id} successfully restarted!");
+
+    Ok(())
+}
+
+/// Stops the given stream, releasing its device and ports, but keeping its configuration so it can be enabled again
+#[instrument(level = "debug")]
+pub async fn disable_stream(stream_id: &uuid::Uuid) -> Result<()> {
+    let mut manager = MANAGER.write().await;
+
+    if let Some(stream) = manager.streams.remove(stream_id) {
+        let video_and_stream_information = stream
+            .state
+            .read()
+            .await
+            .as_ref()
+            .map(|state| state.video_and_stream_information.clone());
+
+        stream.stop("The stream was disabled").await;
+
+        manager.idle_streams.insert(
+            *stream_id,
+            video_and_stream_information.context("Stream without State")?,
+        );
+        publish_event(*stream_id, PipelineEventKind::StreamRemoved);
+    }
+
+    // An enabled idle stream stops waiting for its device
+    manager
+        .idle_streams
+        .get_mut(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?
+        .stream_information
+        .extended_configuration
+        .get_or_insert_with(Default::default)
+        .enabled = Some(false);
+    manager.idle_errors.remove(stream_id);
+    manager.update_settings().await;
+
+    info!("Stream {stream_id} disabled");
+
+    Ok(())
+}
+
+/// Starts the given disabled stream again. If its device is absent or in use, it waits for a device to be connected
+#[instrument(level = "debug")]
+pub async fn enable_stream(stream_id: &uuid::Uuid) -> Result<()> {
+    {
+        let mut manager = MANAGER.write().await;
+
+        if manager.streams.contains_key(stream_id) {
+            return Ok(());
+        }
+
+        let video_and_stream_information = manager
+            .idle_streams
+            .get_mut(stream_id)
+            .context(format!("Stream {stream_id:?} not found"))?;
+        if video_and_stream_information.stream_information.enabled() {
+            // Already waiting for its device
+            return Ok(());
+        }
+        video_and_stream_information
+            .stream_information
+            .extended_configuration
+            .get_or_insert_with(Default::default)
+            .enabled = Some(true);
+        manager.update_settings().await;
+    }
+
+    if let Err(error) = start_idle_stream(stream_id).await {
+        warn!("Stream {stream_id} is waiting for its device to be connected: {error:?}");
+        tokio::spawn(idle_stream_task(*stream_id));
+    }
+
+    Ok(())
+}
+
+/// Starts the given idle stream if it is enabled, picking a free device for it
+async fn start_idle_stream(stream_id: &uuid::Uuid) -> Result<()> {
+    let mut candidates = free_cameras().await;
+
+    let mut manager = MANAGER.write().await;
+
+    let video_and_stream_information = manager
+        .idle_streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} is not idle"))?
+        .clone();
+    if !video_and_stream_information.stream_information.enabled() {
+        return Err(anyhow!("Stream {stream_id:?} is disabled"));
+    }
+
+    let mut streams = vec![video_and_stream_information];
+    update_devices(&mut streams, &mut candidates, false);
+    let mut video_and_stream_information = streams.remove(0);
+    if !video_and_stream_information.video_source.inner().is_valid() {
+        return Err(anyhow!("No free device for stream {stream_id:?}"));
+    }
+    // The free device might not capture the same formats as the one the stream had before
+    let format_fallback = formats::negotiate(&mut video_and_stream_information)?;
+
+    let mut stream = Stream::try_new_with_id(&video_and_stream_information, *stream_id).await?;
+    stream.set_format_fallback(format_fallback);
+    manager.idle_streams.remove(stream_id);
+    manager.idle_errors.remove(stream_id);
+    manager.streams.insert(*stream_id, stream);
+    manager.update_settings().await;
+
+    info!("Stream {stream_id} enabled");
+
+    publish_event(*stream_id, PipelineEventKind::StreamCreated);
+
+    Ok(())
+}
+
+/// Retries starting the given enabled stream whenever a video device is connected, until it starts or is disabled
+#[instrument(level = "debug")]
+async fn idle_stream_task(stream_id: uuid::Uuid) {
+    let mut device_events = crate::video::device_monitor::subscribe();
+
+    loop {
+        match device_events.recv().await {
+            Ok(crate::video::device_monitor::DeviceEvent::Added(device)) => {
+                debug!("Device {device:?} was connected, retrying stream {stream_id}");
+            }
+            Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+        }
+
+        let waiting = MANAGER
+            .read()
+            .await
+            .idle_streams
+            .get(&stream_id)
+            .is_some_and(|stream| stream.stream_information.enabled());
+        if !waiting {
+            break;
+        }
+
+        match start_idle_stream(&stream_id).await {
+            Ok(()) => break,
+            Err(error) => debug!("Stream {stream_id} is still waiting for its device: {error:?}"),
+        }
+    }
+}
+
+/// Removes the given stream
+#[instrument(level = "debug")]
+pub async fn remove_stream(stream_id: &uuid::Uuid) -> Result<()> {
+    Manager::remove_stream(stream_id).await
+}
+
+/// Changes the name and/or the tags of the given stream
+#[instrument(level = "debug")]
+pub async fn set_metadata(
+    stream_id: &uuid::Uuid,
+    name: Option<String>,
+    tags: Option<Vec<String>>,
+) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_metadata(name, tags).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Changes the keyframe interval of the given stream, in frames
+#[instrument(level = "debug")]
+pub async fn set_keyframe_interval(stream_id: &uuid::Uuid, frames: u32) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_keyframe_interval(frames).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Changes the MAVLink camera mode of the given stream
+#[instrument(level = "debug")]
+pub async fn set_camera_mode(stream_id: &uuid::Uuid, mode: CameraMode) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_camera_mode(mode).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Changes the rate control of the given stream
+#[instrument(level = "debug")]
+pub async fn set_rate_control(
+    stream_id: &uuid::Uuid,
+    rate_control: RateControlConfiguration,
+) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_rate_control(rate_control).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Changes the H264 profile and level of the given stream
+#[instrument(level = "debug")]
+pub async fn set_h264_profile(
+    stream_id: &uuid::Uuid,
+    h264_profile: H264ProfileConfiguration,
+) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_h264_profile(h264_profile).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Changes the resolution streamed by the given stream
+#[instrument(level = "debug")]
+pub async fn set_output_resolution(
+    stream_id: &uuid::Uuid,
+    output_resolution: Option<OutputResolutionConfiguration>,
+) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_output_resolution(output_resolution).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Changes the maximum framerate streamed by the given stream
+#[instrument(level = "debug")]
+pub async fn set_max_framerate(stream_id: &uuid::Uuid, max_framerate: Option<u32>) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_max_framerate(max_framerate).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Changes how the given stream recovers from changes of the host's network
+#[instrument(level = "debug")]
+pub async fn set_reconnection(
+    stream_id: &uuid::Uuid,
+    reconnection: ReconnectionConfiguration,
+) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_reconnection(reconnection).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// The digital zoom of the given stream
+#[instrument(level = "debug")]
+pub async fn zoom(stream_id: &uuid::Uuid) -> Result<Arc<DigitalZoom>> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.zoom().await
+}
+
+/// The text overlay of the given stream
+#[instrument(level = "debug")]
+pub async fn overlay(stream_id: &uuid::Uuid) -> Result<OverlayConfiguration> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.overlay().await
+}
+
+/// Changes the text overlay of the given stream
+#[instrument(level = "debug")]
+pub async fn set_overlay(
+    stream_id: &uuid::Uuid,
+    configuration: OverlayConfiguration,
+) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_overlay(configuration).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// The caps negotiated by each element of the given stream's pipeline
+#[instrument(level = "debug")]
+pub async fn caps(stream_id: &uuid::Uuid) -> Result<Vec<ElementCaps>> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.caps().await
+}
+
+/// The audio of the given stream
+#[instrument(level = "debug")]
+pub async fn audio(stream_id: &uuid::Uuid) -> Result<AudioStatus> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.audio().await
+}
+
+/// Changes the audio of the given stream
+#[instrument(level = "debug")]
+pub async fn set_audio(stream_id: &uuid::Uuid, audio: Option<AudioConfiguration>) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_audio(audio).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// The clock and the latency of the given stream
+#[instrument(level = "debug")]
+pub async fn clock(stream_id: &uuid::Uuid) -> Result<ClockStatus> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.clock().await
+}
+
+/// Changes the clock of the given stream
+#[instrument(level = "debug")]
+pub async fn set_clock(stream_id: &uuid::Uuid, clock: Option<ClockConfiguration>) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.set_clock(clock).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Asks the encoder of the given stream for a keyframe
+#[instrument(level = "debug")]
+pub async fn force_keyframe(stream_id: &uuid::Uuid) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.force_keyframe().await
+}
+
+/// Finds the stream served by RTSP at the given path, like "/video_stream_0"
+#[instrument(level = "debug")]
+pub async fn stream_id_from_rtsp_path(path: &str) -> Option<uuid::Uuid> {
+    let manager = MANAGER.read().await;
+
+    for (stream_id, stream) in manager.streams.iter() {
+        let is_served_at_path = stream.state.read().await.as_ref().is_some_and(|state| {
+            state
+                .pipeline
+                .inner_state_as_ref()
+                .sinks
+                .values()
+                .any(|sink| matches!(sink, Sink::Rtsp(sink) if sink.path() == path))
+        });
+
+        if is_served_at_path {
+            return Some(*stream_id);
+        }
+    }
+
+    None
+}
+
+/// Adds an UDP destination to the given stream
+#[instrument(level = "debug")]
+pub async fn add_udp_destination(stream_id: &uuid::Uuid, destination: &url::Url) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.add_udp_destination(destination).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Removes an UDP destination from the given stream
+#[instrument(level = "debug")]
+pub async fn remove_udp_destination(stream_id: &uuid::Uuid, destination: &url::Url) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.remove_udp_destination(destination).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
+}
+
+/// Starts recording the given running stream, returning the location pattern of the recorded files
+#[instrument(level = "debug")]
+pub async fn start_recording(
+    stream_id: &uuid::Uuid,
+    settings: &RecordingSettings,
+) -> Result<String> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.start_recording(settings).await
+}
 
-        let video_and_stream_informations =
-            futures::StreamExt::filter_map(streams, |stream| async move {
-                let stream_guard = stream.state.read().await;
+/// Records a clip of the given running stream, returning its file once finalized
+#[instrument(level = "debug")]
+pub async fn record_clip(stream_id: &uuid::Uuid, duration: std::time::Duration) -> Result<String> {
+    let manager = MANAGER.read().await;
 
-                stream_guard
-                    .as_ref()
-                    .map(|state| state.video_and_stream_information.clone())
-            })
-            .collect::<Vec<VideoAndStreamInformation>>()
-            .await;
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
 
-        settings::manager::set_streams(video_and_stream_informations.as_slice());
-    }
+    stream.record_clip(duration).await
 }
 
+/// Changes how much of the given stream's video is kept in memory for its clips
 #[instrument(level = "debug")]
-pub fn init() {
-    debug!("Starting video stream service.");
+pub async fn set_clip_pre_roll(stream_id: &uuid::Uuid, pre_roll_secs: u32) -> Result<()> {
+    let manager = MANAGER.read().await;
 
-    if let Err(error) = gst::init() {
-        error!("Error! {error}");
-    };
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
 
-    config_gst_plugins();
+    stream.set_clip_pre_roll(pre_roll_secs).await?;
+
+    manager.update_settings().await;
+
+    Ok(())
 }
 
+/// Stops recording the given stream, returning after its file is finalized
 #[instrument(level = "debug")]
-fn config_gst_plugins() {
-    let plugins_config = crate::cli::manager::gst_feature_rank();
+pub async fn stop_recording(stream_id: &uuid::Uuid) -> Result<()> {
+    let manager = MANAGER.read().await;
 
-    for config in plugins_config {
-        match crate::stream::gst::utils::set_plugin_rank(config.name.as_str(), config.rank) {
-            Ok(_) => info!(
-                "Gstreamer Plugin {name:?} configured with rank {rank:?}.",
-                name = config.name,
-                rank = config.rank,
-            ),
-            Err(error) => error!("Error when trying to configure plugin {name:?} rank to {rank:?}. Reason: {error:?}", name = config.name, rank = config.rank, error=error.to_string()),
-        }
-    }
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.stop_recording().await
 }
 
-pub async fn remove_all_streams() -> Result<()> {
-    let keys = {
-        let mut ids = vec![];
+/// Changes the STUN and TURN servers of the given stream, or goes back to the global ones when none is given
+#[instrument(level = "debug")]
+pub async fn set_ice_servers(
+    stream_id: &uuid::Uuid,
+    ice_servers: Option<Vec<IceServer>>,
+) -> Result<()> {
+    let manager = MANAGER.read().await;
 
-        MANAGER.read().await.streams.keys().for_each(|id| {
-            ids.push(*id);
-        });
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
 
-        ids
-    };
+    stream.set_ice_servers(ice_servers).await?;
 
-    for stream_id in keys.iter() {
-        if let Err(error) = Manager::remove_stream(stream_id).await {
-            warn!("Failed removing stream {stream_id:?}: {error:?}")
-        }
-    }
+    manager.update_settings().await;
 
     Ok(())
 }
 
+/// If one more client can watch the given stream
 #[instrument(level = "debug")]
-pub async fn start_default() -> Result<()> {
-    // Get streams from default settings, this needs to be done first because
-    // remove_all_streams will modify the settings as its using the stream manager
-    // to remove the streams, and the stream manager will save the state after
-    // each removal in the settings
-    let mut streams = settings::manager::streams();
+pub async fn has_free_client_slot(stream_id: &uuid::Uuid) -> bool {
+    let manager = MANAGER.read().await;
 
-    // Gently remove all streams as we are going to replace the entire list below
-    remove_all_streams().await?;
+    let Some(stream) = manager.streams.get(stream_id) else {
+        return false;
+    };
 
-    // Update all local video sources to make sure that they are available
-    let mut candidates = video_source::cameras_available();
-    update_devices(&mut streams, &mut candidates, true);
+    let state_guard = stream.state.read().await;
 
-    // Remove all invalid video_sources
-    let streams: Vec<VideoAndStreamInformation> = streams
-        .into_iter()
-        .filter(|stream| stream.video_source.inner().is_valid())
-        .collect();
+    state_guard
+        .as_ref()
+        .is_some_and(|state| state.has_free_client_slot())
+}
 
-    debug!("Streams: {streams:#?}");
+/// If every stream is limited by `max_clients` and has no free slot left, so no new client can watch anything
+#[instrument(level = "debug")]
+pub async fn all_streams_full() -> bool {
+    let manager = MANAGER.read().await;
 
-    for stream in streams {
-        if let Err(error) = add_stream_and_start(stream).await {
-            error!("Not possible to start stream: {error:?}");
-        };
+    if manager.streams.is_empty() {
+        return false;
     }
 
-    Ok(())
+    for stream in manager.streams.values() {
+        let state_guard = stream.state.read().await;
+        if state_guard
+            .as_ref()
+            .is_some_and(|state| state.has_free_client_slot())
+        {
+            return false;
+        }
+    }
+
+    true
 }
 
+/// The WebRTC and RTSP clients watching the given stream
 #[instrument(level = "debug")]
-pub fn update_devices(
-    streams: &mut Vec<VideoAndStreamInformation>,
-    candidates: &mut Vec<VideoSourceType>,
-    verbose: bool,
-) {
-    for stream in streams {
-        let VideoSourceType::Local(source) = &mut stream.video_source else {
-            continue;
-        };
-        let CaptureConfiguration::Video(capture_configuration) =
-            &stream.stream_information.configuration
-        else {
-            continue;
-        };
+pub async fn clients(stream_id: &uuid::Uuid) -> Result<Vec<ClientStatus>> {
+    let manager = MANAGER.read().await;
 
-        match source.try_identify_device(capture_configuration, candidates) {
-            Ok(Some(candidate_source_string)) => {
-                let Some((idx, candidate)) =
-                    candidates.iter().enumerate().find_map(|(idx, candidate)| {
-                        (candidate.inner().source_string() == candidate_source_string)
-                            .then_some((idx, candidate))
-                    })
-                else {
-                    error!("CRITICAL: The device was identified as {candidate_source_string:?}, but it is not the candidates list"); // This shouldn't ever be reachable, otherwise the above logic is flawed
-                    continue;
-                };
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
 
-                let VideoSourceType::Local(camera) = candidate else {
-                    error!("CRITICAL: The device was identified as {candidate_source_string:?}, but it is not a Local device"); // This shouldn't ever be reachable, otherwise the above logic is flawed
-                    continue;
-                };
-                *source = camera.clone();
-                // Only remove the candidate from the list after using it, avoiding the wrong but possible logic from the CRITICAL erros branches above, the additional cost is this clone
-                candidates.remove(idx);
-            }
-            Err(reason) => {
-                // Invalidate the device
-                source.device_path = "".into();
-                if verbose {
-                    warn!("Device {source:?} was invalidated. Reason: {reason:?}");
-                };
-            }
-            _ => (),
-        }
-    }
+    stream.clients(stream_id).await
 }
 
+/// Disconnects a single client from the given stream, keeping the other clients and the encoding untouched
 #[instrument(level = "debug")]
-pub async fn streams() -> Result<Vec<StreamStatus>> {
-    Manager::streams_information().await
+pub async fn disconnect_client(stream_id: &uuid::Uuid, client_id: &uuid::Uuid) -> Result<()> {
+    let manager = MANAGER.read().await;
+
+    let stream = manager
+        .streams
+        .get(stream_id)
+        .context(format!("Stream {stream_id:?} not found"))?;
+
+    stream.disconnect_client(stream_id, client_id).await
+}
+
+#[instrument(level = "debug")]
+pub async fn webrtc_sessions() -> Vec<WebRTCSessionStatus> {
+    Manager::webrtc_sessions_information().await
+}
+
+#[instrument(level = "debug")]
+pub async fn metrics() -> Vec<StreamMetrics> {
+    Manager::streams_metrics().await
 }
 
 #[instrument(level = "debug")]
@@ -337,34 +1383,87 @@ pub async fn get_jpeg_thumbnail_from_source(
     }
 }
 
+/// Fails if the given stream would conflict with any existing one, like by using the same device or endpoint
 #[instrument(level = "debug")]
-pub async fn add_stream_and_start(
-    video_and_stream_information: VideoAndStreamInformation,
+pub async fn check_conflicts(
+    video_and_stream_information: &VideoAndStreamInformation,
 ) -> Result<()> {
-    {
-        let manager = MANAGER.read().await;
-        for stream in manager.streams.values() {
-            let state_guard = stream.state.read().await;
+    let manager = MANAGER.read().await;
+    for (stream_id, stream) in &manager.streams {
+        let state_guard = stream.state.read().await;
 
-            let state_ref = state_guard.as_ref().context("Stream without State")?;
+        let state_ref = state_guard.as_ref().context("Stream without State")?;
 
-            state_ref
-                .video_and_stream_information
-                .conflicts_with(&video_and_stream_information)?;
-        }
+        state_ref
+            .video_and_stream_information
+            .conflicts_with(video_and_stream_information)
+            .with_context(|| format!("Conflict with stream {stream_id}"))?;
     }
 
-    let stream = Stream::try_new(&video_and_stream_information).await?;
+    Ok(())
+}
+
+#[instrument(level = "debug")]
+pub async fn add_stream_and_start(
+    mut video_and_stream_information: VideoAndStreamInformation,
+) -> Result<()> {
+    check_conflicts(&video_and_stream_information).await?;
+    let format_fallback = formats::negotiate(&mut video_and_stream_information)?;
+
+    let mut stream = Stream::try_new(&video_and_stream_information).await?;
+    stream.set_format_fallback(format_fallback);
+    Manager::add_stream(stream).await?;
+
+    Ok(())
+}
+
+/// Like [`add_stream_and_start`], but only keeps the stream if its pipeline manages to start,
+/// so the caller gets the actual reason (see [`crate::stream::pipeline::runner::PipelineError`]) when it doesn't
+#[instrument(level = "debug")]
+pub async fn create_stream(
+    mut video_and_stream_information: VideoAndStreamInformation,
+) -> Result<()> {
+    check_conflicts(&video_and_stream_information).await?;
+    let format_fallback = formats::negotiate(&mut video_and_stream_information)?;
+
+    let mut stream = Stream::try_new(&video_and_stream_information).await?;
+    stream.set_format_fallback(format_fallback);
+    stream
+        .wait_for_start(std::time::Duration::from_secs(10))
+        .await?;
     Manager::add_stream(stream).await?;
 
     Ok(())
 }
 
+/// Stops all streams in parallel, ending their clients' sessions and draining their pipelines so recordings are
+/// finalized. The streams are kept in the settings, so they are back on the next start
+#[instrument(level = "debug")]
+pub async fn shutdown(timeout: std::time::Duration) {
+    let streams = std::mem::take(&mut MANAGER.write().await.streams);
+
+    info!("Shutting down {} streams...", streams.len());
+
+    let shutdowns = streams.values().map(|stream| stream.shutdown());
+    if tokio::time::timeout(timeout, futures::future::join_all(shutdowns))
+        .await
+        .is_err()
+    {
+        warn!("Timed out after {timeout:?} waiting for the streams to shut down");
+    }
+
+    for stream_id in streams.keys() {
+        publish_event(*stream_id, PipelineEventKind::StreamRemoved);
+    }
+
+    info!("Streams shut down");
+}
+
 #[instrument(level = "debug")]
 async fn get_stream_id_from_name(stream_name: &str) -> Result<uuid::Uuid> {
     let manager = MANAGER.read().await;
 
-    let stream_id = futures::stream::iter(&manager.streams)
+    let mut stream_ids = futures::stream::iter(&manager.streams)
         .filter_map(|(id, stream)| {
             let future = async move {
                 let state_guard = stream.state.read().await;
@@ -379,17 +1478,53 @@ async fn get_stream_id_from_name(stream_name: &str) -> Result<uuid::Uuid> {
             };
             Box::pin(future)
         })
-        .next()
-        .await
-        .context(format!("Stream named {stream_name:?} not found"));
+        .collect::<Vec<uuid::Uuid>>()
+        .await;
+    stream_ids.extend(
+        manager
+            .idle_streams
+            .iter()
+            .filter(|(_, stream)| stream.name == stream_name)
+            .map(|(id, _)| *id),
+    );
 
     drop(manager);
 
-    stream_id
+    // Names don't need to be unique, only ids are
+    match stream_ids.as_slice() {
+        [] => Err(anyhow!("Stream named {stream_name:?} not found")),
+        [stream_id] => Ok(*stream_id),
+        _ => Err(anyhow!(
+            "There is more than one stream named {stream_name:?}, refer to it by its id instead"
+        )),
+    }
 }
 
 #[instrument(level = "debug")]
 pub async fn remove_stream_by_name(stream_name: &str) -> Result<()> {
+    {
+        let mut manager = MANAGER.write().await;
+
+        let pending_streams_count = manager
+            .pending_streams
+            .iter()
+            .filter(|stream| stream.name == stream_name)
+            .count();
+        if pending_streams_count > 1 {
+            return Err(anyhow!(
+                "There is more than one stream named {stream_name:?}, refer to it by its id instead"
+            ));
+        }
+        if pending_streams_count == 1 {
+            manager
+                .pending_streams
+                .retain(|stream| stream.name != stream_name);
+            manager.update_settings().await;
+            info!("Pending stream {stream_name:?} successfully removed!");
+            return Ok(());
+        }
+    }
+
     let stream_id = get_stream_id_from_name(stream_name).await?;
 
     Manager::remove_stream(&stream_id).await?;
@@ -402,6 +1537,8 @@ impl Manager {
     pub async fn add_session(
         bind: &webrtc::signalling_protocol::BindOffer,
         sender: tokio::sync::mpsc::UnboundedSender<Result<webrtc::signalling_protocol::Message>>,
+        remote_address: std::net::SocketAddr,
+        trickle_ice: bool,
     ) -> Result<webrtc::signalling_protocol::SessionId> {
         let mut manager = MANAGER.write().await;
 
@@ -419,16 +1556,48 @@ impl Manager {
             session_id,
         };
 
-        let sink = Sink::WebRTC(WebRTCSink::try_new(bind, sender)?);
-
         let mut state_guard = stream.state.write().await;
 
         let state_mut = state_guard.as_mut().context("Stream without State")?;
 
+        if !state_mut.has_free_client_slot() {
+            return Err(anyhow!(
+                "Stream {producer_id:?} reached its maximum number of clients"
+            ));
+        }
+
+        let stream_information = &state_mut.video_and_stream_information.stream_information;
+        let ice_servers = webrtc::ice_servers::for_stream(stream_information);
+        let h264_profile = stream_information.h264_profile().cloned();
+        let codecs = stream_information
+            .webrtc_codecs()
+            .map(<[_]>::to_vec)
+            .unwrap_or_default();
+        let native_codec = webrtc::codecs::native(stream_information);
+
+        let sink = Sink::WebRTC(WebRTCSink::try_new(
+            bind,
+            sender,
+            remote_address,
+            &ice_servers,
+            trickle_ice,
+            h264_profile,
+            codecs,
+            native_codec,
+        )?);
+
         state_mut.pipeline.add_sink(sink)?;
 
         debug!("WebRTC session created: {session_id:?}");
 
+        publish_event(
+            producer_id,
+            PipelineEventKind::ClientConnected {
+                client: session_id.to_string(),
+                protocol: "webrtc".to_string(),
+            },
+        );
+
         Ok(session_id)
     }
 
@@ -455,9 +1624,59 @@ impl Manager {
 
         info!("Session {:?} successfully removed!", bind.session_id);
 
+        publish_event(
+            bind.producer_id,
+            PipelineEventKind::ClientDisconnected {
+                client: bind.session_id.to_string(),
+                protocol: "webrtc".to_string(),
+            },
+        );
+
         Ok(())
     }
 
+    /// Removes the WebRTC sessions whose signalling connection is gone, like when the consumer dropped abruptly
+    #[instrument(level = "debug")]
+    pub async fn remove_orphan_sessions() {
+        let binds: Vec<BindAnswer> = {
+            let manager = MANAGER.read().await;
+
+            futures::stream::iter(manager.streams.values())
+                .filter_map(|stream| async move {
+                    let state_guard = stream.state.read().await;
+                    let state_ref = state_guard.as_ref()?;
+
+                    let binds: Vec<BindAnswer> = state_ref
+                        .pipeline
+                        .inner_state_as_ref()
+                        .sinks
+                        .values()
+                        .filter_map(|sink| match sink {
+                            Sink::WebRTC(webrtc_sink) if webrtc_sink.sender.is_closed() => {
+                                Some(webrtc_sink.bind.clone())
+                            }
+                            _ => None,
+                        })
+                        .collect();
+
+                    Some(binds)
+                })
+                .collect::<Vec<Vec<BindAnswer>>>()
+                .await
+                .into_iter()
+                .flatten()
+                .collect()
+        };
+
+        for bind in binds {
+            if let Err(error) =
+                Self::remove_session(&bind, "Signalling connection closed".to_string()).await
+            {
+                warn!("Failed removing orphan session {bind:?}: {error:?}");
+            }
+        }
+    }
+
     #[instrument(level = "debug")]
     pub async fn handle_sdp(
         bind: &webrtc::signalling_protocol::BindAnswer,
@@ -557,6 +1776,8 @@ impl Manager {
 
         info!("Stream {stream_id} successfully added!");
 
+        publish_event(stream_id, PipelineEventKind::StreamCreated);
+
         Ok(())
     }
 
@@ -564,18 +1785,33 @@ impl Manager {
     pub async fn remove_stream(stream_id: &webrtc::signalling_protocol::PeerId) -> Result<()> {
         let mut manager = MANAGER.write().await;
 
+        if manager.idle_streams.remove(stream_id).is_some() {
+            manager.idle_errors.remove(stream_id);
+            manager.update_settings().await;
+            bandwidth::remove(stream_id);
+            info!("Stream {stream_id} successfully removed!");
+            return Ok(());
+        }
+
         if !manager.streams.contains_key(stream_id) {
             return Err(anyhow!("Already removed"));
         }
 
-        manager
+        let stream = manager
             .streams
             .remove(stream_id)
             .context(format!("Stream {stream_id:?} not found"))?;
         manager.update_settings().await;
+        bandwidth::remove(stream_id);
+
+        // Draining the pipeline can take up to its EOS timeout, so don't hold the manager meanwhile
+        drop(manager);
+        stream.stop("The stream was removed").await;
 
         info!("Stream {stream_id} successfully removed!");
 
+        publish_event(*stream_id, PipelineEventKind::StreamRemoved);
+
         Ok(())
     }
 
@@ -589,10 +1825,63 @@ impl Manager {
 
                 let state_ref = state_guard.as_ref()?;
 
+                let srt_urls: Vec<url::Url> = state_ref
+                    .pipeline
+                    .inner_state_as_ref()
+                    .sinks
+                    .values()
+                    .filter_map(|sink| match sink {
+                        Sink::Srt(srt_sink) => srt_sink.client_url().ok(),
+                        _ => None,
+                    })
+                    .collect();
+
                 Some(StreamStatus {
                     id: state_ref.pipeline_id,
                     running: state_ref.pipeline.is_running(),
                     video_and_stream: state_ref.video_and_stream_information.clone(),
+                    restart_count: stream.restart_count(),
+                    last_error: stream.last_error(),
+                    endpoints: endpoints::describe(
+                        &state_ref.pipeline_id,
+                        &state_ref.video_and_stream_information,
+                        &srt_urls,
+                    ),
+                    srt_urls,
+                    last_heartbeat: state_ref
+                        .pipeline
+                        .inner_state_as_ref()
+                        .pipeline_runner
+                        .last_heartbeat()
+                        .map(|last_heartbeat| {
+                            chrono::Utc::now().timestamp_millis()
+                                - last_heartbeat.elapsed().as_millis() as i64
+                        }),
+                    clients: state_ref.client_count(),
+                    max_clients: state_ref
+                        .video_and_stream_information
+                        .stream_information
+                        .max_clients(),
+                    disabled: false,
+                    format_fallback: stream.format_fallback(),
+                    dropped_frames: state_ref
+                        .pipeline
+                        .inner_state_as_ref()
+                        .pipeline_runner
+                        .dropped_frames(),
+                    cpu_seconds: state_ref
+                        .pipeline
+                        .inner_state_as_ref()
+                        .pipeline_runner
+                        .cpu_time()
+                        .as_secs_f64(),
+                    queued_bytes: pipeline::resources::queued_bytes(
+                        &state_ref.pipeline.inner_state_as_ref().pipeline,
+                    ),
+                    mavlink_component_id: state_ref
+                        .mavlink_camera
+                        .as_ref()
+                        .map(MavlinkCamera::component_id),
                 })
             })
             .collect()
@@ -602,7 +1891,138 @@ impl Manager {
     }
 
     #[instrument(level = "debug")]
+    pub async fn webrtc_sessions_information() -> Vec<WebRTCSessionStatus> {
+        let manager = MANAGER.read().await;
+
+        let sessions: Vec<Vec<WebRTCSessionStatus>> =
+            futures::stream::iter(manager.streams.values())
+                .filter_map(|stream| async move {
+                    let state_guard = stream.state.read().await;
+
+                    let state_ref = state_guard.as_ref()?;
+
+                    let sessions = state_ref
+                        .pipeline
+                        .inner_state_as_ref()
+                        .sinks
+                        .values()
+                        .filter_map(|sink| match sink {
+                            Sink::WebRTC(webrtc_sink) => Some(WebRTCSessionStatus {
+                                producer_id: webrtc_sink.bind.producer_id,
+                                consumer_id: webrtc_sink.bind.consumer_id,
+                                session_id: webrtc_sink.bind.session_id,
+                                target_bitrate: webrtc_sink.target_bitrate(),
+                                codec: webrtc_sink
+                                    .negotiated_codec()
+                                    .map(|codec| codec.encoding_name().to_string()),
+                                layer: webrtc_sink.layer(),
+                            }),
+                            _ => None,
+                        })
+                        .collect();
+
+                    Some(sessions)
+                })
+                .collect()
+                .await;
+
+        sessions.into_iter().flatten().collect()
+    }
+
+    #[instrument(level = "debug")]
+    pub async fn streams_metrics() -> Vec<StreamMetrics> {
+        let manager = MANAGER.read().await;
+
+        futures::stream::iter(manager.streams.values())
+            .filter_map(|stream| async move {
+                let state_guard = stream.state.read().await;
+
+                let state_ref = state_guard.as_ref()?;
+                let pipeline_state = state_ref.pipeline.inner_state_as_ref();
+
+                Some(StreamMetrics {
+                    id: state_ref.pipeline_id,
+                    name: state_ref
+                        .video_and_stream_information
+                        .display_name()
+                        .to_string(),
+                    running: state_ref.pipeline.is_running(),
+                    bitrate: pipeline_state.statistics.bitrate(),
+                    framerate: pipeline_state.statistics.framerate(),
+                    uptime_seconds: pipeline_state
+                        .pipeline_runner
+                        .uptime()
+                        .map(|uptime| uptime.as_secs_f64()),
+                    restart_count: stream.restart_count(),
+                    clients: state_ref.client_count(),
+                    dropped_frames: pipeline_state
+                        .pipeline_runner
+                        .dropped_frames()
+                        .values()
+                        .sum(),
+                    cpu_seconds: pipeline_state.pipeline_runner.cpu_time().as_secs_f64(),
+                    queued_bytes: pipeline::resources::queued_bytes(&pipeline_state.pipeline),
+                })
+            })
+            .collect()
+            .await
+    }
+
+    #[instrument(level = "debug")]
+    /// Takes the pending streams that should be running, leaving the ones not auto-started
+    fn take_auto_start_pending_streams(&mut self) -> Vec<VideoAndStreamInformation> {
+        let (streams, pending_streams): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.pending_streams)
+                .into_iter()
+                .partition(|stream| stream.stream_information.auto_start());
+        self.pending_streams = pending_streams;
+
+        streams
+    }
+
     pub fn generate_uuid() -> uuid::Uuid {
         uuid::Uuid::new_v4()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::types::{StreamInformation, VideoCaptureConfiguration};
+    use crate::video::{
+        types::{FrameInterval, VideoEncodeType},
+        video_source_gst::{VideoSourceGst, VideoSourceGstType},
+    };
+
+    #[tokio::test]
+    async fn test_start_streams_reports_failures() {
+        gst::init().unwrap();
+
+        // Without the Tees, the custom pipeline can't be built
+        let broken = VideoAndStreamInformation {
+            name: "Broken".into(),
+            tags: vec![],
+            stream_information: StreamInformation {
+                endpoints: vec![url::Url::parse("udp://127.0.0.1:5600").unwrap()],
+                configuration: CaptureConfiguration::Video(VideoCaptureConfiguration {
+                    encode: VideoEncodeType::Yuyv,
+                    height: 240,
+                    width: 320,
+                    frame_interval: FrameInterval {
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                }),
+                extended_configuration: None,
+            },
+            video_source: VideoSourceType::Gst(VideoSourceGst {
+                name: "Custom".into(),
+                source: VideoSourceGstType::Custom("videotestsrc ! fakesink".into()),
+            }),
+        };
+
+        let error = start_streams(vec![broken]).await.unwrap_err();
+        assert!(format!("{error:#}").contains("\"Broken\""));
+        assert!(MANAGER.read().await.streams.is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use paperclip::actix::Apiv2Schema;
+use prometheus::{CounterVec, Encoder, GaugeVec, Opts, Registry, TextEncoder};
+use serde::Serialize;
+use tracing::*;
+
+use super::manager;
+
+/// Interval between the samples of the statistics history
+const HISTORY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// Number of samples kept per stream, covering the last 5 minutes
+const HISTORY_LENGTH: usize = 300;
+
+lazy_static! {
+    static ref HISTORY: Arc<Mutex<HashMap<uuid::Uuid, VecDeque<StatsSample>>>> = Default::default();
+}
+
+/// A snapshot of the metrics of a single stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamMetrics {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub running: bool,
+    /// In bits per second
+    pub bitrate: f64,
+    /// In frames per second
+    pub framerate: f64,
+    pub uptime_seconds: Option<f64>,
+    pub restart_count: usize,
+    pub clients: usize,
+    /// Frames dropped by the pipeline's elements for not keeping up, as reported in their QoS messages
+    pub dropped_frames: u64,
+    /// CPU time spent by the pipeline's streaming threads, in seconds
+    pub cpu_seconds: f64,
+    /// Memory held by the pipeline's queues, in bytes
+    pub queued_bytes: u64,
+}
+
+/// A sample of the statistics history of a stream
+#[derive(Apiv2Schema, Debug, Clone, PartialEq, Serialize)]
+pub struct StatsSample {
+    /// Unix time, in milliseconds
+    pub timestamp: i64,
+    pub running: bool,
+    /// In bits per second
+    pub bitrate: f64,
+    /// In frames per second
+    pub framerate: f64,
+    pub clients: usize,
+}
+
+/// The statistics of the last minutes of the given stream, oldest first
+pub fn history(stream_id: &uuid::Uuid) -> Option<Vec<StatsSample>> {
+    HISTORY
+        .lock()
+        .unwrap()
+        .get(stream_id)
+        .map(|samples| samples.iter().cloned().collect())
+}
+
+/// Starts sampling the metrics of all streams into their statistics history, and their restarts into the health
+/// status
+#[instrument(level = "debug")]
+pub fn start() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(HISTORY_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            let streams = manager::metrics().await;
+            super::health::observe(&streams);
+            record(
+                &mut HISTORY.lock().unwrap(),
+                &streams,
+                chrono::Utc::now().timestamp_millis(),
+            );
+        }
+    });
+}
+
+/// Appends a sample to the history of each stream, dropping the oldest ones and the histories of removed streams
+fn record(
+    history: &mut HashMap<uuid::Uuid, VecDeque<StatsSample>>,
+    streams: &[StreamMetrics],
+    timestamp: i64,
+) {
+    history.retain(|stream_id, _| streams.iter().any(|stream| stream.id == *stream_id));
+
+    for stream in streams {
+        let samples = history
+            .entry(stream.id)
+            .or_insert_with(|| VecDeque::with_capacity(HISTORY_LENGTH));
+        if samples.len() == HISTORY_LENGTH {
+            samples.pop_front();
+        }
+        samples.push_back(StatsSample {
+            timestamp,
+            running: stream.running,
+            bitrate: stream.bitrate,
+            framerate: stream.framerate,
+            clients: stream.clients,
+        });
+    }
+}
+
+/// Labels identifying the stream of each sample
+const LABELS: &[&str] = &["stream_id", "name"];
+
+/// Renders the metrics using the Prometheus text exposition format (version 0.0.4)
+pub fn render(streams: &[StreamMetrics]) -> Result<String> {
+    let registry = Registry::new();
+
+    let gauge = |name: &str, help: &str| -> Result<GaugeVec> {
+        let gauge = GaugeVec::new(Opts::new(name, help), LABELS)?;
+        registry.register(Box::new(gauge.clone()))?;
+        Ok(gauge)
+    };
+    let counter = |name: &str, help: &str| -> Result<CounterVec> {
+        let counter = CounterVec::new(Opts::new(name, help), LABELS)?;
+        registry.register(Box::new(counter.clone()))?;
+        Ok(counter)
+    };
+
+    let running = gauge(
+        "mcm_stream_running",
+        "Whether the stream pipeline is running",
+    )?;
+    let bitrate = gauge(
+        "mcm_stream_bitrate_bits_per_second",
+        "Measured bitrate of the stream",
+    )?;
+    let framerate = gauge(
+        "mcm_stream_framerate_frames_per_second",
+        "Measured framerate of the stream",
+    )?;
+    let uptime = gauge(
+        "mcm_stream_uptime_seconds",
+        "Time since the stream pipeline reached the playing state",
+    )?;
+    let restarts = counter(
+        "mcm_stream_restarts_total",
+        "Number of times the stream pipeline was restarted",
+    )?;
+    let clients = gauge("mcm_stream_clients", "Number of connected clients")?;
+    let dropped_frames = counter(
+        "mcm_stream_dropped_frames_total",
+        "Number of frames dropped by the stream pipeline for not keeping up",
+    )?;
+    let cpu_seconds = counter(
+        "mcm_stream_cpu_seconds_total",
+        "CPU time spent by the streaming threads of the stream pipeline, not counting the encoders' own threads",
+    )?;
+    let queued_bytes = gauge(
+        "mcm_stream_queued_bytes",
+        "Memory held by the queues of the stream pipeline",
+    )?;
+
+    for stream in streams {
+        let id = stream.id.to_string();
+        let labels = [id.as_str(), stream.name.as_str()];
+
+        running
+            .with_label_values(&labels)
+            .set(stream.running as u8 as f64);
+        bitrate.with_label_values(&labels).set(stream.bitrate);
+        framerate.with_label_values(&labels).set(stream.framerate);
+        if let Some(uptime_seconds) = stream.uptime_seconds {
+            uptime.with_label_values(&labels).set(uptime_seconds);
+        }
+        restarts
+            .with_label_values(&labels)
+            .inc_by(stream.restart_count as f64);
+        clients
+            .with_label_values(&labels)
+            .set(stream.clients as f64);
+        dropped_frames
+            .with_label_values(&labels)
+            .inc_by(stream.dropped_frames as f64);
+        cpu_seconds
+            .with_label_values(&labels)
+            .inc_by(stream.cpu_seconds);
+        queued_bytes
+            .with_label_values(&labels)
+            .set(stream.queued_bytes as f64);
+    }
+
+    let mut output = vec![];
+    TextEncoder::new().encode(&registry.gather(), &mut output)?;
+
+    Ok(String::from_utf8(output)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_metrics(id: uuid::Uuid, bitrate: f64) -> StreamMetrics {
+        StreamMetrics {
+            id,
+            name: "camera".to_string(),
+            running: true,
+            bitrate,
+            framerate: 30.0,
+            uptime_seconds: None,
+            restart_count: 0,
+            clients: 0,
+            dropped_frames: 0,
+            cpu_seconds: 0.0,
+            queued_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let (first, second) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let mut history = HashMap::new();
+
+        for timestamp in 0..(HISTORY_LENGTH as i64 + 10) {
+            record(
+                &mut history,
+                &[
+                    stream_metrics(first, timestamp as f64),
+                    stream_metrics(second, 0.0),
+                ],
+                timestamp,
+            );
+        }
+
+        // Bounded, keeping the latest samples
+        let samples = &history[&first];
+        assert_eq!(samples.len(), HISTORY_LENGTH);
+        assert_eq!(samples.front().unwrap().timestamp, 10);
+        assert_eq!(samples.back().unwrap().bitrate, HISTORY_LENGTH as f64 + 9.0);
+
+        // Removed streams are forgotten
+        record(&mut history, &[stream_metrics(first, 0.0)], 0);
+        assert!(!history.contains_key(&second));
+    }
+
+    #[test]
+    fn test_render() {
+        let id = uuid::Uuid::nil();
+        let output = render(&[StreamMetrics {
+            id,
+            name: "my \"camera\"".to_string(),
+            running: true,
+            bitrate: 1000.5,
+            framerate: 30.0,
+            uptime_seconds: None,
+            restart_count: 2,
+            clients: 1,
+            dropped_frames: 7,
+            cpu_seconds: 1.5,
+            queued_bytes: 4096,
+        }])
+        .unwrap();
+
+        let labels = format!("{{name=\"my \\\"camera\\\"\",stream_id=\"{id}\"}}");
+        assert!(output.contains("# TYPE mcm_stream_restarts_total counter\n"));
+        assert!(output.contains(&format!("mcm_stream_running{labels} 1\n")));
+        assert!(output.contains(&format!(
+            "mcm_stream_bitrate_bits_per_second{labels} 1000.5\n"
+        )));
+        assert!(output.contains(&format!(
+            "mcm_stream_framerate_frames_per_second{labels} 30\n"
+        )));
+        assert!(output.contains(&format!("mcm_stream_restarts_total{labels} 2\n")));
+        assert!(output.contains(&format!("mcm_stream_clients{labels} 1\n")));
+        assert!(output.contains(&format!("mcm_stream_dropped_frames_total{labels} 7\n")));
+        assert!(output.contains(&format!("mcm_stream_cpu_seconds_total{labels} 1.5\n")));
+        assert!(output.contains(&format!("mcm_stream_queued_bytes{labels} 4096\n")));
+        assert!(!output.contains("mcm_stream_uptime_seconds{"));
+    }
+}
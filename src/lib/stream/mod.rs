@@ -1,33 +1,52 @@
+pub mod bandwidth;
+pub mod endpoints;
+pub mod formats;
 pub mod gst;
+pub mod health;
 pub mod manager;
+pub mod metrics;
 pub mod pipeline;
+pub mod rtp;
 pub mod rtsp;
 pub mod sink;
+pub mod thumbnails;
 pub mod types;
+pub mod validation;
 pub mod webrtc;
 
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
 use tokio::sync::RwLock;
 
+use crate::cli;
 use crate::mavlink::mavlink_camera::MavlinkCamera;
+use crate::network;
+use crate::video::device_monitor::{self, DeviceEvent};
 use crate::video::types::{VideoEncodeType, VideoSourceType};
 use crate::video::video_source::cameras_available;
 use crate::video_stream::types::VideoAndStreamInformation;
 
 use manager::Manager;
-use pipeline::Pipeline;
-use sink::{create_image_sink, create_rtsp_sink, create_udp_sink};
+use pipeline::runner::{publish_event, PipelineEventKind, PipelineRunnerConfig, RestartBackoff};
+use pipeline::{zoom::DigitalZoom, Pipeline};
+use sink::{
+    create_image_sink, create_mjpeg_sink, create_recording_sink, create_rtsp_sink, create_srt_sink,
+    create_udp_sink, Sink,
+};
 use types::*;
 use webrtc::signalling_protocol::PeerId;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 
 use tracing::*;
 
-use self::gst::utils::wait_for_element_state;
+use self::gst::encoders::{self, EncoderUpdate};
+use self::gst::utils::{negotiated_caps, rtp_encoding_name, wait_for_element_state};
 use self::rtsp::rtsp_scheme::RTSPScheme;
-use self::rtsp::rtsp_server::RTSP_SERVER_PORT;
+use self::rtsp::rtsp_server::{RTSPServer, RTSP_SERVER_PORT};
 use self::sink::SinkInterface;
 
 use ::gst::prelude::*;
@@ -37,6 +56,9 @@ pub struct Stream {
     state: Arc<RwLock<Option<StreamState>>>,
     terminated: Arc<RwLock<bool>>,
     watcher_handle: Option<tokio::task::JoinHandle<()>>,
+    restart_count: Arc<AtomicUsize>,
+    last_error: Arc<std::sync::Mutex<Option<String>>>,
+    format_fallback: Option<String>,
 }
 
 #[derive(Debug)]
@@ -45,13 +67,22 @@ pub struct StreamState {
     pub pipeline: Pipeline,
     pub video_and_stream_information: VideoAndStreamInformation,
     pub mavlink_camera: Option<MavlinkCamera>,
+    /// Follows the pipeline of a local camera starting and stopping, see [`crate::video::video_source::set_streaming`]
+    streaming_tracker: Option<tokio::task::JoinHandle<()>>,
 }
 
 impl Stream {
     #[instrument(level = "debug")]
     pub async fn try_new(video_and_stream_information: &VideoAndStreamInformation) -> Result<Self> {
-        let pipeline_id = Manager::generate_uuid();
+        Self::try_new_with_id(video_and_stream_information, Manager::generate_uuid()).await
+    }
 
+    /// Like [`Stream::try_new`], but reusing an existing id, so the stream keeps its URLs
+    #[instrument(level = "debug")]
+    pub async fn try_new_with_id(
+        video_and_stream_information: &VideoAndStreamInformation,
+        pipeline_id: uuid::Uuid,
+    ) -> Result<Self> {
         let state = Arc::new(RwLock::new(Some(
             StreamState::try_new(video_and_stream_information, &pipeline_id).await?,
         )));
@@ -59,38 +90,884 @@ impl Stream {
         let terminated = Arc::new(RwLock::new(false));
         let terminated_cloned = terminated.clone();
 
+        let restart_count = Arc::new(AtomicUsize::new(0));
+        let restart_count_cloned = restart_count.clone();
+        let last_error = Arc::new(std::sync::Mutex::new(None));
+        let last_error_cloned = last_error.clone();
+
         debug!("Starting StreamWatcher task...");
 
         let video_and_stream_information_cloned = video_and_stream_information.clone();
         let state_cloned = state.clone();
-        let watcher_handle = Some(tokio::spawn(async move {
-            debug!("StreamWatcher task started!");
-            match Self::watcher(
-                video_and_stream_information_cloned,
-                pipeline_id,
-                state_cloned,
-                terminated_cloned,
-            )
-            .await
-            {
-                Ok(_) => debug!("StreamWatcher task eneded with no errors"),
-                Err(error) => warn!("StreamWatcher task ended with error: {error:#?}"),
-            };
-        }));
+        // Everything logged by the watcher carries the stream it belongs to
+        let watcher_span = info_span!(
+            "stream",
+            stream_id = %pipeline_id,
+            stream_name = %video_and_stream_information.name
+        );
+        let watcher_handle = Some(tokio::spawn(
+            async move {
+                debug!("StreamWatcher task started!");
+                match Self::watcher(
+                    video_and_stream_information_cloned,
+                    pipeline_id,
+                    state_cloned,
+                    terminated_cloned,
+                    restart_count_cloned,
+                    last_error_cloned,
+                )
+                .await
+                {
+                    Ok(_) => debug!("StreamWatcher task eneded with no errors"),
+                    Err(error) => warn!("StreamWatcher task ended with error: {error:#?}"),
+                };
+            }
+            .instrument(watcher_span),
+        ));
 
         Ok(Self {
             state,
             terminated,
             watcher_handle,
+            restart_count,
+            last_error,
+            format_fallback: None,
+        })
+    }
+
+    /// Grabs a single JPEG frame from the running pipeline, without opening the source a second time
+    #[instrument(level = "debug", skip(self))]
+    pub async fn snapshot(&self) -> Result<Vec<u8>> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        let encoding = match &state
+            .video_and_stream_information
+            .stream_information
+            .configuration
+        {
+            CaptureConfiguration::Video(configuration) => &configuration.encode,
+            CaptureConfiguration::Redirect(_) => {
+                return Err(anyhow!("Snapshots are not supported for Redirect streams"))
+            }
+        };
+
+        let pipeline_state = state.pipeline.inner_state_as_ref();
+        let video_tee = pipeline_state
+            .video_tee
+            .as_ref()
+            .context("Pipeline without a video Tee")?;
+
+        pipeline::snapshot::take_jpeg_snapshot(
+            &pipeline_state.pipeline,
+            video_tee,
+            &pipeline_state.pipeline_id,
+            encoding,
+            std::time::Duration::from_secs(2),
+        )
+        .await
+    }
+
+    /// Starts recording this stream to disk, without restarting it. Returns the location pattern of the recorded files
+    #[instrument(level = "debug", skip(self))]
+    pub async fn start_recording(&self, settings: &RecordingSettings) -> Result<String> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        if state
+            .pipeline
+            .inner_state_as_ref()
+            .sinks
+            .values()
+            .any(|sink| matches!(sink, Sink::Recording(_)))
+        {
+            return Err(anyhow!("Stream is already recording"));
+        }
+
+        let sink = create_recording_sink(
+            Manager::generate_uuid(),
+            &state.video_and_stream_information,
+            settings,
+        )?;
+        let Sink::Recording(recording_sink) = &sink else {
+            unreachable!()
+        };
+        let location = recording_sink.location().to_string();
+
+        state.pipeline.add_sink(sink)?;
+
+        Ok(location)
+    }
+
+    /// Stops the current recording, returning after its file is finalized
+    #[instrument(level = "debug", skip(self))]
+    pub async fn stop_recording(&self) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        let sink_id = state
+            .pipeline
+            .inner_state_as_ref()
+            .sinks
+            .iter()
+            .find_map(|(id, sink)| matches!(sink, Sink::Recording(_)).then_some(*id))
+            .context("Stream is not recording")?;
+
+        state.pipeline.remove_sink(&sink_id)
+    }
+
+    /// The SDP describing this stream's UDP endpoints, always from the currently negotiated caps
+    #[instrument(level = "debug", skip(self))]
+    pub async fn sdp(&self) -> Result<gst_sdp::SDPMessage> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        let udp_sink = state
+            .pipeline
+            .inner_state_as_ref()
+            .sinks
+            .values()
+            .find(|sink| matches!(sink, Sink::Udp(_)))
+            .context("Stream has no UDP endpoint")?;
+
+        udp_sink.get_sdp()
+    }
+
+    /// Adds a branch encoding this stream as JPEG frames, at most `max_framerate` per second. Returns the branch's
+    /// id, to remove it once the client is gone, and its frames
+    #[instrument(level = "debug", skip(self))]
+    pub async fn add_mjpeg_client(
+        &self,
+        max_framerate: u32,
+    ) -> Result<(uuid::Uuid, tokio::sync::mpsc::Receiver<Vec<u8>>)> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        let sink_id = Manager::generate_uuid();
+        let sink = create_mjpeg_sink(sink_id, &state.video_and_stream_information, max_framerate)?;
+        let Sink::Mjpeg(mjpeg_sink) = &sink else {
+            unreachable!()
+        };
+        let frames = mjpeg_sink
+            .take_frames()
+            .context("MjpegSink without frames")?;
+
+        state.pipeline.add_sink(sink)?;
+
+        Ok((sink_id, frames))
+    }
+
+    /// Removes the branch of a MJPEG client
+    #[instrument(level = "debug", skip(self))]
+    pub async fn remove_mjpeg_client(&self, sink_id: &uuid::Uuid) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        if !matches!(
+            state.pipeline.inner_state_as_ref().sinks.get(sink_id),
+            Some(Sink::Mjpeg(_))
+        ) {
+            return Err(anyhow!("MJPEG client {sink_id:?} not found"));
+        }
+
+        state.pipeline.remove_sink(sink_id)
+    }
+
+    /// Records a clip of the next `duration` of video into the storage path, preceded by the configured pre-roll.
+    /// Returns the clip's file once it is finalized
+    #[instrument(level = "debug", skip(self))]
+    pub async fn record_clip(&self, duration: std::time::Duration) -> Result<String> {
+        let (clip_recorder, name) = {
+            let state_guard = self.state.read().await;
+            let state = state_guard.as_ref().context("Stream without State")?;
+
+            let clip_recorder = state
+                .pipeline
+                .inner_state_as_ref()
+                .clip_recorder
+                .clone()
+                .context("Stream has no encoded video to record clips from")?;
+
+            (
+                clip_recorder,
+                state
+                    .video_and_stream_information
+                    .display_name()
+                    .to_string(),
+            )
+        };
+
+        let path = cli::manager::storage_path();
+        std::fs::create_dir_all(&path)
+            .context(format!("Failed creating storage directory {path:?}"))?;
+        let file_name = format!(
+            "{}-clip-{}.mp4",
+            name.replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+            chrono::Local::now().format("%Y%m%d-%H%M%S%.3f"),
+        );
+        let location = std::path::Path::new(&path)
+            .join(file_name)
+            .to_str()
+            .context("Invalid storage path")?
+            .to_string();
+
+        // The state isn't held while recording, so the stream can still be changed
+        clip_recorder.record(&location, duration).await?;
+
+        Ok(location)
+    }
+
+    /// Changes how much of the encoded video is kept in memory for the clips, dropping it when zero
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_clip_pre_roll(&self, pre_roll_secs: u32) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        state
+            .pipeline
+            .inner_state_as_ref()
+            .clip_recorder
+            .as_ref()
+            .context("Stream has no encoded video to record clips from")?
+            .set_pre_roll(pre_roll_secs)?;
+
+        state.update_extended_configuration(|extended| {
+            extended.clip_pre_roll_secs = Some(pre_roll_secs)
+        });
+
+        Ok(())
+    }
+
+    pub async fn is_recording(&self) -> bool {
+        self.state.read().await.as_ref().is_some_and(|state| {
+            state
+                .pipeline
+                .inner_state_as_ref()
+                .sinks
+                .values()
+                .any(|sink| matches!(sink, Sink::Recording(_)))
         })
     }
 
-    #[instrument(level = "debug", skip(video_and_stream_information, state, terminated))]
+    /// Changes the jitterbuffer latency of the running stream, keeping it for when the stream is recreated
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_latency(&self, latency_ms: u32) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        let max_latency_ms = if state
+            .video_and_stream_information
+            .stream_information
+            .low_latency()
+        {
+            types::LOW_LATENCY_MAX_LATENCY_MS
+        } else {
+            types::MAX_LATENCY_MS
+        };
+        if latency_ms > max_latency_ms {
+            return Err(anyhow!(
+                "Latency of {latency_ms} ms is out of the allowed range (0..={max_latency_ms} ms)"
+            ));
+        }
+
+        state.update_extended_configuration(|extended| extended.latency_ms = Some(latency_ms));
+
+        let pipeline_state = state.pipeline.inner_state_as_ref();
+
+        pipeline_state
+            .pipeline
+            .iterate_recurse()
+            .into_iter()
+            .flatten()
+            .filter(|element| {
+                element.factory().is_some_and(|factory| {
+                    matches!(factory.name().as_str(), "rtspsrc" | "rtpjitterbuffer")
+                })
+            })
+            .for_each(|element| {
+                element.set_property("latency", latency_ms);
+                debug!(
+                    "Element {:?} latency set to {latency_ms} ms",
+                    element.name()
+                );
+            });
+
+        for sink in pipeline_state.sinks.values() {
+            if let Sink::Rtsp(sink) = sink {
+                rtsp::rtsp_server::RTSPServer::set_latency(&sink.path(), latency_ms)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts sending this stream to one more UDP destination, without disturbing the current ones
+    #[instrument(level = "debug", skip(self))]
+    pub async fn add_udp_destination(&self, destination: &url::Url) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        let udp_sink = state
+            .pipeline
+            .inner_state_mut()
+            .sinks
+            .values_mut()
+            .find_map(|sink| match sink {
+                Sink::Udp(udp_sink) => Some(udp_sink),
+                _ => None,
+            })
+            .context("Stream has no UDP endpoints")?;
+
+        udp_sink.add_destination(destination)?;
+
+        state
+            .video_and_stream_information
+            .stream_information
+            .endpoints
+            .push(destination.clone());
+
+        Ok(())
+    }
+
+    /// Stops sending this stream to the given UDP destination, without disturbing the others
+    #[instrument(level = "debug", skip(self))]
+    pub async fn remove_udp_destination(&self, destination: &url::Url) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        let endpoints = &mut state
+            .video_and_stream_information
+            .stream_information
+            .endpoints;
+        if endpoints.len() <= 1 {
+            return Err(anyhow!(
+                "Cannot remove the last endpoint of a stream, remove the stream instead"
+            ));
+        }
+
+        let udp_sink = state
+            .pipeline
+            .inner_state_mut()
+            .sinks
+            .values_mut()
+            .find_map(|sink| match sink {
+                Sink::Udp(udp_sink) => Some(udp_sink),
+                _ => None,
+            })
+            .context("Stream has no UDP endpoints")?;
+
+        udp_sink.remove_destination(destination)?;
+
+        endpoints.retain(|endpoint| endpoint != destination);
+
+        Ok(())
+    }
+
+    /// Asks the stream's encoder for a keyframe
+    #[instrument(level = "debug", skip(self))]
+    pub async fn force_keyframe(&self) -> Result<()> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        state.pipeline.inner_state_as_ref().force_keyframe()
+    }
+
+    /// The digital zoom of this stream, only available for raw video pipelines
+    #[instrument(level = "debug", skip(self))]
+    pub async fn zoom(&self) -> Result<Arc<DigitalZoom>> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        state
+            .pipeline
+            .inner_state_as_ref()
+            .zoom
+            .clone()
+            .context("Stream has no digital zoom, which is only available for raw video")
+    }
+
+    /// The text overlay of this stream, only available for raw video pipelines
+    #[instrument(level = "debug", skip(self))]
+    pub async fn overlay(&self) -> Result<OverlayConfiguration> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        let overlay = state
+            .pipeline
+            .inner_state_as_ref()
+            .overlay
+            .as_ref()
+            .context("Stream has no text overlay, which is only available for raw video")?;
+
+        Ok(overlay.configuration())
+    }
+
+    /// Changes the text overlay while the pipeline is running, as its stage is always part of raw video pipelines
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_overlay(&self, configuration: OverlayConfiguration) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        state
+            .pipeline
+            .inner_state_as_ref()
+            .overlay
+            .as_ref()
+            .context("Stream has no text overlay, which is only available for raw video")?
+            .set_configuration(configuration.clone())?;
+
+        state.update_extended_configuration(|extended| extended.overlay = Some(configuration));
+
+        Ok(())
+    }
+
+    /// Changes the STUN and TURN servers of this stream, falling back to the global ones when none is given.
+    /// Only new WebRTC sessions pick them up, so the running ones are left untouched
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_ice_servers(&self, ice_servers: Option<Vec<IceServer>>) -> Result<()> {
+        if let Some(ice_servers) = &ice_servers {
+            webrtc::ice_servers::validate(ice_servers)?;
+        }
+
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        state.update_extended_configuration(|extended| extended.ice_servers = ice_servers);
+
+        Ok(())
+    }
+
+    /// The WebRTC sessions and RTSP clients watching this stream
+    #[instrument(level = "debug", skip(self))]
+    pub async fn clients(&self, stream_id: &uuid::Uuid) -> Result<Vec<ClientStatus>> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        let pipeline_state = state.pipeline.inner_state_as_ref();
+
+        // All clients receive the same RTP packets
+        let codec = pipeline_state
+            .rtp_tee
+            .as_ref()
+            .and_then(|rtp_tee| rtp_tee.static_pad("sink"))
+            .and_then(|pad| pad.current_caps())
+            .and_then(|caps| rtp_encoding_name(&caps));
+
+        let clients = pipeline_state
+            .sinks
+            .values()
+            .filter_map(|sink| match sink {
+                Sink::WebRTC(webrtc_sink) => Some(ClientStatus {
+                    id: webrtc_sink.get_id(),
+                    protocol: ClientProtocol::WebRTC,
+                    remote_address: Some(webrtc_sink.remote_address.ip().to_string()),
+                    connected_at: webrtc_sink.connected_at,
+                    codec: webrtc_sink
+                        .negotiated_codec()
+                        .map(|codec| codec.encoding_name().to_string()),
+                    estimated_bitrate: webrtc_sink.target_bitrate(),
+                }),
+                _ => None,
+            })
+            .chain(RTSPServer::clients(stream_id))
+            .map(|client| ClientStatus {
+                codec: client.codec.clone().or_else(|| codec.clone()),
+                ..client
+            })
+            .collect();
+
+        Ok(clients)
+    }
+
+    /// Disconnects a single client. WebRTC sessions have their own branch removed, while RTSP clients only
+    /// have their connection closed, as they share the same media
+    #[instrument(level = "debug", skip(self))]
+    pub async fn disconnect_client(
+        &self,
+        stream_id: &uuid::Uuid,
+        client_id: &uuid::Uuid,
+    ) -> Result<()> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        if let Some(Sink::WebRTC(webrtc_sink)) =
+            state.pipeline.inner_state_as_ref().sinks.get(client_id)
+        {
+            // The session is removed by the signalling server, which also tells the consumer about it
+            return webrtc_sink.terminate("Disconnected by the user".to_string());
+        }
+
+        RTSPServer::disconnect_client(stream_id, client_id)
+    }
+
+    /// The audio configured for this stream, and if it is being captured
+    #[instrument(level = "debug", skip(self))]
+    pub async fn audio(&self) -> Result<AudioStatus> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        Ok(AudioStatus {
+            audio: state
+                .video_and_stream_information
+                .stream_information
+                .audio()
+                .cloned(),
+            active: state.pipeline.inner_state_as_ref().audio_tee.is_some(),
+        })
+    }
+
+    /// The clock and the latency of the running pipeline
+    #[instrument(level = "debug", skip(self))]
+    pub async fn clock(&self) -> Result<ClockStatus> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        Ok(pipeline::clock::status(
+            &state.pipeline.inner_state_as_ref().pipeline,
+            state
+                .video_and_stream_information
+                .stream_information
+                .clock(),
+        ))
+    }
+
+    /// Changes the clock and the base time of this stream, which rebuilds its pipeline
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_clock(&self, clock: Option<ClockConfiguration>) -> Result<()> {
+        if let Some(clock) = &clock {
+            pipeline::clock::validate(clock)?;
+        }
+
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        info!(
+            "Rebuilding stream {:?} to apply the clock {clock:?}",
+            state.pipeline_id
+        );
+        state
+            .pipeline
+            .inner_state_as_ref()
+            .pipeline_runner
+            .request_restart();
+
+        state.update_extended_configuration(|extended| extended.clock = clock);
+
+        Ok(())
+    }
+
+    /// The caps negotiated by each element of the running pipeline
+    #[instrument(level = "debug", skip(self))]
+    pub async fn caps(&self) -> Result<Vec<ElementCaps>> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        Ok(negotiated_caps(
+            &state.pipeline.inner_state_as_ref().pipeline,
+        ))
+    }
+
+    /// Waits until the pipeline of this stream is Playing for the first time, see [`pipeline::runner::PipelineRunner::wait_for_start`]
+    #[instrument(level = "debug", skip(self))]
+    pub async fn wait_for_start(&self, timeout: std::time::Duration) -> Result<()> {
+        let state_guard = self.state.read().await;
+        let state = state_guard.as_ref().context("Stream without State")?;
+
+        // Redirect pipelines are never started
+        if matches!(
+            state.video_and_stream_information.video_source,
+            VideoSourceType::Redirect(_)
+        ) {
+            return Ok(());
+        }
+
+        state
+            .pipeline
+            .inner_state_as_ref()
+            .pipeline_runner
+            .wait_for_start(timeout)
+            .await
+    }
+
+    /// Enables, changes or disables the audio of this stream, which rebuilds its pipeline
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_audio(&self, audio: Option<AudioConfiguration>) -> Result<()> {
+        if let Some(audio) = &audio {
+            pipeline::audio::validate(audio)?;
+        }
+
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        let pipeline_state = state.pipeline.inner_state_as_ref();
+        if audio.is_some() && pipeline_state.video_tee.is_none() {
+            return Err(anyhow!(
+                "Stream has no encoded video to carry the audio along"
+            ));
+        }
+
+        info!(
+            "Rebuilding stream {:?} to apply the audio {audio:?}",
+            state.pipeline_id
+        );
+        pipeline_state.pipeline_runner.request_restart();
+
+        state.update_extended_configuration(|extended| extended.audio = audio);
+
+        Ok(())
+    }
+
+    /// Changes the user metadata of this stream. The MAVLink camera is recreated to advertise the new name
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_metadata(
+        &self,
+        name: Option<String>,
+        tags: Option<Vec<String>>,
+    ) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        if let Some(tags) = tags {
+            state.video_and_stream_information.tags = tags;
+        }
+
+        let Some(name) = name else {
+            return Ok(());
+        };
+        state.video_and_stream_information.name = name;
+
+        // Dropping the old camera first frees its component id for the new one
+        if state.mavlink_camera.take().is_some() {
+            state.mavlink_camera =
+                MavlinkCamera::try_new(&state.video_and_stream_information, &state.pipeline_id)
+                    .await
+                    .inspect_err(|error| warn!("Stream without a MAVLink camera: {error:?}"))
+                    .ok();
+        }
+
+        Ok(())
+    }
+
+    /// Changes the MAVLink camera mode stored in this stream's configuration
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_camera_mode(&self, mode: CameraMode) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        state.update_extended_configuration(|extended| extended.camera_mode = Some(mode));
+
+        Ok(())
+    }
+
+    /// Changes the maximum distance between keyframes, in frames. Encoders that can't change it while running
+    /// have their pipeline rebuilt with the new value
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_keyframe_interval(&self, frames: u32) -> Result<()> {
+        encoders::validate_keyframe_interval(frames)?;
+
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        let pipeline_state = state.pipeline.inner_state_as_ref();
+
+        match encoders::apply_keyframe_interval(&pipeline_state.pipeline, frames)? {
+            EncoderUpdate::Unsupported => {
+                return Err(anyhow!(
+                    "Stream has no encoder with a configurable keyframe interval"
+                ));
+            }
+            EncoderUpdate::RequiresRebuild => {
+                info!(
+                    "Rebuilding stream {:?} to apply a keyframe interval of {frames} frames",
+                    state.pipeline_id
+                );
+                pipeline_state.pipeline_runner.request_restart();
+            }
+            EncoderUpdate::Applied => (),
+        }
+
+        state.update_extended_configuration(|extended| extended.keyframe_interval = Some(frames));
+
+        Ok(())
+    }
+
+    /// Changes how the encoder distributes its bitrate. Encoders that can't change it while running have their
+    /// pipeline rebuilt with the new configuration
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_rate_control(&self, rate_control: RateControlConfiguration) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        let pipeline_state = state.pipeline.inner_state_as_ref();
+
+        match encoders::apply_rate_control(&pipeline_state.pipeline, &rate_control)? {
+            EncoderUpdate::Unsupported => {
+                return Err(anyhow!(
+                    "Stream has no encoder with a configurable rate control"
+                ));
+            }
+            EncoderUpdate::RequiresRebuild => {
+                info!(
+                    "Rebuilding stream {:?} to apply the rate control {rate_control:?}",
+                    state.pipeline_id
+                );
+                pipeline_state.pipeline_runner.request_restart();
+            }
+            EncoderUpdate::Applied => (),
+        }
+
+        pipeline_state
+            .bitrate_controller
+            .set_rate_control(Some(rate_control.clone()));
+
+        state.update_extended_configuration(|extended| extended.rate_control = Some(rate_control));
+
+        Ok(())
+    }
+
+    /// Changes the H264 profile and level of the encoder, rebuilding the pipeline with them
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_h264_profile(&self, h264_profile: H264ProfileConfiguration) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        let pipeline_state = state.pipeline.inner_state_as_ref();
+
+        let encoder = encoders::pipeline_encoder(&pipeline_state.pipeline, &VideoEncodeType::H264)
+            .context("Stream has no H264 encoder")?;
+        encoders::validate_h264_profile_for_encoder(&h264_profile, encoder)?;
+
+        info!(
+            "Rebuilding stream {:?} to apply the H264 profile {h264_profile:?}",
+            state.pipeline_id
+        );
+        pipeline_state.pipeline_runner.request_restart();
+
+        state.update_extended_configuration(|extended| extended.h264_profile = Some(h264_profile));
+
+        Ok(())
+    }
+
+    /// Changes the resolution streamed, renegotiating the caps of the running pipeline, which is only available for
+    /// raw video. If the renegotiation fails, the pipeline is rebuilt with the new resolution
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_output_resolution(
+        &self,
+        output_resolution: Option<OutputResolutionConfiguration>,
+    ) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        state
+            .pipeline
+            .inner_state_as_ref()
+            .scale
+            .as_ref()
+            .context("Stream has no output scale, which is only available for raw video")?
+            .set_output_resolution(output_resolution.as_ref())?;
+
+        state.update_extended_configuration(|extended| {
+            extended.output_resolution = output_resolution
+        });
+
+        Ok(())
+    }
+
+    /// Changes the maximum framerate streamed, renegotiating the caps of the running pipeline, which is only available
+    /// for raw video. The camera keeps capturing at its own framerate
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_max_framerate(&self, max_framerate: Option<u32>) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        state
+            .pipeline
+            .inner_state_as_ref()
+            .rate
+            .as_ref()
+            .context("Stream has no output rate, which is only available for raw video")?
+            .set_max_framerate(max_framerate)?;
+
+        state.update_extended_configuration(|extended| extended.max_framerate = max_framerate);
+
+        Ok(())
+    }
+
+    /// Changes how this stream recovers from changes of the host's network, taking effect on the next change
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_reconnection(&self, reconnection: ReconnectionConfiguration) -> Result<()> {
+        let mut state_guard = self.state.write().await;
+        let state = state_guard.as_mut().context("Stream without State")?;
+
+        state.update_extended_configuration(|extended| extended.reconnection = Some(reconnection));
+
+        Ok(())
+    }
+
+    /// Stops this stream for good, telling its clients and draining its pipeline so recordings are finalized.
+    /// The stream is left without a state, so it should be dropped afterwards
+    #[instrument(level = "debug", skip(self))]
+    pub async fn shutdown(&self) {
+        self.stop("The service is shutting down").await
+    }
+
+    /// Like [`Stream::shutdown`], telling the clients the given reason
+    #[instrument(level = "debug", skip(self))]
+    pub async fn stop(&self, reason: &str) {
+        // Keep the watcher from recreating the pipeline while it is drained
+        *self.terminated.write().await = true;
+        if let Some(handle) = &self.watcher_handle {
+            handle.abort();
+        }
+
+        let Some(state) = self.state.write().await.take() else {
+            return;
+        };
+
+        state.disconnect_clients(reason);
+
+        // Dropping the state drains the EOS through the pipeline, which blocks up to its configured timeout
+        if let Err(error) = tokio::task::spawn_blocking(move || drop(state)).await {
+            warn!("Failed draining the stream pipeline: {error:?}");
+        }
+    }
+
+    /// How many times this stream was recreated after its pipeline stopped running
+    pub fn restart_count(&self) -> usize {
+        self.restart_count.load(Ordering::Relaxed)
+    }
+
+    /// The last reason why this stream's pipeline stopped running
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().clone()
+    }
+
+    /// The format this stream switched to when created, as the source couldn't capture the configured one
+    pub fn format_fallback(&self) -> Option<String> {
+        self.format_fallback.clone()
+    }
+
+    pub fn set_format_fallback(&mut self, format_fallback: Option<String>) {
+        self.format_fallback = format_fallback;
+    }
+
+    #[instrument(
+        level = "debug",
+        skip(
+            video_and_stream_information,
+            state,
+            terminated,
+            restart_count,
+            last_error
+        )
+    )]
     async fn watcher(
         video_and_stream_information: VideoAndStreamInformation,
         pipeline_id: uuid::Uuid,
         state: Arc<RwLock<Option<StreamState>>>,
         terminated: Arc<RwLock<bool>>,
+        restart_count: Arc<AtomicUsize>,
+        last_error: Arc<std::sync::Mutex<Option<String>>>,
     ) -> Result<()> {
         // To reduce log size, each report we raise the report interval geometrically until a maximum value is reached:
         let report_interval_mult = 2;
@@ -100,20 +977,142 @@ impl Stream {
 
         let mut video_and_stream_information = video_and_stream_information;
 
+        // Space out consecutive restarts, so a missing device doesn't make us spin on recreating the pipeline
+        let pipeline_runner_config =
+            PipelineRunnerConfig::from_video_and_stream_information(&video_and_stream_information);
+        let mut restart_backoff = RestartBackoff::new(&pipeline_runner_config);
+        restart_backoff.started();
+
+        // Hot-plug events let us react to the device being unplugged or replugged without waiting for the next poll
+        let mut device_events = device_monitor::subscribe();
+        let mut device_removed = false;
+
+        let mut network_events = network::monitor::subscribe();
+
         let mut period = tokio::time::interval(tokio::time::Duration::from_millis(100));
         loop {
-            period.tick().await;
+            tokio::select! {
+                _ = period.tick() => {}
+                event = device_events.recv() => {
+                    if let Ok(DeviceEvent::Removed(device)) = event {
+                        let source_string = video_and_stream_information.video_source.inner().source_string();
+                        if device.device_path.as_deref() == Some(source_string) {
+                            warn!("Device {source_string:?} of stream {pipeline_id:?} was disconnected");
+                            *last_error.lock().unwrap() = Some(format!("Device {source_string:?} was disconnected"));
+                            device_removed = true;
+                        }
+                    }
+                }
+                event = network_events.recv() => {
+                    if let Ok(event) = event {
+                        if let Some(state) = state.read().await.as_ref() {
+                            if let Err(error) = state.reconnect() {
+                                warn!("Failed to reconnect stream {pipeline_id:?} after the network change {event:?}: {error:?}");
+                            }
+                        }
+                    }
+                }
+            }
 
-            if !state.read().await.as_ref().is_some_and(|state| {
+            // A runner stuck in a GStreamer call looks alive, but its watchdog stopped ticking
+            let wedged = state.read().await.as_ref().is_some_and(|state| {
                 state
                     .pipeline
                     .inner_state_as_ref()
                     .pipeline_runner
-                    .is_running()
-            }) {
+                    .is_wedged()
+            });
+            if wedged {
+                warn!("Stream {pipeline_id:?} stopped responding, it will be rebuilt");
+                *last_error.lock().unwrap() = Some("Pipeline stopped responding".to_string());
+            }
+
+            if device_removed
+                || wedged
+                || !state.read().await.as_ref().is_some_and(|state| {
+                    state
+                        .pipeline
+                        .inner_state_as_ref()
+                        .pipeline_runner
+                        .is_running()
+                })
+            {
+                // Restarting wouldn't help on fatal errors, so keep the stopped state around (to report it) and give up
+                if let Some(state) = state.read().await.as_ref() {
+                    let pipeline_state = state.pipeline.inner_state_as_ref();
+                    if !device_removed && pipeline_state.pipeline_runner.is_fatal() {
+                        let error = pipeline_state.pipeline_runner.last_error();
+                        error!("Stream {pipeline_id:?} stopped with a fatal error, it won't be restarted: {error:?}");
+                        *last_error.lock().unwrap() = error;
+
+                        if let Err(error) = pipeline_state.pipeline.set_state(::gst::State::Null) {
+                            warn!("Failed setting Pipeline state to Null. Reason: {error:?}");
+                        }
+                        break;
+                    }
+                }
+
                 // First, drop the current state
-                if let Some(state) = state.write().await.take() {
-                    drop(state);
+                let previous_state = state.write().await.take();
+                if let Some(state) = previous_state {
+                    restart_count.fetch_add(1, Ordering::Relaxed);
+
+                    // Keep any configuration changed while it was running
+                    video_and_stream_information.stream_information = state
+                        .video_and_stream_information
+                        .stream_information
+                        .clone();
+                    if let Some(error) = state
+                        .pipeline
+                        .inner_state_as_ref()
+                        .pipeline_runner
+                        .last_error()
+                    {
+                        *last_error.lock().unwrap() = Some(error);
+                    }
+
+                    // Dropping the state drains the EOS through the pipeline, which blocks up to its configured timeout
+                    let dropped = tokio::task::spawn_blocking(move || drop(state));
+                    // Tearing a wedged one down may hang on the same GStreamer calls, so don't let it hold the watcher
+                    if !wedged {
+                        if let Err(error) = dropped.await {
+                            warn!("Failed draining the stream pipeline: {error:?}");
+                        }
+                    }
+                }
+                device_removed = false;
+
+                let delay = restart_backoff.next_delay();
+                debug!(
+                    "Restarting stream {pipeline_id:?} in {delay:?} (attempt {})",
+                    restart_backoff.attempts()
+                );
+                publish_event(
+                    pipeline_id,
+                    PipelineEventKind::Restarting {
+                        attempt: restart_backoff.attempts(),
+                        delay_ms: delay.as_millis() as u64,
+                    },
+                );
+                // A camera being plugged in might be our device coming back, so try it right away
+                let plugged = async {
+                    loop {
+                        if let Ok(DeviceEvent::Added(device)) = device_events.recv().await {
+                            break device;
+                        }
+                    }
+                };
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    device = plugged => {
+                        debug!("Device {device:?} was connected, retrying stream {pipeline_id:?} now");
+                        restart_backoff.reset();
+                    }
+                }
+
+                if *terminated.read().await {
+                    debug!("Ending stream {pipeline_id:?}.");
+                    break;
                 }
 
                 // If it's a camera, try to update the device
@@ -127,8 +1126,15 @@ impl Stream {
                         .unwrap()
                         .iter()
                         .filter_map(|status| {
-                            status
-                                .running
+                            // Unless both share the same capture of it
+                            let shared_capture = video_and_stream_information
+                                .stream_information
+                                .shared_capture();
+                            let shared = shared_capture.is_some()
+                                && status.video_and_stream.stream_information.shared_capture()
+                                    == shared_capture;
+
+                            (status.running && !shared)
                                 .then_some(status.video_and_stream.video_source.clone())
                         })
                         .collect::<Vec<VideoSourceType>>();
@@ -186,6 +1192,7 @@ impl Stream {
 
                 // Try to recreate the stream
                 state.write().await.replace(new_state);
+                restart_backoff.started();
             }
 
             if *terminated.read().await {
@@ -241,9 +1248,7 @@ impl StreamState {
         video_and_stream_information: &VideoAndStreamInformation,
         pipeline_id: &uuid::Uuid,
     ) -> Result<Self> {
-        if let Err(error) = validate_endpoints(video_and_stream_information) {
-            return Err(anyhow!("Failed validating endpoints. Reason: {error:?}"));
-        }
+        validate_configuration(video_and_stream_information)?;
 
         let pipeline = Pipeline::try_new(video_and_stream_information, pipeline_id)?;
 
@@ -252,6 +1257,7 @@ impl StreamState {
             pipeline,
             video_and_stream_information: video_and_stream_information.clone(),
             mavlink_camera: None,
+            streaming_tracker: None,
         };
 
         // Do not add any Sink if it's a redirect Pipeline
@@ -261,16 +1267,9 @@ impl StreamState {
         ) {
             let endpoints = &video_and_stream_information.stream_information.endpoints;
 
-            // Disable concurrent RTSP and UDP sinks creation, as it is failing.
-            if endpoints.iter().any(|endpoint| endpoint.scheme() == "udp")
-                && endpoints.iter().any(|endpoint| endpoint.scheme() == "rtsp")
-            {
-                return Err(anyhow!(
-                    "UDP endpoints won't work together with RTSP endpoints. You need to choose one. This is a (temporary) software limitation, if this is a feature you need, please, contact us."
-                ));
-            }
+            let is_udp = |endpoint: &url::Url| matches!(endpoint.scheme(), "udp" | "udp265");
 
-            if endpoints.iter().any(|endpoint| endpoint.scheme() == "udp") {
+            if endpoints.iter().any(is_udp) {
                 if let Err(reason) =
                     create_udp_sink(Manager::generate_uuid(), video_and_stream_information)
                         .and_then(|sink| stream.pipeline.add_sink(sink))
@@ -295,6 +1294,24 @@ impl StreamState {
                 }
             }
 
+            // Each SRT endpoint has its own connection
+            for endpoint in endpoints
+                .iter()
+                .filter(|endpoint| endpoint.scheme() == "srt")
+            {
+                if let Err(reason) = create_srt_sink(
+                    Manager::generate_uuid(),
+                    endpoint,
+                    video_and_stream_information,
+                )
+                .and_then(|sink| stream.pipeline.add_sink(sink))
+                {
+                    return Err(anyhow!(
+                        "Failed to add Sink of type SRT to the Pipeline. Reason: {reason}"
+                    ));
+                }
+            }
+
             if let Err(reason) =
                 create_image_sink(Manager::generate_uuid(), video_and_stream_information)
                     .and_then(|sink| stream.pipeline.add_sink(sink))
@@ -304,6 +1321,17 @@ impl StreamState {
                 ));
             }
 
+            // Subscribe before starting, so the pipeline can't reach Playing unnoticed
+            if matches!(
+                &video_and_stream_information.video_source,
+                VideoSourceType::Local(_)
+            ) {
+                stream.streaming_tracker = Some(spawn_streaming_tracker(
+                    video_and_stream_information.video_source.clone(),
+                    *pipeline_id,
+                ));
+            }
+
             // Start the pipeline. This will automatically start sinks with linked proxy-isolated pipelines
             stream
                 .pipeline
@@ -323,25 +1351,135 @@ impl StreamState {
                 .stream_information
                 .extended_configuration,
             Some(ExtendedConfiguration {
-                thermal: _,
                 disable_mavlink: false,
+                ..
             })
         ) {
-            stream.mavlink_camera = MavlinkCamera::try_new(video_and_stream_information)
-                .await
-                .ok();
+            stream.mavlink_camera =
+                MavlinkCamera::try_new(video_and_stream_information, pipeline_id)
+                    .await
+                    .inspect_err(|error| warn!("Stream without a MAVLink camera: {error:?}"))
+                    .ok();
         }
 
         Ok(stream)
     }
+
+    /// Recovers the sinks from a change of the host's network, if this stream has a reconnection policy enabled
+    #[instrument(level = "debug", skip(self), fields(pipeline_id = self.pipeline_id.to_string()))]
+    pub fn reconnect(&self) -> Result<()> {
+        let Some(reconnection) = self
+            .video_and_stream_information
+            .stream_information
+            .reconnection()
+        else {
+            return Ok(());
+        };
+
+        info!("Reconnecting stream after a network change");
+
+        let pipeline_state = self.pipeline.inner_state_as_ref();
+
+        for sink in pipeline_state.sinks.values() {
+            if let Sink::Udp(udp_sink) = sink {
+                udp_sink.reconnect();
+            }
+        }
+
+        // RTSP clients don't notice their TCP connection went stale, so close them to make them reconnect
+        for client in RTSPServer::clients(&self.pipeline_id) {
+            if let Err(error) = RTSPServer::disconnect_client(&self.pipeline_id, &client.id) {
+                warn!(
+                    "Failed to disconnect RTSP client {:?}: {error:?}",
+                    client.id
+                );
+            }
+        }
+
+        if reconnection.force_keyframe {
+            pipeline_state.force_keyframe()?;
+        }
+
+        Ok(())
+    }
+
+    /// Ends all WebRTC sessions and RTSP connections of this stream, telling the WebRTC peers the reason
+    #[instrument(level = "debug", skip(self), fields(pipeline_id = self.pipeline_id.to_string()))]
+    pub fn disconnect_clients(&self, reason: &str) {
+        for sink in self.pipeline.inner_state_as_ref().sinks.values() {
+            if let Sink::WebRTC(webrtc_sink) = sink {
+                if let Err(error) = webrtc_sink.terminate(reason.to_string()) {
+                    warn!("Failed to end WebRTC session: {error:?}");
+                }
+            }
+        }
+
+        for client in RTSPServer::clients(&self.pipeline_id) {
+            if let Err(error) = RTSPServer::disconnect_client(&self.pipeline_id, &client.id) {
+                warn!(
+                    "Failed to disconnect RTSP client {:?}: {error:?}",
+                    client.id
+                );
+            }
+        }
+    }
+
+    /// Changes the extended configuration of this stream. The watcher carries it over when rebuilding the pipeline,
+    /// so the change outlives it
+    pub fn update_extended_configuration(
+        &mut self,
+        update: impl FnOnce(&mut ExtendedConfiguration),
+    ) {
+        update(
+            self.video_and_stream_information
+                .stream_information
+                .extended_configuration
+                .get_or_insert_with(Default::default),
+        );
+    }
+
+    /// How many WebRTC sessions and RTSP clients are watching this stream
+    pub fn client_count(&self) -> usize {
+        let webrtc_sessions = self
+            .pipeline
+            .inner_state_as_ref()
+            .sinks
+            .values()
+            .filter(|sink| matches!(sink, Sink::WebRTC(_)))
+            .count();
+
+        webrtc_sessions + RTSPServer::clients(&self.pipeline_id).len()
+    }
+
+    /// If one more client can watch this stream, according to its `max_clients`
+    pub fn has_free_client_slot(&self) -> bool {
+        self.video_and_stream_information
+            .stream_information
+            .max_clients()
+            .map_or(true, |max_clients| {
+                self.client_count() < max_clients as usize
+            })
+    }
 }
 
 impl Drop for StreamState {
     #[instrument(level = "debug", skip(self), fields(pipeline_id = self.pipeline_id.to_string()))]
     fn drop(&mut self) {
+        if let Some(streaming_tracker) = self.streaming_tracker.take() {
+            streaming_tracker.abort();
+            crate::video::video_source::set_streaming(
+                &self.video_and_stream_information.video_source,
+                &self.pipeline_id,
+                false,
+            );
+        }
+
         let pipeline_state = self.pipeline.inner_state_as_ref();
         let pipeline = &pipeline_state.pipeline;
 
+        // Give the EOS a chance to flush through the pipeline before tearing it down
+        pipeline_state.pipeline_runner.drain_eos();
+
         let pipeline_weak = pipeline.downgrade();
         std::thread::spawn(move || {
             let pipeline = pipeline_weak.upgrade().unwrap();
@@ -375,7 +1513,241 @@ impl Drop for StreamState {
     }
 }
 
+/// Marks the camera as streaming while the pipeline is Playing, so the controls waiting for it get written
+fn spawn_streaming_tracker(
+    video_source: VideoSourceType,
+    pipeline_id: uuid::Uuid,
+) -> tokio::task::JoinHandle<()> {
+    let mut events = pipeline::runner::subscribe_events();
+
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            };
+            if event.pipeline_id != pipeline_id {
+                continue;
+            }
+
+            let is_streaming = match event.kind {
+                PipelineEventKind::Playing => true,
+                PipelineEventKind::Stopped { .. } => false,
+                _ => continue,
+            };
+            crate::video::video_source::set_streaming(&video_source, &pipeline_id, is_streaming);
+        }
+    })
+}
+
 #[instrument(level = "debug")]
+/// Checks the stream configuration, before anything is created for it
+pub fn validate_configuration(
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<()> {
+    if let Err(error) = validate_endpoints(video_and_stream_information) {
+        return Err(anyhow!("Failed validating endpoints. Reason: {error:?}"));
+    }
+
+    if let Some(keyframe_interval) = video_and_stream_information
+        .stream_information
+        .keyframe_interval()
+    {
+        encoders::validate_keyframe_interval(keyframe_interval)?;
+    }
+
+    if let Some(rate_control) = video_and_stream_information
+        .stream_information
+        .rate_control()
+    {
+        encoders::validate_rate_control(rate_control)?;
+    }
+
+    if let Some(h264_profile) = video_and_stream_information
+        .stream_information
+        .h264_profile()
+    {
+        encoders::validate_h264_profile(h264_profile)?;
+    }
+
+    if let Some(overlay) = video_and_stream_information.stream_information.overlay() {
+        pipeline::overlay::validate(overlay)?;
+    }
+
+    if let Some(queues) = video_and_stream_information.stream_information.queues() {
+        pipeline::queues::validate(queues)?;
+    }
+
+    if let Some(pipeline_runner) = video_and_stream_information
+        .stream_information
+        .pipeline_runner()
+    {
+        pipeline::runner::validate(pipeline_runner)?;
+    }
+
+    if video_and_stream_information
+        .stream_information
+        .clip_pre_roll_secs()
+        > pipeline::clip::MAX_CLIP_PRE_ROLL_SECS
+    {
+        return Err(anyhow!(
+            "Clip pre-roll should be at most {} seconds",
+            pipeline::clip::MAX_CLIP_PRE_ROLL_SECS
+        ));
+    }
+
+    if let Some(output_resolution) = video_and_stream_information
+        .stream_information
+        .output_resolution()
+    {
+        pipeline::scale::validate(output_resolution)?;
+    }
+
+    if let Some(clock) = video_and_stream_information.stream_information.clock() {
+        pipeline::clock::validate(clock)?;
+    }
+
+    if let Some(focal_length_mm) = video_and_stream_information
+        .stream_information
+        .focal_length_mm()
+    {
+        if !(focal_length_mm.is_finite() && focal_length_mm > 0.0) {
+            return Err(anyhow!(
+                "Focal length of {focal_length_mm} mm should be a positive number"
+            ));
+        }
+    }
+
+    if let Some(max_framerate) = video_and_stream_information
+        .stream_information
+        .max_framerate()
+    {
+        if let CaptureConfiguration::Video(configuration) = &video_and_stream_information
+            .stream_information
+            .configuration
+        {
+            pipeline::frame_rate::validate(max_framerate, &configuration.frame_interval)?;
+        }
+    }
+
+    if let Some(multicast) = video_and_stream_information.stream_information.multicast() {
+        sink::udp_sink::validate_multicast(
+            &video_and_stream_information.stream_information.endpoints,
+            multicast,
+        )?;
+    }
+
+    if let Some(udp_socket) = video_and_stream_information.stream_information.udp_socket() {
+        sink::udp_sink::validate_socket(udp_socket)?;
+    }
+
+    if let Some(rtp) = video_and_stream_information.stream_information.rtp() {
+        rtp::validate(rtp)?;
+
+        if video_and_stream_information
+            .stream_information
+            .audio()
+            .is_some()
+            && rtp.payload_type.map(u32::from) == Some(pipeline::audio::AUDIO_PAYLOAD_TYPE)
+        {
+            return Err(anyhow!(
+                "RTP payload type {} is used by the audio",
+                pipeline::audio::AUDIO_PAYLOAD_TYPE
+            ));
+        }
+    }
+
+    if let Some(audio) = video_and_stream_information.stream_information.audio() {
+        pipeline::audio::validate(audio)?;
+    }
+
+    if let Some(webrtc_codecs) = video_and_stream_information
+        .stream_information
+        .webrtc_codecs()
+    {
+        webrtc::codecs::validate(
+            webrtc_codecs,
+            webrtc::codecs::native(&video_and_stream_information.stream_information),
+        )?;
+    }
+
+    if let Some(element_properties) = video_and_stream_information
+        .stream_information
+        .element_properties()
+    {
+        pipeline::properties::validate(element_properties)?;
+    }
+
+    if let Some(shared_capture) = video_and_stream_information
+        .stream_information
+        .shared_capture()
+    {
+        if !matches!(
+            video_and_stream_information.video_source,
+            VideoSourceType::Local(_)
+        ) {
+            return Err(anyhow!("Only local devices can have a shared capture"));
+        }
+
+        pipeline::shared_capture::caps(shared_capture)?;
+    }
+
+    if let Some(webrtc_layers) = video_and_stream_information
+        .stream_information
+        .webrtc_layers()
+    {
+        match &video_and_stream_information
+            .stream_information
+            .configuration
+        {
+            CaptureConfiguration::Video(configuration) => pipeline::layers::validate(
+                webrtc_layers,
+                &configuration.encode,
+                configuration.width,
+                configuration.height,
+            )?,
+            CaptureConfiguration::Redirect(_) => {
+                return Err(anyhow!(
+                    "WebRTC layers are not available for redirect streams"
+                ))
+            }
+        }
+    }
+
+    if let Some(ice_servers) = video_and_stream_information
+        .stream_information
+        .ice_servers()
+    {
+        webrtc::ice_servers::validate(ice_servers)?;
+    }
+
+    if video_and_stream_information
+        .stream_information
+        .max_clients()
+        == Some(0)
+    {
+        return Err(anyhow!("Maximum number of clients should be at least 1"));
+    }
+
+    // Disable concurrent RTSP and UDP sinks creation, as it is failing.
+    let endpoints = &video_and_stream_information.stream_information.endpoints;
+    let is_udp = |endpoint: &url::Url| matches!(endpoint.scheme(), "udp" | "udp265");
+
+    if !matches!(
+        &video_and_stream_information.video_source,
+        VideoSourceType::Redirect(_)
+    ) && endpoints.iter().any(is_udp)
+        && endpoints.iter().any(|endpoint| endpoint.scheme() == "rtsp")
+    {
+        return Err(anyhow!(
+            "UDP endpoints won't work together with RTSP endpoints. You need to choose one. This is a (temporary) software limitation, if this is a feature you need, please, contact us."
+        ));
+    }
+
+    Ok(())
+}
+
 fn validate_endpoints(video_and_stream_information: &VideoAndStreamInformation) -> Result<()> {
     let endpoints = &video_and_stream_information.stream_information.endpoints;
 
@@ -436,10 +1808,6 @@ fn validate_endpoints(video_and_stream_information: &VideoAndStreamInformation)
 
         match scheme {
             "udp" => {
-                if VideoEncodeType::H265 == encode {
-                    return Some(anyhow!("Endpoint with udp scheme only supports H264, encode type is H265, the scheme should be udp265."));
-                }
-
                 // UDP endpoints should contain both host and port
                 if endpoint.host().is_none() || endpoint.port().is_none()
                 {
@@ -448,10 +1816,27 @@ fn validate_endpoints(video_and_stream_information: &VideoAndStreamInformation)
                     ));
                 }
             }
+            "srt" => {
+                if !matches!(encode, VideoEncodeType::H264 | VideoEncodeType::H265) {
+                    return Some(anyhow!("Endpoint with srt scheme only supports H264 and H265 encodes. Encode: {encode:?}, Endpoint: {endpoint:?}"));
+                }
+
+                if let Err(error) = sink::srt_sink::SrtSettings::try_from(endpoint) {
+                    return Some(error);
+                }
+            }
+            // Kept for compatibility, "udp" also supports H265
             "udp265" => {
                 if VideoEncodeType::H265 != encode {
                     return Some(anyhow!("Endpoint with udp265 scheme only supports H265 encode. Encode: {encode:?}, Endpoint: {endpoints:?}"));
                 }
+
+                if endpoint.host().is_none() || endpoint.port().is_none()
+                {
+                    return Some(anyhow!(
+                        "Endpoint with udp265 scheme should contain host and port. Endpoint: {endpoint:?}"
+                    ));
+                }
             }
             _ => {
                 return Some(anyhow!(
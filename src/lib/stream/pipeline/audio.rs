@@ -0,0 +1,200 @@
+use anyhow::{anyhow, Context, Result};
+use gst::prelude::*;
+use tracing::*;
+
+use crate::stream::types::{AudioBackend, AudioConfiguration, AudioDevice, AudioEncodeType};
+
+pub const PIPELINE_AUDIO_TEE_NAME: &str = "AudioTee";
+pub const PIPELINE_AUDIO_RTP_TEE_NAME: &str = "AudioRTPTee";
+
+/// The RTP payload type of the audio, as the video uses 96
+pub const AUDIO_PAYLOAD_TYPE: u32 = 97;
+
+const DEFAULT_BITRATE_KBPS: u32 = 64;
+const MIN_BITRATE_KBPS: u32 = 16;
+const MAX_BITRATE_KBPS: u32 = 512;
+
+/// AAC encoders, in order of preference
+const AAC_ENCODERS: &[&str] = &["fdkaacenc", "avenc_aac", "voaacenc"];
+
+#[instrument(level = "debug")]
+pub fn validate(configuration: &AudioConfiguration) -> Result<()> {
+    if let Some(bitrate) = configuration.bitrate {
+        if !(MIN_BITRATE_KBPS..=MAX_BITRATE_KBPS).contains(&bitrate) {
+            return Err(anyhow!(
+                "Audio bitrate should be in the range {MIN_BITRATE_KBPS}..={MAX_BITRATE_KBPS} kbit/s, but was {bitrate}"
+            ));
+        }
+    }
+
+    if matches!(&configuration.device, Some(device) if device.trim().is_empty()) {
+        return Err(anyhow!("Audio device can't be empty"));
+    }
+
+    Ok(())
+}
+
+/// The audio branch, from the capture device to the Tees of the encoded audio and of its RTP packets
+pub fn description(pipeline_id: &uuid::Uuid, configuration: &AudioConfiguration) -> Result<String> {
+    let source = match configuration.backend {
+        AudioBackend::Alsa => "alsasrc",
+        AudioBackend::Pulse => "pulsesrc",
+    };
+    let device = configuration
+        .device
+        .as_ref()
+        .map(|device| format!(" device={device:?}"))
+        .unwrap_or_default();
+
+    let bitrate = configuration.bitrate.unwrap_or(DEFAULT_BITRATE_KBPS) * 1000;
+    let (encoder, payloader) = match configuration.encode {
+        AudioEncodeType::Opus => (format!("opusenc bitrate={bitrate}"), "rtpopuspay"),
+        AudioEncodeType::Aac => {
+            let encoder = AAC_ENCODERS
+                .iter()
+                .find(|encoder| gst::ElementFactory::find(encoder).is_some())
+                .context(format!(
+                    "No AAC encoder available, the supported are: {AAC_ENCODERS:?}"
+                ))?;
+
+            (
+                format!("{encoder} bitrate={bitrate} ! aacparse"),
+                "rtpmp4gpay",
+            )
+        }
+    };
+
+    Ok(format!(
+        concat!(
+            "{source}{device}",
+            // Decouples the capture from the encoding, so a slow encoder doesn't make the device overrun
+            " ! queue",
+            " ! audioconvert",
+            " ! audioresample",
+            " ! audio/x-raw,rate=48000",
+            " ! {encoder}",
+            " ! tee name={audio_tee_name} allow-not-linked=true",
+            " ! {payloader} pt={payload_type}",
+            " ! tee name={audio_rtp_tee_name} allow-not-linked=true",
+        ),
+        source = source,
+        device = device,
+        encoder = encoder,
+        audio_tee_name = format!("{PIPELINE_AUDIO_TEE_NAME}-{pipeline_id}"),
+        payloader = payloader,
+        payload_type = AUDIO_PAYLOAD_TYPE,
+        audio_rtp_tee_name = format!("{PIPELINE_AUDIO_RTP_TEE_NAME}-{pipeline_id}"),
+    ))
+}
+
+/// Adds the audio branch to the pipeline, returning its encoded audio Tee and its RTP Tee.
+///
+/// The device is opened beforehand, so an absent or busy device is reported here instead of failing the whole
+/// pipeline. As both branches share the pipeline's clock, the audio and video timestamps are in sync.
+#[instrument(level = "debug", skip(pipeline))]
+pub fn add_branch(
+    pipeline: &gst::Pipeline,
+    pipeline_id: &uuid::Uuid,
+    configuration: &AudioConfiguration,
+) -> Result<(gst::Element, gst::Element)> {
+    validate(configuration)?;
+
+    let description = description(pipeline_id, configuration)?;
+    debug!("audio description: {description:#?}");
+
+    let bin = gst::parse::bin_from_description(&description, false)?;
+
+    if let Err(error) = bin.set_state(gst::State::Ready) {
+        let _ = bin.set_state(gst::State::Null);
+        return Err(anyhow!(
+            "Failed opening the {:?} audio device {:?}: {error:?}",
+            configuration.backend,
+            configuration.device.as_deref().unwrap_or("default"),
+        ));
+    }
+    bin.set_state(gst::State::Null)?;
+
+    let audio_tee = bin
+        .by_name(&format!("{PIPELINE_AUDIO_TEE_NAME}-{pipeline_id}"))
+        .context("Audio branch without its Tee")?;
+    let audio_rtp_tee = bin
+        .by_name(&format!("{PIPELINE_AUDIO_RTP_TEE_NAME}-{pipeline_id}"))
+        .context("Audio branch without its RTP Tee")?;
+
+    pipeline.add(&bin)?;
+
+    Ok((audio_tee, audio_rtp_tee))
+}
+
+/// Waits for the audio to flow through the given Tee, returning its caps
+#[instrument(level = "debug")]
+pub fn wait_for_caps(tee: &gst::Element) -> Option<gst::Caps> {
+    let pad = tee.static_pad("sink")?;
+
+    // Audio sources start producing only once playing, which can take a few buffers' time
+    for _ in 0..10 {
+        if let Some(caps) = pad.current_caps() {
+            return Some(caps);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    None
+}
+
+/// The audio capture devices currently available
+#[instrument(level = "debug")]
+pub fn available_devices() -> Vec<AudioDevice> {
+    let monitor = gst::DeviceMonitor::new();
+    monitor.add_filter(Some("Audio/Source"), None);
+
+    if let Err(error) = monitor.start() {
+        warn!("Failed to start the audio device monitor: {error:?}");
+        return vec![];
+    }
+
+    let devices = monitor
+        .devices()
+        .iter()
+        .filter_map(|device| {
+            let element = device.create_element(None).ok()?;
+            let backend = match element.factory()?.name().as_str() {
+                "alsasrc" => AudioBackend::Alsa,
+                "pulsesrc" => AudioBackend::Pulse,
+                _ => return None,
+            };
+
+            Some(AudioDevice {
+                name: device.display_name().to_string(),
+                backend,
+                device: element.property::<Option<String>>("device")?,
+            })
+        })
+        .collect();
+
+    monitor.stop();
+
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&AudioConfiguration::default()).is_ok());
+
+        let configuration = AudioConfiguration {
+            bitrate: Some(8),
+            ..Default::default()
+        };
+        assert!(validate(&configuration).is_err());
+
+        let configuration = AudioConfiguration {
+            device: Some(" ".into()),
+            ..Default::default()
+        };
+        assert!(validate(&configuration).is_err());
+    }
+}
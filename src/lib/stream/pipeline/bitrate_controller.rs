@@ -0,0 +1,506 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, Weak},
+};
+
+use gst::prelude::*;
+use tracing::*;
+
+use super::layers;
+use crate::stream::types::{RateControlConfiguration, RateControlMode};
+
+/// Above this fraction of lost packets the target bitrate is decreased
+const LOSS_DECREASE_THRESHOLD: f64 = 0.1;
+/// Below this fraction of lost packets the target bitrate is increased
+const LOSS_INCREASE_THRESHOLD: f64 = 0.02;
+/// Multiplicative increase applied on each update without significant losses
+const INCREASE_FACTOR: f64 = 1.05;
+/// Interval between each poll of the sessions' RTCP statistics
+const ADAPTATION_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Computes the next target bitrate from the fraction of lost packets reported by the receiver,
+/// following the loss-based controller from the Google Congestion Control algorithm
+pub fn next_target_bitrate(current: u64, fraction_lost: f64, min: u64, max: u64) -> u64 {
+    let current = current as f64;
+
+    let next = if fraction_lost > LOSS_DECREASE_THRESHOLD {
+        current * (1.0 - 0.5 * fraction_lost)
+    } else if fraction_lost < LOSS_INCREASE_THRESHOLD {
+        current * INCREASE_FACTOR
+    } else {
+        current
+    };
+
+    (next as u64).clamp(min, max)
+}
+
+/// Reads the bitrate estimated by the receiver from the FCI of a REMB (Receiver Estimated Maximum Bitrate)
+/// feedback message, in bits per second
+pub fn remb_bitrate(fci: &[u8]) -> Option<u64> {
+    // "REMB", the number of SSRCs, and the bitrate as a 6 bits exponent and an 18 bits mantissa
+    let [b'R', b'E', b'M', b'B', _num_ssrc, exponent_mantissa, mantissa_1, mantissa_2, ..] = *fci
+    else {
+        return None;
+    };
+
+    let exponent = exponent_mantissa >> 2;
+    let mantissa = (u64::from(exponent_mantissa & 0x03) << 16)
+        | (u64::from(mantissa_1) << 8)
+        | u64::from(mantissa_2);
+
+    mantissa.checked_shl(exponent as u32)
+}
+
+/// Adapts a WebRTC session to the feedback from its peer, driven by the adaptation task of its pipeline
+pub trait SessionAdaptation: std::fmt::Debug + Send {
+    /// The WebRTCBin whose statistics are read, or none once the session is gone
+    fn webrtcbin(&self) -> Option<gst::Element>;
+
+    /// Steps the session with its latest statistics
+    fn update(&mut self, controller: &BitrateController, stats: &gst::StructureRef);
+}
+
+/// Aggregates the target bitrates of all WebRTC sessions of a pipeline, applying the lowest one to
+/// the pipeline's encoder, as the encoded stream is shared by all of them
+#[derive(Debug)]
+pub struct BitrateController {
+    pipeline_weak: gst::glib::WeakRef<gst::Pipeline>,
+    min: u64,
+    max: u64,
+    targets: Mutex<HashMap<uuid::Uuid, u64>>,
+    /// The sessions receiving a lower quality layer, which don't constrain the encoder of the stream itself
+    layered_sessions: Mutex<HashSet<uuid::Uuid>>,
+    /// The rate control of the stream, whose bitrate caps the sessions' targets
+    rate_control: Mutex<Option<RateControlConfiguration>>,
+    /// The encoder's bitrate before the sessions changed it, restored once they are gone
+    original_bitrate: Mutex<Option<u64>>,
+    adaptations: Mutex<HashMap<uuid::Uuid, Box<dyn SessionAdaptation>>>,
+    /// Polls the statistics of all sessions, only running while there is any
+    adaptation_task: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl Drop for BitrateController {
+    fn drop(&mut self) {
+        if let Some(adaptation_task) = self.adaptation_task.lock().unwrap().take() {
+            adaptation_task.abort();
+        }
+    }
+}
+
+impl BitrateController {
+    pub fn new(pipeline: &gst::Pipeline, min: u64, max: u64) -> Self {
+        Self {
+            pipeline_weak: pipeline.downgrade(),
+            min,
+            max,
+            targets: Default::default(),
+            layered_sessions: Default::default(),
+            rate_control: Default::default(),
+            original_bitrate: Default::default(),
+            adaptations: Default::default(),
+            adaptation_task: Default::default(),
+        }
+    }
+
+    /// The limits of the sessions' targets, in bits per second, where the maximum is capped by the bitrate
+    /// configured for the stream
+    pub fn limits(&self) -> (u64, u64) {
+        let max = self
+            .configured_max_bitrate()
+            .map_or(self.max, |configured| configured.min(self.max))
+            .max(self.min);
+
+        (self.min, max)
+    }
+
+    /// Changes the rate control of the stream, capping the targets of the current sessions with its bitrate
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_rate_control(&self, rate_control: Option<RateControlConfiguration>) {
+        *self.rate_control.lock().unwrap() = rate_control;
+
+        let (min, max) = self.limits();
+        let mut targets = self.targets.lock().unwrap();
+        if targets.is_empty() {
+            return;
+        }
+        for target in targets.values_mut() {
+            *target = (*target).clamp(min, max);
+        }
+
+        self.apply(&targets);
+    }
+
+    /// If the encoder's bitrate follows the sessions, which isn't the case with a constant quantizer
+    fn is_adaptive(&self) -> bool {
+        !matches!(
+            self.rate_control.lock().unwrap().as_ref(),
+            Some(RateControlConfiguration {
+                mode: RateControlMode::Cqp,
+                ..
+            })
+        )
+    }
+
+    /// The bitrate configured for the stream, in bits per second
+    fn configured_bitrate(&self) -> Option<u64> {
+        let rate_control = self.rate_control.lock().unwrap();
+        let rate_control = rate_control.as_ref()?;
+
+        match rate_control.mode {
+            RateControlMode::Cbr | RateControlMode::Vbr => rate_control
+                .bitrate
+                .map(|bitrate| u64::from(bitrate) * 1000),
+            RateControlMode::Cqp => None,
+        }
+    }
+
+    /// The highest bitrate configured for the stream, in bits per second: the VBR maximum, or its bitrate
+    fn configured_max_bitrate(&self) -> Option<u64> {
+        let rate_control = self.rate_control.lock().unwrap();
+        let rate_control = rate_control.as_ref()?;
+
+        let bitrate = match rate_control.mode {
+            RateControlMode::Cbr => rate_control.bitrate,
+            RateControlMode::Vbr => rate_control.max_bitrate.or(rate_control.bitrate),
+            RateControlMode::Cqp => None,
+        }?;
+
+        Some(u64::from(bitrate) * 1000)
+    }
+
+    /// The current target bitrate of the given session, in bits per second
+    pub fn session_target(&self, session_id: &uuid::Uuid) -> Option<u64> {
+        self.targets.lock().unwrap().get(session_id).copied()
+    }
+
+    /// Updates the target bitrate of the given session, in bits per second
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_session_target(&self, session_id: &uuid::Uuid, bitrate: u64) {
+        let (min, max) = self.limits();
+        let bitrate = bitrate.clamp(min, max);
+
+        let mut targets = self.targets.lock().unwrap();
+        let previous = targets.insert(*session_id, bitrate);
+        if previous == Some(bitrate) {
+            return;
+        }
+
+        self.apply(&targets);
+    }
+
+    /// Tells if the given session is receiving a lower quality layer instead of the stream itself
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_session_layered(&self, session_id: &uuid::Uuid, layered: bool) {
+        let changed = {
+            let mut layered_sessions = self.layered_sessions.lock().unwrap();
+            if layered {
+                layered_sessions.insert(*session_id)
+            } else {
+                layered_sessions.remove(session_id)
+            }
+        };
+        if !changed {
+            return;
+        }
+
+        self.apply(&self.targets.lock().unwrap());
+    }
+
+    /// Starts adapting the session to the feedback from its peer, along with the other sessions of the pipeline
+    #[instrument(level = "debug", skip(self, adaptation))]
+    pub fn add_session(
+        self: &Arc<Self>,
+        session_id: &uuid::Uuid,
+        adaptation: Box<dyn SessionAdaptation>,
+    ) {
+        // With a constant quantizer, the bitrate isn't controlled at all
+        if !self.is_adaptive() {
+            debug!("Session {session_id} is not adapted, as the stream uses a constant quantizer");
+            return;
+        }
+
+        let (_min, max) = self.limits();
+        self.set_session_target(session_id, max);
+
+        self.adaptations
+            .lock()
+            .unwrap()
+            .insert(*session_id, adaptation);
+
+        let mut adaptation_task = self.adaptation_task.lock().unwrap();
+        if adaptation_task
+            .as_ref()
+            .map_or(true, |adaptation_task| adaptation_task.is_finished())
+        {
+            *adaptation_task = Some(tokio::spawn(adaptation_task_loop(Arc::downgrade(self))));
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub fn remove_session(&self, session_id: &uuid::Uuid) {
+        let no_adaptations = {
+            let mut adaptations = self.adaptations.lock().unwrap();
+            adaptations.remove(session_id);
+            adaptations.is_empty()
+        };
+        if no_adaptations {
+            if let Some(adaptation_task) = self.adaptation_task.lock().unwrap().take() {
+                adaptation_task.abort();
+            }
+        }
+
+        self.layered_sessions.lock().unwrap().remove(session_id);
+
+        let mut targets = self.targets.lock().unwrap();
+        if targets.remove(session_id).is_none() {
+            return;
+        }
+
+        self.apply(&targets);
+    }
+
+    fn apply(&self, targets: &HashMap<uuid::Uuid, u64>) {
+        if !self.is_adaptive() {
+            return;
+        }
+
+        let Some(pipeline) = self.pipeline_weak.upgrade() else {
+            return;
+        };
+
+        // The layers keep their own bitrates
+        let encoders = pipeline
+            .iterate_recurse()
+            .into_iter()
+            .flatten()
+            .filter(|element| !layers::is_layer_element(element))
+            .collect::<Vec<gst::Element>>();
+
+        let target = {
+            let layered_sessions = self.layered_sessions.lock().unwrap();
+            targets
+                .iter()
+                .filter(|(session_id, _)| !layered_sessions.contains(session_id))
+                .map(|(_, target)| *target)
+                .min()
+        };
+
+        let mut original_bitrate = self.original_bitrate.lock().unwrap();
+        let bitrate = match target {
+            Some(target) => {
+                if original_bitrate.is_none() {
+                    *original_bitrate = encoders.iter().find_map(encoder_bitrate);
+                }
+
+                target
+            }
+            // Without any session, go back to the configured bitrate, or the one the pipeline was built with
+            None => match self.configured_bitrate().or(original_bitrate.take()) {
+                Some(bitrate) => {
+                    original_bitrate.take();
+                    bitrate
+                }
+                None => return,
+            },
+        };
+
+        let mut found = false;
+        encoders.iter().for_each(|element| {
+            if set_encoder_bitrate(element, bitrate) {
+                found = true;
+            }
+        });
+
+        if !found {
+            trace!("No encoder with a configurable bitrate found, the target of {bitrate} bps is not applied");
+        }
+    }
+}
+
+/// Reads the statistics of every session of the pipeline, stepping each of them with their own
+async fn adaptation_task_loop(controller: Weak<BitrateController>) {
+    let mut period = tokio::time::interval(ADAPTATION_INTERVAL);
+    period.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        period.tick().await;
+
+        let Some(controller) = controller.upgrade() else {
+            break;
+        };
+
+        let session_ids = controller
+            .adaptations
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<uuid::Uuid>>();
+
+        for session_id in session_ids {
+            let webrtcbin = controller
+                .adaptations
+                .lock()
+                .unwrap()
+                .get(&session_id)
+                .and_then(|adaptation| adaptation.webrtcbin());
+            let Some(webrtcbin) = webrtcbin else {
+                continue;
+            };
+
+            let (promise, reply) = gst::Promise::new_future();
+            webrtcbin.emit_by_name::<()>("get-stats", &[&None::<gst::Pad>, &promise]);
+            drop(webrtcbin);
+
+            let stats = match reply.await {
+                Ok(Some(stats)) => stats,
+                Ok(None) | Err(_) => continue,
+            };
+
+            if let Some(adaptation) = controller.adaptations.lock().unwrap().get_mut(&session_id) {
+                adaptation.update(&controller, &stats);
+            }
+        }
+    }
+
+    debug!("Bitrate adaptation finished");
+}
+
+/// Reads the bitrate of known encoders, converting it to bits per second
+pub fn encoder_bitrate(element: &gst::Element) -> Option<u64> {
+    let (property, multiplier) = encoder_bitrate_property(element)?;
+
+    let pspec = element.find_property(property)?;
+    let value = if pspec.value_type() == u32::static_type() {
+        u64::from(element.property::<u32>(property))
+    } else if pspec.value_type() == i32::static_type() {
+        u64::try_from(element.property::<i32>(property)).ok()?
+    } else {
+        return None;
+    };
+
+    Some(value * multiplier)
+}
+
+/// The bitrate property of known encoders, and how many bits per second each of its units is
+fn encoder_bitrate_property(element: &gst::Element) -> Option<(&'static str, u64)> {
+    let factory = element.factory()?;
+
+    Some(match factory.name().as_str() {
+        // In kbit/s
+        "x264enc" | "x265enc" | "nvh264enc" | "nvh265enc" | "vaapih264enc" | "vaapih265enc"
+        | "msdkh264enc" | "msdkh265enc" => ("bitrate", 1000),
+        // In bit/s
+        "openh264enc" | "nvv4l2h264enc" | "nvv4l2h265enc" => ("bitrate", 1),
+        "vp8enc" | "vp9enc" | "omxh264enc" => ("target-bitrate", 1),
+        _ => return None,
+    })
+}
+
+/// Sets the bitrate of known encoders, converting it to the unit of each of them
+pub fn set_encoder_bitrate(element: &gst::Element, bitrate: u64) -> bool {
+    let Some((property, multiplier)) = encoder_bitrate_property(element) else {
+        return false;
+    };
+    let value = bitrate / multiplier;
+
+    let Some(pspec) = element.find_property(property) else {
+        return false;
+    };
+
+    // Some encoders use u32, others i32
+    let value = if pspec.value_type() == u32::static_type() {
+        (value.min(u32::MAX as u64) as u32).to_value()
+    } else if pspec.value_type() == i32::static_type() {
+        (value.min(i32::MAX as u64) as i32).to_value()
+    } else {
+        return false;
+    };
+
+    element.set_property_from_value(property, &value);
+    debug!("Encoder {:?} {property:?} set to {value:?}", element.name());
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_target_bitrate() {
+        let (min, max) = (300_000, 5_000_000);
+
+        // Heavy losses should step down quickly
+        assert_eq!(next_target_bitrate(4_000_000, 0.5, min, max), 3_000_000);
+        // Moderate losses hold the bitrate
+        assert_eq!(next_target_bitrate(4_000_000, 0.05, min, max), 4_000_000);
+        // No losses slowly step up
+        assert_eq!(next_target_bitrate(4_000_000, 0.0, min, max), 4_200_000);
+        // Always clamped
+        assert_eq!(next_target_bitrate(4_900_000, 0.0, min, max), max);
+        assert_eq!(next_target_bitrate(400_000, 1.0, min, max), min);
+    }
+
+    #[test]
+    fn test_rate_control_limits() {
+        gst::init().unwrap();
+
+        let pipeline = gst::Pipeline::new();
+        let controller = BitrateController::new(&pipeline, 300_000, 5_000_000);
+        assert_eq!(controller.limits(), (300_000, 5_000_000));
+        assert!(controller.is_adaptive());
+
+        let rate_control = |mode, bitrate, max_bitrate| RateControlConfiguration {
+            mode,
+            bitrate,
+            max_bitrate,
+            quality: None,
+        };
+
+        // Capped by the configured bitrate
+        controller.set_rate_control(Some(rate_control(RateControlMode::Cbr, Some(2000), None)));
+        assert_eq!(controller.limits(), (300_000, 2_000_000));
+        assert_eq!(controller.configured_bitrate(), Some(2_000_000));
+
+        // Or the VBR maximum
+        controller.set_rate_control(Some(rate_control(
+            RateControlMode::Vbr,
+            Some(2000),
+            Some(3000),
+        )));
+        assert_eq!(controller.limits(), (300_000, 3_000_000));
+
+        // Never above the WebRTC maximum
+        controller.set_rate_control(Some(rate_control(RateControlMode::Cbr, Some(8000), None)));
+        assert_eq!(controller.limits(), (300_000, 5_000_000));
+
+        controller.set_rate_control(Some(rate_control(RateControlMode::Cqp, None, None)));
+        assert!(!controller.is_adaptive());
+        assert_eq!(controller.configured_bitrate(), None);
+    }
+
+    #[test]
+    fn test_remb_bitrate() {
+        // 1 SSRC, exponent 3 and mantissa 250000: 2 Mbps
+        let mantissa: u32 = 250_000;
+        let fci = [
+            b'R',
+            b'E',
+            b'M',
+            b'B',
+            1,
+            (3 << 2) | (mantissa >> 16) as u8,
+            (mantissa >> 8) as u8,
+            mantissa as u8,
+            0x12,
+            0x34,
+            0x56,
+            0x78,
+        ];
+        assert_eq!(remb_bitrate(&fci), Some(2_000_000));
+
+        assert_eq!(remb_bitrate(b"GOOG1234"), None);
+        assert_eq!(remb_bitrate(b"REMB"), None);
+    }
+}
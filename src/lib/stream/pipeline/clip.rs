@@ -0,0 +1,390 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use gst::prelude::*;
+use tracing::*;
+
+/// The longest clip accepted, in seconds
+pub const MAX_CLIP_DURATION_SECS: u64 = 300;
+/// The longest pre-roll kept in memory, in seconds
+pub const MAX_CLIP_PRE_ROLL_SECS: u32 = 30;
+/// The most memory used by the pre-roll, which is shortened when exceeded, like for high bitrates
+const MAX_CLIP_PRE_ROLL_BYTES: usize = 64 * 1024 * 1024;
+/// Maximum time waiting for the muxer to finalize the clip after its last frame
+const FINALIZE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Records short clips of the encoded video on demand. The frames reaching the video Tee go through a gate into a
+/// separate muxing pipeline, which gets an EOS once the clip's duration is reached. The latest frames can be kept
+/// in memory, so clips start a few seconds before being requested
+#[derive(Debug)]
+pub struct ClipRecorder {
+    pad: gst::Pad,
+    probe_id: Mutex<Option<gst::PadProbeId>>,
+    inner: Arc<Mutex<ClipRecorderInner>>,
+}
+
+#[derive(Debug, Default)]
+struct ClipRecorderInner {
+    pre_roll: gst::ClockTime,
+    /// The frames of the last pre-roll, always starting on a keyframe
+    frames: VecDeque<gst::Buffer>,
+    frames_bytes: usize,
+    clips: Vec<ActiveClip>,
+}
+
+#[derive(Debug)]
+struct ActiveClip {
+    appsrc: gst_app::AppSrc,
+    start: Option<gst::ClockTime>,
+    end: Option<gst::ClockTime>,
+    duration: gst::ClockTime,
+}
+
+impl Drop for ClipRecorder {
+    fn drop(&mut self) {
+        if let Some(probe_id) = self.probe_id.lock().unwrap().take() {
+            self.pad.remove_probe(probe_id);
+        }
+    }
+}
+
+impl ClipRecorder {
+    /// Gates the frames going through the given pad, keeping the last `pre_roll` seconds of them
+    pub fn try_new(pad: &gst::Pad, pre_roll_secs: u32) -> Option<Self> {
+        let inner = Arc::new(Mutex::new(ClipRecorderInner {
+            pre_roll: gst::ClockTime::from_seconds(pre_roll_secs.min(MAX_CLIP_PRE_ROLL_SECS) as u64),
+            ..Default::default()
+        }));
+
+        let inner_cloned = inner.clone();
+        let probe_id = pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+            if let Some(gst::PadProbeData::Buffer(buffer)) = &info.data {
+                inner_cloned.lock().unwrap().push(buffer);
+            }
+
+            gst::PadProbeReturn::Ok
+        })?;
+
+        Some(Self {
+            pad: pad.clone(),
+            probe_id: Mutex::new(Some(probe_id)),
+            inner,
+        })
+    }
+
+    /// Changes how much video is kept in memory, dropping all of it when zero
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_pre_roll(&self, pre_roll_secs: u32) -> Result<()> {
+        if pre_roll_secs > MAX_CLIP_PRE_ROLL_SECS {
+            return Err(anyhow!(
+                "Clip pre-roll should be at most {MAX_CLIP_PRE_ROLL_SECS} seconds"
+            ));
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.pre_roll = gst::ClockTime::from_seconds(pre_roll_secs as u64);
+        if inner.pre_roll.is_zero() {
+            inner.frames = Default::default();
+            inner.frames_bytes = 0;
+        } else {
+            inner.trim();
+        }
+
+        Ok(())
+    }
+
+    /// Records the next `duration` of video to the given location as MP4, preceded by the pre-roll, returning once
+    /// the file is finalized. A zero duration saves only the pre-roll. If the stream stops flowing, the clip is
+    /// finalized with what was recorded
+    #[instrument(level = "debug", skip(self))]
+    pub async fn record(&self, location: &str, duration: std::time::Duration) -> Result<()> {
+        if duration.as_secs() > MAX_CLIP_DURATION_SECS {
+            return Err(anyhow!(
+                "Clip duration should be at most {MAX_CLIP_DURATION_SECS} seconds"
+            ));
+        }
+
+        if duration.is_zero() && self.inner.lock().unwrap().frames.is_empty() {
+            return Err(anyhow!("Nothing to save, as no video was kept in memory"));
+        }
+
+        let caps = self
+            .pad
+            .current_caps()
+            .context("The stream has no video flowing yet")?;
+        let parser = match caps.structure(0).map(|structure| structure.name().as_str()) {
+            Some("video/x-h264") => "h264parse",
+            Some("video/x-h265") => "h265parse",
+            Some("image/jpeg") => "jpegparse",
+            unsupported => {
+                return Err(anyhow!(
+                    "Unsupported video encoding for clips: {unsupported:?}. The supported are: H264, H265 and MJPG"
+                ))
+            }
+        };
+
+        let pipeline = gst::Pipeline::new();
+        // Unlimited, as the whole pre-roll is pushed at once
+        let appsrc = gst_app::AppSrc::builder()
+            .caps(&caps)
+            .format(gst::Format::Time)
+            .max_bytes(0)
+            .build();
+        let parser = gst::ElementFactory::make(parser).build()?;
+        let muxer = gst::ElementFactory::make("mp4mux").build()?;
+        let filesink = gst::ElementFactory::make("filesink")
+            .property("location", location)
+            .build()?;
+
+        let elements = [appsrc.upcast_ref(), &parser, &muxer, &filesink];
+        pipeline.add_many(elements)?;
+        gst::Element::link_many(elements)?;
+        pipeline.set_state(gst::State::Playing)?;
+
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let mut clip = ActiveClip {
+                appsrc: appsrc.clone(),
+                start: None,
+                // The duration counts from now, after the pre-roll. An instant replay has no end, so its newest
+                // frame isn't left out
+                end: match duration.is_zero() {
+                    true => Some(gst::ClockTime::MAX),
+                    false => inner
+                        .frames
+                        .back()
+                        .and_then(|frame| frame.pts())
+                        .map(|pts| pts + gst::ClockTime::from_nseconds(duration.as_nanos() as u64)),
+                },
+                duration: gst::ClockTime::from_nseconds(duration.as_nanos() as u64),
+            };
+            let recording = inner.frames.iter().all(|frame| clip.push(frame));
+            if duration.is_zero() {
+                // An instant replay is complete once the pre-roll is pushed
+                let _ = appsrc.end_of_stream();
+            } else if recording {
+                inner.clips.push(clip);
+            }
+        }
+
+        info!("Recording a clip of {duration:?} to {location:?}");
+
+        let bus = pipeline.bus().context("Clip pipeline without bus")?;
+        let appsrc_cloned = appsrc.clone();
+        let message = tokio::task::spawn_blocking(move || {
+            let wait = |timeout: std::time::Duration| {
+                bus.timed_pop_filtered(
+                    gst::ClockTime::from_nseconds(timeout.as_nanos() as u64),
+                    &[gst::MessageType::Eos, gst::MessageType::Error],
+                )
+            };
+
+            wait(duration + FINALIZE_TIMEOUT).or_else(|| {
+                warn!("The stream stopped flowing, finalizing the clip with what was recorded");
+                let _ = appsrc_cloned.end_of_stream();
+                wait(FINALIZE_TIMEOUT)
+            })
+        })
+        .await?;
+
+        self.inner
+            .lock()
+            .unwrap()
+            .clips
+            .retain(|clip| clip.appsrc != appsrc);
+        pipeline.set_state(gst::State::Null)?;
+
+        match message.as_ref().map(|message| message.view()) {
+            Some(gst::MessageView::Eos(_)) => {
+                info!("Clip {location:?} finalized");
+                Ok(())
+            }
+            Some(gst::MessageView::Error(error)) => Err(anyhow!(
+                "Failed recording the clip {location:?}: {}",
+                error.error()
+            )),
+            _ => Err(anyhow!(
+                "Timed out waiting for the clip {location:?} to be finalized"
+            )),
+        }
+    }
+}
+
+impl ClipRecorderInner {
+    fn push(&mut self, buffer: &gst::Buffer) {
+        if !self.pre_roll.is_zero() {
+            self.frames_bytes += buffer.size();
+            self.frames.push_back(buffer.clone());
+            self.trim();
+        }
+
+        self.clips.retain_mut(|clip| clip.push(buffer));
+    }
+
+    /// Drops the oldest group of pictures while the next one still covers the pre-roll, or while the memory limit
+    /// is exceeded
+    fn trim(&mut self) {
+        while self
+            .frames
+            .front()
+            .is_some_and(|frame| frame.flags().contains(gst::BufferFlags::DELTA_UNIT))
+        {
+            self.drop_frames(1);
+        }
+
+        let Some(newest) = self.frames.back().and_then(|frame| frame.pts()) else {
+            return;
+        };
+
+        while let Some(next_keyframe) = self
+            .frames
+            .iter()
+            .skip(1)
+            .position(|frame| !frame.flags().contains(gst::BufferFlags::DELTA_UNIT))
+            .map(|position| position + 1)
+        {
+            let Some(pts) = self.frames[next_keyframe].pts() else {
+                break;
+            };
+            if newest.saturating_sub(pts) < self.pre_roll
+                && self.frames_bytes <= MAX_CLIP_PRE_ROLL_BYTES
+            {
+                break;
+            }
+            self.drop_frames(next_keyframe);
+        }
+
+        // A single group of pictures over the limit is dropped, and the pre-roll restarts on the next keyframe
+        if self.frames_bytes > MAX_CLIP_PRE_ROLL_BYTES {
+            self.drop_frames(self.frames.len());
+        }
+    }
+
+    fn drop_frames(&mut self, count: usize) {
+        self.frames_bytes -= self
+            .frames
+            .drain(..count)
+            .map(|frame| frame.size())
+            .sum::<usize>();
+    }
+}
+
+impl ActiveClip {
+    /// Pushes the frame into the clip, starting on a keyframe, with timestamps from zero. Returns false once the
+    /// clip is complete
+    fn push(&mut self, buffer: &gst::Buffer) -> bool {
+        let Some(pts) = buffer.pts() else {
+            return true;
+        };
+
+        let start = match self.start {
+            Some(start) => start,
+            None if buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) => return true,
+            None => *self.start.insert(pts),
+        };
+
+        if pts >= *self.end.get_or_insert(start + self.duration) {
+            let _ = self.appsrc.end_of_stream();
+            return false;
+        }
+
+        let mut buffer = buffer.copy();
+        {
+            let buffer = buffer.make_mut();
+            buffer.set_pts(pts - start);
+            if let Some(dts) = buffer.dts() {
+                buffer.set_dts(dts.saturating_sub(start));
+            }
+        }
+
+        self.appsrc.push_buffer(buffer).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(seconds: u64, keyframe: bool, size: usize) -> gst::Buffer {
+        let mut buffer = gst::Buffer::with_size(size).unwrap();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(gst::ClockTime::from_seconds(seconds));
+            if !keyframe {
+                buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+            }
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_pre_roll() {
+        gst::init().unwrap();
+
+        let mut inner = ClipRecorderInner {
+            pre_roll: gst::ClockTime::from_seconds(3),
+            ..Default::default()
+        };
+
+        // Frames before the first keyframe can't be decoded
+        inner.push(&frame(0, false, 0));
+        assert!(inner.frames.is_empty());
+
+        // A keyframe each 2 seconds
+        for seconds in 1..=10 {
+            inner.push(&frame(seconds, seconds % 2 == 1, 0));
+        }
+
+        // The last 3 seconds are kept, from the keyframe before them
+        let pts: Vec<u64> = inner
+            .frames
+            .iter()
+            .map(|frame| frame.pts().unwrap().seconds())
+            .collect();
+        assert_eq!(pts, vec![7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_pre_roll_memory_limit() {
+        gst::init().unwrap();
+
+        let size = MAX_CLIP_PRE_ROLL_BYTES / 4;
+        let mut inner = ClipRecorderInner {
+            pre_roll: gst::ClockTime::from_seconds(MAX_CLIP_PRE_ROLL_SECS as u64),
+            ..Default::default()
+        };
+        let pts = |inner: &ClipRecorderInner| -> Vec<u64> {
+            inner
+                .frames
+                .iter()
+                .map(|frame| frame.pts().unwrap().seconds())
+                .collect()
+        };
+
+        // A keyframe each 2 seconds, so the oldest groups of pictures are dropped to stay under the limit
+        for seconds in 0..6 {
+            inner.push(&frame(seconds, seconds % 2 == 0, size));
+            assert!(inner.frames_bytes <= MAX_CLIP_PRE_ROLL_BYTES);
+        }
+        assert_eq!(pts(&inner), vec![2, 3, 4, 5]);
+        assert_eq!(inner.frames_bytes, 4 * size);
+
+        // A single group of pictures over the limit is dropped as a whole
+        for seconds in 6..=10 {
+            inner.push(&frame(seconds, seconds == 6, size));
+            assert!(inner.frames_bytes <= MAX_CLIP_PRE_ROLL_BYTES);
+        }
+        assert!(inner.frames.is_empty());
+        assert_eq!(inner.frames_bytes, 0);
+
+        // And the pre-roll restarts on the next keyframe
+        inner.push(&frame(11, false, size));
+        assert!(inner.frames.is_empty());
+        inner.push(&frame(12, true, size));
+        assert_eq!(pts(&inner), vec![12]);
+        assert_eq!(inner.frames_bytes, size);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use gst::prelude::*;
+use lazy_static::lazy_static;
+use tracing::*;
+
+use crate::stream::types::{ClockConfiguration, ClockSource, ClockStatus};
+
+const DEFAULT_NTP_PORT: u16 = 123;
+const DEFAULT_PTP_DOMAIN: u8 = 0;
+
+/// How long a network clock has to synchronize before the pipeline starts with it anyway
+const SYNC_TIMEOUT: gst::ClockTime = gst::ClockTime::from_seconds(5);
+
+type ClockKey = (ClockSource, Option<String>, Option<u16>, Option<u8>);
+
+lazy_static! {
+    /// The network clocks in use, so the pipelines using the same one share its connection and its time
+    static ref CLOCKS: Arc<Mutex<HashMap<ClockKey, gst::Clock>>> = Default::default();
+}
+
+#[instrument(level = "debug")]
+pub fn validate(configuration: &ClockConfiguration) -> Result<()> {
+    match configuration.source {
+        ClockSource::System | ClockSource::Ptp => {}
+        ClockSource::Ntp | ClockSource::Net => {
+            if configuration
+                .address
+                .as_ref()
+                .map_or(true, |address| address.trim().is_empty())
+            {
+                return Err(anyhow!(
+                    "Clock {:?} needs the address of its server",
+                    configuration.source
+                ));
+            }
+
+            if configuration.source == ClockSource::Net && configuration.port.is_none() {
+                return Err(anyhow!(
+                    "Clock {:?} needs the port of its server",
+                    configuration.source
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The clock of the configuration, reusing the one already created for the same source
+fn clock(configuration: &ClockConfiguration) -> Result<gst::Clock> {
+    if configuration.source == ClockSource::System {
+        return Ok(gst::SystemClock::obtain());
+    }
+
+    let key = (
+        configuration.source,
+        configuration.address.clone(),
+        configuration.port,
+        configuration.domain,
+    );
+    let mut clocks = CLOCKS.lock().unwrap();
+    if let Some(clock) = clocks.get(&key) {
+        return Ok(clock.clone());
+    }
+
+    let address = configuration.address.as_deref().unwrap_or_default();
+    let clock = match configuration.source {
+        ClockSource::System => unreachable!(),
+        ClockSource::Ntp => gst_net::NtpClock::new(
+            None,
+            address,
+            configuration.port.unwrap_or(DEFAULT_NTP_PORT) as i32,
+            gst::ClockTime::ZERO,
+        )
+        .upcast::<gst::Clock>(),
+        ClockSource::Net => gst_net::NetClientClock::new(
+            None,
+            address,
+            configuration.port.context("Missing the port")? as i32,
+            gst::ClockTime::ZERO,
+        )
+        .upcast::<gst::Clock>(),
+        ClockSource::Ptp => {
+            if !gst_net::PtpClock::is_initialized() {
+                gst_net::PtpClock::init(None, &[])?;
+            }
+
+            gst_net::PtpClock::new(
+                None,
+                configuration.domain.unwrap_or(DEFAULT_PTP_DOMAIN) as u32,
+            )?
+            .upcast::<gst::Clock>()
+        }
+    };
+
+    clocks.insert(key, clock.clone());
+
+    Ok(clock)
+}
+
+/// Makes the pipeline use the configured clock and base time, so its running time is on the same timeline of the
+/// other pipelines using them. Network clocks are given some time to synchronize first
+#[instrument(level = "debug", skip(pipeline))]
+pub fn apply(
+    pipeline: &gst::Pipeline,
+    pipeline_id: &uuid::Uuid,
+    configuration: &ClockConfiguration,
+) -> Result<()> {
+    let clock = clock(configuration)?;
+
+    if clock.wait_for_sync(SYNC_TIMEOUT).is_err() {
+        warn!(
+            "Clock {:?} of pipeline {pipeline_id} didn't synchronize in {SYNC_TIMEOUT}, starting with it anyway",
+            clock.name()
+        );
+    }
+
+    pipeline.use_clock(Some(&clock));
+
+    // Without a start time, the base time isn't changed when the pipeline starts
+    pipeline.set_start_time(gst::ClockTime::NONE);
+    pipeline.set_base_time(gst::ClockTime::from_mseconds(
+        configuration.base_time_ms.unwrap_or_default(),
+    ));
+
+    debug!(
+        "Pipeline {pipeline_id} using clock {:?} with base time {}",
+        clock.name(),
+        pipeline.base_time().display()
+    );
+
+    Ok(())
+}
+
+/// The clock and the latency of the pipeline
+pub fn status(pipeline: &gst::Pipeline, configuration: Option<&ClockConfiguration>) -> ClockStatus {
+    let clock = pipeline.clock();
+
+    let mut query = gst::query::Latency::new();
+    let (live, latency) = if pipeline.query(&mut query) {
+        let (live, min_latency, _max_latency) = query.result();
+        (live, live.then_some(min_latency.mseconds()))
+    } else {
+        (false, None)
+    };
+
+    ClockStatus {
+        clock: configuration.cloned(),
+        name: clock.as_ref().map(|clock| clock.name().to_string()),
+        synced: clock.as_ref().map_or(false, |clock| clock.is_synced()),
+        time_ns: clock
+            .as_ref()
+            .and_then(|clock| clock.time())
+            .map(|time| time.nseconds()),
+        base_time_ns: pipeline.base_time().map(|base_time| base_time.nseconds()),
+        live,
+        latency_ms: latency,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_timeline() {
+        gst::init().unwrap();
+
+        let configuration = ClockConfiguration {
+            base_time_ms: Some(1000),
+            ..Default::default()
+        };
+        assert!(validate(&configuration).is_ok());
+
+        let pipelines = (0..2)
+            .map(|_| {
+                let pipeline_id = uuid::Uuid::new_v4();
+                let pipeline = gst::parse::launch("videotestsrc is-live=true ! fakesink")
+                    .unwrap()
+                    .downcast::<gst::Pipeline>()
+                    .unwrap();
+                apply(&pipeline, &pipeline_id, &configuration).unwrap();
+                pipeline.set_state(gst::State::Playing).unwrap();
+                pipeline.state(gst::ClockTime::from_seconds(5)).0.unwrap();
+
+                pipeline
+            })
+            .collect::<Vec<gst::Pipeline>>();
+
+        // Both pipelines keep the configured base time once started, so they share the same timeline
+        for pipeline in &pipelines {
+            assert_eq!(
+                pipeline.base_time(),
+                Some(gst::ClockTime::from_mseconds(1000))
+            );
+
+            let status = status(pipeline, Some(&configuration));
+            assert!(status.synced);
+            assert!(status.live);
+            assert_eq!(status.base_time_ns, Some(1_000_000_000));
+        }
+
+        for pipeline in &pipelines {
+            pipeline.set_state(gst::State::Null).unwrap();
+        }
+
+        assert!(validate(&ClockConfiguration {
+            source: ClockSource::Ntp,
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(&ClockConfiguration {
+            source: ClockSource::Net,
+            address: Some("192.168.2.1".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
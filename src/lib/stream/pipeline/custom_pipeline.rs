@@ -0,0 +1,202 @@
+use crate::{
+    video::{types::VideoSourceType, video_source_gst::VideoSourceGstType},
+    video_stream::types::VideoAndStreamInformation,
+};
+
+use super::{
+    PipelineGstreamerInterface, PipelineState, PIPELINE_FILTER_NAME, PIPELINE_RTP_TEE_NAME,
+    PIPELINE_VIDEO_TEE_NAME,
+};
+
+use anyhow::{anyhow, Result};
+
+use tracing::*;
+
+use gst::prelude::*;
+
+/// Runs a user provided gst-launch description, like
+/// "videotestsrc ! x264enc tune=zerolatency ! h264parse ! tee name=VideoTee ! rtph264pay pt=96 ! tee name=RTPTee".
+/// The stream's sinks are linked to the Tees named "VideoTee" (encoded or raw video) and "RTPTee" (RTP payloaded
+/// video), so at least one of them is required. A capsfilter named "Filter" is optional, and is used to report the
+/// stream's caps
+#[derive(Debug)]
+pub struct CustomPipeline {
+    pub state: PipelineState,
+}
+
+impl CustomPipeline {
+    #[instrument(level = "debug")]
+    pub fn try_new(
+        pipeline_id: &uuid::Uuid,
+        video_and_stream_information: &VideoAndStreamInformation,
+    ) -> Result<gst::Pipeline> {
+        let video_source = match &video_and_stream_information.video_source {
+            VideoSourceType::Gst(source) => source,
+            unsupported => {
+                return Err(anyhow!(
+                    "VideoSourceType {unsupported:?} is not supported as Custom Pipeline"
+                ))
+            }
+        };
+
+        let description = match &video_source.source {
+            VideoSourceGstType::Custom(description) => description,
+            unsupported => {
+                return Err(anyhow!(
+                    "VideoSourceGstType {unsupported:?} is not supported as Custom Pipeline"
+                ))
+            }
+        };
+
+        let description = tap_points_description(description, pipeline_id);
+
+        debug!("pipeline_description: {description:#?}");
+
+        let pipeline = gst::parse::launch(&description)
+            .map_err(|error| anyhow!("Failed parsing the custom pipeline: {error}"))?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("The custom pipeline should have more than one element"))?;
+
+        let tees = [PIPELINE_VIDEO_TEE_NAME, PIPELINE_RTP_TEE_NAME]
+            .iter()
+            .filter_map(|name| pipeline.by_name(&format!("{name}-{pipeline_id}")))
+            .collect::<Vec<_>>();
+
+        if tees.is_empty() {
+            return Err(anyhow!(
+                "The custom pipeline should have a tee named {PIPELINE_VIDEO_TEE_NAME} or {PIPELINE_RTP_TEE_NAME}, where the stream's sinks are linked"
+            ));
+        }
+
+        for tee in &tees {
+            if tee.factory().map(|factory| factory.name()).as_deref() != Some("tee") {
+                return Err(anyhow!(
+                    "The custom pipeline's element {:?} should be a tee",
+                    tee.name()
+                ));
+            }
+            // Sinks come and go, so the pipeline shouldn't fail when no sink is linked
+            tee.set_property("allow-not-linked", true);
+        }
+
+        Ok(pipeline)
+    }
+}
+
+/// Suffixes the tap points' names (and the references to them, like "VideoTee. ! queue") with the pipeline id, as
+/// done by the other pipelines, so they are found when adding sinks
+fn tap_points_description(description: &str, pipeline_id: &uuid::Uuid) -> String {
+    let names = [
+        PIPELINE_VIDEO_TEE_NAME,
+        PIPELINE_RTP_TEE_NAME,
+        PIPELINE_FILTER_NAME,
+    ]
+    .join("|");
+
+    let declarations = regex::Regex::new(&format!(r#"(\bname\s*=\s*["']?)({names})\b"#))
+        .expect("Invalid tap point declaration regex");
+    let description = declarations.replace_all(description, format!("${{1}}${{2}}-{pipeline_id}"));
+
+    let references = regex::Regex::new(&format!(r"(^|[\s!])({names})\."))
+        .expect("Invalid tap point reference regex");
+    references
+        .replace_all(&description, format!("${{1}}${{2}}-{pipeline_id}."))
+        .into_owned()
+}
+
+impl PipelineGstreamerInterface for CustomPipeline {
+    #[instrument(level = "trace")]
+    fn is_running(&self) -> bool {
+        self.state.pipeline_runner.is_running()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        stream::types::{CaptureConfiguration, StreamInformation, VideoCaptureConfiguration},
+        video::{
+            types::{FrameInterval, VideoEncodeType},
+            video_source_gst::VideoSourceGst,
+        },
+    };
+
+    fn custom_stream(description: &str) -> VideoAndStreamInformation {
+        VideoAndStreamInformation {
+            name: "Custom".into(),
+            tags: vec![],
+            stream_information: StreamInformation {
+                endpoints: vec![],
+                configuration: CaptureConfiguration::Video(VideoCaptureConfiguration {
+                    encode: VideoEncodeType::Yuyv,
+                    height: 240,
+                    width: 320,
+                    frame_interval: FrameInterval {
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                }),
+                extended_configuration: None,
+            },
+            video_source: VideoSourceType::Gst(VideoSourceGst {
+                name: "Custom".into(),
+                source: VideoSourceGstType::Custom(description.into()),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_tap_points_description() {
+        let pipeline_id = uuid::Uuid::nil();
+        assert_eq!(
+            tap_points_description(
+                "videotestsrc ! tee name=VideoTee VideoTee. ! rtpvrawpay ! tee name=\"RTPTee\"",
+                &pipeline_id
+            ),
+            format!("videotestsrc ! tee name=VideoTee-{pipeline_id} VideoTee-{pipeline_id}. ! rtpvrawpay ! tee name=\"RTPTee-{pipeline_id}\"")
+        );
+    }
+
+    #[test]
+    fn test_custom_pipeline() {
+        gst::init().unwrap();
+
+        let pipeline_id = uuid::Uuid::new_v4();
+        let pipeline = CustomPipeline::try_new(
+            &pipeline_id,
+            &custom_stream(
+                "videotestsrc ! video/x-raw,format=I420 ! tee name=VideoTee ! rtpvrawpay pt=96 ! tee name=RTPTee",
+            ),
+        )
+        .unwrap();
+        assert!(pipeline
+            .by_name(&format!("{PIPELINE_VIDEO_TEE_NAME}-{pipeline_id}"))
+            .is_some());
+        assert!(pipeline
+            .by_name(&format!("{PIPELINE_RTP_TEE_NAME}-{pipeline_id}"))
+            .is_some());
+
+        // Parsing errors are reported to the user
+        let error = CustomPipeline::try_new(
+            &pipeline_id,
+            &custom_stream("videotestsrc ! nonexistentelement ! tee name=VideoTee"),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("nonexistentelement"));
+
+        // Without a tap point, sinks can't be linked
+        assert!(
+            CustomPipeline::try_new(&pipeline_id, &custom_stream("videotestsrc ! fakesink"))
+                .is_err()
+        );
+
+        // The tap points should be tees
+        assert!(CustomPipeline::try_new(
+            &pipeline_id,
+            &custom_stream("videotestsrc ! identity name=VideoTee ! fakesink")
+        )
+        .is_err());
+    }
+}
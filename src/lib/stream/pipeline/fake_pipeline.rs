@@ -1,15 +1,20 @@
 use crate::{
-    stream::types::CaptureConfiguration,
+    stream::{
+        gst::encoders::{
+            h264_caps_fields, select_encoder, validate_h264_profile_for_encoder, EncoderCandidate,
+        },
+        types::CaptureConfiguration,
+    },
     video::{
         types::{VideoEncodeType, VideoSourceType},
-        video_source_gst::VideoSourceGstType,
+        video_source_gst::{VideoSourceGstType, FAKE_PATTERNS},
     },
     video_stream::types::VideoAndStreamInformation,
 };
 
 use super::{
-    PipelineGstreamerInterface, PipelineState, PIPELINE_FILTER_NAME, PIPELINE_RTP_TEE_NAME,
-    PIPELINE_VIDEO_TEE_NAME,
+    frame_rate, overlay, queues, scale, zoom, PipelineGstreamerInterface, PipelineState,
+    PIPELINE_FILTER_NAME, PIPELINE_RTP_TEE_NAME, PIPELINE_VIDEO_TEE_NAME,
 };
 
 use anyhow::{anyhow, Result};
@@ -57,9 +62,45 @@ impl FakePipeline {
             }
         };
 
+        if !FAKE_PATTERNS.contains(&pattern.as_str()) {
+            return Err(anyhow!(
+                "Pattern {pattern:?} is not valid, the available options are: {FAKE_PATTERNS:?}"
+            ));
+        }
+
+        let source_queue = queues::description(
+            pipeline_id,
+            queues::PIPELINE_SOURCE_QUEUE_NAME,
+            video_and_stream_information.stream_information.queues(),
+        );
+        let encoder_queue = queues::description(
+            pipeline_id,
+            queues::PIPELINE_ENCODER_QUEUE_NAME,
+            video_and_stream_information.stream_information.queues(),
+        );
         let filter_name = format!("{PIPELINE_FILTER_NAME}-{pipeline_id}");
         let video_tee_name = format!("{PIPELINE_VIDEO_TEE_NAME}-{pipeline_id}");
         let rtp_tee_name = format!("{PIPELINE_RTP_TEE_NAME}-{pipeline_id}");
+        let zoom = zoom::description(pipeline_id, configuration.width, configuration.height);
+        let overlay = overlay::description(pipeline_id);
+        let output_resolution = video_and_stream_information
+            .stream_information
+            .output_resolution();
+        let scale = scale::description(
+            pipeline_id,
+            configuration.width,
+            configuration.height,
+            output_resolution,
+        );
+        let (output_width, output_height) =
+            scale::output_size(configuration.width, configuration.height, output_resolution);
+        let max_framerate = video_and_stream_information
+            .stream_information
+            .max_framerate();
+        let rate =
+            frame_rate::description(pipeline_id, &configuration.frame_interval, max_framerate);
+        let output_interval =
+            frame_rate::output_interval(&configuration.frame_interval, max_framerate);
 
         // Fakes (videotestsrc) are only "video/x-raw" or "video/x-bayer",
         // and to be able to encode it, we need to define an available
@@ -67,28 +108,92 @@ impl FakePipeline {
         // We are choosing "UYVY" because it is compatible with the
         // application-rtp template capabilities.
         // For more information: https://gstreamer.freedesktop.org/documentation/additional/design/mediatype-video-raw.html?gi-language=c#formats
+        // The encoder is only selected when the encode needs one
+        let encoder = || -> Result<&'static EncoderCandidate> {
+            let encoder = select_encoder(&configuration.encode)?;
+            info!(
+                "Pipeline {pipeline_id} using encoder {:?} (hardware: {})",
+                encoder.name, encoder.hardware
+            );
+            Ok(encoder)
+        };
+
         let description = match &configuration.encode {
             VideoEncodeType::H264 => {
+                let encoder = encoder()?;
+                let h264_profile = video_and_stream_information
+                    .stream_information
+                    .h264_profile();
+                if let Some(h264_profile) = h264_profile {
+                    validate_h264_profile_for_encoder(h264_profile, encoder.name)?;
+                }
+
                 format!(concat!(
-                        "videotestsrc pattern={pattern} is-live=true do-timestamp=true",
+                        "videotestsrc pattern={pattern} is-live=true do-timestamp=true{source_queue}",
                         " ! timeoverlay",
                         " ! video/x-raw,format=I420",
-                        " ! x264enc tune=zerolatency speed-preset=ultrafast bitrate=5000",
+                        "{zoom}",
+                        "{overlay}",
+                        "{scale}",
+                        "{rate}",
+                        " ! videoconvert",
+                        " ! {encoder}{encoder_queue}",
                         " ! h264parse",
-                        " ! capsfilter name={filter_name} caps=video/x-h264,profile={profile},stream-format=avc,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                        " ! capsfilter name={filter_name} caps=video/x-h264,{profile},stream-format=avc,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
                         " ! tee name={video_tee_name} allow-not-linked=true",
                         " ! rtph264pay aggregate-mode=zero-latency config-interval=10 pt=96",
                         " ! tee name={rtp_tee_name} allow-not-linked=true"
                     ),
                     pattern = pattern,
-                    profile = "constrained-baseline",
-                    width = configuration.width,
-                    height = configuration.height,
-                    interval_denominator = configuration.frame_interval.denominator,
-                    interval_numerator = configuration.frame_interval.numerator,
+                    zoom = zoom,
+                    overlay = overlay,
+                    scale = scale,
+                    rate = rate,
+                    encoder = encoder.description,
+                    profile = h264_caps_fields(h264_profile),
+                    width = output_width,
+                    height = output_height,
+                    interval_denominator = output_interval.denominator,
+                    interval_numerator = output_interval.numerator,
+                    filter_name = filter_name,
+                    video_tee_name = video_tee_name,
+                    rtp_tee_name = rtp_tee_name,
+                    source_queue = source_queue,
+                    encoder_queue = encoder_queue,
+                )
+            }
+            VideoEncodeType::H265 => {
+                format!(concat!(
+                        "videotestsrc pattern={pattern} is-live=true do-timestamp=true{source_queue}",
+                        " ! timeoverlay",
+                        " ! video/x-raw,format=I420",
+                        "{zoom}",
+                        "{overlay}",
+                        "{scale}",
+                        "{rate}",
+                        " ! videoconvert",
+                        " ! {encoder}{encoder_queue}",
+                        " ! h265parse",
+                        " ! capsfilter name={filter_name} caps=video/x-h265,stream-format=byte-stream,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                        " ! tee name={video_tee_name} allow-not-linked=true",
+                        " ! rtph265pay aggregate-mode=zero-latency config-interval=10 pt=96",
+                        " ! tee name={rtp_tee_name} allow-not-linked=true"
+                    ),
+                    pattern = pattern,
+                    zoom = zoom,
+                    overlay = overlay,
+                    scale = scale,
+                    rate = rate,
+                    encoder = encoder()?.description,
+                    width = output_width,
+                    height = output_height,
+                    interval_denominator = output_interval.denominator,
+                    interval_numerator = output_interval.numerator,
                     filter_name = filter_name,
                     video_tee_name = video_tee_name,
                     rtp_tee_name = rtp_tee_name,
+                    source_queue = source_queue,
+                    encoder_queue = encoder_queue,
                 )
             }
             VideoEncodeType::Yuyv => {
@@ -97,44 +202,63 @@ impl FakePipeline {
                         // Because application-rtp templates doesn't accept "YUY2", we
                         // need to transcode it. We are arbitrarily chosing the closest
                         // format available ("UYVY").
-                        "videotestsrc pattern={pattern} is-live=true do-timestamp=true",
+                        "videotestsrc pattern={pattern} is-live=true do-timestamp=true{source_queue}",
                         " ! timeoverlay",
                         " ! video/x-raw,format=I420",
+                        "{zoom}",
+                        "{overlay}",
+                        "{scale}",
+                        "{rate}",
                         " ! capsfilter name={filter_name} caps=video/x-raw,format=I420,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
                         " ! tee name={video_tee_name} allow-not-linked=true",
                         " ! rtpvrawpay pt=96",
                         " ! tee name={rtp_tee_name} allow-not-linked=true",
                     ),
                     pattern = pattern,
-                    width = configuration.width,
-                    height = configuration.height,
-                    interval_denominator = configuration.frame_interval.denominator,
-                    interval_numerator = configuration.frame_interval.numerator,
+                    zoom = zoom,
+                    overlay = overlay,
+                    scale = scale,
+                    rate = rate,
+                    width = output_width,
+                    height = output_height,
+                    interval_denominator = output_interval.denominator,
+                    interval_numerator = output_interval.numerator,
                     filter_name = filter_name,
                     video_tee_name = video_tee_name,
                     rtp_tee_name = rtp_tee_name,
+                    source_queue = source_queue,
                 )
             }
             VideoEncodeType::Mjpg => {
                 format!(
                     concat!(
-                        "videotestsrc pattern={pattern} is-live=true do-timestamp=true",
+                        "videotestsrc pattern={pattern} is-live=true do-timestamp=true{source_queue}",
                         " ! timeoverlay",
                         " ! video/x-raw,format=I420",
-                        " ! jpegenc quality=85 idct-method=1",
+                        "{zoom}",
+                        "{overlay}",
+                        "{scale}",
+                        "{rate}",
+                        " ! jpegenc quality=85 idct-method=1{encoder_queue}",
                         " ! capsfilter name={filter_name} caps=image/jpeg,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
                         " ! tee name={video_tee_name} allow-not-linked=true",
                         " ! rtpjpegpay pt=96",
                         " ! tee name={rtp_tee_name} allow-not-linked=true",
                     ),
                     pattern = pattern,
-                    width = configuration.width,
-                    height = configuration.height,
-                    interval_denominator = configuration.frame_interval.denominator,
-                    interval_numerator = configuration.frame_interval.numerator,
+                    zoom = zoom,
+                    overlay = overlay,
+                    scale = scale,
+                    rate = rate,
+                    width = output_width,
+                    height = output_height,
+                    interval_denominator = output_interval.denominator,
+                    interval_numerator = output_interval.numerator,
                     filter_name = filter_name,
                     video_tee_name = video_tee_name,
                     rtp_tee_name = rtp_tee_name,
+                    source_queue = source_queue,
+                    encoder_queue = encoder_queue,
                 )
             }
             unsupported => {
@@ -160,3 +284,96 @@ impl PipelineGstreamerInterface for FakePipeline {
         self.state.pipeline_runner.is_running()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    use crate::{
+        stream::{
+            pipeline::runner::{PipelineRunner, PipelineRunnerConfig},
+            types::{StreamInformation, VideoCaptureConfiguration},
+        },
+        video::{types::FrameInterval, video_source_gst::VideoSourceGst},
+    };
+
+    fn fake_stream(pattern: &str) -> VideoAndStreamInformation {
+        VideoAndStreamInformation {
+            name: "Fake".into(),
+            tags: vec![],
+            stream_information: StreamInformation {
+                endpoints: vec![],
+                configuration: CaptureConfiguration::Video(VideoCaptureConfiguration {
+                    encode: VideoEncodeType::Yuyv,
+                    height: 240,
+                    width: 320,
+                    frame_interval: FrameInterval {
+                        numerator: 1,
+                        denominator: 30,
+                    },
+                }),
+                extended_configuration: None,
+            },
+            video_source: VideoSourceType::Gst(VideoSourceGst {
+                name: "Fake".into(),
+                source: VideoSourceGstType::Fake(pattern.into()),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        gst::init().unwrap();
+
+        let pipeline_id = uuid::Uuid::new_v4();
+        assert!(FakePipeline::try_new(&pipeline_id, &fake_stream("invalid")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_runner_with_fake_pipeline() {
+        gst::init().unwrap();
+
+        let pipeline_id = uuid::Uuid::new_v4();
+        let pipeline = FakePipeline::try_new(&pipeline_id, &fake_stream("smpte")).unwrap();
+
+        // Count the frames reaching the video Tee, which is shared by all sinks
+        let frames = Arc::new(AtomicUsize::new(0));
+        let frames_cloned = frames.clone();
+        pipeline
+            .by_name(&format!("{PIPELINE_VIDEO_TEE_NAME}-{pipeline_id}"))
+            .unwrap()
+            .static_pad("sink")
+            .unwrap()
+            .add_probe(gst::PadProbeType::BUFFER, move |_pad, _info| {
+                frames_cloned.fetch_add(1, Ordering::Relaxed);
+                gst::PadProbeReturn::Ok
+            })
+            .unwrap();
+
+        let runner = PipelineRunner::try_new(
+            &pipeline,
+            &pipeline_id,
+            false,
+            PipelineRunnerConfig::default(),
+            None,
+        )
+        .unwrap();
+        runner.start().unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while frames.load(Ordering::Relaxed) < 30 {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("The fake pipeline didn't produce frames in time");
+        assert!(runner.is_running());
+
+        pipeline.set_state(gst::State::Null).unwrap();
+    }
+}
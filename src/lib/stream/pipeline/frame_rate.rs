@@ -0,0 +1,224 @@
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::*;
+
+use crate::video::types::FrameInterval;
+
+use super::PIPELINE_FILTER_NAME;
+
+pub const PIPELINE_RATE_NAME: &str = "OutputRate";
+pub const PIPELINE_RATE_FILTER_NAME: &str = "OutputRateFilter";
+
+/// If the captured framerate is higher than the given one
+fn exceeds(frame_interval: &FrameInterval, framerate: u32) -> bool {
+    frame_interval.denominator as u64 > framerate as u64 * frame_interval.numerator as u64
+}
+
+/// The frame interval streamed for the captured one, capped by the maximum framerate
+pub fn output_interval(
+    frame_interval: &FrameInterval,
+    max_framerate: Option<u32>,
+) -> FrameInterval {
+    match max_framerate {
+        Some(max_framerate) if exceeds(frame_interval, max_framerate) => FrameInterval {
+            numerator: 1,
+            denominator: max_framerate,
+        },
+        _ => frame_interval.clone(),
+    }
+}
+
+/// The raw video stage dropping frames down to the maximum framerate. Just like the scale stage, it is always part
+/// of raw video pipelines, passing the frames through while there is no cap, so it can be changed without
+/// rebuilding the pipeline. The captured framerate is fixed before it, so the cap never changes the camera's format
+pub fn description(
+    pipeline_id: &uuid::Uuid,
+    frame_interval: &FrameInterval,
+    max_framerate: Option<u32>,
+) -> String {
+    let output_interval = output_interval(frame_interval, max_framerate);
+
+    format!(
+        concat!(
+            " ! video/x-raw,framerate={capture_denominator}/{capture_numerator}",
+            " ! videorate name={PIPELINE_RATE_NAME}-{pipeline_id} drop-only=true",
+            " ! capsfilter name={PIPELINE_RATE_FILTER_NAME}-{pipeline_id} caps=video/x-raw,framerate={interval_denominator}/{interval_numerator}",
+        ),
+        PIPELINE_RATE_NAME = PIPELINE_RATE_NAME,
+        PIPELINE_RATE_FILTER_NAME = PIPELINE_RATE_FILTER_NAME,
+        pipeline_id = pipeline_id,
+        capture_denominator = frame_interval.denominator,
+        capture_numerator = frame_interval.numerator,
+        interval_denominator = output_interval.denominator,
+        interval_numerator = output_interval.numerator,
+    )
+}
+
+#[instrument(level = "debug")]
+pub fn validate(max_framerate: u32, frame_interval: &FrameInterval) -> Result<()> {
+    if max_framerate == 0 {
+        return Err(anyhow!("Maximum framerate should be at least 1 fps"));
+    }
+
+    if max_framerate as u64 * frame_interval.numerator as u64 > frame_interval.denominator as u64 {
+        return Err(anyhow!(
+            "Maximum framerate of {max_framerate} fps exceeds the capture framerate of {}/{} fps",
+            frame_interval.denominator,
+            frame_interval.numerator
+        ));
+    }
+
+    Ok(())
+}
+
+/// Drops the captured frames down to the maximum framerate streamed
+#[derive(Debug)]
+pub struct OutputRate {
+    capsfilter_weak: gst::glib::WeakRef<gst::Element>,
+    filter_weak: gst::glib::WeakRef<gst::Element>,
+    frame_interval: FrameInterval,
+}
+
+impl OutputRate {
+    /// Finds the rate stage of the pipeline, which only exists for raw video in our pipelines
+    pub fn try_new(
+        pipeline: &gst::Pipeline,
+        pipeline_id: &uuid::Uuid,
+        frame_interval: &FrameInterval,
+    ) -> Option<Self> {
+        let capsfilter = pipeline.by_name(&format!("{PIPELINE_RATE_FILTER_NAME}-{pipeline_id}"))?;
+        let filter = pipeline.by_name(&format!("{PIPELINE_FILTER_NAME}-{pipeline_id}"))?;
+
+        Some(Self {
+            capsfilter_weak: capsfilter.downgrade(),
+            filter_weak: filter.downgrade(),
+            frame_interval: frame_interval.clone(),
+        })
+    }
+
+    /// Changes the maximum framerate streamed, going back to the captured one when none is given. The caps are
+    /// renegotiated downstream, so the encoder restarts with the new framerate
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_max_framerate(&self, max_framerate: Option<u32>) -> Result<()> {
+        if let Some(max_framerate) = max_framerate {
+            validate(max_framerate, &self.frame_interval)?;
+        }
+
+        let element = |weak: &gst::glib::WeakRef<gst::Element>| {
+            weak.upgrade()
+                .ok_or_else(|| anyhow!("The output rate's elements no longer exist"))
+        };
+        let capsfilter = element(&self.capsfilter_weak)?;
+        let filter = element(&self.filter_weak)?;
+
+        let output_interval = output_interval(&self.frame_interval, max_framerate);
+        let framerate = gst::Fraction::new(
+            output_interval.denominator as i32,
+            output_interval.numerator as i32,
+        );
+
+        // The stream's caps are changed first, so they accept the new framerate once it is negotiated upstream
+        let mut filter_caps = filter.property::<gst::Caps>("caps");
+        filter_caps
+            .make_mut()
+            .iter_mut()
+            .filter(|structure| structure.has_field("framerate"))
+            .for_each(|structure| structure.set("framerate", framerate));
+        filter.set_property("caps", &filter_caps);
+
+        capsfilter.set_property(
+            "caps",
+            gst::Caps::builder("video/x-raw")
+                .field("framerate", framerate)
+                .build(),
+        );
+
+        debug!("Output framerate set to {framerate}");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let interval = |numerator, denominator| FrameInterval {
+            numerator,
+            denominator,
+        };
+
+        assert!(validate(15, &interval(1, 30)).is_ok());
+        assert!(validate(30, &interval(1, 30)).is_ok());
+        assert!(validate(0, &interval(1, 30)).is_err());
+        assert!(validate(60, &interval(1, 30)).is_err());
+        assert!(validate(30, &interval(1001, 30000)).is_err());
+
+        assert_eq!(output_interval(&interval(1, 60), Some(30)), interval(1, 30));
+        assert_eq!(output_interval(&interval(1, 30), Some(30)), interval(1, 30));
+        assert_eq!(output_interval(&interval(1, 30), None), interval(1, 30));
+    }
+
+    #[tokio::test]
+    async fn test_output_rate() {
+        gst::init().unwrap();
+
+        let pipeline_id = uuid::Uuid::new_v4();
+        let frame_interval = FrameInterval {
+            numerator: 1,
+            denominator: 30,
+        };
+        let description = format!(
+            concat!(
+                "videotestsrc is-live=true",
+                " ! video/x-raw,format=I420,width=320,height=240,framerate=30/1",
+                "{rate}",
+                " ! capsfilter name={PIPELINE_FILTER_NAME}-{pipeline_id} caps=video/x-raw,format=I420,framerate=10/1",
+                " ! fakesink name=Sink",
+            ),
+            rate = description(&pipeline_id, &frame_interval, Some(10)),
+            PIPELINE_FILTER_NAME = PIPELINE_FILTER_NAME,
+            pipeline_id = pipeline_id,
+        );
+        let pipeline = gst::parse::launch(&description)
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+        let sink_pad = pipeline
+            .by_name("Sink")
+            .unwrap()
+            .static_pad("sink")
+            .unwrap();
+
+        let negotiated_framerate = |pad: &gst::Pad| {
+            pad.current_caps()
+                .and_then(|caps| caps.structure(0)?.get::<gst::Fraction>("framerate").ok())
+        };
+        let wait_for_framerate = |framerate: gst::Fraction| {
+            let sink_pad = sink_pad.clone();
+            async move {
+                tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                    while negotiated_framerate(&sink_pad) != Some(framerate) {
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    }
+                })
+                .await
+                .unwrap_or_else(|_| panic!("The output wasn't renegotiated to {framerate}"))
+            }
+        };
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+        wait_for_framerate(gst::Fraction::new(10, 1)).await;
+
+        // Back to the captured framerate, without rebuilding the pipeline
+        let rate = OutputRate::try_new(&pipeline, &pipeline_id, &frame_interval).unwrap();
+        rate.set_max_framerate(None).unwrap();
+        wait_for_framerate(gst::Fraction::new(30, 1)).await;
+
+        assert!(rate.set_max_framerate(Some(60)).is_err());
+
+        pipeline.set_state(gst::State::Null).unwrap();
+    }
+}
@@ -0,0 +1,420 @@
+use std::{collections::HashSet, sync::mpsc};
+
+use anyhow::{anyhow, Context, Result};
+use gst::prelude::*;
+use tracing::*;
+
+use crate::{
+    stream::{
+        bandwidth,
+        gst::{encoders, utils::request_keyframe},
+        types::WebRTCLayerConfiguration,
+    },
+    video::types::VideoEncodeType,
+};
+
+use super::bitrate_controller::set_encoder_bitrate;
+
+pub const PIPELINE_LAYERS_NAME: &str = "Layers";
+pub const PIPELINE_LAYER_RTP_TEE_NAME: &str = "LayerRTPTee";
+
+/// The layers encoded besides the stream itself, keeping up to three qualities per stream
+pub const MAX_LAYERS: usize = 2;
+/// How many consecutive bandwidth estimations should agree before a session changes its layer, so it doesn't
+/// keep switching back and forth
+pub const LAYER_SWITCH_ESTIMATIONS: usize = 3;
+/// How long to wait for the data to stop flowing through a session's branch to relink it
+const LAYER_SWITCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[instrument(level = "debug")]
+pub fn validate(
+    layers: &[WebRTCLayerConfiguration],
+    encode: &VideoEncodeType,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    if *encode != VideoEncodeType::H264 {
+        return Err(anyhow!(
+            "WebRTC layers are only available for H264 streams, got {encode:?}"
+        ));
+    }
+
+    if layers.len() > MAX_LAYERS {
+        return Err(anyhow!(
+            "WebRTC layers should be at most {MAX_LAYERS}, besides the stream itself"
+        ));
+    }
+
+    let mut bitrates = HashSet::new();
+    for layer in layers {
+        if layer.width == 0 || layer.height == 0 {
+            return Err(anyhow!("WebRTC layer resolution can't be empty"));
+        }
+
+        // Raw formats like I420 are subsampled
+        if layer.width % 2 != 0 || layer.height % 2 != 0 {
+            return Err(anyhow!(
+                "WebRTC layer resolution should have even dimensions"
+            ));
+        }
+
+        if layer.width > width || layer.height > height {
+            return Err(anyhow!(
+                "WebRTC layer {}x{} should not be larger than the stream itself ({width}x{height})",
+                layer.width,
+                layer.height
+            ));
+        }
+
+        if layer.bitrate == 0 {
+            return Err(anyhow!("WebRTC layer bitrate can't be zero"));
+        }
+
+        // The bitrate is what tells the layers apart when choosing one for a session
+        if !bitrates.insert(layer.bitrate) {
+            return Err(anyhow!(
+                "WebRTC layers should have different bitrates, got {} twice",
+                layer.bitrate
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The layer to send to a session with the given target bitrate, as an index of the layers sorted by decreasing
+/// bitrate: none (the stream itself) when the target is above every layer, otherwise the best layer fitting
+/// in it, or the lowest layer when none fits
+pub fn select(layers: &[WebRTCLayerConfiguration], target_bitrate: u64) -> Option<usize> {
+    let highest = layers.first()?;
+    if target_bitrate > highest.bitrate as u64 {
+        return None;
+    }
+
+    layers
+        .iter()
+        .position(|layer| layer.bitrate as u64 <= target_bitrate)
+        .or(Some(layers.len() - 1))
+}
+
+/// Lower quality versions of the encoded video, decoded once and scaled and encoded again for each layer, each
+/// ending in its own RTP Tee, so the WebRTC sessions can be moved between them
+#[derive(Debug)]
+pub struct WebRTCLayers {
+    pipeline_id: uuid::Uuid,
+    /// Sorted by decreasing bitrate
+    configurations: Vec<WebRTCLayerConfiguration>,
+    rtp_tee: gst::Element,
+    layer_rtp_tees: Vec<gst::Element>,
+}
+
+impl WebRTCLayers {
+    /// Adds the layers' branch to the pipeline, fed by the encoded video Tee. The layers are sent with the same
+    /// SSRC and RTP timestamps as the stream itself, so the clients see a single stream changing its resolution
+    #[instrument(level = "debug", skip(pipeline))]
+    pub fn try_new(
+        pipeline: &gst::Pipeline,
+        pipeline_id: &uuid::Uuid,
+        video_tee: &gst::Element,
+        rtp_tee: &gst::Element,
+        configurations: &[WebRTCLayerConfiguration],
+    ) -> Result<Self> {
+        let mut configurations = configurations.to_vec();
+        configurations.sort_by_key(|configuration| std::cmp::Reverse(configuration.bitrate));
+
+        let encoder = encoders::select_encoder(&VideoEncodeType::H264)?;
+        let ssrc = uuid::Uuid::new_v4().as_u128() as u32;
+        let timestamp_offset = uuid::Uuid::new_v4().as_u128() as u32;
+
+        let raw_tee_name = format!("{PIPELINE_LAYERS_NAME}RawTee-{pipeline_id}");
+        let branches = configurations
+            .iter()
+            .enumerate()
+            .map(|(index, configuration)| {
+                format!(
+                    concat!(
+                        " {raw_tee_name}.",
+                        " ! queue leaky=downstream silent=true max-size-buffers=1",
+                        " ! videoscale",
+                        " ! videoconvert",
+                        " ! capsfilter caps=video/x-raw,format=I420,width={width},height={height},pixel-aspect-ratio=1/1",
+                        " ! {encoder}",
+                        " ! h264parse",
+                        " ! capsfilter caps=video/x-h264,profile=constrained-baseline,stream-format=avc,alignment=au",
+                        " ! rtph264pay aggregate-mode=zero-latency config-interval=-1 pt=96 ssrc={ssrc} timestamp-offset={timestamp_offset}",
+                        " ! tee name={layer_rtp_tee_name} allow-not-linked=true",
+                    ),
+                    raw_tee_name = raw_tee_name,
+                    width = configuration.width,
+                    height = configuration.height,
+                    encoder = encoder.description,
+                    ssrc = ssrc,
+                    timestamp_offset = timestamp_offset,
+                    layer_rtp_tee_name = layer_rtp_tee_name(pipeline_id, index),
+                )
+            })
+            .collect::<String>();
+
+        let description = format!(
+            concat!(
+                "queue leaky=downstream silent=true max-size-buffers=1",
+                " ! decodebin",
+                " ! videoconvert",
+                " ! tee name={raw_tee_name} allow-not-linked=true",
+                "{branches}",
+            ),
+            raw_tee_name = raw_tee_name,
+            branches = branches,
+        );
+
+        debug!("WebRTC layers description: {description:#?}");
+
+        let bin = gst::parse::bin_from_description(&description, true)?;
+        bin.set_property("name", format!("{PIPELINE_LAYERS_NAME}-{pipeline_id}"));
+
+        let layer_rtp_tees = (0..configurations.len())
+            .map(|index| {
+                bin.by_name(&layer_rtp_tee_name(pipeline_id, index))
+                    .context(format!("No RTP Tee found for WebRTC layer {index}"))
+            })
+            .collect::<Result<Vec<gst::Element>>>()?;
+
+        // Each layer keeps its own bitrate, out of the reach of the sessions' bandwidth adaptation
+        for (layer_rtp_tee, configuration) in layer_rtp_tees.iter().zip(&configurations) {
+            let applied = encoders_upstream(layer_rtp_tee)
+                .iter()
+                .any(|element| set_encoder_bitrate(element, configuration.bitrate as u64));
+            if !applied {
+                warn!(
+                    "WebRTC layer {}x{} has no encoder with a configurable bitrate, keeping its default",
+                    configuration.width, configuration.height
+                );
+            }
+        }
+
+        // The stream itself is sent with the same SSRC and timestamps as the layers
+        let payloader = rtp_tee
+            .static_pad("sink")
+            .and_then(|pad| pad.peer())
+            .and_then(|pad| pad.parent_element())
+            .context("No RTP payloader found for the stream")?;
+        payloader.set_property("ssrc", ssrc);
+        payloader.set_property("timestamp-offset", timestamp_offset);
+
+        pipeline.add(&bin)?;
+
+        let video_tee_src_pad = video_tee
+            .request_pad_simple("src_%u")
+            .context("Failed requesting src pad for the WebRTC layers")?;
+        let bin_sink_pad = bin
+            .static_pad("sink")
+            .context("No sink pad found on the WebRTC layers")?;
+        if let Err(error) = video_tee_src_pad.link(&bin_sink_pad) {
+            video_tee.release_request_pad(&video_tee_src_pad);
+            let _ = pipeline.remove(&bin);
+            return Err(anyhow!("Failed linking the WebRTC layers: {error:?}"));
+        }
+
+        Ok(Self {
+            pipeline_id: *pipeline_id,
+            configurations,
+            rtp_tee: rtp_tee.clone(),
+            layer_rtp_tees,
+        })
+    }
+
+    /// The layers, sorted by decreasing bitrate
+    pub fn configurations(&self) -> &[WebRTCLayerConfiguration] {
+        &self.configurations
+    }
+
+    /// If any session is using one of the layers
+    pub fn is_used(&self) -> bool {
+        self.layer_rtp_tees
+            .iter()
+            .any(|layer_rtp_tee| !layer_rtp_tee.src_pads().is_empty())
+    }
+
+    /// Moves a session's branch, given by the sink pad of its first element, to the given layer, or to the stream
+    /// itself when none is given. The branch is relinked once no data is flowing through it
+    #[instrument(level = "debug", skip(self, branch_sink_pad))]
+    pub fn switch(&self, branch_sink_pad: &gst::Pad, layer: Option<usize>) -> Result<()> {
+        let tee = match layer {
+            Some(index) => self
+                .layer_rtp_tees
+                .get(index)
+                .context(format!("WebRTC layer {index} not found"))?,
+            None => &self.rtp_tee,
+        };
+
+        let old_tee_src_pad = branch_sink_pad
+            .peer()
+            .context("The session's branch is not linked")?;
+        let new_tee_src_pad = tee
+            .request_pad_simple("src_%u")
+            .context("Failed requesting src pad for the WebRTC layer")?;
+
+        if bandwidth::attach(&self.pipeline_id, &new_tee_src_pad, None).is_none() {
+            warn!(
+                "Failed adding bandwidth probe to Pipeline {}",
+                self.pipeline_id
+            );
+        }
+
+        let (relinked_tx, relinked_rx) = mpsc::channel::<Result<()>>();
+        old_tee_src_pad.add_probe(gst::PadProbeType::IDLE, {
+            let new_tee_src_pad = new_tee_src_pad.clone();
+            let branch_sink_pad = branch_sink_pad.clone();
+
+            move |old_tee_src_pad, _info| {
+                let _ =
+                    relinked_tx.send(relink(old_tee_src_pad, &new_tee_src_pad, &branch_sink_pad));
+
+                gst::PadProbeReturn::Remove
+            }
+        });
+
+        let result = relinked_rx
+            .recv_timeout(LAYER_SWITCH_TIMEOUT)
+            .map_err(|error| anyhow!("Timed out relinking the session's branch: {error}"))
+            .and_then(|result| result);
+
+        // Whichever pad isn't used anymore goes back to its Tee
+        let unused_tee_src_pad = match &result {
+            Ok(()) => &old_tee_src_pad,
+            Err(_) => &new_tee_src_pad,
+        };
+        if let Some(tee) = unused_tee_src_pad.parent_element() {
+            tee.release_request_pad(unused_tee_src_pad);
+        }
+        result?;
+
+        // The clients can't decode the new layer until its next keyframe
+        if !request_keyframe(tee) {
+            debug!("No element upstream handled the keyframe request");
+        }
+
+        Ok(())
+    }
+}
+
+/// If the element is part of the WebRTC layers, which are encoded at their own bitrate
+pub fn is_layer_element(element: &gst::Element) -> bool {
+    let mut parent = element.parent();
+    while let Some(object) = parent {
+        if object.name().starts_with(PIPELINE_LAYERS_NAME) {
+            return true;
+        }
+        parent = object.parent();
+    }
+
+    false
+}
+
+fn layer_rtp_tee_name(pipeline_id: &uuid::Uuid, index: usize) -> String {
+    format!("{PIPELINE_LAYER_RTP_TEE_NAME}-{pipeline_id}-{index}")
+}
+
+/// The elements upstream from the given one, up to the layers' raw video Tee
+fn encoders_upstream(element: &gst::Element) -> Vec<gst::Element> {
+    let mut elements = vec![];
+    let mut current = element.clone();
+
+    while let Some(upstream) = current
+        .sink_pads()
+        .first()
+        .and_then(|pad| pad.peer())
+        .and_then(|pad| pad.parent_element())
+    {
+        if upstream.name().starts_with(PIPELINE_LAYERS_NAME) {
+            break;
+        }
+        elements.push(upstream.clone());
+        current = upstream;
+    }
+
+    elements
+}
+
+fn relink(
+    old_tee_src_pad: &gst::Pad,
+    new_tee_src_pad: &gst::Pad,
+    sink_pad: &gst::Pad,
+) -> Result<()> {
+    old_tee_src_pad.unlink(sink_pad)?;
+
+    if let Err(error) = new_tee_src_pad.link(sink_pad) {
+        // Keep the session on the previous layer
+        old_tee_src_pad.link(sink_pad)?;
+        return Err(anyhow!("Failed linking the new layer: {error:?}"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(width: u32, height: u32, bitrate: u32) -> WebRTCLayerConfiguration {
+        WebRTCLayerConfiguration {
+            width,
+            height,
+            bitrate,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let encode = VideoEncodeType::H264;
+
+        assert!(validate(&[], &encode, 1920, 1080).is_ok());
+        assert!(validate(
+            &[layer(1280, 720, 2_000_000), layer(640, 360, 600_000)],
+            &encode,
+            1920,
+            1080
+        )
+        .is_ok());
+        assert!(validate(
+            &[layer(640, 360, 600_000)],
+            &VideoEncodeType::Mjpg,
+            1920,
+            1080
+        )
+        .is_err());
+        assert!(validate(&[layer(2560, 1440, 8_000_000)], &encode, 1920, 1080).is_err());
+        assert!(validate(&[layer(641, 360, 600_000)], &encode, 1920, 1080).is_err());
+        assert!(validate(&[layer(640, 360, 0)], &encode, 1920, 1080).is_err());
+        assert!(validate(
+            &[layer(1280, 720, 600_000), layer(640, 360, 600_000)],
+            &encode,
+            1920,
+            1080
+        )
+        .is_err());
+        assert!(validate(
+            &[
+                layer(1280, 720, 2_000_000),
+                layer(854, 480, 1_000_000),
+                layer(640, 360, 600_000)
+            ],
+            &encode,
+            1920,
+            1080
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let layers = [layer(1280, 720, 2_000_000), layer(640, 360, 600_000)];
+
+        assert_eq!(select(&[], 100_000), None);
+        assert_eq!(select(&layers, 5_000_000), None);
+        assert_eq!(select(&layers, 2_000_000), Some(0));
+        assert_eq!(select(&layers, 1_000_000), Some(1));
+        // The lowest layer is kept even when it doesn't fit
+        assert_eq!(select(&layers, 300_000), Some(1));
+    }
+}
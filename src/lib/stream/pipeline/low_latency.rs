@@ -0,0 +1,97 @@
+use anyhow::Result;
+use gst::prelude::*;
+use tracing::*;
+
+use crate::stream::gst::encoders::{self, EncoderUpdate};
+
+/// Keyframe interval of low latency streams not configuring one, in frames, so receivers joining or recovering
+/// from a loss don't wait long for a keyframe
+pub const KEYFRAME_INTERVAL: u32 = 15;
+
+/// Tunes the pipeline for the lowest latency: the encoders skip the lookahead and the B-frames and send keyframes
+/// more often. The jitterbuffer latency is capped by the stream itself, and the sinks are only tuned once added,
+/// with [`apply_to_sinks`]
+#[instrument(level = "debug", skip(pipeline))]
+pub fn apply(pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid) -> Result<()> {
+    if encoders::apply_low_latency(pipeline)? == EncoderUpdate::Unsupported {
+        debug!("Pipeline {pipeline_id} has no encoder to tune for low latency");
+    }
+
+    encoders::apply_keyframe_interval(pipeline, KEYFRAME_INTERVAL)?;
+
+    Ok(())
+}
+
+/// Keeps the sinks of the pipeline from waiting for the clock, so each frame goes out as soon as it is ready
+#[instrument(level = "debug", skip(pipeline))]
+pub fn apply_to_sinks(pipeline: &gst::Pipeline) {
+    pipeline
+        .iterate_recurse()
+        .into_iter()
+        .flatten()
+        .filter(|element| {
+            element.element_flags().contains(gst::ElementFlags::SINK)
+                && element.find_property("sync").is_some()
+        })
+        .for_each(|sink| {
+            sink.set_property("sync", false);
+            debug!("Sink {:?} set to not sync", sink.name());
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The latency reported by the pipeline once the encoder outputs its first frame
+    fn encoding_latency(low_latency: bool) -> gst::ClockTime {
+        gst::init().unwrap();
+
+        let pipeline_id = uuid::Uuid::new_v4();
+        let pipeline = gst::parse::launch(
+            "videotestsrc is-live=true ! video/x-raw,width=320,height=240,framerate=30/1 ! x264enc ! fakesink name=sink",
+        )
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        let sink = pipeline.by_name("sink").unwrap();
+
+        if low_latency {
+            apply(&pipeline, &pipeline_id).unwrap();
+            assert!(sink.property::<bool>("sync"));
+
+            apply_to_sinks(&pipeline);
+            assert!(!sink.property::<bool>("sync"));
+        }
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+        let start = std::time::Instant::now();
+        while sink
+            .property::<Option<gst::Sample>>("last-sample")
+            .is_none()
+        {
+            assert!(start.elapsed() < std::time::Duration::from_secs(10));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let mut query = gst::query::Latency::new();
+        assert!(pipeline.query(&mut query));
+        pipeline.set_state(gst::State::Null).unwrap();
+
+        let (live, min_latency, _max_latency) = query.result();
+        assert!(live);
+
+        min_latency
+    }
+
+    #[test]
+    fn test_latency() {
+        // By default, x264enc holds about 40 frames for its lookahead and B-frames, over a second at 30 fps,
+        // while the low latency profile outputs each frame as soon as it is encoded, leaving about a frame
+        let default_latency = encoding_latency(false);
+        let low_latency = encoding_latency(true);
+
+        assert!(default_latency > gst::ClockTime::from_seconds(1));
+        assert!(low_latency < gst::ClockTime::from_mseconds(100));
+    }
+}
@@ -1,11 +1,29 @@
+pub mod audio;
+pub mod bitrate_controller;
+pub mod clip;
+pub mod clock;
+pub mod custom_pipeline;
 pub mod fake_pipeline;
+pub mod frame_rate;
+pub mod layers;
+pub mod low_latency;
+pub mod overlay;
+pub mod properties;
 pub mod qr_pipeline;
+pub mod queues;
 pub mod redirect_pipeline;
+pub mod resources;
+pub mod rtsp_pipeline;
 pub mod runner;
+pub mod scale;
+pub mod shared_capture;
+pub mod snapshot;
+pub mod statistics;
 #[cfg(target_os = "linux")]
 pub mod v4l_pipeline;
+pub mod zoom;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use enum_dispatch::enum_dispatch;
 
@@ -16,19 +34,36 @@ use tracing::*;
 use gst::prelude::*;
 
 use crate::{
+    cli,
     stream::{
-        gst::utils::wait_for_element_state,
+        bandwidth,
+        gst::{
+            encoders::{self, EncoderUpdate},
+            utils::{dump_dot_file, request_keyframe, wait_for_element_state},
+        },
         rtsp::rtsp_server::RTSPServer,
         sink::{Sink, SinkInterface},
+        types::CaptureConfiguration,
     },
     video::types::VideoSourceType,
     video_stream::types::VideoAndStreamInformation,
 };
 
+use bitrate_controller::BitrateController;
+use clip::ClipRecorder;
+use custom_pipeline::CustomPipeline;
 use fake_pipeline::FakePipeline;
+use frame_rate::OutputRate;
+use layers::WebRTCLayers;
+use overlay::TextOverlay;
 use qr_pipeline::QrPipeline;
 use redirect_pipeline::RedirectPipeline;
-use runner::PipelineRunner;
+use rtsp_pipeline::RtspPipeline;
+use runner::{PipelineRunner, PipelineRunnerConfig};
+use scale::OutputScale;
+use shared_capture::SharedCaptureConsumer;
+use statistics::PipelineStatistics;
+use zoom::DigitalZoom;
 
 #[cfg(target_os = "linux")]
 use v4l_pipeline::V4lPipeline;
@@ -45,6 +80,8 @@ pub enum Pipeline {
     V4l(V4lPipeline),
     Fake(FakePipeline),
     QR(QrPipeline),
+    Rtsp(RtspPipeline),
+    Custom(CustomPipeline),
     Redirect(RedirectPipeline),
 }
 
@@ -55,6 +92,8 @@ impl Pipeline {
             Pipeline::V4l(pipeline) => &mut pipeline.state,
             Pipeline::Fake(pipeline) => &mut pipeline.state,
             Pipeline::QR(pipeline) => &mut pipeline.state,
+            Pipeline::Rtsp(pipeline) => &mut pipeline.state,
+            Pipeline::Custom(pipeline) => &mut pipeline.state,
             Pipeline::Redirect(pipeline) => &mut pipeline.state,
         }
     }
@@ -65,6 +104,8 @@ impl Pipeline {
             Pipeline::V4l(pipeline) => &pipeline.state,
             Pipeline::Fake(pipeline) => &pipeline.state,
             Pipeline::QR(pipeline) => &pipeline.state,
+            Pipeline::Rtsp(pipeline) => &pipeline.state,
+            Pipeline::Custom(pipeline) => &pipeline.state,
             Pipeline::Redirect(pipeline) => &pipeline.state,
         }
     }
@@ -89,6 +130,16 @@ impl Pipeline {
                         state: pipeline_state,
                     })
                 }
+                crate::video::video_source_gst::VideoSourceGstType::Rtsp(_) => {
+                    Pipeline::Rtsp(RtspPipeline {
+                        state: pipeline_state,
+                    })
+                }
+                crate::video::video_source_gst::VideoSourceGstType::Custom(_) => {
+                    Pipeline::Custom(CustomPipeline {
+                        state: pipeline_state,
+                    })
+                }
             },
             #[cfg(target_os = "linux")]
             VideoSourceType::Local(_) => Pipeline::V4l(V4lPipeline {
@@ -122,6 +173,19 @@ pub struct PipelineState {
     pub rtp_tee: Option<gst::Element>,
     pub sinks: HashMap<uuid::Uuid, Sink>,
     pub pipeline_runner: PipelineRunner,
+    pub statistics: Arc<PipelineStatistics>,
+    pub bitrate_controller: Arc<BitrateController>,
+    pub zoom: Option<Arc<DigitalZoom>>,
+    pub overlay: Option<Arc<TextOverlay>>,
+    pub scale: Option<Arc<OutputScale>>,
+    pub rate: Option<Arc<OutputRate>>,
+    pub clip_recorder: Option<Arc<ClipRecorder>>,
+    pub audio_tee: Option<gst::Element>,
+    pub audio_rtp_tee: Option<gst::Element>,
+    pub webrtc_layers: Option<Arc<WebRTCLayers>>,
+    pub shared_capture: Option<SharedCaptureConsumer>,
+    /// If the sinks are tuned for the lowest latency when added
+    pub low_latency: bool,
 }
 
 pub const PIPELINE_RTP_TEE_NAME: &str = "RTPTee";
@@ -129,11 +193,12 @@ pub const PIPELINE_VIDEO_TEE_NAME: &str = "VideoTee";
 pub const PIPELINE_FILTER_NAME: &str = "Filter";
 
 impl PipelineState {
+    /// Builds the GStreamer pipeline for the given source, without any sink
     #[instrument(level = "debug")]
-    pub fn try_new(
+    pub fn build(
         video_and_stream_information: &VideoAndStreamInformation,
         pipeline_id: &uuid::Uuid,
-    ) -> Result<Self> {
+    ) -> Result<gst::Pipeline> {
         let pipeline = match &video_and_stream_information.video_source {
             VideoSourceType::Gst(video) => match video.source {
                 crate::video::video_source_gst::VideoSourceGstType::Local(_) => todo!(),
@@ -143,6 +208,12 @@ impl PipelineState {
                 crate::video::video_source_gst::VideoSourceGstType::QR(_) => {
                     QrPipeline::try_new(pipeline_id, video_and_stream_information)
                 }
+                crate::video::video_source_gst::VideoSourceGstType::Rtsp(_) => {
+                    RtspPipeline::try_new(pipeline_id, video_and_stream_information)
+                }
+                crate::video::video_source_gst::VideoSourceGstType::Custom(_) => {
+                    CustomPipeline::try_new(pipeline_id, video_and_stream_information)
+                }
             },
             #[cfg(target_os = "linux")]
             VideoSourceType::Local(_) => {
@@ -157,17 +228,233 @@ impl PipelineState {
             }
         }?;
 
+        if let Some(clock) = video_and_stream_information.stream_information.clock() {
+            clock::apply(&pipeline, pipeline_id, clock)?;
+        }
+
+        // Tuned before the element properties, so they can still override any of it
+        if video_and_stream_information
+            .stream_information
+            .low_latency()
+        {
+            low_latency::apply(&pipeline, pipeline_id)?;
+        }
+
+        if let Some(element_properties) = video_and_stream_information
+            .stream_information
+            .element_properties()
+        {
+            properties::apply(&pipeline, pipeline_id, element_properties)?;
+        }
+
+        Ok(pipeline)
+    }
+
+    #[instrument(level = "debug")]
+    pub fn try_new(
+        video_and_stream_information: &VideoAndStreamInformation,
+        pipeline_id: &uuid::Uuid,
+    ) -> Result<Self> {
+        let pipeline = Self::build(video_and_stream_information, pipeline_id)?;
+
+        // Streams sharing their device are fed by its capture, opened by the first of them
+        let shared_capture = match (
+            video_and_stream_information.local_device(),
+            video_and_stream_information
+                .stream_information
+                .shared_capture(),
+        ) {
+            (Some(device), Some(configuration)) => Some(SharedCaptureConsumer::try_new(
+                &pipeline,
+                pipeline_id,
+                &device,
+                configuration,
+            )?),
+            _ => None,
+        };
+
         let video_tee = pipeline.by_name(&format!("{PIPELINE_VIDEO_TEE_NAME}-{pipeline_id}"));
 
         let rtp_tee = pipeline.by_name(&format!("{PIPELINE_RTP_TEE_NAME}-{pipeline_id}"));
 
-        let pipeline_runner = PipelineRunner::try_new(&pipeline, pipeline_id, false)?;
+        let pipeline_runner_config =
+            PipelineRunnerConfig::from_video_and_stream_information(video_and_stream_information);
+
+        let pipeline_runner =
+            PipelineRunner::try_new(&pipeline, pipeline_id, false, pipeline_runner_config, None)?;
+
+        // Measure the data flowing into the Tees, which is shared by all sinks
+        let statistics: Arc<PipelineStatistics> = Default::default();
+        if let Some(pad) = video_tee
+            .as_ref()
+            .or(rtp_tee.as_ref())
+            .and_then(|tee| tee.static_pad("sink"))
+        {
+            if statistics.attach(&pad).is_none() {
+                warn!("Failed adding statistics probe to Pipeline {pipeline_id}");
+            }
+        }
 
-        pipeline.debug_to_dot_file_with_ts(
-            gst::DebugGraphDetails::all(),
-            format!("pipeline-{pipeline_id}-created"),
+        let (min_bitrate, max_bitrate) = cli::manager::webrtc_bitrate_limits();
+        let bitrate_controller =
+            Arc::new(BitrateController::new(&pipeline, min_bitrate, max_bitrate));
+        // The WebRTC sessions can't go above the stream's own bitrate, and don't change a constant quantizer
+        bitrate_controller.set_rate_control(
+            video_and_stream_information
+                .stream_information
+                .rate_control()
+                .cloned(),
         );
 
+        if let Some(keyframe_interval) = video_and_stream_information
+            .stream_information
+            .keyframe_interval()
+        {
+            if encoders::apply_keyframe_interval(&pipeline, keyframe_interval)?
+                == EncoderUpdate::Unsupported
+            {
+                warn!("Pipeline {pipeline_id} has no encoder with a configurable keyframe interval, ignoring it");
+            }
+        }
+
+        if let Some(rate_control) = video_and_stream_information
+            .stream_information
+            .rate_control()
+        {
+            if encoders::apply_rate_control(&pipeline, rate_control)? == EncoderUpdate::Unsupported
+            {
+                warn!("Pipeline {pipeline_id} has no encoder with a configurable rate control, ignoring it");
+            }
+        }
+
+        // Only raw video pipelines have the crop stage needed for the digital zoom
+        let zoom = DigitalZoom::try_new(&pipeline, pipeline_id).map(Arc::new);
+
+        // Just like the zoom, the overlay stage only exists for raw video pipelines
+        let overlay = TextOverlay::try_new(&pipeline, pipeline_id).map(Arc::new);
+        if let Some(configuration) = video_and_stream_information.stream_information.overlay() {
+            match &overlay {
+                Some(overlay) => overlay.set_configuration(configuration.clone())?,
+                None => {
+                    warn!("Pipeline {pipeline_id} has no raw video to draw the overlay on, ignoring it")
+                }
+            }
+        }
+
+        // Just like the zoom, the scale stage only exists for raw video pipelines, already built with the output
+        // resolution
+        let scale = match &video_and_stream_information
+            .stream_information
+            .configuration
+        {
+            CaptureConfiguration::Video(configuration) => OutputScale::try_new(
+                &pipeline,
+                pipeline_id,
+                configuration.width,
+                configuration.height,
+            )
+            .map(Arc::new),
+            _ => None,
+        };
+        if scale.is_none()
+            && video_and_stream_information
+                .stream_information
+                .output_resolution()
+                .is_some()
+        {
+            warn!("Pipeline {pipeline_id} has no raw video to scale to the output resolution, ignoring it")
+        }
+
+        // The rate stage is next to the scale one, only for raw video pipelines, already built with the maximum
+        // framerate
+        let rate = match &video_and_stream_information
+            .stream_information
+            .configuration
+        {
+            CaptureConfiguration::Video(configuration) => {
+                OutputRate::try_new(&pipeline, pipeline_id, &configuration.frame_interval)
+                    .map(Arc::new)
+            }
+            _ => None,
+        };
+        if rate.is_none()
+            && video_and_stream_information
+                .stream_information
+                .max_framerate()
+                .is_some()
+        {
+            warn!("Pipeline {pipeline_id} has no raw video to drop down to the maximum framerate, ignoring it")
+        }
+
+        // Clips are taken from the encoded video, like the recordings
+        let clip_recorder = video_tee
+            .as_ref()
+            .and_then(|tee| tee.static_pad("sink"))
+            .and_then(|pad| {
+                ClipRecorder::try_new(
+                    &pad,
+                    video_and_stream_information
+                        .stream_information
+                        .clip_pre_roll_secs(),
+                )
+            })
+            .map(Arc::new);
+
+        // An absent audio device shouldn't prevent the video from streaming
+        let (audio_tee, audio_rtp_tee) = match video_and_stream_information
+            .stream_information
+            .audio()
+        {
+            Some(_) if video_tee.is_none() => {
+                warn!("Pipeline {pipeline_id} has no encoded video to carry the audio along, ignoring it");
+                (None, None)
+            }
+            Some(configuration) => {
+                match audio::add_branch(&pipeline, pipeline_id, configuration) {
+                    Ok((audio_tee, audio_rtp_tee)) => (Some(audio_tee), Some(audio_rtp_tee)),
+                    Err(error) => {
+                        warn!("Pipeline {pipeline_id} will be video only, as its audio failed: {error:?}");
+                        (None, None)
+                    }
+                }
+            }
+            None => (None, None),
+        };
+
+        // The layers are encoded again from the encoded video, so any H264 pipeline can have them
+        let webrtc_layers = match (
+            video_and_stream_information
+                .stream_information
+                .webrtc_layers(),
+            &video_tee,
+            &rtp_tee,
+        ) {
+            (Some(configurations), Some(video_tee), Some(rtp_tee))
+                if !configurations.is_empty() =>
+            {
+                match WebRTCLayers::try_new(
+                    &pipeline,
+                    pipeline_id,
+                    video_tee,
+                    rtp_tee,
+                    configurations,
+                ) {
+                    Ok(webrtc_layers) => Some(Arc::new(webrtc_layers)),
+                    Err(error) => {
+                        warn!("Pipeline {pipeline_id} will have a single WebRTC layer, as the others failed: {error:?}");
+                        None
+                    }
+                }
+            }
+            (Some(configurations), _, _) if !configurations.is_empty() => {
+                warn!("Pipeline {pipeline_id} has no encoded video to build the WebRTC layers from, ignoring them");
+                None
+            }
+            _ => None,
+        };
+
+        dump_dot_file(&pipeline, pipeline_id, "created");
+
         Ok(Self {
             pipeline_id: *pipeline_id,
             pipeline,
@@ -175,9 +462,42 @@ impl PipelineState {
             rtp_tee,
             sinks: Default::default(),
             pipeline_runner,
+            statistics,
+            bitrate_controller,
+            zoom,
+            overlay,
+            scale,
+            rate,
+            clip_recorder,
+            audio_tee,
+            audio_rtp_tee,
+            webrtc_layers,
+            shared_capture,
+            low_latency: video_and_stream_information
+                .stream_information
+                .low_latency(),
         })
     }
 
+    /// Asks the encoder for a keyframe, so clients joining mid-stream can start decoding right away
+    #[instrument(level = "debug", skip(self))]
+    pub fn force_keyframe(&self) -> Result<()> {
+        let tee = self
+            .video_tee
+            .as_ref()
+            .or(self.rtp_tee.as_ref())
+            .context("No Tee for this kind of Pipeline")?;
+
+        if !request_keyframe(tee) {
+            return Err(anyhow!(
+                "No element upstream from the Pipeline {} handled the keyframe request",
+                self.pipeline_id
+            ));
+        }
+
+        Ok(())
+    }
+
     /// Links the sink pad from the given Sink to this Pipeline's Tee element
     #[instrument(level = "debug", skip(self))]
     pub fn add_sink(&mut self, mut sink: Sink) -> Result<()> {
@@ -186,7 +506,7 @@ impl PipelineState {
         // Request a new src pad for the used Tee
         // Note: Here we choose if the sink will receive a Video or RTP packages
         let tee = match sink {
-            Sink::Image(_) => &self.video_tee,
+            Sink::Image(_) | Sink::Recording(_) | Sink::Srt(_) | Sink::Mjpeg(_) => &self.video_tee,
             Sink::Udp(_) | Sink::Rtsp(_) | Sink::WebRTC(_) => &self.rtp_tee,
         };
 
@@ -199,11 +519,49 @@ impl PipelineState {
         ))?;
         debug!("Got tee's src pad {:#?}", tee_src_pad.name());
 
+        // Count what is sent by each sink, except for the recordings and snapshots, which stay in this host
+        if !matches!(sink, Sink::Image(_) | Sink::Recording(_)) {
+            let destinations = match &sink {
+                Sink::Udp(udp_sink) => Some(udp_sink.destinations_count()),
+                _ => None,
+            };
+            if bandwidth::attach(pipeline_id, &tee_src_pad, destinations).is_none() {
+                warn!("Failed adding bandwidth probe to Pipeline {pipeline_id}");
+            }
+        }
+
+        // WebRTC sessions adapt the encoder's bitrate to their estimated bandwidth, or move to a lower layer
+        // Recordings and RTSP endpoints carry the audio along with the video
+        match &mut sink {
+            Sink::WebRTC(webrtc_sink) => {
+                webrtc_sink.set_bitrate_controller(self.bitrate_controller.clone());
+                if let Some(webrtc_layers) = &self.webrtc_layers {
+                    webrtc_sink.set_webrtc_layers(webrtc_layers.clone());
+                }
+            }
+            Sink::Recording(recording_sink) => {
+                if let Some(audio_tee) = &self.audio_tee {
+                    recording_sink.set_audio_tee(audio_tee.clone());
+                }
+            }
+            Sink::Rtsp(rtsp_sink) => {
+                if let Some(audio_rtp_tee) = &self.audio_rtp_tee {
+                    rtsp_sink.set_audio_tee(audio_rtp_tee.clone());
+                }
+            }
+            _ => (),
+        }
+
         // Link the Sink
         let pipeline = &self.pipeline;
         sink.link(pipeline, pipeline_id, tee_src_pad)?;
         let sink_id = &sink.get_id();
 
+        // The sinks only exist once linked, so they are tuned here instead of when the pipeline is built
+        if self.low_latency {
+            low_latency::apply_to_sinks(pipeline);
+        }
+
         // Start the pipeline if not playing yet
         if pipeline.current_state() != gst::State::Playing {
             if let Err(error) = pipeline.set_state(gst::State::Playing) {
@@ -240,7 +598,27 @@ impl PipelineState {
                 // In case it exisits, try to remove it first, but skip the result
                 let _ = RTSPServer::stop_pipeline(&sink.path());
 
-                RTSPServer::add_pipeline(&sink.scheme(), &sink.path(), &sink.socket_path(), caps)?;
+                let audio_socket_path = sink.audio_socket_path();
+                let audio_caps = audio_socket_path.as_ref().and_then(|_| {
+                    let caps = self.audio_rtp_tee.as_ref().and_then(audio::wait_for_caps);
+                    if caps.is_none() {
+                        warn!(
+                            "No audio caps for the RTSP endpoint {:?}, it will be video only",
+                            sink.path()
+                        );
+                    }
+                    caps
+                });
+
+                RTSPServer::add_pipeline(
+                    &sink.scheme(),
+                    &sink.path(),
+                    &sink.socket_path(),
+                    caps,
+                    sink.latency_ms(),
+                    sink.rtp(),
+                    audio_socket_path.as_deref().zip(audio_caps.as_ref()),
+                )?;
 
                 RTSPServer::start_pipeline(&sink.path())?;
             }
@@ -268,9 +646,10 @@ impl PipelineState {
         let pipeline_id = &self.pipeline_id;
 
         let pipeline = &self.pipeline;
-        pipeline.debug_to_dot_file_with_ts(
-            gst::DebugGraphDetails::all(),
-            format!("pipeline-{pipeline_id}-sink-{sink_id}-before-removing"),
+        dump_dot_file(
+            pipeline,
+            pipeline_id,
+            &format!("sink-{sink_id}-before-removing"),
         );
 
         let sink = self.sinks.remove(sink_id).context(format!(
@@ -286,14 +665,18 @@ impl PipelineState {
         // Set pipeline state to NULL when there are no consumers to save CPU usage.
         // TODO: We are skipping rtspsrc here because once back to null, we are having
         // trouble knowing how to propper reuse it.
-        if !self
-            .pipeline
-            .children()
-            .iter()
-            .any(|child| child.name().starts_with("rtspsrc"))
-        {
+        if !self.pipeline.children().iter().any(|child| {
+            child.name().starts_with("rtspsrc")
+                || child
+                    .name()
+                    .starts_with(rtsp_pipeline::PIPELINE_RTSP_SOURCE_NAME)
+        }) {
             if let Some(rtp_tee) = &self.rtp_tee {
-                if rtp_tee.src_pads().is_empty() {
+                let layers_used = self
+                    .webrtc_layers
+                    .as_ref()
+                    .is_some_and(|webrtc_layers| webrtc_layers.is_used());
+                if rtp_tee.src_pads().is_empty() && !layers_used {
                     if let Err(error) = pipeline.set_state(gst::State::Null) {
                         return Err(anyhow!(
                             "Failed to change state of Pipeline {pipeline_id} to NULL. Reason: {error}"
@@ -307,9 +690,10 @@ impl PipelineState {
             RTSPServer::stop_pipeline(&sink.path())?;
         }
 
-        pipeline.debug_to_dot_file_with_ts(
-            gst::DebugGraphDetails::all(),
-            format!("pipeline-{pipeline_id}-sink-{sink_id}-after-removing"),
+        dump_dot_file(
+            pipeline,
+            pipeline_id,
+            &format!("sink-{sink_id}-after-removing"),
         );
 
         Ok(())
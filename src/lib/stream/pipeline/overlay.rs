@@ -0,0 +1,220 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::*;
+
+use crate::{
+    mavlink::telemetry::{self, Telemetry},
+    stream::types::{OverlayConfiguration, OverlayPosition},
+};
+
+pub const PIPELINE_OVERLAY_NAME: &str = "TextOverlay";
+
+const DEFAULT_FONT: &str = "Sans 18";
+/// Period between each render of the template, fast enough for the seconds of the clock
+const OVERLAY_UPDATE_PERIOD: std::time::Duration = std::time::Duration::from_millis(250);
+/// Maximum length of a template, in characters
+const MAX_TEMPLATE_LENGTH: usize = 256;
+
+/// The raw video stage drawing the text. It is always part of raw video pipelines, silent while disabled, so
+/// the overlay can be enabled and edited without rebuilding the pipeline
+pub fn description(pipeline_id: &uuid::Uuid) -> String {
+    format!(
+        " ! textoverlay name={PIPELINE_OVERLAY_NAME}-{pipeline_id} silent=true shaded-background=true",
+    )
+}
+
+#[instrument(level = "debug")]
+pub fn validate(configuration: &OverlayConfiguration) -> Result<()> {
+    if configuration.template.chars().count() > MAX_TEMPLATE_LENGTH {
+        return Err(anyhow!(
+            "Overlay template is longer than {MAX_TEMPLATE_LENGTH} characters"
+        ));
+    }
+
+    if matches!(&configuration.font, Some(font) if font.trim().is_empty()) {
+        return Err(anyhow!("Overlay font can't be empty"));
+    }
+
+    Ok(())
+}
+
+/// Replaces the template fields by the given time and telemetry, using "--" for the telemetry not received yet
+pub fn render<Tz: chrono::TimeZone>(
+    template: &str,
+    now: &chrono::DateTime<Tz>,
+    telemetry: &Telemetry,
+) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let value = |value: Option<String>| value.unwrap_or_else(|| "--".to_string());
+
+    template
+        .replace("{time}", &now.format("%H:%M:%S").to_string())
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace(
+            "{depth}",
+            &value(telemetry.depth.map(|depth| format!("{depth:.1}m"))),
+        )
+        .replace(
+            "{heading}",
+            &value(telemetry.heading.map(|heading| format!("{heading}°"))),
+        )
+        .replace(
+            "{altitude}",
+            &value(telemetry.altitude.map(|altitude| format!("{altitude:.1}m"))),
+        )
+        .replace(
+            "{groundspeed}",
+            &value(telemetry.groundspeed.map(|speed| format!("{speed:.1}m/s"))),
+        )
+}
+
+/// Text drawn over the frames, rendered periodically from a template
+#[derive(Debug)]
+pub struct TextOverlay {
+    textoverlay_weak: gst::glib::WeakRef<gst::Element>,
+    configuration: Mutex<OverlayConfiguration>,
+    update_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl Drop for TextOverlay {
+    fn drop(&mut self) {
+        if let Some(handle) = self.update_handle.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+}
+
+impl TextOverlay {
+    /// Finds the overlay stage of the pipeline, which only exists for raw video in our pipelines
+    pub fn try_new(pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid) -> Option<Self> {
+        let textoverlay = pipeline.by_name(&format!("{PIPELINE_OVERLAY_NAME}-{pipeline_id}"))?;
+
+        Some(Self {
+            textoverlay_weak: textoverlay.downgrade(),
+            configuration: Default::default(),
+            update_handle: Default::default(),
+        })
+    }
+
+    /// The current overlay configuration
+    pub fn configuration(&self) -> OverlayConfiguration {
+        self.configuration.lock().unwrap().clone()
+    }
+
+    /// Applies the configuration to the running pipeline, starting or stopping the template updates
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_configuration(self: &Arc<Self>, configuration: OverlayConfiguration) -> Result<()> {
+        validate(&configuration)?;
+
+        let textoverlay = self.textoverlay()?;
+
+        let (valignment, halignment) = match configuration.position {
+            OverlayPosition::TopLeft => ("top", "left"),
+            OverlayPosition::TopRight => ("top", "right"),
+            OverlayPosition::BottomLeft => ("bottom", "left"),
+            OverlayPosition::BottomRight => ("bottom", "right"),
+            OverlayPosition::Center => ("center", "center"),
+        };
+        textoverlay.set_property_from_str("valignment", valignment);
+        textoverlay.set_property_from_str("halignment", halignment);
+        textoverlay.set_property(
+            "font-desc",
+            configuration.font.as_deref().unwrap_or(DEFAULT_FONT),
+        );
+
+        if let Some(handle) = self.update_handle.lock().unwrap().take() {
+            handle.abort();
+        }
+
+        let enabled = configuration.enabled;
+        *self.configuration.lock().unwrap() = configuration;
+
+        if enabled {
+            self.update_text()?;
+            self.start_updates();
+        }
+        textoverlay.set_property("silent", !enabled);
+
+        Ok(())
+    }
+
+    fn start_updates(self: &Arc<Self>) {
+        let overlay = Arc::downgrade(self);
+        let handle = tokio::spawn(async move {
+            let mut period = tokio::time::interval(OVERLAY_UPDATE_PERIOD);
+            loop {
+                period.tick().await;
+
+                let Some(overlay) = overlay.upgrade() else {
+                    break;
+                };
+
+                if let Err(error) = overlay.update_text() {
+                    warn!("Failed updating the overlay text: {error:?}");
+                    break;
+                }
+            }
+        });
+
+        *self.update_handle.lock().unwrap() = Some(handle);
+    }
+
+    fn update_text(&self) -> Result<()> {
+        let textoverlay = self.textoverlay()?;
+
+        let text = render(
+            &self.configuration.lock().unwrap().template,
+            &chrono::Local::now(),
+            &telemetry::telemetry(),
+        );
+        textoverlay.set_property("text", text);
+
+        Ok(())
+    }
+
+    fn textoverlay(&self) -> Result<gst::Element> {
+        self.textoverlay_weak
+            .upgrade()
+            .ok_or_else(|| anyhow!("The overlay's textoverlay element no longer exists"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_render() {
+        let now = chrono::Utc.with_ymd_and_hms(2024, 5, 17, 13, 4, 9).unwrap();
+
+        let telemetry = Telemetry {
+            depth: Some(12.34),
+            heading: Some(271),
+            altitude: Some(-12.34),
+            groundspeed: Some(0.5),
+        };
+        assert_eq!(
+            render(
+                "{date} {time} D:{depth} H:{heading} A:{altitude} S:{groundspeed}",
+                &now,
+                &telemetry
+            ),
+            "2024-05-17 13:04:09 D:12.3m H:271° A:-12.3m S:0.5m/s"
+        );
+
+        // Telemetry not received yet
+        assert_eq!(
+            render("{time} {depth} {heading}", &now, &Telemetry::default()),
+            "13:04:09 -- --"
+        );
+
+        // Unknown fields are kept as they are
+        assert_eq!(render("{unknown}", &now, &telemetry), "{unknown}");
+    }
+}
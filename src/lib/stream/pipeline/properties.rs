@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::*;
+
+/// Sets the given properties on the elements of the pipeline, matched by their factory name, like "x264enc", or by
+/// their own name, with or without the pipeline id suffix, like "Filter". The values are parsed as the property's
+/// type, like in a gst-launch description
+#[instrument(level = "debug", skip(pipeline))]
+pub fn apply(
+    pipeline: &gst::Pipeline,
+    pipeline_id: &uuid::Uuid,
+    element_properties: &BTreeMap<String, BTreeMap<String, String>>,
+) -> Result<()> {
+    let elements = pipeline
+        .iterate_recurse()
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect::<Vec<gst::Element>>();
+
+    for (element_name, properties) in element_properties {
+        let matched = elements
+            .iter()
+            .filter(|element| matches(element, element_name, pipeline_id))
+            .collect::<Vec<&gst::Element>>();
+
+        if matched.is_empty() {
+            return Err(anyhow!(
+                "Element {element_name:?} is not part of the pipeline, the available ones are: {:?}",
+                elements
+                    .iter()
+                    .filter_map(|element| element.factory().map(|factory| factory.name()))
+                    .collect::<std::collections::BTreeSet<_>>()
+            ));
+        }
+
+        for element in matched {
+            for (property, value) in properties {
+                set_property(element, element_name, property, value)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[instrument(level = "debug")]
+pub fn validate(element_properties: &BTreeMap<String, BTreeMap<String, String>>) -> Result<()> {
+    for (element_name, properties) in element_properties {
+        if element_name.is_empty() {
+            return Err(anyhow!("Element properties need an element name"));
+        }
+
+        if properties.keys().any(String::is_empty) {
+            return Err(anyhow!(
+                "Element properties of {element_name:?} need property names"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn matches(element: &gst::Element, element_name: &str, pipeline_id: &uuid::Uuid) -> bool {
+    let name = element.name();
+
+    name == element_name
+        || name == format!("{element_name}-{pipeline_id}")
+        || element
+            .factory()
+            .is_some_and(|factory| factory.name() == element_name)
+}
+
+fn set_property(
+    element: &gst::Element,
+    element_name: &str,
+    property: &str,
+    value: &str,
+) -> Result<()> {
+    let pspec = element.find_property(property).ok_or_else(|| {
+        anyhow!(
+            "Element {element_name:?} has no property {property:?}, the available ones are: {:?}",
+            element
+                .list_properties()
+                .iter()
+                .map(|pspec| pspec.name())
+                .collect::<Vec<_>>()
+        )
+    })?;
+
+    let flags = pspec.flags();
+    if !flags.contains(gst::glib::ParamFlags::WRITABLE)
+        || flags.contains(gst::glib::ParamFlags::CONSTRUCT_ONLY)
+    {
+        return Err(anyhow!(
+            "Property {property:?} of element {element_name:?} can't be changed"
+        ));
+    }
+
+    let parsed = gst::glib::Value::deserialize(value, pspec.value_type()).map_err(|_| {
+        anyhow!(
+            "Invalid value {value:?} for property {property:?} of element {element_name:?}, expected a {}",
+            pspec.value_type().name()
+        )
+    })?;
+
+    element.set_property_from_value(property, &parsed);
+
+    debug!(
+        "Element {:?} property {property:?} set to {value:?}",
+        element.name()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(
+        element_name: &str,
+        property: &str,
+        value: &str,
+    ) -> BTreeMap<String, BTreeMap<String, String>> {
+        BTreeMap::from([(
+            element_name.to_string(),
+            BTreeMap::from([(property.to_string(), value.to_string())]),
+        )])
+    }
+
+    #[test]
+    fn test_apply() {
+        gst::init().unwrap();
+
+        let pipeline_id = uuid::Uuid::new_v4();
+        let pipeline = gst::parse::launch(&format!(
+            "videotestsrc ! capsfilter name=Filter-{pipeline_id} ! fakesink"
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        let element = |factory_name: &str| {
+            pipeline
+                .iterate_elements()
+                .into_iter()
+                .filter_map(Result::ok)
+                .find(|element| element.factory().unwrap().name() == factory_name)
+                .unwrap()
+        };
+
+        apply(
+            &pipeline,
+            &pipeline_id,
+            &properties("videotestsrc", "is-live", "true"),
+        )
+        .unwrap();
+        assert!(element("videotestsrc").property::<bool>("is-live"));
+
+        apply(
+            &pipeline,
+            &pipeline_id,
+            &properties("videotestsrc", "pattern", "ball"),
+        )
+        .unwrap();
+        apply(
+            &pipeline,
+            &pipeline_id,
+            &properties("Filter", "caps", "video/x-raw,width=320"),
+        )
+        .unwrap();
+        assert_eq!(
+            element("capsfilter")
+                .property::<gst::Caps>("caps")
+                .to_string(),
+            "video/x-raw, width=(int)320"
+        );
+
+        assert!(apply(
+            &pipeline,
+            &pipeline_id,
+            &properties("x264enc", "tune", "zerolatency")
+        )
+        .is_err());
+        assert!(apply(
+            &pipeline,
+            &pipeline_id,
+            &properties("videotestsrc", "unknown", "1")
+        )
+        .is_err());
+        assert!(apply(
+            &pipeline,
+            &pipeline_id,
+            &properties("videotestsrc", "is-live", "maybe")
+        )
+        .is_err());
+    }
+}
@@ -1,5 +1,10 @@
 use crate::{
-    stream::types::CaptureConfiguration,
+    stream::{
+        gst::encoders::{
+            h264_caps_fields, select_encoder, validate_h264_profile_for_encoder, EncoderCandidate,
+        },
+        types::CaptureConfiguration,
+    },
     video::{
         types::{VideoEncodeType, VideoSourceType},
         video_source_gst::VideoSourceGstType,
@@ -8,7 +13,7 @@ use crate::{
 };
 
 use super::{
-    PipelineGstreamerInterface, PipelineState, PIPELINE_FILTER_NAME, PIPELINE_RTP_TEE_NAME,
+    queues, PipelineGstreamerInterface, PipelineState, PIPELINE_FILTER_NAME, PIPELINE_RTP_TEE_NAME,
     PIPELINE_VIDEO_TEE_NAME,
 };
 
@@ -59,24 +64,77 @@ impl QrPipeline {
             }
         };
 
+        let source_queue = queues::description(
+            pipeline_id,
+            queues::PIPELINE_SOURCE_QUEUE_NAME,
+            video_and_stream_information.stream_information.queues(),
+        );
+        let encoder_queue = queues::description(
+            pipeline_id,
+            queues::PIPELINE_ENCODER_QUEUE_NAME,
+            video_and_stream_information.stream_information.queues(),
+        );
         let filter_name = format!("{PIPELINE_FILTER_NAME}-{pipeline_id}");
         let video_tee_name = format!("{PIPELINE_VIDEO_TEE_NAME}-{pipeline_id}");
         let rtp_tee_name = format!("{PIPELINE_RTP_TEE_NAME}-{pipeline_id}");
 
+        // The encoder is only selected when the encode needs one
+        let encoder = || -> Result<&'static EncoderCandidate> {
+            let encoder = select_encoder(&configuration.encode)?;
+            info!(
+                "Pipeline {pipeline_id} using encoder {:?} (hardware: {})",
+                encoder.name, encoder.hardware
+            );
+            Ok(encoder)
+        };
+
         let description = match &configuration.encode {
             VideoEncodeType::H264 => {
+                let encoder = encoder()?;
+                let h264_profile = video_and_stream_information
+                    .stream_information
+                    .h264_profile();
+                if let Some(h264_profile) = h264_profile {
+                    validate_h264_profile_for_encoder(h264_profile, encoder.name)?;
+                }
+
                 format!(concat!(
-                        "qrtimestampsrc",
+                        "qrtimestampsrc{source_queue}",
                         " ! video/x-raw,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
                         " ! videoconvert",
-                        " ! x264enc tune=zerolatency speed-preset=ultrafast bitrate=5000",
+                        " ! {encoder}{encoder_queue}",
                         " ! h264parse",
-                        " ! capsfilter name={filter_name} caps=video/x-h264,profile={profile},stream-format=avc,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                        " ! capsfilter name={filter_name} caps=video/x-h264,{profile},stream-format=avc,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
                         " ! tee name={video_tee_name} allow-not-linked=true",
                         " ! rtph264pay aggregate-mode=zero-latency config-interval=10 pt=96",
                         " ! tee name={rtp_tee_name} allow-not-linked=true"
                     ),
-                    profile = "constrained-baseline",
+                    encoder = encoder.description,
+                    profile = h264_caps_fields(h264_profile),
+                    width = configuration.width,
+                    height = configuration.height,
+                    interval_denominator = configuration.frame_interval.denominator,
+                    interval_numerator = configuration.frame_interval.numerator,
+                    filter_name = filter_name,
+                    video_tee_name = video_tee_name,
+                    rtp_tee_name = rtp_tee_name,
+                    source_queue = source_queue,
+                    encoder_queue = encoder_queue,
+                )
+            }
+            VideoEncodeType::H265 => {
+                format!(concat!(
+                        "qrtimestampsrc{source_queue}",
+                        " ! video/x-raw,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                        " ! videoconvert",
+                        " ! {encoder}{encoder_queue}",
+                        " ! h265parse",
+                        " ! capsfilter name={filter_name} caps=video/x-h265,stream-format=byte-stream,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                        " ! tee name={video_tee_name} allow-not-linked=true",
+                        " ! rtph265pay aggregate-mode=zero-latency config-interval=10 pt=96",
+                        " ! tee name={rtp_tee_name} allow-not-linked=true"
+                    ),
+                    encoder = encoder()?.description,
                     width = configuration.width,
                     height = configuration.height,
                     interval_denominator = configuration.frame_interval.denominator,
@@ -84,12 +142,14 @@ impl QrPipeline {
                     filter_name = filter_name,
                     video_tee_name = video_tee_name,
                     rtp_tee_name = rtp_tee_name,
+                    source_queue = source_queue,
+                    encoder_queue = encoder_queue,
                 )
             }
             VideoEncodeType::Rgb => {
                 format!(
                     concat!(
-                        "qrtimestampsrc",
+                        "qrtimestampsrc{source_queue}",
                         " ! video/x-raw,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
                         " ! tee name={video_tee_name} allow-not-linked=true",
                         " ! rtpvrawpay pt=96",
@@ -101,6 +161,7 @@ impl QrPipeline {
                     interval_numerator = configuration.frame_interval.numerator,
                     video_tee_name = video_tee_name,
                     rtp_tee_name = rtp_tee_name,
+                    source_queue = source_queue,
                 )
             }
             unsupported => {
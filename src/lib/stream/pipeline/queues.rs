@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+use tracing::*;
+
+use crate::stream::types::{QueueConfiguration, QueueLeakiness};
+
+pub const PIPELINE_SOURCE_QUEUE_NAME: &str = "SourceQueue";
+pub const PIPELINE_ENCODER_QUEUE_NAME: &str = "EncoderQueue";
+
+const DEFAULT_MAX_SIZE_BUFFERS: u32 = 3;
+const DEFAULT_MAX_SIZE_TIME_MS: u32 = 200;
+
+/// The range of frames a queue can hold
+const MAX_SIZE_BUFFERS_RANGE: std::ops::RangeInclusive<u32> = 1..=1000;
+/// The range of time a queue can hold, in milliseconds
+const MAX_SIZE_TIME_MS_RANGE: std::ops::RangeInclusive<u32> = 1..=10000;
+
+/// The queue placed after a stage of the pipeline, like the source or the encoder, so the stage keeps running
+/// while the next elements are busy. When full, it drops frames as configured instead of holding more of them
+pub fn description(
+    pipeline_id: &uuid::Uuid,
+    name: &str,
+    configuration: Option<&QueueConfiguration>,
+) -> String {
+    let configuration = configuration.cloned().unwrap_or_default();
+    let leaky = match configuration.leaky.unwrap_or_default() {
+        QueueLeakiness::No => "no",
+        QueueLeakiness::Upstream => "upstream",
+        QueueLeakiness::Downstream => "downstream",
+    };
+    let max_size_buffers = configuration
+        .max_size_buffers
+        .unwrap_or(DEFAULT_MAX_SIZE_BUFFERS);
+    let max_size_time = gst::ClockTime::from_mseconds(
+        configuration
+            .max_size_time_ms
+            .unwrap_or(DEFAULT_MAX_SIZE_TIME_MS) as u64,
+    )
+    .nseconds();
+
+    format!(
+        " ! queue name={name}-{pipeline_id} leaky={leaky} max-size-buffers={max_size_buffers} max-size-bytes=0 max-size-time={max_size_time}"
+    )
+}
+
+#[instrument(level = "debug")]
+pub fn validate(configuration: &QueueConfiguration) -> Result<()> {
+    if let Some(max_size_buffers) = configuration.max_size_buffers {
+        if !MAX_SIZE_BUFFERS_RANGE.contains(&max_size_buffers) {
+            return Err(anyhow!(
+                "Queue size of {max_size_buffers} frames is out of the allowed range ({MAX_SIZE_BUFFERS_RANGE:?} frames)"
+            ));
+        }
+    }
+
+    if let Some(max_size_time_ms) = configuration.max_size_time_ms {
+        if !MAX_SIZE_TIME_MS_RANGE.contains(&max_size_time_ms) {
+            return Err(anyhow!(
+                "Queue size of {max_size_time_ms} ms is out of the allowed range ({MAX_SIZE_TIME_MS_RANGE:?} ms)"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gst::prelude::*;
+
+    #[test]
+    fn test_description() {
+        gst::init().unwrap();
+
+        let pipeline_id = uuid::Uuid::new_v4();
+        let queue = |configuration: Option<&QueueConfiguration>| {
+            let pipeline = gst::parse::launch(&format!(
+                "videotestsrc{} ! fakesink",
+                description(&pipeline_id, PIPELINE_SOURCE_QUEUE_NAME, configuration)
+            ))
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+
+            pipeline
+                .by_name(&format!("{PIPELINE_SOURCE_QUEUE_NAME}-{pipeline_id}"))
+                .unwrap()
+        };
+
+        // Low latency defaults
+        let default_queue = queue(None);
+        assert_eq!(
+            default_queue.property::<u32>("max-size-buffers"),
+            DEFAULT_MAX_SIZE_BUFFERS
+        );
+        assert_eq!(default_queue.property::<u32>("max-size-bytes"), 0);
+        assert_eq!(
+            default_queue.property::<u64>("max-size-time"),
+            DEFAULT_MAX_SIZE_TIME_MS as u64 * 1_000_000
+        );
+        assert_eq!(
+            default_queue
+                .property_value("leaky")
+                .serialize()
+                .unwrap()
+                .as_str(),
+            "downstream"
+        );
+
+        let configuration = QueueConfiguration {
+            leaky: Some(QueueLeakiness::No),
+            max_size_buffers: Some(30),
+            max_size_time_ms: None,
+        };
+        assert!(validate(&configuration).is_ok());
+        let configured_queue = queue(Some(&configuration));
+        assert_eq!(configured_queue.property::<u32>("max-size-buffers"), 30);
+        assert_eq!(
+            configured_queue
+                .property_value("leaky")
+                .serialize()
+                .unwrap()
+                .as_str(),
+            "no"
+        );
+
+        assert!(validate(&QueueConfiguration {
+            max_size_buffers: Some(0),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(&QueueConfiguration {
+            max_size_time_ms: Some(60_000),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
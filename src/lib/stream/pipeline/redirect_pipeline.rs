@@ -58,16 +58,18 @@ impl RedirectPipeline {
             .context("Failed to access the fisrt endpoint")?;
 
         let sink_tee_name = format!("{PIPELINE_RTP_TEE_NAME}-{pipeline_id}");
+        let latency_ms = video_and_stream_information.stream_information.latency_ms();
 
         let description = match url.scheme() {
             "rtsp" => {
                 format!(
                     concat!(
-                        "rtspsrc location={location} is-live=true latency=0",
+                        "rtspsrc location={location} is-live=true latency={latency_ms}",
                         " ! application/x-rtp",
                         " ! tee name={sink_tee_name} allow-not-linked=true"
                     ),
                     location = url,
+                    latency_ms = latency_ms,
                     sink_tee_name = sink_tee_name,
                 )
             }
@@ -76,9 +78,11 @@ impl RedirectPipeline {
                     concat!(
                         "udpsrc address={address} port={port} close-socket=false auto-multicast=true",
                         " ! application/x-rtp",
+                        " ! rtpjitterbuffer latency={latency_ms}",
                         " ! tee name={sink_tee_name} allow-not-linked=true"
                     ),
                     address = url.host().context("UDP URL without host")?,
+                    latency_ms = latency_ms,
                     port = url.port().context("UDP URL without port")?,
                     sink_tee_name = sink_tee_name,
                 )
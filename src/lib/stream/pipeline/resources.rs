@@ -0,0 +1,134 @@
+use std::{collections::HashMap, time::Duration};
+
+use gst::prelude::*;
+
+/// The kernel reports the CPU time of the threads in ticks of USER_HZ, which Linux fixes at 100 for userspace
+const CLOCK_TICKS_PER_SECOND: u64 = 100;
+
+/// Accounts the CPU time of the streaming threads of a pipeline, which run its elements between the queues, like
+/// the source, the converters and most encoders. Threads created by the elements themselves, like the ones of
+/// x264enc, aren't known to GStreamer, so this is a lower bound of the CPU used by the pipeline
+#[derive(Debug, Default)]
+pub struct ThreadAccounting {
+    /// The CPU time of each streaming thread when it entered the pipeline, by thread id, as GStreamer reuses them
+    threads: HashMap<u32, Duration>,
+    /// The CPU time spent by the streaming threads that already left the pipeline
+    finished: Duration,
+}
+
+impl ThreadAccounting {
+    /// Tracks the thread posting the stream status message. GStreamer posts it from the streaming thread itself
+    /// when it enters and leaves, so this has to be called from the bus sync handler
+    pub fn handle_stream_status(&mut self, status_type: gst::StreamStatusType) {
+        let Some(thread_id) = current_thread_id() else {
+            return;
+        };
+
+        match status_type {
+            gst::StreamStatusType::Enter => {
+                let cpu_time = thread_cpu_time(thread_id).unwrap_or_default();
+                self.threads.insert(thread_id, cpu_time);
+            }
+            gst::StreamStatusType::Leave => {
+                if let Some(entered) = self.threads.remove(&thread_id) {
+                    let cpu_time = thread_cpu_time(thread_id).unwrap_or(entered);
+                    self.finished += cpu_time.saturating_sub(entered);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The CPU time spent by the streaming threads since they entered the pipeline
+    pub fn cpu_time(&self) -> Duration {
+        self.threads
+            .iter()
+            .filter_map(|(thread_id, entered)| {
+                thread_cpu_time(*thread_id).map(|cpu_time| cpu_time.saturating_sub(*entered))
+            })
+            .fold(self.finished, |total, cpu_time| total + cpu_time)
+    }
+}
+
+/// The id of the calling thread, as seen by the kernel
+fn current_thread_id() -> Option<u32> {
+    // Like "1234/task/1236"
+    std::fs::read_link("/proc/thread-self")
+        .ok()?
+        .file_name()?
+        .to_str()?
+        .parse()
+        .ok()
+}
+
+/// The CPU time spent by the thread of this process, in user and system modes
+fn thread_cpu_time(thread_id: u32) -> Option<Duration> {
+    let stat = std::fs::read_to_string(format!("/proc/self/task/{thread_id}/stat")).ok()?;
+    parse_cpu_time(&stat)
+}
+
+fn parse_cpu_time(stat: &str) -> Option<Duration> {
+    // The thread name can have spaces and parentheses, so the fields are counted from its end. The user and system
+    // times are the 14th and 15th fields, counting the ones before the name
+    let mut fields = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .skip(11);
+    let user_ticks: u64 = fields.next()?.parse().ok()?;
+    let system_ticks: u64 = fields.next()?.parse().ok()?;
+
+    Some(Duration::from_millis(
+        (user_ticks + system_ticks) * 1000 / CLOCK_TICKS_PER_SECOND,
+    ))
+}
+
+/// The bytes held by the queues of the pipeline, which is where most of the memory of a stream sits
+pub fn queued_bytes(pipeline: &gst::Pipeline) -> u64 {
+    pipeline
+        .iterate_recurse()
+        .into_iter()
+        .flatten()
+        .filter(|element| {
+            element
+                .factory()
+                .is_some_and(|factory| factory.name() == "queue")
+        })
+        .map(|queue| queue.property::<u32>("current-level-bytes") as u64)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_time() {
+        let stat = "1236 (x264enc:sink (2)) S 1 1234 1234 0 -1 4194368 120 0 0 0 250 50 0 0 20 0 42 0 100 0 0";
+        assert_eq!(parse_cpu_time(stat), Some(Duration::from_secs(3)));
+        assert_eq!(parse_cpu_time("1236 (broken"), None);
+    }
+
+    #[test]
+    fn test_thread_accounting() {
+        let mut accounting = ThreadAccounting::default();
+
+        let accounting = std::thread::spawn(move || {
+            accounting.handle_stream_status(gst::StreamStatusType::Enter);
+
+            let start = std::time::Instant::now();
+            while start.elapsed() < Duration::from_millis(200) {
+                std::hint::black_box(start.elapsed());
+            }
+            assert!(accounting.cpu_time() > Duration::ZERO);
+
+            accounting.handle_stream_status(gst::StreamStatusType::Leave);
+            accounting
+        })
+        .join()
+        .unwrap();
+
+        // Kept after the thread left
+        assert!(accounting.threads.is_empty());
+        assert!(accounting.cpu_time() >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{
+    stream::types::CaptureConfiguration,
+    video::{
+        types::{VideoEncodeType, VideoSourceType},
+        video_source_gst::{RtspTransport, VideoSourceGstType},
+    },
+    video_stream::types::VideoAndStreamInformation,
+};
+
+use super::{
+    frame_rate, overlay, scale, zoom, PipelineGstreamerInterface, PipelineState,
+    PIPELINE_FILTER_NAME, PIPELINE_RTP_TEE_NAME, PIPELINE_VIDEO_TEE_NAME,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use tracing::*;
+
+use gst::prelude::*;
+
+pub const PIPELINE_RTSP_SOURCE_NAME: &str = "RtspSource";
+
+/// Reads a network camera over RTSP. When the camera's server drops, the pipeline fails with a recoverable error
+/// (or stalls, which is caught by the PipelineRunner's watchdog), and the stream is recreated as any other source
+#[derive(Debug)]
+pub struct RtspPipeline {
+    pub state: PipelineState,
+}
+
+impl RtspPipeline {
+    #[instrument(level = "debug")]
+    pub fn try_new(
+        pipeline_id: &uuid::Uuid,
+        video_and_stream_information: &VideoAndStreamInformation,
+    ) -> Result<gst::Pipeline> {
+        let configuration = match &video_and_stream_information
+            .stream_information
+            .configuration
+        {
+            CaptureConfiguration::Video(configuration) => configuration,
+            unsupported => {
+                return Err(anyhow!("{unsupported:?} is not supported as RTSP Pipeline"))
+            }
+        };
+
+        let video_source = match &video_and_stream_information.video_source {
+            VideoSourceType::Gst(source) => source,
+            unsupported => {
+                return Err(anyhow!(
+                    "VideoSourceType {unsupported:?} is not supported as RTSP Pipeline"
+                ))
+            }
+        };
+
+        let rtsp = match &video_source.source {
+            VideoSourceGstType::Rtsp(rtsp) => rtsp,
+            unsupported => {
+                return Err(anyhow!(
+                    "VideoSourceGstType {unsupported:?} is not supported as RTSP Pipeline"
+                ))
+            }
+        };
+
+        let width = configuration.width;
+        let height = configuration.height;
+        let source_name = format!("{PIPELINE_RTSP_SOURCE_NAME}-{pipeline_id}");
+        let filter_name = format!("{PIPELINE_FILTER_NAME}-{pipeline_id}");
+        let video_tee_name = format!("{PIPELINE_VIDEO_TEE_NAME}-{pipeline_id}");
+        let rtp_tee_name = format!("{PIPELINE_RTP_TEE_NAME}-{pipeline_id}");
+        let latency_ms = video_and_stream_information.stream_information.latency_ms();
+        let zoom = zoom::description(pipeline_id, width, height);
+        let overlay = overlay::description(pipeline_id);
+        let output_resolution = video_and_stream_information
+            .stream_information
+            .output_resolution();
+        let scale = scale::description(pipeline_id, width, height, output_resolution);
+        let (output_width, output_height) = scale::output_size(width, height, output_resolution);
+        let max_framerate = video_and_stream_information
+            .stream_information
+            .max_framerate();
+        let rate =
+            frame_rate::description(pipeline_id, &configuration.frame_interval, max_framerate);
+        let output_interval =
+            frame_rate::output_interval(&configuration.frame_interval, max_framerate);
+
+        // Only the video stream is linked, ignoring any audio from the camera. The encoded formats are repackaged as
+        // they come from the camera, so their resolution and framerate can't be changed
+        let description = match &configuration.encode {
+            VideoEncodeType::H264 => {
+                format!(
+                    concat!(
+                        "rtspsrc name={source_name} latency={latency_ms}",
+                        " ! application/x-rtp,media=video,encoding-name=H264",
+                        " ! rtph264depay",
+                        " ! h264parse",
+                        " ! capsfilter name={filter_name} caps=video/x-h264,stream-format=avc,alignment=au",
+                        " ! tee name={video_tee_name} allow-not-linked=true",
+                        " ! rtph264pay aggregate-mode=zero-latency config-interval=10 pt=96",
+                        " ! tee name={rtp_tee_name} allow-not-linked=true"
+                    ),
+                    source_name = source_name,
+                    latency_ms = latency_ms,
+                    filter_name = filter_name,
+                    video_tee_name = video_tee_name,
+                    rtp_tee_name = rtp_tee_name,
+                )
+            }
+            VideoEncodeType::H265 => {
+                format!(
+                    concat!(
+                        "rtspsrc name={source_name} latency={latency_ms}",
+                        " ! application/x-rtp,media=video,encoding-name=H265",
+                        " ! rtph265depay",
+                        " ! h265parse",
+                        " ! capsfilter name={filter_name} caps=video/x-h265,stream-format=byte-stream,alignment=au",
+                        " ! tee name={video_tee_name} allow-not-linked=true",
+                        " ! rtph265pay aggregate-mode=zero-latency config-interval=10 pt=96",
+                        " ! tee name={rtp_tee_name} allow-not-linked=true"
+                    ),
+                    source_name = source_name,
+                    latency_ms = latency_ms,
+                    filter_name = filter_name,
+                    video_tee_name = video_tee_name,
+                    rtp_tee_name = rtp_tee_name,
+                )
+            }
+            VideoEncodeType::Yuyv => {
+                format!(
+                    concat!(
+                        "rtspsrc name={source_name} latency={latency_ms}",
+                        " ! application/x-rtp,media=video",
+                        " ! decodebin",
+                        " ! videoconvert",
+                        " ! videorate",
+                        " ! videoscale",
+                        "{zoom}",
+                        "{overlay}",
+                        "{scale}",
+                        "{rate}",
+                        " ! capsfilter name={filter_name} caps=video/x-raw,format=I420,width={output_width},height={output_height},framerate={interval_denominator}/{interval_numerator}",
+                        " ! tee name={video_tee_name} allow-not-linked=true",
+                        " ! rtpvrawpay pt=96",
+                        " ! tee name={rtp_tee_name} allow-not-linked=true"
+                    ),
+                    source_name = source_name,
+                    latency_ms = latency_ms,
+                    zoom = zoom,
+                    overlay = overlay,
+                    scale = scale,
+                    rate = rate,
+                    output_width = output_width,
+                    output_height = output_height,
+                    interval_denominator = output_interval.denominator,
+                    interval_numerator = output_interval.numerator,
+                    filter_name = filter_name,
+                    video_tee_name = video_tee_name,
+                    rtp_tee_name = rtp_tee_name,
+                )
+            }
+            unsupported => {
+                return Err(anyhow!(
+                    "Encode {unsupported:?} is not supported for RTSP Pipeline"
+                ))
+            }
+        };
+
+        debug!("pipeline_description: {description:#?}");
+
+        let pipeline = gst::parse::launch(&description)?;
+
+        let pipeline = pipeline
+            .downcast::<gst::Pipeline>()
+            .expect("Couldn't downcast pipeline");
+
+        // Set after parsing, so the credentials don't need to be escaped for the description
+        let rtspsrc = pipeline
+            .by_name(&source_name)
+            .context("Failed to find the rtspsrc element")?;
+        rtspsrc.set_property("location", rtsp.url.as_str());
+        if let Some(username) = &rtsp.username {
+            rtspsrc.set_property("user-id", username);
+        }
+        if let Some(password) = &rtsp.password {
+            rtspsrc.set_property("user-pw", password);
+        }
+        match rtsp.transport {
+            RtspTransport::Auto => (),
+            RtspTransport::Tcp => rtspsrc.set_property_from_str("protocols", "tcp"),
+            RtspTransport::Udp => rtspsrc.set_property_from_str("protocols", "udp"),
+        }
+
+        Ok(pipeline)
+    }
+}
+
+impl PipelineGstreamerInterface for RtspPipeline {
+    #[instrument(level = "trace")]
+    fn is_running(&self) -> bool {
+        self.state.pipeline_runner.is_running()
+    }
+}
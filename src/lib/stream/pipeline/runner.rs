@@ -1,14 +1,440 @@
 use anyhow::{anyhow, Context, Result};
 use gst::prelude::*;
+use serde::Serialize;
+use tokio::sync::broadcast;
 use tracing::*;
 
-use crate::stream::gst::utils::wait_for_element_state_async;
+use crate::{
+    stream::{
+        gst::utils::{dot_file_config, write_dot_file},
+        types::PipelineRunnerConfiguration,
+    },
+    video_stream::types::VideoAndStreamInformation,
+};
+
+lazy_static! {
+    static ref EVENTS: broadcast::Sender<PipelineEvent> = broadcast::channel(100).0;
+}
+
+/// A structured, timestamped notification about the lifecycle of a pipeline
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineEvent {
+    pub pipeline_id: uuid::Uuid,
+    /// Unix timestamp, in milliseconds
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub kind: PipelineEventKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PipelineEventKind {
+    /// The runner task started and is waiting for the start command
+    Started,
+    /// The pipeline reached the Playing state
+    Playing,
+    /// The pipeline itself (not one of its children) changed its state
+    StateChanged { old: String, current: String },
+    /// The stream is going to be recreated after the given delay
+    Restarting { attempt: u32, delay_ms: u64 },
+    /// The pipeline received an EndOfStream
+    Eos,
+    /// The pipeline posted an error in its bus
+    Error { message: String },
+    /// The runner task ended, and the pipeline is no longer being watched
+    Stopped { reason: String },
+    /// The stream was added to the manager
+    StreamCreated,
+    /// The stream was removed from the manager
+    StreamRemoved,
+    /// A client started receiving the stream, through the given protocol (like "webrtc" or "rtsp")
+    ClientConnected { client: String, protocol: String },
+    /// A client stopped receiving the stream
+    ClientDisconnected { client: String, protocol: String },
+}
+
+impl PipelineEvent {
+    pub fn new(pipeline_id: uuid::Uuid, kind: PipelineEventKind) -> Self {
+        Self {
+            pipeline_id,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            kind,
+        }
+    }
+}
+
+/// Subscribe to the events of all pipelines
+pub fn subscribe_events() -> broadcast::Receiver<PipelineEvent> {
+    EVENTS.subscribe()
+}
+
+/// Publish an event to all subscribers. Events are silently dropped when there are no subscribers
+pub fn publish_event(pipeline_id: uuid::Uuid, kind: PipelineEventKind) {
+    let event = PipelineEvent::new(pipeline_id, kind);
+    trace!("Publishing pipeline event: {event:?}");
+    let _ = EVENTS.send(event);
+}
+
+/// An error posted in the pipeline bus, with the context needed to act on it
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineError {
+    /// Path of the element that posted the error, like "/GstPipeline:pipeline0/GstV4l2Src:v4l2src0"
+    pub element: Option<String>,
+    pub message: String,
+    /// GStreamer's debug information, usually pointing to the underlying cause
+    pub debug: Option<String>,
+    /// If restarting the pipeline won't fix it
+    pub fatal: bool,
+}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.element {
+            Some(element) => write!(f, "Error from {element}: {}", self.message)?,
+            None => write!(f, "{}", self.message)?,
+        }
+        if let Some(debug) = &self.debug {
+            write!(f, " ({debug})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+impl PipelineError {
+    fn from_message(error: &gst::message::Error, classification: ErrorClassification) -> Self {
+        Self {
+            element: error.src().map(|src| src.path_string().to_string()),
+            message: error.error().to_string(),
+            debug: error.debug().map(|debug| debug.to_string()),
+            fatal: classification == ErrorClassification::Fatal,
+        }
+    }
+}
+
+/// Whether it is worth restarting a pipeline after a given error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClassification {
+    /// Transient errors, the pipeline should be restarted
+    Recoverable,
+    /// Errors that will happen again if the pipeline is restarted
+    Fatal,
+}
+
+/// Default mapping from GStreamer error domains/codes to their classification.
+/// Anything not explicitly known as fatal is considered recoverable.
+pub fn classify_error(error: &gst::glib::Error) -> ErrorClassification {
+    let is_fatal = matches!(
+        error.kind::<gst::CoreError>(),
+        Some(gst::CoreError::MissingPlugin | gst::CoreError::NotImplemented)
+    ) || matches!(
+        error.kind::<gst::ResourceError>(),
+        Some(gst::ResourceError::Busy | gst::ResourceError::NotAuthorized)
+    ) || matches!(
+        error.kind::<gst::StreamError>(),
+        Some(
+            gst::StreamError::CodecNotFound
+                | gst::StreamError::TypeNotFound
+                | gst::StreamError::WrongType
+        )
+    );
+
+    if is_fatal {
+        ErrorClassification::Fatal
+    } else {
+        ErrorClassification::Recoverable
+    }
+}
+
+/// The result of a [`HealthProbe`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthVerdict {
+    Healthy,
+    /// Ends the runner, so the stream gets recreated
+    Restart,
+    /// Ends the runner as a fatal error, so the stream won't be recreated
+    Kill(String),
+}
+
+/// A custom check called by the runner's watchdog on each tick, alongside its position check.
+///
+/// It runs on the watchdog task (and only for pipelines not allowed to block, while not paused), so it must be cheap.
+pub type HealthProbe = Box<dyn Fn(&gst::Pipeline) -> HealthVerdict + Send>;
+
+/// Tunables for the PipelineRunner's watchdog
+#[derive(Debug, Clone)]
+pub struct PipelineRunnerConfig {
+    /// Number of consecutive watchdog ticks with an unchanged position before the pipeline is considered blocked
+    pub max_lost_timestamps: usize,
+    /// Period between each watchdog position check
+    pub poll_interval: std::time::Duration,
+    /// Maximum time the runner waits for the start command before checking if the pipeline is still alive
+    pub bus_poll_timeout: std::time::Duration,
+    /// Delay before the first restart attempt of a pipeline that stopped running
+    pub restart_backoff_initial: std::time::Duration,
+    /// Maximum delay between consecutive restart attempts
+    pub restart_backoff_max: std::time::Duration,
+    /// Minimum time a pipeline needs to be running to reset the restart backoff
+    pub restart_backoff_reset_after: std::time::Duration,
+    /// Maximum time to wait for an EOS to drain through the pipeline when it is being dropped
+    pub eos_timeout: std::time::Duration,
+    /// Maximum time without a watchdog tick before the runner is considered stuck in a GStreamer call
+    pub heartbeat_timeout: std::time::Duration,
+    /// Decides if an error posted in the pipeline bus should stop the pipeline from being restarted
+    pub error_classifier: fn(&gst::glib::Error) -> ErrorClassification,
+}
+
+impl Default for PipelineRunnerConfig {
+    fn default() -> Self {
+        Self {
+            max_lost_timestamps: 30,
+            poll_interval: std::time::Duration::from_millis(100),
+            bus_poll_timeout: std::time::Duration::from_millis(100),
+            restart_backoff_initial: std::time::Duration::from_millis(200),
+            restart_backoff_max: std::time::Duration::from_secs(5),
+            restart_backoff_reset_after: std::time::Duration::from_secs(10),
+            eos_timeout: std::time::Duration::from_secs(3),
+            heartbeat_timeout: std::time::Duration::from_secs(10),
+            error_classifier: classify_error,
+        }
+    }
+}
+
+impl PipelineRunnerConfig {
+    pub fn from_video_and_stream_information(
+        video_and_stream_information: &VideoAndStreamInformation,
+    ) -> Self {
+        video_and_stream_information
+            .stream_information
+            .pipeline_runner()
+            .map(Self::from)
+            .unwrap_or_default()
+    }
+}
+
+impl From<&PipelineRunnerConfiguration> for PipelineRunnerConfig {
+    fn from(configuration: &PipelineRunnerConfiguration) -> Self {
+        let default = Self::default();
+
+        Self {
+            max_lost_timestamps: configuration
+                .max_lost_timestamps
+                .map(|value| value as usize)
+                .unwrap_or(default.max_lost_timestamps),
+            poll_interval: configuration
+                .poll_interval_ms
+                .map(std::time::Duration::from_millis)
+                .unwrap_or(default.poll_interval),
+            bus_poll_timeout: configuration
+                .bus_poll_timeout_ms
+                .map(std::time::Duration::from_millis)
+                .unwrap_or(default.bus_poll_timeout),
+            restart_backoff_initial: configuration
+                .restart_backoff_initial_ms
+                .map(std::time::Duration::from_millis)
+                .unwrap_or(default.restart_backoff_initial),
+            restart_backoff_max: configuration
+                .restart_backoff_max_ms
+                .map(std::time::Duration::from_millis)
+                .unwrap_or(default.restart_backoff_max),
+            restart_backoff_reset_after: configuration
+                .restart_backoff_reset_after_ms
+                .map(std::time::Duration::from_millis)
+                .unwrap_or(default.restart_backoff_reset_after),
+            eos_timeout: configuration
+                .eos_timeout_ms
+                .map(std::time::Duration::from_millis)
+                .unwrap_or(default.eos_timeout),
+            heartbeat_timeout: configuration
+                .heartbeat_timeout_ms
+                .map(std::time::Duration::from_millis)
+                .unwrap_or(default.heartbeat_timeout),
+            ..default
+        }
+    }
+}
+
+/// The range of the allowed [`PipelineRunnerConfiguration`] values
+const MAX_LOST_TIMESTAMPS_RANGE: std::ops::RangeInclusive<u32> = 1..=10000;
+const POLL_INTERVAL_MS_RANGE: std::ops::RangeInclusive<u64> = 10..=10000;
+const BUS_POLL_TIMEOUT_MS_RANGE: std::ops::RangeInclusive<u64> = 10..=10000;
+const RESTART_BACKOFF_INITIAL_MS_RANGE: std::ops::RangeInclusive<u64> = 1..=60000;
+const RESTART_BACKOFF_MAX_MS_RANGE: std::ops::RangeInclusive<u64> = 1..=600000;
+const RESTART_BACKOFF_RESET_AFTER_MS_RANGE: std::ops::RangeInclusive<u64> = 1..=3600000;
+const EOS_TIMEOUT_MS_RANGE: std::ops::RangeInclusive<u64> = 1..=60000;
+const HEARTBEAT_TIMEOUT_MS_RANGE: std::ops::RangeInclusive<u64> = 1..=600000;
+
+/// Rejects values that would make the runner spin, or restart the pipeline on every check
+pub fn validate(configuration: &PipelineRunnerConfiguration) -> Result<()> {
+    if let Some(max_lost_timestamps) = configuration.max_lost_timestamps {
+        if !MAX_LOST_TIMESTAMPS_RANGE.contains(&max_lost_timestamps) {
+            return Err(anyhow!(
+                "Maximum lost timestamps of {max_lost_timestamps} is out of the allowed range ({MAX_LOST_TIMESTAMPS_RANGE:?})"
+            ));
+        }
+    }
+
+    let durations = [
+        (
+            "Poll interval",
+            configuration.poll_interval_ms,
+            POLL_INTERVAL_MS_RANGE,
+        ),
+        (
+            "Bus poll timeout",
+            configuration.bus_poll_timeout_ms,
+            BUS_POLL_TIMEOUT_MS_RANGE,
+        ),
+        (
+            "Initial restart delay",
+            configuration.restart_backoff_initial_ms,
+            RESTART_BACKOFF_INITIAL_MS_RANGE,
+        ),
+        (
+            "Maximum restart delay",
+            configuration.restart_backoff_max_ms,
+            RESTART_BACKOFF_MAX_MS_RANGE,
+        ),
+        (
+            "Restart backoff reset",
+            configuration.restart_backoff_reset_after_ms,
+            RESTART_BACKOFF_RESET_AFTER_MS_RANGE,
+        ),
+        (
+            "EOS timeout",
+            configuration.eos_timeout_ms,
+            EOS_TIMEOUT_MS_RANGE,
+        ),
+        (
+            "Heartbeat timeout",
+            configuration.heartbeat_timeout_ms,
+            HEARTBEAT_TIMEOUT_MS_RANGE,
+        ),
+    ];
+    for (name, value, range) in durations {
+        if let Some(value) = value {
+            if !range.contains(&value) {
+                return Err(anyhow!(
+                    "{name} of {value} ms is out of the allowed range ({range:?} ms)"
+                ));
+            }
+        }
+    }
+
+    // The values left out fall back to their defaults, which also have to fit with the given ones
+    let config = PipelineRunnerConfig::from(configuration);
+
+    if config.restart_backoff_max < config.restart_backoff_initial {
+        return Err(anyhow!(
+            "Maximum restart delay of {:?} is below the initial one of {:?}",
+            config.restart_backoff_max,
+            config.restart_backoff_initial
+        ));
+    }
+
+    if config.heartbeat_timeout <= config.poll_interval {
+        return Err(anyhow!(
+            "Heartbeat timeout of {:?} should be above the poll interval of {:?}",
+            config.heartbeat_timeout,
+            config.poll_interval
+        ));
+    }
+
+    Ok(())
+}
+
+/// Exponential backoff used to space out consecutive pipeline restart attempts.
+///
+/// A restart recreates the pipeline along with its runner, so the backoff is kept by whoever recreates them, like
+/// the stream's watcher, to outlive each runner.
+#[derive(Debug, Clone)]
+pub struct RestartBackoff {
+    initial: std::time::Duration,
+    max: std::time::Duration,
+    reset_after: std::time::Duration,
+    attempts: u32,
+    started_at: Option<std::time::Instant>,
+}
+
+impl RestartBackoff {
+    pub fn new(config: &PipelineRunnerConfig) -> Self {
+        Self {
+            initial: config.restart_backoff_initial,
+            max: config.restart_backoff_max,
+            reset_after: config.restart_backoff_reset_after,
+            attempts: 0,
+            started_at: None,
+        }
+    }
+
+    /// Marks the pipeline as (re)started, so the backoff resets if it keeps running long enough
+    pub fn started(&mut self) {
+        self.started_at = Some(std::time::Instant::now());
+    }
+
+    /// Returns the delay to wait before the next restart attempt, doubling it each call until the maximum is reached.
+    /// It starts over from the initial delay if the pipeline ran for long enough since it last started
+    pub fn next_delay(&mut self) -> std::time::Duration {
+        if self
+            .started_at
+            .take()
+            .is_some_and(|started_at| started_at.elapsed() >= self.reset_after)
+        {
+            self.reset();
+        }
+
+        let delay = self
+            .initial
+            .checked_mul(2u32.saturating_pow(self.attempts))
+            .unwrap_or(self.max)
+            .min(self.max);
+
+        self.attempts = self.attempts.saturating_add(1);
+
+        delay
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+/// Values cached by the runner task, so they can be cheaply read without touching the pipeline
+#[derive(Debug, Default)]
+struct PipelineRunnerStatus {
+    position: Option<gst::ClockTime>,
+    playing_since: Option<std::time::Instant>,
+    /// Updated on each watchdog tick, so it stops if a GStreamer call hangs
+    last_heartbeat: Option<std::time::Instant>,
+    last_error: Option<String>,
+    bus_error: Option<PipelineError>,
+    paused: bool,
+    fatal: bool,
+    /// Frames dropped for not keeping up, by the element reporting them in its QoS messages
+    dropped_frames: std::collections::BTreeMap<String, u64>,
+    /// The streaming threads running the pipeline, to account their CPU time
+    threads: super::resources::ThreadAccounting,
+}
+
+/// Set by the BusWatcher when the pipeline's EOS reaches the bus
+type EosSignal = std::sync::Arc<(std::sync::Mutex<bool>, std::sync::Condvar)>;
 
 #[derive(Debug)]
 pub struct PipelineRunner {
     start: tokio::sync::mpsc::Sender<()>,
     handle: Option<tokio::task::JoinHandle<()>>,
     pipeline_id: uuid::Uuid,
+    pipeline_weak: gst::glib::WeakRef<gst::Pipeline>,
+    eos_received: EosSignal,
+    eos_timeout: std::time::Duration,
+    heartbeat_timeout: std::time::Duration,
+    status: std::sync::Arc<std::sync::Mutex<PipelineRunnerStatus>>,
+    dot_files_enabled: std::sync::Arc<std::sync::atomic::AtomicBool>,
 }
 
 impl Drop for PipelineRunner {
@@ -16,6 +442,8 @@ impl Drop for PipelineRunner {
     fn drop(&mut self) {
         debug!("Dropping PipelineRunner...");
 
+        self.drain_eos();
+
         if let Some(handle) = self.handle.take() {
             if !handle.is_finished() {
                 handle.abort();
@@ -33,30 +461,216 @@ impl Drop for PipelineRunner {
 }
 
 impl PipelineRunner {
-    #[instrument(level = "debug", skip(pipeline))]
+    #[instrument(level = "debug", skip(pipeline, health_probe))]
     pub fn try_new(
         pipeline: &gst::Pipeline,
         pipeline_id: &uuid::Uuid,
         allow_block: bool,
+        config: PipelineRunnerConfig,
+        health_probe: Option<HealthProbe>,
     ) -> Result<Self> {
         let pipeline_weak = pipeline.downgrade();
         let pipeline_id = *pipeline_id;
+        let eos_received = EosSignal::default();
+        let eos_timeout = config.eos_timeout;
+        let heartbeat_timeout = config.heartbeat_timeout;
+        let status: std::sync::Arc<std::sync::Mutex<PipelineRunnerStatus>> = Default::default();
 
         let (start_tx, start_rx) = tokio::sync::mpsc::channel(1);
 
         debug!("Starting PipelineRunner task...");
 
+        let pipeline_weak_cloned = pipeline_weak.clone();
+        let eos_received_cloned = eos_received.clone();
+        let status_cloned = status.clone();
+        let status_cloned_for_error = status.clone();
+        let dot_files_enabled = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(
+            dot_file_config().enabled,
+        ));
+        let dot_files_enabled_cloned = dot_files_enabled.clone();
         Ok(Self {
             start: start_tx,
             handle: Some(tokio::spawn(async move {
                 debug!("PipelineRunner task started!");
-                match Self::runner(pipeline_weak, pipeline_id, start_rx, allow_block).await {
-                    Ok(_) => debug!("PipelineRunner task eneded with no errors"),
-                    Err(error) => warn!("PipelineRunner task ended with error: {error:#?}"),
+                publish_event(pipeline_id, PipelineEventKind::Started);
+                let reason = match Self::runner(
+                    pipeline_weak_cloned,
+                    pipeline_id,
+                    start_rx,
+                    allow_block,
+                    config,
+                    health_probe,
+                    eos_received_cloned,
+                    status_cloned,
+                    dot_files_enabled_cloned,
+                )
+                .await
+                {
+                    Ok(_) => {
+                        debug!("PipelineRunner task eneded with no errors");
+                        "Finished".to_string()
+                    }
+                    Err(error) => {
+                        warn!("PipelineRunner task ended with error: {error:#?}");
+                        let reason = format!("{error:#}");
+                        status_cloned_for_error.lock().unwrap().last_error = Some(reason.clone());
+                        reason
+                    }
                 };
+                publish_event(pipeline_id, PipelineEventKind::Stopped { reason });
             })),
             pipeline_id,
+            pipeline_weak,
+            eos_received,
+            eos_timeout,
+            heartbeat_timeout,
+            status,
+            dot_files_enabled,
+        })
+    }
+
+    /// Enables or disables the dot files dumped by this runner on state changes, errors and EOS
+    pub fn set_dot_files_enabled(&self, enabled: bool) {
+        self.dot_files_enabled
+            .store(enabled, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// The last position queried by the runner's watchdog
+    pub fn position(&self) -> Option<gst::ClockTime> {
+        self.status.lock().unwrap().position
+    }
+
+    /// The reason why the runner task ended, if it ended with an error
+    pub fn last_error(&self) -> Option<String> {
+        self.status.lock().unwrap().last_error.clone()
+    }
+
+    /// The error posted in the pipeline bus that ended the runner, if any
+    pub fn bus_error(&self) -> Option<PipelineError> {
+        self.status.lock().unwrap().bus_error.clone()
+    }
+
+    /// Frames dropped by the pipeline's elements for not keeping up, by element, as reported in their QoS messages
+    pub fn dropped_frames(&self) -> std::collections::BTreeMap<String, u64> {
+        self.status.lock().unwrap().dropped_frames.clone()
+    }
+
+    /// CPU time spent by the streaming threads of the pipeline, see [`super::resources::ThreadAccounting`]
+    pub fn cpu_time(&self) -> std::time::Duration {
+        self.status.lock().unwrap().threads.cpu_time()
+    }
+
+    /// Waits until the pipeline reaches the Playing state after [`PipelineRunner::start`].
+    /// If it fails to get there, the error posted in its bus is returned as a [`PipelineError`] whenever available.
+    /// Pipelines still starting after the timeout are not considered failed, as some sources are slow to start.
+    #[instrument(level = "debug", skip(self), fields(pipeline_id = self.pipeline_id.to_string()))]
+    pub async fn wait_for_start(&self, timeout: std::time::Duration) -> Result<()> {
+        let mut events = subscribe_events();
+
+        let result = tokio::time::timeout(timeout, async {
+            loop {
+                if let Some(error) = self.bus_error() {
+                    return Err(anyhow::Error::new(error));
+                }
+
+                if self.status.lock().unwrap().playing_since.is_some() {
+                    return Ok(());
+                }
+
+                if !self.is_running() {
+                    return Err(anyhow!(self
+                        .last_error()
+                        .unwrap_or_else(|| "PipelineRunner task ended".to_string())));
+                }
+
+                // Any event (or a lag) is just a hint to check the status again
+                if let Err(broadcast::error::RecvError::Closed) = events.recv().await {
+                    return Err(anyhow!("Pipeline events channel closed"));
+                }
+            }
         })
+        .await;
+
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("Pipeline is still not Playing after {timeout:?}, leaving it starting");
+                Ok(())
+            }
+        }
+    }
+
+    /// When the runner's watchdog last ticked, only known after the pipeline started Playing
+    pub fn last_heartbeat(&self) -> Option<std::time::Instant> {
+        self.status.lock().unwrap().last_heartbeat
+    }
+
+    /// If the runner is alive but its watchdog stopped ticking, like when a GStreamer call never returns.
+    /// Such a runner can't be aborted, as it is blocked outside of any await point
+    pub fn is_wedged(&self) -> bool {
+        self.is_running()
+            && self
+                .last_heartbeat()
+                .is_some_and(|last_heartbeat| last_heartbeat.elapsed() > self.heartbeat_timeout)
+    }
+
+    /// For how long the pipeline has been Playing
+    pub fn uptime(&self) -> Option<std::time::Duration> {
+        if !self.is_running() {
+            return None;
+        }
+
+        self.status
+            .lock()
+            .unwrap()
+            .playing_since
+            .map(|playing_since| playing_since.elapsed())
+    }
+
+    /// Sends an EOS event into the pipeline and blocks until it reaches the bus, or until the configured timeout elapses.
+    /// This gives elements like muxers a chance to finalize their outputs before the pipeline is torn down.
+    #[instrument(level = "debug", skip(self), fields(pipeline_id = self.pipeline_id.to_string()))]
+    pub fn drain_eos(&self) {
+        if !self.is_running() {
+            return;
+        }
+
+        let Some(pipeline) = self.pipeline_weak.upgrade() else {
+            return;
+        };
+
+        // Data only flows (and so the EOS) when the pipeline is Playing
+        if pipeline.current_state() != gst::State::Playing {
+            return;
+        }
+
+        let (lock, condvar) = &*self.eos_received;
+
+        if *lock.lock().unwrap() {
+            return;
+        }
+
+        debug!("Draining EOS...");
+
+        if !pipeline.send_event(gst::event::Eos::new()) {
+            warn!("Failed sending EOS event to the Pipeline");
+            return;
+        }
+
+        let (_, wait_result) = condvar
+            .wait_timeout_while(lock.lock().unwrap(), self.eos_timeout, |received| {
+                !*received
+            })
+            .unwrap();
+
+        if wait_result.timed_out() {
+            warn!(
+                "Timed out after {:?} waiting for the EOS to drain through the Pipeline",
+                self.eos_timeout
+            );
+        } else {
+            debug!("EOS drained");
+        }
     }
 
     #[instrument(level = "debug", skip(self), fields(pipeline_id = self.pipeline_id.to_string()))]
@@ -73,6 +687,77 @@ impl PipelineRunner {
         Ok(())
     }
 
+    /// Subscribe to the events of all pipelines. Events from this runner's pipeline are those matching [`PipelineRunner::pipeline_id`]
+    pub fn subscribe_events(&self) -> broadcast::Receiver<PipelineEvent> {
+        subscribe_events()
+    }
+
+    pub fn pipeline_id(&self) -> &uuid::Uuid {
+        &self.pipeline_id
+    }
+
+    /// Pauses the pipeline without tearing it down. The watchdog is disabled until it gets resumed
+    #[instrument(level = "debug", skip(self), fields(pipeline_id = self.pipeline_id.to_string()))]
+    pub fn pause(&self) -> Result<()> {
+        let pipeline = self
+            .pipeline_weak
+            .upgrade()
+            .context("Unable to access the Pipeline from its weak reference")?;
+
+        self.status.lock().unwrap().paused = true;
+
+        if let Err(error) = pipeline.set_state(gst::State::Paused) {
+            self.status.lock().unwrap().paused = false;
+            return Err(anyhow!(
+                "Failed setting Pipeline {} to Paused state. Reason: {error:?}",
+                self.pipeline_id
+            ));
+        }
+
+        debug!("Pipeline paused");
+
+        Ok(())
+    }
+
+    /// Resumes a pipeline previously paused with [`PipelineRunner::pause`]
+    #[instrument(level = "debug", skip(self), fields(pipeline_id = self.pipeline_id.to_string()))]
+    pub fn resume(&self) -> Result<()> {
+        let pipeline = self
+            .pipeline_weak
+            .upgrade()
+            .context("Unable to access the Pipeline from its weak reference")?;
+
+        if let Err(error) = pipeline.set_state(gst::State::Playing) {
+            return Err(anyhow!(
+                "Failed setting Pipeline {} to Playing state. Reason: {error:?}",
+                self.pipeline_id
+            ));
+        }
+
+        self.status.lock().unwrap().paused = false;
+
+        debug!("Pipeline resumed");
+
+        Ok(())
+    }
+
+    /// If the runner ended because of an error classified as fatal, meaning it shouldn't be restarted
+    pub fn is_fatal(&self) -> bool {
+        self.status.lock().unwrap().fatal
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.status.lock().unwrap().paused
+    }
+
+    /// Ends the runner, so the stream gets recreated with its current configuration
+    #[instrument(level = "debug", skip(self))]
+    pub fn request_restart(&self) {
+        if let Some(handle) = &self.handle {
+            handle.abort();
+        }
+    }
+
     #[instrument(level = "debug", skip(self))]
     pub fn is_running(&self) -> bool {
         self.handle
@@ -81,19 +766,33 @@ impl PipelineRunner {
             .unwrap_or(false)
     }
 
-    #[instrument(level = "debug", skip(pipeline_weak, start))]
+    #[instrument(
+        level = "debug",
+        skip(
+            pipeline_weak,
+            start,
+            health_probe,
+            eos_received,
+            status,
+            dot_files_enabled
+        )
+    )]
     async fn runner(
         pipeline_weak: gst::glib::WeakRef<gst::Pipeline>,
         pipeline_id: uuid::Uuid,
         mut start: tokio::sync::mpsc::Receiver<()>,
         allow_block: bool,
+        config: PipelineRunnerConfig,
+        health_probe: Option<HealthProbe>,
+        eos_received: EosSignal,
+        status: std::sync::Arc<std::sync::Mutex<PipelineRunnerStatus>>,
+        dot_files_enabled: std::sync::Arc<std::sync::atomic::AtomicBool>,
     ) -> Result<()> {
         let (finish_tx, mut finish) = tokio::sync::mpsc::channel(1);
-        let pipeline = pipeline_weak
+        // Only the weak reference is kept, so the runner notices when the pipeline is gone
+        let bus = pipeline_weak
             .upgrade()
-            .context("Unable to access the Pipeline from its weak reference")?;
-
-        let bus = pipeline
+            .context("Unable to access the Pipeline from its weak reference")?
             .bus()
             .context("Unable to access the pipeline bus")?;
 
@@ -101,7 +800,18 @@ impl PipelineRunner {
         let pipeline_weak_cloned = pipeline_weak.clone();
         let (bus_tx, bus_rx) = tokio::sync::mpsc::unbounded_channel::<gst::Message>();
         let bus_tx = std::sync::Mutex::new(bus_tx);
+        let status_cloned = status.clone();
         bus.set_sync_handler(move |_, msg| {
+            // Handled here as it is posted from the streaming thread itself, the only way to know which one it is
+            if let gst::MessageView::StreamStatus(stream_status) = msg.view() {
+                let (status_type, _owner) = stream_status.get();
+                status_cloned
+                    .lock()
+                    .unwrap()
+                    .threads
+                    .handle_stream_status(status_type);
+            }
+
             let _ = bus_tx.lock().unwrap().send(msg.to_owned());
             gst::BusSyncReply::Drop
         });
@@ -115,6 +825,10 @@ impl PipelineRunner {
             pipeline_id,
             bus_rx,
             finish_tx,
+            config.error_classifier,
+            eos_received,
+            status.clone(),
+            dot_files_enabled,
         ));
 
         // Wait until start receive the signal
@@ -131,6 +845,9 @@ impl PipelineRunner {
                         .upgrade()
                         .context("Unable to access the Pipeline from its weak reference")?;
 
+                    // Subscribe before changing the state so we don't miss the transition
+                    let mut events = subscribe_events();
+
                     if pipeline.current_state() != gst::State::Playing {
                         if let Err(error) = pipeline.set_state(gst::State::Playing) {
                             error!(
@@ -141,18 +858,25 @@ impl PipelineRunner {
                         }
                     }
 
-                    if let Err(error) = wait_for_element_state_async(
-                        pipeline_weak.clone(),
-                        gst::State::Playing,
-                        100,
-                        5,
+                    if let Err(error) = wait_for_playing(
+                        &pipeline_weak,
+                        &mut events,
+                        std::time::Duration::from_secs(5),
                     ).await {
-
                         return Err(anyhow!("{error:?}"));
                     }
 
+                    status.lock().unwrap().playing_since = Some(std::time::Instant::now());
+                    publish_event(pipeline_id, PipelineEventKind::Playing);
+
                     break;
                 }
+                _ = tokio::time::sleep(config.bus_poll_timeout) => {
+                    // No start command yet, just make sure the pipeline is still alive
+                    if pipeline_weak.upgrade().is_none() {
+                        return Err(anyhow!("Pipeline dropped before being started"));
+                    }
+                }
             };
         }
 
@@ -164,9 +888,10 @@ impl PipelineRunner {
         // this checks for a maximum number of lost before restarting.
         let mut previous_position: Option<gst::ClockTime> = None;
         let mut lost_timestamps: usize = 0;
-        let max_lost_timestamps: usize = 30;
+        let max_lost_timestamps = config.max_lost_timestamps;
 
-        let mut period = tokio::time::interval(tokio::time::Duration::from_millis(100));
+        // Bus messages are handled by the BusWatcher, so this only wakes up for the watchdog
+        let mut period = tokio::time::interval(config.poll_interval);
 
         loop {
             tokio::select! {
@@ -174,35 +899,69 @@ impl PipelineRunner {
                     return Err(anyhow!("{reason:?}"));
                 }
                 _ = period.tick() => {
-                    if !allow_block {
-                        // Restart pipeline if pipeline position do not change,
-                        // occur if usb connection is lost and gst do not detect it
-                        let pipeline = pipeline_weak
-                            .upgrade()
-                            .context("Unable to access the Pipeline from its weak reference")?;
-
-                        if let Some(position) = pipeline.query_position::<gst::ClockTime>() {
-                            previous_position = match previous_position {
-                                Some(current_previous_position) => {
-                                    if current_previous_position.nseconds() != 0
-                                        && current_previous_position == position
-                                    {
-                                        lost_timestamps += 1;
-                                    } else if lost_timestamps > 0 {
-                                        // We are back in track, erase lost timestamps
-                                        warn!("Position normalized, but didn't changed for {lost_timestamps} timestamps");
-                                        lost_timestamps = 0;
-                                    }
-                                    if lost_timestamps == 1 {
-                                        warn!("Position did not change for {lost_timestamps}, silently tracking until {max_lost_timestamps}, then the stream will be recreated");
-                                    } else if lost_timestamps > max_lost_timestamps {
-                                        return Err(anyhow!("Pipeline lost too many timestamps (max. was {max_lost_timestamps})"));
-                                    }
-
-                                    Some(position)
+                    let now = std::time::Instant::now();
+
+                    let pipeline = pipeline_weak
+                        .upgrade()
+                        .context("Unable to access the Pipeline from its weak reference")?;
+
+                    let position = pipeline.query_position::<gst::ClockTime>();
+                    let paused = {
+                        let mut status = status.lock().unwrap();
+                        status.last_heartbeat = Some(now);
+                        status.position = position;
+                        status.paused
+                    };
+
+                    // Pipelines allowed to block have nothing to watch, but still report they are alive
+                    if allow_block {
+                        continue;
+                    }
+
+
+                    // The position legitimately freezes while paused, so there is nothing to watch
+                    if paused {
+                        previous_position = None;
+                        lost_timestamps = 0;
+                        continue;
+                    }
+
+                    if let Some(health_probe) = &health_probe {
+                        match health_probe(&pipeline) {
+                            HealthVerdict::Healthy => (),
+                            HealthVerdict::Restart => {
+                                return Err(anyhow!("Health probe requested a restart"));
+                            }
+                            HealthVerdict::Kill(reason) => {
+                                status.lock().unwrap().fatal = true;
+                                return Err(anyhow!("Health probe killed the pipeline: {reason}"));
+                            }
+                        }
+                    }
+
+                    // Restart pipeline if pipeline position do not change,
+                    // occur if usb connection is lost and gst do not detect it
+                    if let Some(position) = position {
+                        previous_position = match previous_position {
+                            Some(current_previous_position) => {
+                                if current_previous_position.nseconds() != 0
+                                    && current_previous_position == position
+                                {
+                                    lost_timestamps += 1;
+                                } else if lost_timestamps > 0 {
+                                    // We are back in track, erase lost timestamps
+                                    warn!("Position normalized, but didn't changed for {lost_timestamps} timestamps");
+                                    lost_timestamps = 0;
+                                }
+                                if lost_timestamps == 1 {
+                                    warn!("Position did not change for {lost_timestamps}, silently tracking until {max_lost_timestamps}, then the stream will be recreated");
+                                } else if lost_timestamps > max_lost_timestamps {
+                                    return Err(anyhow!("Pipeline lost too many timestamps (max. was {max_lost_timestamps})"));
                                 }
-                                None => Some(position),
+
+                                Some(position)
                             }
+                            None => Some(position),
                         }
                     }
                 }
@@ -211,15 +970,60 @@ impl PipelineRunner {
     }
 }
 
-#[instrument(level = "debug", skip(pipeline_weak, bus_rx, finish_tx))]
+/// Waits until the pipeline reaches the Playing state, waking up only when the BusWatcher reports pipeline events
+#[instrument(level = "debug", skip_all)]
+async fn wait_for_playing(
+    pipeline_weak: &gst::glib::WeakRef<gst::Pipeline>,
+    events: &mut broadcast::Receiver<PipelineEvent>,
+    timeout: std::time::Duration,
+) -> Result<()> {
+    tokio::time::timeout(timeout, async {
+        loop {
+            let pipeline = pipeline_weak
+                .upgrade()
+                .context("Unable to access the Pipeline from its weak reference")?;
+
+            if pipeline.current_state() == gst::State::Playing {
+                return Ok(());
+            }
+
+            // Any event (or a lag) is just a hint to check the state again
+            if let Err(broadcast::error::RecvError::Closed) = events.recv().await {
+                return Err(anyhow!("Pipeline events channel closed"));
+            }
+        }
+    })
+    .await
+    .map_err(|_| {
+        anyhow!("Timed out after {timeout:?} waiting for the Pipeline to reach the Playing state")
+    })?
+}
+
+#[instrument(
+    level = "debug",
+    skip(
+        pipeline_weak,
+        bus_rx,
+        finish_tx,
+        error_classifier,
+        eos_received,
+        status,
+        dot_files_enabled
+    )
+)]
 async fn bus_watcher_task(
     pipeline_weak: gst::glib::WeakRef<gst::Pipeline>,
     pipeline_id: uuid::Uuid,
     mut bus_rx: tokio::sync::mpsc::UnboundedReceiver<gst::Message>,
     finish_tx: tokio::sync::mpsc::Sender<String>,
+    error_classifier: fn(&gst::glib::Error) -> ErrorClassification,
+    eos_received: EosSignal,
+    status: std::sync::Arc<std::sync::Mutex<PipelineRunnerStatus>>,
+    dot_files_enabled: std::sync::Arc<std::sync::atomic::AtomicBool>,
 ) {
     debug!("BusWatcher task started!");
 
+    // Ends once the bus is gone along with its pipeline, dropping the sender
     while let Some(message) = bus_rx.recv().await {
         use gst::MessageView;
 
@@ -229,39 +1033,64 @@ async fn bus_watcher_task(
 
         match message.view() {
             MessageView::Eos(eos) => {
-                pipeline.debug_to_dot_file_with_ts(
-                    gst::DebugGraphDetails::all(),
-                    format!("pipeline-{pipeline_id}-eos"),
-                );
+                if dot_files_enabled.load(std::sync::atomic::Ordering::Relaxed) {
+                    write_dot_file(&pipeline, &pipeline_id, "eos");
+                }
                 let msg = format!("Received EndOfStream: {eos:?}");
                 trace!(msg);
+                publish_event(pipeline_id, PipelineEventKind::Eos);
+                let (lock, condvar) = &*eos_received;
+                *lock.lock().unwrap() = true;
+                condvar.notify_all();
                 let _ = finish_tx.send(msg).await;
                 break;
             }
             MessageView::Error(error) => {
+                let classification = error_classifier(&error.error());
                 let msg = format!(
-                    "Error from {:?}: {} ({:?})",
+                    "{classification:?} error from {:?}: {} ({:?})",
                     error.src().map(|s| s.path_string()),
                     error.error(),
                     error.debug()
                 );
-                pipeline.debug_to_dot_file_with_ts(
-                    gst::DebugGraphDetails::all(),
-                    format!("pipeline-{pipeline_id}-error"),
-                );
+                {
+                    let mut status = status.lock().unwrap();
+                    if classification == ErrorClassification::Fatal {
+                        status.fatal = true;
+                    }
+                    status.bus_error = Some(PipelineError::from_message(error, classification));
+                }
+                if dot_files_enabled.load(std::sync::atomic::Ordering::Relaxed) {
+                    write_dot_file(&pipeline, &pipeline_id, "error");
+                }
                 trace!(msg);
+                publish_event(
+                    pipeline_id,
+                    PipelineEventKind::Error {
+                        message: msg.clone(),
+                    },
+                );
                 let _ = finish_tx.send(msg).await;
                 break;
             }
             MessageView::StateChanged(state) => {
-                pipeline.debug_to_dot_file_with_ts(
-                    gst::DebugGraphDetails::all(),
-                    format!(
-                        "pipeline-{pipeline_id}-{:?}-to-{:?}",
-                        state.old(),
-                        state.current()
-                    ),
-                );
+                if dot_files_enabled.load(std::sync::atomic::Ordering::Relaxed) {
+                    write_dot_file(
+                        &pipeline,
+                        &pipeline_id,
+                        &format!("{:?}-to-{:?}", state.old(), state.current()),
+                    );
+                }
+
+                if state.src() == Some(pipeline.upcast_ref::<gst::Object>()) {
+                    publish_event(
+                        pipeline_id,
+                        PipelineEventKind::StateChanged {
+                            old: format!("{:?}", state.old()),
+                            current: format!("{:?}", state.current()),
+                        },
+                    );
+                }
 
                 trace!(
                     "State changed from {:?}: {:?} to {:?} ({:?})",
@@ -282,9 +1111,334 @@ async fn bus_watcher_task(
                     debug!("New latency: {new_latency:?}");
                 }
             }
+            MessageView::Qos(qos) => {
+                // Only frames are counted, as audio elements report their drops in samples
+                let gst::GenericFormattedValue::Buffers(Some(dropped)) = qos.stats().2 else {
+                    continue;
+                };
+                let element = qos
+                    .src()
+                    .map(|src| element_name(&src.name(), &pipeline_id))
+                    .unwrap_or_default();
+
+                if record_dropped_frames(
+                    &mut status.lock().unwrap().dropped_frames,
+                    element.clone(),
+                    *dropped,
+                ) {
+                    debug!("Element {element:?} dropped frames, {dropped} in total");
+                }
+            }
             other_message => trace!("{other_message:#?}"),
         }
     }
 
     debug!("BusWatcher task ended!");
 }
+
+/// The element name without the pipeline id suffix, like "Filter" for "Filter-<pipeline_id>"
+fn element_name(name: &str, pipeline_id: &uuid::Uuid) -> String {
+    name.strip_suffix(&format!("-{pipeline_id}"))
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// Keeps the total of frames dropped by the element, as QoS messages carry the running total of the element.
+/// Returns if it increased
+fn record_dropped_frames(
+    dropped_frames: &mut std::collections::BTreeMap<String, u64>,
+    element: String,
+    dropped: u64,
+) -> bool {
+    let total = dropped_frames.entry(element).or_default();
+    if dropped <= *total {
+        return false;
+    }
+
+    *total = dropped;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_dropped_frames() {
+        let pipeline_id = uuid::Uuid::new_v4();
+        assert_eq!(
+            element_name(&format!("Encoder-{pipeline_id}"), &pipeline_id),
+            "Encoder"
+        );
+        assert_eq!(element_name("x264enc0", &pipeline_id), "x264enc0");
+
+        let mut dropped_frames = std::collections::BTreeMap::new();
+        assert!(record_dropped_frames(
+            &mut dropped_frames,
+            "x264enc0".to_string(),
+            3
+        ));
+        assert!(record_dropped_frames(
+            &mut dropped_frames,
+            "appsink0".to_string(),
+            1
+        ));
+        // Repeated totals don't count again
+        assert!(!record_dropped_frames(
+            &mut dropped_frames,
+            "x264enc0".to_string(),
+            3
+        ));
+        assert!(record_dropped_frames(
+            &mut dropped_frames,
+            "x264enc0".to_string(),
+            5
+        ));
+
+        assert_eq!(dropped_frames["x264enc0"], 5);
+        assert_eq!(dropped_frames.values().sum::<u64>(), 6);
+    }
+
+    #[test]
+    fn test_classify_error() {
+        let fatal = [
+            gst::glib::Error::new(gst::CoreError::MissingPlugin, "missing plugin"),
+            gst::glib::Error::new(gst::ResourceError::Busy, "device busy"),
+            gst::glib::Error::new(gst::StreamError::CodecNotFound, "no codec"),
+        ];
+        for error in fatal {
+            assert_eq!(
+                classify_error(&error),
+                ErrorClassification::Fatal,
+                "{error:?}"
+            );
+        }
+
+        let recoverable = [
+            gst::glib::Error::new(gst::ResourceError::Read, "read failed"),
+            gst::glib::Error::new(gst::ResourceError::NotFound, "device unplugged"),
+            gst::glib::Error::new(gst::StreamError::Failed, "internal data stream error"),
+        ];
+        for error in recoverable {
+            assert_eq!(
+                classify_error(&error),
+                ErrorClassification::Recoverable,
+                "{error:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_start_reports_bus_error() {
+        gst::init().unwrap();
+
+        let pipeline = gst::parse::launch("filesrc location=/nonexistent/file ! fakesink")
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+        let pipeline_id = uuid::Uuid::new_v4();
+
+        let runner = PipelineRunner::try_new(
+            &pipeline,
+            &pipeline_id,
+            false,
+            PipelineRunnerConfig::default(),
+            None,
+        )
+        .unwrap();
+        runner.start().unwrap();
+
+        let error = runner
+            .wait_for_start(std::time::Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        let error = error.downcast_ref::<PipelineError>().unwrap();
+        assert!(
+            error.element.as_ref().unwrap().contains("filesrc"),
+            "{error}"
+        );
+        assert!(!error.message.is_empty());
+
+        pipeline.set_state(gst::State::Null).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat() {
+        gst::init().unwrap();
+
+        let pipeline = gst::parse::launch("videotestsrc is-live=true ! fakesink")
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+        let pipeline_id = uuid::Uuid::new_v4();
+
+        let runner = PipelineRunner::try_new(
+            &pipeline,
+            &pipeline_id,
+            false,
+            PipelineRunnerConfig::default(),
+            None,
+        )
+        .unwrap();
+        assert!(runner.last_heartbeat().is_none());
+
+        runner.start().unwrap();
+        runner
+            .wait_for_start(std::time::Duration::from_secs(5))
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+        let last_heartbeat = runner.last_heartbeat().unwrap();
+        assert!(last_heartbeat.elapsed() < std::time::Duration::from_millis(500));
+        assert!(!runner.is_wedged());
+
+        pipeline.set_state(gst::State::Null).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        gst::init().unwrap();
+
+        let pipeline = gst::parse::launch("videotestsrc is-live=true ! fakesink")
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+        let pipeline_id = uuid::Uuid::new_v4();
+
+        let config = PipelineRunnerConfig {
+            max_lost_timestamps: 2,
+            poll_interval: std::time::Duration::from_millis(20),
+            ..Default::default()
+        };
+        let poll_interval = config.poll_interval;
+        // Long enough for the watchdog to lose many more timestamps than allowed
+        let frozen_for = 20 * poll_interval;
+
+        let runner = PipelineRunner::try_new(&pipeline, &pipeline_id, false, config, None).unwrap();
+        runner.start().unwrap();
+        runner
+            .wait_for_start(std::time::Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        // The position freezes while paused, which the watchdog ignores
+        runner.pause().unwrap();
+        assert!(runner.is_paused());
+        tokio::time::sleep(frozen_for).await;
+        assert!(runner.is_running(), "{:?}", runner.last_error());
+
+        // Once resumed, the position moves again
+        runner.resume().unwrap();
+        assert!(!runner.is_paused());
+        tokio::time::sleep(frozen_for).await;
+        let position = runner.position().unwrap();
+        tokio::time::sleep(frozen_for).await;
+        assert!(runner.position().unwrap() > position);
+        assert!(runner.is_running(), "{:?}", runner.last_error());
+
+        // And the watchdog is back to restarting the pipeline if it freezes without being paused
+        pipeline.set_state(gst::State::Paused).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while runner.is_running() {
+                tokio::time::sleep(poll_interval).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(runner
+            .last_error()
+            .unwrap()
+            .contains("lost too many timestamps"));
+
+        pipeline.set_state(gst::State::Null).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&PipelineRunnerConfiguration::default()).is_ok());
+        assert!(validate(&PipelineRunnerConfiguration {
+            max_lost_timestamps: Some(300),
+            poll_interval_ms: Some(1000),
+            heartbeat_timeout_ms: Some(30000),
+            ..Default::default()
+        })
+        .is_ok());
+
+        // Busy loops
+        for configuration in [
+            PipelineRunnerConfiguration {
+                poll_interval_ms: Some(0),
+                ..Default::default()
+            },
+            PipelineRunnerConfiguration {
+                bus_poll_timeout_ms: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(validate(&configuration).is_err());
+        }
+
+        // Restarts on every check
+        assert!(validate(&PipelineRunnerConfiguration {
+            max_lost_timestamps: Some(0),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(&PipelineRunnerConfiguration {
+            heartbeat_timeout_ms: Some(50),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(&PipelineRunnerConfiguration {
+            poll_interval_ms: Some(5000),
+            heartbeat_timeout_ms: Some(5000),
+            ..Default::default()
+        })
+        .is_err());
+
+        assert!(validate(&PipelineRunnerConfiguration {
+            restart_backoff_initial_ms: Some(1000),
+            restart_backoff_max_ms: Some(500),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(&PipelineRunnerConfiguration {
+            eos_timeout_ms: Some(0),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_restart_backoff() {
+        let config = PipelineRunnerConfig::default();
+        let mut backoff = RestartBackoff::new(&config);
+
+        let delays = (0..8)
+            .map(|_| backoff.next_delay().as_millis())
+            .collect::<Vec<u128>>();
+        assert_eq!(delays, vec![200, 400, 800, 1600, 3200, 5000, 5000, 5000]);
+        assert_eq!(backoff.attempts(), 8);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay().as_millis(), 200);
+
+        // It should never overflow
+        for _ in 0..100 {
+            assert!(backoff.next_delay() <= config.restart_backoff_max);
+        }
+
+        // Pipelines running for long enough start over
+        let config = PipelineRunnerConfig {
+            restart_backoff_reset_after: std::time::Duration::ZERO,
+            ..PipelineRunnerConfig::default()
+        };
+        let mut backoff = RestartBackoff::new(&config);
+        backoff.started();
+        assert_eq!(backoff.next_delay().as_millis(), 200);
+        assert_eq!(backoff.next_delay().as_millis(), 400);
+        backoff.started();
+        assert_eq!(backoff.next_delay().as_millis(), 200);
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::*;
+
+use crate::stream::types::{OutputResolutionConfiguration, ScalingMode};
+
+use super::PIPELINE_FILTER_NAME;
+
+pub const PIPELINE_SCALE_NAME: &str = "OutputScale";
+pub const PIPELINE_SCALE_FILTER_NAME: &str = "OutputScaleFilter";
+
+/// The largest output resolution accepted (8K)
+const MAX_OUTPUT_WIDTH: u32 = 7680;
+const MAX_OUTPUT_HEIGHT: u32 = 4320;
+
+/// The resolution streamed for the given captured one
+pub fn output_size(
+    width: u32,
+    height: u32,
+    output_resolution: Option<&OutputResolutionConfiguration>,
+) -> (u32, u32) {
+    output_resolution
+        .map(|output_resolution| (output_resolution.width, output_resolution.height))
+        .unwrap_or((width, height))
+}
+
+/// The raw video stage scaling the frames to the output resolution. It is always part of raw video pipelines,
+/// passing the frames through while the output resolution is the captured one, so it can be changed without
+/// rebuilding the pipeline
+pub fn description(
+    pipeline_id: &uuid::Uuid,
+    width: u32,
+    height: u32,
+    output_resolution: Option<&OutputResolutionConfiguration>,
+) -> String {
+    let (width, height) = output_size(width, height, output_resolution);
+    let scaling = output_resolution
+        .map(|output_resolution| output_resolution.scaling)
+        .unwrap_or_default();
+
+    format!(
+        concat!(
+            " ! videoscale name={PIPELINE_SCALE_NAME}-{pipeline_id} add-borders={add_borders}",
+            " ! capsfilter name={PIPELINE_SCALE_FILTER_NAME}-{pipeline_id} caps=video/x-raw,width={width},height={height},pixel-aspect-ratio=1/1",
+        ),
+        PIPELINE_SCALE_NAME = PIPELINE_SCALE_NAME,
+        PIPELINE_SCALE_FILTER_NAME = PIPELINE_SCALE_FILTER_NAME,
+        pipeline_id = pipeline_id,
+        add_borders = scaling == ScalingMode::Letterbox,
+        width = width,
+        height = height,
+    )
+}
+
+#[instrument(level = "debug")]
+pub fn validate(configuration: &OutputResolutionConfiguration) -> Result<()> {
+    if configuration.width == 0 || configuration.height == 0 {
+        return Err(anyhow!("Output resolution can't be empty"));
+    }
+
+    if configuration.width > MAX_OUTPUT_WIDTH || configuration.height > MAX_OUTPUT_HEIGHT {
+        return Err(anyhow!(
+            "Output resolution can't be larger than {MAX_OUTPUT_WIDTH}x{MAX_OUTPUT_HEIGHT}"
+        ));
+    }
+
+    // Raw formats like I420 are subsampled
+    if configuration.width % 2 != 0 || configuration.height % 2 != 0 {
+        return Err(anyhow!("Output resolution should have even dimensions"));
+    }
+
+    Ok(())
+}
+
+/// Scales the captured frames to the resolution streamed
+#[derive(Debug)]
+pub struct OutputScale {
+    videoscale_weak: gst::glib::WeakRef<gst::Element>,
+    capsfilter_weak: gst::glib::WeakRef<gst::Element>,
+    filter_weak: gst::glib::WeakRef<gst::Element>,
+    width: u32,
+    height: u32,
+}
+
+impl OutputScale {
+    /// Finds the scale stage of the pipeline, which only exists for raw video in our pipelines
+    pub fn try_new(
+        pipeline: &gst::Pipeline,
+        pipeline_id: &uuid::Uuid,
+        width: u32,
+        height: u32,
+    ) -> Option<Self> {
+        let videoscale = pipeline.by_name(&format!("{PIPELINE_SCALE_NAME}-{pipeline_id}"))?;
+        let capsfilter =
+            pipeline.by_name(&format!("{PIPELINE_SCALE_FILTER_NAME}-{pipeline_id}"))?;
+        let filter = pipeline.by_name(&format!("{PIPELINE_FILTER_NAME}-{pipeline_id}"))?;
+
+        Some(Self {
+            videoscale_weak: videoscale.downgrade(),
+            capsfilter_weak: capsfilter.downgrade(),
+            filter_weak: filter.downgrade(),
+            width,
+            height,
+        })
+    }
+
+    /// Changes the resolution streamed, going back to the captured one when none is given. The caps are
+    /// renegotiated downstream, so the encoder restarts with the new resolution
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_output_resolution(
+        &self,
+        output_resolution: Option<&OutputResolutionConfiguration>,
+    ) -> Result<()> {
+        if let Some(output_resolution) = output_resolution {
+            validate(output_resolution)?;
+        }
+
+        let element = |weak: &gst::glib::WeakRef<gst::Element>| {
+            weak.upgrade()
+                .ok_or_else(|| anyhow!("The output scale's elements no longer exist"))
+        };
+        let videoscale = element(&self.videoscale_weak)?;
+        let capsfilter = element(&self.capsfilter_weak)?;
+        let filter = element(&self.filter_weak)?;
+
+        let (width, height) = output_size(self.width, self.height, output_resolution);
+        let scaling = output_resolution
+            .map(|output_resolution| output_resolution.scaling)
+            .unwrap_or_default();
+
+        // The stream's caps are changed first, so they accept the new resolution once it is negotiated upstream
+        let mut filter_caps = filter.property::<gst::Caps>("caps");
+        filter_caps
+            .make_mut()
+            .iter_mut()
+            .filter(|structure| structure.has_field("width"))
+            .for_each(|structure| {
+                structure.set("width", width as i32);
+                structure.set("height", height as i32);
+            });
+        filter.set_property("caps", &filter_caps);
+
+        videoscale.set_property("add-borders", scaling == ScalingMode::Letterbox);
+        capsfilter.set_property(
+            "caps",
+            gst::Caps::builder("video/x-raw")
+                .field("width", width as i32)
+                .field("height", height as i32)
+                .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
+                .build(),
+        );
+
+        debug!("Output resolution set to {width}x{height} ({scaling:?})");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let output_resolution = |width, height| OutputResolutionConfiguration {
+            width,
+            height,
+            scaling: ScalingMode::Letterbox,
+        };
+
+        assert!(validate(&output_resolution(1280, 720)).is_ok());
+        assert!(validate(&output_resolution(0, 720)).is_err());
+        assert!(validate(&output_resolution(1281, 720)).is_err());
+        assert!(validate(&output_resolution(15360, 8640)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_output_scale() {
+        gst::init().unwrap();
+
+        let pipeline_id = uuid::Uuid::new_v4();
+        let output_resolution = OutputResolutionConfiguration {
+            width: 160,
+            height: 120,
+            scaling: ScalingMode::Stretch,
+        };
+        let description = format!(
+            concat!(
+                "videotestsrc is-live=true",
+                " ! video/x-raw,format=I420,width=320,height=240",
+                "{scale}",
+                " ! capsfilter name={PIPELINE_FILTER_NAME}-{pipeline_id} caps=video/x-raw,format=I420,width=160,height=120",
+                " ! fakesink name=Sink",
+            ),
+            scale = description(&pipeline_id, 320, 240, Some(&output_resolution)),
+            PIPELINE_FILTER_NAME = PIPELINE_FILTER_NAME,
+            pipeline_id = pipeline_id,
+        );
+        let pipeline = gst::parse::launch(&description)
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+        let sink_pad = pipeline
+            .by_name("Sink")
+            .unwrap()
+            .static_pad("sink")
+            .unwrap();
+
+        let negotiated_width = |pad: &gst::Pad| {
+            pad.current_caps()
+                .and_then(|caps| caps.structure(0)?.get::<i32>("width").ok())
+        };
+        let wait_for_width = |width: i32| {
+            let sink_pad = sink_pad.clone();
+            async move {
+                tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                    while negotiated_width(&sink_pad) != Some(width) {
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    }
+                })
+                .await
+                .unwrap_or_else(|_| panic!("The output wasn't renegotiated to {width}"))
+            }
+        };
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+        wait_for_width(160).await;
+
+        // Back to the captured resolution, without rebuilding the pipeline
+        let scale = OutputScale::try_new(&pipeline, &pipeline_id, 320, 240).unwrap();
+        scale.set_output_resolution(None).unwrap();
+        wait_for_width(320).await;
+
+        pipeline.set_state(gst::State::Null).unwrap();
+    }
+}
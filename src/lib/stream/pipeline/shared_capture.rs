@@ -0,0 +1,484 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use anyhow::{anyhow, Context, Result};
+use gst::prelude::*;
+use lazy_static::lazy_static;
+use tracing::*;
+
+use crate::{
+    stream::{
+        gst::encoders::{h264_caps_fields, select_encoder, validate_h264_profile_for_encoder},
+        types::VideoCaptureConfiguration,
+    },
+    video::types::VideoEncodeType,
+    video_stream::types::VideoAndStreamInformation,
+};
+
+use super::{
+    frame_rate, overlay, queues, scale, zoom, PIPELINE_FILTER_NAME, PIPELINE_RTP_TEE_NAME,
+    PIPELINE_VIDEO_TEE_NAME,
+};
+
+pub const PIPELINE_SHARED_SOURCE_NAME: &str = "SharedSource";
+
+lazy_static! {
+    /// The captures opened, by device
+    static ref CAPTURES: Mutex<HashMap<String, Weak<SharedCapture>>> = Default::default();
+}
+
+/// A device opened once, in a single format, feeding the pipelines of every stream sharing it
+#[derive(Debug)]
+pub struct SharedCapture {
+    device: String,
+    configuration: VideoCaptureConfiguration,
+    caps: gst::Caps,
+    pipeline: gst::Pipeline,
+    consumers: Arc<Mutex<HashMap<uuid::Uuid, gst_app::AppSrc>>>,
+    failed: Arc<AtomicBool>,
+}
+
+/// The subscription of a stream's pipeline to the capture of its device, which is closed once no stream uses it
+#[derive(Debug)]
+pub struct SharedCaptureConsumer {
+    pipeline_id: uuid::Uuid,
+    capture: Arc<SharedCapture>,
+}
+
+impl Drop for SharedCaptureConsumer {
+    fn drop(&mut self) {
+        self.capture
+            .consumers
+            .lock()
+            .unwrap()
+            .remove(&self.pipeline_id);
+    }
+}
+
+impl SharedCaptureConsumer {
+    /// Feeds the shared source of the given pipeline from the capture of the given device, opening the device if
+    /// no other stream did
+    #[instrument(level = "debug", skip(pipeline))]
+    pub fn try_new(
+        pipeline: &gst::Pipeline,
+        pipeline_id: &uuid::Uuid,
+        device: &str,
+        configuration: &VideoCaptureConfiguration,
+    ) -> Result<Self> {
+        let appsrc = pipeline
+            .by_name(&format!("{PIPELINE_SHARED_SOURCE_NAME}-{pipeline_id}"))
+            .context("Pipeline without a shared source")?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("Shared source is not an AppSrc"))?;
+
+        let capture = SharedCapture::get_or_try_new(device, configuration)?;
+        appsrc.set_caps(Some(&capture.caps));
+        capture
+            .consumers
+            .lock()
+            .unwrap()
+            .insert(*pipeline_id, appsrc);
+
+        info!("Pipeline {pipeline_id} is sharing the capture of device {device:?}");
+
+        Ok(Self {
+            pipeline_id: *pipeline_id,
+            capture,
+        })
+    }
+}
+
+impl Drop for SharedCapture {
+    fn drop(&mut self) {
+        if let Err(error) = self.pipeline.set_state(gst::State::Null) {
+            warn!(
+                "Failed stopping the shared capture of device {:?}: {error:?}",
+                self.device
+            );
+        }
+
+        info!("Shared capture of device {:?} closed", self.device);
+    }
+}
+
+impl SharedCapture {
+    fn get_or_try_new(
+        device: &str,
+        configuration: &VideoCaptureConfiguration,
+    ) -> Result<Arc<Self>> {
+        let mut captures = CAPTURES.lock().unwrap();
+        captures.retain(|_, capture| capture.strong_count() > 0);
+
+        // A failed capture is left to its consumers, which will be restarted and open a new one
+        if let Some(capture) = captures
+            .get(device)
+            .and_then(Weak::upgrade)
+            .filter(|capture| !capture.failed.load(Ordering::Relaxed))
+        {
+            if capture.configuration != *configuration {
+                return Err(anyhow!(
+                    "Device {device:?} is already captured as {:?}, every stream sharing it should use the same capture",
+                    capture.configuration
+                ));
+            }
+
+            return Ok(capture);
+        }
+
+        let capture = Arc::new(Self::try_new(device, configuration)?);
+        captures.insert(device.to_string(), Arc::downgrade(&capture));
+
+        Ok(capture)
+    }
+
+    #[instrument(level = "debug")]
+    fn try_new(device: &str, configuration: &VideoCaptureConfiguration) -> Result<Self> {
+        let caps = caps(configuration)?;
+
+        let description = format!(
+            concat!(
+                "v4l2src device={device} do-timestamp=true",
+                " ! capsfilter name=SharedFilter",
+                " ! appsink name=SharedSink sync=false max-buffers=1 drop=true",
+            ),
+            device = device,
+        );
+
+        debug!("Shared capture description: {description:#?}");
+
+        let pipeline = gst::parse::launch(&description)?
+            .downcast::<gst::Pipeline>()
+            .expect("Couldn't downcast pipeline");
+
+        pipeline
+            .by_name("SharedFilter")
+            .context("Shared capture without its capsfilter")?
+            .set_property("caps", &caps);
+
+        let appsink = pipeline
+            .by_name("SharedSink")
+            .context("Shared capture without its AppSink")?
+            .downcast::<gst_app::AppSink>()
+            .map_err(|_| anyhow!("Shared capture sink is not an AppSink"))?;
+
+        let consumers: Arc<Mutex<HashMap<uuid::Uuid, gst_app::AppSrc>>> = Default::default();
+        let consumers_cloned = consumers.clone();
+        appsink.set_callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |appsink| {
+                    let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    let Some(buffer) = sample.buffer() else {
+                        return Ok(gst::FlowSuccess::Ok);
+                    };
+
+                    for appsrc in consumers_cloned.lock().unwrap().values() {
+                        // A slow stream drops frames instead of holding the others back
+                        if appsrc.current_level_bytes() >= appsrc.max_bytes() {
+                            continue;
+                        }
+
+                        // Each stream timestamps the frames with its own clock
+                        let mut buffer = buffer.copy();
+                        {
+                            let buffer = buffer.make_mut();
+                            buffer.set_pts(gst::ClockTime::NONE);
+                            buffer.set_dts(gst::ClockTime::NONE);
+                        }
+
+                        let _ = appsrc.push_buffer(buffer);
+                    }
+
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .build(),
+        );
+
+        // The consumers notice the frames stopped and restart, so here the device only needs to be released
+        let failed = Arc::new(AtomicBool::new(false));
+        let failed_cloned = failed.clone();
+        let pipeline_weak = pipeline.downgrade();
+        let device_cloned = device.to_string();
+        pipeline
+            .bus()
+            .context("Shared capture without a bus")?
+            .set_sync_handler(move |_bus, message| {
+                if let gst::MessageView::Error(error) = message.view() {
+                    error!(
+                        "Shared capture of device {device_cloned:?} failed: {:?}",
+                        error.error()
+                    );
+                    failed_cloned.store(true, Ordering::Relaxed);
+
+                    let pipeline_weak = pipeline_weak.clone();
+                    std::thread::spawn(move || {
+                        if let Some(pipeline) = pipeline_weak.upgrade() {
+                            let _ = pipeline.set_state(gst::State::Null);
+                        }
+                    });
+                }
+
+                gst::BusSyncReply::Drop
+            });
+
+        pipeline.set_state(gst::State::Playing)?;
+
+        info!("Shared capture of device {device:?} opened as {configuration:?}");
+
+        Ok(Self {
+            device: device.to_string(),
+            configuration: configuration.clone(),
+            caps,
+            pipeline,
+            consumers,
+            failed,
+        })
+    }
+}
+
+/// The caps of the given capture configuration, as delivered by the device
+pub fn caps(configuration: &VideoCaptureConfiguration) -> Result<gst::Caps> {
+    let builder = match &configuration.encode {
+        VideoEncodeType::H264 => gst::Caps::builder("video/x-h264"),
+        VideoEncodeType::H265 => gst::Caps::builder("video/x-h265"),
+        VideoEncodeType::Mjpg => gst::Caps::builder("image/jpeg"),
+        VideoEncodeType::Yuyv => gst::Caps::builder("video/x-raw").field("format", "YUY2"),
+        unsupported => {
+            return Err(anyhow!(
+                "Encode {unsupported:?} is not supported for a shared capture"
+            ))
+        }
+    };
+
+    Ok(builder
+        .field("width", configuration.width as i32)
+        .field("height", configuration.height as i32)
+        .field(
+            "framerate",
+            gst::Fraction::new(
+                configuration.frame_interval.denominator as i32,
+                configuration.frame_interval.numerator as i32,
+            ),
+        )
+        .build())
+}
+
+/// The source of a pipeline fed by a shared capture
+pub fn source_description(pipeline_id: &uuid::Uuid) -> String {
+    format!(
+        "appsrc name={PIPELINE_SHARED_SOURCE_NAME}-{pipeline_id} is-live=true do-timestamp=true format=time"
+    )
+}
+
+/// The pipeline of a stream whose encoding differs from its shared capture: the frames are decoded, scaled to the
+/// stream's resolution and framerate, and encoded again
+#[instrument(level = "debug", skip(video_and_stream_information))]
+pub fn transcoding_description(
+    pipeline_id: &uuid::Uuid,
+    video_and_stream_information: &VideoAndStreamInformation,
+    configuration: &VideoCaptureConfiguration,
+) -> Result<String> {
+    let source = format!(
+        "{}{}",
+        source_description(pipeline_id),
+        queues::description(
+            pipeline_id,
+            queues::PIPELINE_SOURCE_QUEUE_NAME,
+            video_and_stream_information.stream_information.queues(),
+        )
+    );
+    let encoder_queue = queues::description(
+        pipeline_id,
+        queues::PIPELINE_ENCODER_QUEUE_NAME,
+        video_and_stream_information.stream_information.queues(),
+    );
+    let filter_name = format!("{PIPELINE_FILTER_NAME}-{pipeline_id}");
+    let video_tee_name = format!("{PIPELINE_VIDEO_TEE_NAME}-{pipeline_id}");
+    let rtp_tee_name = format!("{PIPELINE_RTP_TEE_NAME}-{pipeline_id}");
+    let width = configuration.width;
+    let height = configuration.height;
+    let interval_numerator = configuration.frame_interval.numerator;
+    let interval_denominator = configuration.frame_interval.denominator;
+    let zoom = zoom::description(pipeline_id, width, height);
+    let overlay = overlay::description(pipeline_id);
+    let output_resolution = video_and_stream_information
+        .stream_information
+        .output_resolution();
+    let scale = scale::description(pipeline_id, width, height, output_resolution);
+    let (output_width, output_height) = scale::output_size(width, height, output_resolution);
+    let max_framerate = video_and_stream_information
+        .stream_information
+        .max_framerate();
+    let rate = frame_rate::description(pipeline_id, &configuration.frame_interval, max_framerate);
+    let output_interval = frame_rate::output_interval(&configuration.frame_interval, max_framerate);
+
+    let decoded = format!(
+        concat!(
+            "{source}",
+            " ! decodebin",
+            " ! videoconvert",
+            " ! videoscale",
+            " ! videorate",
+            " ! video/x-raw,format=I420,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+            "{zoom}",
+            "{overlay}",
+            "{scale}",
+            "{rate}",
+        ),
+        source = source,
+        width = width,
+        height = height,
+        interval_denominator = interval_denominator,
+        interval_numerator = interval_numerator,
+        zoom = zoom,
+        overlay = overlay,
+        scale = scale,
+        rate = rate,
+    );
+
+    let description = match &configuration.encode {
+        VideoEncodeType::H264 => {
+            let encoder = select_encoder(&configuration.encode)?;
+            let h264_profile = video_and_stream_information
+                .stream_information
+                .h264_profile();
+            if let Some(h264_profile) = h264_profile {
+                validate_h264_profile_for_encoder(h264_profile, encoder.name)?;
+            }
+
+            format!(
+                concat!(
+                    "{decoded}",
+                    " ! videoconvert",
+                    " ! {encoder}{encoder_queue}",
+                    " ! h264parse",
+                    " ! capsfilter name={filter_name} caps=video/x-h264,{profile},stream-format=avc,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                    " ! tee name={video_tee_name} allow-not-linked=true",
+                    " ! rtph264pay aggregate-mode=zero-latency config-interval=10 pt=96",
+                    " ! tee name={rtp_tee_name} allow-not-linked=true"
+                ),
+                decoded = decoded,
+                encoder = encoder.description,
+                profile = h264_caps_fields(h264_profile),
+                width = output_width,
+                height = output_height,
+                interval_denominator = output_interval.denominator,
+                interval_numerator = output_interval.numerator,
+                filter_name = filter_name,
+                video_tee_name = video_tee_name,
+                rtp_tee_name = rtp_tee_name,
+                encoder_queue = encoder_queue,
+            )
+        }
+        VideoEncodeType::H265 => {
+            format!(
+                concat!(
+                    "{decoded}",
+                    " ! videoconvert",
+                    " ! {encoder}{encoder_queue}",
+                    " ! h265parse",
+                    " ! capsfilter name={filter_name} caps=video/x-h265,stream-format=byte-stream,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                    " ! tee name={video_tee_name} allow-not-linked=true",
+                    " ! rtph265pay aggregate-mode=zero-latency config-interval=10 pt=96",
+                    " ! tee name={rtp_tee_name} allow-not-linked=true"
+                ),
+                decoded = decoded,
+                encoder = select_encoder(&configuration.encode)?.description,
+                width = output_width,
+                height = output_height,
+                interval_denominator = output_interval.denominator,
+                interval_numerator = output_interval.numerator,
+                filter_name = filter_name,
+                video_tee_name = video_tee_name,
+                rtp_tee_name = rtp_tee_name,
+                encoder_queue = encoder_queue,
+            )
+        }
+        VideoEncodeType::Yuyv => {
+            format!(
+                concat!(
+                    "{decoded}",
+                    " ! capsfilter name={filter_name} caps=video/x-raw,format=I420,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                    " ! tee name={video_tee_name} allow-not-linked=true",
+                    " ! rtpvrawpay pt=96",
+                    " ! tee name={rtp_tee_name} allow-not-linked=true"
+                ),
+                decoded = decoded,
+                width = output_width,
+                height = output_height,
+                interval_denominator = output_interval.denominator,
+                interval_numerator = output_interval.numerator,
+                filter_name = filter_name,
+                video_tee_name = video_tee_name,
+                rtp_tee_name = rtp_tee_name,
+            )
+        }
+        VideoEncodeType::Mjpg => {
+            format!(
+                concat!(
+                    "{decoded}",
+                    " ! jpegenc quality=85 idct-method=1{encoder_queue}",
+                    " ! capsfilter name={filter_name} caps=image/jpeg,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                    " ! tee name={video_tee_name} allow-not-linked=true",
+                    " ! rtpjpegpay pt=96",
+                    " ! tee name={rtp_tee_name} allow-not-linked=true"
+                ),
+                decoded = decoded,
+                width = output_width,
+                height = output_height,
+                interval_denominator = output_interval.denominator,
+                interval_numerator = output_interval.numerator,
+                filter_name = filter_name,
+                video_tee_name = video_tee_name,
+                rtp_tee_name = rtp_tee_name,
+                encoder_queue = encoder_queue,
+            )
+        }
+        unsupported => {
+            return Err(anyhow!(
+                "Encode {unsupported:?} is not supported for a stream sharing its capture"
+            ))
+        }
+    };
+
+    Ok(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    use crate::video::types::FrameInterval;
+
+    #[test]
+    fn test_caps() {
+        gst::init().unwrap();
+
+        let configuration = |encode| VideoCaptureConfiguration {
+            encode,
+            height: 720,
+            width: 1280,
+            frame_interval: FrameInterval {
+                numerator: 1,
+                denominator: 30,
+            },
+        };
+
+        assert_eq!(
+            caps(&configuration(VideoEncodeType::Yuyv)).unwrap(),
+            gst::Caps::from_str("video/x-raw,format=YUY2,width=1280,height=720,framerate=30/1")
+                .unwrap()
+        );
+        assert_eq!(
+            caps(&configuration(VideoEncodeType::Mjpg)).unwrap(),
+            gst::Caps::from_str("image/jpeg,width=1280,height=720,framerate=30/1").unwrap()
+        );
+        assert!(caps(&configuration(VideoEncodeType::Unknown("RGB".into()))).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Context, Result};
+use gst::prelude::*;
+use tracing::*;
+
+use crate::{stream::gst::utils::request_keyframe, video::types::VideoEncodeType};
+
+/// Grabs a single JPEG frame from a running pipeline by temporarily linking a new branch to its video Tee.
+/// This reuses the running pipeline, so exclusive-access devices don't need to be opened twice.
+#[instrument(level = "debug", skip(pipeline, video_tee))]
+pub async fn take_jpeg_snapshot(
+    pipeline: &gst::Pipeline,
+    video_tee: &gst::Element,
+    pipeline_id: &uuid::Uuid,
+    encoding: &VideoEncodeType,
+    timeout: std::time::Duration,
+) -> Result<Vec<u8>> {
+    let snapshot_id = uuid::Uuid::new_v4();
+
+    let queue = gst::ElementFactory::make("queue")
+        .property_from_str("leaky", "downstream") // Throw away any data
+        .property("silent", true)
+        .property("max-size-buffers", 1u32)
+        .build()?;
+
+    // Depending of the sources' format we need different elements to get a JPEG out of it
+    let mut elements = vec![queue];
+    match encoding {
+        VideoEncodeType::Mjpg => {
+            // Already JPEG, just passthrough one frame
+        }
+        VideoEncodeType::H264 | VideoEncodeType::H265 => {
+            // For h264 and h265, we need to filter-out unwanted non-key frames here, before decoding it.
+            elements.push(
+                gst::ElementFactory::make("identity")
+                    .property("drop-buffer-flags", gst::BufferFlags::DELTA_UNIT)
+                    .property("sync", false)
+                    .build()?,
+            );
+            let decoder = match encoding {
+                VideoEncodeType::H265 => "avdec_h265",
+                _ => "avdec_h264",
+            };
+            elements.push(gst::ElementFactory::make(decoder).build()?);
+            elements.push(gst::ElementFactory::make("videoconvert").build()?);
+            elements.push(gst::ElementFactory::make("jpegenc").build()?);
+        }
+        VideoEncodeType::Rgb | VideoEncodeType::Yuyv => {
+            elements.push(gst::ElementFactory::make("videoconvert").build()?);
+            elements.push(gst::ElementFactory::make("jpegenc").build()?);
+        }
+        _ => {
+            return Err(anyhow!(
+                "Unsupported video encoding for snapshots: {encoding:?}. The supported are: H264, H265, MJPG, RGB and YUYV"
+            ))
+        }
+    }
+
+    let appsink = gst_app::AppSink::builder()
+        .name(format!("SnapshotAppSink-{pipeline_id}-{snapshot_id}"))
+        .sync(false)
+        .max_buffers(1u32)
+        .drop(true)
+        .caps(&gst::Caps::builder("image/jpeg").build())
+        .build();
+    elements.push(appsink.clone().upcast());
+
+    let elements_refs = elements.iter().collect::<Vec<&gst::Element>>();
+    let elements_refs = elements_refs.as_slice();
+
+    pipeline
+        .add_many(elements_refs)
+        .context("Failed adding snapshot elements to the Pipeline")?;
+
+    if let Err(link_err) = gst::Element::link_many(elements_refs) {
+        remove_elements(pipeline, elements_refs);
+        return Err(anyhow!("Failed linking snapshot elements: {link_err:?}"));
+    }
+
+    for element in elements_refs {
+        if let Err(error) = element.sync_state_with_parent() {
+            remove_elements(pipeline, elements_refs);
+            return Err(anyhow!(
+                "Failed synchronizing snapshot element state: {error:?}"
+            ));
+        }
+    }
+
+    let Some(tee_src_pad) = video_tee.request_pad_simple("src_%u") else {
+        remove_elements(pipeline, elements_refs);
+        return Err(anyhow!("Failed requesting a src pad from the video Tee"));
+    };
+
+    let queue_sink_pad = elements[0]
+        .static_pad("sink")
+        .expect("No sink pad found on Queue");
+    if let Err(link_err) = tee_src_pad.link(&queue_sink_pad) {
+        video_tee.release_request_pad(&tee_src_pad);
+        remove_elements(pipeline, elements_refs);
+        return Err(anyhow!(
+            "Failed to link Tee's src pad with Queue's sink pad: {link_err:?}"
+        ));
+    }
+
+    // Only keyframes are decoded, so ask for one instead of waiting for the next GOP, which can take longer than
+    // the timeout
+    if matches!(encoding, VideoEncodeType::H264 | VideoEncodeType::H265)
+        && !request_keyframe(&elements[0])
+    {
+        debug!("No upstream element handled the keyframe request of the snapshot");
+    }
+
+    // Pulling is blocking, so do it outside of the async runtime's threads
+    let appsink_cloned = appsink.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        appsink_cloned
+            .try_pull_sample(gst::ClockTime::from_nseconds(timeout.as_nanos() as u64))
+            .context(format!("Timed out after {timeout:?} waiting for a frame"))
+            .and_then(|sample| {
+                let buffer = sample
+                    .buffer()
+                    .context("Failed to get buffer from appsink")?;
+                let map = buffer
+                    .map_readable()
+                    .map_err(|_| anyhow!("Failed to map buffer readable"))?;
+
+                Ok(map.as_slice().to_vec())
+            })
+    })
+    .await
+    .map_err(|error| anyhow!("Snapshot task failed: {error:?}"))
+    .and_then(|result| result);
+
+    // Remove the branch. Block data flow first, to prevent any data from holding the elements alive
+    let _ = tee_src_pad.add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+        gst::PadProbeReturn::Ok
+    });
+    if let Err(unlink_err) = tee_src_pad.unlink(&queue_sink_pad) {
+        warn!("Failed unlinking snapshot's Queue element from Tee's src pad: {unlink_err:?}");
+    }
+    video_tee.release_request_pad(&tee_src_pad);
+    remove_elements(pipeline, elements_refs);
+
+    result
+}
+
+fn remove_elements(pipeline: &gst::Pipeline, elements: &[&gst::Element]) {
+    for element in elements {
+        if let Err(state_err) = element.set_state(gst::State::Null) {
+            warn!("Failed to set snapshot element's state to NULL: {state_err:#?}");
+        }
+    }
+
+    if let Err(remove_err) = pipeline.remove_many(elements) {
+        warn!("Failed removing snapshot elements from Pipeline: {remove_err:?}");
+    }
+}
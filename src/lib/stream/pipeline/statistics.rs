@@ -0,0 +1,138 @@
+use std::sync::Mutex;
+
+use gst::prelude::*;
+
+/// Period in which the rates are measured
+const RATE_WINDOW: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Data flow statistics from a pipeline, measured by a pad probe
+#[derive(Debug, Default)]
+pub struct PipelineStatistics {
+    inner: Mutex<PipelineStatisticsInner>,
+}
+
+#[derive(Debug, Default)]
+struct PipelineStatisticsInner {
+    total_bytes: u64,
+    total_buffers: u64,
+    window_start: Option<std::time::Instant>,
+    window_bytes: u64,
+    window_buffers: u64,
+    /// In bits per second
+    bitrate: f64,
+    /// In buffers (frames) per second
+    framerate: f64,
+}
+
+impl PipelineStatistics {
+    /// Counts every buffer going through the given pad
+    pub fn attach(self: &std::sync::Arc<Self>, pad: &gst::Pad) -> Option<gst::PadProbeId> {
+        let statistics = self.clone();
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+            move |_pad, info| {
+                match &info.data {
+                    Some(gst::PadProbeData::Buffer(buffer)) => statistics.record(buffer.size()),
+                    Some(gst::PadProbeData::BufferList(list)) => list
+                        .iter()
+                        .for_each(|buffer| statistics.record(buffer.size())),
+                    _ => (),
+                }
+
+                gst::PadProbeReturn::Ok
+            },
+        )
+    }
+
+    pub fn record(&self, bytes: usize) {
+        self.inner
+            .lock()
+            .unwrap()
+            .record(bytes, std::time::Instant::now());
+    }
+
+    /// The last measured bitrate, in bits per second, decaying to 0 when the buffers stop flowing
+    pub fn bitrate(&self) -> f64 {
+        self.inner
+            .lock()
+            .unwrap()
+            .rates(std::time::Instant::now())
+            .0
+    }
+
+    /// The last measured framerate, in frames per second, decaying to 0 when the buffers stop flowing
+    pub fn framerate(&self) -> f64 {
+        self.inner
+            .lock()
+            .unwrap()
+            .rates(std::time::Instant::now())
+            .1
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.inner.lock().unwrap().total_bytes
+    }
+
+    pub fn total_buffers(&self) -> u64 {
+        self.inner.lock().unwrap().total_buffers
+    }
+}
+
+impl PipelineStatisticsInner {
+    fn record(&mut self, bytes: usize, now: std::time::Instant) {
+        self.total_bytes += bytes as u64;
+        self.total_buffers += 1;
+        self.window_bytes += bytes as u64;
+        self.window_buffers += 1;
+
+        let window_start = *self.window_start.get_or_insert(now);
+        let elapsed = now.duration_since(window_start);
+        if elapsed >= RATE_WINDOW {
+            (self.bitrate, self.framerate) = self.window_rates(elapsed);
+            self.window_start = Some(now);
+            self.window_bytes = 0;
+            self.window_buffers = 0;
+        }
+    }
+
+    /// The bitrate and framerate, measured over the current window instead once it lasted longer than a window
+    /// without a buffer closing it, so a stalled source decays to 0
+    fn rates(&self, now: std::time::Instant) -> (f64, f64) {
+        match self.window_start {
+            Some(window_start) if now.duration_since(window_start) >= RATE_WINDOW => {
+                self.window_rates(now.duration_since(window_start))
+            }
+            _ => (self.bitrate, self.framerate),
+        }
+    }
+
+    fn window_rates(&self, elapsed: std::time::Duration) -> (f64, f64) {
+        (
+            (self.window_bytes * 8) as f64 / elapsed.as_secs_f64(),
+            self.window_buffers as f64 / elapsed.as_secs_f64(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_decay_when_stalled() {
+        let start = std::time::Instant::now();
+        let mut inner = PipelineStatisticsInner::default();
+
+        for frame in 0..=30 {
+            inner.record(1000, start + RATE_WINDOW * frame / 30);
+        }
+        assert_eq!(inner.rates(start + RATE_WINDOW), (248000.0, 31.0));
+
+        // Within the window, the last measure holds
+        assert_eq!(inner.rates(start + RATE_WINDOW * 3 / 2), (248000.0, 31.0));
+
+        // Once it passed without buffers, the rates fall to 0
+        assert_eq!(inner.rates(start + RATE_WINDOW * 2), (0.0, 0.0));
+        assert_eq!(inner.rates(start + RATE_WINDOW * 10), (0.0, 0.0));
+    }
+}
@@ -5,8 +5,8 @@ use crate::{
 };
 
 use super::{
-    PipelineGstreamerInterface, PipelineState, PIPELINE_FILTER_NAME, PIPELINE_RTP_TEE_NAME,
-    PIPELINE_VIDEO_TEE_NAME,
+    frame_rate, overlay, queues, scale, shared_capture, zoom, PipelineGstreamerInterface,
+    PipelineState, PIPELINE_FILTER_NAME, PIPELINE_RTP_TEE_NAME, PIPELINE_VIDEO_TEE_NAME,
 };
 
 use anyhow::{anyhow, Result};
@@ -43,7 +43,47 @@ impl V4lPipeline {
             }
         };
 
-        let device = video_source.device_path.as_str();
+        // The node may have changed since the stream was configured, like after a reboot
+        let device = video_source.current_device_path();
+        if device != video_source.device_path {
+            info!(
+                "Device {:?} is now {device:?}, found through its stable id {:?}",
+                video_source.device_path, video_source.stable_id
+            );
+        }
+
+        // Streams sharing their device get its frames from the shared capture, and only need to decode and encode
+        // them again when streaming them differently
+        let source = match video_and_stream_information
+            .stream_information
+            .shared_capture()
+        {
+            Some(shared_capture) if shared_capture == configuration => {
+                shared_capture::source_description(pipeline_id)
+            }
+            Some(_) => {
+                let description = shared_capture::transcoding_description(
+                    pipeline_id,
+                    video_and_stream_information,
+                    configuration,
+                )?;
+
+                debug!("pipeline_description: {description:#?}");
+
+                return Ok(gst::parse::launch(&description)?
+                    .downcast::<gst::Pipeline>()
+                    .expect("Couldn't downcast pipeline"));
+            }
+            None => format!("v4l2src device={device} do-timestamp=true"),
+        };
+        let source = format!(
+            "{source}{}",
+            queues::description(
+                pipeline_id,
+                queues::PIPELINE_SOURCE_QUEUE_NAME,
+                video_and_stream_information.stream_information.queues(),
+            )
+        );
         let width = configuration.width;
         let height = configuration.height;
         let interval_numerator = configuration.frame_interval.numerator;
@@ -51,19 +91,33 @@ impl V4lPipeline {
         let filter_name = format!("{PIPELINE_FILTER_NAME}-{pipeline_id}");
         let video_tee_name = format!("{PIPELINE_VIDEO_TEE_NAME}-{pipeline_id}");
         let rtp_tee_name = format!("{PIPELINE_RTP_TEE_NAME}-{pipeline_id}");
+        let zoom = zoom::description(pipeline_id, width, height);
+        let overlay = overlay::description(pipeline_id);
+        let output_resolution = video_and_stream_information
+            .stream_information
+            .output_resolution();
+        let scale = scale::description(pipeline_id, width, height, output_resolution);
+        let (output_width, output_height) = scale::output_size(width, height, output_resolution);
+        let max_framerate = video_and_stream_information
+            .stream_information
+            .max_framerate();
+        let rate =
+            frame_rate::description(pipeline_id, &configuration.frame_interval, max_framerate);
+        let output_interval =
+            frame_rate::output_interval(&configuration.frame_interval, max_framerate);
 
         let description = match &configuration.encode {
             VideoEncodeType::H264 => {
                 format!(
                     concat!(
-                        "v4l2src device={device} do-timestamp=true",
+                        "{source}",
                         " ! h264parse",  // Here we need the parse to help the stream-format and alignment part, which is being fixed here because avc/au seems to reduce the CPU usage in the RTP payloading part.
                         " ! capsfilter name={filter_name} caps=video/x-h264,stream-format=avc,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
                         " ! tee name={video_tee_name} allow-not-linked=true",
                         " ! rtph264pay aggregate-mode=zero-latency config-interval=10 pt=96",
                         " ! tee name={rtp_tee_name} allow-not-linked=true"
                     ),
-                    device = device,
+                    source = source,
                     width = width,
                     height = height,
                     interval_denominator = interval_denominator,
@@ -73,17 +127,17 @@ impl V4lPipeline {
                     rtp_tee_name = rtp_tee_name,
                 )
             }
-            VideoEncodeType::Yuyv => {
+            VideoEncodeType::H265 => {
                 format!(
                     concat!(
-                        "v4l2src device={device} do-timestamp=true",
-                        " ! videoconvert",
-                        " ! capsfilter name={filter_name} caps=video/x-raw,format=I420,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
+                        "{source}",
+                        " ! h265parse",
+                        " ! capsfilter name={filter_name} caps=video/x-h265,stream-format=byte-stream,alignment=au,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
                         " ! tee name={video_tee_name} allow-not-linked=true",
-                        " ! rtpvrawpay pt=96",
+                        " ! rtph265pay aggregate-mode=zero-latency config-interval=10 pt=96",
                         " ! tee name={rtp_tee_name} allow-not-linked=true"
                     ),
-                    device = device,
+                    source = source,
                     width = width,
                     height = height,
                     interval_denominator = interval_denominator,
@@ -93,17 +147,45 @@ impl V4lPipeline {
                     rtp_tee_name = rtp_tee_name,
                 )
             }
+            VideoEncodeType::Yuyv => {
+                format!(
+                    concat!(
+                        "{source}",
+                        " ! videoconvert",
+                        "{zoom}",
+                        "{overlay}",
+                        "{scale}",
+                        "{rate}",
+                        " ! capsfilter name={filter_name} caps=video/x-raw,format=I420,width={output_width},height={output_height},framerate={interval_denominator}/{interval_numerator}",
+                        " ! tee name={video_tee_name} allow-not-linked=true",
+                        " ! rtpvrawpay pt=96",
+                        " ! tee name={rtp_tee_name} allow-not-linked=true"
+                    ),
+                    source = source,
+                    zoom = zoom,
+                    overlay = overlay,
+                    scale = scale,
+                    rate = rate,
+                    output_width = output_width,
+                    output_height = output_height,
+                    interval_denominator = output_interval.denominator,
+                    interval_numerator = output_interval.numerator,
+                    filter_name = filter_name,
+                    video_tee_name = video_tee_name,
+                    rtp_tee_name = rtp_tee_name,
+                )
+            }
             VideoEncodeType::Mjpg => {
                 format!(
                     concat!(
-                        "v4l2src device={device} do-timestamp=true",
+                        "{source}",
                         // We don't need a jpegparse, as it leads to incompatible caps, spoiling the negotiation.
                         " ! capsfilter name={filter_name} caps=image/jpeg,width={width},height={height},framerate={interval_denominator}/{interval_numerator}",
                         " ! tee name={video_tee_name} allow-not-linked=true",
                         " ! rtpjpegpay pt=96",
                         " ! tee name={rtp_tee_name} allow-not-linked=true"
                     ),
-                    device = device,
+                    source = source,
                     width = width,
                     height = height,
                     interval_denominator = interval_denominator,
@@ -0,0 +1,200 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::*;
+
+pub const PIPELINE_ZOOM_CROP_NAME: &str = "ZoomCrop";
+
+/// The maximum digital zoom factor, beyond which the image is too degraded to be useful
+pub const MAX_ZOOM: f64 = 8.0;
+/// Zoom factor multiplied (or divided) on each zoom step
+const ZOOM_STEP_FACTOR: f64 = 1.25;
+/// How fast the continuous zoom changes, in zoom factor per second
+const ZOOM_CONTINUOUS_RATE: f64 = 1.0;
+/// Period between each update of the continuous zoom
+const ZOOM_CONTINUOUS_PERIOD: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// The raw video stage cropping and scaling the frames back to the given resolution, so the caps downstream
+/// don't change with the zoom
+pub fn description(pipeline_id: &uuid::Uuid, width: u32, height: u32) -> String {
+    format!(
+        concat!(
+            " ! video/x-raw,width={width},height={height}",
+            " ! videocrop name={PIPELINE_ZOOM_CROP_NAME}-{pipeline_id}",
+            " ! videoscale",
+            " ! video/x-raw,width={width},height={height}",
+        ),
+        width = width,
+        height = height,
+        pipeline_id = pipeline_id,
+        PIPELINE_ZOOM_CROP_NAME = PIPELINE_ZOOM_CROP_NAME,
+    )
+}
+
+/// The crop, in pixels from each border, centered on the frame for the given zoom factor: (left, right, top, bottom)
+pub fn crop_for_zoom(width: u32, height: u32, zoom: f64) -> (i32, i32, i32, i32) {
+    let zoom = zoom.clamp(1.0, MAX_ZOOM);
+
+    // Raw formats like I420 are subsampled, so keep everything even
+    let border = |size: u32| -> i32 {
+        let cropped = (size as f64 - size as f64 / zoom) / 2.0;
+        (cropped as i32) & !1
+    };
+
+    let horizontal = border(width);
+    let vertical = border(height);
+
+    (horizontal, horizontal, vertical, vertical)
+}
+
+/// Digital zoom done by cropping the center of the frames
+#[derive(Debug)]
+pub struct DigitalZoom {
+    videocrop_weak: gst::glib::WeakRef<gst::Element>,
+    width: u32,
+    height: u32,
+    level: Mutex<f64>,
+    continuous_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl Drop for DigitalZoom {
+    fn drop(&mut self) {
+        if let Some(handle) = self.continuous_handle.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+}
+
+impl DigitalZoom {
+    /// Finds the zoom stage of the pipeline, which only exists for raw video in our pipelines
+    pub fn try_new(pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid) -> Option<Self> {
+        let videocrop = pipeline.by_name(&format!("{PIPELINE_ZOOM_CROP_NAME}-{pipeline_id}"))?;
+
+        let caps = videocrop
+            .static_pad("sink")?
+            .peer()?
+            .parent_element()?
+            .property::<gst::Caps>("caps");
+        let structure = caps.structure(0)?;
+        let width = structure.get::<i32>("width").ok()? as u32;
+        let height = structure.get::<i32>("height").ok()? as u32;
+
+        Some(Self {
+            videocrop_weak: videocrop.downgrade(),
+            width,
+            height,
+            level: Mutex::new(1.0),
+            continuous_handle: Default::default(),
+        })
+    }
+
+    /// The current zoom factor, from 1 to [`MAX_ZOOM`]
+    pub fn level(&self) -> f64 {
+        *self.level.lock().unwrap()
+    }
+
+    /// Sets the zoom factor, clamped from 1 to [`MAX_ZOOM`], stopping any continuous zoom
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_level(&self, level: f64) -> Result<()> {
+        self.stop();
+        self.apply(level)
+    }
+
+    /// Zooms in (positive) or out (negative) by the given number of steps
+    #[instrument(level = "debug", skip(self))]
+    pub fn step(&self, steps: f64) -> Result<()> {
+        self.stop();
+        self.apply(self.level() * ZOOM_STEP_FACTOR.powf(steps))
+    }
+
+    /// Sets the zoom from a percentage of its range, where 0 is no zoom and 100 is [`MAX_ZOOM`]
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_range(&self, percentage: f64) -> Result<()> {
+        let percentage = percentage.clamp(0.0, 100.0);
+        self.set_level(1.0 + (MAX_ZOOM - 1.0) * percentage / 100.0)
+    }
+
+    /// Keeps zooming in (positive direction) or out (negative direction) until stopped or a limit is reached
+    #[instrument(level = "debug", skip(self))]
+    pub fn start_continuous(self: &Arc<Self>, direction: f64) {
+        self.stop();
+
+        if direction == 0.0 {
+            return;
+        }
+
+        let zoom = Arc::downgrade(self);
+        let rate = ZOOM_CONTINUOUS_RATE * direction.signum();
+        let handle = tokio::spawn(async move {
+            let mut period = tokio::time::interval(ZOOM_CONTINUOUS_PERIOD);
+            loop {
+                period.tick().await;
+
+                let Some(zoom) = zoom.upgrade() else {
+                    break;
+                };
+
+                let level = zoom.level() + rate * ZOOM_CONTINUOUS_PERIOD.as_secs_f64();
+                if let Err(error) = zoom.apply(level) {
+                    warn!("Failed applying the continuous zoom: {error:?}");
+                    break;
+                }
+
+                if !(1.0..MAX_ZOOM).contains(&level) {
+                    break;
+                }
+            }
+        });
+
+        *self.continuous_handle.lock().unwrap() = Some(handle);
+    }
+
+    /// Stops any continuous zoom, keeping the current level
+    pub fn stop(&self) {
+        if let Some(handle) = self.continuous_handle.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+
+    fn apply(&self, level: f64) -> Result<()> {
+        let level = level.clamp(1.0, MAX_ZOOM);
+
+        let videocrop = self
+            .videocrop_weak
+            .upgrade()
+            .ok_or_else(|| anyhow!("The zoom's videocrop element no longer exists"))?;
+
+        let (left, right, top, bottom) = crop_for_zoom(self.width, self.height, level);
+        videocrop.set_property("left", left);
+        videocrop.set_property("right", right);
+        videocrop.set_property("top", top);
+        videocrop.set_property("bottom", bottom);
+
+        *self.level.lock().unwrap() = level;
+        trace!("Zoom set to {level:.2}x");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crop_for_zoom() {
+        assert_eq!(crop_for_zoom(1920, 1080, 1.0), (0, 0, 0, 0));
+        assert_eq!(crop_for_zoom(1920, 1080, 2.0), (480, 480, 270, 270));
+        // Always even, as required by subsampled formats
+        assert_eq!(crop_for_zoom(1920, 1080, 3.0), (640, 640, 360, 360));
+        assert_eq!(crop_for_zoom(100, 100, 1.1), (4, 4, 4, 4));
+        // Clamped to the maximum zoom
+        assert_eq!(
+            crop_for_zoom(1920, 1080, 100.0),
+            crop_for_zoom(1920, 1080, MAX_ZOOM)
+        );
+        // Clamped to no zoom
+        assert_eq!(crop_for_zoom(1920, 1080, 0.5), (0, 0, 0, 0));
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Result};
+use tracing::*;
+
+use super::types::RtpConfiguration;
+
+/// The payload type used when none is configured
+pub const DEFAULT_PAYLOAD_TYPE: u8 = 96;
+/// The dynamic payload types, as defined by the [RFC 3551](https://www.rfc-editor.org/rfc/rfc3551#section-6)
+const DYNAMIC_PAYLOAD_TYPES: std::ops::RangeInclusive<u8> = 96..=127;
+
+/// The size of the RTP header, without CSRCs or extensions
+const RTP_HEADER_SIZE: usize = 12;
+
+#[instrument(level = "debug")]
+pub fn validate(configuration: &RtpConfiguration) -> Result<()> {
+    if let Some(payload_type) = configuration.payload_type {
+        if !DYNAMIC_PAYLOAD_TYPES.contains(&payload_type) {
+            return Err(anyhow!(
+                "RTP payload type should be in the dynamic range ({}..={}), got {payload_type}",
+                DYNAMIC_PAYLOAD_TYPES.start(),
+                DYNAMIC_PAYLOAD_TYPES.end()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The properties of a video RTP payloader, like "pt=96", for gst-launch descriptions
+pub fn payloader_properties(configuration: Option<&RtpConfiguration>) -> String {
+    let payload_type = configuration
+        .and_then(|configuration| configuration.payload_type)
+        .unwrap_or(DEFAULT_PAYLOAD_TYPE);
+
+    match configuration.and_then(|configuration| configuration.ssrc) {
+        Some(ssrc) => format!("pt={payload_type} ssrc={ssrc}"),
+        None => format!("pt={payload_type}"),
+    }
+}
+
+/// Overwrites the payload type and SSRC of the given RTP packet, when configured. Returns false when it is not
+/// a RTP packet
+pub fn rewrite_header(packet: &mut [u8], configuration: &RtpConfiguration) -> bool {
+    if packet.len() < RTP_HEADER_SIZE || packet[0] >> 6 != 2 {
+        return false;
+    }
+
+    if let Some(payload_type) = configuration.payload_type {
+        // The marker bit shares the byte with the payload type
+        packet[1] = (packet[1] & 0x80) | (payload_type & 0x7f);
+    }
+
+    if let Some(ssrc) = configuration.ssrc {
+        packet[8..12].copy_from_slice(&ssrc.to_be_bytes());
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let configuration = |payload_type| RtpConfiguration {
+            payload_type,
+            ssrc: Some(1),
+        };
+
+        assert!(validate(&configuration(None)).is_ok());
+        assert!(validate(&configuration(Some(96))).is_ok());
+        assert!(validate(&configuration(Some(127))).is_ok());
+        assert!(validate(&configuration(Some(26))).is_err());
+        assert!(validate(&configuration(Some(128))).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_header() {
+        // Version 2, marker set, payload type 96, SSRC 0x01020304
+        let mut packet = [
+            0x80, 0xe0, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x01, 0x02, 0x03, 0x04, 0xff,
+        ];
+
+        assert!(rewrite_header(
+            &mut packet,
+            &RtpConfiguration {
+                payload_type: Some(100),
+                ssrc: Some(0xdeadbeef),
+            }
+        ));
+        assert_eq!(packet[1], 0x80 | 100);
+        assert_eq!(&packet[8..12], &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(packet[12], 0xff);
+
+        assert!(!rewrite_header(
+            &mut [0x80, 0xe0],
+            &RtpConfiguration::default()
+        ));
+    }
+}
@@ -1,2 +1,3 @@
+pub mod rtsp_auth;
 pub mod rtsp_scheme;
 pub mod rtsp_server;
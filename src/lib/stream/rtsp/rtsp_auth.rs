@@ -0,0 +1,154 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{anyhow, Result};
+use gst_rtsp_server::prelude::*;
+use tracing::*;
+
+use crate::{settings, stream::types::RtspCredentials};
+
+use super::rtsp_server::RTSPServer;
+
+/// The role given to authenticated clients, which every media factory allows to access and construct its media
+pub const RTSP_USER_ROLE: &str = "user";
+
+/// If the RTSP clients need credentials, kept apart from the settings so describing the endpoints doesn't depend on
+/// them
+static REQUIRES_CREDENTIALS: AtomicBool = AtomicBool::new(false);
+
+/// Applies the RTSP credentials from the settings, to be called once they are loaded
+#[instrument(level = "debug")]
+pub fn init() {
+    let credentials = settings::manager::rtsp_credentials().unwrap_or_default();
+
+    if let Err(error) = apply(&credentials) {
+        error!("Failed applying the RTSP credentials from the settings: {error:?}");
+    }
+}
+
+/// The usernames accepted by the RTSP server. The passwords are never given back
+#[instrument(level = "debug")]
+pub fn usernames() -> Vec<String> {
+    settings::manager::rtsp_credentials()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|credentials| credentials.username)
+        .collect()
+}
+
+/// If the RTSP clients need to authenticate, so the advertised RTSP URLs are not enough by themselves
+pub fn requires_credentials() -> bool {
+    REQUIRES_CREDENTIALS.load(Ordering::Relaxed)
+}
+
+/// Changes the users accepted by the RTSP server, or opens it to anyone when none is given.
+/// Only new connections are affected, the clients already playing keep going until they disconnect
+#[instrument(level = "debug", skip(credentials))]
+pub fn set_credentials(credentials: Vec<RtspCredentials>) -> Result<()> {
+    apply(&credentials)?;
+
+    settings::manager::set_rtsp_credentials((!credentials.is_empty()).then_some(credentials));
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip(credentials))]
+pub fn validate(credentials: &[RtspCredentials]) -> Result<()> {
+    for (index, credential) in credentials.iter().enumerate() {
+        let username = &credential.username;
+
+        if username.is_empty() {
+            return Err(anyhow!("RTSP username should not be empty"));
+        }
+
+        // The basic authentication separates the username from the password with a colon
+        if username.contains(':') {
+            return Err(anyhow!(
+                "RTSP username {username:?} should not contain a colon"
+            ));
+        }
+
+        if credential.password.is_empty() {
+            return Err(anyhow!("RTSP password of {username:?} should not be empty"));
+        }
+
+        if credentials[..index]
+            .iter()
+            .any(|other| &other.username == username)
+        {
+            return Err(anyhow!("RTSP username {username:?} is repeated"));
+        }
+    }
+
+    Ok(())
+}
+
+fn apply(credentials: &[RtspCredentials]) -> Result<()> {
+    validate(credentials)?;
+
+    RTSPServer::set_auth(auth(credentials).as_ref());
+    REQUIRES_CREDENTIALS.store(!credentials.is_empty(), Ordering::Relaxed);
+
+    info!(
+        "RTSP authentication {}",
+        if credentials.is_empty() {
+            "disabled"
+        } else {
+            "enabled"
+        }
+    );
+
+    Ok(())
+}
+
+/// The RTSP authentication accepting the given users, through both basic and digest authentication, or none when
+/// the server should be open
+fn auth(credentials: &[RtspCredentials]) -> Option<gst_rtsp_server::RTSPAuth> {
+    if credentials.is_empty() {
+        return None;
+    }
+
+    let auth = gst_rtsp_server::RTSPAuth::new();
+    auth.set_supported_methods(gst_rtsp::RTSPAuthMethod::Basic | gst_rtsp::RTSPAuthMethod::Digest);
+
+    let token = gst_rtsp_server::RTSPToken::builder()
+        .field(
+            gst_rtsp_server::RTSP_TOKEN_MEDIA_FACTORY_ROLE,
+            RTSP_USER_ROLE,
+        )
+        .build();
+
+    for credential in credentials {
+        auth.add_basic(
+            gst_rtsp_server::RTSPAuth::make_basic(&credential.username, &credential.password)
+                .as_str(),
+            &token,
+        );
+        auth.add_digest(&credential.username, &credential.password, &token);
+    }
+
+    Some(auth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let credentials = |username: &str, password: &str| RtspCredentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+
+        assert!(validate(&[]).is_ok());
+        assert!(validate(&[credentials("pilot", "secret"), credentials("qgc", "secret")]).is_ok());
+        assert!(validate(&[credentials("", "secret")]).is_err());
+        assert!(validate(&[credentials("pi:lot", "secret")]).is_err());
+        assert!(validate(&[credentials("pilot", "")]).is_err());
+        assert!(validate(&[
+            credentials("pilot", "secret"),
+            credentials("pilot", "other")
+        ])
+        .is_err());
+    }
+}
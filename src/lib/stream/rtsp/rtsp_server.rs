@@ -7,7 +7,15 @@ use gst_rtsp::RTSPLowerTrans;
 use gst_rtsp_server::{prelude::*, RTSPTransportMode};
 use tracing::*;
 
-use super::rtsp_scheme::RTSPScheme;
+use crate::network::utils::unspecified_address;
+use crate::stream::{
+    gst::utils::rtp_encoding_name,
+    pipeline::runner::{publish_event, PipelineEventKind},
+    rtp,
+    types::{ClientProtocol, ClientStatus, RtpConfiguration},
+};
+
+use super::{rtsp_auth::RTSP_USER_ROLE, rtsp_scheme::RTSPScheme};
 
 #[allow(dead_code)]
 pub struct RTSPServer {
@@ -27,6 +35,20 @@ lazy_static! {
 
 pub const RTSP_SERVER_PORT: u16 = 8554;
 
+/// A client connected to the RTSP server
+struct RTSPClientEntry {
+    /// The stream played by this client, known after its first play request
+    stream_id: Arc<Mutex<Option<uuid::Uuid>>>,
+    remote_address: Option<String>,
+    /// When the client connected, in milliseconds since the UNIX epoch
+    connected_at: i64,
+    client: gst::glib::WeakRef<gst_rtsp_server::RTSPClient>,
+}
+
+lazy_static! {
+    static ref RTSP_CLIENTS: Mutex<HashMap<uuid::Uuid, RTSPClientEntry>> = Default::default();
+}
+
 impl RTSPServer {
     #[instrument(level = "debug")]
     fn default() -> Self {
@@ -37,12 +59,112 @@ impl RTSPServer {
         let is_running = false;
         let (sender, receiver) = std::sync::mpsc::channel::<String>();
 
-        let host = "0.0.0.0".to_string();
+        let host = unspecified_address().to_string();
         let port = RTSP_SERVER_PORT;
         let server = gst_rtsp_server::RTSPServer::new();
         server.set_address(&host);
         server.set_service(&port.to_string());
 
+        // The shared media is already running when a new client joins, so ask for a keyframe to not
+        // let it wait for the next one to start decoding
+        server.connect_client_connected(|_server, client| {
+            let client_id = uuid::Uuid::new_v4();
+            // The stream played by this client, known after its first play request
+            let stream_id: Arc<Mutex<Option<uuid::Uuid>>> = Default::default();
+
+            RTSP_CLIENTS.lock().unwrap().insert(
+                client_id,
+                RTSPClientEntry {
+                    stream_id: stream_id.clone(),
+                    remote_address: client_ip(client),
+                    connected_at: chrono::Utc::now().timestamp_millis(),
+                    client: client.downgrade(),
+                },
+            );
+
+            // Clients are counted once they start playing, so this is where full streams reject them
+            client.connect_pre_play_request({
+                let stream_id = stream_id.clone();
+
+                move |_client, context| {
+                    // A client can pause and play again, but it only connected once
+                    if stream_id.lock().unwrap().is_some() {
+                        return gst_rtsp::RTSPStatusCode::Ok;
+                    }
+
+                    let Some(path) = context
+                        .uri()
+                        .and_then(|uri| url::Url::parse(&uri.request_uri()).ok())
+                        .map(|uri| uri.path().to_string())
+                    else {
+                        return gst_rtsp::RTSPStatusCode::Ok;
+                    };
+
+                    let id = futures::executor::block_on(async move {
+                        let id = crate::stream::manager::stream_id_from_rtsp_path(&path).await?;
+
+                        if !crate::stream::manager::has_free_client_slot(&id).await {
+                            return Some(Err(id));
+                        }
+
+                        Some(Ok(id))
+                    });
+
+                    match id {
+                        Some(Ok(id)) => {
+                            stream_id.lock().unwrap().replace(id);
+                            publish_event(
+                                id,
+                                PipelineEventKind::ClientConnected {
+                                    client: client_id.to_string(),
+                                    protocol: "rtsp".to_string(),
+                                },
+                            );
+                        }
+                        Some(Err(id)) => {
+                            warn!("Rejecting RTSP client {client_id}: stream {id} reached its maximum number of clients");
+                            return gst_rtsp::RTSPStatusCode::NotEnoughBandwidth;
+                        }
+                        None => (),
+                    }
+
+                    gst_rtsp::RTSPStatusCode::Ok
+                }
+            });
+
+            // New clients can only start decoding from a keyframe, so ask the stream being played for one
+            client.connect_play_request({
+                let stream_id = stream_id.clone();
+
+                move |_client, _context| {
+                    // Resolved from the request's path before it started playing
+                    let Some(id) = *stream_id.lock().unwrap() else {
+                        return;
+                    };
+
+                    if let Err(error) =
+                        futures::executor::block_on(crate::stream::manager::force_keyframe(&id))
+                    {
+                        debug!("Failed forcing a keyframe on stream {id:?}: {error:?}");
+                    }
+                }
+            });
+
+            client.connect_closed(move |_client| {
+                RTSP_CLIENTS.lock().unwrap().remove(&client_id);
+
+                if let Some(id) = stream_id.lock().unwrap().take() {
+                    publish_event(
+                        id,
+                        PipelineEventKind::ClientDisconnected {
+                            client: client_id.to_string(),
+                            protocol: "rtsp".to_string(),
+                        },
+                    );
+                }
+            });
+        });
+
         RTSPServer {
             server,
             host,
@@ -112,6 +234,9 @@ impl RTSPServer {
         path: &str,
         socket_path: &str,
         rtp_caps: &gst::Caps,
+        latency_ms: u32,
+        rtp: Option<&RtpConfiguration>,
+        audio: Option<(&str, &gst::Caps)>,
     ) -> Result<()> {
         // Initialize the singleton before calling gst factory
         let mut rtsp_server = RTSP_SERVER.as_ref().lock().unwrap();
@@ -136,31 +261,31 @@ impl RTSPServer {
             RTSPScheme::Rtspsh => RTSPLowerTrans::HTTP | RTSPLowerTrans::TCP | RTSPLowerTrans::TLS,
         };
 
+        // A shared factory constructs a single media, reading the already encoded stream from the
+        // shared memory once, and fans it out to every client
         let factory = gst_rtsp_server::RTSPMediaFactory::new();
         factory.set_shared(true);
+        // A client going away without a TEARDOWN shouldn't stop the shared media for the others
+        factory.set_stop_on_disconnect(false);
         factory.set_buffer_size(0);
-        factory.set_latency(0u32);
+        factory.set_latency(latency_ms);
         factory.set_transport_mode(RTSPTransportMode::PLAY);
         factory.set_protocols(protocols);
-
-        let Some(encode) = rtp_caps.iter().find_map(|structure| {
-            structure.iter().find_map(|(key, sendvalue)| {
-                if key == "encoding-name" {
-                    Some(
-                        sendvalue
-                            .to_value()
-                            .get::<String>()
-                            .expect("Failed accessing encoding-name parameter"),
-                    )
-                } else {
-                    None
-                }
-            })
-        }) else {
+        // Only matters when the server requires authentication, in which case the authenticated users get this role
+        factory.add_role_from_structure(
+            &gst::Structure::builder(RTSP_USER_ROLE)
+                .field(gst_rtsp_server::RTSP_PERM_MEDIA_FACTORY_ACCESS, true)
+                .field(gst_rtsp_server::RTSP_PERM_MEDIA_FACTORY_CONSTRUCT, true)
+                .build(),
+        );
+
+        let Some(encode) = rtp_encoding_name(rtp_caps) else {
             return Err(anyhow!("Cannot find 'media' in caps"));
         };
 
         let rtp_caps = rtp_caps.to_string();
+        // The clients get the configured payload type and SSRC, as the video is payloaded again
+        let payloader_properties = rtp::payloader_properties(rtp);
         let description = match encode.as_str() {
             "H264" => {
                 format!(
@@ -169,10 +294,27 @@ impl RTSPServer {
                         " ! queue leaky=downstream flush-on-eos=true silent=true max-size-buffers=0",
                         " ! capsfilter caps={rtp_caps:?}",
                         " ! rtph264depay",
-                        " ! rtph264pay name=pay0 aggregate-mode=zero-latency config-interval=10 pt=96",
+                        // Send SPS/PPS with every IDR, so clients joining a shared media can start decoding right away
+                        " ! rtph264pay name=pay0 aggregate-mode=zero-latency config-interval=-1 {payloader_properties}",
                     ),
                     socket_path = socket_path,
                     rtp_caps = rtp_caps,
+                    payloader_properties = payloader_properties,
+                )
+            }
+            "H265" => {
+                format!(
+                    concat!(
+                        "shmsrc socket-path={socket_path} do-timestamp=true is-live=false",
+                        " ! queue leaky=downstream flush-on-eos=true silent=true max-size-buffers=0",
+                        " ! capsfilter caps={rtp_caps:?}",
+                        " ! rtph265depay",
+                        // Send VPS/SPS/PPS with every IDR, so clients joining a shared media can start decoding right away
+                        " ! rtph265pay name=pay0 aggregate-mode=zero-latency config-interval=-1 {payloader_properties}",
+                    ),
+                    socket_path = socket_path,
+                    rtp_caps = rtp_caps,
+                    payloader_properties = payloader_properties,
                 )
             }
             "RAW" => {
@@ -182,10 +324,11 @@ impl RTSPServer {
                         " ! queue leaky=downstream flush-on-eos=true silent=true max-size-buffers=0",
                         " ! capsfilter caps={rtp_caps:?}",
                         " ! rtpvrawdepay",
-                        " ! rtpvrawpay name=pay0 pt=96",
+                        " ! rtpvrawpay name=pay0 {payloader_properties}",
                     ),
                     socket_path = socket_path,
                     rtp_caps = rtp_caps,
+                    payloader_properties = payloader_properties,
                 )
             }
             "JPEG" => {
@@ -195,10 +338,11 @@ impl RTSPServer {
                         " ! queue leaky=downstream flush-on-eos=true silent=true max-size-buffers=10",
                         " ! capsfilter caps={rtp_caps:?}",
                         " ! rtpjpegdepay",
-                        " ! rtpjpegpay name=pay0 pt=96",
+                        " ! rtpjpegpay name=pay0 {payloader_properties}",
                     ),
                     socket_path = socket_path,
                     rtp_caps = rtp_caps,
+                    payloader_properties = payloader_properties,
                 )
             }
             unsupported => {
@@ -208,6 +352,15 @@ impl RTSPServer {
             }
         };
 
+        // The audio goes as a second stream of the same media, so clients keep it in sync with the video
+        let description = match audio {
+            Some((audio_socket_path, audio_caps)) => format!(
+                "{description} {}",
+                audio_description(audio_socket_path, audio_caps)?
+            ),
+            None => description,
+        };
+
         debug!("RTSP Server description: {description:#?}");
 
         factory.set_launch(&description);
@@ -253,6 +406,29 @@ impl RTSPServer {
         Ok(())
     }
 
+    /// Changes the authentication of the server, or opens it to anyone when none is given. A client is checked with
+    /// the authentication it got when connecting, so the ones already playing are not dropped
+    #[instrument(level = "debug", skip(auth))]
+    pub fn set_auth(auth: Option<&gst_rtsp_server::RTSPAuth>) {
+        let rtsp_server = RTSP_SERVER.as_ref().lock().unwrap();
+
+        rtsp_server.server.set_auth(auth);
+    }
+
+    /// Changes the latency of the given path, which takes effect on the next media constructed for it
+    #[instrument(level = "debug")]
+    pub fn set_latency(path: &str, latency_ms: u32) -> Result<()> {
+        let rtsp_server = RTSP_SERVER.as_ref().lock().unwrap();
+
+        let factory = rtsp_server.path_to_factory.get(path).context(format!(
+            "Factory for path {path:?} not found in RTSP factories"
+        ))?;
+
+        factory.set_latency(latency_ms);
+
+        Ok(())
+    }
+
     #[instrument(level = "debug")]
     pub fn stop_pipeline(path: &str) -> Result<()> {
         let mut rtsp_server = RTSP_SERVER.as_ref().lock().unwrap();
@@ -278,4 +454,209 @@ impl RTSPServer {
 
         Ok(())
     }
+
+    /// The RTSP clients playing the given stream
+    #[instrument(level = "debug")]
+    pub fn clients(stream_id: &uuid::Uuid) -> Vec<ClientStatus> {
+        RTSP_CLIENTS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| *entry.stream_id.lock().unwrap() == Some(*stream_id))
+            .map(|(client_id, entry)| ClientStatus {
+                id: *client_id,
+                protocol: ClientProtocol::Rtsp,
+                remote_address: entry.remote_address.clone(),
+                connected_at: entry.connected_at,
+                codec: None,
+                estimated_bitrate: None,
+            })
+            .collect()
+    }
+
+    /// Closes the connection of a single RTSP client, keeping the shared media running for the others
+    #[instrument(level = "debug")]
+    pub fn disconnect_client(stream_id: &uuid::Uuid, client_id: &uuid::Uuid) -> Result<()> {
+        let client = {
+            let clients = RTSP_CLIENTS.lock().unwrap();
+
+            let entry = clients
+                .get(client_id)
+                .filter(|entry| *entry.stream_id.lock().unwrap() == Some(*stream_id))
+                .context(format!(
+                    "RTSP client {client_id} not found for stream {stream_id}"
+                ))?;
+
+            entry
+                .client
+                .upgrade()
+                .context(format!("RTSP client {client_id} is already gone"))?
+        };
+
+        // The server is attached to the default main context, so its clients are handled from there
+        gst::glib::MainContext::default().invoke(move || client.close());
+
+        info!("RTSP client {client_id} of stream {stream_id} disconnected");
+
+        Ok(())
+    }
+}
+
+/// The IP address of the client's connection, which isn't exposed by the bindings
+fn client_ip(client: &gst_rtsp_server::RTSPClient) -> Option<String> {
+    use gst::glib::translate::ToGlibPtr;
+
+    // SAFETY: The connection is owned by the client, which is alive during this call, and its IP is copied
+    // before returning
+    unsafe {
+        let connection =
+            gst_rtsp_server::ffi::gst_rtsp_client_get_connection(client.to_glib_none().0);
+        if connection.is_null() {
+            return None;
+        }
+
+        let ip = gst_rtsp::ffi::gst_rtsp_connection_get_ip(connection);
+        if ip.is_null() {
+            return None;
+        }
+
+        Some(std::ffi::CStr::from_ptr(ip).to_string_lossy().into_owned())
+    }
+}
+
+fn audio_description(socket_path: &str, rtp_caps: &gst::Caps) -> Result<String> {
+    let Some(encode) = rtp_encoding_name(rtp_caps) else {
+        return Err(anyhow!("Cannot find 'media' in audio caps"));
+    };
+
+    let (depayloader, payloader) = match encode.as_str() {
+        "OPUS" => ("rtpopusdepay", "rtpopuspay"),
+        "MPEG4-GENERIC" => ("rtpmp4gdepay", "rtpmp4gpay"),
+        unsupported => {
+            return Err(anyhow!(
+                "Audio encode {unsupported:?} is not supported for RTSP Sink"
+            ))
+        }
+    };
+
+    Ok(format!(
+        concat!(
+            "shmsrc socket-path={socket_path} do-timestamp=true is-live=false",
+            " ! queue leaky=downstream flush-on-eos=true silent=true max-size-buffers=0",
+            " ! capsfilter caps={rtp_caps:?}",
+            " ! {depayloader}",
+            " ! {payloader} name=pay1 pt={payload_type}",
+        ),
+        socket_path = socket_path,
+        rtp_caps = rtp_caps.to_string(),
+        depayloader = depayloader,
+        payloader = payloader,
+        payload_type = crate::stream::pipeline::audio::AUDIO_PAYLOAD_TYPE,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiple_clients_share_one_media() {
+        gst::init().unwrap();
+
+        let path = "/test_multiple_clients";
+        let socket_path = format!("/tmp/{}", uuid::Uuid::new_v4());
+
+        // The encoding happens once, in the source pipeline, exactly like with the RTSP sink
+        let source = gst::parse::launch(&format!(
+            concat!(
+                "videotestsrc is-live=true pattern=ball",
+                " ! video/x-raw,width=320,height=240,framerate=30/1",
+                " ! x264enc tune=zerolatency speed-preset=ultrafast",
+                " ! rtph264pay aggregate-mode=zero-latency config-interval=-1 pt=96",
+                " ! shmsink name=sink socket-path={socket_path} sync=false wait-for-connection=false",
+            ),
+            socket_path = socket_path,
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        source.set_state(gst::State::Playing).unwrap();
+
+        // Wait for the caps to be negotiated
+        let shmsink_sink_pad = source.by_name("sink").unwrap().static_pad("sink").unwrap();
+        let rtp_caps = (0..50)
+            .find_map(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                shmsink_sink_pad.current_caps()
+            })
+            .unwrap();
+
+        RTSPServer::add_pipeline(
+            &RTSPScheme::Rtsp,
+            path,
+            &socket_path,
+            &rtp_caps,
+            0,
+            None,
+            None,
+        )
+        .unwrap();
+
+        // Count how many medias (pipelines) are constructed by the RTSP server for this path
+        let medias_constructed = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        {
+            let medias_constructed = medias_constructed.clone();
+            RTSP_SERVER
+                .lock()
+                .unwrap()
+                .path_to_factory
+                .get(path)
+                .unwrap()
+                .connect_media_constructed(move |_factory, _media| {
+                    medias_constructed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                });
+        }
+
+        RTSPServer::start_pipeline(path).unwrap();
+        while !RTSPServer::is_running() {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+
+        let clients = (0..2)
+            .map(|_| {
+                let client = gst::parse::launch(&format!(
+                    "rtspsrc location=rtsp://127.0.0.1:{RTSP_SERVER_PORT}{path} latency=0 ! rtph264depay ! appsink name=sink sync=false"
+                ))
+                .unwrap()
+                .downcast::<gst::Pipeline>()
+                .unwrap();
+                client.set_state(gst::State::Playing).unwrap();
+                client
+            })
+            .collect::<Vec<gst::Pipeline>>();
+
+        for client in &clients {
+            let appsink = client
+                .by_name("sink")
+                .unwrap()
+                .downcast::<gst_app::AppSink>()
+                .unwrap();
+
+            assert!(appsink
+                .try_pull_sample(gst::ClockTime::from_seconds(10))
+                .is_some());
+        }
+
+        assert_eq!(
+            medias_constructed.load(std::sync::atomic::Ordering::Relaxed),
+            1
+        );
+
+        for client in &clients {
+            client.set_state(gst::State::Null).unwrap();
+        }
+        RTSPServer::stop_pipeline(path).unwrap();
+        source.set_state(gst::State::Null).unwrap();
+        let _ = std::fs::remove_file(&socket_path);
+    }
 }
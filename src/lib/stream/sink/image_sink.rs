@@ -357,6 +357,19 @@ impl ImageSink {
                 _transcoding_elements.push(filter);
                 _transcoding_elements.push(decoder);
             }
+            VideoEncodeType::H265 => {
+                // Same as h264, non-key frames are filtered out before decoding
+                let filter = gst::ElementFactory::make("identity")
+                    .property("drop-buffer-flags", gst::BufferFlags::DELTA_UNIT)
+                    .property("sync", false)
+                    .build()?;
+                let decoder = gst::ElementFactory::make("avdec_h265")
+                    .property_from_str("lowres", "2") // (0) is 'full'; (1) is '1/2-size'; (2) is '1/4-size'
+                    .build()?;
+                decoder.has_property("discard-corrupted-frames", None).then(|| decoder.set_property("discard-corrupted-frames", true));
+                _transcoding_elements.push(filter);
+                _transcoding_elements.push(decoder);
+            }
             VideoEncodeType::Mjpg => {
                 let decoder = gst::ElementFactory::make("jpegdec").build()?;
                 decoder.has_property("discard-corrupted-frames", None).then(|| decoder.set_property("discard-corrupted-frames", true));
@@ -364,7 +377,7 @@ impl ImageSink {
             }
             VideoEncodeType::Rgb => {}
             VideoEncodeType::Yuyv => {}
-            _ => return Err(anyhow!("Unsupported video encoding for ImageSink: {encoding:?}. The supported are: H264, MJPG and YUYV")),
+            _ => return Err(anyhow!("Unsupported video encoding for ImageSink: {encoding:?}. The supported are: H264, H265, MJPG and YUYV")),
         };
 
         let videoconvert = gst::ElementFactory::make("videoconvert").build()?;
@@ -509,7 +522,8 @@ impl ImageSink {
             return Err(anyhow!("Failed linking ImageSink's elements: {link_err:?}"));
         }
 
-        let pipeline_runner = PipelineRunner::try_new(&pipeline, &sink_id, true)?;
+        let pipeline_runner =
+            PipelineRunner::try_new(&pipeline, &sink_id, true, Default::default(), None)?;
 
         // Start the pipeline in Pause, because we want to wait the snapshot
         if let Err(state_err) = pipeline.set_state(gst::State::Paused) {
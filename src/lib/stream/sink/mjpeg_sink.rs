@@ -0,0 +1,303 @@
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+
+use tracing::*;
+
+use gst::prelude::*;
+
+use super::SinkInterface;
+use crate::video::types::VideoEncodeType;
+
+/// The default framerate limit of the MJPEG streams, bounding their bandwidth regardless of the stream's framerate
+pub const DEFAULT_MJPEG_MAX_FRAMERATE: u32 = 5;
+/// The highest framerate limit accepted for the MJPEG streams
+pub const MAX_MJPEG_MAX_FRAMERATE: u32 = 30;
+
+#[derive(Debug)]
+pub struct MjpegSink {
+    sink_id: uuid::Uuid,
+    elements: Vec<gst::Element>,
+    tee_src_pad: Option<gst::Pad>,
+    frames: Mutex<Option<tokio::sync::mpsc::Receiver<Vec<u8>>>>,
+}
+
+impl SinkInterface for MjpegSink {
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn link(
+        &mut self,
+        pipeline: &gst::Pipeline,
+        pipeline_id: &uuid::Uuid,
+        tee_src_pad: gst::Pad,
+    ) -> Result<()> {
+        let sink_id = &self.get_id();
+
+        // Set Tee's src pad
+        if self.tee_src_pad.is_some() {
+            return Err(anyhow!(
+                "Tee's src pad from MjpegSink {sink_id} has already been configured"
+            ));
+        }
+        self.tee_src_pad.replace(tee_src_pad);
+        let Some(tee_src_pad) = &self.tee_src_pad else {
+            unreachable!()
+        };
+
+        // Block data flow to prevent any data before set Playing, which would cause an error
+        let Some(tee_src_pad_data_blocker) = tee_src_pad
+            .add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+                gst::PadProbeReturn::Ok
+            })
+        else {
+            let msg =
+                "Failed adding probe to Tee's src pad to block data before going to playing state"
+                    .to_string();
+            error!(msg);
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            return Err(anyhow!(msg));
+        };
+
+        // Add the Sink elements to the source's pipeline
+        let elements = &self.elements.iter().collect::<Vec<&gst::Element>>();
+        if let Err(add_err) = pipeline.add_many(elements) {
+            let msg = format!(
+                "Failed to add MjpegSink's elements to Pipeline {pipeline_id}: {add_err:#?}"
+            );
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Link the Sink's elements
+        if let Err(link_err) = gst::Element::link_many(elements) {
+            let msg = format!("Failed linking MjpegSink's elements: {link_err:?}");
+            error!(msg);
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            if let Err(remove_err) = pipeline.remove_many(elements) {
+                error!("Failed to remove elements from pipeline: {remove_err:?}");
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Link the new Tee's src pad to the Queue's sink pad
+        let queue_sink_pad = &self.elements[0]
+            .static_pad("sink")
+            .expect("No sink pad found on Queue");
+        if let Err(link_err) = tee_src_pad.link(queue_sink_pad) {
+            let msg = format!("Failed to link Tee's src pad with Queue's sink pad: {link_err:?}");
+            error!(msg);
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            if let Err(remove_err) = pipeline.remove_many(elements) {
+                error!("Failed to remove elements from pipeline: {remove_err:?}");
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Syncronize added and linked elements
+        for element in elements {
+            if let Err(sync_err) = element.sync_state_with_parent() {
+                let msg = format!("Failed to synchronize MjpegSink's element state: {sync_err:?}");
+                error!(msg);
+
+                if let Err(unlink_err) = tee_src_pad.unlink(queue_sink_pad) {
+                    error!("Failed to unlink Tee's src pad and Queue's sink pad: {unlink_err:?}");
+                }
+
+                if let Some(parent) = tee_src_pad.parent_element() {
+                    parent.release_request_pad(tee_src_pad)
+                }
+
+                if let Err(remove_err) = pipeline.remove_many(elements) {
+                    error!("Failed to remove elements from pipeline: {remove_err:?}");
+                }
+
+                return Err(anyhow!(msg));
+            }
+        }
+
+        // Unblock data to go through this added Tee src pad
+        tee_src_pad.remove_probe(tee_src_pad_data_blocker);
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn unlink(&self, pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid) -> Result<()> {
+        let Some(tee_src_pad) = &self.tee_src_pad else {
+            warn!("Tried to unlink Sink from a pipeline without a Tee src pad.");
+            return Ok(());
+        };
+
+        // Block data flow to prevent any data from holding the Pipeline elements alive
+        if tee_src_pad
+            .add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+                gst::PadProbeReturn::Ok
+            })
+            .is_none()
+        {
+            warn!(
+                "Failed adding probe to Tee's src pad to block data before going to playing state"
+            );
+        }
+
+        // Unlink the Queue element from the source's pipeline Tee's src pad
+        let queue_sink_pad = self.elements[0]
+            .static_pad("sink")
+            .expect("No sink pad found on Queue");
+        if let Err(unlink_err) = tee_src_pad.unlink(&queue_sink_pad) {
+            warn!("Failed unlinking MjpegSink's Queue element from Tee's src pad: {unlink_err:?}");
+        }
+        drop(queue_sink_pad);
+
+        // Release Tee's src pad
+        if let Some(parent) = tee_src_pad.parent_element() {
+            parent.release_request_pad(tee_src_pad)
+        }
+
+        // Set the Sink's elements to null and remove them from the Source's pipeline
+        let elements = &self.elements.iter().collect::<Vec<&gst::Element>>();
+        for element in elements {
+            if let Err(state_err) = element.set_state(gst::State::Null) {
+                warn!("Failed to set MjpegSink's element state to NULL: {state_err:#?}");
+            }
+        }
+        if let Err(remove_err) = pipeline.remove_many(elements) {
+            warn!(
+                "Failed removing MjpegSink's elements from pipeline {pipeline_id}: {remove_err:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn get_id(&self) -> uuid::Uuid {
+        self.sink_id
+    }
+
+    #[instrument(level = "trace", skip(self))]
+    fn get_sdp(&self) -> Result<gst_sdp::SDPMessage> {
+        Err(anyhow!(
+            "Not available. Reason: MJPEG Sink doesn't provide endpoints"
+        ))
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn start(&self) -> Result<()> {
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn eos(&self) {
+        // Nothing to finalize, the frames channel closes once the appsink is dropped
+    }
+}
+
+impl MjpegSink {
+    /// Encodes the video as JPEG frames, at most `max_framerate` per second, never holding the stream back
+    #[instrument(level = "debug")]
+    pub fn try_new(
+        sink_id: uuid::Uuid,
+        encoding: VideoEncodeType,
+        max_framerate: u32,
+    ) -> Result<Self> {
+        if !(1..=MAX_MJPEG_MAX_FRAMERATE).contains(&max_framerate) {
+            return Err(anyhow!(
+                "MJPEG framerate should be between 1 and {MAX_MJPEG_MAX_FRAMERATE}"
+            ));
+        }
+
+        let queue = gst::ElementFactory::make("queue")
+            .property_from_str("leaky", "downstream") // Throw away any data
+            .property("silent", true)
+            .build()?;
+
+        // The framerate is limited before encoding, so the dropped frames cost nothing
+        let videorate = gst::ElementFactory::make("videorate")
+            .property("drop-only", true)
+            .property("max-rate", max_framerate as i32)
+            .build()?;
+
+        // Depending of the sources' format we need different elements to get a JPEG out of it
+        let mut elements = vec![queue];
+        match encoding {
+            VideoEncodeType::Mjpg => {
+                // Already JPEG, only the framerate is limited
+                elements.push(videorate);
+            }
+            VideoEncodeType::H264 | VideoEncodeType::H265 => {
+                let decoder = match encoding {
+                    VideoEncodeType::H265 => "avdec_h265",
+                    _ => "avdec_h264",
+                };
+                let decoder = gst::ElementFactory::make(decoder).build()?;
+                decoder.has_property("discard-corrupted-frames", None).then(|| decoder.set_property("discard-corrupted-frames", true));
+                elements.push(decoder);
+                elements.push(videorate);
+                elements.push(gst::ElementFactory::make("videoconvert").build()?);
+                elements.push(gst::ElementFactory::make("jpegenc").build()?);
+            }
+            VideoEncodeType::Rgb | VideoEncodeType::Yuyv => {
+                elements.push(videorate);
+                elements.push(gst::ElementFactory::make("videoconvert").build()?);
+                elements.push(gst::ElementFactory::make("jpegenc").build()?);
+            }
+            _ => return Err(anyhow!("Unsupported video encoding for MjpegSink: {encoding:?}. The supported are: H264, H265, MJPG, RGB and YUYV")),
+        }
+
+        // Only the latest frame is kept for a slow client
+        let (frames_tx, frames_rx) = tokio::sync::mpsc::channel::<Vec<u8>>(1);
+        let appsink = gst_app::AppSink::builder()
+            .name(format!("MjpegAppSink-{sink_id}"))
+            .sync(false)
+            .max_buffers(1u32)
+            .drop(true)
+            .caps(&gst::Caps::builder("image/jpeg").build())
+            .callbacks(
+                gst_app::AppSinkCallbacks::builder()
+                    .new_sample(move |appsink| {
+                        let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                        let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
+                        let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+
+                        // Frames are dropped while the client is still sending the previous one, or once it is
+                        // gone, as the sink is about to be removed
+                        let _ = frames_tx.try_send(map.as_slice().to_vec());
+
+                        Ok(gst::FlowSuccess::Ok)
+                    })
+                    .build(),
+            )
+            .build();
+        elements.push(appsink.upcast());
+
+        Ok(Self {
+            sink_id,
+            elements,
+            tee_src_pad: Default::default(),
+            frames: Mutex::new(Some(frames_rx)),
+        })
+    }
+
+    /// The JPEG frames of this sink, which can only be taken once
+    pub fn take_frames(&self) -> Option<tokio::sync::mpsc::Receiver<Vec<u8>>> {
+        self.frames.lock().unwrap().take()
+    }
+}
@@ -1,5 +1,8 @@
 pub mod image_sink;
+pub mod mjpeg_sink;
+pub mod recording_sink;
 pub mod rtsp_sink;
+pub mod srt_sink;
 pub mod udp_sink;
 pub mod webrtc_sink;
 
@@ -8,7 +11,10 @@ use enum_dispatch::enum_dispatch;
 use crate::video_stream::types::VideoAndStreamInformation;
 
 use image_sink::ImageSink;
+use mjpeg_sink::MjpegSink;
+use recording_sink::RecordingSink;
 use rtsp_sink::RtspSink;
+use srt_sink::SrtSink;
 use udp_sink::UdpSink;
 use webrtc_sink::WebRTCSink;
 
@@ -51,8 +57,11 @@ pub trait SinkInterface {
 pub enum Sink {
     Udp(UdpSink),
     Rtsp(RtspSink),
+    Srt(SrtSink),
     WebRTC(WebRTCSink),
     Image(ImageSink),
+    Recording(RecordingSink),
+    Mjpeg(MjpegSink),
 }
 
 #[instrument(level = "debug")]
@@ -65,7 +74,22 @@ pub fn create_udp_sink(
         .endpoints
         .clone();
 
-    Ok(Sink::Udp(UdpSink::try_new(id, addresses)?))
+    let rtp = video_and_stream_information
+        .stream_information
+        .rtp()
+        .cloned();
+    let multicast = video_and_stream_information
+        .stream_information
+        .multicast()
+        .cloned();
+    let udp_socket = video_and_stream_information
+        .stream_information
+        .udp_socket()
+        .cloned();
+
+    Ok(Sink::Udp(UdpSink::try_new(
+        id, addresses, rtp, multicast, udp_socket,
+    )?))
 }
 
 #[instrument(level = "debug")]
@@ -78,7 +102,38 @@ pub fn create_rtsp_sink(
         .endpoints
         .clone();
 
-    Ok(Sink::Rtsp(RtspSink::try_new(id, addresses)?))
+    let latency_ms = video_and_stream_information.stream_information.latency_ms();
+    let rtp = video_and_stream_information
+        .stream_information
+        .rtp()
+        .cloned();
+
+    Ok(Sink::Rtsp(RtspSink::try_new(
+        id, addresses, latency_ms, rtp,
+    )?))
+}
+
+#[instrument(level = "debug")]
+pub fn create_srt_sink(
+    id: uuid::Uuid,
+    address: &url::Url,
+    video_and_stream_information: &VideoAndStreamInformation,
+) -> Result<Sink> {
+    let encoding = match &video_and_stream_information
+        .stream_information
+        .configuration
+    {
+        super::types::CaptureConfiguration::Video(video_configuraiton) => {
+            video_configuraiton.encode.clone()
+        }
+        super::types::CaptureConfiguration::Redirect(_) => {
+            return Err(anyhow!(
+                "SrtSinks are not yet implemented for Redirect sources"
+            ))
+        }
+    };
+
+    Ok(Sink::Srt(SrtSink::try_new(id, address, encoding)?))
 }
 
 #[instrument(level = "debug")]
@@ -101,3 +156,56 @@ pub fn create_image_sink(
     };
     Ok(Sink::Image(ImageSink::try_new(id, encoding)?))
 }
+
+#[instrument(level = "debug")]
+pub fn create_recording_sink(
+    id: uuid::Uuid,
+    video_and_stream_information: &VideoAndStreamInformation,
+    settings: &super::types::RecordingSettings,
+) -> Result<Sink> {
+    let encoding = match &video_and_stream_information
+        .stream_information
+        .configuration
+    {
+        super::types::CaptureConfiguration::Video(video_configuraiton) => {
+            video_configuraiton.encode.clone()
+        }
+        super::types::CaptureConfiguration::Redirect(_) => {
+            return Err(anyhow!(
+                "RecordingSinks are not yet implemented for Redirect sources"
+            ))
+        }
+    };
+    Ok(Sink::Recording(RecordingSink::try_new(
+        id,
+        video_and_stream_information.display_name(),
+        encoding,
+        settings,
+    )?))
+}
+
+#[instrument(level = "debug")]
+pub fn create_mjpeg_sink(
+    id: uuid::Uuid,
+    video_and_stream_information: &VideoAndStreamInformation,
+    max_framerate: u32,
+) -> Result<Sink> {
+    let encoding = match &video_and_stream_information
+        .stream_information
+        .configuration
+    {
+        super::types::CaptureConfiguration::Video(video_configuraiton) => {
+            video_configuraiton.encode.clone()
+        }
+        super::types::CaptureConfiguration::Redirect(_) => {
+            return Err(anyhow!(
+                "MjpegSinks are not yet implemented for Redirect sources"
+            ))
+        }
+    };
+    Ok(Sink::Mjpeg(MjpegSink::try_new(
+        id,
+        encoding,
+        max_framerate,
+    )?))
+}
@@ -0,0 +1,470 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+
+use tracing::*;
+
+use gst::prelude::*;
+
+use super::SinkInterface;
+use crate::{
+    stream::types::{RecordingFormat, RecordingSettings},
+    video::types::VideoEncodeType,
+};
+
+/// Maximum time waiting for the muxer to finalize the current file when the recording is stopped
+const FINALIZE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct FinalizeState {
+    stopping: bool,
+    finalized: bool,
+}
+
+#[derive(Debug)]
+pub struct RecordingSink {
+    sink_id: uuid::Uuid,
+    queue: gst::Element,
+    parser: gst::Element,
+    splitmuxsink: gst::Element,
+    tee_src_pad: Option<gst::Pad>,
+    audio_tee: Option<gst::Element>,
+    audio_queue: gst::Element,
+    audio_tee_src_pad: Option<gst::Pad>,
+    audio_muxer_pad: Option<gst::Pad>,
+    location: String,
+    finalize: Arc<(Mutex<FinalizeState>, Condvar)>,
+}
+
+impl SinkInterface for RecordingSink {
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn link(
+        &mut self,
+        pipeline: &gst::Pipeline,
+        pipeline_id: &uuid::Uuid,
+        tee_src_pad: gst::Pad,
+    ) -> Result<()> {
+        let sink_id = &self.get_id();
+
+        // Set Tee's src pad
+        if self.tee_src_pad.is_some() {
+            return Err(anyhow!(
+                "Tee's src pad from RecordingSink {sink_id} has already been configured"
+            ));
+        }
+        self.tee_src_pad.replace(tee_src_pad);
+        let Some(tee_src_pad) = &self.tee_src_pad else {
+            unreachable!()
+        };
+
+        // Block data flow to prevent any data before set Playing, which would cause an error
+        let Some(tee_src_pad_data_blocker) = tee_src_pad
+            .add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+                gst::PadProbeReturn::Ok
+            })
+        else {
+            let msg =
+                "Failed adding probe to Tee's src pad to block data before going to playing state"
+                    .to_string();
+            error!(msg);
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            return Err(anyhow!(msg));
+        };
+
+        // Add the Sink elements to the source's pipeline
+        let elements = &[&self.queue, &self.parser, &self.splitmuxsink];
+        if let Err(add_err) = pipeline.add_many(elements) {
+            let msg = format!(
+                "Failed to add RecordingSink's elements to Pipeline {pipeline_id}: {add_err:#?}"
+            );
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Link the Sink's elements
+        if let Err(link_err) = gst::Element::link_many(elements) {
+            let msg = format!("Failed linking RecordingSink's elements: {link_err:?}");
+            error!(msg);
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            if let Err(remove_err) = pipeline.remove_many(elements) {
+                error!("Failed to remove elements from pipeline: {remove_err:?}");
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Link the new Tee's src pad to the Queue's sink pad
+        let queue_sink_pad = &self
+            .queue
+            .static_pad("sink")
+            .expect("No sink pad found on Queue");
+        if let Err(link_err) = tee_src_pad.link(queue_sink_pad) {
+            let msg = format!("Failed to link Tee's src pad with Queue's sink pad: {link_err:?}");
+            error!(msg);
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            if let Err(remove_err) = pipeline.remove_many(elements) {
+                error!("Failed to remove elements from pipeline: {remove_err:?}");
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Syncronize added and linked elements
+        for element in elements {
+            if let Err(sync_err) = element.sync_state_with_parent() {
+                let msg =
+                    format!("Failed to synchronize RecordingSink's element state: {sync_err:?}");
+                error!(msg);
+
+                if let Err(unlink_err) = tee_src_pad.unlink(queue_sink_pad) {
+                    error!("Failed to unlink Tee's src pad and Queue's sink pad: {unlink_err:?}");
+                }
+
+                if let Some(parent) = tee_src_pad.parent_element() {
+                    parent.release_request_pad(tee_src_pad)
+                }
+
+                if let Err(remove_err) = pipeline.remove_many(elements) {
+                    error!("Failed to remove elements from pipeline: {remove_err:?}");
+                }
+
+                return Err(anyhow!(msg));
+            }
+        }
+
+        // A failing audio shouldn't stop the video from being recorded
+        if let Err(error) = self.link_audio(pipeline) {
+            warn!("Recording {:?} without audio: {error:?}", self.location);
+            self.unlink_audio(pipeline);
+            self.audio_tee_src_pad = None;
+            self.audio_muxer_pad = None;
+        }
+
+        // Unblock data to go through this added Tee src pad
+        let Some(tee_src_pad) = &self.tee_src_pad else {
+            unreachable!()
+        };
+        tee_src_pad.remove_probe(tee_src_pad_data_blocker);
+
+        info!("Recording to {:?}", self.location);
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn unlink(&self, pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid) -> Result<()> {
+        let Some(tee_src_pad) = &self.tee_src_pad else {
+            warn!("Tried to unlink Sink from a pipeline without a Tee src pad.");
+            return Ok(());
+        };
+
+        // Block data flow, so the EOS is the last thing going into the recording branch
+        if tee_src_pad
+            .add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+                gst::PadProbeReturn::Ok
+            })
+            .is_none()
+        {
+            warn!("Failed adding probe to Tee's src pad to block data before finalizing the recording");
+        }
+
+        if let Some(audio_tee_src_pad) = &self.audio_tee_src_pad {
+            if audio_tee_src_pad
+                .add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+                    gst::PadProbeReturn::Ok
+                })
+                .is_none()
+            {
+                warn!("Failed adding probe to the audio Tee's src pad to block data before finalizing the recording");
+            }
+        }
+
+        // Send EOS only into this branch, and wait for the muxer to finalize the file
+        self.finalize_recording();
+
+        self.unlink_audio(pipeline);
+
+        // Unlink the Queue element from the source's pipeline Tee's src pad
+        let queue_sink_pad = self
+            .queue
+            .static_pad("sink")
+            .expect("No sink pad found on Queue");
+        if let Err(unlink_err) = tee_src_pad.unlink(&queue_sink_pad) {
+            warn!(
+                "Failed unlinking RecordingSink's Queue element from Tee's src pad: {unlink_err:?}"
+            );
+        }
+        drop(queue_sink_pad);
+
+        // Release Tee's src pad
+        if let Some(parent) = tee_src_pad.parent_element() {
+            parent.release_request_pad(tee_src_pad)
+        }
+
+        // Set the Sink's elements to null and remove them from the Source's pipeline
+        let elements = &[&self.queue, &self.parser, &self.splitmuxsink];
+        for element in elements {
+            if let Err(state_err) = element.set_state(gst::State::Null) {
+                warn!("Failed to set RecordingSink's element state to NULL: {state_err:#?}");
+            }
+        }
+        if let Err(remove_err) = pipeline.remove_many(elements) {
+            warn!("Failed removing RecordingSink's elements from pipeline {pipeline_id}: {remove_err:?}");
+        }
+
+        info!("Recording to {:?} finished", self.location);
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn get_id(&self) -> uuid::Uuid {
+        self.sink_id
+    }
+
+    #[instrument(level = "trace", skip(self))]
+    fn get_sdp(&self) -> Result<gst_sdp::SDPMessage> {
+        Err(anyhow!(
+            "Not available. Reason: Recording Sink doesn't provide endpoints"
+        ))
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn start(&self) -> Result<()> {
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn eos(&self) {
+        // The EOS is sent into this branch only when unlinking, so the file can be finalized
+    }
+}
+
+impl RecordingSink {
+    #[instrument(level = "debug")]
+    pub fn try_new(
+        sink_id: uuid::Uuid,
+        name: &str,
+        encoding: VideoEncodeType,
+        settings: &RecordingSettings,
+    ) -> Result<Self> {
+        let queue = gst::ElementFactory::make("queue")
+            .property("silent", true)
+            .build()?;
+
+        let parser = match encoding {
+            VideoEncodeType::H264 => gst::ElementFactory::make("h264parse").build()?,
+            VideoEncodeType::H265 => gst::ElementFactory::make("h265parse").build()?,
+            VideoEncodeType::Mjpg => gst::ElementFactory::make("jpegparse").build()?,
+            _ => return Err(anyhow!("Unsupported video encoding for RecordingSink: {encoding:?}. The supported are: H264, H265 and MJPG")),
+        };
+
+        let muxer = match settings.format {
+            RecordingFormat::Mp4 => gst::ElementFactory::make("mp4mux").build()?,
+            RecordingFormat::Mkv => gst::ElementFactory::make("matroskamux").build()?,
+        };
+
+        let filesink = gst::ElementFactory::make("filesink")
+            .property("async", false)
+            .build()?;
+
+        // Timestamped files, with a fragment index for when they get split
+        std::fs::create_dir_all(&settings.path).context(format!(
+            "Failed creating recording directory {:?}",
+            settings.path
+        ))?;
+        let file_name = format!(
+            "{}-{}-%05d.{}",
+            name.replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+            settings.format.extension()
+        );
+        let location = std::path::Path::new(&settings.path)
+            .join(file_name)
+            .to_str()
+            .context("Invalid recording path")?
+            .to_string();
+
+        // The splitmuxsink handles splitting into new files on keyframes, continuing seamlessly
+        let splitmuxsink = gst::ElementFactory::make("splitmuxsink")
+            .property("location", &location)
+            .property("muxer", &muxer)
+            .property("sink", &filesink)
+            .property("max-size-bytes", settings.max_size_bytes.unwrap_or(0))
+            .property(
+                "max-size-time",
+                settings
+                    .max_duration_secs
+                    .map(|secs| secs * 1_000_000_000)
+                    .unwrap_or(0),
+            )
+            .build()?;
+
+        // Each file gets an EOS when it is closed, but we only care about the one sent when stopping
+        let finalize: Arc<(Mutex<FinalizeState>, Condvar)> = Default::default();
+        let finalize_cloned = finalize.clone();
+        filesink
+            .static_pad("sink")
+            .expect("No sink pad found on FileSink")
+            .add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_pad, info| {
+                if let Some(gst::PadProbeData::Event(event)) = &info.data {
+                    if event.type_() == gst::EventType::Eos {
+                        let (lock, condvar) = &*finalize_cloned;
+                        let mut state = lock.lock().unwrap();
+                        if state.stopping {
+                            state.finalized = true;
+                            condvar.notify_all();
+                        }
+                    }
+                }
+
+                gst::PadProbeReturn::Ok
+            });
+
+        let audio_queue = gst::ElementFactory::make("queue")
+            .property("silent", true)
+            .build()?;
+
+        Ok(Self {
+            sink_id,
+            queue,
+            parser,
+            splitmuxsink,
+            tee_src_pad: Default::default(),
+            audio_tee: Default::default(),
+            audio_queue,
+            audio_tee_src_pad: Default::default(),
+            audio_muxer_pad: Default::default(),
+            location,
+            finalize,
+        })
+    }
+
+    /// The location pattern of the recording files, where `%05d` is replaced by the fragment index
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    /// Records the encoded audio from the given Tee along with the video, which must be set before linking
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_audio_tee(&mut self, audio_tee: gst::Element) {
+        self.audio_tee.replace(audio_tee);
+    }
+
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn link_audio(&mut self, pipeline: &gst::Pipeline) -> Result<()> {
+        let Some(audio_tee) = &self.audio_tee else {
+            return Ok(());
+        };
+
+        // Both streams are timestamped by the Pipeline's clock, so the muxer interleaves them in sync
+        let audio_tee_src_pad = audio_tee
+            .request_pad_simple("src_%u")
+            .context("Failed requesting src pad for the audio Tee")?;
+        self.audio_tee_src_pad.replace(audio_tee_src_pad.clone());
+
+        pipeline.add(&self.audio_queue)?;
+
+        let audio_muxer_pad = self
+            .splitmuxsink
+            .request_pad_simple("audio_%u")
+            .context("Failed requesting audio pad from the splitmuxsink")?;
+        self.audio_muxer_pad.replace(audio_muxer_pad.clone());
+
+        self.audio_queue
+            .static_pad("src")
+            .expect("No src pad found on Queue")
+            .link(&audio_muxer_pad)?;
+        audio_tee_src_pad.link(
+            &self
+                .audio_queue
+                .static_pad("sink")
+                .expect("No sink pad found on Queue"),
+        )?;
+
+        self.audio_queue.sync_state_with_parent()?;
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn unlink_audio(&self, pipeline: &gst::Pipeline) {
+        if let Some(audio_tee_src_pad) = &self.audio_tee_src_pad {
+            if let Some(peer) = audio_tee_src_pad.peer() {
+                let _ = audio_tee_src_pad.unlink(&peer);
+            }
+            if let Some(parent) = audio_tee_src_pad.parent_element() {
+                parent.release_request_pad(audio_tee_src_pad)
+            }
+        }
+
+        if let Some(audio_muxer_pad) = &self.audio_muxer_pad {
+            if let Some(peer) = audio_muxer_pad.peer() {
+                let _ = peer.unlink(audio_muxer_pad);
+            }
+            self.splitmuxsink.release_request_pad(audio_muxer_pad);
+        }
+
+        if self.audio_queue.parent().is_some() {
+            if let Err(state_err) = self.audio_queue.set_state(gst::State::Null) {
+                warn!("Failed to set RecordingSink's audio Queue state to NULL: {state_err:#?}");
+            }
+            if let Err(remove_err) = pipeline.remove(&self.audio_queue) {
+                warn!("Failed removing RecordingSink's audio Queue from pipeline: {remove_err:?}");
+            }
+        }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn finalize_recording(&self) {
+        let (lock, condvar) = &*self.finalize;
+
+        lock.lock().unwrap().stopping = true;
+
+        // The muxer only finalizes the file once all of its streams have ended
+        if self.audio_tee_src_pad.is_some() {
+            let audio_queue_sink_pad = self
+                .audio_queue
+                .static_pad("sink")
+                .expect("No sink pad found on Queue");
+            if !audio_queue_sink_pad.send_event(gst::event::Eos::new()) {
+                warn!("Failed sending EOS into the RecordingSink's audio");
+            }
+        }
+
+        let queue_sink_pad = self
+            .queue
+            .static_pad("sink")
+            .expect("No sink pad found on Queue");
+        if !queue_sink_pad.send_event(gst::event::Eos::new()) {
+            warn!("Failed sending EOS into the RecordingSink");
+            return;
+        }
+
+        let (_, wait_result) = condvar
+            .wait_timeout_while(lock.lock().unwrap(), FINALIZE_TIMEOUT, |state| {
+                !state.finalized
+            })
+            .unwrap();
+
+        if wait_result.timed_out() {
+            warn!("Timed out after {FINALIZE_TIMEOUT:?} waiting for the recording to be finalized");
+        }
+    }
+}
@@ -4,7 +4,7 @@ use tracing::*;
 
 use gst::prelude::*;
 
-use crate::stream::rtsp::rtsp_scheme::RTSPScheme;
+use crate::stream::{rtsp::rtsp_scheme::RTSPScheme, types::RtpConfiguration};
 
 use super::SinkInterface;
 
@@ -18,6 +18,13 @@ pub struct RtspSink {
     scheme: RTSPScheme,
     path: String,
     socket_path: String,
+    latency_ms: u32,
+    rtp: Option<RtpConfiguration>,
+    audio_tee: Option<gst::Element>,
+    audio_queue: gst::Element,
+    audio_sink: gst::Element,
+    audio_tee_src_pad: Option<gst::Pad>,
+    audio_socket_path: String,
 }
 impl SinkInterface for RtspSink {
     #[instrument(level = "debug", skip(self, pipeline))]
@@ -141,7 +148,17 @@ impl SinkInterface for RtspSink {
             return Err(anyhow!(msg));
         }
 
+        // A failing audio shouldn't stop the video from being served
+        if let Err(error) = self.link_audio(pipeline) {
+            warn!("RtspSink {sink_id} without audio: {error:?}");
+            self.unlink_audio(pipeline);
+            self.audio_tee_src_pad = None;
+        }
+
         // Unblock data to go through this added Tee src pad
+        let Some(tee_src_pad) = &self.tee_src_pad else {
+            unreachable!()
+        };
         tee_src_pad.remove_probe(tee_src_pad_data_blocker);
 
         Ok(())
@@ -153,6 +170,8 @@ impl SinkInterface for RtspSink {
             warn!("Failed removing the RTSP Sink socket file. Reason: {error:?}");
         }
 
+        self.unlink_audio(pipeline);
+
         let Some(tee_src_pad) = &self.tee_src_pad else {
             warn!("Tried to unlink Sink from a pipeline without a Tee src pad.");
             return Ok(());
@@ -227,7 +246,12 @@ impl SinkInterface for RtspSink {
 
 impl RtspSink {
     #[instrument(level = "debug")]
-    pub fn try_new(id: uuid::Uuid, addresses: Vec<url::Url>) -> Result<Self> {
+    pub fn try_new(
+        id: uuid::Uuid,
+        addresses: Vec<url::Url>,
+        latency_ms: u32,
+        rtp: Option<RtpConfiguration>,
+    ) -> Result<Self> {
         let queue = gst::ElementFactory::make("queue")
             .property_from_str("leaky", "downstream") // Throw away any data
             .property("silent", true)
@@ -259,6 +283,22 @@ impl RtspSink {
 
         let sink_sink_pad = sink.static_pad("sink").context("Failed to get Sink Pad")?;
 
+        let audio_queue = gst::ElementFactory::make("queue")
+            .property_from_str("leaky", "downstream") // Throw away any data
+            .property("silent", true)
+            .property("flush-on-eos", true)
+            .property("max-size-buffers", 0u32) // Disable buffers
+            .build()?;
+
+        let audio_socket_path = format!("/tmp/{id}-audio");
+        let audio_sink = gst::ElementFactory::make("shmsink")
+            .property_from_str("socket-path", &audio_socket_path)
+            .property("sync", false)
+            .property("wait-for-connection", false)
+            .property("shm-size", 1_000_000u32)
+            .property("enable-last-sample", false)
+            .build()?;
+
         Ok(Self {
             sink_id: id,
             queue,
@@ -267,7 +307,14 @@ impl RtspSink {
             scheme,
             path,
             socket_path,
+            latency_ms,
+            rtp,
             tee_src_pad: Default::default(),
+            audio_tee: Default::default(),
+            audio_queue,
+            audio_sink,
+            audio_tee_src_pad: Default::default(),
+            audio_socket_path,
         })
     }
 
@@ -285,4 +332,87 @@ impl RtspSink {
     pub fn socket_path(&self) -> String {
         self.socket_path.clone()
     }
+
+    #[instrument(level = "trace", skip(self))]
+    pub fn latency_ms(&self) -> u32 {
+        self.latency_ms
+    }
+
+    /// The RTP payload type and SSRC served to the clients, when configured
+    #[instrument(level = "trace", skip(self))]
+    pub fn rtp(&self) -> Option<&RtpConfiguration> {
+        self.rtp.as_ref()
+    }
+
+    /// The socket path of the audio, when it is linked
+    #[instrument(level = "trace", skip(self))]
+    pub fn audio_socket_path(&self) -> Option<String> {
+        self.audio_tee_src_pad
+            .as_ref()
+            .map(|_| self.audio_socket_path.clone())
+    }
+
+    /// Serves the audio RTP packets from the given Tee along with the video, which must be set before linking
+    #[instrument(level = "debug", skip(self))]
+    pub fn set_audio_tee(&mut self, audio_tee: gst::Element) {
+        self.audio_tee.replace(audio_tee);
+    }
+
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn link_audio(&mut self, pipeline: &gst::Pipeline) -> Result<()> {
+        let Some(audio_tee) = &self.audio_tee else {
+            return Ok(());
+        };
+
+        let _ = std::fs::remove_file(&self.audio_socket_path); // Remove if already exists
+
+        let audio_tee_src_pad = audio_tee
+            .request_pad_simple("src_%u")
+            .context("Failed requesting src pad for the audio Tee")?;
+        self.audio_tee_src_pad.replace(audio_tee_src_pad.clone());
+
+        let elements = &[&self.audio_queue, &self.audio_sink];
+        pipeline.add_many(elements)?;
+        gst::Element::link_many(elements)?;
+        audio_tee_src_pad.link(
+            &self
+                .audio_queue
+                .static_pad("sink")
+                .expect("No sink pad found on Queue"),
+        )?;
+
+        for element in elements {
+            element.sync_state_with_parent()?;
+        }
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn unlink_audio(&self, pipeline: &gst::Pipeline) {
+        if let Some(audio_tee_src_pad) = &self.audio_tee_src_pad {
+            if let Some(peer) = audio_tee_src_pad.peer() {
+                let _ = audio_tee_src_pad.unlink(&peer);
+            }
+            if let Some(parent) = audio_tee_src_pad.parent_element() {
+                parent.release_request_pad(audio_tee_src_pad)
+            }
+
+            if let Err(error) = std::fs::remove_file(&self.audio_socket_path) {
+                warn!("Failed removing the RTSP Sink audio socket file. Reason: {error:?}");
+            }
+        }
+
+        for element in [&self.audio_queue, &self.audio_sink] {
+            if element.parent().is_none() {
+                continue;
+            }
+            if let Err(state_err) = element.set_state(gst::State::Null) {
+                warn!("Failed to set RtspSink's audio element state to NULL: {state_err:#?}");
+            }
+            if let Err(remove_err) = pipeline.remove(element) {
+                warn!("Failed removing RtspSink's audio element from pipeline: {remove_err:?}");
+            }
+        }
+    }
 }
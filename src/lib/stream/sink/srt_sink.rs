@@ -0,0 +1,506 @@
+use anyhow::{anyhow, Context, Result};
+
+use tracing::*;
+
+use gst::prelude::*;
+
+use super::SinkInterface;
+use crate::{stream::pipeline::runner::PipelineRunner, video::types::VideoEncodeType};
+
+/// Default SRT latency, in milliseconds, the same as libsrt's
+const DEFAULT_LATENCY_MS: u32 = 120;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SrtMode {
+    Caller,
+    Listener,
+    Rendezvous,
+}
+
+/// The SRT options taken from an "srt://" endpoint, like "srt://0.0.0.0:9000?mode=listener&latency=200&passphrase=0123456789"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrtSettings {
+    pub host: String,
+    pub port: u16,
+    pub mode: SrtMode,
+    /// In milliseconds
+    pub latency: u32,
+    pub passphrase: Option<String>,
+}
+
+impl TryFrom<&url::Url> for SrtSettings {
+    type Error = anyhow::Error;
+
+    fn try_from(address: &url::Url) -> Result<Self> {
+        if address.scheme() != "srt" {
+            return Err(anyhow!("Endpoint {address} should use the srt scheme"));
+        }
+
+        let host = address
+            .host_str()
+            .context(format!("Endpoint {address} without host"))?
+            .to_string();
+        let port = address
+            .port()
+            .context(format!("Endpoint {address} without port"))?;
+
+        let mut settings = Self {
+            host,
+            port,
+            mode: SrtMode::Caller,
+            latency: DEFAULT_LATENCY_MS,
+            passphrase: None,
+        };
+
+        for (key, value) in address.query_pairs() {
+            match key.as_ref() {
+                "mode" => {
+                    settings.mode = match value.as_ref() {
+                        "caller" => SrtMode::Caller,
+                        "listener" => SrtMode::Listener,
+                        "rendezvous" => SrtMode::Rendezvous,
+                        unknown => return Err(anyhow!(
+                            "Unknown SRT mode {unknown:?}, it should be one of \"caller\", \"listener\" or \"rendezvous\""
+                        )),
+                    }
+                }
+                "latency" => {
+                    settings.latency = value
+                        .parse()
+                        .context(format!("Invalid SRT latency {value:?}"))?
+                }
+                "passphrase" => {
+                    // As required by libsrt
+                    if !(10..=79).contains(&value.len()) {
+                        return Err(anyhow!(
+                            "SRT passphrase should have from 10 to 79 characters"
+                        ));
+                    }
+                    settings.passphrase = Some(value.to_string())
+                }
+                unknown => return Err(anyhow!("Unknown SRT option {unknown:?}")),
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+impl SrtSettings {
+    fn options(&self, mode: &SrtMode) -> String {
+        let mode = match mode {
+            SrtMode::Caller => "caller",
+            SrtMode::Listener => "listener",
+            SrtMode::Rendezvous => "rendezvous",
+        };
+
+        let mut options = format!("mode={mode}&latency={}", self.latency);
+        if let Some(passphrase) = &self.passphrase {
+            options.push_str(&format!("&passphrase={passphrase}"));
+        }
+
+        options
+    }
+
+    /// The URI used by the srtsink
+    pub fn uri(&self) -> String {
+        format!(
+            "srt://{}:{}?{}",
+            self.host,
+            self.port,
+            self.options(&self.mode)
+        )
+    }
+
+    /// The URL a client should use to connect to this sink
+    pub fn client_url(&self) -> Result<url::Url> {
+        let (host, mode) = match self.mode {
+            // The client listens for us on its own address
+            SrtMode::Caller => ("0.0.0.0", SrtMode::Listener),
+            SrtMode::Listener => (self.host.as_str(), SrtMode::Caller),
+            SrtMode::Rendezvous => (self.host.as_str(), SrtMode::Rendezvous),
+        };
+
+        url::Url::parse(&format!(
+            "srt://{host}:{}?{}",
+            self.port,
+            self.options(&mode)
+        ))
+        .context("Failed to build the SRT client URL")
+    }
+}
+
+#[derive(Debug)]
+pub struct SrtSink {
+    sink_id: uuid::Uuid,
+    pipeline: gst::Pipeline,
+    queue: gst::Element,
+    proxysink: gst::Element,
+    _proxysrc: gst::Element,
+    _srtsink: gst::Element,
+    settings: SrtSettings,
+    tee_src_pad: Option<gst::Pad>,
+    pipeline_runner: PipelineRunner,
+}
+impl SinkInterface for SrtSink {
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn link(
+        &mut self,
+        pipeline: &gst::Pipeline,
+        pipeline_id: &uuid::Uuid,
+        tee_src_pad: gst::Pad,
+    ) -> Result<()> {
+        let sink_id = &self.get_id();
+
+        // Set Tee's src pad
+        if self.tee_src_pad.is_some() {
+            return Err(anyhow!(
+                "Tee's src pad from SrtSink {sink_id} has already been configured"
+            ));
+        }
+        self.tee_src_pad.replace(tee_src_pad);
+        let Some(tee_src_pad) = &self.tee_src_pad else {
+            unreachable!()
+        };
+
+        // Block data flow to prevent any data before set Playing, which would cause an error
+        let Some(tee_src_pad_data_blocker) = tee_src_pad
+            .add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+                gst::PadProbeReturn::Ok
+            })
+        else {
+            let msg =
+                "Failed adding probe to Tee's src pad to block data before going to playing state"
+                    .to_string();
+            error!(msg);
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            return Err(anyhow!(msg));
+        };
+
+        // Add the ProxySink element to the source's pipeline
+        let elements = &[&self.queue, &self.proxysink];
+        if let Err(error) = pipeline.add_many(elements) {
+            let msg = format!("Failed to add ProxySink to Pipeline {pipeline_id}: {error:#?}");
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Link the queue's src pad to the ProxySink's sink pad
+        let queue_src_pad = &self
+            .queue
+            .static_pad("src")
+            .expect("No src pad found on Queue");
+        let proxysink_sink_pad = &self
+            .proxysink
+            .static_pad("sink")
+            .expect("No sink pad found on ProxySink");
+        if let Err(link_err) = queue_src_pad.link(proxysink_sink_pad) {
+            let msg =
+                format!("Failed to link Queue's src pad with ProxySink's sink pad: {link_err:?}");
+            error!(msg);
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            if let Err(remove_err) = pipeline.remove_many(elements) {
+                error!("Failed to remove elements from pipeline: {remove_err:?}");
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Link the new Tee's src pad to the ProxySink's sink pad
+        let queue_sink_pad = &self
+            .queue
+            .static_pad("sink")
+            .expect("No sink pad found on Queue");
+        if let Err(link_err) = tee_src_pad.link(queue_sink_pad) {
+            let msg = format!("Failed to link Tee's src pad with Queue's sink pad: {link_err:?}");
+            error!(msg);
+
+            if let Err(unlink_err) = queue_src_pad.unlink(proxysink_sink_pad) {
+                error!("Failed to unlink Queue's src pad and ProxySink's sink pad: {unlink_err:?}");
+            }
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            if let Err(remove_err) = pipeline.remove_many(elements) {
+                error!("Failed to remove elements from pipeline: {remove_err:?}");
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Syncronize added and linked elements
+        if let Err(sync_err) = pipeline.sync_children_states() {
+            let msg = format!("Failed to synchronize children states: {sync_err:?}");
+            error!(msg);
+
+            if let Err(unlink_err) = queue_src_pad.unlink(proxysink_sink_pad) {
+                error!("Failed to unlink Queue's src pad and ProxySink's sink pad: {unlink_err:?}");
+            }
+
+            if let Err(unlink_err) = queue_src_pad.unlink(proxysink_sink_pad) {
+                error!("Failed to unlink Queue's src pad and ProxySink's sink pad: {unlink_err:?}");
+            }
+
+            if let Some(parent) = tee_src_pad.parent_element() {
+                parent.release_request_pad(tee_src_pad)
+            }
+
+            if let Err(remove_err) = pipeline.remove_many(elements) {
+                error!("Failed to remove elements from pipeline: {remove_err:?}");
+            }
+
+            return Err(anyhow!(msg));
+        }
+
+        // Unblock data to go through this added Tee src pad
+        tee_src_pad.remove_probe(tee_src_pad_data_blocker);
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, pipeline))]
+    fn unlink(&self, pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid) -> Result<()> {
+        let Some(tee_src_pad) = &self.tee_src_pad else {
+            warn!("Tried to unlink Sink from a pipeline without a Tee src pad.");
+            return Ok(());
+        };
+
+        // Block data flow to prevent any data from holding the Pipeline elements alive
+        if tee_src_pad
+            .add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+                gst::PadProbeReturn::Ok
+            })
+            .is_none()
+        {
+            warn!(
+                "Failed adding probe to Tee's src pad to block data before going to playing state"
+            );
+        }
+
+        // Unlink the Queue element from the source's pipeline Tee's src pad
+        let queue_sink_pad = self
+            .queue
+            .static_pad("sink")
+            .expect("No sink pad found on Queue");
+        if let Err(unlink_err) = tee_src_pad.unlink(&queue_sink_pad) {
+            warn!("Failed unlinking SrtSink's Queue element from Tee's src pad: {unlink_err:?}");
+        }
+        drop(queue_sink_pad);
+
+        // Release Tee's src pad
+        if let Some(parent) = tee_src_pad.parent_element() {
+            parent.release_request_pad(tee_src_pad)
+        }
+
+        // Remove the Sink's elements from the Source's pipeline
+        let elements = &[&self.queue, &self.proxysink];
+        if let Err(remove_err) = pipeline.remove_many(elements) {
+            warn!("Failed removing SrtSink's elements from pipeline: {remove_err:?}");
+        }
+
+        // Set Sink's pipeline to null
+        if let Err(state_err) = self.pipeline.set_state(gst::State::Null) {
+            warn!("Failed to set Pipeline's state from SrtSink to NULL: {state_err:#?}");
+        }
+
+        // Set Queue to null
+        if let Err(state_err) = self.queue.set_state(gst::State::Null) {
+            warn!("Failed to set Queue's state to NULL: {state_err:#?}");
+        }
+
+        // Set ProxySink to null
+        if let Err(state_err) = self.proxysink.set_state(gst::State::Null) {
+            warn!("Failed to set ProxySink's state to NULL: {state_err:#?}");
+        }
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn get_id(&self) -> uuid::Uuid {
+        self.sink_id
+    }
+
+    #[instrument(level = "trace", skip(self))]
+    fn get_sdp(&self) -> Result<gst_sdp::SDPMessage> {
+        Err(anyhow!(
+            "Not available. Reason: SRT Sink doesn't use SDP, clients should connect using its SRT URL"
+        ))
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn start(&self) -> Result<()> {
+        self.pipeline_runner.start()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    fn eos(&self) {
+        let pipeline_weak = self.pipeline.downgrade();
+        if let Err(error) = std::thread::Builder::new()
+            .name("EOS".to_string())
+            .spawn(move || {
+                let pipeline = pipeline_weak.upgrade().unwrap();
+                if let Err(error) = pipeline.post_message(gst::message::Eos::new()) {
+                    error!("Failed posting Eos message into Sink bus. Reason: {error:?}");
+                }
+            })
+            .expect("Failed spawning EOS thread")
+            .join()
+        {
+            error!(
+                "EOS Thread Panicked with: {:?}",
+                error.downcast_ref::<String>()
+            );
+        }
+    }
+}
+
+impl SrtSink {
+    #[instrument(level = "debug")]
+    pub fn try_new(
+        sink_id: uuid::Uuid,
+        address: &url::Url,
+        encoding: VideoEncodeType,
+    ) -> Result<Self> {
+        let settings = SrtSettings::try_from(address)?;
+
+        let queue = gst::ElementFactory::make("queue")
+            .property_from_str("leaky", "downstream") // Throw away any data
+            .property("silent", true)
+            .property("flush-on-eos", true)
+            .property("max-size-buffers", 0u32) // Disable buffers
+            .build()?;
+
+        // Create a pair of proxies. The proxysink will be used in the source's pipeline,
+        // while the proxysrc will be used in this sink's pipeline, isolating any SRT connection
+        // error from the source's pipeline
+        let proxysink = gst::ElementFactory::make("proxysink").build()?;
+        let _proxysrc = gst::ElementFactory::make("proxysrc")
+            .property("proxysink", &proxysink)
+            .build()?;
+
+        // SRT carries MPEG-TS, which needs the parameter sets in-band
+        let parser = match encoding {
+            VideoEncodeType::H264 => gst::ElementFactory::make("h264parse")
+                .property("config-interval", -1i32)
+                .build()?,
+            VideoEncodeType::H265 => gst::ElementFactory::make("h265parse")
+                .property("config-interval", -1i32)
+                .build()?,
+            _ => return Err(anyhow!("Unsupported video encoding for SrtSink: {encoding:?}. The supported are: H264 and H265")),
+        };
+
+        let muxer = gst::ElementFactory::make("mpegtsmux")
+            .property("alignment", 7i32) // Each SRT packet carries 7 TS packets
+            .build()?;
+
+        let _srtsink = gst::ElementFactory::make("srtsink")
+            .property("uri", settings.uri())
+            .property("wait-for-connection", false)
+            .property("sync", false)
+            .build()?;
+
+        // Create the pipeline
+        let pipeline = gst::Pipeline::builder()
+            .name(format!("pipeline-sink-{sink_id}"))
+            .build();
+
+        // Add Sink elements to the Sink's Pipeline
+        let elements = [&_proxysrc, &parser, &muxer, &_srtsink];
+        if let Err(add_err) = pipeline.add_many(elements) {
+            return Err(anyhow!(
+                "Failed adding SrtSink's elements to Sink Pipeline: {add_err:?}"
+            ));
+        }
+
+        // Link Sink's elements
+        if let Err(link_err) = gst::Element::link_many(elements) {
+            if let Err(remove_err) = pipeline.remove_many(elements) {
+                warn!("Failed removing elements from SrtSink Pipeline: {remove_err:?}")
+            };
+            return Err(anyhow!("Failed linking SrtSink's elements: {link_err:?}"));
+        }
+
+        let pipeline_runner =
+            PipelineRunner::try_new(&pipeline, &sink_id, false, Default::default(), None)?;
+
+        // Start the pipeline
+        if let Err(state_err) = pipeline.set_state(gst::State::Playing) {
+            return Err(anyhow!(
+                "Failed starting SrtSink's pipeline: {state_err:#?}"
+            ));
+        }
+
+        info!("SRT sink {sink_id} using {:?}", settings.uri());
+
+        Ok(Self {
+            sink_id,
+            pipeline,
+            queue,
+            proxysink,
+            _proxysrc,
+            _srtsink,
+            settings,
+            tee_src_pad: Default::default(),
+            pipeline_runner,
+        })
+    }
+
+    /// The URL a client should use to connect to this sink
+    pub fn client_url(&self) -> Result<url::Url> {
+        self.settings.client_url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srt_settings() {
+        let address =
+            url::Url::parse("srt://0.0.0.0:9000?mode=listener&latency=200&passphrase=0123456789")
+                .unwrap();
+        let settings = SrtSettings::try_from(&address).unwrap();
+        assert_eq!(settings.mode, SrtMode::Listener);
+        assert_eq!(settings.latency, 200);
+        assert_eq!(
+            settings.uri(),
+            "srt://0.0.0.0:9000?mode=listener&latency=200&passphrase=0123456789"
+        );
+        assert_eq!(
+            settings.client_url().unwrap().as_str(),
+            "srt://0.0.0.0:9000?mode=caller&latency=200&passphrase=0123456789"
+        );
+
+        let address = url::Url::parse("srt://192.168.2.1:9000").unwrap();
+        let settings = SrtSettings::try_from(&address).unwrap();
+        assert_eq!(settings.mode, SrtMode::Caller);
+        assert_eq!(settings.latency, DEFAULT_LATENCY_MS);
+
+        for invalid in [
+            "srt://0.0.0.0:9000?mode=server",
+            "srt://0.0.0.0:9000?passphrase=short",
+            "srt://0.0.0.0:9000?latency=-1",
+            "srt://0.0.0.0",
+            "udp://0.0.0.0:9000",
+        ] {
+            let address = url::Url::parse(invalid).unwrap();
+            assert!(SrtSettings::try_from(&address).is_err(), "{invalid}");
+        }
+    }
+}
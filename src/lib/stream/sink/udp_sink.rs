@@ -1,3 +1,8 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
 use anyhow::{anyhow, Context, Result};
 
 use tracing::*;
@@ -5,7 +10,16 @@ use tracing::*;
 use gst::prelude::*;
 
 use super::SinkInterface;
-use crate::stream::pipeline::runner::PipelineRunner;
+use crate::stream::{
+    pipeline::runner::PipelineRunner,
+    rtp,
+    types::{MulticastConfiguration, RtpConfiguration, UdpSocketConfiguration},
+};
+
+/// The multicast TTL when none is configured, keeping the packets in the local network
+const DEFAULT_MULTICAST_TTL: u8 = 1;
+/// The highest DSCP value, as it takes the 6 upper bits of the IP header's ToS field
+const MAX_DSCP: u8 = 63;
 
 #[derive(Debug)]
 pub struct UdpSink {
@@ -14,10 +28,14 @@ pub struct UdpSink {
     queue: gst::Element,
     proxysink: gst::Element,
     _proxysrc: gst::Element,
-    _udpsink: gst::Element,
+    udpsink: gst::Element,
     udpsink_sink_pad: gst::Pad,
     tee_src_pad: Option<gst::Pad>,
     addresses: Vec<url::Url>,
+    /// How many destinations each packet is sent to, for the bandwidth accounting
+    destinations_count: Arc<AtomicUsize>,
+    rtp: Option<RtpConfiguration>,
+    multicast: Option<MulticastConfiguration>,
     pipeline_runner: PipelineRunner,
 }
 impl SinkInterface for UdpSink {
@@ -215,17 +233,47 @@ impl SinkInterface for UdpSink {
 
     #[instrument(level = "debug", skip(self))]
     fn get_sdp(&self) -> Result<gst_sdp::SDPMessage> {
-        let caps = self
+        let mut caps = self
             .udpsink_sink_pad
             .current_caps()
             .context("Failed to get caps from UDP Sink 'sink' pad")?;
         debug!("Got caps: {caps:#?}");
 
+        // The payloader adds the parameter sets to its caps once it gets them from the encoder, and receivers
+        // can't decode without them
+        if let Some(field) = missing_parameter_sets(&caps) {
+            return Err(anyhow!(
+                "The encoder's parameter sets weren't received yet, missing {field:?}"
+            ));
+        }
+
+        // The packets are rewritten after the caps, so they describe the payload type and SSRC sent
+        if let Some(rtp) = &self.rtp {
+            if let Some(structure) = caps.make_mut().structure_mut(0) {
+                if let Some(payload_type) = rtp.payload_type {
+                    structure.set("payload", payload_type as i32);
+                }
+                if let Some(ssrc) = rtp.ssrc {
+                    structure.set("ssrc", ssrc);
+                }
+            }
+        }
+
         let mut sdp_media = gst_sdp::SDPMedia::new();
         gst_sdp::SDPMediaRef::set_media_from_caps(&caps, &mut sdp_media)?;
 
         let url = self.addresses.first().context("Missing address")?.clone();
-        sdp_media.add_connection("IN", "IP4", url.host_str().context("Missing host")?, 127, 1);
+        let (address_type, host) = match url.host().context("Missing host")? {
+            url::Host::Ipv6(ip) => ("IP6", ip.to_string()),
+            host => ("IP4", host.to_string()),
+        };
+        // The TTL is only meaningful for multicast groups
+        let ttl = self
+            .multicast
+            .as_ref()
+            .and_then(|multicast| multicast.ttl)
+            .unwrap_or(DEFAULT_MULTICAST_TTL);
+        sdp_media.add_connection("IN", address_type, &host, ttl as u32, 1);
         sdp_media.set_port_info(url.port().context("Missing port")? as u32, 1);
         sdp_media.set_proto("RTP/AVP");
 
@@ -281,7 +329,13 @@ impl SinkInterface for UdpSink {
 
 impl UdpSink {
     #[instrument(level = "debug")]
-    pub fn try_new(sink_id: uuid::Uuid, addresses: Vec<url::Url>) -> Result<Self> {
+    pub fn try_new(
+        sink_id: uuid::Uuid,
+        addresses: Vec<url::Url>,
+        rtp: Option<RtpConfiguration>,
+        multicast: Option<MulticastConfiguration>,
+        udp_socket: Option<UdpSocketConfiguration>,
+    ) -> Result<Self> {
         let queue = gst::ElementFactory::make("queue")
             .property_from_str("leaky", "downstream") // Throw away any data
             .property("silent", true)
@@ -319,35 +373,74 @@ impl UdpSink {
             }
         }
 
-        let clients = addresses
+        let addresses = addresses
+            .into_iter()
+            .filter(|address| host_and_port(address).is_ok())
+            .collect::<Vec<url::Url>>();
+        let udpsink = gst::ElementFactory::make("multiudpsink")
+            .property("sync", false)
+            .build()?;
+        // Multicast groups are joined automatically, these only change how the packets are sent to them
+        if let Some(multicast) = &multicast {
+            udpsink.set_property(
+                "ttl-mc",
+                multicast.ttl.unwrap_or(DEFAULT_MULTICAST_TTL) as i32,
+            );
+            udpsink.set_property("loop", multicast.loopback);
+            if let Some(interface) = &multicast.interface {
+                udpsink.set_property("multicast-iface", interface);
+            }
+        }
+        if let Some(udp_socket) = &udp_socket {
+            validate_socket(udp_socket)?;
+
+            if let Some(buffer_size) = udp_socket.buffer_size {
+                udpsink.set_property("buffer-size", buffer_size as i32);
+            }
+            if let Some(dscp) = udp_socket.dscp {
+                udpsink.set_property("qos-dscp", dscp as i32);
+            }
+        }
+        // Clients are added one by one, as the "clients" property can't hold IPv6 literals
+        for (host, port) in addresses
             .iter()
-            .filter_map(|address| {
-                if address.scheme() != "udp" {
-                    return None;
-                }
-                if let (Some(host), Some(port)) = (address.host(), address.port()) {
-                    Some(format!("{host}:{port}"))
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<String>>()
-            .join(",");
-        let description = format!("multiudpsink sync=false clients={clients}");
-        let _udpsink =
-            gst::parse::launch(&description).context("Failed parsing pipeline description")?;
+            .filter_map(|address| host_and_port(address).ok())
+        {
+            udpsink.emit_by_name::<()>("add", &[&host, &port]);
+        }
 
-        let udpsink_sink_pad = _udpsink
+        let udpsink_sink_pad = udpsink
             .static_pad("sink")
             .context("Failed to get Sink Pad")?;
 
+        // The video is shared with other sinks, so its payload type and SSRC are only changed for this one
+        if let Some(rtp) = rtp.clone() {
+            udpsink_sink_pad.add_probe(
+                gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+                move |_pad, info| {
+                    match &mut info.data {
+                        Some(gst::PadProbeData::Buffer(buffer)) => rewrite_rtp_header(buffer, &rtp),
+                        Some(gst::PadProbeData::BufferList(list)) => {
+                            list.make_mut().foreach_mut(|mut buffer, _idx| {
+                                rewrite_rtp_header(&mut buffer, &rtp);
+                                std::ops::ControlFlow::Continue(Some(buffer))
+                            });
+                        }
+                        _ => (),
+                    }
+
+                    gst::PadProbeReturn::Ok
+                },
+            );
+        }
+
         // Create the pipeline
         let pipeline = gst::Pipeline::builder()
             .name(format!("pipeline-sink-{sink_id}"))
             .build();
 
         // Add Sink elements to the Sink's Pipeline
-        let elements = [&_proxysrc, &_udpsink];
+        let elements = [&_proxysrc, &udpsink];
         if let Err(add_err) = pipeline.add_many(elements) {
             return Err(anyhow!(
                 "Failed adding UdpSink's elements to Sink Pipeline: {add_err:?}"
@@ -362,7 +455,8 @@ impl UdpSink {
             return Err(anyhow!("Failed linking UdpSink's elements: {link_err:?}"));
         }
 
-        let pipeline_runner = PipelineRunner::try_new(&pipeline, &sink_id, false)?;
+        let pipeline_runner =
+            PipelineRunner::try_new(&pipeline, &sink_id, false, Default::default(), None)?;
 
         // Start the pipeline
         if let Err(state_err) = pipeline.set_state(gst::State::Playing) {
@@ -377,11 +471,413 @@ impl UdpSink {
             queue,
             proxysink,
             _proxysrc,
-            _udpsink,
+            udpsink,
             udpsink_sink_pad,
+            destinations_count: Arc::new(AtomicUsize::new(addresses.len())),
             addresses,
+            rtp,
+            multicast,
             tee_src_pad: Default::default(),
             pipeline_runner,
         })
     }
+
+    /// The destinations this sink is sending to
+    pub fn destinations(&self) -> &[url::Url] {
+        &self.addresses
+    }
+
+    /// How many destinations this sink is sending to, kept up to date as they are added and removed
+    pub fn destinations_count(&self) -> Arc<AtomicUsize> {
+        self.destinations_count.clone()
+    }
+
+    /// Starts sending to the given destination, without disturbing the others
+    #[instrument(level = "debug", skip(self))]
+    pub fn add_destination(&mut self, address: &url::Url) -> Result<()> {
+        let (host, port) = host_and_port(address)?;
+
+        if self.addresses.contains(address) {
+            return Err(anyhow!("Destination {address} already exists"));
+        }
+
+        self.udpsink.emit_by_name::<()>("add", &[&host, &port]);
+        self.addresses.push(address.clone());
+        self.destinations_count
+            .store(self.addresses.len(), Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Stops sending to the given destination, without disturbing the others
+    #[instrument(level = "debug", skip(self))]
+    pub fn remove_destination(&mut self, address: &url::Url) -> Result<()> {
+        let (host, port) = host_and_port(address)?;
+
+        let Some(idx) = self.addresses.iter().position(|other| other == address) else {
+            return Err(anyhow!("Destination {address} not found"));
+        };
+
+        self.udpsink.emit_by_name::<()>("remove", &[&host, &port]);
+        self.addresses.remove(idx);
+        self.destinations_count
+            .store(self.addresses.len(), Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Resolves all destinations again, so hostnames follow their new addresses after a network change.
+    /// The socket itself is not bound to any interface, so packets already take the new route
+    #[instrument(level = "debug", skip(self))]
+    pub fn reconnect(&self) {
+        self.udpsink.emit_by_name::<()>("clear", &[]);
+
+        for (host, port) in self
+            .addresses
+            .iter()
+            .filter_map(|address| host_and_port(address).ok())
+        {
+            self.udpsink.emit_by_name::<()>("add", &[&host, &port]);
+        }
+    }
+}
+
+/// The first parameter sets field missing from the RTP caps, for encodings that need them out of band
+fn missing_parameter_sets(caps: &gst::CapsRef) -> Option<&'static str> {
+    let structure = caps.structure(0)?;
+
+    let fields: &[&'static str] = match structure.get::<&str>("encoding-name").ok()? {
+        "H264" => &["sprop-parameter-sets"],
+        "H265" => &["sprop-vps", "sprop-sps", "sprop-pps"],
+        _ => &[],
+    };
+
+    fields
+        .iter()
+        .find(|field| !structure.has_field(field))
+        .copied()
+}
+
+fn rewrite_rtp_header(buffer: &mut gst::Buffer, configuration: &RtpConfiguration) {
+    let Ok(mut map) = buffer.make_mut().map_writable() else {
+        warn!("Failed mapping RTP packet to rewrite its header");
+        return;
+    };
+
+    if !rtp::rewrite_header(map.as_mut_slice(), configuration) {
+        trace!("Skipping the rewrite of a packet that isn't RTP");
+    }
+}
+
+/// If the UDP address is a multicast group
+pub fn is_multicast(address: &url::Url) -> bool {
+    match address.host() {
+        Some(url::Host::Ipv4(ip)) => ip.is_multicast(),
+        Some(url::Host::Ipv6(ip)) => ip.is_multicast(),
+        _ => false,
+    }
+}
+
+#[instrument(level = "debug")]
+pub fn validate_multicast(
+    endpoints: &[url::Url],
+    configuration: &MulticastConfiguration,
+) -> Result<()> {
+    if configuration.ttl == Some(0) {
+        return Err(anyhow!("Multicast TTL should be between 1 and 255"));
+    }
+
+    if !endpoints
+        .iter()
+        .any(|endpoint| matches!(endpoint.scheme(), "udp" | "udp265") && is_multicast(endpoint))
+    {
+        return Err(anyhow!(
+            "Multicast is configured, but no UDP endpoint is a multicast group (224.0.0.0/4 or ff00::/8)"
+        ));
+    }
+
+    Ok(())
+}
+
+#[instrument(level = "debug")]
+pub fn validate_socket(configuration: &UdpSocketConfiguration) -> Result<()> {
+    if let Some(buffer_size) = configuration.buffer_size {
+        if buffer_size == 0 || buffer_size > i32::MAX as u32 {
+            return Err(anyhow!(
+                "UDP buffer size should be between 1 and {} bytes",
+                i32::MAX
+            ));
+        }
+    }
+
+    if let Some(dscp) = configuration.dscp {
+        if dscp > MAX_DSCP {
+            return Err(anyhow!(
+                "DSCP should be between 0 and {MAX_DSCP}, got {dscp}"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Extracts the multiudpsink's client host and port from an UDP address
+fn host_and_port(address: &url::Url) -> Result<(String, i32)> {
+    if !matches!(address.scheme(), "udp" | "udp265") {
+        return Err(anyhow!(
+            "Destination {address} should use the udp or udp265 scheme"
+        ));
+    }
+
+    let host = match address
+        .host()
+        .context(format!("Destination {address} without host"))?
+    {
+        // The multiudpsink takes IPv6 literals without their brackets
+        url::Host::Ipv6(ip) => ip.to_string(),
+        host => host.to_string(),
+    };
+    let port = address
+        .port()
+        .context(format!("Destination {address} without port"))?;
+
+    Ok((host, port as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receiver(port: u16) -> (gst::Pipeline, gst_app::AppSink) {
+        let pipeline = gst::parse::launch(&format!(
+            "udpsrc address=127.0.0.1 port={port} ! appsink name=sink sync=false"
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        let appsink = pipeline
+            .by_name("sink")
+            .unwrap()
+            .downcast::<gst_app::AppSink>()
+            .unwrap();
+        pipeline.set_state(gst::State::Playing).unwrap();
+
+        (pipeline, appsink)
+    }
+
+    fn pull_bytes(appsink: &gst_app::AppSink, timeout: gst::ClockTime) -> Option<Vec<u8>> {
+        let sample = appsink.try_pull_sample(timeout)?;
+        let buffer = sample.buffer()?.map_readable().ok()?;
+
+        Some(buffer.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_multiple_destinations() {
+        gst::init().unwrap();
+
+        let (first_port, second_port) = (15600, 15601);
+        let first_address = url::Url::parse(&format!("udp://127.0.0.1:{first_port}")).unwrap();
+        let second_address = url::Url::parse(&format!("udp://127.0.0.1:{second_port}")).unwrap();
+
+        // Receivers are ready before anything is sent, so both get the same first packet
+        let (first_receiver, first_appsink) = receiver(first_port);
+        let (second_receiver, second_appsink) = receiver(second_port);
+
+        let source = gst::parse::launch(concat!(
+            "videotestsrc is-live=true",
+            " ! video/x-raw,width=320,height=240,framerate=30/1",
+            " ! x264enc tune=zerolatency speed-preset=ultrafast",
+            " ! rtph264pay aggregate-mode=zero-latency config-interval=-1 pt=96",
+            " ! tee name=tee allow-not-linked=true",
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        let source_id = uuid::Uuid::new_v4();
+
+        let mut sink = UdpSink::try_new(
+            uuid::Uuid::new_v4(),
+            vec![first_address.clone()],
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        sink.add_destination(&second_address).unwrap();
+        assert!(sink.add_destination(&second_address).is_err());
+
+        let tee_src_pad = source
+            .by_name("tee")
+            .unwrap()
+            .request_pad_simple("src_%u")
+            .unwrap();
+        sink.link(&source, &source_id, tee_src_pad).unwrap();
+        sink.start().unwrap();
+        source.set_state(gst::State::Playing).unwrap();
+
+        // Both receivers get identical RTP
+        let timeout = gst::ClockTime::from_seconds(10);
+        for _ in 0..10 {
+            let first = pull_bytes(&first_appsink, timeout).unwrap();
+            let second = pull_bytes(&second_appsink, timeout).unwrap();
+            assert_eq!(first, second);
+        }
+
+        // Removing one destination doesn't disturb the other
+        sink.remove_destination(&first_address).unwrap();
+        assert_eq!(sink.destinations(), &[second_address]);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        while pull_bytes(&first_appsink, gst::ClockTime::ZERO).is_some() {}
+
+        assert!(pull_bytes(&second_appsink, timeout).is_some());
+        assert!(pull_bytes(&first_appsink, gst::ClockTime::from_mseconds(500)).is_none());
+
+        sink.unlink(&source, &source_id).unwrap();
+        source.set_state(gst::State::Null).unwrap();
+        first_receiver.set_state(gst::State::Null).unwrap();
+        second_receiver.set_state(gst::State::Null).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_destination() {
+        gst::init().unwrap();
+
+        let address = url::Url::parse("udp://[::1]:15602").unwrap();
+        assert_eq!(host_and_port(&address).unwrap(), ("::1".to_string(), 15602));
+
+        let source = gst::parse::launch(concat!(
+            "videotestsrc is-live=true",
+            " ! video/x-raw,width=320,height=240,framerate=30/1",
+            " ! x264enc tune=zerolatency speed-preset=ultrafast",
+            " ! rtph264pay aggregate-mode=zero-latency config-interval=-1 pt=96",
+            " ! tee name=tee allow-not-linked=true",
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        let source_id = uuid::Uuid::new_v4();
+
+        let mut sink = UdpSink::try_new(
+            uuid::Uuid::new_v4(),
+            vec![address.clone()],
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(sink.destinations(), &[address]);
+
+        let tee_src_pad = source
+            .by_name("tee")
+            .unwrap()
+            .request_pad_simple("src_%u")
+            .unwrap();
+        sink.link(&source, &source_id, tee_src_pad).unwrap();
+        sink.start().unwrap();
+        source.set_state(gst::State::Playing).unwrap();
+
+        // The SDP is only available once the caps are negotiated
+        let mut sdp = None;
+        for _ in 0..50 {
+            if let Ok(message) = sink.get_sdp() {
+                sdp = Some(message.as_text().unwrap());
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let sdp = sdp.expect("Caps were never negotiated");
+        assert!(sdp.contains("c=IN IP6 ::1"), "{sdp}");
+        assert!(sdp.contains("m=video 15602 RTP/AVP 96"), "{sdp}");
+        assert!(sdp.contains("a=rtpmap:96 H264/90000"), "{sdp}");
+        // Playable without waiting for a keyframe
+        assert!(sdp.contains("sprop-parameter-sets="), "{sdp}");
+
+        sink.unlink(&source, &source_id).unwrap();
+        source.set_state(gst::State::Null).unwrap();
+    }
+
+    #[test]
+    fn test_validate_multicast() {
+        let endpoints = |address: &str| vec![url::Url::parse(address).unwrap()];
+        let configuration = MulticastConfiguration {
+            ttl: Some(4),
+            ..Default::default()
+        };
+
+        assert!(validate_multicast(&endpoints("udp://239.1.2.3:5600"), &configuration).is_ok());
+        assert!(validate_multicast(&endpoints("udp://[ff02::1]:5600"), &configuration).is_ok());
+        assert!(validate_multicast(&endpoints("udp://192.168.2.1:5600"), &configuration).is_err());
+        assert!(validate_multicast(
+            &endpoints("udp://239.1.2.3:5600"),
+            &MulticastConfiguration {
+                ttl: Some(0),
+                ..Default::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_validate_socket() {
+        let configuration = |buffer_size, dscp| UdpSocketConfiguration { buffer_size, dscp };
+
+        assert!(validate_socket(&configuration(None, None)).is_ok());
+        assert!(validate_socket(&configuration(Some(4 * 1024 * 1024), Some(46))).is_ok());
+        assert!(validate_socket(&configuration(Some(0), None)).is_err());
+        assert!(validate_socket(&configuration(None, Some(64))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rtp_configuration() {
+        gst::init().unwrap();
+
+        let port = 15603;
+        let address = url::Url::parse(&format!("udp://127.0.0.1:{port}")).unwrap();
+        let (receiver, appsink) = receiver(port);
+
+        let source = gst::parse::launch(concat!(
+            "videotestsrc is-live=true",
+            " ! video/x-raw,width=320,height=240,framerate=30/1",
+            " ! x264enc tune=zerolatency speed-preset=ultrafast",
+            " ! rtph264pay aggregate-mode=zero-latency config-interval=-1 pt=96",
+            " ! tee name=tee allow-not-linked=true",
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        let source_id = uuid::Uuid::new_v4();
+
+        let rtp = RtpConfiguration {
+            payload_type: Some(110),
+            ssrc: Some(0x12345678),
+        };
+        let mut sink =
+            UdpSink::try_new(uuid::Uuid::new_v4(), vec![address], Some(rtp), None, None).unwrap();
+
+        let tee_src_pad = source
+            .by_name("tee")
+            .unwrap()
+            .request_pad_simple("src_%u")
+            .unwrap();
+        sink.link(&source, &source_id, tee_src_pad).unwrap();
+        sink.start().unwrap();
+        source.set_state(gst::State::Playing).unwrap();
+
+        // Every packet carries the configured header fields, marker bit aside
+        let timeout = gst::ClockTime::from_seconds(10);
+        for _ in 0..10 {
+            let packet = pull_bytes(&appsink, timeout).unwrap();
+            assert_eq!(packet[1] & 0x7f, 110);
+            assert_eq!(&packet[8..12], &0x12345678u32.to_be_bytes());
+        }
+
+        let sdp = sink.get_sdp().unwrap().as_text().unwrap();
+        assert!(sdp.contains("m=video 15603 RTP/AVP 110"), "{sdp}");
+        assert!(sdp.contains("a=rtpmap:110 H264/90000"), "{sdp}");
+
+        sink.unlink(&source, &source_id).unwrap();
+        source.set_state(gst::State::Null).unwrap();
+        receiver.set_state(gst::State::Null).unwrap();
+    }
 }
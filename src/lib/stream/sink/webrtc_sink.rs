@@ -1,20 +1,37 @@
-use crate::cli;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
 use anyhow::{anyhow, Context, Result};
 use gst::prelude::*;
 use tokio::sync::mpsc::{self, WeakUnboundedSender};
 use tracing::*;
 
 use super::SinkInterface;
+use crate::stream::gst::encoders;
+use crate::stream::pipeline::bitrate_controller::{
+    next_target_bitrate, remb_bitrate, BitrateController, SessionAdaptation,
+};
+use crate::stream::pipeline::layers::{self, WebRTCLayers};
+use crate::stream::types::{
+    H264ProfileConfiguration, IceServer, WebRTCCodec, WebRTCLayerConfiguration,
+};
 use crate::stream::webrtc::signalling_protocol::{
     Answer, BindAnswer, EndSessionQuestion, IceNegotiation, MediaNegotiation, Message, Question,
     RTCIceCandidateInit, RTCSessionDescription, Sdp,
 };
 use crate::stream::webrtc::webrtcbin_interface::WebRTCBinInterface;
+use crate::stream::webrtc::{codecs, ice_servers};
 
 #[derive(Clone)]
 pub struct WebRTCSinkWeakProxy {
     bind: BindAnswer,
     sender: WeakUnboundedSender<Result<Message>>,
+    trickle_ice: bool,
+    h264_profile: Option<H264ProfileConfiguration>,
+    codecs: Vec<WebRTCCodec>,
+    native_codec: Option<WebRTCCodec>,
 }
 
 #[derive(Debug)]
@@ -27,7 +44,138 @@ pub struct WebRTCSink {
     /// MPSC channel's sender to send messages to the respective Websocket from Signaller server. Err can be used to end the WebSocket.
     pub sender: mpsc::UnboundedSender<Result<Message>>,
     pub end_reason: Option<String>,
+    /// Adapts the encoder's bitrate to the bandwidth estimated for this session
+    pub bitrate_controller: Option<Arc<BitrateController>>,
+    /// The latest bitrate estimated by the peer with REMB, in bits per second, or zero until it sends one
+    remb_bitrate: Arc<AtomicU64>,
+    /// The address of the consumer's signalling connection
+    pub remote_address: std::net::SocketAddr,
+    /// When the session was created, in milliseconds since the UNIX epoch
+    pub connected_at: i64,
+    /// If the ICE candidates are sent as they are gathered, instead of inside the SDP offer
+    pub trickle_ice: bool,
+    /// The H264 profile and level the stream is encoded with, advertised in the SDP
+    pub h264_profile: Option<H264ProfileConfiguration>,
+    /// The codecs offered, from the most to the least preferred. When empty, only the native codec is offered
+    pub codecs: Vec<WebRTCCodec>,
+    /// The codec the stream is encoded with, when known
+    pub native_codec: Option<WebRTCCodec>,
+    /// The codec used with the client, once it answered
+    negotiated_codec: Arc<Mutex<Option<WebRTCCodec>>>,
+    /// The branch transcoding the native codec, between the queue and WebRTCBin, when the client picked another one
+    transcoder: Arc<Mutex<Option<gst::Element>>>,
+    /// The lower quality layers this session can be moved to, following its estimated bandwidth
+    webrtc_layers: Option<Arc<WebRTCLayers>>,
+    /// The layer sent to this session, or none for the stream itself
+    layer: Arc<Mutex<Option<usize>>>,
+}
+
+/// The RTCP Receiver Report statistics from the remote peer
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ReceiverReport {
+    packets_lost: i64,
+    fraction_lost: f64,
+    round_trip_time: f64,
+}
+
+/// The bitrate adaptation of a session, stepped by the adaptation task of its pipeline
+#[derive(Debug)]
+struct WebRTCAdaptation {
+    session_id: uuid::Uuid,
+    webrtcbin_weak: gst::glib::WeakRef<gst::Element>,
+    queue_weak: gst::glib::WeakRef<gst::Element>,
+    webrtc_layers: Option<Arc<WebRTCLayers>>,
+    layer: Arc<Mutex<Option<usize>>>,
+    remb_bitrate: Arc<AtomicU64>,
+    target: u64,
+    last_report: Option<ReceiverReport>,
+    last_remb: u64,
+    /// The layer selected by the last estimations, and how many of them in a row
+    layer_candidate: Option<(Option<usize>, usize)>,
+}
+
+impl SessionAdaptation for WebRTCAdaptation {
+    fn webrtcbin(&self) -> Option<gst::Element> {
+        self.webrtcbin_weak.upgrade()
+    }
+
+    fn update(&mut self, bitrate_controller: &BitrateController, stats: &gst::StructureRef) {
+        let session_id = self.session_id;
+        let (min, max) = bitrate_controller.limits();
+
+        // Only react to new feedback, otherwise the same losses would be accounted many times
+        let report = receiver_report(stats).filter(|report| self.last_report != Some(*report));
+        let remb = self.remb_bitrate.load(Ordering::Relaxed);
+        let new_remb = remb != 0 && remb != self.last_remb;
+        if report.is_none() && !new_remb {
+            return;
+        }
+
+        let mut next_target = self.target;
+        if let Some(report) = report {
+            self.last_report = Some(report);
+            next_target = next_target_bitrate(next_target, report.fraction_lost, min, max);
+        }
+        // The peer's estimation caps the target, so it steps down right away when its bandwidth drops
+        if remb != 0 {
+            self.last_remb = remb;
+            next_target = next_target.min(remb).clamp(min, max);
+        }
+
+        if next_target != self.target {
+            debug!(
+                "Session {session_id} target bitrate changed from {} to {next_target} bps. Report: {report:?}, REMB: {remb} bps",
+                self.target
+            );
+            self.target = next_target;
+            bitrate_controller.set_session_target(&session_id, self.target);
+        }
+
+        let Some(webrtc_layers) = &self.webrtc_layers else {
+            return;
+        };
+
+        let current_layer = *self.layer.lock().unwrap();
+        let selected_layer = layers::select(webrtc_layers.configurations(), self.target);
+        if selected_layer == current_layer {
+            self.layer_candidate = None;
+            return;
+        }
+
+        let estimations = match self.layer_candidate {
+            Some((candidate, estimations)) if candidate == selected_layer => estimations + 1,
+            _ => 1,
+        };
+        self.layer_candidate = Some((selected_layer, estimations));
+        if estimations < layers::LAYER_SWITCH_ESTIMATIONS {
+            return;
+        }
+        self.layer_candidate = None;
+
+        let Some(queue_sink_pad) = self
+            .queue_weak
+            .upgrade()
+            .and_then(|queue| queue.static_pad("sink"))
+        else {
+            return;
+        };
+
+        match webrtc_layers.switch(&queue_sink_pad, selected_layer) {
+            Ok(()) => {
+                info!(
+                    "Session {session_id} moved from layer {current_layer:?} to {selected_layer:?}, with a target of {} bps",
+                    self.target
+                );
+                *self.layer.lock().unwrap() = selected_layer;
+                bitrate_controller.set_session_layered(&session_id, selected_layer.is_some());
+            }
+            Err(error) => {
+                warn!("Failed moving session {session_id} to layer {selected_layer:?}: {error:?}");
+            }
+        }
+    }
 }
+
 impl SinkInterface for WebRTCSink {
     #[instrument(level = "debug", skip(self, pipeline))]
     fn link(
@@ -197,16 +345,29 @@ impl SinkInterface for WebRTCSink {
             });
         });
 
+        self.start_bitrate_adaptation()?;
+
         Ok(())
     }
 
     #[instrument(level = "debug", skip(self, pipeline))]
     fn unlink(&self, pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid) -> Result<()> {
-        let Some(tee_src_pad) = &self.tee_src_pad else {
+        // With WebRTC layers, the queue might be linked to another Tee than the one it started with
+        let tee_src_pad = self
+            .queue
+            .static_pad("sink")
+            .and_then(|pad| pad.peer())
+            .or_else(|| self.tee_src_pad.clone());
+        let Some(tee_src_pad) = &tee_src_pad else {
             warn!("Tried to unlink Sink from a pipeline without a Tee src pad.");
             return Ok(());
         };
 
+        // Stop adapting the encoder's bitrate for this session
+        if let Some(bitrate_controller) = &self.bitrate_controller {
+            bitrate_controller.remove_session(&self.get_id());
+        }
+
         // Block data flow to prevent any data from holding the Pipeline elements alive
         if tee_src_pad
             .add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
@@ -240,6 +401,15 @@ impl SinkInterface for WebRTCSink {
             warn!("Failed removing WebRTCBin's elements from pipeline: {remove_err:?}");
         }
 
+        if let Some(transcoder) = self.transcoder.lock().unwrap().take() {
+            if let Err(remove_err) = pipeline.remove(&transcoder) {
+                warn!("Failed removing WebRTC's transcoder from pipeline: {remove_err:?}");
+            }
+            if let Err(state_err) = transcoder.set_state(gst::State::Null) {
+                warn!("Failed stopping WebRTC's transcoder: {state_err:?}");
+            }
+        }
+
         // Instead of setting each element individually to null, we are using a temporary
         // pipeline so we can post and EOS and set the state of the elements to null
         // It is important to send EOS to the queue, otherwise it can hang when setting its state to null.
@@ -295,6 +465,12 @@ impl WebRTCSink {
     pub fn try_new(
         bind: BindAnswer,
         sender: mpsc::UnboundedSender<Result<Message>>,
+        remote_address: std::net::SocketAddr,
+        ice_servers: &[IceServer],
+        trickle_ice: bool,
+        h264_profile: Option<H264ProfileConfiguration>,
+        codecs: Vec<WebRTCCodec>,
+        native_codec: Option<WebRTCCodec>,
     ) -> Result<Self> {
         let queue = gst::ElementFactory::make("queue")
             .property_from_str("leaky", "downstream") // Throw away any data
@@ -303,6 +479,8 @@ impl WebRTCSink {
             .property("max-size-buffers", 0u32) // Disable buffers
             .build()?;
 
+        let (stun_server, turn_servers) = ice_servers::to_webrtcbin(ice_servers);
+
         // Workaround to have a better name for the threads created by the WebRTCBin element
         let webrtcbin = std::thread::Builder::new()
             .name("WebRTCBin".to_string())
@@ -312,21 +490,19 @@ impl WebRTCSink {
                     .property("async-handling", true)
                     .property("bundle-policy", gst_webrtc::WebRTCBundlePolicy::MaxBundle) // https://webrtcstandards.info/sdp-bundle/
                     .property("latency", 0u32)
-                    .property_from_str("stun-server", cli::manager::stun_server_address().as_str())
+                    .property("stun-server", stun_server)
                     .build()
             })
             .expect("Failed spawning WebRTCBin thread")
             .join()
             .map_err(|e| anyhow!("{:?}", e.downcast_ref::<String>()))??;
 
-        cli::manager::turn_server_addresses()
-            .iter()
-            .for_each(|turn_server| {
-                debug!("Trying to add turn server: {turn_server:?}");
-                if !webrtcbin.emit_by_name::<bool>("add-turn-server", &[&turn_server.as_str()]) {
-                    warn!("Failed adding turn server {turn_server:?}");
-                }
-            });
+        turn_servers.iter().for_each(|turn_server| {
+            debug!("Trying to add turn server: {turn_server:?}");
+            if !webrtcbin.emit_by_name::<bool>("add-turn-server", &[&turn_server.as_str()]) {
+                warn!("Failed adding turn server {turn_server:?}");
+            }
+        });
 
         // Configure RTP
         let webrtcbin = webrtcbin.downcast::<gst::Bin>().unwrap();
@@ -340,6 +516,42 @@ impl WebRTCSink {
                 // Use the pipeline clock time. This will ensure that the timestamps from the source are correct.
                 rtp_bin.set_property_from_str("ntp-time-source", "clock-time");
             });
+
+        // Keep the bandwidth estimated by the peer, which browsers send with REMB when offered
+        let remb_estimate: Arc<AtomicU64> = Default::default();
+        if let Some(rtp_bin) = webrtcbin
+            .iterate_elements()
+            .into_iter()
+            .flatten()
+            .find(|element| element.name().starts_with("rtpbin"))
+        {
+            // With the max-bundle policy, all media goes through the first session
+            if let Some(rtp_session) =
+                rtp_bin.emit_by_name::<Option<gst::glib::Object>>("get-internal-session", &[&0u32])
+            {
+                let remb_estimate = remb_estimate.clone();
+                rtp_session.connect("on-feedback-rtcp", false, move |values| {
+                    // Payload-specific feedback (206) of the application layer type (15)
+                    const RTCP_PSFB: u32 = 206;
+                    const RTCP_PSFB_AFB: u32 = 15;
+
+                    let rtcp_type = values.get(1)?.get::<u32>().ok()?;
+                    let feedback_type = values.get(2)?.get::<u32>().ok()?;
+                    if rtcp_type != RTCP_PSFB || feedback_type != RTCP_PSFB_AFB {
+                        return None;
+                    }
+
+                    let fci = values.get(5)?.get::<gst::Buffer>().ok()?;
+                    let fci = fci.map_readable().ok()?;
+                    if let Some(bitrate) = remb_bitrate(fci.as_slice()) {
+                        trace!("REMB received: {bitrate} bps");
+                        remb_estimate.store(bitrate, Ordering::Relaxed);
+                    }
+
+                    None
+                });
+            }
+        }
         let webrtcbin = webrtcbin.upcast::<gst::Element>();
 
         let webrtcbin_sink_pad = webrtcbin
@@ -356,6 +568,18 @@ impl WebRTCSink {
             bind,
             sender,
             end_reason: None,
+            bitrate_controller: None,
+            remb_bitrate: remb_estimate,
+            remote_address,
+            connected_at: chrono::Utc::now().timestamp_millis(),
+            trickle_ice,
+            h264_profile,
+            codecs,
+            native_codec,
+            negotiated_codec: Default::default(),
+            transcoder: Default::default(),
+            webrtc_layers: None,
+            layer: Default::default(),
         };
 
         let (peer_connected_tx, peer_connected_rx) = std::sync::mpsc::channel::<()>();
@@ -422,6 +646,11 @@ impl WebRTCSink {
                     if let Err(error) = peer_connected_tx.send(()) {
                         error!("Failed to disable FailSafeKiller: {error:?}");
                     }
+
+                    // Don't let the new peer wait for the next keyframe to start decoding
+                    if !crate::stream::gst::utils::request_keyframe(webrtcbin) {
+                        debug!("No element upstream handled the keyframe request");
+                    }
                 }
 
                 if let Err(error) = weak_proxy.on_connection_state_change(webrtcbin, &state) {
@@ -459,12 +688,150 @@ impl WebRTCSink {
         WebRTCSinkWeakProxy {
             bind: self.bind.clone(),
             sender: self.sender.downgrade(),
+            trickle_ice: self.trickle_ice,
+            h264_profile: self.h264_profile.clone(),
+            codecs: self.codecs.clone(),
+            native_codec: self.native_codec,
         }
     }
 
+    pub fn set_bitrate_controller(&mut self, bitrate_controller: Arc<BitrateController>) {
+        self.bitrate_controller.replace(bitrate_controller);
+    }
+
+    pub fn set_webrtc_layers(&mut self, webrtc_layers: Arc<WebRTCLayers>) {
+        self.webrtc_layers.replace(webrtc_layers);
+    }
+
+    /// The lower quality layer sent to this session, or none for the stream itself
+    pub fn layer(&self) -> Option<WebRTCLayerConfiguration> {
+        let index = (*self.layer.lock().unwrap())?;
+
+        self.webrtc_layers
+            .as_ref()?
+            .configurations()
+            .get(index)
+            .cloned()
+    }
+
+    /// The current target bitrate for this session, in bits per second
+    pub fn target_bitrate(&self) -> Option<u64> {
+        self.bitrate_controller
+            .as_ref()?
+            .session_target(&self.get_id())
+    }
+
+    /// Adapts the encoder's bitrate to the RTCP feedback from the peer, stepping it down when losses are reported
+    /// or the bandwidth estimated with REMB drops, and slowly back up when they are gone. With WebRTC layers, the
+    /// session is also moved to the layer fitting its target bitrate.
+    /// Note: webrtcbin doesn't expose the transport-cc estimations, so the loss-based part of the Google Congestion
+    /// Control is used, capped by the REMB estimations when the peer sends them.
+    #[instrument(level = "debug", skip(self))]
+    fn start_bitrate_adaptation(&self) -> Result<()> {
+        let Some(bitrate_controller) = &self.bitrate_controller else {
+            return Ok(());
+        };
+
+        let (_min, max) = bitrate_controller.limits();
+        let adaptation = WebRTCAdaptation {
+            session_id: self.get_id(),
+            webrtcbin_weak: self.webrtcbin.downgrade(),
+            queue_weak: self.queue.downgrade(),
+            webrtc_layers: self.webrtc_layers.clone(),
+            layer: self.layer.clone(),
+            remb_bitrate: self.remb_bitrate.clone(),
+            target: max,
+            last_report: None,
+            last_remb: 0,
+            layer_candidate: None,
+        };
+        bitrate_controller.add_session(&self.get_id(), Box::new(adaptation));
+
+        Ok(())
+    }
+
+    /// Ends this session, telling the consumer about it
+    #[instrument(level = "debug", skip(self))]
+    pub fn terminate(&self, reason: String) -> Result<()> {
+        self.downgrade().terminate(reason)
+    }
+
     #[instrument(level = "debug", skip(self))]
     pub fn handle_sdp(&self, sdp: &gst_webrtc::WebRTCSessionDescription) -> Result<()> {
-        self.downgrade().handle_sdp(&self.webrtcbin, sdp)
+        self.downgrade().handle_sdp(&self.webrtcbin, sdp)?;
+
+        if sdp.type_() == gst_webrtc::WebRTCSDPType::Answer {
+            self.on_answer_received(sdp)?;
+        }
+
+        Ok(())
+    }
+
+    /// The codec used with the client, once it answered
+    pub fn negotiated_codec(&self) -> Option<WebRTCCodec> {
+        *self.negotiated_codec.lock().unwrap()
+    }
+
+    /// Finds which of the offered codecs the client picked, transcoding the video when it isn't the native one
+    #[instrument(level = "debug", skip(self, answer))]
+    fn on_answer_received(&self, answer: &gst_webrtc::WebRTCSessionDescription) -> Result<()> {
+        let Some(native_codec) = self.native_codec else {
+            return Ok(());
+        };
+
+        let offered = if self.codecs.is_empty() {
+            vec![native_codec]
+        } else {
+            self.codecs.clone()
+        };
+
+        let (codec, payload_type) = codecs::negotiated(&answer.sdp(), &offered)
+            .context("The client accepted none of the offered codecs")?;
+
+        info!("WebRTC session negotiated {codec:?} with payload type {payload_type}");
+        self.negotiated_codec.lock().unwrap().replace(codec);
+
+        if codec != native_codec {
+            self.start_transcoding(native_codec, payload_type)?;
+        }
+
+        Ok(())
+    }
+
+    /// Inserts a transcoder between the queue and WebRTCBin, once no data is flowing through them
+    #[instrument(level = "debug", skip(self))]
+    fn start_transcoding(&self, native_codec: WebRTCCodec, payload_type: u8) -> Result<()> {
+        let mut transcoder_guard = self.transcoder.lock().unwrap();
+        if transcoder_guard.is_some() {
+            return Ok(());
+        }
+
+        let transcoder = codecs::transcoder(native_codec, payload_type)?;
+        let pipeline = self
+            .queue
+            .parent()
+            .and_then(|parent| parent.downcast::<gst::Bin>().ok())
+            .context("WebRTC's queue is not in a pipeline")?;
+        let queue_src_pad = self
+            .queue
+            .static_pad("src")
+            .context("No src pad found on Queue")?;
+        let webrtcbin_sink_pad = self.webrtcbin_sink_pad.clone();
+
+        transcoder_guard.replace(transcoder.clone());
+        drop(transcoder_guard);
+
+        queue_src_pad.add_probe(gst::PadProbeType::IDLE, move |queue_src_pad, _info| {
+            if let Err(error) =
+                insert_transcoder(&pipeline, queue_src_pad, &transcoder, &webrtcbin_sink_pad)
+            {
+                error!("Failed inserting WebRTC's transcoder: {error:?}");
+            }
+
+            gst::PadProbeReturn::Remove
+        });
+
+        Ok(())
     }
 
     #[instrument(level = "debug", skip(self))]
@@ -475,6 +842,23 @@ impl WebRTCSink {
 }
 
 impl WebRTCSinkWeakProxy {
+    fn send_offer(&self, sdp: String) -> Result<()> {
+        debug!("Sending SDP offer to peer. Offer:\n{sdp}");
+
+        let message = MediaNegotiation {
+            bind: self.bind.clone(),
+            sdp: RTCSessionDescription::Offer(Sdp { sdp }),
+        }
+        .into();
+
+        self.sender
+            .upgrade()
+            .context("Failed accessing MPSC Sender")?
+            .send(Ok(message))?;
+
+        Ok(())
+    }
+
     fn terminate(&self, reason: String) -> Result<()> {
         let Some(sender) = self.sender.upgrade() else {
             return Err(anyhow!("Failed accessing MPSC Sender"));
@@ -547,8 +931,15 @@ impl WebRTCBinInterface for WebRTCSinkWeakProxy {
         offer: &gst_webrtc::WebRTCSessionDescription,
     ) -> Result<()> {
         // Recreate the SDP offer with our customized SDP
-        let offer =
-            gst_webrtc::WebRTCSessionDescription::new(offer.type_(), customize_sdp(&offer.sdp())?);
+        let offer = gst_webrtc::WebRTCSessionDescription::new(
+            offer.type_(),
+            customize_sdp(
+                &codecs::with_remb_feedback(&offer.sdp())?,
+                self.h264_profile.as_ref(),
+                &self.codecs,
+                self.native_codec,
+            )?,
+        );
 
         let Ok(sdp) = offer.sdp().as_text() else {
             return Err(anyhow!("Failed reading the received SDP"));
@@ -557,20 +948,13 @@ impl WebRTCBinInterface for WebRTCSinkWeakProxy {
         // All good, then set local description
         webrtcbin.emit_by_name::<()>("set-local-description", &[&offer, &None::<gst::Promise>]);
 
-        debug!("Sending SDP offer to peer. Offer:\n{sdp}");
-
-        let message = MediaNegotiation {
-            bind: self.bind.clone(),
-            sdp: RTCSessionDescription::Offer(Sdp { sdp }),
+        // Without trickle ICE, the offer is sent once all candidates are in it
+        if !self.trickle_ice {
+            debug!("Holding SDP offer until ICE gathering completes");
+            return Ok(());
         }
-        .into();
 
-        self.sender
-            .upgrade()
-            .context("Failed accessing MPSC Sender")?
-            .send(Ok(message))?;
-
-        Ok(())
+        self.send_offer(sdp)
     }
 
     // Once webrtcbin has create the answer SDP for us, handle it by sending it to the peer via the
@@ -584,7 +968,12 @@ impl WebRTCBinInterface for WebRTCSinkWeakProxy {
         // Recreate the SDP answer with our customized SDP
         let answer = gst_webrtc::WebRTCSessionDescription::new(
             answer.type_(),
-            customize_sdp(&answer.sdp())?,
+            customize_sdp(
+                &answer.sdp(),
+                self.h264_profile.as_ref(),
+                &self.codecs,
+                self.native_codec,
+            )?,
         );
 
         let Ok(sdp) = answer.sdp().as_text() else {
@@ -616,6 +1005,11 @@ impl WebRTCBinInterface for WebRTCSinkWeakProxy {
         sdp_m_line_index: &u32,
         candidate: &str,
     ) -> Result<()> {
+        // Without trickle ICE, the candidates go inside the SDP offer
+        if !self.trickle_ice {
+            return Ok(());
+        }
+
         let message = IceNegotiation {
             bind: self.bind.clone(),
             ice: RTCIceCandidateInit {
@@ -637,14 +1031,27 @@ impl WebRTCBinInterface for WebRTCSinkWeakProxy {
         Ok(())
     }
 
-    #[instrument(level = "debug", skip(self, _webrtcbin))]
+    #[instrument(level = "debug", skip(self, webrtcbin))]
     fn on_ice_gathering_state_change(
         &self,
-        _webrtcbin: &gst::Element,
+        webrtcbin: &gst::Element,
         state: &gst_webrtc::WebRTCICEGatheringState,
     ) -> Result<()> {
         if let gst_webrtc::WebRTCICEGatheringState::Complete = state {
-            debug!("ICE gathering complete")
+            debug!("ICE gathering complete");
+
+            if !self.trickle_ice {
+                // WebRTCBin adds each gathered candidate to the local description
+                let offer = webrtcbin
+                    .property::<Option<gst_webrtc::WebRTCSessionDescription>>("local-description")
+                    .context("ICE gathering completed without a local description")?;
+
+                let Ok(sdp) = offer.sdp().as_text() else {
+                    return Err(anyhow!("Failed reading the local SDP"));
+                };
+
+                self.send_offer(sdp)?;
+            }
         }
 
         Ok(())
@@ -721,10 +1128,69 @@ impl WebRTCBinInterface for WebRTCSinkWeakProxy {
     }
 }
 
+/// Relinks the queue to WebRTCBin through the given transcoder
+fn insert_transcoder(
+    pipeline: &gst::Bin,
+    queue_src_pad: &gst::Pad,
+    transcoder: &gst::Element,
+    webrtcbin_sink_pad: &gst::Pad,
+) -> Result<()> {
+    queue_src_pad.unlink(webrtcbin_sink_pad)?;
+    pipeline.add(transcoder)?;
+
+    let transcoder_sink_pad = transcoder
+        .static_pad("sink")
+        .context("No sink pad found on the transcoder")?;
+    queue_src_pad.link(&transcoder_sink_pad)?;
+
+    let transcoder_src_pad = transcoder
+        .static_pad("src")
+        .context("No src pad found on the transcoder")?;
+    transcoder_src_pad.link(webrtcbin_sink_pad)?;
+
+    transcoder.sync_state_with_parent()?;
+
+    Ok(())
+}
+
+/// Reads the last RTCP Receiver Report from WebRTCBin's statistics
+fn receiver_report(stats: &gst::StructureRef) -> Option<ReceiverReport> {
+    stats.iter().find_map(|(_name, value)| {
+        let stats = value.get::<gst::Structure>().ok()?;
+
+        if stats.get::<gst_webrtc::WebRTCStatsType>("type").ok()?
+            != gst_webrtc::WebRTCStatsType::RemoteInboundRtp
+        {
+            return None;
+        }
+
+        let packets_lost = stats
+            .get::<i64>("packets-lost")
+            .or_else(|_| stats.get::<i32>("packets-lost").map(i64::from))
+            .unwrap_or_default();
+
+        Some(ReceiverReport {
+            packets_lost,
+            fraction_lost: stats.get::<f64>("fraction-lost").ok()?,
+            round_trip_time: stats.get::<f64>("round-trip-time").unwrap_or_default(),
+        })
+    })
+}
+
 /// Because GSTreamer's WebRTCBin often crashes when receiving an invalid SDP,
 /// we use Mozzila's SDP parser to manipulate the SDP Message before giving it to GStreamer
 #[instrument(level = "debug")]
-fn customize_sdp(sdp: &gst_sdp::SDPMessage) -> Result<gst_sdp::SDPMessage> {
+fn customize_sdp(
+    sdp: &gst_sdp::SDPMessage,
+    h264_profile: Option<&H264ProfileConfiguration>,
+    codecs: &[WebRTCCodec],
+    native_codec: Option<WebRTCCodec>,
+) -> Result<gst_sdp::SDPMessage> {
+    let mut sdp = sdp.to_owned();
+    if let Some(native_codec) = native_codec.filter(|_| !codecs.is_empty()) {
+        codecs::apply_preferences(&mut sdp, codecs, native_codec)?;
+    }
+
     let mut sdp = webrtc_sdp::parse_sdp(sdp.as_text()?.as_str(), false)?;
 
     for media in sdp.media.iter_mut() {
@@ -739,10 +1205,19 @@ fn customize_sdp(sdp: &gst_sdp::SDPMessage) -> Result<gst_sdp::SDPMessage> {
                     debug!("Removed unsupported/unwanted attribute: {attribute:?}");
                 }
                 // Customize FMTP
-                // Here we are lying to the peer about our profile-level-id (to constrained-baseline) so any browser can accept it
                 Fmtp(mut fmtp) => {
-                    const CONSTRAINED_BASELINE_LEVEL_ID: u32 = 0x42e01f;
-                    fmtp.parameters.profile_level_id = CONSTRAINED_BASELINE_LEVEL_ID;
+                    fmtp.parameters.profile_level_id = match h264_profile {
+                        // The encoder was constrained to the configured profile, so it can be told as it is
+                        Some(h264_profile) => encoders::h264_profile_level_id(
+                            h264_profile,
+                            fmtp.parameters.profile_level_id,
+                        ),
+                        // Here we are lying to the peer about our profile-level-id (to constrained-baseline) so any browser can accept it
+                        None => {
+                            const CONSTRAINED_BASELINE_LEVEL_ID: u32 = 0x42e01f;
+                            CONSTRAINED_BASELINE_LEVEL_ID
+                        }
+                    };
                     fmtp.parameters.level_asymmetry_allowed = true;
 
                     let attribute = webrtc_sdp::attribute_type::SdpAttribute::Fmtp(fmtp);
@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use tracing::*;
+
+use crate::cli;
+
+use super::manager;
+
+/// Height of the cached thumbnails, the width follows the stream's aspect ratio
+const THUMBNAIL_HEIGHT: u32 = 240;
+const THUMBNAIL_QUALITY: u8 = 70;
+
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    pub image: Vec<u8>,
+    pub captured_at: Instant,
+}
+
+lazy_static! {
+    static ref THUMBNAILS: Arc<Mutex<HashMap<uuid::Uuid, Thumbnail>>> = Default::default();
+}
+
+/// The last thumbnail captured from the given stream, which can be old if the stream is down
+pub fn get(stream_id: &uuid::Uuid) -> Option<Thumbnail> {
+    THUMBNAILS.lock().unwrap().get(stream_id).cloned()
+}
+
+/// Starts capturing a thumbnail of each running stream periodically, unless disabled by the user
+#[instrument(level = "debug")]
+pub fn start() {
+    let Some(interval) = cli::manager::thumbnails_interval() else {
+        debug!("Thumbnails disabled");
+        return;
+    };
+
+    tokio::spawn(thumbnails_task(interval));
+}
+
+#[instrument(level = "debug")]
+async fn thumbnails_task(interval: Duration) {
+    debug!("Thumbnails task started");
+
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        let streams = match manager::streams().await {
+            Ok(streams) => streams,
+            Err(error) => {
+                warn!("Failed getting the streams for their thumbnails: {error:?}");
+                continue;
+            }
+        };
+
+        // Thumbnails of removed streams are no longer served
+        THUMBNAILS
+            .lock()
+            .unwrap()
+            .retain(|stream_id, _| streams.iter().any(|stream| stream.id == *stream_id));
+
+        // Streams are captured one at a time, spreading the load. Streams not running keep their last thumbnail
+        for stream in streams.iter().filter(|stream| stream.running) {
+            match capture(&stream.id).await {
+                Ok(image) => {
+                    THUMBNAILS.lock().unwrap().insert(
+                        stream.id,
+                        Thumbnail {
+                            image,
+                            captured_at: Instant::now(),
+                        },
+                    );
+                }
+                Err(error) => debug!(
+                    "Failed capturing a thumbnail of stream {:?}: {error:?}",
+                    stream.id
+                ),
+            }
+        }
+    }
+}
+
+/// Takes a snapshot from the running stream and scales it down
+#[instrument(level = "debug")]
+async fn capture(stream_id: &uuid::Uuid) -> Result<Vec<u8>> {
+    let snapshot = manager::snapshot(stream_id).await?;
+
+    // Decoding and encoding are CPU bound, so do it outside of the async runtime's threads
+    tokio::task::spawn_blocking(move || downscale(&snapshot, THUMBNAIL_HEIGHT, THUMBNAIL_QUALITY))
+        .await?
+}
+
+fn downscale(jpeg: &[u8], height: u32, quality: u8) -> Result<Vec<u8>> {
+    let image = image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg)
+        .map_err(|error| anyhow!("Failed decoding the snapshot: {error}"))?;
+
+    // Never upscaled, and the width is derived from the aspect ratio
+    let image = if image.height() > height {
+        image.thumbnail(u32::MAX, height)
+    } else {
+        image
+    };
+
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    image
+        .into_rgb8()
+        .write_to(&mut buffer, image::ImageOutputFormat::Jpeg(quality))
+        .map_err(|error| anyhow!("Failed encoding the thumbnail: {error}"))?;
+
+    Ok(buffer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downscale() {
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(960, 720)
+            .write_to(&mut jpeg, image::ImageOutputFormat::Jpeg(90))
+            .unwrap();
+
+        let thumbnail = downscale(jpeg.get_ref(), THUMBNAIL_HEIGHT, THUMBNAIL_QUALITY).unwrap();
+        let thumbnail = image::load_from_memory(&thumbnail).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (320, 240));
+    }
+}
@@ -3,11 +3,13 @@ use crate::{
     video_stream::types::VideoAndStreamInformation,
 };
 
+use std::collections::BTreeMap;
+
 use paperclip::actix::Apiv2Schema;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct VideoCaptureConfiguration {
     pub encode: VideoEncodeType,
     pub height: u32,
@@ -29,6 +31,551 @@ pub enum CaptureConfiguration {
 pub struct ExtendedConfiguration {
     pub thermal: bool,
     pub disable_mavlink: bool,
+    #[serde(default)]
+    pub pipeline_runner: Option<PipelineRunnerConfiguration>,
+    /// How long RTP packets are held to be reordered and have their timing smoothed, in milliseconds, clamped
+    /// to 0..=2000 (default: 0). Higher values absorb more network jitter at the cost of a larger end-to-end
+    /// delay. It is applied to the jitterbuffer of Redirect sources and to the RTSP server's media, while the
+    /// jitterbuffer of UDP receivers is configured on the receiver side. Low latency streams cap it to 50.
+    #[serde(default)]
+    pub latency_ms: Option<u32>,
+    /// The maximum distance between keyframes, in frames (1..=1000), for streams encoded by this service.
+    /// When missing, each encoder's default is kept.
+    #[serde(default)]
+    pub keyframe_interval: Option<u32>,
+    /// If the stream should be recreated when the service starts (default: true). Streams not auto-started
+    /// are kept in the settings, but not running.
+    #[serde(default)]
+    pub auto_start: Option<bool>,
+    /// If the stream is running (default: true). Disabled streams release their device and ports, but are kept in
+    /// the settings and listed, until enabled again.
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    /// How the encoder distributes its bitrate, for streams encoded by this service. When missing, each encoder's
+    /// default is kept.
+    #[serde(default)]
+    pub rate_control: Option<RateControlConfiguration>,
+    /// The MAVLink camera mode, which gates the image and video capture commands (default: video)
+    #[serde(default)]
+    pub camera_mode: Option<CameraMode>,
+    /// Text burned into the video, only available for raw video pipelines
+    #[serde(default)]
+    pub overlay: Option<OverlayConfiguration>,
+    /// Audio captured alongside the video, muxed into the recordings and the RTSP endpoints. When missing, the
+    /// stream is video only
+    #[serde(default)]
+    pub audio: Option<AudioConfiguration>,
+    /// The maximum number of WebRTC and RTSP clients watching this stream at the same time. When missing, any
+    /// number of clients is accepted
+    #[serde(default)]
+    pub max_clients: Option<u32>,
+    /// The STUN and TURN servers of this stream's WebRTC sessions, overriding the global ones
+    #[serde(default)]
+    pub ice_servers: Option<Vec<IceServer>>,
+    /// The H264 profile and level, for H264 streams encoded by this service. When missing, constrained-baseline
+    /// is used, with the level picked by the encoder
+    #[serde(default)]
+    pub h264_profile: Option<H264ProfileConfiguration>,
+    /// How the stream recovers when the host's network changes, like on a DHCP renew or a WiFi roam. When
+    /// missing, nothing is done
+    #[serde(default)]
+    pub reconnection: Option<ReconnectionConfiguration>,
+    /// The resolution streamed, scaled from the captured one, only available for raw video pipelines. When
+    /// missing, the captured resolution is streamed
+    #[serde(default)]
+    pub output_resolution: Option<OutputResolutionConfiguration>,
+    /// The highest framerate streamed, in frames per second, dropping the captured frames above it without changing
+    /// the camera's format. Only available for raw video pipelines, and can't exceed the capture framerate. When
+    /// missing, the captured framerate is streamed
+    #[serde(default)]
+    pub max_framerate: Option<u32>,
+    /// How much of the encoded video is kept in memory, in seconds (0..=30), so clips include the moments before
+    /// being requested (default: 0)
+    #[serde(default)]
+    pub clip_pre_roll_secs: Option<u32>,
+    /// The RTP payload type and SSRC of the UDP and RTSP endpoints, for receivers expecting specific values. When
+    /// missing, the payload type is 96 and the SSRC is random
+    #[serde(default)]
+    pub rtp: Option<RtpConfiguration>,
+    /// How the UDP endpoints send to multicast groups (224.0.0.0/4 or ff00::/8). When missing, multicast is sent
+    /// with a TTL of 1, looped back to the host, through the system's default interface
+    #[serde(default)]
+    pub multicast: Option<MulticastConfiguration>,
+    /// The socket options of the UDP endpoints. When missing, the system's defaults are used
+    #[serde(default)]
+    pub udp_socket: Option<UdpSocketConfiguration>,
+    /// The codecs offered to WebRTC clients, from the most to the least preferred. Besides the stream's own
+    /// encoding, VP8 can be listed to be transcoded in software for clients that can't decode it. When missing,
+    /// only the stream's own encoding is offered
+    #[serde(default)]
+    pub webrtc_codecs: Option<Vec<WebRTCCodec>>,
+    /// Lower quality versions of the stream, encoded in parallel, to be sent to the WebRTC clients whose estimated
+    /// bandwidth doesn't fit the stream itself. Only available for H264 streams. When missing, every client gets
+    /// the stream itself
+    #[serde(default)]
+    pub webrtc_layers: Option<Vec<WebRTCLayerConfiguration>>,
+    /// If the nearest format the source captures is used when it can't capture the configured one, instead of
+    /// failing (default: false). Which one is the nearest follows the negotiation priority. Only local devices
+    /// report their formats
+    #[serde(default)]
+    pub nearest_format: Option<bool>,
+    /// What is kept from the configured format when the nearest one is used instead (default: prefer-format)
+    #[serde(default)]
+    pub negotiation_priority: Option<NegotiationPriority>,
+    /// Opens the local device in this format, shared with the other streams of the same device with this same
+    /// capture, each one streaming it as its own configuration says, decoding and encoding it again when they
+    /// differ. When missing, the stream opens the device by itself, exclusively
+    #[serde(default)]
+    pub shared_capture: Option<VideoCaptureConfiguration>,
+    /// Properties set on the pipeline's elements once it is built, by element, like
+    /// {"x264enc": {"speed-preset": "superfast"}}. Elements are matched by their factory name or by their own
+    /// name, and the values are parsed as the property's type, like in a gst-launch description
+    #[serde(default)]
+    pub element_properties: Option<BTreeMap<String, BTreeMap<String, String>>>,
+    /// Tunes the whole pipeline for the lowest glass-to-glass latency (default: false): the encoders skip the
+    /// lookahead and the B-frames and send keyframes more often, the jitterbuffer latency is capped and the sinks
+    /// don't wait for the clock, including the ones added while running. Useful for teleoperation, at the cost of
+    /// some quality for the same bitrate
+    #[serde(default)]
+    pub low_latency: Option<bool>,
+    /// How the queues after the source and after the encoder behave when the next elements can't keep up. By
+    /// default they hold a few frames and drop the oldest ones, keeping the latency bounded while a sink stalls
+    #[serde(default)]
+    pub queues: Option<QueueConfiguration>,
+    /// The clock driving the pipeline and its base time, so pipelines using the same clock and base time, even on
+    /// different hosts, timestamp their frames on a shared timeline. When missing, GStreamer picks the clock and the
+    /// base time is set when the pipeline starts
+    #[serde(default)]
+    pub clock: Option<ClockConfiguration>,
+    /// The focal length of the camera's lens, in millimeters, advertised to the ground stations in the MAVLink
+    /// camera information. When missing, it is advertised as unknown
+    #[serde(default)]
+    pub focal_length_mm: Option<f32>,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NegotiationPriority {
+    /// Keeps the configured encode, preferring the same or a lower resolution, and then the same or a lower
+    /// framerate
+    #[default]
+    PreferFormat,
+    /// Prefers the same or a lower framerate, and then the same or a lower resolution, from any encode, with the
+    /// compressed ones preferred over the raw ones, as they take less of the USB bandwidth. Changing the encode
+    /// changes the streamed one as well
+    PreferFramerate,
+    /// Prefers the same or a lower resolution, and then the same or a lower framerate, from any encode, with the
+    /// compressed ones preferred over the raw ones, as they take less of the USB bandwidth. Changing the encode
+    /// changes the streamed one as well
+    PreferResolution,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WebRTCLayerConfiguration {
+    pub width: u32,
+    pub height: u32,
+    /// The bitrate of this layer, in bits per second. Clients whose estimated bandwidth is above the highest
+    /// layer's bitrate get the stream itself
+    pub bitrate: u32,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum WebRTCCodec {
+    H264,
+    H265,
+    VP8,
+}
+
+impl WebRTCCodec {
+    /// The RTP encoding name, like "H264"
+    pub fn encoding_name(&self) -> &'static str {
+        match self {
+            WebRTCCodec::H264 => "H264",
+            WebRTCCodec::H265 => "H265",
+            WebRTCCodec::VP8 => "VP8",
+        }
+    }
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UdpSocketConfiguration {
+    /// The size of the socket's send buffer, in bytes. When missing, the system's default is used
+    #[serde(default)]
+    pub buffer_size: Option<u32>,
+    /// The DSCP value marking the packets, between 0 and 63, like 46 (Expedited Forwarding) or 34 (AF41) for
+    /// video. When missing, the packets are not marked
+    #[serde(default)]
+    pub dscp: Option<u8>,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MulticastConfiguration {
+    /// How many routers the packets can cross (1..=255, default: 1), where 1 keeps them in the local network
+    #[serde(default)]
+    pub ttl: Option<u8>,
+    /// If the packets are also received by the host itself
+    #[serde(default)]
+    pub loopback: bool,
+    /// The network interface sending the packets, like "eth0". When missing, the system's default is used
+    #[serde(default)]
+    pub interface: Option<String>,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RtpConfiguration {
+    /// The payload type of the video, in the dynamic range (96..=127)
+    #[serde(default)]
+    pub payload_type: Option<u8>,
+    /// The synchronization source identifier of the video
+    #[serde(default)]
+    pub ssrc: Option<u32>,
+}
+
+/// A user accepted by the RTSP server, through basic or digest authentication
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RtspCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScalingMode {
+    /// Keeps the aspect ratio, filling the rest of the frame with black borders
+    #[default]
+    Letterbox,
+    /// Fills the whole frame, distorting the image when the aspect ratios differ
+    Stretch,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OutputResolutionConfiguration {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub scaling: ScalingMode,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReconnectionConfiguration {
+    /// If the UDP destinations should be resolved again and the RTSP clients disconnected (so they reconnect
+    /// through the new route) whenever an address of the host's network interfaces changes
+    pub enabled: bool,
+    /// If a keyframe should be forced after reconnecting, so receivers don't wait for the next one to recover
+    #[serde(default)]
+    pub force_keyframe: bool,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum H264Profile {
+    /// Decodable by any H264 decoder, like the ones of browsers and older hardware
+    ConstrainedBaseline,
+    Baseline,
+    Main,
+    High,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct H264ProfileConfiguration {
+    pub profile: H264Profile,
+    /// The H264 level, from "1" to "5.2", like "3.1" or "4.1". When missing, the encoder picks it
+    pub level: Option<String>,
+}
+
+/// A STUN or TURN server, just like the browser's RTCIceServer
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IceServer {
+    /// The server URLs, like "stun:stun.example.com:3478" or "turn:turn.example.com:3478?transport=tcp"
+    pub urls: Vec<String>,
+    /// Required by TURN servers
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Required by TURN servers
+    #[serde(default)]
+    pub credential: Option<String>,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioBackend {
+    #[default]
+    Alsa,
+    Pulse,
+}
+
+/// An audio capture device, as found by GStreamer's device providers
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AudioDevice {
+    pub name: String,
+    pub backend: AudioBackend,
+    /// The value for the `device` of the [`AudioConfiguration`]
+    pub device: String,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AudioStatus {
+    pub audio: Option<AudioConfiguration>,
+    /// If the audio is being captured, which is not the case when its device is absent
+    pub active: bool,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationStep {
+    /// The source exists and is available
+    Source,
+    /// The stream configuration, like its endpoints and encoder settings
+    Configuration,
+    /// The stream doesn't use the same source or endpoints of an existing one
+    Conflicts,
+    /// The source captures the requested encode, size and frame interval
+    Format,
+    /// The ports the stream listens on are free
+    Ports,
+    /// The pipeline can be built, its elements opened, and its caps negotiated with the source
+    Pipeline,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValidationCheck {
+    pub step: ValidationStep,
+    pub success: bool,
+    /// Why the step failed
+    pub message: Option<String>,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValidationReport {
+    /// If the stream would be created
+    pub valid: bool,
+    /// The steps run, in order. The ones depending on the source are skipped when it isn't available
+    pub checks: Vec<ValidationCheck>,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PadDirection {
+    Src,
+    Sink,
+    Unknown,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PadCaps {
+    pub pad: String,
+    pub direction: PadDirection,
+    /// The negotiated caps, missing while the pad hasn't negotiated any
+    pub caps: Option<String>,
+    /// The pad linked to this one, like "h264parse0:sink"
+    pub peer: Option<String>,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ElementCaps {
+    pub element: String,
+    /// The factory that created the element, like "x264enc"
+    pub factory: Option<String>,
+    pub pads: Vec<PadCaps>,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioEncodeType {
+    #[default]
+    Opus,
+    Aac,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AudioConfiguration {
+    #[serde(default)]
+    pub backend: AudioBackend,
+    /// The capture device, like "hw:1,0" for ALSA, or the source name for PulseAudio. When missing, the backend's
+    /// default device is used
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub encode: AudioEncodeType,
+    /// Encoder bitrate, in kbit/s (16..=512, default: 64)
+    #[serde(default)]
+    pub bitrate: Option<u32>,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OverlayConfiguration {
+    pub enabled: bool,
+    /// The text drawn, where "{time}", "{date}", "{depth}", "{heading}", "{altitude}" and "{groundspeed}" are
+    /// replaced by the current time and the vehicle's MAVLink telemetry
+    pub template: String,
+    /// Pango font description, like "Sans Bold 18" (default: "Sans 18")
+    #[serde(default)]
+    pub font: Option<String>,
+    #[serde(default)]
+    pub position: OverlayPosition,
+}
+
+impl Default for OverlayConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            template: "{date} {time}".to_string(),
+            font: None,
+            position: Default::default(),
+        }
+    }
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CameraMode {
+    /// Accepts image capture commands
+    Image,
+    /// Accepts video capture commands
+    #[default]
+    Video,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateControlMode {
+    /// Constant bitrate
+    Cbr,
+    /// Variable bitrate, optionally capped by a maximum bitrate
+    Vbr,
+    /// Constant quantizer, ignoring the bitrate
+    Cqp,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RateControlConfiguration {
+    pub mode: RateControlMode,
+    /// Target bitrate, in kbit/s (100..=100000), required by CBR and VBR
+    pub bitrate: Option<u32>,
+    /// Maximum bitrate for VBR, in kbit/s (100..=100000)
+    pub max_bitrate: Option<u32>,
+    /// Quantizer, from 0 (best) to 51 (worst), required by CQP. Encoders in a quality based VBR use it as well
+    pub quality: Option<u32>,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueLeakiness {
+    /// Blocks the upstream elements while full, so frames are never dropped but the latency grows
+    No,
+    /// Drops the new frames while full
+    Upstream,
+    /// Drops the oldest frames while full
+    #[default]
+    Downstream,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct QueueConfiguration {
+    /// Which frames are dropped when the queue is full (default: downstream)
+    #[serde(default)]
+    pub leaky: Option<QueueLeakiness>,
+    /// Maximum frames held, 1..=1000 (default: 3)
+    #[serde(default)]
+    pub max_size_buffers: Option<u32>,
+    /// Maximum time held, in milliseconds, 1..=10000 (default: 200)
+    #[serde(default)]
+    pub max_size_time_ms: Option<u32>,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClockSource {
+    /// The monotonic clock of this host
+    #[default]
+    System,
+    /// An NTP server
+    Ntp,
+    /// The PTP grandmaster of a domain (IEEE 1588)
+    Ptp,
+    /// A GStreamer network time provider, like the clock of another pipeline published with GstNetTimeProvider
+    Net,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClockConfiguration {
+    #[serde(default)]
+    pub source: ClockSource,
+    /// The address of the NTP server or of the network time provider
+    #[serde(default)]
+    pub address: Option<String>,
+    /// The port of the NTP server (default: 123) or of the network time provider (required)
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// The PTP domain (default: 0)
+    #[serde(default)]
+    pub domain: Option<u8>,
+    /// The base time of the pipeline, in milliseconds of the clock (default: 0). The running time of the frames is
+    /// the clock time minus the base time, so pipelines with the same clock and base time share their timeline
+    #[serde(default)]
+    pub base_time_ms: Option<u64>,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClockStatus {
+    pub clock: Option<ClockConfiguration>,
+    /// The name of the clock used by the pipeline, if it is running
+    pub name: Option<String>,
+    /// If the clock is synchronized to its source, which local clocks always are
+    pub synced: bool,
+    /// The current time of the clock, in nanoseconds
+    pub time_ns: Option<u64>,
+    /// The base time of the pipeline, in nanoseconds of the clock
+    pub base_time_ns: Option<u64>,
+    /// If the pipeline is live, in which case it has a latency
+    pub live: bool,
+    /// The latency of the pipeline, from the capture to the sinks, in milliseconds
+    pub latency_ms: Option<u64>,
+}
+
+/// The maximum jitterbuffer latency accepted, in milliseconds
+pub const MAX_LATENCY_MS: u32 = 2000;
+/// The maximum jitterbuffer latency of low latency streams, in milliseconds
+pub const LOW_LATENCY_MAX_LATENCY_MS: u32 = 50;
+
+/// Overrides for the PipelineRunner's watchdog. Any missing value falls back to its default.
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Deserialize, Serialize, Default)]
+pub struct PipelineRunnerConfiguration {
+    /// Number of consecutive checks with an unchanged position before restarting the pipeline, 1..=10000
+    /// (default: 30)
+    pub max_lost_timestamps: Option<u32>,
+    /// Interval between each position check, in milliseconds, 10..=10000 (default: 100)
+    pub poll_interval_ms: Option<u64>,
+    /// Maximum time waiting for the start command before checking the pipeline again, in milliseconds, 10..=10000
+    /// (default: 100)
+    pub bus_poll_timeout_ms: Option<u64>,
+    /// Delay before the first restart attempt of a stopped pipeline, doubled on each consecutive one, in
+    /// milliseconds, 1..=60000 (default: 200)
+    pub restart_backoff_initial_ms: Option<u64>,
+    /// Maximum delay between consecutive restart attempts of a stopped pipeline, in milliseconds, 1..=600000, not
+    /// below the initial delay (default: 5000)
+    pub restart_backoff_max_ms: Option<u64>,
+    /// Time a restarted pipeline needs to keep running for the next restart to wait the initial delay again, in
+    /// milliseconds, 1..=3600000 (default: 10000)
+    pub restart_backoff_reset_after_ms: Option<u64>,
+    /// Maximum time waiting for an EOS to drain through the pipeline when it's stopped, in milliseconds, 1..=60000
+    /// (default: 3000)
+    pub eos_timeout_ms: Option<u64>,
+    /// Maximum time without a watchdog check before the pipeline is considered stuck and rebuilt, in milliseconds,
+    /// 1..=600000, above the poll interval (default: 10000)
+    pub heartbeat_timeout_ms: Option<u64>,
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize, Apiv2Schema)]
@@ -38,9 +585,358 @@ pub struct StreamInformation {
     pub extended_configuration: Option<ExtendedConfiguration>,
 }
 
+impl StreamInformation {
+    /// The jitterbuffer latency configured for this stream, in milliseconds
+    pub fn latency_ms(&self) -> u32 {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.latency_ms)
+            .unwrap_or_default()
+            .min(if self.low_latency() {
+                LOW_LATENCY_MAX_LATENCY_MS
+            } else {
+                MAX_LATENCY_MS
+            })
+    }
+
+    /// The overrides for the PipelineRunner's watchdog
+    pub fn pipeline_runner(&self) -> Option<&PipelineRunnerConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.pipeline_runner.as_ref())
+    }
+
+    /// How the queues after the source and after the encoder behave
+    pub fn queues(&self) -> Option<&QueueConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.queues.as_ref())
+    }
+
+    /// The clock configured for this stream
+    pub fn clock(&self) -> Option<&ClockConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.clock.as_ref())
+    }
+
+    /// The focal length of the camera's lens, in millimeters, when known
+    pub fn focal_length_mm(&self) -> Option<f32> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.focal_length_mm)
+    }
+
+    /// If the whole pipeline is tuned for the lowest latency
+    pub fn low_latency(&self) -> bool {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.low_latency)
+            .unwrap_or(false)
+    }
+
+    /// If the stream should be recreated when the service starts
+    pub fn auto_start(&self) -> bool {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.auto_start)
+            .unwrap_or(true)
+    }
+
+    /// If the stream wasn't disabled
+    pub fn enabled(&self) -> bool {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.enabled)
+            .unwrap_or(true)
+    }
+
+    /// The properties set on the pipeline's elements
+    pub fn element_properties(&self) -> Option<&BTreeMap<String, BTreeMap<String, String>>> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.element_properties.as_ref())
+    }
+
+    /// The capture shared with the other streams of the same device, if any
+    pub fn shared_capture(&self) -> Option<&VideoCaptureConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.shared_capture.as_ref())
+    }
+
+    /// If the nearest format captured by the source can replace the configured one
+    pub fn nearest_format(&self) -> bool {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.nearest_format)
+            .unwrap_or(false)
+    }
+
+    /// What is kept from the configured format when the nearest one is used instead
+    pub fn negotiation_priority(&self) -> NegotiationPriority {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.negotiation_priority)
+            .unwrap_or_default()
+    }
+
+    /// The MAVLink camera mode configured for this stream
+    pub fn camera_mode(&self) -> CameraMode {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.camera_mode)
+            .unwrap_or_default()
+    }
+
+    /// The text overlay configured for this stream
+    pub fn overlay(&self) -> Option<&OverlayConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.overlay.as_ref())
+    }
+
+    /// The STUN and TURN servers configured for this stream
+    pub fn ice_servers(&self) -> Option<&[IceServer]> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.ice_servers.as_deref())
+    }
+
+    /// The maximum number of clients configured for this stream
+    pub fn max_clients(&self) -> Option<u32> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.max_clients)
+    }
+
+    /// The audio configured for this stream
+    pub fn audio(&self) -> Option<&AudioConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.audio.as_ref())
+    }
+
+    /// The H264 profile and level configured for this stream
+    pub fn h264_profile(&self) -> Option<&H264ProfileConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.h264_profile.as_ref())
+    }
+
+    /// The seconds of video kept before a clip is requested
+    pub fn clip_pre_roll_secs(&self) -> u32 {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.clip_pre_roll_secs)
+            .unwrap_or_default()
+    }
+
+    /// The socket options configured for this stream's UDP endpoints
+    pub fn udp_socket(&self) -> Option<&UdpSocketConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.udp_socket.as_ref())
+    }
+
+    /// The codecs offered to WebRTC clients configured for this stream
+    pub fn webrtc_codecs(&self) -> Option<&[WebRTCCodec]> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.webrtc_codecs.as_deref())
+    }
+
+    /// The lower quality layers for WebRTC clients configured for this stream
+    pub fn webrtc_layers(&self) -> Option<&[WebRTCLayerConfiguration]> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.webrtc_layers.as_deref())
+    }
+
+    /// The multicast options configured for this stream's UDP endpoints
+    pub fn multicast(&self) -> Option<&MulticastConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.multicast.as_ref())
+    }
+
+    /// The RTP payload type and SSRC configured for this stream's UDP and RTSP endpoints
+    pub fn rtp(&self) -> Option<&RtpConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.rtp.as_ref())
+    }
+
+    /// The output resolution configured for this stream
+    pub fn output_resolution(&self) -> Option<&OutputResolutionConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.output_resolution.as_ref())
+    }
+
+    /// The maximum framerate configured for this stream
+    pub fn max_framerate(&self) -> Option<u32> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.max_framerate)
+    }
+
+    /// The reconnection policy configured for this stream, only when enabled
+    pub fn reconnection(&self) -> Option<&ReconnectionConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.reconnection.as_ref())
+            .filter(|reconnection| reconnection.enabled)
+    }
+
+    /// The rate control configured for this stream
+    pub fn rate_control(&self) -> Option<&RateControlConfiguration> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.rate_control.as_ref())
+    }
+
+    /// The keyframe interval configured for this stream, in frames
+    pub fn keyframe_interval(&self) -> Option<u32> {
+        self.extended_configuration
+            .as_ref()
+            .and_then(|configuration| configuration.keyframe_interval)
+    }
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingFormat {
+    #[default]
+    Mp4,
+    Mkv,
+}
+
+impl RecordingFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordingFormat::Mp4 => "mp4",
+            RecordingFormat::Mkv => "mkv",
+        }
+    }
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RecordingSettings {
+    /// Directory in which the recording files will be created
+    pub path: String,
+    #[serde(default)]
+    pub format: RecordingFormat,
+    /// Starts a new file when the current one exceeds this size, in bytes
+    pub max_size_bytes: Option<u64>,
+    /// Starts a new file when the current one exceeds this duration, in seconds
+    pub max_duration_secs: Option<u64>,
+}
+
 #[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
 pub struct StreamStatus {
     pub id: uuid::Uuid,
     pub running: bool,
     pub video_and_stream: VideoAndStreamInformation,
+    #[serde(default)]
+    pub restart_count: usize,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    /// The URLs that SRT clients should use to connect to this stream
+    #[serde(default)]
+    pub srt_urls: Vec<Url>,
+    /// How many WebRTC and RTSP clients are watching this stream
+    #[serde(default)]
+    pub clients: usize,
+    #[serde(default)]
+    pub max_clients: Option<u32>,
+    /// The ready to use URLs of this stream, for each of the host's addresses
+    #[serde(default)]
+    pub endpoints: Vec<EndpointDescriptor>,
+    /// When the pipeline's watchdog last ticked, in milliseconds since the UNIX epoch. A stale value means the
+    /// pipeline is stuck, and it will be rebuilt
+    #[serde(default)]
+    pub last_heartbeat: Option<i64>,
+    /// If the stream was disabled, keeping only its configuration
+    #[serde(default)]
+    pub disabled: bool,
+    /// The format the stream switched to when created, as the source couldn't capture the configured one
+    #[serde(default)]
+    pub format_fallback: Option<String>,
+    /// Frames dropped by the running pipeline for not keeping up, by the element that reported them in its QoS
+    /// messages. Frequent drops usually mean the stream should use a lower resolution or framerate
+    #[serde(default)]
+    pub dropped_frames: BTreeMap<String, u64>,
+    /// CPU time spent by the streaming threads of the running pipeline, in seconds. It is a lower bound, as the
+    /// threads created by some encoders themselves aren't accounted
+    #[serde(default)]
+    pub cpu_seconds: f64,
+    /// Memory held by the queues of the running pipeline, in bytes
+    #[serde(default)]
+    pub queued_bytes: u64,
+    /// The MAVLink component id the stream's camera is advertised with, from MAV_COMP_ID_CAMERA (100) to
+    /// MAV_COMP_ID_CAMERA6 (105). None when MAVLink is disabled for the stream, or all of them are in use
+    #[serde(default)]
+    pub mavlink_component_id: Option<u8>,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EndpointProtocol {
+    Rtsp,
+    Udp,
+    Srt,
+    WebRTC,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EndpointDescriptor {
+    pub protocol: EndpointProtocol,
+    /// For WebRTC, the URL of the signalling server
+    pub url: Url,
+    /// Like "H264", when known
+    pub codec: Option<String>,
+    /// For WebRTC, the producer to start a session with through the signalling server
+    pub producer_id: Option<uuid::Uuid>,
+    /// For UDP, if the URL is a multicast group, to be joined by the receivers
+    #[serde(default)]
+    pub multicast: bool,
+    /// For RTSP, if the server asks for a username and password, which are not part of the URL
+    #[serde(default)]
+    pub requires_credentials: bool,
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize, Serialize)]
+pub struct WebRTCSessionStatus {
+    pub producer_id: uuid::Uuid,
+    pub consumer_id: uuid::Uuid,
+    pub session_id: uuid::Uuid,
+    /// The bitrate currently targeted for this session's bandwidth, in bits per second
+    pub target_bitrate: Option<u64>,
+    /// The codec negotiated with the client, like "H264", once it answered
+    pub codec: Option<String>,
+    /// The lower quality layer sent to the client, or none when it gets the stream itself
+    pub layer: Option<WebRTCLayerConfiguration>,
+}
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientProtocol {
+    WebRTC,
+    Rtsp,
+}
+
+/// A client watching a stream
+#[derive(Apiv2Schema, Clone, Debug, Deserialize, Serialize)]
+pub struct ClientStatus {
+    /// The WebRTC session id, or the RTSP client id
+    pub id: uuid::Uuid,
+    pub protocol: ClientProtocol,
+    pub remote_address: Option<String>,
+    /// When the client connected, in milliseconds since the UNIX epoch
+    pub connected_at: i64,
+    /// The RTP encoding sent to the client, like "H264"
+    pub codec: Option<String>,
+    /// The bitrate estimated for the client's bandwidth, in bits per second. Only available for WebRTC clients
+    pub estimated_bitrate: Option<u64>,
 }
@@ -0,0 +1,284 @@
+use anyhow::{anyhow, Context, Result};
+use gst::prelude::*;
+use tracing::*;
+
+use crate::{
+    network::utils::unspecified_address,
+    video::{
+        types::VideoSourceType,
+        video_source::{self, VideoSource},
+    },
+    video_stream::types::VideoAndStreamInformation,
+};
+
+use super::{
+    formats,
+    manager::{self, Manager},
+    pipeline::{PipelineState, PIPELINE_FILTER_NAME},
+    rtsp::{
+        rtsp_scheme::RTSPScheme,
+        rtsp_server::{RTSPServer, RTSP_SERVER_PORT},
+    },
+    sink::srt_sink::{SrtMode, SrtSettings},
+    types::{StreamInformation, ValidationCheck, ValidationReport, ValidationStep},
+};
+
+/// Runs the checks of the stream creation without creating it: the pipeline is built and its elements opened,
+/// but it is torn down before anything streams
+#[instrument(level = "debug", skip(stream_information))]
+pub async fn validate(
+    name: String,
+    tags: Vec<String>,
+    source: &str,
+    stream_information: StreamInformation,
+) -> ValidationReport {
+    let mut checks = Vec::new();
+
+    let video_source = record(
+        &mut checks,
+        ValidationStep::Source,
+        video_source::get_video_source(source)
+            .map_err(anyhow::Error::from)
+            .and_then(|video_source| {
+                if !video_source.inner().is_valid() {
+                    return Err(anyhow!("Source {source:?} is not available"));
+                }
+
+                Ok(video_source)
+            }),
+    );
+
+    if let Some(video_source) = video_source {
+        let mut video_and_stream_information = VideoAndStreamInformation {
+            name,
+            tags,
+            stream_information,
+            video_source,
+        };
+
+        record(
+            &mut checks,
+            ValidationStep::Configuration,
+            super::validate_configuration(&video_and_stream_information),
+        );
+        record(
+            &mut checks,
+            ValidationStep::Conflicts,
+            manager::check_conflicts(&video_and_stream_information).await,
+        );
+        record(
+            &mut checks,
+            ValidationStep::Format,
+            formats::negotiate(&mut video_and_stream_information),
+        );
+        record(
+            &mut checks,
+            ValidationStep::Ports,
+            validate_ports(&video_and_stream_information),
+        );
+        record(
+            &mut checks,
+            ValidationStep::Pipeline,
+            validate_pipeline(&video_and_stream_information),
+        );
+    }
+
+    ValidationReport {
+        valid: checks.iter().all(|check| check.success),
+        checks,
+    }
+}
+
+fn record<T>(
+    checks: &mut Vec<ValidationCheck>,
+    step: ValidationStep,
+    result: Result<T>,
+) -> Option<T> {
+    match result {
+        Ok(value) => {
+            checks.push(ValidationCheck {
+                step,
+                success: true,
+                message: None,
+            });
+
+            Some(value)
+        }
+        Err(error) => {
+            debug!("Validation step {step:?} failed: {error:#}");
+
+            checks.push(ValidationCheck {
+                step,
+                success: false,
+                message: Some(format!("{error:#}")),
+            });
+
+            None
+        }
+    }
+}
+
+/// Checks that the ports the stream would listen on can be bound
+fn validate_ports(video_and_stream_information: &VideoAndStreamInformation) -> Result<()> {
+    // Redirect endpoints belong to someone else
+    if matches!(
+        video_and_stream_information.video_source,
+        VideoSourceType::Redirect(_)
+    ) {
+        return Ok(());
+    }
+
+    for endpoint in &video_and_stream_information.stream_information.endpoints {
+        if RTSPScheme::try_from(endpoint.scheme()).is_ok() {
+            // The RTSP server is shared by all streams, so its port is only expected to be free before it starts
+            if !RTSPServer::is_running() {
+                std::net::TcpListener::bind((unspecified_address(), RTSP_SERVER_PORT))
+                    .with_context(|| format!("RTSP port {RTSP_SERVER_PORT} is not available"))?;
+            }
+        } else if endpoint.scheme() == "srt" {
+            let settings = SrtSettings::try_from(endpoint)?;
+
+            // Callers connect from an ephemeral port
+            if !matches!(settings.mode, SrtMode::Caller) {
+                std::net::UdpSocket::bind(("0.0.0.0", settings.port))
+                    .with_context(|| format!("SRT port {} is not available", settings.port))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the pipeline and opens its elements, checking the source can produce the caps requested, then
+/// tears it down
+fn validate_pipeline(video_and_stream_information: &VideoAndStreamInformation) -> Result<()> {
+    // A throwaway id, so its elements don't clash with the ones of the running pipelines
+    let pipeline_id = Manager::generate_uuid();
+
+    let pipeline = PipelineState::build(video_and_stream_information, &pipeline_id)?;
+
+    let result = probe_pipeline(&pipeline, &pipeline_id);
+
+    if let Err(error) = pipeline.set_state(gst::State::Null) {
+        warn!("Failed tearing down the validation pipeline {pipeline_id}: {error:?}");
+    }
+
+    result
+}
+
+fn probe_pipeline(pipeline: &gst::Pipeline, pipeline_id: &uuid::Uuid) -> Result<()> {
+    // Opening the elements reaches the devices, while nothing streams until PLAYING
+    pipeline
+        .set_state(gst::State::Ready)
+        .context("Failed opening the pipeline elements")?;
+
+    let Some(filter) = pipeline.by_name(&format!("{PIPELINE_FILTER_NAME}-{pipeline_id}")) else {
+        return Ok(());
+    };
+
+    let caps = filter.property::<gst::Caps>("caps");
+    let pad = filter
+        .static_pad("sink")
+        .context("No static sink pad found on capsfilter")?;
+
+    if pad.peer_query_caps(Some(&caps)).is_empty() {
+        return Err(anyhow!(
+            "The source can't produce {caps}, it offers: {}",
+            pad.peer_query_caps(None)
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::stream::types::{
+        CaptureConfiguration, ExtendedConfiguration, VideoCaptureConfiguration,
+    };
+    use crate::video::types::{FrameInterval, VideoEncodeType};
+
+    fn stream_information(encode: VideoEncodeType, endpoint: &str) -> StreamInformation {
+        StreamInformation {
+            endpoints: vec![url::Url::parse(endpoint).unwrap()],
+            configuration: CaptureConfiguration::Video(VideoCaptureConfiguration {
+                encode,
+                height: 240,
+                width: 320,
+                frame_interval: FrameInterval {
+                    numerator: 1,
+                    denominator: 30,
+                },
+            }),
+            extended_configuration: Some(ExtendedConfiguration {
+                disable_mavlink: true,
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        gst::init().unwrap();
+
+        let steps = |report: &ValidationReport| {
+            report
+                .checks
+                .iter()
+                .map(|check| (check.step, check.success))
+                .collect::<Vec<_>>()
+        };
+
+        let report = validate(
+            "test".into(),
+            vec![],
+            "ball",
+            stream_information(VideoEncodeType::Yuyv, "udp://127.0.0.1:5600"),
+        )
+        .await;
+        assert_eq!(
+            steps(&report),
+            vec![
+                (ValidationStep::Source, true),
+                (ValidationStep::Configuration, true),
+                (ValidationStep::Conflicts, true),
+                (ValidationStep::Format, true),
+                (ValidationStep::Ports, true),
+                (ValidationStep::Pipeline, true),
+            ]
+        );
+        assert!(report.valid);
+
+        // Nothing else is checked without a source
+        let report = validate(
+            "test".into(),
+            vec![],
+            "not-a-source",
+            stream_information(VideoEncodeType::Yuyv, "udp://127.0.0.1:5600"),
+        )
+        .await;
+        assert_eq!(steps(&report), vec![(ValidationStep::Source, false)]);
+        assert!(!report.valid);
+
+        // A busy SRT listener port
+        let socket = std::net::UdpSocket::bind(("0.0.0.0", 0)).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let report = validate(
+            "test".into(),
+            vec![],
+            "ball",
+            stream_information(
+                VideoEncodeType::Yuyv,
+                &format!("srt://0.0.0.0:{port}?mode=listener"),
+            ),
+        )
+        .await;
+        assert!(!report.valid);
+        assert!(report
+            .checks
+            .iter()
+            .any(|check| check.step == ValidationStep::Ports && !check.success));
+    }
+}
@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Context, Result};
+use gst::prelude::*;
+use tracing::*;
+
+use crate::{
+    stream::types::{CaptureConfiguration, StreamInformation, WebRTCCodec},
+    video::types::VideoEncodeType,
+};
+
+/// The bitrate of the VP8 transcoding, in bits per second, as the encoder's bitrate adaptation only reaches the
+/// stream's own encoder
+const TRANSCODING_BITRATE: u32 = 2_000_000;
+/// The RTCP feedback of the added codecs, so the clients can ask for keyframes
+const RTCP_FEEDBACKS: &[&str] = &["nack", "nack pli", "ccm fir"];
+/// The RTCP feedback the clients send their estimated bandwidth with
+const REMB_FEEDBACK: &str = "goog-remb";
+
+/// The codec the given stream is encoded with, when it can be sent to WebRTC clients as it is
+#[instrument(level = "debug", skip(stream_information))]
+pub fn native(stream_information: &StreamInformation) -> Option<WebRTCCodec> {
+    match &stream_information.configuration {
+        CaptureConfiguration::Video(configuration) => match configuration.encode {
+            VideoEncodeType::H264 => Some(WebRTCCodec::H264),
+            VideoEncodeType::H265 => Some(WebRTCCodec::H265),
+            _ => None,
+        },
+        CaptureConfiguration::Redirect(_) => None,
+    }
+}
+
+#[instrument(level = "debug")]
+pub fn validate(codecs: &[WebRTCCodec], native: Option<WebRTCCodec>) -> Result<()> {
+    if codecs.is_empty() {
+        return Err(anyhow!("WebRTC codecs should have at least one codec"));
+    }
+
+    let native = native.context("WebRTC codecs can only be chosen for H264 and H265 streams")?;
+
+    for (index, codec) in codecs.iter().enumerate() {
+        if codecs[..index].contains(codec) {
+            return Err(anyhow!("WebRTC codec {codec:?} is repeated"));
+        }
+
+        // Only VP8 is transcoded, any other codec should be the stream's own
+        if *codec != native && *codec != WebRTCCodec::VP8 {
+            return Err(anyhow!(
+                "WebRTC codec {codec:?} is not available for a {native:?} stream, only {native:?} and VP8 are"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Rewrites the video medias of our SDP offer to list the given codecs in their order of preference. The ones
+/// other than the native codec are added, to be transcoded if the client picks them, while the native codec is
+/// removed when not listed
+#[instrument(level = "debug", skip(sdp))]
+pub fn apply_preferences(
+    sdp: &mut gst_sdp::SDPMessageRef,
+    codecs: &[WebRTCCodec],
+    native: WebRTCCodec,
+) -> Result<()> {
+    for media_index in 0..sdp.medias_len() {
+        let media = sdp
+            .media_mut(media_index)
+            .context("Failed accessing SDP media")?;
+        if media.media() != Some("video") {
+            continue;
+        }
+
+        let formats = rtpmaps(media);
+        let native_payload_type = formats
+            .iter()
+            .find(|(_, encoding_name)| encoding_name.eq_ignore_ascii_case(native.encoding_name()))
+            .map(|(payload_type, _)| *payload_type)
+            .context(format!("SDP offer without {native:?}"))?;
+
+        let mut used_payload_types = formats
+            .iter()
+            .map(|(payload_type, _)| *payload_type)
+            .collect::<Vec<u8>>();
+        let payload_types = codecs
+            .iter()
+            .map(|codec| {
+                if *codec == native {
+                    return Ok(native_payload_type);
+                }
+
+                let payload_type = (crate::stream::rtp::DEFAULT_PAYLOAD_TYPE..=127)
+                    .find(|payload_type| !used_payload_types.contains(payload_type))
+                    .context("No dynamic RTP payload type left")?;
+                used_payload_types.push(payload_type);
+
+                media.add_attribute(
+                    "rtpmap",
+                    Some(&format!("{payload_type} {}/90000", codec.encoding_name())),
+                );
+                for feedback in RTCP_FEEDBACKS {
+                    media.add_attribute("rtcp-fb", Some(&format!("{payload_type} {feedback}")));
+                }
+
+                Ok(payload_type)
+            })
+            .collect::<Result<Vec<u8>>>()?;
+
+        if !codecs.contains(&native) {
+            remove_payload_type_attributes(media, native_payload_type);
+        }
+
+        // The other formats, like retransmissions, go after the video codecs
+        let others = (0..media.formats_len())
+            .filter_map(|index| media.format(index).map(str::to_string))
+            .filter(|format| {
+                format
+                    .parse::<u8>()
+                    .map_or(true, |payload_type| payload_type != native_payload_type)
+            })
+            .collect::<Vec<String>>();
+
+        while media.formats_len() > 0 {
+            media.remove_format(0)?;
+        }
+        for format in payload_types
+            .iter()
+            .map(u8::to_string)
+            .chain(others.into_iter())
+        {
+            media.add_format(&format);
+        }
+    }
+
+    Ok(())
+}
+
+/// Our SDP offer with the REMB feedback added to every video codec, so the clients send the bandwidth they estimate
+#[instrument(level = "debug", skip(sdp))]
+pub fn with_remb_feedback(sdp: &gst_sdp::SDPMessageRef) -> Result<gst_sdp::SDPMessage> {
+    let mut sdp = sdp.to_owned();
+
+    for media_index in 0..sdp.medias_len() {
+        let media = sdp
+            .media_mut(media_index)
+            .context("Failed accessing SDP media")?;
+        if media.media() != Some("video") {
+            continue;
+        }
+
+        for (payload_type, _) in rtpmaps(media) {
+            let feedback = format!("{payload_type} {REMB_FEEDBACK}");
+            let exists = media.attributes().any(|attribute| {
+                attribute.key() == "rtcp-fb" && attribute.value() == Some(feedback.as_str())
+            });
+            if !exists {
+                media.add_attribute("rtcp-fb", Some(&feedback));
+            }
+        }
+    }
+
+    Ok(sdp)
+}
+
+/// The codec used to send the video to the client, from its SDP answer: the most preferred of ours it accepted,
+/// with its RTP payload type
+#[instrument(level = "debug", skip(sdp))]
+pub fn negotiated(
+    sdp: &gst_sdp::SDPMessageRef,
+    codecs: &[WebRTCCodec],
+) -> Option<(WebRTCCodec, u8)> {
+    let media = sdp
+        .medias()
+        .find(|media| media.media() == Some("video") && media.port() != 0)?;
+
+    let accepted = (0..media.formats_len())
+        .filter_map(|index| media.format(index)?.parse::<u8>().ok())
+        .collect::<Vec<u8>>();
+    let formats = rtpmaps(media)
+        .into_iter()
+        .filter(|(payload_type, _)| accepted.contains(payload_type))
+        .collect::<Vec<(u8, String)>>();
+
+    codecs.iter().find_map(|codec| {
+        formats
+            .iter()
+            .find(|(_, encoding_name)| encoding_name.eq_ignore_ascii_case(codec.encoding_name()))
+            .map(|(payload_type, _)| (*codec, *payload_type))
+    })
+}
+
+/// The branch transcoding the native RTP video into VP8 RTP video, with the given payload type
+#[instrument(level = "debug")]
+pub fn transcoder(native: WebRTCCodec, payload_type: u8) -> Result<gst::Element> {
+    let depayloader = match native {
+        WebRTCCodec::H264 => "rtph264depay ! h264parse",
+        WebRTCCodec::H265 => "rtph265depay ! h265parse",
+        WebRTCCodec::VP8 => return Err(anyhow!("VP8 is not transcoded from itself")),
+    };
+
+    let description = format!(
+        concat!(
+            "{depayloader}",
+            " ! decodebin",
+            " ! videoconvert",
+            " ! vp8enc deadline=1 cpu-used=8 end-usage=cbr target-bitrate={bitrate} error-resilient=partitions keyframe-max-dist=60",
+            " ! rtpvp8pay picture-id-mode=15-bit pt={payload_type}",
+        ),
+        depayloader = depayloader,
+        bitrate = TRANSCODING_BITRATE,
+        payload_type = payload_type,
+    );
+
+    debug!("WebRTC transcoder description: {description:#?}");
+
+    Ok(gst::parse::bin_from_description(&description, true)?.upcast::<gst::Element>())
+}
+
+/// The RTP payload types of a SDP media and their encoding names, like (96, "H264")
+fn rtpmaps(media: &gst_sdp::SDPMediaRef) -> Vec<(u8, String)> {
+    media
+        .attributes()
+        .filter(|attribute| attribute.key() == "rtpmap")
+        .filter_map(|attribute| {
+            let (payload_type, encoding) = attribute.value()?.split_once(' ')?;
+            let encoding_name = encoding.split('/').next()?;
+
+            Some((payload_type.parse().ok()?, encoding_name.to_string()))
+        })
+        .collect()
+}
+
+/// Removes the rtpmap, fmtp and rtcp-fb attributes of the given payload type
+fn remove_payload_type_attributes(media: &mut gst_sdp::SDPMediaRef, payload_type: u8) {
+    let prefix = format!("{payload_type} ");
+
+    for index in (0..media.attributes_len()).rev() {
+        let Some(attribute) = media.attribute(index) else {
+            continue;
+        };
+
+        if matches!(attribute.key(), "rtpmap" | "fmtp" | "rtcp-fb")
+            && attribute
+                .value()
+                .is_some_and(|value| value.starts_with(&prefix))
+        {
+            let _ = media.remove_attribute(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFER: &str = concat!(
+        "v=0\r\n",
+        "o=- 0 0 IN IP4 0.0.0.0\r\n",
+        "s=-\r\n",
+        "t=0 0\r\n",
+        "m=video 9 UDP/TLS/RTP/SAVPF 96\r\n",
+        "c=IN IP4 0.0.0.0\r\n",
+        "a=rtpmap:96 H264/90000\r\n",
+        "a=fmtp:96 packetization-mode=1;profile-level-id=42e01f\r\n",
+        "a=rtcp-fb:96 nack pli\r\n",
+    );
+
+    fn sdp(text: &str) -> gst_sdp::SDPMessage {
+        gst_sdp::SDPMessage::parse_buffer(text.as_bytes()).unwrap()
+    }
+
+    fn formats(sdp: &gst_sdp::SDPMessageRef) -> Vec<String> {
+        let media = sdp.media(0).unwrap();
+        (0..media.formats_len())
+            .filter_map(|index| media.format(index).map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn test_with_remb_feedback() {
+        let offer = with_remb_feedback(&sdp(OFFER)).unwrap();
+        let text = offer.as_text().unwrap();
+        assert!(text.contains("a=rtcp-fb:96 goog-remb\r\n"));
+        assert!(text.contains("a=rtcp-fb:96 nack pli\r\n"));
+
+        // Not repeated
+        let again = with_remb_feedback(&offer).unwrap();
+        assert_eq!(again.as_text().unwrap().matches("goog-remb").count(), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        use WebRTCCodec::*;
+
+        assert!(validate(&[H264, VP8], Some(H264)).is_ok());
+        assert!(validate(&[VP8], Some(H265)).is_ok());
+        assert!(validate(&[], Some(H264)).is_err());
+        assert!(validate(&[H264], None).is_err());
+        assert!(validate(&[H265], Some(H264)).is_err());
+        assert!(validate(&[H264, H264], Some(H264)).is_err());
+    }
+
+    #[test]
+    fn test_apply_preferences() {
+        use WebRTCCodec::*;
+
+        let mut offer = sdp(OFFER);
+        apply_preferences(&mut offer, &[VP8, H264], H264).unwrap();
+        assert_eq!(formats(&offer), vec!["97", "96"]);
+        assert_eq!(negotiated(&offer, &[VP8, H264]), Some((VP8, 97)));
+
+        // The native codec goes away when not listed
+        let mut offer = sdp(OFFER);
+        apply_preferences(&mut offer, &[VP8], H264).unwrap();
+        assert_eq!(formats(&offer), vec!["97"]);
+        assert_eq!(negotiated(&offer, &[H264, VP8]), Some((VP8, 97)));
+        assert!(!offer.as_text().unwrap().contains("H264"));
+    }
+
+    #[test]
+    fn test_negotiated() {
+        use WebRTCCodec::*;
+
+        // A client without VP8 answers with H264 only
+        let answer = sdp(OFFER);
+        assert_eq!(negotiated(&answer, &[VP8, H264]), Some((H264, 96)));
+        assert_eq!(negotiated(&answer, &[VP8]), None);
+    }
+}
@@ -113,7 +113,16 @@ export class Manager {
           stream,
           signaller,
           bundlePolicy,
-          iceServers,
+          [
+            ...iceServers,
+            ...stream.ice_servers.map(
+              (server): RTCIceServer => ({
+                urls: server.urls,
+                username: server.username ?? undefined,
+                credential: server.credential ?? undefined,
+              })
+            ),
+          ],
           allowedIps,
           allowedProtocols,
           jitterBufferTarget,
@@ -113,6 +113,20 @@ export class Signaller {
       "message",
       function session_start_listener(ev: MessageEvent): void {
         try {
+          const rejection = signaller.parseSessionStartRejectedAnswer(
+            ev,
+            consumer_id,
+            producer_id
+          );
+          if (rejection !== undefined) {
+            signaller.ws.removeEventListener("message", session_start_listener);
+
+            const error_msg = `Session rejected. Reason: ${rejection}`;
+            console.warn(error_msg);
+            on_status_changed?.(error_msg);
+            return;
+          }
+
           const session_id = signaller.parseSessionStartAnswer(ev);
           if (session_id === undefined) {
             return;
@@ -139,6 +153,7 @@ export class Signaller {
         content: {
           consumer_id,
           producer_id,
+          trickle_ice: true,
         },
       },
     };
@@ -229,6 +244,31 @@ export class Signaller {
     return answer.content.session_id;
   }
 
+  parseSessionStartRejectedAnswer(
+    ev: MessageEvent,
+    consumer_id: string,
+    producer_id: string
+  ): string | undefined {
+    const message: Message = JSON.parse(ev.data);
+    if (message.type !== "answer") {
+      return;
+    }
+
+    const answer: Answer = message.content;
+    if (answer.type !== "startSessionRejected") {
+      return;
+    }
+
+    if (
+      answer.content.consumer_id !== consumer_id ||
+      answer.content.producer_id !== producer_id
+    ) {
+      return;
+    }
+
+    return answer.content.reason;
+  }
+
   public parseEndSessionQuestion(
     consumer_id: string,
     producer_id: string,
@@ -1,3 +1,5 @@
+pub mod codecs;
+pub mod ice_servers;
 pub mod signalling_protocol;
 pub mod signalling_server;
 pub mod turn_server;
@@ -23,10 +23,21 @@ pub enum Message {
 pub enum Question {
     PeerId,
     AvailableStreams,
-    StartSession(BindOffer),
+    StartSession(StartSessionQuestion),
     EndSession(EndSessionQuestion),
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+pub struct StartSessionQuestion {
+    #[serde(flatten)]
+    pub bind: BindOffer,
+    /// If ICE candidates should be sent one by one as they are gathered. When false, the SDP offer is only
+    /// sent once gathering completes, carrying all candidates. Defaults to true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub trickle_ice: Option<bool>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, TS)]
 pub struct BindOffer {
     /// each tab in the browser
@@ -60,6 +71,15 @@ pub enum Answer {
     PeerId(PeerIdAnswer),
     AvailableStreams(Vec<Stream>),
     StartSession(BindAnswer),
+    /// The session wasn't started, like when the stream already has its maximum number of clients
+    StartSessionRejected(StartSessionRejectedAnswer),
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct StartSessionRejectedAnswer {
+    #[serde(flatten)]
+    pub bind: BindOffer,
+    pub reason: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, TS)]
@@ -77,6 +97,17 @@ pub struct Stream {
     pub interval: Option<String>,
     pub source: Option<String>,
     pub created: Option<String>,
+    /// The STUN and TURN servers to be used by the sessions of this stream
+    #[serde(default)]
+    pub ice_servers: Vec<IceServer>,
+}
+
+/// A STUN or TURN server, just like the browser's RTCIceServer
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    pub username: Option<String>,
+    pub credential: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, TS)]
@@ -186,3 +217,35 @@ impl From<Question> for Message {
         Message::Question(question)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_session_trickle_ice() {
+        let consumer_id = Uuid::new_v4();
+        let producer_id = Uuid::new_v4();
+
+        // Consumers unaware of the option keep working, and trickle by default
+        let message: Message = serde_json::from_str(&format!(
+            r#"{{"type":"question","content":{{"type":"startSession","content":{{"consumer_id":"{consumer_id}","producer_id":"{producer_id}"}}}}}}"#
+        ))
+        .unwrap();
+        let Message::Question(Question::StartSession(question)) = message else {
+            panic!("Expected a StartSession question");
+        };
+        assert_eq!(question.bind.consumer_id, consumer_id);
+        assert_eq!(question.bind.producer_id, producer_id);
+        assert_eq!(question.trickle_ice, None);
+
+        let message: Message = serde_json::from_str(&format!(
+            r#"{{"type":"question","content":{{"type":"startSession","content":{{"consumer_id":"{consumer_id}","producer_id":"{producer_id}","trickle_ice":false}}}}}}"#
+        ))
+        .unwrap();
+        let Message::Question(Question::StartSession(question)) = message else {
+            panic!("Expected a StartSession question");
+        };
+        assert_eq!(question.trickle_ice, Some(false));
+    }
+}
@@ -10,7 +10,10 @@ use tokio::sync::mpsc;
 
 use tracing::*;
 
-use super::signalling_protocol::{self, *};
+use super::{
+    ice_servers,
+    signalling_protocol::{self, *},
+};
 
 #[derive(Debug)]
 pub struct SignallingServer {
@@ -77,17 +80,35 @@ impl SignallingServer {
         while let Ok((stream, address)) = listener.accept().await {
             info!("Accepting connection from {address:?}");
 
-            tokio::spawn(Self::accept_connection(stream));
+            tokio::spawn(Self::accept_connection(stream, address));
         }
 
         Ok(())
     }
 
     #[instrument(level = "debug", skip(stream))]
-    async fn accept_connection(stream: TcpStream) {
+    async fn accept_connection(stream: TcpStream, address: SocketAddr) {
         debug!("Accepting connection...");
 
-        let stream = match async_tungstenite::tokio::accept_async(stream).await {
+        // When no stream can take another client, there is no point in accepting the consumer
+        let all_streams_full = stream::manager::all_streams_full().await;
+        let callback =
+            move |_request: &tungstenite::handshake::server::Request,
+                  response: tungstenite::handshake::server::Response| {
+                if !all_streams_full {
+                    return Ok(response);
+                }
+
+                warn!("Rejecting signalling connection from {address:?}: all streams are full");
+
+                let mut response = tungstenite::handshake::server::ErrorResponse::new(Some(
+                    "All streams reached their maximum number of clients".to_string(),
+                ));
+                *response.status_mut() = tungstenite::http::StatusCode::TOO_MANY_REQUESTS;
+                Err(response)
+            };
+
+        let stream = match async_tungstenite::tokio::accept_hdr_async(stream, callback).await {
             Ok(stream) => stream,
             Err(error) => {
                 error!("Failed to accept websocket connection: {error:?}");
@@ -95,13 +116,16 @@ impl SignallingServer {
             }
         };
 
-        if let Err(error) = Self::handle_connection(stream).await {
+        if let Err(error) = Self::handle_connection(stream, address).await {
             error!("Error processing connection: {error}");
         }
     }
 
     #[instrument(level = "debug", skip(stream))]
-    async fn handle_connection(stream: WebSocketStream<TokioAdapter<TcpStream>>) -> Result<()> {
+    async fn handle_connection(
+        stream: WebSocketStream<TokioAdapter<TcpStream>>,
+        address: SocketAddr,
+    ) -> Result<()> {
         info!("New Signalling connection");
 
         let (mut ws_sink, mut ws_stream) = stream.split();
@@ -116,19 +140,19 @@ impl SignallingServer {
                     .await
                 {
                     Ok(Some(Ok(message))) => {
+                        // Sessions ended from our side are removed here, and the consumer is told about it
                         if let Message::Question(Question::EndSession(end_session_question)) =
-                            message
+                            &message
                         {
-                            let bind = end_session_question.bind;
-                            let reason = end_session_question.reason;
+                            let bind = &end_session_question.bind;
+                            let reason = end_session_question.reason.clone();
 
-                            if let Err(error) = stream::Manager::remove_session(&bind, reason).await
+                            if let Err(error) = stream::Manager::remove_session(bind, reason).await
                             {
                                 error!("Failed removing session {bind:?}. Reason: {error}",);
                             }
 
-                            info!("Session {bind:?} ended by consumer");
-                            continue;
+                            info!("Session {bind:?} ended by producer");
                         }
 
                         let message = serde_json::to_string(&message)?;
@@ -173,7 +197,7 @@ impl SignallingServer {
                     }
                 };
 
-                if let Err(error) = Self::handle_message(msg.clone(), &mpsc_sender).await {
+                if let Err(error) = Self::handle_message(msg.clone(), &mpsc_sender, address).await {
                     error!("Failed handling message: {error}");
                     break;
                 }
@@ -186,6 +210,9 @@ impl SignallingServer {
 
         let _ = tokio::join!(sender_task_handle, receiver_task_handle);
 
+        // Consumers dropping without ending their sessions would otherwise keep holding their client slots
+        stream::Manager::remove_orphan_sessions().await;
+
         debug!("Signalling connection terminated");
 
         Ok(())
@@ -195,6 +222,7 @@ impl SignallingServer {
     async fn handle_message(
         msg: String,
         sender: &mpsc::UnboundedSender<Result<Message>>,
+        address: SocketAddr,
     ) -> Result<()> {
         let protocol = match serde_json::from_str::<Protocol>(&msg) {
             Ok(protocol) => protocol,
@@ -219,15 +247,34 @@ impl SignallingServer {
                         let streams = Self::streams_information().await.unwrap_or_default();
                         Some(Answer::AvailableStreams(streams))
                     }
-                    Question::StartSession(bind) => {
-                        // After this point, any further negotiation will be sent from webrtcbin,
-                        // which will use this mpsc channel's sender to queue the message for the
-                        // WebSocket, which will receive and send it to the consumer via WebSocket.
-                        stream::Manager::add_session(&bind, sender.clone())
+                    Question::StartSession(question) => {
+                        let bind = question.bind;
+                        let trickle_ice = question.trickle_ice.unwrap_or(true);
+
+                        // A full stream rejects the session, but the consumer stays connected
+                        if !stream::manager::has_free_client_slot(&bind.producer_id).await {
+                            warn!("Rejecting session {bind:?}: maximum number of clients reached");
+
+                            Some(Answer::StartSessionRejected(StartSessionRejectedAnswer {
+                                bind,
+                                reason: "The stream reached its maximum number of clients"
+                                    .to_string(),
+                            }))
+                        } else {
+                            // After this point, any further negotiation will be sent from webrtcbin,
+                            // which will use this mpsc channel's sender to queue the message for the
+                            // WebSocket, which will receive and send it to the consumer via WebSocket.
+                            stream::Manager::add_session(
+                                &bind,
+                                sender.clone(),
+                                address,
+                                trickle_ice,
+                            )
                             .await
                             .context("Failed adding session.")?;
 
-                        None
+                            None
+                        }
                     }
                     Question::EndSession(end_session_question) => {
                         let bind = end_session_question.bind;
@@ -256,7 +303,15 @@ impl SignallingServer {
                 }
                 Negotiation::IceNegotiation(negotiation) => {
                     let bind = negotiation.bind;
-                    let candidate = negotiation.ice.candidate.context("No candidate -> Done")?;
+                    // An empty candidate marks the end of the consumer's candidates
+                    let Some(candidate) = negotiation
+                        .ice
+                        .candidate
+                        .filter(|candidate| !candidate.is_empty())
+                    else {
+                        debug!("Consumer finished sending ICE candidates for {bind:?}");
+                        return Ok(());
+                    };
                     let sdp_m_line_index = negotiation
                         .ice
                         .sdp_m_line_index
@@ -291,9 +346,13 @@ impl SignallingServer {
                 let (height, width, encode, interval) =
                     match &stream.video_and_stream.stream_information.configuration {
                         crate::stream::types::CaptureConfiguration::Video(configuration) => {
-                            // Filter out non-H264 local streams
-                            if configuration.encode != crate::video::types::VideoEncodeType::H264 {
-                                trace!("Stream {:?} will not be listed in available streams because it's encoding isn't H264 (it's {:?} instead)", stream.video_and_stream.name, configuration.encode);
+                            // Filter out local streams not encoded as H264 or H265
+                            if !matches!(
+                                configuration.encode,
+                                crate::video::types::VideoEncodeType::H264
+                                    | crate::video::types::VideoEncodeType::H265
+                            ) {
+                                trace!("Stream {:?} will not be listed in available streams because it's encoding isn't H264 or H265 (it's {:?} instead)", stream.video_and_stream.name, configuration.encode);
                                 return None;
                             }
                             (
@@ -328,7 +387,7 @@ impl SignallingServer {
                         .to_string(),
                 );
 
-                let name = stream.video_and_stream.name.clone();
+                let name = stream.video_and_stream.display_name().to_string();
                 let id = stream.id;
 
                 Some(Stream {
@@ -340,6 +399,9 @@ impl SignallingServer {
                     interval,
                     source,
                     created: None,
+                    ice_servers: ice_servers::to_signalling(&ice_servers::for_stream(
+                        &stream.video_and_stream.stream_information,
+                    )),
                 })
             })
             .collect())
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use tracing::*;
+
+type ControlWrite = Box<dyn FnOnce() -> std::io::Result<()> + Send>;
+
+#[derive(Default)]
+struct ControlState {
+    last_write: Option<Instant>,
+    // The latest value received while the control was written less than an interval ago
+    pending: Option<ControlWrite>,
+    is_flushing: bool,
+}
+
+lazy_static! {
+    static ref CONTROLS: Arc<Mutex<HashMap<(String, u64), ControlState>>> = Default::default();
+}
+
+/// Coalesces the writes to the same control of a source, like the ones from a slider being dragged, so the device is
+/// written at most once per interval. A control not written in the last interval is written right away, otherwise
+/// only its latest value is kept, to be written once the interval elapses. Deferred writes return Ok, with their
+/// errors logged
+#[instrument(level = "debug", skip(write))]
+pub fn debounce(
+    source_string: &str,
+    control_id: u64,
+    interval: Duration,
+    write: impl FnOnce() -> std::io::Result<()> + Send + 'static,
+) -> std::io::Result<()> {
+    let key = (source_string.to_string(), control_id);
+
+    let delay = {
+        let mut controls = CONTROLS.lock().unwrap();
+        let state = controls.entry(key.clone()).or_default();
+
+        let elapsed = state.last_write.map(|last_write| last_write.elapsed());
+        if !state.is_flushing && elapsed.map_or(true, |elapsed| elapsed >= interval) {
+            state.last_write = Some(Instant::now());
+            None
+        } else {
+            state.pending = Some(Box::new(write));
+            if state.is_flushing {
+                return Ok(());
+            }
+            state.is_flushing = true;
+            Some(interval.saturating_sub(elapsed.unwrap_or_default()))
+        }
+    };
+
+    let Some(delay) = delay else {
+        return write();
+    };
+
+    trace!("Deferring the write of control {control_id} from {source_string:?} by {delay:?}");
+    std::thread::Builder::new()
+        .name("ControlDebounce".to_string())
+        .spawn(move || flush(key, delay, interval))
+        .map(|_| ())
+}
+
+/// Writes the pending values of a control, once per interval, until there is none left
+fn flush(key: (String, u64), delay: Duration, interval: Duration) {
+    std::thread::sleep(delay);
+
+    loop {
+        let write = {
+            let mut controls = CONTROLS.lock().unwrap();
+            let Some(state) = controls.get_mut(&key) else {
+                return;
+            };
+
+            match state.pending.take() {
+                Some(write) => {
+                    state.last_write = Some(Instant::now());
+                    write
+                }
+                None => {
+                    // The last write happened an interval ago, so the next one can be done right away
+                    controls.remove(&key);
+                    return;
+                }
+            }
+        };
+
+        if let Err(error) = write() {
+            warn!(
+                "Failed writing control {} from {:?}: {error:?}",
+                key.1, key.0
+            );
+        }
+
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce() {
+        let interval = Duration::from_millis(50);
+        let writes = Arc::new(Mutex::new(Vec::new()));
+
+        for value in 0..20 {
+            let writes = writes.clone();
+            debounce("test_debounce", 1, interval, move || {
+                writes.lock().unwrap().push(value);
+                Ok(())
+            })
+            .unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        std::thread::sleep(interval * 4);
+
+        let writes = writes.lock().unwrap();
+        // The first value is written right away, and the last one is always written
+        assert_eq!(writes.first(), Some(&0));
+        assert_eq!(writes.last(), Some(&19));
+        assert!(writes.len() < 10, "Writes weren't coalesced: {writes:?}");
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Mutex,
+};
+
+use lazy_static::lazy_static;
+use tracing::*;
+
+#[derive(Debug, Default)]
+struct SourceState {
+    /// The pipelines currently streaming from this source
+    pipelines: HashSet<uuid::Uuid>,
+    /// The controls found to only take effect while the source is streaming
+    requires_streaming: HashSet<u64>,
+    /// The latest value of each control waiting for the source to stream
+    pending: BTreeMap<u64, i64>,
+}
+
+lazy_static! {
+    static ref SOURCES: Mutex<HashMap<String, SourceState>> = Default::default();
+}
+
+/// If any pipeline is streaming from the source
+pub fn is_streaming(source_string: &str) -> bool {
+    SOURCES
+        .lock()
+        .unwrap()
+        .get(source_string)
+        .is_some_and(|state| !state.pipelines.is_empty())
+}
+
+/// If the control was found to only take effect while the source is streaming
+pub fn requires_streaming(source_string: &str, control_id: u64) -> bool {
+    SOURCES
+        .lock()
+        .unwrap()
+        .get(source_string)
+        .is_some_and(|state| state.requires_streaming.contains(&control_id))
+}
+
+/// Remembers that the control only takes effect while the source is streaming, like some exposure modes
+#[instrument(level = "debug")]
+pub fn set_requires_streaming(source_string: &str, control_id: u64) {
+    SOURCES
+        .lock()
+        .unwrap()
+        .entry(source_string.to_string())
+        .or_default()
+        .requires_streaming
+        .insert(control_id);
+}
+
+/// Keeps the value to be written once the source starts streaming, replacing any value queued before
+#[instrument(level = "debug")]
+pub fn queue(source_string: &str, control_id: u64, value: i64) {
+    SOURCES
+        .lock()
+        .unwrap()
+        .entry(source_string.to_string())
+        .or_default()
+        .pending
+        .insert(control_id, value);
+}
+
+/// The value waiting for the source to stream, if any
+pub fn pending_value(source_string: &str, control_id: u64) -> Option<i64> {
+    SOURCES
+        .lock()
+        .unwrap()
+        .get(source_string)
+        .and_then(|state| state.pending.get(&control_id).copied())
+}
+
+/// Marks the pipeline as streaming from the source or not. Returns the values queued for the source when it starts
+/// streaming, leaving it up to the caller to write them
+#[instrument(level = "debug")]
+pub fn set_streaming(
+    source_string: &str,
+    pipeline_id: &uuid::Uuid,
+    is_streaming: bool,
+) -> BTreeMap<u64, i64> {
+    let mut sources = SOURCES.lock().unwrap();
+    let state = sources.entry(source_string.to_string()).or_default();
+
+    if !is_streaming {
+        state.pipelines.remove(pipeline_id);
+        return Default::default();
+    }
+
+    state.pipelines.insert(*pipeline_id);
+    std::mem::take(&mut state.pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queued_until_streaming() {
+        let source_string = "/dev/test_queued_until_streaming";
+        let control_id = 1;
+        let pipeline_id = uuid::Uuid::new_v4();
+
+        assert!(!is_streaming(source_string));
+        assert!(!requires_streaming(source_string, control_id));
+
+        set_requires_streaming(source_string, control_id);
+        assert!(requires_streaming(source_string, control_id));
+
+        // Only the latest value is kept
+        queue(source_string, control_id, 10);
+        queue(source_string, control_id, 20);
+        assert_eq!(pending_value(source_string, control_id), Some(20));
+
+        let pending = set_streaming(source_string, &pipeline_id, true);
+        assert_eq!(pending, BTreeMap::from([(control_id, 20)]));
+        assert!(is_streaming(source_string));
+        assert_eq!(pending_value(source_string, control_id), None);
+
+        // A pipeline starting again doesn't write the same values twice
+        assert!(set_streaming(source_string, &pipeline_id, true).is_empty());
+
+        assert!(set_streaming(source_string, &pipeline_id, false).is_empty());
+        assert!(!is_streaming(source_string));
+        // The requirement is kept for the next writes
+        assert!(requires_streaming(source_string, control_id));
+    }
+}
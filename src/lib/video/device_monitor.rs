@@ -0,0 +1,100 @@
+use gst::prelude::*;
+use tokio::sync::broadcast;
+use tracing::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Added(DeviceDescription),
+    Removed(DeviceDescription),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceDescription {
+    pub name: String,
+    /// The device node, like `/dev/video0`
+    pub device_path: Option<String>,
+    /// The V4L2 bus info, like `usb-0000:01:00.0-1.4`
+    pub bus_info: Option<String>,
+}
+
+impl From<&gst::Device> for DeviceDescription {
+    fn from(device: &gst::Device) -> Self {
+        let properties = device.properties();
+        let property = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                properties
+                    .as_ref()
+                    .and_then(|properties| properties.get::<String>(*name).ok())
+            })
+        };
+
+        Self {
+            name: device.display_name().to_string(),
+            device_path: property(&["device.path", "api.v4l2.path"]),
+            bus_info: property(&["v4l2.device.bus_info", "api.v4l2.cap.bus_info"]),
+        }
+    }
+}
+
+lazy_static! {
+    static ref EVENTS: broadcast::Sender<DeviceEvent> = broadcast::channel(16).0;
+}
+
+/// Receives the video devices plugged or unplugged after the monitor was started
+pub fn subscribe() -> broadcast::Receiver<DeviceEvent> {
+    EVENTS.subscribe()
+}
+
+/// Starts a GstDeviceMonitor watching for video sources, publishing their hotplug events
+#[instrument(level = "debug")]
+pub fn start() {
+    if let Err(error) = std::thread::Builder::new()
+        .name("DeviceMonitor".to_string())
+        .spawn(device_monitor_thread)
+    {
+        error!("Failed to spawn the device monitor thread: {error:?}");
+    }
+}
+
+#[instrument(level = "debug")]
+fn device_monitor_thread() {
+    if let Err(error) = gst::init() {
+        error!("Failed to init GStreamer: {error:?}");
+        return;
+    }
+
+    let monitor = gst::DeviceMonitor::new();
+    monitor.add_filter(Some("Video/Source"), None);
+
+    let Some(bus) = monitor.bus() else {
+        error!("Failed to get the device monitor bus");
+        return;
+    };
+
+    if let Err(error) = monitor.start() {
+        error!("Failed to start the device monitor, hot-plugged cameras will only be found on stream restarts: {error:?}");
+        return;
+    }
+
+    debug!("Device monitor started");
+
+    for message in bus.iter_timed(gst::ClockTime::NONE) {
+        let event = match message.view() {
+            gst::MessageView::DeviceAdded(message) => {
+                DeviceEvent::Added(DeviceDescription::from(&message.device()))
+            }
+            gst::MessageView::DeviceRemoved(message) => {
+                DeviceEvent::Removed(DeviceDescription::from(&message.device()))
+            }
+            _ => continue,
+        };
+
+        info!("Video device event: {event:?}");
+
+        // Having no subscribers is not an error
+        let _ = EVENTS.send(event);
+    }
+
+    monitor.stop();
+    debug!("Device monitor stopped");
+}
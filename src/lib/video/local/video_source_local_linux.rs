@@ -4,6 +4,7 @@ use std::sync::{Arc, Mutex};
 
 use crate::stream::types::VideoCaptureConfiguration;
 
+use crate::video::control_prerequisites;
 use crate::video::types::*;
 use crate::video::video_source::{VideoSource, VideoSourceAvailable};
 use paperclip::actix::Apiv2Schema;
@@ -52,8 +53,23 @@ pub struct VideoSourceLocal {
     pub device_path: String,
     #[serde(rename = "type")]
     pub typ: VideoSourceLocalType,
+    /// A path that keeps identifying the device across reboots and reconnections, unlike its "/dev/videoN" node,
+    /// like "/dev/v4l/by-id/usb-Foo_Camera_1234-video-index0". Devices whose "/dev/v4l/by-id" link may be shared
+    /// with an identical camera, because they have no serial number, get their "/dev/v4l/by-path" one instead,
+    /// which is stable for the same USB port
+    #[serde(default)]
+    pub stable_id: Option<String>,
 }
 
+/// Where udev places the symbolic links of the video devices named after their vendor, model and serial number
+const BY_ID_DIRECTORY: &str = "/dev/v4l/by-id";
+/// Where udev places the symbolic links of the video devices named after the port they are connected to
+const BY_PATH_DIRECTORY: &str = "/dev/v4l/by-path";
+/// Where the kernel lists the video devices
+const VIDEO4LINUX_DIRECTORY: &str = "/sys/class/video4linux";
+/// Where udev keeps the properties of the character devices, by their "major:minor" numbers
+const UDEV_DATA_DIRECTORY: &str = "/run/udev/data";
+
 impl VideoSourceLocalType {
     // For PCI:
     // https://wiki.xenproject.org/wiki/Bus:Device.Function_(BDF)_Notation
@@ -120,12 +136,197 @@ impl VideoSourceLocalType {
     }
 }
 
+/// The stable symbolic link of the given device node, if udev created one
+pub fn stable_id(device_path: &str) -> Option<String> {
+    let device_path = std::fs::canonicalize(device_path).ok()?;
+
+    // Without a serial number, udev names the by-id link only after the vendor and model, so identical cameras
+    // share one link, pointing at whichever of them was enumerated last
+    if has_unique_id(&device_path) {
+        if let Some(link) = link_to(BY_ID_DIRECTORY, &device_path) {
+            return Some(link);
+        }
+    }
+
+    link_to(BY_PATH_DIRECTORY, &device_path)
+}
+
+/// The first symbolic link of the directory pointing to the given canonical device node
+fn link_to(directory: &str, device_path: &std::path::Path) -> Option<String> {
+    let mut links = std::fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|link| std::fs::canonicalize(link).is_ok_and(|path| path == device_path))
+        .filter_map(|link| link.to_str().map(String::from))
+        .collect::<Vec<String>>();
+    links.sort();
+    links.into_iter().next()
+}
+
+/// If the by-id link of the device can only point to it: either the device has a serial number, or no other
+/// device, connected to another port, shares its vendor and model
+fn has_unique_id(device_path: &std::path::Path) -> bool {
+    let properties = udev_properties(device_path);
+    if properties
+        .get("ID_SERIAL_SHORT")
+        .is_some_and(|serial| !serial.is_empty())
+    {
+        return true;
+    }
+
+    let Some(serial) = properties.get("ID_SERIAL") else {
+        return false;
+    };
+
+    let Ok(entries) = std::fs::read_dir(VIDEO4LINUX_DIRECTORY) else {
+        return false;
+    };
+
+    // The nodes of the same device share its port, like its capture and metadata ones
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| std::path::Path::new("/dev").join(entry.file_name()))
+        .filter(|other| other != device_path)
+        .map(|other| udev_properties(&other))
+        .filter(|other| other.get("ID_SERIAL") == Some(serial))
+        .all(|other| other.get("ID_PATH") == properties.get("ID_PATH"))
+}
+
+/// The udev properties of the given device node, like "ID_SERIAL", empty if udev has no data about it
+fn udev_properties(device_path: &std::path::Path) -> HashMap<String, String> {
+    let Some(name) = device_path.file_name() else {
+        return HashMap::new();
+    };
+
+    let Ok(numbers) = std::fs::read_to_string(
+        std::path::Path::new(VIDEO4LINUX_DIRECTORY)
+            .join(name)
+            .join("dev"),
+    ) else {
+        return HashMap::new();
+    };
+
+    std::fs::read_to_string(format!("{UDEV_DATA_DIRECTORY}/c{}", numbers.trim()))
+        .map(|data| parse_udev_properties(&data))
+        .unwrap_or_default()
+}
+
+/// Parses the properties, the "E:KEY=value" lines, of an udev data file
+fn parse_udev_properties(data: &str) -> HashMap<String, String> {
+    data.lines()
+        .filter_map(|line| line.strip_prefix("E:")?.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
 impl VideoSourceLocal {
+    /// The current node of the device, like "/dev/video2", found through its stable id when the device was
+    /// renumbered since it was configured
+    pub fn current_device_path(&self) -> String {
+        self.stable_id
+            .as_ref()
+            .and_then(|stable_id| std::fs::canonicalize(stable_id).ok())
+            .and_then(|path| path.to_str().map(String::from))
+            .unwrap_or_else(|| self.device_path.clone())
+    }
+
+    /// Describes the formats exactly as enumerated by the device, keeping the frame interval ranges
+    /// instead of expanding them like `formats()` does
+    pub fn formats_description(&self) -> Vec<FormatDescription> {
+        let device_path = self.device_path.clone();
+
+        unpanic(move || {
+            let mut formats = vec![];
+
+            let v4l_device = match v4l::Device::with_path(&device_path) {
+                Ok(device) => device,
+                Err(error) => {
+                    error!("Failed to get device {device_path:?}: {error:?}");
+                    return formats;
+                }
+            };
+
+            use v4l::video::Capture;
+
+            for v4l_format in v4l_device.enum_formats().unwrap_or_default() {
+                let encode = match v4l_format.fourcc.str() {
+                    Ok(encode_str) => VideoEncodeType::from_str(encode_str),
+                    Err(error) => {
+                        warn!(
+                            "Failed to represent fourcc {:?} as a string: {error:?}",
+                            v4l_format.fourcc
+                        );
+                        continue;
+                    }
+                };
+
+                let v4l_framesizes = match v4l_device.enum_framesizes(v4l_format.fourcc) {
+                    Ok(v4l_framesizes) => v4l_framesizes,
+                    Err(error) => {
+                        warn!(
+                            "Failed to get framesizes from format {v4l_format:?} for device {device_path:?}: {error:#?}"
+                        );
+                        continue;
+                    }
+                };
+
+                let mut sizes = vec![];
+                for v4l_framesize in v4l_framesizes {
+                    let v4l_sizes = match v4l_framesize.size {
+                        v4l::framesize::FrameSizeEnum::Discrete(v4l_size) => {
+                            vec![(v4l_size.width, v4l_size.height)]
+                        }
+                        v4l::framesize::FrameSizeEnum::Stepwise(v4l_size) => {
+                            let mut std_sizes: Vec<(u32, u32)> = STANDARD_SIZES.to_vec();
+                            std_sizes.push((v4l_size.max_width, v4l_size.max_height));
+                            std_sizes
+                        }
+                    };
+
+                    for (width, height) in v4l_sizes {
+                        match v4l_device.enum_frameintervals(v4l_framesize.fourcc, width, height) {
+                            Ok(enum_frameintervals) => sizes.push(SizeDescription {
+                                width,
+                                height,
+                                intervals: describe_v4l_intervals(&enum_frameintervals),
+                            }),
+                            Err(error) => trace!(
+                                "Failed to fetch frameintervals for camera {device_path}, encode: {:?}, size: {:?}: {error:#?}",
+                                v4l_format.fourcc,
+                                (width, height),
+                            ),
+                        }
+                    }
+                }
+
+                sizes.dedup();
+
+                formats.push(FormatDescription {
+                    support: FormatSupport::from(&encode),
+                    encode,
+                    sizes,
+                });
+            }
+
+            formats
+        })
+    }
+
     pub fn try_identify_device(
         &mut self,
         capture_configuration: &VideoCaptureConfiguration,
         candidates: &[VideoSourceType],
     ) -> Result<Option<String>> {
+        // Rule n.0 - A candidate with the same stable id is the same device, wherever it was enumerated
+        if let Some(candidate) = self.stable_id.as_ref().and_then(|stable_id| {
+            candidates.iter().find(|candidate| {
+                matches!(candidate, VideoSourceType::Local(camera) if camera.stable_id.as_ref() == Some(stable_id))
+            })
+        }) {
+            trace!("Outcome n.0!");
+            return Ok(Some(candidate.inner().source_string().to_string()));
+        }
+
         // Rule n.1 - All candidates must share the same camera name
         let candidates = Self::get_cameras_with_same_name(candidates, &self.name);
 
@@ -282,11 +483,48 @@ fn convert_v4l_intervals(v4l_intervals: &[v4l::FrameInterval]) -> Vec<FrameInter
     intervals
 }
 
+fn describe_v4l_intervals(v4l_intervals: &[v4l::FrameInterval]) -> Vec<FrameIntervalDescription> {
+    v4l_intervals
+        .iter()
+        .map(|v4l_interval| match &v4l_interval.interval {
+            v4l::frameinterval::FrameIntervalEnum::Discrete(fraction) => {
+                FrameIntervalDescription::Discrete(FrameInterval {
+                    numerator: fraction.numerator,
+                    denominator: fraction.denominator,
+                })
+            }
+            v4l::frameinterval::FrameIntervalEnum::Stepwise(stepwise) => {
+                FrameIntervalDescription::Stepwise {
+                    min: FrameInterval {
+                        numerator: stepwise.min.numerator,
+                        denominator: stepwise.min.denominator,
+                    },
+                    max: FrameInterval {
+                        numerator: stepwise.max.numerator,
+                        denominator: stepwise.max.denominator,
+                    },
+                    step: FrameInterval {
+                        numerator: stepwise.step.numerator,
+                        denominator: stepwise.step.denominator,
+                    },
+                }
+            }
+        })
+        .collect()
+}
+
 fn validate_control(control: &Control, value: i64) -> Result<(), String> {
     if control.state.is_inactive {
-        return Err("Control is inactive".to_string());
+        return Err("Control is inactive, which usually means it is driven by an automatic mode (like auto exposure or autofocus). Disable the automatic mode to set it manually".to_string());
     } else if control.state.is_disabled {
         return Err("Control is disabled".to_string());
+    } else if control.state.is_read_only {
+        return Err("Control is read-only".to_string());
+    } else if control.state.is_grabbed {
+        return Err(
+            "Control is grabbed while the device is streaming, stop its stream to change it"
+                .to_string(),
+        );
     }
 
     match &control.configuration {
@@ -301,6 +539,11 @@ fn validate_control(control: &Control, value: i64) -> Result<(), String> {
                     "Value {value:?} is lower than the Control minimum value: {:?}",
                     control.min
                 ));
+            } else if control.step > 1 && (value - control.min) % control.step as i64 != 0 {
+                return Err(format!(
+                    "Value {value:?} is not a multiple of the Control step {:?} starting from its minimum value {:?}",
+                    control.step, control.min
+                ));
             }
         }
         ControlType::Menu(control) => {
@@ -606,14 +849,33 @@ impl VideoSource for VideoSourceLocal {
             }
         };
 
+        let is_streaming = control_prerequisites::is_streaming(self.source_string());
+
         for v4l_control in v4l_controls {
+            let id = v4l_control.id as u64;
+            let mut state = ControlState {
+                is_disabled: v4l_control.flags.contains(v4l::control::Flags::DISABLED),
+                is_inactive: v4l_control.flags.contains(v4l::control::Flags::INACTIVE),
+                is_read_only: v4l_control.flags.contains(v4l::control::Flags::READ_ONLY),
+                is_volatile: v4l_control.flags.contains(v4l::control::Flags::VOLATILE),
+                is_grabbed: v4l_control.flags.contains(v4l::control::Flags::GRABBED),
+                requires_streaming: control_prerequisites::requires_streaming(
+                    self.source_string(),
+                    id,
+                ),
+                pending_value: control_prerequisites::pending_value(self.source_string(), id),
+                ..Default::default()
+            };
+            state.is_writable = !(state.is_disabled
+                || state.is_inactive
+                || state.is_read_only
+                || state.is_grabbed
+                || (state.requires_streaming && !is_streaming));
+
             let mut control = Control {
                 name: v4l_control.name,
-                id: v4l_control.id as u64,
-                state: ControlState {
-                    is_disabled: v4l_control.flags.contains(v4l::control::Flags::DISABLED),
-                    is_inactive: v4l_control.flags.contains(v4l::control::Flags::INACTIVE),
-                },
+                id,
+                state,
                 ..Default::default()
             };
 
@@ -750,6 +1012,7 @@ impl VideoSourceAvailable for VideoSourceLocal {
                 name: caps.card,
                 device_path: camera_path.to_string(),
                 typ,
+                stable_id: stable_id(camera_path),
             };
             cameras.push(VideoSourceType::Local(source));
         }
@@ -796,6 +1059,29 @@ mod tests {
             assert_eq!(description.0, VideoSourceLocalType::from_str(description.1));
         }
     }
+
+    #[test]
+    fn test_validate_control() {
+        let mut control = Control {
+            configuration: ControlType::Slider(ControlSlider {
+                default: 10,
+                value: 10,
+                step: 5,
+                max: 100,
+                min: 0,
+            }),
+            ..Default::default()
+        };
+
+        assert!(validate_control(&control, 15).is_ok());
+        assert!(validate_control(&control, 17).is_err());
+        assert!(validate_control(&control, 105).is_err());
+        assert!(validate_control(&control, -5).is_err());
+
+        // Controls driven by an automatic mode should reject manual values
+        control.state.is_inactive = true;
+        assert!(validate_control(&control, 15).is_err());
+    }
 }
 
 #[cfg(test)]
@@ -842,6 +1128,7 @@ mod device_identification_tests {
             name: name.into(),
             device_path: device_path.into(),
             typ: VideoSourceLocalType::Usb(usb_bus.into()),
+            stable_id: None,
         })
     }
 
@@ -853,6 +1140,7 @@ mod device_identification_tests {
     ) -> VideoAndStreamInformation {
         VideoAndStreamInformation {
             name: "dummy stream".into(),
+            tags: vec![],
             stream_information: StreamInformation {
                 configuration: CaptureConfiguration::Video(VideoCaptureConfiguration {
                     encode,
@@ -870,6 +1158,7 @@ mod device_identification_tests {
                 name: name.into(),
                 device_path: device_path.into(),
                 typ: VideoSourceLocalType::Usb(usb_bus.into()),
+                stable_id: None,
             }),
         }
     }
@@ -1114,4 +1403,73 @@ mod device_identification_tests {
 
         VIDEO_FORMATS.lock().unwrap().clear();
     }
+
+    #[traced_test]
+    #[serial("Using a mocked global VIDEO_FORMATS")]
+    #[test]
+    fn identify_a_candidate_by_stable_id() {
+        VIDEO_FORMATS.lock().unwrap().clear();
+
+        let with_stable_id = |camera: VideoSourceType, stable_id: &str| {
+            let VideoSourceType::Local(mut camera) = camera else {
+                unreachable!("Wrong setup")
+            };
+            camera.stable_id = Some(stable_id.into());
+            VideoSourceType::Local(camera)
+        };
+
+        // Two identical cameras, only told apart by the serial number in their stable ids, swapped their ports
+        let candidates = vec![
+            with_stable_id(
+                add_available_camera("A", "/dev/video0", "usb_port_0", vec![H264]),
+                "/dev/v4l/by-id/usb-A_1111-video-index0",
+            ),
+            with_stable_id(
+                add_available_camera("A", "/dev/video2", "usb_port_1", vec![H264]),
+                "/dev/v4l/by-id/usb-A_2222-video-index0",
+            ),
+        ];
+        let mut stream = create_stream("A", "/dev/video0", "usb_port_0", H264);
+        let (VideoSourceType::Local(source), CaptureConfiguration::Video(capture_configuration)) = (
+            &mut stream.video_source,
+            &stream.stream_information.configuration,
+        ) else {
+            unreachable!("Wrong setup")
+        };
+        source.stable_id = Some("/dev/v4l/by-id/usb-A_2222-video-index0".into());
+
+        assert_eq!(
+            source
+                .to_owned()
+                .try_identify_device(capture_configuration, &candidates)
+                .unwrap(),
+            Some("/dev/video2".to_string())
+        );
+
+        // Without it, the same port wins
+        source.stable_id = None;
+        assert_eq!(
+            source
+                .to_owned()
+                .try_identify_device(capture_configuration, &candidates)
+                .unwrap(),
+            Some("/dev/video0".to_string())
+        );
+
+        VIDEO_FORMATS.lock().unwrap().clear();
+    }
+
+    #[test]
+    fn parse_the_udev_properties() {
+        let data = "S:v4l/by-id/usb-Foo_Camera-video-index0\nI:12345\nE:ID_SERIAL=Foo_Camera\nE:ID_PATH=pci-0000:00:14.0-usb-0:1:1.0\nG:uaccess\n";
+        let properties = parse_udev_properties(data);
+
+        assert_eq!(properties.len(), 2);
+        assert_eq!(properties.get("ID_SERIAL").unwrap(), "Foo_Camera");
+        assert_eq!(
+            properties.get("ID_PATH").unwrap(),
+            "pci-0000:00:14.0-usb-0:1:1.0"
+        );
+        assert!(!properties.contains_key("ID_SERIAL_SHORT"));
+    }
 }
@@ -14,9 +14,15 @@ pub enum VideoSourceLocalType {}
 pub struct VideoSourceLocal {
     pub name: String,
     pub device_path: String,
+    #[serde(default)]
+    pub stable_id: Option<String>,
 }
 
 impl VideoSourceLocal {
+    pub fn current_device_path(&self) -> String {
+        self.device_path.clone()
+    }
+
     pub fn try_identify_device(
         &mut self,
         capture_configuration: &VideoCaptureConfiguration,
@@ -24,6 +30,10 @@ impl VideoSourceLocal {
     ) -> Result<Option<String>> {
         Ok(None)
     }
+
+    pub fn formats_description(&self) -> Vec<FormatDescription> {
+        vec![]
+    }
 }
 
 impl VideoSource for VideoSourceLocal {
@@ -1,5 +1,8 @@
 mod local;
 
+pub mod control_debounce;
+pub mod control_prerequisites;
+pub mod device_monitor;
 pub mod types;
 pub mod video_source;
 pub mod xml;
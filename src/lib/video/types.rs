@@ -52,6 +52,80 @@ impl From<gst::Fraction> for FrameInterval {
     }
 }
 
+/// How a format from the device can be streamed
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormatSupport {
+    /// Streamed as it comes from the device
+    Native,
+    /// Converted before being streamed
+    Transcoded,
+    Unsupported,
+}
+
+impl From<&VideoEncodeType> for FormatSupport {
+    fn from(encode: &VideoEncodeType) -> Self {
+        match encode {
+            VideoEncodeType::H264 | VideoEncodeType::H265 | VideoEncodeType::Mjpg => {
+                FormatSupport::Native
+            }
+            VideoEncodeType::Yuyv => FormatSupport::Transcoded,
+            _ => FormatSupport::Unsupported,
+        }
+    }
+}
+
+/// A format as reported by the device, without expanding the frame interval ranges
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FormatDescription {
+    pub encode: VideoEncodeType,
+    pub support: FormatSupport,
+    pub sizes: Vec<SizeDescription>,
+}
+
+impl From<&Format> for FormatDescription {
+    fn from(format: &Format) -> Self {
+        FormatDescription {
+            encode: format.encode.clone(),
+            support: FormatSupport::from(&format.encode),
+            sizes: format
+                .sizes
+                .iter()
+                .map(|size| SizeDescription {
+                    width: size.width,
+                    height: size.height,
+                    intervals: size
+                        .intervals
+                        .iter()
+                        .cloned()
+                        .map(FrameIntervalDescription::Discrete)
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SizeDescription {
+    pub width: u32,
+    pub height: u32,
+    pub intervals: Vec<FrameIntervalDescription>,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameIntervalDescription {
+    /// A single frame interval
+    Discrete(FrameInterval),
+    /// Any frame interval from min to max, in steps of step
+    Stepwise {
+        min: FrameInterval,
+        max: FrameInterval,
+        step: FrameInterval,
+    },
+}
+
 #[derive(Apiv2Schema, Clone, Debug, Default, Serialize)]
 pub struct Control {
     pub name: String,
@@ -72,6 +146,26 @@ pub enum ControlType {
 pub struct ControlState {
     pub is_disabled: bool,
     pub is_inactive: bool,
+    pub is_read_only: bool,
+    /// Changes on its own, like the exposure under an automatic mode, so its value should be polled
+    pub is_volatile: bool,
+    /// Busy while the device is streaming, so it can only be written while stopped
+    pub is_grabbed: bool,
+    /// Only takes effect while the device is streaming, so writes are queued until it is
+    pub requires_streaming: bool,
+    /// If a write takes effect in the current state of the device
+    pub is_writable: bool,
+    /// The value queued to be written once the device starts streaming
+    pub pending_value: Option<i64>,
+}
+
+/// A control changed by a reset, with its value before and after it
+#[derive(Apiv2Schema, Clone, Debug, Serialize)]
+pub struct ControlChange {
+    pub id: u64,
+    pub name: String,
+    pub old_value: i64,
+    pub new_value: i64,
 }
 
 #[derive(Apiv2Schema, Clone, Debug, Serialize)]
@@ -118,6 +212,7 @@ impl VideoEncodeType {
         let fourcc = fourcc.to_uppercase();
         match fourcc.as_str() {
             "H264" => VideoEncodeType::H264,
+            "H265" | "HEVC" => VideoEncodeType::H265,
             "MJPG" => VideoEncodeType::Mjpg,
             "YUYV" => VideoEncodeType::Yuyv,
             _ => VideoEncodeType::Unknown(fourcc),
@@ -1,7 +1,15 @@
+use crate::cli;
+
+use super::control_debounce;
+use super::control_prerequisites;
 use super::types::*;
-use super::video_source_gst::VideoSourceGst;
+use super::video_source_gst::{
+    VideoSourceGst, VideoSourceGstType, VideoSourceRtsp, CUSTOM_PIPELINE_PREFIX, FAKE_PATTERNS,
+};
 use super::video_source_local::VideoSourceLocal;
 use super::video_source_redirect::VideoSourceRedirect;
+use lazy_static::lazy_static;
+use tokio::sync::broadcast;
 use tracing::*;
 
 pub trait VideoSource {
@@ -17,6 +25,41 @@ pub trait VideoSource {
     fn is_shareable(&self) -> bool;
 }
 
+/// How a control write was handled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlWriteStatus {
+    /// Written to the device
+    Applied,
+    /// Only takes effect while the device is streaming, so it will be written once it starts
+    Queued,
+}
+
+/// A control written to a device, through any of the interfaces
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WrittenControl {
+    pub source_string: String,
+    pub control_id: u64,
+    pub value: i64,
+}
+
+lazy_static! {
+    static ref CONTROL_WRITES: broadcast::Sender<WrittenControl> = broadcast::channel(64).0;
+}
+
+/// Receives the controls written from now on, so the interfaces showing their values can keep them in sync
+pub fn subscribe_control_writes() -> broadcast::Receiver<WrittenControl> {
+    CONTROL_WRITES.subscribe()
+}
+
+fn publish_control_write(source_string: &str, control_id: u64, value: i64) {
+    // Writes are silently dropped when there are no subscribers
+    let _ = CONTROL_WRITES.send(WrittenControl {
+        source_string: source_string.to_string(),
+        control_id,
+        value,
+    });
+}
+
 pub trait VideoSourceAvailable {
     fn cameras_available() -> Vec<VideoSourceType>;
 }
@@ -40,6 +83,44 @@ pub fn get_video_source(source_string: &str) -> Result<VideoSourceType, std::io:
         return Ok(camera.clone());
     }
 
+    // Local devices can also be referenced by their stable id, or any other link to their current node
+    let device_path = std::fs::canonicalize(source_string).ok();
+    if let Some(camera) = cameras.iter().find(|source| match source {
+        VideoSourceType::Local(camera) => {
+            camera.stable_id.as_deref() == Some(source_string)
+                || device_path.as_ref().is_some_and(|device_path| {
+                    device_path.as_os_str() == camera.device_path.as_str()
+                })
+        }
+        _ => false,
+    }) {
+        return Ok(camera.clone());
+    }
+
+    // Any videotestsrc pattern can be used as a synthetic source, like "smpte"
+    if FAKE_PATTERNS.contains(&source_string) {
+        return Ok(VideoSourceType::Gst(VideoSourceGst {
+            name: format!("Fake source ({source_string})"),
+            source: VideoSourceGstType::Fake(source_string.into()),
+        }));
+    }
+
+    // Network cameras can't be enumerated, so they are created from their URL
+    if let Some(rtsp) = VideoSourceRtsp::try_from_source_string(source_string) {
+        return Ok(VideoSourceType::Gst(VideoSourceGst {
+            name: format!("RTSP {}", rtsp.url.host_str().unwrap_or_default()),
+            source: VideoSourceGstType::Rtsp(rtsp),
+        }));
+    }
+
+    // Advanced users can provide their own pipeline, like "gst:videotestsrc ! x264enc ! tee name=VideoTee ! ..."
+    if let Some(description) = source_string.strip_prefix(CUSTOM_PIPELINE_PREFIX) {
+        return Ok(VideoSourceType::Gst(VideoSourceGst {
+            name: "Custom pipeline".into(),
+            source: VideoSourceGstType::Custom(description.trim().into()),
+        }));
+    }
+
     let sources_available: Vec<String> = cameras
         .iter()
         .map(|source| source.inner().source_string().to_string())
@@ -53,13 +134,94 @@ pub fn get_video_source(source_string: &str) -> Result<VideoSourceType, std::io:
     ))
 }
 
-pub fn set_control(source_string: &str, control_id: u64, value: i64) -> std::io::Result<()> {
+pub fn set_control(
+    source_string: &str,
+    control_id: u64,
+    value: i64,
+) -> std::io::Result<ControlWriteStatus> {
     let camera = get_video_source(source_string)?;
     debug!("Set camera ({source_string}) control ({control_id}) value ({value}).");
-    return camera.inner().set_control_by_id(control_id, value);
+    write_control(camera, control_id, value)
 }
 
-pub fn reset_controls(source_string: &str) -> Result<(), Vec<std::io::Error>> {
+/// Writes the control through the debouncer, so rapid updates are coalesced to the latest value.
+/// Controls that only take effect while the device is streaming are queued until it does
+pub fn write_control(
+    camera: VideoSourceType,
+    control_id: u64,
+    value: i64,
+) -> std::io::Result<ControlWriteStatus> {
+    let source_string = camera.inner().source_string().to_string();
+
+    if !control_prerequisites::is_streaming(&source_string)
+        && control_prerequisites::requires_streaming(&source_string, control_id)
+    {
+        debug!("Queueing control {control_id} of {source_string:?} until it starts streaming");
+        control_prerequisites::queue(&source_string, control_id, value);
+        return Ok(ControlWriteStatus::Queued);
+    }
+
+    control_debounce::debounce(
+        &source_string,
+        control_id,
+        cli::manager::controls_debounce_interval(),
+        move || {
+            camera.inner().set_control_by_id(control_id, value)?;
+            verify_control(&camera, control_id, value);
+            publish_control_write(camera.inner().source_string(), control_id, value);
+            Ok(())
+        },
+    )?;
+
+    match control_prerequisites::pending_value(&source_string, control_id) {
+        Some(pending_value) if pending_value == value => Ok(ControlWriteStatus::Queued),
+        _ => Ok(ControlWriteStatus::Applied),
+    }
+}
+
+/// Some drivers accept writes to controls that only apply while streaming, silently ignoring them while stopped.
+/// Reading the value back tells them apart, so the value is queued for the stream to start
+fn verify_control(camera: &VideoSourceType, control_id: u64, value: i64) {
+    let source_string = camera.inner().source_string();
+    if control_prerequisites::is_streaming(source_string) {
+        return;
+    }
+
+    match camera.inner().control_value_by_id(control_id) {
+        Ok(current_value) if current_value != value => {
+            warn!("Control {control_id} of {source_string:?} stayed at {current_value} instead of {value} while not streaming, it will be written once it starts streaming");
+            control_prerequisites::set_requires_streaming(source_string, control_id);
+            control_prerequisites::queue(source_string, control_id, value);
+        }
+        Ok(_) => {}
+        Err(error) => {
+            debug!("Failed reading back control {control_id} of {source_string:?}: {error:?}")
+        }
+    }
+}
+
+/// Tracks whether the pipeline streams from the camera, writing the controls queued for it once it starts
+#[instrument(level = "debug", skip(camera))]
+pub fn set_streaming(camera: &VideoSourceType, pipeline_id: &uuid::Uuid, is_streaming: bool) {
+    let source_string = camera.inner().source_string();
+
+    let pending = control_prerequisites::set_streaming(source_string, pipeline_id, is_streaming);
+    for (control_id, value) in pending {
+        match camera.inner().set_control_by_id(control_id, value) {
+            Ok(_) => {
+                debug!("Wrote queued control {control_id} of {source_string:?} to {value}");
+                publish_control_write(source_string, control_id, value);
+            }
+            Err(error) => warn!(
+                "Failed writing queued control {control_id} of {source_string:?} to {value}: {error:?}"
+            ),
+        }
+    }
+}
+
+/// Writes the driver's default value back to each control, skipping the inactive, disabled and read-only ones.
+/// Returns the controls that were changed
+pub fn reset_controls(source_string: &str) -> Result<Vec<ControlChange>, Vec<std::io::Error>> {
     let camera = match get_video_source(source_string) {
         Ok(camera) => camera,
         Err(error) => return Err(vec![error]),
@@ -67,28 +229,43 @@ pub fn reset_controls(source_string: &str) -> Result<(), Vec<std::io::Error>> {
 
     debug!("Resetting all controls of camera ({source_string}).",);
 
+    let mut changes: Vec<ControlChange> = Default::default();
     let mut errors: Vec<std::io::Error> = Default::default();
     for control in camera.inner().controls() {
-        if control.state.is_inactive {
+        if control.state.is_inactive || control.state.is_disabled || control.state.is_read_only {
             continue;
         }
 
-        let default_value = match &control.configuration {
-            ControlType::Bool(bool) => bool.default,
-            ControlType::Slider(slider) => slider.default,
-            ControlType::Menu(menu) => menu.default,
+        let (value, default_value) = match &control.configuration {
+            ControlType::Bool(bool) => (bool.value, bool.default),
+            ControlType::Slider(slider) => (slider.value, slider.default),
+            ControlType::Menu(menu) => (menu.value, menu.default),
         };
 
+        if value == default_value {
+            continue;
+        }
+
         if let Err(error) = camera.inner().set_control_by_id(control.id, default_value) {
             let error_message = format!(
                 "Error when trying to reset control '{}' (id {}). Error: {error}.",
                 control.name, control.id,
             );
             errors.push(std::io::Error::new(error.kind(), error_message));
+            continue;
         }
+
+        publish_control_write(source_string, control.id, default_value);
+
+        changes.push(ControlChange {
+            id: control.id,
+            name: control.name,
+            old_value: value,
+            new_value: default_value,
+        });
     }
     if errors.is_empty() {
-        return Ok(());
+        return Ok(changes);
     }
 
     error!("{errors:#?}");
@@ -11,30 +11,67 @@ use anyhow::{anyhow, Result};
 //TODO: move to stream ?
 #[derive(Apiv2Schema, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct VideoAndStreamInformation {
+    /// A user friendly name, which doesn't need to be unique. When empty, the source's name is used instead
+    #[serde(default)]
     pub name: String,
+    /// User metadata to organize the streams
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub stream_information: StreamInformation,
     pub video_source: VideoSourceType,
 }
 
 impl VideoAndStreamInformation {
-    pub fn conflicts_with(&self, other: &VideoAndStreamInformation) -> Result<()> {
-        if self.name == other.name {
-            return Err(anyhow!(
-                "Stream ({other_name:#?} - {other_source:#?}) is already using the name {name:#?}.",
-                other_name = other.name,
-                other_source = other.video_source.inner().source_string(),
-                name = self.name,
-            ));
+    /// The name given by the user, falling back to the source's name
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() {
+            return self.video_source.inner().name();
         }
 
-        if (!self.video_source.inner().is_shareable())
-            && (self.video_source.inner().source_string()
-                == other.video_source.inner().source_string())
+        &self.name
+    }
+
+    /// The device this stream opens while running, which no other stream can open unless they share the same
+    /// capture. Symbolic links, like the ones from "/dev/v4l/by-id", are resolved so the same device is recognized
+    /// under any of its paths
+    pub fn local_device(&self) -> Option<String> {
+        if self.video_source.inner().is_shareable() {
+            return None;
+        }
+
+        let source_string = self.video_source.inner().source_string();
+
+        Some(
+            std::fs::canonicalize(source_string)
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or_else(|_| source_string.to_string()),
+        )
+    }
+
+    pub fn conflicts_with(&self, other: &VideoAndStreamInformation) -> Result<()> {
+        if let Some(device) = self
+            .local_device()
+            .filter(|device| other.local_device().as_ref() == Some(device))
         {
-            return Err(anyhow!(
-                "Streams have same source: {:#?}",
-                self.video_source.inner().source_string()
-            ));
+            match (
+                self.stream_information.shared_capture(),
+                other.stream_information.shared_capture(),
+            ) {
+                (Some(our_capture), Some(other_capture)) if our_capture == other_capture => (),
+                (Some(our_capture), Some(_)) => {
+                    return Err(anyhow!(
+                        "Device {device:?} is shared by stream {our_name:?} with the capture {our_capture:?}, every stream sharing it should use the same capture",
+                        our_name = self.display_name(),
+                    ));
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Device {device:?} is already in use by stream {our_name:?}, as {our_source:?}",
+                        our_name = self.display_name(),
+                        our_source = self.video_source.inner().source_string(),
+                    ));
+                }
+            }
         }
 
         let our_endpoints: HashSet<_> = self.stream_information.endpoints.iter().collect();
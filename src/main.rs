@@ -1,4 +1,6 @@
-use mavlink_camera_manager::{cli, helper, logger, mavlink, server, settings, stream};
+use mavlink_camera_manager::{
+    cli, helper, logger, mavlink, network, server, settings, stream, video,
+};
 
 use tracing::*;
 
@@ -11,9 +13,19 @@ async fn main() -> Result<(), std::io::Error> {
     // Settings should start before everybody else to ensure that the CLI are stored
     settings::manager::init(Some(&cli::manager::settings_file()));
 
+    // An unusable TLS certificate should stop us before anything else starts
+    let tls_config = server::tls::server_config().map_err(|error| {
+        error!("Failed loading the REST API's TLS configuration: {error:#}");
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{error:#}"))
+    })?;
+
     mavlink::manager::Manager::init();
 
     stream::manager::init();
+    stream::rtsp::rtsp_auth::init();
+    // Device monitor should start before the streams, so they can react to cameras being unplugged
+    video::device_monitor::start();
+    network::monitor::start();
     settings::manager::set_mavlink_endpoint(&cli::manager::mavlink_connection_string());
 
     if cli::manager::enable_thread_counter() {
@@ -30,7 +42,13 @@ async fn main() -> Result<(), std::io::Error> {
         error!("Failed to start default streams. Reason: {error:?}")
     }
 
-    server::manager::run(&cli::manager::server_address()).await?;
+    stream::thumbnails::start();
+    stream::metrics::start();
+
+    // The server handles SIGINT and SIGTERM, returning once it stops
+    server::manager::run(&cli::manager::server_address(), tls_config).await?;
+
+    stream::manager::shutdown(cli::manager::shutdown_timeout()).await;
 
     Ok(())
 }